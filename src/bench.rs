@@ -0,0 +1,180 @@
+use crate::query::parser::parse_query;
+use crate::query::planner::QueryEngine;
+use crate::storage::settings::StorageOptions;
+use crate::storage::testing::SplitMix;
+use crate::storage::StorageManager;
+use crate::types::{DbError, Value};
+use ordered_float::OrderedFloat;
+use std::fmt;
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Shape of the synthetic data and how often each query runs.
+#[derive(Debug, Clone)]
+pub struct BenchConfig {
+    /// Directory the benchmark database is created in; must not exist yet.
+    pub data_dir: String,
+    /// Rows in the fact table.
+    pub rows: usize,
+    /// Distinct keys and categories, which is also the dimension table's size.
+    pub cardinality: usize,
+    /// Length of the generated strings.
+    pub string_len: usize,
+    /// Timed runs per query; the fastest one is reported.
+    pub iterations: usize,
+    pub seed: u64,
+    /// Leaves the database behind instead of removing it afterwards.
+    pub keep_data: bool,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        BenchConfig {
+            data_dir: "bench_data".to_string(),
+            rows: 100_000,
+            cardinality: 100,
+            string_len: 16,
+            iterations: 3,
+            seed: 42,
+            keep_data: false,
+        }
+    }
+}
+
+/// Timing of one step of the suite.
+#[derive(Debug, Clone)]
+pub struct BenchResult {
+    pub name: String,
+    /// Input rows the step processed.
+    pub rows_in: usize,
+    pub rows_out: usize,
+    pub elapsed: Duration,
+}
+
+impl BenchResult {
+    pub fn rows_per_sec(&self) -> f64 {
+        self.rows_in as f64 / self.elapsed.as_secs_f64().max(1e-9)
+    }
+}
+
+pub struct BenchReport {
+    pub results: Vec<BenchResult>,
+}
+
+impl fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:<10} {:>12} {:>12} {:>12} {:>14}", "step", "rows in", "rows out", "ms", "rows/sec")?;
+        for r in &self.results {
+            writeln!(
+                f,
+                "{:<10} {:>12} {:>12} {:>12.2} {:>14.0}",
+                r.name,
+                r.rows_in,
+                r.rows_out,
+                r.elapsed.as_secs_f64() * 1000.0,
+                r.rows_per_sec()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The standard query suite, run against `bench_facts` and `bench_dims`.
+const SUITE: &[(&str, &str)] = &[
+    ("scan", "SELECT * FROM bench_facts"),
+    ("filter", "SELECT ID, Amount FROM bench_facts WHERE Amount > 900.0"),
+    (
+        "join",
+        "SELECT bench_facts.ID, bench_dims.Name FROM bench_facts JOIN bench_dims ON bench_facts.Key = bench_dims.ID",
+    ),
+    ("aggregate", "SELECT Category, COUNT(*), AVG(Amount) FROM bench_facts GROUP BY Category"),
+];
+
+/// `cardinality` rows of `(ID, Name)`.
+pub fn generate_dims(config: &BenchConfig) -> Vec<Vec<Value>> {
+    let mut rng = SplitMix::new(config.seed ^ 0xd1);
+    (0..config.cardinality.max(1))
+        .map(|id| vec![Value::Int32(id as i32), Value::String(rng.string(config.string_len))])
+        .collect()
+}
+
+/// `rows` rows of `(ID, Key, Category, Amount, TS)`; Key and Category each
+/// take `cardinality` distinct values.
+pub fn generate_facts(config: &BenchConfig) -> Vec<Vec<Value>> {
+    let mut rng = SplitMix::new(config.seed);
+    let cardinality = config.cardinality.max(1);
+    let categories: Vec<String> = (0..cardinality).map(|_| rng.string(config.string_len)).collect();
+    (0..config.rows)
+        .map(|id| {
+            let amount = rng.below(100_000) as f32 / 100.0;
+            vec![
+                Value::Int32(id as i32),
+                Value::Int32(rng.below(cardinality) as i32),
+                Value::String(categories[rng.below(cardinality)].clone()),
+                Value::Float32(OrderedFloat(amount)),
+                Value::Timestamp(1_700_000_000 + id as i64),
+            ]
+        })
+        .collect()
+}
+
+/// Creates a fresh database in `config.data_dir`, loads the synthetic tables
+/// and times the query suite.
+pub fn run(config: &BenchConfig, options: &StorageOptions) -> Result<BenchReport, DbError> {
+    if fs::metadata(&config.data_dir).is_ok() {
+        return Err(DbError::InvalidData(format!("Bench directory {} already exists", config.data_dir)));
+    }
+    let outcome = run_suite(config, options);
+    if !config.keep_data {
+        let _ = fs::remove_dir_all(&config.data_dir);
+    }
+    outcome
+}
+
+fn run_suite(config: &BenchConfig, options: &StorageOptions) -> Result<BenchReport, DbError> {
+    let (_schema, storage, _tx_manager) = crate::create_database(&config.data_dir, options)?;
+    let mut engine = QueryEngine::new(Arc::clone(&storage));
+    engine.execute(parse_query("CREATE TABLE bench_dims (ID INT, Name STRING)")?)?;
+    engine.execute(parse_query(
+        "CREATE TABLE bench_facts (ID INT, Key INT, Category STRING, Amount FLOAT, TS TIMESTAMP)",
+    )?)?;
+
+    let mut results = Vec::new();
+    let dims = generate_dims(config);
+    let facts = generate_facts(config);
+    let rows_in = facts.len() + dims.len();
+    let start = Instant::now();
+    load(&storage, "bench_dims", dims)?;
+    load(&storage, "bench_facts", facts)?;
+    results.push(BenchResult {
+        name: "load".to_string(),
+        rows_in,
+        rows_out: rows_in,
+        elapsed: start.elapsed(),
+    });
+
+    for (name, sql) in SUITE {
+        let mut best: Option<Duration> = None;
+        let mut rows_out = 0;
+        for _ in 0..config.iterations.max(1) {
+            let query = parse_query(sql)?;
+            let start = Instant::now();
+            rows_out = engine.execute(query)?.len();
+            let elapsed = start.elapsed();
+            best = Some(best.map_or(elapsed, |b| b.min(elapsed)));
+        }
+        results.push(BenchResult {
+            name: name.to_string(),
+            rows_in: config.rows,
+            rows_out,
+            elapsed: best.unwrap_or_default(),
+        });
+    }
+    Ok(BenchReport { results })
+}
+
+fn load(storage: &Arc<Mutex<StorageManager>>, table: &str, rows: Vec<Vec<Value>>) -> Result<(), DbError> {
+    storage.lock().unwrap().bulk_load(table, rows)?;
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use crate::query::result::QueryResult;
+use crate::query::stream::RowStream;
+use crate::storage::settings::StorageOptions;
+use crate::storage::StorageManager;
+use crate::transaction::{Session, Transaction, TransactionManager};
+use crate::types::DbError;
+use std::sync::{Arc, Mutex};
+
+/// A database opened for use from Rust code: SQL in, `QueryResult`s out,
+/// without handling the storage and transaction managers directly.
+///
+/// ```no_run
+/// let mut db = vddb::Database::open("app_data")?;
+/// db.execute("CREATE TABLE Users (ID INT, Name STRING)")?;
+/// db.transaction(|tx| {
+///     tx.add_sql("INSERT INTO Users VALUES (1, \"ada\")")?;
+///     tx.add_sql("INSERT INTO Users VALUES (2, \"grace\")")
+/// })?;
+/// let users = db.execute("SELECT Name FROM Users")?;
+/// db.close()?;
+/// # Ok::<(), vddb::DbError>(())
+/// ```
+pub struct Database {
+    storage: Arc<Mutex<StorageManager>>,
+    tx_manager: TransactionManager,
+}
+
+impl Database {
+    /// Opens the database in `path`, creating it if it does not exist.
+    pub fn open(path: &str) -> Result<Self, DbError> {
+        Self::open_with(path, &StorageOptions::default())
+    }
+
+    pub fn open_with(path: &str, options: &StorageOptions) -> Result<Self, DbError> {
+        let (_schema, storage, tx_manager) = crate::create_database(path, options)?;
+        Ok(Database { storage, tx_manager })
+    }
+
+    /// Opens an existing database without taking its lock; writes fail.
+    pub fn open_read_only(path: &str, options: &StorageOptions) -> Result<Self, DbError> {
+        let (_schema, storage, tx_manager) = crate::open_database_read_only(path, options)?;
+        Ok(Database { storage, tx_manager })
+    }
+
+    /// Runs one statement in a transaction of its own. Statements that return
+    /// no rows give an empty result.
+    pub fn execute(&mut self, sql: &str) -> Result<QueryResult, DbError> {
+        let mut tx = self.tx_manager.begin_transaction();
+        tx.add_sql(sql)?;
+        let mut results = self.tx_manager.commit_transaction_results(tx)?;
+        Ok(results.pop().unwrap_or_else(|| QueryResult::new(Vec::new(), Vec::new())))
+    }
+
+    /// Runs one statement, returning its rows as they are read rather than
+    /// all at once. See `TransactionManager::execute_streaming`.
+    pub fn execute_streaming(&mut self, sql: &str) -> Result<RowStream, DbError> {
+        self.tx_manager.execute_streaming(sql)
+    }
+
+    /// Runs the statements `f` adds as one transaction, once `f` returns.
+    /// If `f` fails nothing runs; if a statement fails, the ones before it
+    /// are undone. Returns a result per statement that produced rows.
+    pub fn transaction<F>(&mut self, f: F) -> Result<Vec<QueryResult>, DbError>
+    where
+        F: FnOnce(&mut Transaction) -> Result<(), DbError>,
+    {
+        let mut tx = self.tx_manager.begin_transaction();
+        match f(&mut tx) {
+            Ok(()) => self.tx_manager.commit_transaction_results(tx),
+            Err(e) => {
+                self.tx_manager.rollback_transaction(tx)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// The storage manager, for what SQL does not cover.
+    pub fn storage(&self) -> Arc<Mutex<StorageManager>> {
+        Arc::clone(&self.storage)
+    }
+
+    /// A session for running transactions from another thread; see `Session`.
+    pub fn session(&self) -> Session {
+        self.tx_manager.session()
+    }
+
+    pub fn transaction_manager(&mut self) -> &mut TransactionManager {
+        &mut self.tx_manager
+    }
+
+    /// Writes buffered rows and releases the database. Dropping it does the
+    /// same, but can only log a failure.
+    pub fn close(self) -> Result<(), DbError> {
+        self.tx_manager.flush()?;
+        Ok(())
+    }
+}
@@ -1,24 +1,48 @@
+pub mod bench;
+pub mod database;
+pub mod datetime;
+pub mod migrator;
 pub mod query;
 pub mod repl;
+pub mod scheduler;
 pub mod schema;
 pub mod storage;
 pub mod transaction;
 pub mod types;
 
+pub use database::Database;
+pub use query::explain::{ExplainFormat, PlanNode};
+pub use query::prepared::PreparedQuery;
+pub use query::result::{QueryResult, ResultColumn};
 pub use query::{Aggregation, Condition, Query};
+pub use migrator::Migrator;
 pub use repl::Repl;
+pub use scheduler::Scheduler;
 pub use schema::{Column, Schema, Table};
 use std::sync::{Arc, Mutex};
+pub use storage::settings::{DataLayout, Priority, StorageOptions};
 pub use storage::StorageManager;
-pub use transaction::{Transaction, TransactionManager};
+pub use transaction::{ReadTransaction, Session, Transaction, TransactionManager};
 pub use types::{CompressionType, DataType, DbError, Value};
 
 pub fn create_database(
     data_dir: &str,
+    options: &StorageOptions,
 ) -> Result<(Schema, Arc<Mutex<StorageManager>>, TransactionManager), DbError> {
     let schema = Schema::new_schema(data_dir)?;
     
-    let storage = Arc::new(Mutex::new(StorageManager::new(data_dir, schema.clone())?));
+    let storage = Arc::new(Mutex::new(StorageManager::new(data_dir, schema.clone(), options)?));
+    let tx_manager = TransactionManager::new(storage.clone())?;
+    Ok((schema, storage, tx_manager))
+}
+
+/// Like `create_database`, but skips the data-directory lock and rejects writes.
+pub fn open_database_read_only(
+    data_dir: &str,
+    options: &StorageOptions,
+) -> Result<(Schema, Arc<Mutex<StorageManager>>, TransactionManager), DbError> {
+    let schema = Schema::load(data_dir)?;
+    let storage = Arc::new(Mutex::new(StorageManager::new_read_only(data_dir, schema.clone(), options)?));
     let tx_manager = TransactionManager::new(storage.clone())?;
     Ok((schema, storage, tx_manager))
 }
@@ -26,24 +50,25 @@ pub fn create_database(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::query::expr::Expr;
     use ordered_float::OrderedFloat;
     use rand::distributions::{Alphanumeric, DistString};
     use std::fs;
 
-    fn setup_test_db(
-        test_name: &str,
-    ) -> Result<
-        (
-            String,
-            Schema,
-            Arc<Mutex<StorageManager>>,
-            TransactionManager,
-        ),
-        DbError,
-    > {
+    type TestDb = (String, Schema, Arc<Mutex<StorageManager>>, TransactionManager);
+
+    /// Small segments, so a handful of rows already spans several blocks.
+    fn test_options() -> StorageOptions {
+        StorageOptions {
+            segment_rows: 3,
+            ..StorageOptions::default()
+        }
+    }
+
+    fn setup_test_db(test_name: &str) -> Result<TestDb, DbError> {
         let random_suffix = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
         let data_dir = format!("test_data_{}_{}", test_name, random_suffix);
-        let (schema, storage, tx_manager) = create_database(&data_dir)?;
+        let (schema, storage, tx_manager) = create_database(&data_dir, &test_options())?;
         Ok((data_dir, schema, storage, tx_manager))
     }
 
@@ -67,9 +92,9 @@ mod tests {
         let query = Query::CreateTable {
             table: "Employees".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Name".to_string(), DataType::String),
-                ("Salary".to_string(), DataType::Float32),
+                Column::new("ID", DataType::Int32),
+                Column::new("Name", DataType::String),
+                Column::new("Salary", DataType::Float32),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -97,9 +122,9 @@ mod tests {
         let create_query = Query::CreateTable {
             table: "Employees".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Name".to_string(), DataType::String),
-                ("Salary".to_string(), DataType::Float32),
+                Column::new("ID", DataType::Int32),
+                Column::new("Name", DataType::String),
+                Column::new("Salary", DataType::Float32),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -113,6 +138,7 @@ mod tests {
                 Value::String("Alice".to_string()),
                 Value::Float32(OrderedFloat(1000.0)),
             ],
+            returning: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(insert_query);
@@ -120,8 +146,14 @@ mod tests {
 
         let select_query = Query::Select {
             table: "Employees".to_string(),
-            columns: vec!["Name".to_string(), "Salary".to_string()],
+            columns: vec![Expr::Column("Name".to_string()), Expr::Column("Salary".to_string())],
+            distinct: false,
             condition: Some(Condition::Equal("ID".to_string(), Value::Int32(1))),
+            sample: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hints: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(select_query);
@@ -145,8 +177,8 @@ mod tests {
         let create_query = Query::CreateTable {
             table: "Sales".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Amount".to_string(), DataType::Float32),
+                Column::new("ID", DataType::Int32),
+                Column::new("Amount", DataType::Float32),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -157,14 +189,17 @@ mod tests {
             Query::Insert {
                 table: "Sales".to_string(),
                 values: vec![Value::Int32(1), Value::Float32(OrderedFloat(100.0))],
+                returning: Vec::new(),
             },
             Query::Insert {
                 table: "Sales".to_string(),
                 values: vec![Value::Int32(2), Value::Float32(OrderedFloat(200.0))],
+                returning: Vec::new(),
             },
             Query::Insert {
                 table: "Sales".to_string(),
                 values: vec![Value::Int32(3), Value::Float32(OrderedFloat(300.0))],
+                returning: Vec::new(),
             },
         ];
         let mut tx = tx_manager.begin_transaction();
@@ -183,6 +218,9 @@ mod tests {
                 Aggregation::Max("Amount".to_string()),
             ],
             condition: None,
+            sample: None,
+            group_by: Vec::new(),
+            hints: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(agg_query);
@@ -209,15 +247,15 @@ mod tests {
         let create_employees = Query::CreateTable {
             table: "Employees".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Name".to_string(), DataType::String),
+                Column::new("ID", DataType::Int32),
+                Column::new("Name", DataType::String),
             ],
         };
         let create_departments = Query::CreateTable {
             table: "Departments".to_string(),
             columns: vec![
-                ("DeptID".to_string(), DataType::Int32),
-                ("DeptName".to_string(), DataType::String),
+                Column::new("DeptID", DataType::Int32),
+                Column::new("DeptName", DataType::String),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -229,20 +267,24 @@ mod tests {
             Query::Insert {
                 table: "Employees".to_string(),
                 values: vec![Value::Int32(1), Value::String("Alice".to_string())],
+                returning: Vec::new(),
             },
             Query::Insert {
                 table: "Employees".to_string(),
                 values: vec![Value::Int32(2), Value::String("Bob".to_string())],
+                returning: Vec::new(),
             },
         ];
         let insert_departments = vec![
             Query::Insert {
                 table: "Departments".to_string(),
                 values: vec![Value::Int32(1), Value::String("HR".to_string())],
+                returning: Vec::new(),
             },
             Query::Insert {
                 table: "Departments".to_string(),
                 values: vec![Value::Int32(2), Value::String("IT".to_string())],
+                returning: Vec::new(),
             },
         ];
         let mut tx = tx_manager.begin_transaction();
@@ -259,11 +301,13 @@ mod tests {
             right_table: "Departments".to_string(),
             left_column: "ID".to_string(),
             right_column: "DeptID".to_string(),
+            operator: query::JoinOperator::Eq,
             columns: vec![
-                "Employees.Name".to_string(),
-                "Departments.DeptName".to_string(),
+                Expr::Column("Employees.Name".to_string()),
+                Expr::Column("Departments.DeptName".to_string()),
             ],
             condition: None,
+            hints: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(join_query);
@@ -294,8 +338,8 @@ mod tests {
         let create_query = Query::CreateTable {
             table: "Test".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Value".to_string(), DataType::String),
+                Column::new("ID", DataType::Int32),
+                Column::new("Value", DataType::String),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -306,6 +350,7 @@ mod tests {
         let insert_query = Query::Insert {
             table: "Test".to_string(),
             values: vec![Value::Int32(1), Value::String("Committed".to_string())],
+            returning: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(insert_query);
@@ -313,8 +358,14 @@ mod tests {
 
         let select_query = Query::Select {
             table: "Test".to_string(),
-            columns: vec!["Value".to_string()],
+            columns: vec![Expr::Column("Value".to_string())],
+            distinct: false,
             condition: None,
+            sample: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hints: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(select_query.clone());
@@ -325,6 +376,7 @@ mod tests {
         let insert_query = Query::Insert {
             table: "Test".to_string(),
             values: vec![Value::Int32(2), Value::String("RolledBack".to_string())],
+            returning: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(insert_query);
@@ -345,8 +397,8 @@ mod tests {
         let create_query = Query::CreateTable {
             table: "Test".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Value".to_string(), DataType::String),
+                Column::new("ID", DataType::Int32),
+                Column::new("Value", DataType::String),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -360,6 +412,7 @@ mod tests {
                 Value::String("Invalid".to_string()),
                 Value::String("Test".to_string()),
             ],
+            returning: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(insert_query);
@@ -369,8 +422,14 @@ mod tests {
         // Test missing table
         let select_query = Query::Select {
             table: "NonExistent".to_string(),
-            columns: vec!["ID".to_string()],
+            columns: vec![Expr::Column("ID".to_string())],
+            distinct: false,
             condition: None,
+            sample: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hints: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(select_query);
@@ -384,6 +443,7 @@ mod tests {
         let insert_query = Query::Insert {
             table: "Test".to_string(),
             values: vec![Value::Int32(1)],
+            returning: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(insert_query);
@@ -402,9 +462,9 @@ mod tests {
         let create_query = Query::CreateTable {
             table: "Employees".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Name".to_string(), DataType::String),
-                ("Salary".to_string(), DataType::Float32),
+                Column::new("ID", DataType::Int32),
+                Column::new("Name", DataType::String),
+                Column::new("Salary", DataType::Float32),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -419,6 +479,7 @@ mod tests {
                     Value::String("Alice".to_string()),
                     Value::Float32(OrderedFloat(1000.0)),
                 ],
+                returning: Vec::new(),
             },
             Query::Insert {
                 table: "Employees".to_string(),
@@ -427,6 +488,7 @@ mod tests {
                     Value::String("Bob".to_string()),
                     Value::Float32(OrderedFloat(1500.0)),
                 ],
+                returning: Vec::new(),
             },
         ];
         let mut tx = tx_manager.begin_transaction();
@@ -438,6 +500,7 @@ mod tests {
         let delete_query = Query::Delete {
             table: "Employees".to_string(),
             condition: Some(Condition::Equal("ID".to_string(), Value::Int32(1))),
+            returning: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(delete_query);
@@ -445,8 +508,14 @@ mod tests {
 
         let select_query = Query::Select {
             table: "Employees".to_string(),
-            columns: vec!["Name".to_string(), "Salary".to_string()],
+            columns: vec![Expr::Column("Name".to_string()), Expr::Column("Salary".to_string())],
+            distinct: false,
             condition: None,
+            sample: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hints: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(select_query);
@@ -470,8 +539,8 @@ mod tests {
         let create_query = Query::CreateTable {
             table: "Test".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Value".to_string(), DataType::String),
+                Column::new("ID", DataType::Int32),
+                Column::new("Value", DataType::String),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -481,6 +550,7 @@ mod tests {
         let insert_query = Query::Insert {
             table: "Test".to_string(),
             values: vec![Value::Int32(1), Value::String("Test".to_string())],
+            returning: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(insert_query);
@@ -496,8 +566,8 @@ mod tests {
         let storage_guard = storage.lock().unwrap();
         let schema = storage_guard.schema();
         assert!(schema.get_table("Test").is_none());
-        assert!(!fs::metadata(format!("{}/columns/Test_ID", data_dir)).is_ok());
-        assert!(!fs::metadata(format!("{}/indexes/Test_ID.idx", data_dir)).is_ok());
+        assert!(fs::metadata(format!("{}/columns/Test", data_dir)).is_err());
+        assert!(fs::metadata(format!("{}/indexes/Test_c1.idx", data_dir)).is_err());
 
         cleanup_test_db(&data_dir);
     }
@@ -510,8 +580,8 @@ mod tests {
         let create_query = Query::CreateTable {
             table: "Test".to_string(),
             columns: vec![
-                ("ID".to_string(), DataType::Int32),
-                ("Value".to_string(), DataType::String),
+                Column::new("ID", DataType::Int32),
+                Column::new("Value", DataType::String),
             ],
         };
         let mut tx = tx_manager.begin_transaction();
@@ -523,6 +593,7 @@ mod tests {
         tx.add_query(Query::Insert {
             table: "Test".to_string(),
             values: vec![Value::Int32(1), Value::String("Committed".to_string())],
+            returning: Vec::new(),
         });
         tx_manager.commit_transaction(tx).unwrap();
 
@@ -531,14 +602,21 @@ mod tests {
         tx.add_query(Query::Insert {
             table: "Test".to_string(),
             values: vec![Value::Int32(2), Value::String("RolledBack".to_string())],
+            returning: Vec::new(),
         });
         tx_manager.rollback_transaction(tx).unwrap();
 
         // Verify only the committed row is present
         let select_query = Query::Select {
             table: "Test".to_string(),
-            columns: vec!["Value".to_string()],
+            columns: vec![Expr::Column("Value".to_string())],
+            distinct: false,
             condition: None,
+            sample: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hints: Vec::new(),
         };
         let mut tx = tx_manager.begin_transaction();
         tx.add_query(select_query);
@@ -547,4 +625,4483 @@ mod tests {
 
         cleanup_test_db(&data_dir);
     }
+
+    #[test]
+    fn test_query_cache_invalidation() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("query_cache").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Test".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Value", DataType::String),
+            ],
+        });
+        tx.add_query(Query::Insert {
+            table: "Test".to_string(),
+            values: vec![Value::Int32(1), Value::String("First".to_string())],
+            returning: Vec::new(),
+        });
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let select_query = Query::Select {
+            table: "Test".to_string(),
+            columns: vec![Expr::Column("Value".to_string())],
+            distinct: false,
+            condition: None,
+            sample: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hints: Vec::new(),
+        };
+        for _ in 0..2 {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(select_query.clone());
+            let results = tx_manager.commit_transaction(tx).unwrap();
+            assert_eq!(results, vec![vec![Value::String("First".to_string())]]);
+        }
+        assert_eq!(tx_manager.query_cache().lock().unwrap().hits(), 1);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::Insert {
+            table: "Test".to_string(),
+            values: vec![Value::Int32(2), Value::String("Second".to_string())],
+            returning: Vec::new(),
+        });
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(select_query);
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::String("First".to_string())],
+                vec![Value::String("Second".to_string())]
+            ]
+        );
+        assert_eq!(tx_manager.query_cache().lock().unwrap().hits(), 1);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_table_size_stats() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("table_size").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Test".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Value", DataType::String),
+            ],
+        });
+        for i in 0..3 {
+            tx.add_query(Query::Insert {
+                table: "Test".to_string(),
+                values: vec![Value::Int32(i), Value::String("abc".to_string())],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let stats = storage.lock().unwrap().table_stats("Test").unwrap();
+        assert_eq!(stats.columns.len(), 2);
+        assert_eq!(stats.columns[0].block_count, 1);
+        assert_eq!(stats.columns[0].row_count, 3);
+        assert_eq!(stats.columns[0].uncompressed_bytes, 12);
+        assert_eq!(stats.columns[1].uncompressed_bytes, 3 * (8 + 3));
+        assert!(stats.columns[0].on_disk_bytes > 0);
+        assert!(stats.columns[0].index_bytes > 0);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::ShowTableSize {
+            table: "Test".to_string(),
+        });
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[2][0], Value::String("TOTAL".to_string()));
+        assert_eq!(results[2][3], Value::Int64(stats.on_disk_bytes() as i64));
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_table_sample() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("table_sample").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Test".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32)],
+        });
+        for i in 1..=12 {
+            tx.add_query(Query::Insert {
+                table: "Test".to_string(),
+                values: vec![Value::Int32(i)],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let query =
+            query::parser::parse_query("SELECT ID FROM Test TABLESAMPLE (50 PERCENT)").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query);
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        let ids: Vec<Value> = results.into_iter().flatten().collect();
+        assert_eq!(ids, [1, 2, 3, 7, 8, 9].map(Value::Int32).to_vec());
+
+        assert!(query::parser::parse_query("SELECT ID FROM Test TABLESAMPLE (150 PERCENT)").is_err());
+
+        // A table smaller than one block in the sample still returns its rows.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("CREATE TABLE Tiny (ID INT)").unwrap();
+        tx.add_sql("INSERT INTO Tiny VALUES (1)").unwrap();
+        tx.add_sql("INSERT INTO Tiny VALUES (2)").unwrap();
+        tx_manager.commit_transaction(tx).unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("SELECT ID FROM Tiny TABLESAMPLE (50 PERCENT)").unwrap();
+        assert_eq!(tx_manager.commit_transaction(tx).unwrap(), vec![vec![Value::Int32(1)], vec![Value::Int32(2)]]);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_approximate_aggregates() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("approx_aggs").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Events".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("UserID", DataType::Int32),
+            ],
+        });
+        for i in 1..=300 {
+            tx.add_query(Query::Insert {
+                table: "Events".to_string(),
+                values: vec![Value::Int32(i), Value::Int32(i % 50)],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let query = query::parser::parse_query(
+            "SELECT APPROX_COUNT_DISTINCT(UserID), APPROX_QUANTILE(ID, 0.5) FROM Events",
+        )
+        .unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query);
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results.len(), 1);
+        match (&results[0][0], &results[0][1]) {
+            (Value::Int32(distinct), Value::Float32(median)) => {
+                assert!((48..=52).contains(distinct), "distinct estimate {}", distinct);
+                assert!((median.0 - 150.0).abs() < 15.0, "median estimate {}", median.0);
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_percentile_and_histogram() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("percentile").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Scores".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Score", DataType::Float32),
+            ],
+        });
+        for i in 1..=10 {
+            tx.add_query(Query::Insert {
+                table: "Scores".to_string(),
+                values: vec![Value::Int32(i), Value::Float32(OrderedFloat(i as f32 * 10.0))],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT PERCENTILE(Score, 50) FROM Scores").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::Float32(OrderedFloat(55.0))]]);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT HISTOGRAM(Score, 3) FROM Scores").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results.len(), 3);
+        let counts: Vec<Value> = results.iter().map(|row| row[2].clone()).collect();
+        assert_eq!(counts, vec![Value::Int32(3), Value::Int32(3), Value::Int32(4)]);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_group_by_time_bucket() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("time_bucket").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Readings".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("TS", DataType::Int32),
+                Column::new("Temp", DataType::Float32),
+            ],
+        });
+        let readings = [(1, 0, 10.0), (2, 1800, 20.0), (3, 3600, 30.0), (4, 5400, 50.0), (5, 7300, 5.0)];
+        for (id, ts, temp) in readings {
+            tx.add_query(Query::Insert {
+                table: "Readings".to_string(),
+                values: vec![Value::Int32(id), Value::Int32(ts), Value::Float32(OrderedFloat(temp))],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let query = query::parser::parse_query(
+            "SELECT TIME_BUCKET('1 hour', TS), COUNT, AVG(Temp) FROM Readings WHERE Temp > 6.0 GROUP BY TIME_BUCKET('1 hour', TS)",
+        )
+        .unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query);
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::Int32(0), Value::Int32(2), Value::Float32(OrderedFloat(15.0))],
+                vec![Value::Int32(3600), Value::Int32(2), Value::Float32(OrderedFloat(40.0))],
+            ]
+        );
+
+        let query =
+            query::parser::parse_query("SELECT DATE_TRUNC('hour', TS), COUNT FROM Readings GROUP BY DATE_TRUNC('hour', TS)")
+                .unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query);
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[2], vec![Value::Int32(7200), Value::Int32(1)]);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_first_last_aggregates() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("first_last").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Prices".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Symbol", DataType::String),
+                Column::new("TS", DataType::Int32),
+                Column::new("Price", DataType::Float32),
+            ],
+        });
+        let ticks = [(1, "A", 30, 1.5), (2, "B", 10, 7.0), (3, "A", 10, 1.0), (4, "B", 20, 8.0), (5, "A", 20, 2.0)];
+        for (id, symbol, ts, price) in ticks {
+            tx.add_query(Query::Insert {
+                table: "Prices".to_string(),
+                values: vec![
+                    Value::Int32(id),
+                    Value::String(symbol.to_string()),
+                    Value::Int32(ts),
+                    Value::Float32(OrderedFloat(price)),
+                ],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let query = query::parser::parse_query(
+            "SELECT Symbol, FIRST(Price ORDER BY TS), LAST(Price ORDER BY TS) FROM Prices GROUP BY Symbol",
+        )
+        .unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query);
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![
+                    Value::String("A".to_string()),
+                    Value::Float32(OrderedFloat(1.0)),
+                    Value::Float32(OrderedFloat(1.5))
+                ],
+                vec![
+                    Value::String("B".to_string()),
+                    Value::Float32(OrderedFloat(7.0)),
+                    Value::Float32(OrderedFloat(8.0))
+                ],
+            ]
+        );
+
+        let query = query::parser::parse_query("SELECT LAST(Symbol ORDER BY TS) FROM Prices").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query);
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::String("A".to_string())]]);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_analyze_correlated_selectivity() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("analyze").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Addresses".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Zip", DataType::Int32),
+                Column::new("City", DataType::Int32),
+            ],
+        });
+        for i in 0..30 {
+            tx.add_query(Query::Insert {
+                table: "Addresses".to_string(),
+                values: vec![Value::Int32(i), Value::Int32(i % 10), Value::Int32((i % 10) / 2)],
+                returning: Vec::new(),
+            });
+        }
+        tx.add_query(query::parser::parse_query("ANALYZE Addresses").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results[1],
+            vec![
+                Value::String("Zip".to_string()),
+                Value::Int32(10),
+                Value::Int32(0),
+                Value::Int32(9)
+            ]
+        );
+
+        let stats = storage.lock().unwrap().table_statistics("Addresses").unwrap().unwrap();
+        assert_eq!(stats.correlation("Zip", "City"), Some(1.0));
+
+        let engine = query::planner::QueryEngine::new(storage.clone());
+        let select = Query::Select {
+            table: "Addresses".to_string(),
+            columns: vec![Expr::Column("ID".to_string())],
+            distinct: false,
+            condition: Some(Condition::And(
+                Box::new(Condition::Equal("Zip".to_string(), Value::Int32(3))),
+                Box::new(Condition::Equal("City".to_string(), Value::Int32(1))),
+            )),
+            sample: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hints: Vec::new(),
+        };
+        let estimate = engine.estimate_rows(&select).unwrap().unwrap();
+        assert!((estimate - 3.0).abs() < 0.01, "estimate {}", estimate);
+
+        // Filters on columns the join keys determine leave fewer keys to match:
+        // only Zips 2 and 3 survive on both sides, not all ten.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("CREATE TABLE Zones (Zip INT, Region INT)").unwrap();
+        for i in 0..20 {
+            tx.add_sql(&format!("INSERT INTO Zones VALUES ({}, {})", i % 10, (i % 10) / 5)).unwrap();
+        }
+        tx.add_sql("ANALYZE Zones").unwrap();
+        tx_manager.commit_transaction(tx).unwrap();
+        let join = query::parser::parse_query(
+            "SELECT Addresses.ID FROM Addresses JOIN Zones ON Addresses.Zip = Zones.Zip \
+             WHERE Addresses.City = 1 AND Zones.Region = 0",
+        )
+        .unwrap();
+        let estimate = engine.estimate_rows(&join).unwrap().unwrap();
+        assert!((estimate - 12.0).abs() < 0.01, "estimate {}", estimate);
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(join);
+        assert_eq!(tx_manager.commit_transaction(tx).unwrap().len(), 12);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_parallel_csv_load() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("csv_load").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "People".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Name", DataType::String),
+                Column::new("Score", DataType::Float32),
+            ],
+        });
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let csv_path = format!("{}/people.csv", data_dir);
+        let mut csv = String::from("ID,Name,Score\n");
+        for i in 0..2500 {
+            csv.push_str(&format!("{},\"Person, {}\",{}.5\n", i, i % 7, i % 3));
+        }
+        fs::write(&csv_path, csv).unwrap();
+
+        let options = storage::csv::CsvLoadOptions {
+            chunk_rows: 1000,
+            threads: 4,
+            ..Default::default()
+        };
+        let loaded = storage.lock().unwrap().load_csv("People", &csv_path, &options).unwrap();
+        assert_eq!(loaded, 2500);
+        assert_eq!(storage.lock().unwrap().schema().get_table("People").unwrap().row_count, 2500);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::Select {
+            table: "People".to_string(),
+            columns: vec![Expr::Column("Name".to_string()), Expr::Column("Score".to_string())],
+            distinct: false,
+            condition: Some(Condition::Equal("ID".to_string(), Value::Int32(1500))),
+            sample: None,
+            order_by: Vec::new(),
+            limit: None,
+            offset: None,
+            hints: Vec::new(),
+        });
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results,
+            vec![vec![
+                Value::String("Person, 2".to_string()),
+                Value::Float32(OrderedFloat(0.5))
+            ]]
+        );
+
+        // Reloading the same IDs is rejected without writing anything.
+        let result = storage.lock().unwrap().load_csv("People", &csv_path, &options);
+        assert!(matches!(result, Err(DbError::InvalidData(ref s)) if s.contains("Duplicate ID")));
+        assert_eq!(storage.lock().unwrap().schema().get_table("People").unwrap().row_count, 2500);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_zone_map_count_min_max() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("zone_map").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Sales".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Amount", DataType::Float32),
+            ],
+        });
+        for i in 1..=7 {
+            tx.add_query(Query::Insert {
+                table: "Sales".to_string(),
+                values: vec![Value::Int32(i), Value::Float32(OrderedFloat(i as f32 * 10.0))],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        // Clobber the data file: the answer must come from metadata alone.
+        fs::write(format!("{}/columns/Sales/c2.dat", data_dir), b"").unwrap();
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT COUNT(*), MIN(Amount), MAX(Amount) FROM Sales").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results,
+            vec![vec![
+                Value::Int32(7),
+                Value::Float32(OrderedFloat(10.0)),
+                Value::Float32(OrderedFloat(70.0))
+            ]]
+        );
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_decompress_into_reuses_scratch() {
+        use crate::query::batch::DictionaryBuilder;
+        use crate::storage::compression::{compress, decode_dictionary, decompress, decompress_into, DecodeScratch};
+
+        let names: Vec<Value> = ["a", "b", "a", "c", "b"]
+            .iter()
+            .map(|s| Value::String(s.to_string()))
+            .collect();
+        let ids: Vec<Value> = vec![Value::Int32(1), Value::Int32(1), Value::Int32(2)];
+        let dict = compress(&names, CompressionType::Dictionary).unwrap();
+        let rle = compress(&ids, CompressionType::Rle).unwrap();
+
+        let mut scratch = DecodeScratch::default();
+        let mut out = Vec::new();
+        let n = decompress_into(&dict, CompressionType::Dictionary, &DataType::String, &mut out, &mut scratch).unwrap();
+        assert_eq!(n, 5);
+        let n = decompress_into(&dict, CompressionType::Dictionary, &DataType::String, &mut out, &mut scratch).unwrap();
+        assert_eq!(n, 5);
+        assert_eq!(&out[..5], &names[..]);
+        assert_eq!(&out[5..], &names[..]);
+        assert_eq!(decompress(&rle, CompressionType::Rle, &DataType::Int32).unwrap(), ids);
+
+        // A corrupt block leaves earlier output untouched.
+        let result = decompress_into(&dict[..dict.len() - 1], CompressionType::Dictionary, &DataType::String, &mut out, &mut scratch);
+        assert!(result.is_err());
+        assert_eq!(out.len(), 10);
+
+        // Batch reads take a block's ids and dictionary as they are, with its
+        // NULL rows put back where the block left them out.
+        decode_dictionary(&dict, &mut scratch).unwrap();
+        let mut builder = DictionaryBuilder::default();
+        builder.extend_encoded(scratch.ids(), scratch.dictionary(), &[1, 6]).unwrap();
+        let vector = builder.finish();
+        let mut expected = names.clone();
+        expected.insert(1, Value::Null);
+        expected.push(Value::Null);
+        assert_eq!((0..7).map(|row| vector.value(row)).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn test_batch_filtering() {
+        use crate::query::batch::{Batch, ColumnVector};
+
+        let names: Vec<Value> = ["b", "a", "b", "c"].iter().map(|s| Value::String(s.to_string())).collect();
+        let scores: Vec<Value> = (1..=4).map(Value::Int32).collect();
+        let mut batch = Batch::new(4);
+        batch.add_column("Name", &names, &DataType::String).unwrap();
+        batch.add_column("Score", &scores, &DataType::Int32).unwrap();
+        assert!(matches!(batch.column("Name").unwrap(), ColumnVector::Dictionary { dictionary, .. } if dictionary.len() == 3));
+
+        let cond = Condition::Or(
+            Box::new(Condition::Equal("Name".to_string(), Value::String("b".to_string()))),
+            Box::new(Condition::GreaterThanOrEqual("Score".to_string(), Value::Int32(4))),
+        );
+        let selection = batch.filter(&cond, &batch.select_all()).unwrap();
+        assert_eq!(selection, vec![0, 2, 3]);
+        assert_eq!(
+            batch.rows(&["Score".to_string()], &selection).unwrap(),
+            vec![vec![Value::Int32(1)], vec![Value::Int32(3)], vec![Value::Int32(4)]]
+        );
+
+        // Aggregates with WHERE filter individual rows, not just whole blocks.
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("batch_filter").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Scores".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32), Column::new("Score", DataType::Int32)],
+        });
+        for i in 1..=6 {
+            tx.add_query(Query::Insert {
+                table: "Scores".to_string(),
+                values: vec![Value::Int32(i), Value::Int32(i * 10)],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT COUNT(*), SUM(Score) FROM Scores WHERE Score > 20").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results[0][0], Value::Int32(4));
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_dictionary_string_filtering() {
+        use crate::query::batch::ColumnVector;
+
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("dict_filter").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Cities".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32), Column::new("City", DataType::String)],
+        });
+        let cities = ["Oslo", "Lima", "Oslo", "Rome", "Lima", "Oslo", "Rome"];
+        for (i, city) in cities.iter().enumerate() {
+            tx.add_query(Query::Insert {
+                table: "Cities".to_string(),
+                values: vec![Value::Int32(i as i32 + 1), Value::String(city.to_string())],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        // Codes are merged across blocks and pending rows into one dictionary.
+        let vector = storage.lock().unwrap().read_column_vector("Cities", "City", None, None, true).unwrap();
+        match &vector {
+            ColumnVector::Dictionary { codes, dictionary } => {
+                assert_eq!(codes.len(), 7);
+                assert_eq!(dictionary.len(), 3);
+            }
+            other => panic!("expected dictionary vector, got {:?}", other),
+        }
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT ID FROM Cities WHERE City = \"Oslo\"").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::Int32(1)], vec![Value::Int32(3)], vec![Value::Int32(6)]]);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_persistent_row_count() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("row_count").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Events".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32), Column::new("Kind", DataType::Int32)],
+        });
+        for i in 1..=7 {
+            tx.add_query(Query::Insert {
+                table: "Events".to_string(),
+                values: vec![Value::Int32(i), Value::Int32(i % 2)],
+                returning: Vec::new(),
+            });
+        }
+        tx.add_query(query::parser::parse_query("SHOW TABLES").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results,
+            vec![vec![Value::String("Events".to_string()), Value::Int32(2), Value::Int32(7)]]
+        );
+
+        // Inserts no longer rewrite schema.json; the flushed count is in table metadata.
+        let schema = Schema::load(&data_dir).unwrap();
+        assert_eq!(schema.get_table("Events").unwrap().row_count, 0);
+        let meta = crate::schema::metadata::TableMetadata::load(&data_dir, "Events").unwrap().unwrap();
+        assert_eq!(meta.row_count, 6);
+        let reopened = StorageManager::new_read_only(&data_dir, schema, &test_options()).unwrap();
+        assert_eq!(reopened.row_count("Events"), Some(6));
+
+        storage.lock().unwrap().delete_rows("Events", Some(&Condition::Equal("Kind".to_string(), Value::Int32(1)))).unwrap();
+        assert_eq!(storage.lock().unwrap().row_count("Events"), Some(3));
+        let meta = crate::schema::metadata::TableMetadata::load(&data_dir, "Events").unwrap().unwrap();
+        assert_eq!(meta.row_count, 3);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_schema_atomic_save() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("schema_atomic").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "A".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32)],
+        });
+        tx_manager.commit_transaction(tx).unwrap();
+        let schema_path = format!("{}/schema.json", data_dir);
+        let before = fs::read_to_string(&schema_path).unwrap();
+
+        // A half-written temp file from a crash is ignored and replaced by the next DDL.
+        fs::write(format!("{}.tmp", schema_path), b"{\"trunc").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        for i in 1..=5 {
+            tx.add_query(Query::Insert {
+                table: "A".to_string(),
+                values: vec![Value::Int32(i)],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(fs::read_to_string(&schema_path).unwrap(), before);
+        assert!(Schema::load(&data_dir).unwrap().get_table("A").is_some());
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "B".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32)],
+        });
+        tx_manager.commit_transaction(tx).unwrap();
+        assert!(fs::metadata(format!("{}.tmp", schema_path)).is_err());
+        assert_eq!(Schema::load(&data_dir).unwrap().tables.len(), 2);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_atomic_metadata_writes() {
+        use crate::storage::atomic::write_atomic;
+
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("atomic_writes").unwrap();
+        let target = format!("{}/metadata/example.json", data_dir);
+        fs::write(&target, b"old").unwrap();
+        write_atomic(&target, b"new").unwrap();
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+        assert!(fs::metadata(format!("{}.tmp", target)).is_err());
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "T".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32), Column::new("V", DataType::Int32)],
+        });
+        for i in 1..=3 {
+            tx.add_query(Query::Insert {
+                table: "T".to_string(),
+                values: vec![Value::Int32(i), Value::Int32(i)],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        // Metadata and index writes leave no temp files behind.
+        for dir in ["metadata", "indexes"] {
+            for entry in fs::read_dir(format!("{}/{}", data_dir, dir)).unwrap() {
+                let name = entry.unwrap().file_name().into_string().unwrap();
+                assert!(!name.ends_with(".tmp"), "stray temp file {}", name);
+            }
+        }
+        assert!(fs::metadata(format!("{}/indexes/T_c1.idx", data_dir)).unwrap().len() > 0);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_orphan_cleanup() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("orphans").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Kept".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32)],
+        });
+        tx.add_query(Query::Insert {
+            table: "Kept".to_string(),
+            values: vec![Value::Int32(1)],
+            returning: Vec::new(),
+        });
+        tx_manager.commit_transaction(tx).unwrap();
+
+        // Leftovers from a crashed DDL and an interrupted atomic write.
+        fs::write(format!("{}/columns/Gone.dat", data_dir), b"x").unwrap();
+        fs::write(format!("{}/indexes/Old_ID.idx", data_dir), b"x").unwrap();
+        fs::write(format!("{}/schema.json.tmp", data_dir), b"{").unwrap();
+        let expected = vec![
+            "columns/Gone.dat".to_string(),
+            "indexes/Old_ID.idx".to_string(),
+            "schema.json.tmp".to_string(),
+        ];
+        assert_eq!(storage.lock().unwrap().find_orphans().unwrap(), expected);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("CLEANUP DRY RUN").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results.len(), 3);
+        assert!(fs::metadata(format!("{}/columns/Gone.dat", data_dir)).is_ok());
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("CLEANUP").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results[0], vec![Value::String("columns/Gone.dat".to_string()), Value::String("removed".to_string())]);
+        assert!(storage.lock().unwrap().find_orphans().unwrap().is_empty());
+        assert!(fs::metadata(format!("{}/columns/Kept/c1.dat", data_dir)).is_ok());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_data_dir_lock() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("dir_lock").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "T".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32)],
+        });
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let pid = std::process::id().to_string();
+        assert_eq!(fs::read_to_string(format!("{}/LOCK", data_dir)).unwrap(), pid);
+        match create_database(&data_dir, &test_options()) {
+            Err(DbError::TransactionError(msg)) => assert!(msg.contains(&format!("locked by PID {}", pid))),
+            other => panic!("expected lock error, got {:?}", other.map(|_| ())),
+        }
+
+        // The read-only bypass can read but not write.
+        let (_schema, storage, mut reader) = open_database_read_only(&data_dir, &test_options()).unwrap();
+        assert!(storage.lock().unwrap().is_read_only());
+        let mut tx = reader.begin_transaction();
+        tx.add_query(query::parser::parse_query("SHOW TABLES").unwrap());
+        assert_eq!(reader.commit_transaction(tx).unwrap().len(), 1);
+        let mut tx = reader.begin_transaction();
+        tx.add_query(Query::Insert {
+            table: "T".to_string(),
+            values: vec![Value::Int32(1)],
+            returning: Vec::new(),
+        });
+        assert!(matches!(reader.commit_transaction(tx), Err(DbError::TransactionError(_))));
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_update() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("update").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Staff".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Name", DataType::String),
+                Column::new("Salary", DataType::Float32),
+            ],
+        });
+        for (i, name) in ["Ann", "Bob", "Cy", "Dee", "Eve"].iter().enumerate() {
+            tx.add_query(Query::Insert {
+                table: "Staff".to_string(),
+                values: vec![
+                    Value::Int32(i as i32 + 1),
+                    Value::String(name.to_string()),
+                    Value::Float32(OrderedFloat(100.0 * (i as f32 + 1.0))),
+                ],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("UPDATE Staff SET Salary = 1.5, Name = \"X, Y\" WHERE Salary > 250.0").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT ID, Name, Salary FROM Staff WHERE ID > 2").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results.len(), 4);
+        // UPDATE reports the table and how many rows it changed.
+        assert_eq!(results[0], vec![Value::String("Staff".to_string()), Value::Int32(3)]);
+        for row in &results[1..] {
+            assert_eq!(row[1..], [Value::String("X, Y".to_string()), Value::Float32(OrderedFloat(1.5))]);
+        }
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT Name FROM Staff WHERE ID < 3").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::String("Ann".to_string())], vec![Value::String("Bob".to_string())]]);
+
+        // Setting one ID on several rows, or onto an existing ID, is rejected.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("UPDATE Staff SET ID = 9 WHERE ID > 3").unwrap());
+        assert!(matches!(tx_manager.commit_transaction(tx), Err(DbError::InvalidData(_))));
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("UPDATE Staff SET ID = 1 WHERE ID = 2").unwrap());
+        assert!(matches!(tx_manager.commit_transaction(tx), Err(DbError::InvalidData(_))));
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("UPDATE Staff SET ID = 10 WHERE ID = 2").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT Name FROM Staff WHERE ID = 10").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results[1..], [vec![Value::String("Bob".to_string())]]);
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("UPDATE Staff SET Salary = 2.0 WHERE ID > 50").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::String("Staff".to_string()), Value::Int32(0)]]);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_hot_backup() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("backup").unwrap();
+        let backup_dir = format!("{}_copy", data_dir);
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "Logs".to_string(),
+            columns: vec![Column::new("ID", DataType::Int32), Column::new("Msg", DataType::String)],
+        });
+        for i in 1..=5 {
+            tx.add_query(Query::Insert {
+                table: "Logs".to_string(),
+                values: vec![Value::Int32(i), Value::String(format!("m{}", i))],
+                returning: Vec::new(),
+            });
+        }
+        tx.add_query(query::parser::parse_query(&format!("BACKUP TO '{}'", backup_dir)).unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results[0][0], Value::String(backup_dir.clone()));
+
+        // Writes continue on the source after the backup.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(Query::Insert {
+            table: "Logs".to_string(),
+            values: vec![Value::Int32(6), Value::String("m6".to_string())],
+            returning: Vec::new(),
+        });
+        tx_manager.commit_transaction(tx).unwrap();
+
+        // The copy includes the rows that were still buffered when it was taken.
+        let (_schema, _storage, mut restored) = create_database(&backup_dir, &test_options()).unwrap();
+        let mut tx = restored.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT COUNT(*) FROM Logs").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT Msg FROM Logs WHERE ID = 5").unwrap());
+        let results = restored.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::Int32(5)], vec![Value::String("m5".to_string())]]);
+        // Block metadata in the copy is written to the copy, not the source.
+        let source_meta = fs::read_to_string(format!("{}/metadata/Logs/c2.json", data_dir)).unwrap();
+        let mut tx = restored.begin_transaction();
+        for i in 7..=9 {
+            tx.add_query(Query::Insert {
+                table: "Logs".to_string(),
+                values: vec![Value::Int32(i), Value::String(format!("m{}", i))],
+                returning: Vec::new(),
+            });
+        }
+        restored.commit_transaction(tx).unwrap();
+        assert_eq!(fs::read_to_string(format!("{}/metadata/Logs/c2.json", data_dir)).unwrap(), source_meta);
+
+        // A non-empty target is refused.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query(&format!("BACKUP TO \"{}\"", backup_dir)).unwrap());
+        assert!(matches!(tx_manager.commit_transaction(tx), Err(DbError::InvalidData(_))));
+
+        // Settings, jobs and migrations are copied with the tables; a BACKUP of
+        // its own copies the column data after letting go of the storage lock.
+        let second_dir = format!("{}_second", data_dir);
+        fs::write(format!("{}/config.json", data_dir), r#"{"segment_rows": 3}"#).unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("CREATE JOB stats EVERY \"1 hour\" DO ANALYZE Logs").unwrap();
+        tx_manager.commit_transaction(tx).unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql(&format!("BACKUP TO '{}'", second_dir)).unwrap();
+        let results = tx_manager.commit_transaction_results(tx).unwrap();
+        assert_eq!(results[0].columns[2].data_type, Some(DataType::Int64));
+        let Value::Int64(files) = results[0].rows[0][1] else { panic!("files should be Int64") };
+        for file in ["schema.json", "config.json", "jobs.json"] {
+            assert!(fs::metadata(format!("{}/{}", second_dir, file)).is_ok(), "{} missing", file);
+        }
+        fn count_files(dir: &std::path::Path) -> usize {
+            fs::read_dir(dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path())
+                .map(|path| if path.is_dir() { count_files(&path) } else { 1 })
+                .sum()
+        }
+        assert_eq!(files as usize, count_files(std::path::Path::new(&second_dir)));
+        assert_eq!(
+            fs::read(format!("{}/columns/Logs/c1.dat", second_dir)).unwrap(),
+            fs::read(format!("{}/columns/Logs/c1.dat", data_dir)).unwrap()
+        );
+
+        cleanup_test_db(&data_dir);
+        cleanup_test_db(&backup_dir);
+        cleanup_test_db(&second_dir);
+    }
+
+    #[test]
+    fn test_table_bundle_export_import() {
+        let (source_dir, _schema, _storage, mut source) = setup_test_db("bundle_src").unwrap();
+        let (target_dir, _schema, _storage, mut target) = setup_test_db("bundle_dst").unwrap();
+        let bundle_path = format!("{}/people.vddb", source_dir);
+        let mut tx = source.begin_transaction();
+        tx.add_query(Query::CreateTable {
+            table: "People".to_string(),
+            columns: vec![
+                Column::new("ID", DataType::Int32),
+                Column::new("Name", DataType::String),
+                Column::new("Score", DataType::Float32),
+            ],
+        });
+        for i in 1..=5 {
+            tx.add_query(Query::Insert {
+                table: "People".to_string(),
+                values: vec![Value::Int32(i), Value::String(format!("P{}", i)), Value::Float32(OrderedFloat(i as f32 / 2.0))],
+                returning: Vec::new(),
+            });
+        }
+        tx.add_query(query::parser::parse_query(&format!("EXPORT TABLE People TO '{}'", bundle_path)).unwrap());
+        let results = source.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::String("People".to_string()), Value::Int32(5)]]);
+
+        let mut tx = target.begin_transaction();
+        tx.add_query(query::parser::parse_query(&format!("IMPORT TABLE FROM '{}'", bundle_path)).unwrap());
+        tx.add_query(query::parser::parse_query("SELECT Name, Score FROM People WHERE ID = 4").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT COUNT(*) FROM People").unwrap());
+        let results = target.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::String("People".to_string()), Value::Int32(5)],
+                vec![Value::String("P4".to_string()), Value::Float32(OrderedFloat(2.0))],
+                vec![Value::Int32(5)],
+            ]
+        );
+
+        // The imported ID index still rejects duplicates, and re-importing is refused.
+        let mut tx = target.begin_transaction();
+        tx.add_query(Query::Insert {
+            table: "People".to_string(),
+            values: vec![Value::Int32(2), Value::String("Dup".to_string()), Value::Float32(OrderedFloat(0.0))],
+            returning: Vec::new(),
+        });
+        assert!(matches!(target.commit_transaction(tx), Err(DbError::InvalidData(_))));
+        let mut tx = target.begin_transaction();
+        tx.add_query(Query::ImportTable { path: bundle_path.clone() });
+        assert!(matches!(target.commit_transaction(tx), Err(DbError::InvalidData(_))));
+        let mut tx = target.begin_transaction();
+        tx.add_query(Query::ImportTable { path: format!("{}/schema.json", source_dir) });
+        assert!(matches!(target.commit_transaction(tx), Err(DbError::InvalidData(_))));
+
+        cleanup_test_db(&source_dir);
+        cleanup_test_db(&target_dir);
+    }
+
+    #[test]
+    fn test_null_values() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("null_values").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(
+            query::parser::parse_query("CREATE TABLE Readings (ID INT, Temp FLOAT, Site STRING NOT NULL)").unwrap(),
+        );
+        tx.add_query(query::parser::parse_query("INSERT INTO Readings VALUES (1, 10.0, \"a\")").unwrap());
+        tx.add_query(query::parser::parse_query("INSERT INTO Readings VALUES (2, NULL, \"b\")").unwrap());
+        tx.add_query(query::parser::parse_query("INSERT INTO Readings VALUES (3, 30.0, \"a\")").unwrap());
+        tx.add_query(query::parser::parse_query("INSERT INTO Readings VALUES (4, NULL, \"b\")").unwrap());
+        tx_manager.commit_transaction(tx).unwrap();
+
+        // Block 1 is flushed with a NULL in it; row 4 is still pending.
+        let temps = storage.lock().unwrap().read_column("Readings", "Temp", None).unwrap();
+        assert_eq!(temps[1], Value::Null);
+        assert_eq!(temps[3], Value::Null);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT ID FROM Readings WHERE Temp IS NULL").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT ID FROM Readings WHERE Temp IS NOT NULL").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT ID FROM Readings WHERE Temp = NULL").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT COUNT(*), SUM(Temp), AVG(Temp), MIN(Temp) FROM Readings").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        let float = |f: f32| Value::Float32(ordered_float::OrderedFloat(f));
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::Int32(2)],
+                vec![Value::Int32(4)],
+                vec![Value::Int32(1)],
+                vec![Value::Int32(3)],
+                vec![Value::Int32(4), float(40.0), float(20.0), float(10.0)],
+            ]
+        );
+
+        // COUNT(col) skips NULLs, from block metadata, read blocks and per group alike.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("SELECT COUNT(Temp), COUNT(*) FROM Readings").unwrap();
+        tx.add_sql("SELECT COUNT(Temp) FROM Readings WHERE ID > 1").unwrap();
+        tx.add_sql("SELECT Site, COUNT(Temp) FROM Readings GROUP BY Site").unwrap();
+        let results = tx_manager.commit_transaction_results(tx).unwrap();
+        assert_eq!(results[0].rows, vec![vec![Value::Int32(2), Value::Int32(4)]]);
+        assert_eq!(results[0].columns[0].name, "COUNT(Temp)");
+        assert_eq!(results[1].rows, vec![vec![Value::Int32(1)]]);
+        let mut groups = results[2].rows.clone();
+        groups.sort();
+        assert_eq!(
+            groups,
+            vec![
+                vec![Value::String("a".to_string()), Value::Int32(2)],
+                vec![Value::String("b".to_string()), Value::Int32(0)],
+            ]
+        );
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("INSERT INTO Readings VALUES (5, 1.0, NULL)").unwrap());
+        assert!(matches!(tx_manager.commit_transaction(tx), Err(DbError::InvalidData(_))));
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_extended_data_types() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("extended_types").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(
+            query::parser::parse_query("CREATE TABLE Ticks (ID INT, Seq BIGINT, Live BOOL, Day DATE, At TIMESTAMP)").unwrap(),
+        );
+        let rows = [
+            "(1, 5000000000, TRUE, \"2024-02-28\", \"2024-02-28 23:59:59\")",
+            "(2, 5000000001, FALSE, \"2024-02-29\", \"2024-02-29 00:00:00\")",
+            "(3, 7, TRUE, \"2024-03-01\", 1709251200)",
+            "(4, -7, FALSE, \"1969-12-31\", \"1969-12-31T12:00\")",
+        ];
+        for row in rows {
+            tx.add_query(query::parser::parse_query(&format!("INSERT INTO Ticks VALUES {}", row)).unwrap());
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        // Three rows are in a flushed block, the fourth is still pending.
+        let days = storage.lock().unwrap().read_column("Ticks", "Day", None).unwrap();
+        assert_eq!(days, vec![Value::Date(19781), Value::Date(19782), Value::Date(19783), Value::Date(-1)]);
+        assert_eq!(crate::datetime::format_date(19782), "2024-02-29");
+        assert_eq!(crate::datetime::format_timestamp(-43200), "1969-12-31 12:00:00");
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT ID FROM Ticks WHERE Seq > 4999999999").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT ID FROM Ticks WHERE Live = TRUE").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT ID FROM Ticks WHERE Day < \"2024-02-29\"").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT ID FROM Ticks WHERE At = \"2024-03-01T00:00:00\"").unwrap());
+        tx.add_query(query::parser::parse_query("SELECT SUM(Seq), MAX(At) FROM Ticks").unwrap());
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(
+            results,
+            vec![
+                vec![Value::Int32(1)],
+                vec![Value::Int32(2)],
+                vec![Value::Int32(1)],
+                vec![Value::Int32(3)],
+                vec![Value::Int32(1)],
+                vec![Value::Int32(4)],
+                vec![Value::Int32(3)],
+                vec![Value::Int64(10000000001), Value::Timestamp(1709251200)],
+            ]
+        );
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("INSERT INTO Ticks VALUES (5, 1, TRUE, \"2024-02-30\", 0)").unwrap());
+        assert!(matches!(tx_manager.commit_transaction(tx), Err(DbError::InvalidData(_))));
+
+        // SUM of a BIGINT column is exact, and fails instead of wrapping past its range.
+        let mut tx = tx_manager.begin_transaction();
+        let seq = i64::MAX - 5000000000;
+        tx.add_sql(&format!("INSERT INTO Ticks VALUES (5, {}, TRUE, \"2024-03-02\", 0)", seq)).unwrap();
+        tx.add_sql("SELECT SUM(Seq) FROM Ticks WHERE ID > 2").unwrap();
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::Int64(seq)]]);
+        for sql in ["SELECT SUM(Seq) FROM Ticks", "SELECT Live, SUM(Seq) FROM Ticks GROUP BY Live"] {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_sql(sql).unwrap();
+            assert!(matches!(tx_manager.commit_transaction(tx), Err(DbError::InvalidData(_))), "{}", sql);
+        }
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_expression_defaults() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("expr_defaults").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(
+            query::parser::parse_query(
+                "CREATE TABLE Audit (ID INT, Action STRING DEFAULT \"insert\", CreatedAt TIMESTAMP NOT NULL DEFAULT NOW(), Note STRING DEFAULT NULL)",
+            )
+            .unwrap(),
+        );
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let before = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("INSERT INTO Audit VALUES (1)").unwrap());
+        tx.add_query(query::parser::parse_query("INSERT INTO Audit VALUES (2, \"update\", 100)").unwrap());
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let mut storage_guard = storage.lock().unwrap();
+        let actions = storage_guard.read_column("Audit", "Action", None).unwrap();
+        assert_eq!(actions, vec![Value::String("insert".to_string()), Value::String("update".to_string())]);
+        let created = storage_guard.read_column("Audit", "CreatedAt", None).unwrap();
+        assert!(matches!(created[0], Value::Timestamp(t) if t >= before));
+        assert_eq!(created[1], Value::Timestamp(100));
+        assert_eq!(storage_guard.read_column("Audit", "Note", None).unwrap(), vec![Value::Null, Value::Null]);
+        assert!(matches!(
+            storage_guard.schema().get_table("Audit").unwrap().columns[2].default,
+            Some(crate::query::expr::Expr::CurrentTimestamp)
+        ));
+        drop(storage_guard);
+
+        // A default that cannot produce the column's type is rejected up front.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("CREATE TABLE Bad (ID INT, Day DATE DEFAULT NOW())").unwrap());
+        assert!(matches!(tx_manager.commit_transaction(tx), Err(DbError::TypeMismatch)));
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_order_by_limit_offset() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("order_by").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("CREATE TABLE Scores (ID INT, Team STRING, Points INT)").unwrap());
+        let rows = [(1, "red", 30), (2, "blue", 10), (3, "red", 20), (4, "blue", 30), (5, "green", 5), (6, "red", 10)];
+        for (id, team, points) in rows {
+            tx.add_query(Query::Insert {
+                table: "Scores".to_string(),
+                values: vec![Value::Int32(id), Value::String(team.to_string()), Value::Int32(points)],
+                returning: Vec::new(),
+            });
+        }
+        tx.add_query(query::parser::parse_query("INSERT INTO Scores VALUES (7, \"green\", NULL)").unwrap());
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let ids = |results: Vec<Vec<Value>>| -> Vec<i32> {
+            results
+                .into_iter()
+                .map(|row| match row[0] {
+                    Value::Int32(id) => id,
+                    _ => panic!("expected an ID"),
+                })
+                .collect()
+        };
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+
+        // Ties keep row order; NULL sorts last ascending and first descending.
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Scores ORDER BY Points")), vec![5, 2, 6, 3, 1, 4, 7]);
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Scores ORDER BY Points DESC")), vec![7, 1, 4, 3, 2, 6, 5]);
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Scores ORDER BY Team ASC, Points DESC LIMIT 4")),
+            vec![4, 2, 7, 5]
+        );
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Scores WHERE Team = \"red\" ORDER BY Points LIMIT 2 OFFSET 1")),
+            vec![3, 1]
+        );
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Scores LIMIT 2 OFFSET 5")), vec![6, 7]);
+        assert!(run(&mut tx_manager, "SELECT ID FROM Scores ORDER BY ID OFFSET 10").is_empty());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_dml_returning() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("dml_returning").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run(&mut tx_manager, "CREATE TABLE Jobs (ID INT, Name STRING, Tries INT DEFAULT 0)");
+
+        let inserted = run(&mut tx_manager, "INSERT INTO Jobs VALUES (1, \"build\") RETURNING ID, Tries");
+        assert_eq!(inserted, vec![vec![Value::Int32(1), Value::Int32(0)]]);
+        run(&mut tx_manager, "INSERT INTO Jobs VALUES (2, \"test\", 3)");
+        run(&mut tx_manager, "INSERT INTO Jobs VALUES (3, \"deploy\", 1)");
+        assert!(run(&mut tx_manager, "INSERT INTO Jobs VALUES (4, \"lint\", 0)").is_empty());
+
+        let deleted = run(&mut tx_manager, "DELETE FROM Jobs WHERE Tries > 0 RETURNING *");
+        assert_eq!(
+            deleted,
+            vec![
+                vec![Value::Int32(2), Value::String("test".to_string()), Value::Int32(3)],
+                vec![Value::Int32(3), Value::String("deploy".to_string()), Value::Int32(1)],
+            ]
+        );
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Jobs").len(), 2);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("DELETE FROM Jobs RETURNING Missing").unwrap());
+        assert!(tx_manager.commit_transaction(tx).is_err());
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Jobs").len(), 2);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_in_tuple_condition() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("in_tuple").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run(&mut tx_manager, "CREATE TABLE Orders (ID INT, Region STRING, Year INT)");
+        let rows = [(1, "eu", 2023), (2, "us", 2023), (3, "eu", 2024), (4, "us", 2024), (5, "eu", 2023)];
+        for (id, region, year) in rows {
+            run(&mut tx_manager, &format!("INSERT INTO Orders VALUES ({}, \"{}\", {})", id, region, year));
+        }
+
+        let ids = |rows: Vec<Vec<Value>>| -> Vec<Value> { rows.into_iter().map(|r| r[0].clone()).collect() };
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Orders WHERE (Region, Year) IN ((\"eu\", 2023), ('us', 2024))")),
+            vec![Value::Int32(1), Value::Int32(4), Value::Int32(5)]
+        );
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Orders WHERE (Region, Year) IN (('eu', 2024)) AND ID > 1")),
+            vec![Value::Int32(3)]
+        );
+        run(&mut tx_manager, "DELETE FROM Orders WHERE (ID, Year) IN ((2, 2023), (3, 2023))");
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Orders").len(), 4);
+
+        assert!(query::parser::parse_query("SELECT ID FROM Orders WHERE (Region, Year) IN ((\"eu\"))").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_nocase_collation() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("collation").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run(&mut tx_manager, "CREATE TABLE Users (ID INT, Name STRING COLLATE NOCASE, Code STRING)");
+        for (id, name) in [(1, "alice"), (2, "Bob"), (3, "ALICE"), (4, "carol"), (5, "bob")] {
+            run(&mut tx_manager, &format!("INSERT INTO Users VALUES ({}, \"{}\", \"{}\")", id, name, name));
+        }
+        run(&mut tx_manager, "MAKE INDEX ON Users (Name)");
+
+        let ids = |rows: Vec<Vec<Value>>| -> Vec<Value> { rows.into_iter().map(|r| r[0].clone()).collect() };
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Users WHERE Name = \"Alice\"")),
+            vec![Value::Int32(1), Value::Int32(3)]
+        );
+        // Other columns keep binary comparison.
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Users WHERE Code = \"Alice\"")), Vec::<Value>::new());
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Users ORDER BY Name")),
+            vec![Value::Int32(1), Value::Int32(3), Value::Int32(2), Value::Int32(5), Value::Int32(4)]
+        );
+        {
+            let storage_guard = storage.lock().unwrap();
+            let index = &storage_guard.indexes["Users"]["Name"];
+            let upper = index.lookup(&Value::String("BOB".to_string())).unwrap();
+            assert!(!upper.is_empty());
+            assert_eq!(upper, index.lookup(&Value::String("bob".to_string())).unwrap());
+        }
+
+        run(&mut tx_manager, "DELETE FROM Users WHERE Name = \"BOB\"");
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Users").len(), 3);
+        assert!(query::parser::parse_query("CREATE TABLE Bad (ID INT COLLATE NOCASE)").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_index_prunes_select_blocks() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("index_select").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run(&mut tx_manager, "CREATE TABLE Readings (ID INT, Sensor INT)");
+        // Every block spans 1..100, so zone maps alone can't skip any of them.
+        let sensors = [1, 50, 100, 2, 60, 99, 3, 70, 98];
+        for (id, sensor) in sensors.iter().enumerate() {
+            run(&mut tx_manager, &format!("INSERT INTO Readings VALUES ({}, {})", id, sensor));
+        }
+        run(&mut tx_manager, "MAKE INDEX ON Readings (Sensor)");
+
+        let condition = |sql: &str| match query::parser::parse_query(sql).unwrap() {
+            Query::Select { condition, .. } => condition.unwrap(),
+            _ => unreachable!(),
+        };
+        let mask = |sql: &str| storage.lock().unwrap().block_mask("Readings", Some(&condition(sql)));
+        assert_eq!(mask("SELECT ID FROM Readings WHERE Sensor = 60"), Some(vec![false, true, false]));
+        assert_eq!(mask("SELECT ID FROM Readings WHERE Sensor > 99"), Some(vec![true, false, false]));
+        assert_eq!(
+            mask("SELECT ID FROM Readings WHERE Sensor = 2 OR Sensor = 70"),
+            Some(vec![false, true, true])
+        );
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Sensor = 60"), vec![vec![Value::Int32(4)]]);
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Sensor < 3").len(), 2);
+
+        // Rewritten blocks move, and the index follows them.
+        run(&mut tx_manager, "UPDATE Readings SET Sensor = 61 WHERE ID = 4");
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Sensor = 61"), vec![vec![Value::Int32(4)]]);
+        run(&mut tx_manager, "DELETE FROM Readings WHERE ID < 3");
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Sensor = 70"), vec![vec![Value::Int32(7)]]);
+        assert!(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Sensor = 50").is_empty());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_hash_and_range_joins() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("joins").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run(&mut tx_manager, "CREATE TABLE Orders (ID INT, Customer INT, Total INT)");
+        run(&mut tx_manager, "CREATE TABLE Tiers (ID INT, Floor INT, Label STRING)");
+        for (id, customer, total) in [(1, 10, 50), (2, 11, 250), (3, 10, 120), (4, 12, 900), (5, 11, 5)] {
+            run(&mut tx_manager, &format!("INSERT INTO Orders VALUES ({}, {}, {})", id, customer, total));
+        }
+        for (id, floor, label) in [(10, 0, "basic"), (11, 100, "silver"), (12, 500, "gold")] {
+            run(&mut tx_manager, &format!("INSERT INTO Tiers VALUES ({}, {}, \"{}\")", id, floor, label));
+        }
+
+        let pairs = |rows: Vec<Vec<Value>>| -> Vec<(i32, String)> {
+            rows.into_iter()
+                .map(|r| match (&r[0], &r[1]) {
+                    (Value::Int32(id), Value::String(label)) => (*id, label.clone()),
+                    _ => panic!("unexpected row {:?}", r),
+                })
+                .collect()
+        };
+        // The larger side is probed, but rows still come out in left order.
+        assert_eq!(
+            pairs(run(&mut tx_manager, "SELECT Orders.ID, Tiers.Label FROM Orders JOIN Tiers ON Orders.Customer = Tiers.ID")),
+            vec![(1, "basic".into()), (2, "silver".into()), (3, "basic".into()), (4, "gold".into()), (5, "silver".into())]
+        );
+        // WHERE can reference either table and is applied to joined rows.
+        assert_eq!(
+            pairs(run(
+                &mut tx_manager,
+                "SELECT Orders.ID, Tiers.Label FROM Orders JOIN Tiers ON Orders.Customer = Tiers.ID WHERE Orders.Total > 100"
+            )),
+            vec![(2, "silver".into()), (3, "basic".into()), (4, "gold".into())]
+        );
+        assert_eq!(
+            pairs(run(&mut tx_manager, "SELECT Orders.ID, Tiers.Label FROM Orders JOIN Tiers ON Orders.Total > Tiers.Floor WHERE Orders.ID = 3")),
+            vec![(3, "basic".into()), (3, "silver".into())]
+        );
+        assert_eq!(
+            run(&mut tx_manager, "SELECT Orders.ID FROM Orders JOIN Tiers ON Orders.Total <= Tiers.Floor").len(),
+            6
+        );
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_regexp_condition() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("regexp").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run(&mut tx_manager, "CREATE TABLE Logs (ID INT, Line STRING)");
+        let lines = ["GET /api/v1/users 200", "POST /api/v2/orders 500", "GET /health 200", "GET /api/v1/orders 404"];
+        for (id, line) in lines.iter().enumerate() {
+            run(&mut tx_manager, &format!("INSERT INTO Logs VALUES ({}, \"{}\")", id, line));
+        }
+        run(&mut tx_manager, "INSERT INTO Logs VALUES (9, NULL)");
+
+        let ids = |rows: Vec<Vec<Value>>| -> Vec<Value> { rows.into_iter().map(|r| r[0].clone()).collect() };
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Logs WHERE Line REGEXP '^GET /api/v[0-9]+/'")),
+            vec![Value::Int32(0), Value::Int32(3)]
+        );
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Logs WHERE Line REGEXP '[45][0-9]{2}$' ORDER BY ID DESC")),
+            vec![Value::Int32(3), Value::Int32(1)]
+        );
+        run(&mut tx_manager, "DELETE FROM Logs WHERE Line REGEXP 'health'");
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Logs").len(), 4);
+
+        assert!(query::parser::parse_query("SELECT ID FROM Logs WHERE Line REGEXP '(unclosed'").is_err());
+        assert!(query::parser::parse_query("SELECT ID FROM Logs WHERE Line REGEXP 5").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_null_functions() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("null_functions").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run(&mut tx_manager, "CREATE TABLE Contacts (ID INT, Email STRING, Phone STRING, Score INT)");
+        run(&mut tx_manager, "INSERT INTO Contacts VALUES (1, \"a@x.io\", NULL, 0)");
+        run(&mut tx_manager, "INSERT INTO Contacts VALUES (2, NULL, \"555\", 7)");
+        run(&mut tx_manager, "INSERT INTO Contacts VALUES (3, NULL, NULL, NULL)");
+
+        let s = |v: &str| Value::String(v.to_string());
+        assert_eq!(
+            run(&mut tx_manager, "SELECT ID, COALESCE(Email, Phone, \"unknown\") FROM Contacts ORDER BY ID"),
+            vec![
+                vec![Value::Int32(1), s("a@x.io")],
+                vec![Value::Int32(2), s("555")],
+                vec![Value::Int32(3), s("unknown")],
+            ]
+        );
+        assert_eq!(
+            run(&mut tx_manager, "SELECT IFNULL(Score, -1), NULLIF(Score, 0) FROM Contacts"),
+            vec![
+                vec![Value::Int32(0), Value::Null],
+                vec![Value::Int32(7), Value::Int32(7)],
+                vec![Value::Int32(-1), Value::Null],
+            ]
+        );
+
+        assert!(query::parser::parse_query("SELECT NULLIF(Score) FROM Contacts").is_err());
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT COALESCE(Missing, 1) FROM Contacts").unwrap());
+        assert!(tx_manager.commit_transaction(tx).is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_atomic_transaction_rollback() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("atomic_rollback").unwrap();
+        let parse = |sql: &str| query::parser::parse_query(sql).unwrap();
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("CREATE TABLE Orders (ID INT, Total INT)"));
+        tx.add_query(parse("INSERT INTO Orders VALUES (1, 10)"));
+        tx.add_query(parse("INSERT INTO Missing VALUES (1)"));
+        assert!(tx_manager.commit_transaction(tx).is_err());
+        assert!(storage.lock().unwrap().schema().get_table("Orders").is_none());
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("CREATE TABLE Orders (ID INT, Total INT)"));
+        tx.add_query(parse("INSERT INTO Orders VALUES (1, 10)"));
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let mut tx = tx_manager.begin_transaction();
+        for id in 2..6 {
+            tx.add_query(parse(&format!("INSERT INTO Orders VALUES ({}, 20)", id)));
+        }
+        tx.add_query(parse("DELETE FROM Orders WHERE ID = 1"));
+        tx.add_query(parse("INSERT INTO Orders VALUES (\"bad\", 0)"));
+        assert!(tx_manager.commit_transaction(tx).is_err());
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("SELECT ID, Total FROM Orders"));
+        assert_eq!(tx_manager.commit_transaction(tx).unwrap(), vec![vec![Value::Int32(1), Value::Int32(10)]]);
+        assert_eq!(fs::read_dir(format!("{}/txn", data_dir)).unwrap().count(), 0);
+
+        // Only the tables a transaction writes are staged; others keep their buffered rows.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("CREATE TABLE Notes (ID INT)"));
+        tx.add_query(parse("INSERT INTO Notes VALUES (1)"));
+        tx_manager.commit_transaction(tx).unwrap();
+        let workspace = storage.lock().unwrap().stage(99, &[&parse("INSERT INTO Orders VALUES (9, 90)")]).unwrap();
+        assert!(fs::metadata(format!("{}/txn/99/columns/Orders", data_dir)).is_ok());
+        assert!(fs::metadata(format!("{}/txn/99/columns/Notes", data_dir)).is_err());
+        storage.lock().unwrap().apply_staged(workspace).unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        // Enough rows to append a block to the linked column files, which are cut back.
+        for id in 7..11 {
+            tx.add_query(parse(&format!("INSERT INTO Orders VALUES ({}, 70)", id)));
+        }
+        tx.add_query(parse("CREATE TABLE Extra (ID INT)"));
+        tx.add_query(parse("INSERT INTO Extra VALUES (\"bad\")"));
+        assert!(tx_manager.commit_transaction(tx).is_err());
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("SELECT COUNT(*) FROM Orders"));
+        tx.add_query(parse("SELECT ID FROM Notes"));
+        let results = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(results, vec![vec![Value::Int32(1)], vec![Value::Int32(1)]]);
+        assert!(storage.lock().unwrap().schema().get_table("Extra").is_none());
+        assert!(fs::metadata(format!("{}/columns/Extra", data_dir)).is_err());
+        let orders = storage.lock().unwrap().columns["Orders"]["ID"].file_path.clone();
+        let last = storage.lock().unwrap().columns["Orders"]["ID"].metadata.blocks.last().cloned();
+        let end = last.map_or(0, |b| b.offset + b.serialized_size.unwrap_or(0) as u64);
+        assert_eq!(fs::metadata(&orders).unwrap().len(), end);
+
+        // The trash, snapshots and files CLEANUP removes are put back too.
+        let stray = format!("{}/columns/Stray.dat", data_dir);
+        fs::write(&stray, "left over").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("DROP TABLE Notes"));
+        tx.add_query(parse("CREATE SNAPSHOT before_bad"));
+        tx.add_query(parse("CLEANUP"));
+        tx.add_query(parse("INSERT INTO Orders VALUES (\"bad\", 0)"));
+        assert!(tx_manager.commit_transaction(tx).is_err());
+        assert!(fs::metadata(&stray).is_ok());
+        assert!(fs::metadata(format!("{}/snapshots/before_bad", data_dir)).is_err());
+        assert!(fs::metadata(format!("{}/trash/Notes", data_dir)).is_err());
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("DROP TABLE Notes"));
+        tx_manager.commit_transaction(tx).unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("UNDROP TABLE Notes"));
+        tx.add_query(parse("INSERT INTO Notes VALUES (\"bad\")"));
+        assert!(tx_manager.commit_transaction(tx).is_err());
+        assert!(storage.lock().unwrap().schema().get_table("Notes").is_none());
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(parse("UNDROP TABLE Notes"));
+        tx.add_query(parse("SELECT ID FROM Notes"));
+        assert_eq!(tx_manager.commit_transaction(tx).unwrap(), vec![vec![Value::Int32(1)]]);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_verify_table() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("verify_table").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Readings (ID INT, Temp INT)").unwrap();
+        for (id, temp) in [(1, "20"), (2, "-300"), (3, "25"), (4, "NULL"), (5, "900")] {
+            run(&mut tx_manager, &format!("INSERT INTO Readings VALUES ({}, {})", id, temp)).unwrap();
+        }
+
+        let violations = run(&mut tx_manager, "VERIFY TABLE Readings CHECK (Temp > -100 AND Temp < 100)").unwrap();
+        assert_eq!(
+            violations,
+            vec![
+                vec![Value::Int32(2), Value::Int32(-300)],
+                vec![Value::Int32(4), Value::Null],
+                vec![Value::Int32(5), Value::Int32(900)],
+            ]
+        );
+        let limited = run(&mut tx_manager, "VERIFY TABLE Readings CHECK (Temp IS NOT NULL) LIMIT 1").unwrap();
+        assert_eq!(limited, vec![vec![Value::Int32(4), Value::Null]]);
+        assert!(run(&mut tx_manager, "VERIFY TABLE Readings CHECK (ID > 0)").unwrap().is_empty());
+
+        assert!(query::parser::parse_query("VERIFY TABLE Readings CHECK Temp > 0").is_err());
+        assert!(run(&mut tx_manager, "VERIFY TABLE Readings CHECK (Missing > 0)").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_query_result_columns() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("result_columns").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction_results(tx).unwrap()
+        };
+        run("CREATE TABLE People (ID INT, Name STRING, Born DATE)");
+        run("INSERT INTO People VALUES (1, \"Ada\", \"1815-12-10\")");
+
+        let column = |name: &str, data_type: Option<DataType>| ResultColumn {
+            name: name.to_string(),
+            data_type,
+        };
+        let results = run("SELECT * FROM People");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].column_names(), vec!["ID", "Name", "Born"]);
+        assert_eq!(results[0].columns[2], column("Born", Some(DataType::Date)));
+
+        let results = run("SELECT Name, COALESCE(NULL, ID) FROM People WHERE ID > 5");
+        assert!(results[0].rows.is_empty());
+        assert_eq!(
+            results[0].columns,
+            vec![column("Name", Some(DataType::String)), column("COALESCE(NULL, ID)", Some(DataType::Int32))]
+        );
+
+        let results = run("SELECT COUNT(*), AVG(ID) FROM People");
+        assert_eq!(results[0].column_names(), vec!["COUNT(*)", "AVG(ID)"]);
+        assert!(results[0].columns.iter().all(|c| c.data_type.is_some()));
+
+        let results = run("DELETE FROM People WHERE ID = 1 RETURNING Name");
+        assert_eq!(results[0].columns, vec![column("Name", Some(DataType::String))]);
+        assert_eq!(results[0].rows, vec![vec![Value::String("Ada".to_string())]]);
+        assert!(run("DROP TABLE People").is_empty());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_prepared_queries() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("prepared_queries").unwrap();
+        let mut run = |query: Query| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query);
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run(query::parser::parse_query("CREATE TABLE Notes (ID INT, Body STRING, Day DATE)").unwrap());
+
+        let insert = PreparedQuery::new("INSERT INTO Notes VALUES (?, ?, ?)").unwrap();
+        assert_eq!(insert.param_count(), 3);
+        let s = |v: &str| Value::String(v.to_string());
+        for (id, body) in [(1, "it's \"quoted\", with commas"), (2, "what? no"), (3, "x")] {
+            run(insert.bind(&[Value::Int32(id), s(body), s("2024-03-01")]).unwrap());
+        }
+
+        let select = PreparedQuery::new("SELECT Body, \"?\" FROM Notes WHERE ID = ? AND Day = ?").unwrap();
+        assert_eq!(select.param_count(), 2);
+        assert_eq!(
+            run(select.bind(&[Value::Int32(1), s("2024-03-01")]).unwrap()),
+            vec![vec![s("it's \"quoted\", with commas"), s("?")]]
+        );
+        let matching = PreparedQuery::new("SELECT ID FROM Notes WHERE Body REGEXP ?").unwrap();
+        assert_eq!(run(matching.bind(&[s("\\?")]).unwrap()), vec![vec![Value::Int32(2)]]);
+
+        assert!(insert.bind(&[Value::Int32(4)]).is_err());
+        assert!(PreparedQuery::new("SELECT ID FROM Notes LIMIT ?").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_optimizer_hints() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("optimizer_hints").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Codes (ID INT, Code INT)").unwrap();
+        run(&mut tx_manager, "CREATE TABLE Labels (Code INT, Label STRING)").unwrap();
+        // Every block's min/max spans 4, but only the third holds it.
+        for (id, code) in [1, 5, 9, 2, 6, 8, 3, 4, 7].into_iter().enumerate() {
+            run(&mut tx_manager, &format!("INSERT INTO Codes VALUES ({}, {})", id, code)).unwrap();
+        }
+        run(&mut tx_manager, "INSERT INTO Labels VALUES (4, \"four\")").unwrap();
+        run(&mut tx_manager, "MAKE INDEX ON Codes (Code)").unwrap();
+
+        let hinted = query::parser::parse_query("SELECT /*+ NO_INDEX(Codes) */ ID FROM Codes WHERE Code = 4").unwrap();
+        let Query::Select { hints, condition, .. } = hinted else {
+            panic!("expected a SELECT");
+        };
+        assert_eq!(hints, vec![query::Hint::NoIndex("Codes".to_string())]);
+        {
+            let storage_guard = storage.lock().unwrap();
+            let condition = condition.as_ref();
+            assert_eq!(storage_guard.block_mask_with("Codes", condition, true), Some(vec![false, false, true]));
+            assert_eq!(storage_guard.block_mask_with("Codes", condition, false), Some(vec![true, true, true]));
+        }
+        let expected = vec![vec![Value::Int32(7)]];
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Codes WHERE Code = 4").unwrap(), expected);
+        assert_eq!(
+            run(&mut tx_manager, "SELECT /*+ NO_INDEX(Codes) */ ID FROM Codes WHERE Code = 4").unwrap(),
+            expected
+        );
+
+        let join = "Codes.ID, Labels.Label FROM Codes JOIN Labels ON Codes.Code = Labels.Code";
+        let merged = run(&mut tx_manager, &format!("SELECT /*+ MERGE_JOIN */ {}", join)).unwrap();
+        assert_eq!(merged, vec![vec![Value::Int32(7), Value::String("four".to_string())]]);
+        assert_eq!(run(&mut tx_manager, &format!("SELECT {}", join)).unwrap(), merged);
+        assert!(run(
+            &mut tx_manager,
+            "SELECT /*+ HASH_JOIN */ Codes.ID FROM Codes JOIN Labels ON Codes.Code < Labels.Code"
+        )
+        .is_err());
+        assert!(query::parser::parse_query("SELECT /*+ FULL_SCAN */ ID FROM Codes").is_err());
+        assert!(query::parser::parse_query("SELECT /*+ HASH_JOIN ID FROM Codes").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_copy_from_and_bulk_load() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("copy_from").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Staff (ID INT, Name STRING, Hired DATE)").unwrap();
+        run(&mut tx_manager, "MAKE INDEX ON Staff (ID)").unwrap();
+
+        let csv_path = format!("{}/staff.csv", data_dir);
+        fs::write(&csv_path, "ID,Name,Hired\n1,Ann,2020-01-06\n2,\"Lee, Jr\",2021-07-01\n").unwrap();
+        let loaded = run(&mut tx_manager, &format!("COPY Staff FROM '{}'", csv_path)).unwrap();
+        assert_eq!(loaded, vec![vec![Value::String("Staff".to_string()), Value::Int32(2)]]);
+
+        fs::write(&csv_path, "ID,Name,Hired\n3,Kim,2022-02-02\n4,Bo,not a date\n").unwrap();
+        let err = run(&mut tx_manager, &format!("COPY Staff FROM '{}' FORMAT csv", csv_path)).unwrap_err();
+        assert!(err.to_string().contains("Line 3"), "{}", err);
+
+        let s = |v: &str| Value::String(v.to_string());
+        let rows = vec![vec![Value::Int32(5), s("Sam"), s("2023-03-03")], vec![Value::Int32(1), s("Dup"), Value::Null]];
+        let err = storage.lock().unwrap().bulk_load("Staff", rows).unwrap_err();
+        assert!(err.to_string().contains("Duplicate ID"), "{}", err);
+        let rows = vec![vec![Value::Int32(5), s("Sam"), s("2023-03-03")], vec![s("six"), s("Pat"), Value::Null]];
+        let err = storage.lock().unwrap().bulk_load("Staff", rows).unwrap_err();
+        assert!(err.to_string().contains("Row 2"), "{}", err);
+        let rows = (5..2100).map(|id| vec![Value::Int32(id), s("Sam"), s("2023-03-03")]).collect();
+        assert_eq!(storage.lock().unwrap().bulk_load("Staff", rows).unwrap(), 2095);
+
+        assert_eq!(
+            run(&mut tx_manager, "SELECT Name, Hired FROM Staff WHERE ID = 2").unwrap(),
+            vec![vec![s("Lee, Jr"), Value::Date(18809)]]
+        );
+        assert_eq!(run(&mut tx_manager, "SELECT COUNT(*) FROM Staff").unwrap(), vec![vec![Value::Int32(2097)]]);
+        assert_eq!(run(&mut tx_manager, "SELECT ID FROM Staff WHERE ID = 2000").unwrap(), vec![vec![Value::Int32(2000)]]);
+        assert!(query::parser::parse_query("COPY Staff FROM 'x.parquet' FORMAT parquet").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_show_settings() {
+        let (data_dir, _schema, storage, tx_manager) = setup_test_db("show_settings").unwrap();
+        drop((storage, tx_manager));
+        fs::write(format!("{}/config.json", data_dir), r#"{"segment_rows": 5, "sync_mode": "normal"}"#).unwrap();
+        let (_schema, storage, mut tx_manager) = create_database(&data_dir, &test_options()).unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        let setting = |tx_manager: &mut TransactionManager, name: &str| {
+            let rows = run(tx_manager, "SHOW SETTINGS").unwrap();
+            let row = rows.into_iter().find(|r| r[0] == Value::String(name.to_string())).unwrap();
+            (row[1].to_string(), row[2].to_string())
+        };
+        assert_eq!(setting(&mut tx_manager, "segment_rows"), ("5".to_string(), "config".to_string()));
+        assert_eq!(setting(&mut tx_manager, "sync_mode"), ("normal".to_string(), "config".to_string()));
+        assert_eq!(setting(&mut tx_manager, "buffer_cache_bytes").1, "default");
+
+        run(&mut tx_manager, "SET sync_mode = 'full'").unwrap();
+        run(&mut tx_manager, "SET parallelism TO 2").unwrap();
+        assert_eq!(setting(&mut tx_manager, "sync_mode"), ("full".to_string(), "session".to_string()));
+        assert_eq!(setting(&mut tx_manager, "parallelism"), ("2".to_string(), "session".to_string()));
+        assert!(run(&mut tx_manager, "SET segment_rows = 0").is_err());
+        assert!(run(&mut tx_manager, "SET page_size = 4096").is_err());
+
+        // Rows are buffered until segment_rows of them are pending.
+        run(&mut tx_manager, "CREATE TABLE T (ID INT)").unwrap();
+        for id in 0..4 {
+            run(&mut tx_manager, &format!("INSERT INTO T VALUES ({})", id)).unwrap();
+        }
+        let blocks = |storage: &Arc<Mutex<StorageManager>>| {
+            storage.lock().unwrap().columns["T"]["ID"].metadata.blocks.len()
+        };
+        assert_eq!(blocks(&storage), 0);
+        run(&mut tx_manager, "INSERT INTO T VALUES (4)").unwrap();
+        assert_eq!(blocks(&storage), 1);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_copy_to_csv_and_json() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("copy_to").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Staff (ID INT, Name STRING, Hired DATE)").unwrap();
+        run(&mut tx_manager, "INSERT INTO Staff VALUES (1, \"Ann\", \"2020-01-06\")").unwrap();
+        let row = vec![Value::Int32(2), Value::String("Lee, Jr".to_string()), Value::Null];
+        storage.lock().unwrap().bulk_load("Staff", vec![row]).unwrap();
+
+        let csv_path = format!("{}/out.csv", data_dir);
+        let written = run(&mut tx_manager, &format!("COPY (SELECT ID, Name, Hired FROM Staff) TO '{}'", csv_path)).unwrap();
+        assert_eq!(written, vec![vec![Value::String(csv_path.clone()), Value::Int32(2)]]);
+        assert_eq!(
+            fs::read_to_string(&csv_path).unwrap(),
+            "ID,Name,Hired\n1,Ann,2020-01-06\n2,\"Lee, Jr\",\n"
+        );
+
+        run(&mut tx_manager, "CREATE TABLE Copy (CopyID INT, CopyName STRING, CopyHired DATE)").unwrap();
+        run(&mut tx_manager, &format!("COPY Copy FROM '{}' FORMAT csv", csv_path)).unwrap();
+        assert_eq!(
+            run(&mut tx_manager, "SELECT * FROM Copy").unwrap(),
+            run(&mut tx_manager, "SELECT * FROM Staff").unwrap()
+        );
+
+        let json_path = format!("{}/out.json", data_dir);
+        run(&mut tx_manager, &format!("COPY (SELECT * FROM Staff WHERE ID = 2) TO '{}' FORMAT json", json_path)).unwrap();
+        assert_eq!(
+            fs::read_to_string(&json_path).unwrap(),
+            "{\"Hired\":null,\"ID\":2,\"Name\":\"Lee, Jr\"}\n"
+        );
+
+        assert!(query::parser::parse_query("COPY (DROP TABLE Staff) TO 'x.csv'").is_err());
+        assert!(query::parser::parse_query("COPY Staff FROM 'x.json' FORMAT json").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_explain_json_plan() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("explain_json").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Scores (ID INT, Points INT)").unwrap();
+        for id in 1..=10 {
+            run(&mut tx_manager, &format!("INSERT INTO Scores VALUES ({}, {})", id, id * 10)).unwrap();
+        }
+
+        let text = run(&mut tx_manager, "EXPLAIN SELECT ID FROM Scores WHERE Points > 70 ORDER BY ID DESC LIMIT 2").unwrap();
+        let text: Vec<String> = text.iter().map(|row| row[0].to_string()).collect();
+        assert_eq!(
+            text,
+            vec![
+                "Project: ID (estimated rows: ?)",
+                "  Limit: LIMIT 2 (estimated rows: ?)",
+                "    Sort: ID DESC (estimated rows: ?)",
+                "      Filter [Points > 70] (estimated rows: ?)",
+                "        Scan on Scores (blocks: 1 of 3, estimated rows: 10)",
+            ]
+        );
+
+        run(&mut tx_manager, "ANALYZE Scores").unwrap();
+        let json = run(&mut tx_manager, "EXPLAIN ANALYZE FORMAT JSON SELECT ID FROM Scores WHERE Points > 70").unwrap();
+        let Value::String(json) = &json[0][0] else {
+            panic!("expected a JSON string, got {:?}", json);
+        };
+        let plan: PlanNode = serde_json::from_str(json).unwrap();
+        assert_eq!(plan.operator, "Project");
+        assert_eq!(plan.actual_rows, Some(3));
+        let filter = &plan.children[0];
+        assert_eq!(filter.operator, "Filter");
+        assert_eq!(filter.predicate.as_deref(), Some("Points > 70"));
+        assert!(filter.estimated_rows.is_some());
+        assert_eq!(filter.actual_rows, Some(3));
+        let scan = &filter.children[0];
+        assert_eq!((scan.operator.as_str(), scan.table.as_deref()), ("Scan", Some("Scores")));
+        assert_eq!((scan.estimated_rows, scan.actual_rows), (Some(10.0), Some(10)));
+
+        run(&mut tx_manager, "CREATE TABLE Teams (TeamID INT, Lead INT)").unwrap();
+        run(&mut tx_manager, "INSERT INTO Teams VALUES (1, 2)").unwrap();
+        let json = run(
+            &mut tx_manager,
+            "EXPLAIN ANALYZE FORMAT JSON SELECT Scores.ID FROM Scores JOIN Teams ON Scores.ID = Teams.Lead",
+        )
+        .unwrap();
+        let Value::String(json) = &json[0][0] else {
+            panic!("expected a JSON string, got {:?}", json);
+        };
+        let plan: PlanNode = serde_json::from_str(json).unwrap();
+        let join = &plan.children[0];
+        assert_eq!(join.operator, "Hash Join");
+        assert_eq!(join.detail.as_deref(), Some("Scores.ID = Teams.Lead"));
+        assert_eq!(join.actual_rows, Some(1));
+        assert_eq!(join.children.len(), 2);
+
+        // An index on a condition column turns the scan into an index scan.
+        run(&mut tx_manager, "MAKE INDEX ON Scores (ID)").unwrap();
+        let scan_line = |tx_manager: &mut TransactionManager, sql: &str| {
+            run(tx_manager, sql).unwrap().last().unwrap()[0].to_string()
+        };
+        assert_eq!(
+            scan_line(&mut tx_manager, "EXPLAIN SELECT Points FROM Scores WHERE ID = 5"),
+            "    Index Scan on Scores: index on ID (blocks: 1 of 3, estimated rows: 10)"
+        );
+        assert_eq!(
+            scan_line(&mut tx_manager, "EXPLAIN SELECT /*+ NO_INDEX(Scores) */ Points FROM Scores WHERE ID < 3"),
+            "    Scan on Scores (blocks: 1 of 3, estimated rows: 10)"
+        );
+
+        assert!(query::parser::parse_query("EXPLAIN DROP TABLE Scores").is_err());
+        assert!(query::parser::parse_query("EXPLAIN FORMAT XML SELECT * FROM Scores").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_alter_table_columns() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("alter_table").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Pets (ID INT, Kind STRING)").unwrap();
+        // Three rows fill a block, the fourth stays buffered.
+        for (id, kind) in [(1, "cat"), (2, "dog"), (3, "cat"), (4, "eel")] {
+            run(&mut tx_manager, &format!("INSERT INTO Pets VALUES ({}, \"{}\")", id, kind)).unwrap();
+        }
+
+        run(&mut tx_manager, "ALTER TABLE Pets ADD COLUMN Legs INT DEFAULT 4").unwrap();
+        run(&mut tx_manager, "ALTER TABLE Pets ADD Owner STRING").unwrap();
+        run(&mut tx_manager, "INSERT INTO Pets VALUES (5, \"ant\", 6, \"Kim\")").unwrap();
+        assert_eq!(
+            run(&mut tx_manager, "SELECT ID, Legs, Owner FROM Pets").unwrap(),
+            (1..=5)
+                .map(|id| match id {
+                    5 => vec![Value::Int32(5), Value::Int32(6), Value::String("Kim".to_string())],
+                    _ => vec![Value::Int32(id), Value::Int32(4), Value::Null],
+                })
+                .collect::<Vec<_>>()
+        );
+        assert!(run(&mut tx_manager, "ALTER TABLE Pets ADD COLUMN Tag INT NOT NULL").is_err());
+        assert!(run(&mut tx_manager, "ALTER TABLE Pets ADD COLUMN Kind STRING").is_err());
+
+        run(&mut tx_manager, "ALTER TABLE Pets DROP COLUMN Kind").unwrap();
+        assert!(fs::metadata(format!("{}/columns/Pets/c2.dat", data_dir)).is_err());
+        run(&mut tx_manager, "ALTER TABLE Pets RENAME COLUMN Legs TO Feet").unwrap();
+        assert_eq!(
+            run(&mut tx_manager, "SELECT ID FROM Pets WHERE Feet = 6").unwrap(),
+            vec![vec![Value::Int32(5)]]
+        );
+        assert!(run(&mut tx_manager, "SELECT Legs FROM Pets").is_err());
+        assert!(run(&mut tx_manager, "ALTER TABLE Pets DROP COLUMN Kind").is_err());
+
+        let schema = Schema::load(&data_dir).unwrap();
+        let names: Vec<&str> = schema.get_table("Pets").unwrap().columns.iter().map(|c| c.name.as_str()).collect();
+        assert_eq!(names, vec!["ID", "Feet", "Owner"]);
+        let mut reopened = StorageManager::new_read_only(&data_dir, schema, &test_options()).unwrap();
+        assert_eq!(
+            reopened.read_column("Pets", "Feet", None).unwrap(),
+            vec![Value::Int32(4), Value::Int32(4), Value::Int32(4)]
+        );
+        assert!(query::parser::parse_query("ALTER TABLE Pets RENAME Feet Paws").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_deterministic_order_setting() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("deterministic_order").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        let ids = |rows: Vec<Vec<Value>>| rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>();
+        run(&mut tx_manager, "CREATE TABLE Jobs (ID INT, Owner INT)").unwrap();
+        run(&mut tx_manager, "CREATE TABLE Owners (OwnerID INT)").unwrap();
+        for (id, owner) in [(3, 1), (1, 2), (2, 1)] {
+            run(&mut tx_manager, &format!("INSERT INTO Jobs VALUES ({}, {})", id, owner)).unwrap();
+        }
+        for owner in [2, 1] {
+            run(&mut tx_manager, &format!("INSERT INTO Owners VALUES ({})", owner)).unwrap();
+        }
+        let join = "SELECT Jobs.ID FROM Jobs JOIN Owners ON Jobs.Owner = Owners.OwnerID";
+
+        // Without the setting, rows come back in storage order.
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Jobs").unwrap()),
+            vec![Value::Int32(3), Value::Int32(1), Value::Int32(2)]
+        );
+
+        run(&mut tx_manager, "SET deterministic_order = on").unwrap();
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Jobs").unwrap()),
+            vec![Value::Int32(1), Value::Int32(2), Value::Int32(3)]
+        );
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Jobs LIMIT 1").unwrap()), vec![Value::Int32(1)]);
+        assert_eq!(
+            ids(run(&mut tx_manager, join).unwrap()),
+            vec![Value::Int32(1), Value::Int32(2), Value::Int32(3)]
+        );
+        // An explicit ORDER BY still wins.
+        assert_eq!(
+            ids(run(&mut tx_manager, "SELECT ID FROM Jobs ORDER BY ID DESC").unwrap()),
+            vec![Value::Int32(3), Value::Int32(2), Value::Int32(1)]
+        );
+        assert!(run(&mut tx_manager, "SET deterministic_order = maybe").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_column_stats_system_table() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("column_stats").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Cars (ID INT, Color STRING)").unwrap();
+        for id in 0..40 {
+            let color = match id % 8 {
+                0..=4 => "red",
+                5 | 6 => "blue",
+                _ => "green",
+            };
+            run(&mut tx_manager, &format!("INSERT INTO Cars VALUES ({}, \"{}\")", id, color)).unwrap();
+        }
+        let stats_sql = "SELECT position, lower, rows FROM information_schema.column_stats WHERE column = \"Color\"";
+        assert!(run(&mut tx_manager, stats_sql).unwrap().is_empty());
+
+        run(&mut tx_manager, "ANALYZE Cars").unwrap();
+        let top = run(&mut tx_manager, &format!("{} AND kind = \"top_value\"", stats_sql)).unwrap();
+        let s = |v: &str| Value::String(v.to_string());
+        assert_eq!(
+            top,
+            vec![
+                vec![Value::Int32(1), s("red"), Value::Int32(25)],
+                vec![Value::Int32(2), s("blue"), Value::Int32(10)],
+                vec![Value::Int32(3), s("green"), Value::Int32(5)],
+            ]
+        );
+        let buckets = run(
+            &mut tx_manager,
+            "SELECT lower, upper, rows FROM information_schema.column_stats WHERE column = \"ID\" AND kind = \"histogram\"",
+        )
+        .unwrap();
+        assert_eq!(buckets.len(), 10);
+        assert_eq!(buckets[0], vec![s("0"), s("3"), Value::Int32(4)]);
+
+        // The planner uses the frequent values and the histogram too.
+        let stats = storage.lock().unwrap().table_statistics("Cars").unwrap().unwrap();
+        let red = stats.estimate_rows(Some(&Condition::Equal("Color".to_string(), s("red"))));
+        assert!((red - 25.0).abs() < 0.01, "estimate {}", red);
+        let low = stats.estimate_rows(Some(&Condition::LessThan("ID".to_string(), Value::Int32(10))));
+        assert!((low - 10.0).abs() < 1.5, "estimate {}", low);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_primary_key_and_unique_constraints() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("constraints").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Users (UserKey INT PRIMARY KEY, Email STRING UNIQUE COLLATE NOCASE, ID INT)")
+            .unwrap();
+        {
+            let storage = storage.lock().unwrap();
+            let users = storage.schema().get_table("Users").unwrap();
+            assert!(users.is_unique("UserKey") && users.is_unique("Email"));
+            // A declared primary key replaces the implicit one on ID.
+            assert!(!users.is_unique("ID"));
+            assert!(!users.get_column("UserKey").unwrap().nullable);
+            assert!(storage.indexes["Users"].contains_key("Email"));
+        }
+
+        run(&mut tx_manager, "INSERT INTO Users VALUES (1, \"ann@x.org\", 7)").unwrap();
+        // Still buffered, but already taken.
+        let err = run(&mut tx_manager, "INSERT INTO Users VALUES (1, \"bo@x.org\", 7)").unwrap_err();
+        assert!(err.to_string().contains("Duplicate UserKey"), "{}", err);
+        let err = run(&mut tx_manager, "INSERT INTO Users VALUES (2, \"ANN@x.org\", 7)").unwrap_err();
+        assert!(err.to_string().contains("Duplicate Email"), "{}", err);
+        run(&mut tx_manager, "INSERT INTO Users VALUES (2, NULL, 7)").unwrap();
+        run(&mut tx_manager, "INSERT INTO Users VALUES (3, NULL, 7)").unwrap();
+        assert!(run(&mut tx_manager, "INSERT INTO Users VALUES (NULL, \"cy@x.org\", 7)").is_err());
+
+        // Flushed rows are checked through the index.
+        let err = run(&mut tx_manager, "INSERT INTO Users VALUES (4, \"ann@x.org\", 7)").unwrap_err();
+        assert!(err.to_string().contains("Duplicate Email"), "{}", err);
+        let err = run(&mut tx_manager, "UPDATE Users SET Email = \"ann@x.org\" WHERE UserKey = 2").unwrap_err();
+        assert!(err.to_string().contains("Duplicate Email"), "{}", err);
+        run(&mut tx_manager, "UPDATE Users SET Email = \"dee@x.org\" WHERE UserKey = 2").unwrap();
+        let rows = vec![vec![Value::Int32(5), Value::String("DEE@x.org".to_string()), Value::Int32(7)]];
+        let err = storage.lock().unwrap().bulk_load("Users", rows).unwrap_err();
+        assert!(err.to_string().contains("Duplicate Email"), "{}", err);
+        assert!(run(&mut tx_manager, "UNMAKE INDEX Email ON Users").is_err());
+
+        assert!(run(&mut tx_manager, "CREATE TABLE Twice (A INT PRIMARY KEY, B INT PRIMARY KEY)").is_err());
+        assert!(run(&mut tx_manager, "ALTER TABLE Users ADD COLUMN Badge INT UNIQUE DEFAULT 1").is_err());
+        run(&mut tx_manager, "ALTER TABLE Users ADD COLUMN Badge INT UNIQUE").unwrap();
+        run(&mut tx_manager, "INSERT INTO Users VALUES (6, \"fay@x.org\", 7, 1)").unwrap();
+        assert!(run(&mut tx_manager, "INSERT INTO Users VALUES (8, \"gus@x.org\", 7, 1)").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_inclusive_range_pruning() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("inclusive_ranges").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Readings (ID INT, Level INT)").unwrap();
+        // Three blocks: Level 1-3, 4-6 and 7-9, plus a NULL in the last one.
+        for id in 1..=9 {
+            let level = if id == 9 { "NULL".to_string() } else { id.to_string() };
+            run(&mut tx_manager, &format!("INSERT INTO Readings VALUES ({}, {})", id, level)).unwrap();
+        }
+        let ids = |rows: Vec<Vec<Value>>| rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>();
+        let range = |from: i32, to: i32| (from..=to).map(Value::Int32).collect::<Vec<_>>();
+
+        // Bounds that fall inside a block must keep that block.
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Level >= 5").unwrap()), range(5, 8));
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Level <= 5").unwrap()), range(1, 5));
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Level >= 4").unwrap()), range(4, 8));
+        assert_eq!(ids(run(&mut tx_manager, "SELECT ID FROM Readings WHERE Level <= 6").unwrap()), range(1, 6));
+        assert_eq!(
+            run(&mut tx_manager, "SELECT COUNT(*) FROM Readings WHERE Level >= 3").unwrap(),
+            vec![vec![Value::Int32(6)]]
+        );
+
+        // Blocks entirely outside the bound are still skipped.
+        let mask = |condition: Condition| storage.lock().unwrap().block_mask("Readings", Some(&condition)).unwrap();
+        let level = || "Level".to_string();
+        assert_eq!(mask(Condition::GreaterThanOrEqual(level(), Value::Int32(7))), vec![false, false, true]);
+        assert_eq!(mask(Condition::GreaterThanOrEqual(level(), Value::Int32(6))), vec![false, true, true]);
+        assert_eq!(mask(Condition::LessThanOrEqual(level(), Value::Int32(3))), vec![true, false, false]);
+        assert_eq!(mask(Condition::LessThanOrEqual(level(), Value::Int32(4))), vec![true, true, false]);
+
+        // Row evaluation: inclusive at the bound, and NULL never matches.
+        let values = std::collections::HashMap::from([("Level".to_string(), vec![Value::Int32(5), Value::Null])]);
+        let eval = |condition: &Condition, row: usize| {
+            query::evaluator::evaluate_condition_row(condition, &values, &std::collections::HashMap::new(), row).unwrap()
+        };
+        let ge = Condition::GreaterThanOrEqual(level(), Value::Int32(5));
+        let le = Condition::LessThanOrEqual(level(), Value::Int32(5));
+        assert!(eval(&ge, 0) && eval(&le, 0));
+        assert!(!eval(&ge, 1) && !eval(&le, 1));
+        assert!(!eval(&Condition::GreaterThanOrEqual(level(), Value::Int32(6)), 0));
+        assert!(!eval(&Condition::LessThanOrEqual(level(), Value::Int32(4)), 0));
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_read_transaction_snapshot() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("read_snapshot").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        let parse = |sql: &str| query::parser::parse_query(sql).unwrap();
+        run(&mut tx_manager, "CREATE TABLE Ledger (Entry INT, Amount INT)").unwrap();
+        // Three flushed rows and one still buffered.
+        for entry in 1..=4 {
+            run(&mut tx_manager, &format!("INSERT INTO Ledger VALUES ({}, {})", entry, entry * 10)).unwrap();
+        }
+
+        let mut reader = tx_manager.begin_read_transaction().unwrap();
+        run(&mut tx_manager, "INSERT INTO Ledger VALUES (5, 50)").unwrap();
+        run(&mut tx_manager, "DELETE FROM Ledger WHERE Entry = 1").unwrap();
+        run(&mut tx_manager, "UPDATE Ledger SET Amount = 0 WHERE Entry = 2").unwrap();
+
+        // Reads do not wait for the storage lock.
+        let guard = storage.lock().unwrap();
+        let rows = reader.execute(parse("SELECT Entry, Amount FROM Ledger ORDER BY Entry")).unwrap().rows;
+        assert_eq!(
+            rows,
+            (1..=4).map(|e| vec![Value::Int32(e), Value::Int32(e * 10)]).collect::<Vec<_>>()
+        );
+        assert!(reader.execute(parse("INSERT INTO Ledger VALUES (6, 60)")).is_err());
+        drop(guard);
+
+        let mut fresh = tx_manager.begin_read_transaction().unwrap();
+        let rows = fresh.execute(parse("SELECT Entry, Amount FROM Ledger ORDER BY Entry")).unwrap().rows;
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Int32(2), Value::Int32(0)],
+                vec![Value::Int32(3), Value::Int32(30)],
+                vec![Value::Int32(4), Value::Int32(40)],
+                vec![Value::Int32(5), Value::Int32(50)],
+            ]
+        );
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_column_compression_setting() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("column_compression").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        assert!(query::parser::parse_query("CREATE TABLE Bad (Qty INT COMPRESSION DICTIONARY)").is_err());
+        run(
+            &mut tx_manager,
+            "CREATE TABLE Parcels (Tracking INT COMPRESSION NONE, Carrier STRING COMPRESSION RLE, Weight INT)",
+        )
+        .unwrap();
+        for id in 1..=6 {
+            run(&mut tx_manager, &format!("INSERT INTO Parcels VALUES ({}, 'post', {})", id, id % 2)).unwrap();
+        }
+        let encodings = |storage: &Arc<Mutex<StorageManager>>, col: &str| {
+            let guard = storage.lock().unwrap();
+            guard.columns["Parcels"][col].metadata.blocks.iter().map(|b| b.compression.clone()).collect::<Vec<_>>()
+        };
+        assert_eq!(encodings(&storage, "Tracking"), vec![CompressionType::None; 2]);
+        assert_eq!(encodings(&storage, "Carrier"), vec![CompressionType::Rle; 2]);
+        assert_eq!(encodings(&storage, "Weight"), vec![CompressionType::Rle; 2]);
+
+        // Rewrites keep the column's choice.
+        run(&mut tx_manager, "DELETE FROM Parcels WHERE Tracking = 1").unwrap();
+        run(&mut tx_manager, "UPDATE Parcels SET Carrier = 'courier' WHERE Tracking = 2").unwrap();
+        assert!(encodings(&storage, "Tracking").iter().all(|c| *c == CompressionType::None));
+        assert!(encodings(&storage, "Carrier").iter().all(|c| *c == CompressionType::Rle));
+        assert_eq!(
+            run(&mut tx_manager, "SELECT Carrier FROM Parcels WHERE Tracking = 2").unwrap(),
+            vec![vec![Value::String("courier".to_string())]]
+        );
+        assert_eq!(run(&mut tx_manager, "SELECT COUNT(*) FROM Parcels").unwrap(), vec![vec![Value::Int32(5)]]);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_many_to_many_joins() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("many_to_many").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Lefts (LKey INT, LTag STRING, LName STRING COLLATE NOCASE)").unwrap();
+        run(&mut tx_manager, "CREATE TABLE Rights (RKey BIGINT, RTag STRING, RName STRING)").unwrap();
+        let lefts = [("1", "a", "Ann"), ("1", "b", "BOB"), ("2", "c", "cy"), ("NULL", "d", "Dee"), ("3", "e", "eve")];
+        for (key, tag, name) in lefts {
+            run(&mut tx_manager, &format!("INSERT INTO Lefts VALUES ({}, '{}', '{}')", key, tag, name)).unwrap();
+        }
+        let rights = [("1", "x", "ann"), ("1", "y", "bob"), ("1", "z", "Bob"), ("2", "w", "CY"), ("NULL", "v", "dee")];
+        for (key, tag, name) in rights {
+            run(&mut tx_manager, &format!("INSERT INTO Rights VALUES ({}, '{}', '{}')", key, tag, name)).unwrap();
+        }
+        let tags = |rows: Vec<Vec<Value>>| rows.into_iter().map(|r| format!("{}{}", r[0], r[1])).collect::<Vec<_>>();
+        let by_key = "Lefts.LTag, Rights.RTag FROM Lefts JOIN Rights ON Lefts.LKey = Rights.RKey";
+        let expected = vec!["ax", "ay", "az", "bx", "by", "bz", "cw"];
+        for hint in ["", "/*+ HASH_JOIN */", "/*+ MERGE_JOIN */"] {
+            assert_eq!(tags(run(&mut tx_manager, &format!("SELECT {} {}", hint, by_key)).unwrap()), expected);
+        }
+
+        // The NOCASE side makes the whole key comparison case-insensitive.
+        let by_name = "Lefts.LTag, Rights.RTag FROM Lefts JOIN Rights ON Lefts.LName = Rights.RName";
+        let expected = vec!["ax", "by", "bz", "cw", "dv"];
+        for hint in ["", "/*+ MERGE_JOIN */"] {
+            assert_eq!(tags(run(&mut tx_manager, &format!("SELECT {} {}", hint, by_name)).unwrap()), expected);
+        }
+
+        for hint in ["", "/*+ MERGE_JOIN */"] {
+            let sql = format!("SELECT {} Lefts.LTag FROM Lefts JOIN Rights ON Lefts.LKey = Rights.RTag", hint);
+            assert!(run(&mut tx_manager, &sql).is_err());
+        }
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_lz4_and_zstd_codecs() {
+        use crate::storage::compression::{compress, decompress, estimate_compressed_size};
+
+        let columns = [
+            (DataType::Int32, (0..500).map(|i| Value::Int32(i % 7)).collect::<Vec<_>>()),
+            (DataType::Int64, (0..500).map(|i| Value::Int64(i * 1_000_000)).collect()),
+            (DataType::Float32, (0..500).map(|i| Value::Float32(OrderedFloat(i as f32 / 4.0))).collect()),
+            (DataType::Boolean, (0..500).map(|i| Value::Boolean(i % 3 == 0)).collect()),
+            (DataType::Date, (0..500).map(|i| Value::Date(19_000 + i)).collect()),
+            (DataType::Timestamp, (0..500).map(|i| Value::Timestamp(1_700_000_000 + i as i64)).collect()),
+            (DataType::String, (0..500).map(|i| Value::String(format!("sensor-{}", i % 11))).collect()),
+        ];
+        for (data_type, values) in &columns {
+            let plain = compress(values, CompressionType::None).unwrap();
+            for codec in [CompressionType::Lz4, CompressionType::Zstd] {
+                let data = compress(values, codec.clone()).unwrap();
+                if *data_type == DataType::String {
+                    assert!(data.len() < plain.len() / 4, "{:?} did not shrink repeated strings", codec);
+                }
+                assert_eq!(estimate_compressed_size(values, codec.clone()), data.len());
+                assert_eq!(&decompress(&data, codec, data_type).unwrap(), values);
+            }
+        }
+        assert!(decompress(&[1, 2, 3], CompressionType::Zstd, &DataType::Int32).is_err());
+
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("byte_codecs").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Logs (Seq INT COMPRESSION LZ4, Line STRING COMPRESSION ZSTD)").unwrap();
+        for seq in 1..=4 {
+            run(&mut tx_manager, &format!("INSERT INTO Logs VALUES ({}, 'line {}')", seq, seq)).unwrap();
+        }
+        run(&mut tx_manager, "INSERT INTO Logs VALUES (NULL, NULL)").unwrap();
+        run(&mut tx_manager, "INSERT INTO Logs VALUES (6, 'line 6')").unwrap();
+        {
+            let guard = storage.lock().unwrap();
+            let first = |col: &str| guard.columns["Logs"][col].metadata.blocks[0].compression.clone();
+            assert_eq!((first("Seq"), first("Line")), (CompressionType::Lz4, CompressionType::Zstd));
+        }
+        assert_eq!(
+            run(&mut tx_manager, "SELECT Line FROM Logs WHERE Seq >= 4").unwrap(),
+            vec![vec![Value::String("line 4".to_string())], vec![Value::String("line 6".to_string())]]
+        );
+        assert_eq!(run(&mut tx_manager, "SELECT Line FROM Logs WHERE Seq IS NULL").unwrap(), vec![vec![Value::Null]]);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_qualified_column_references() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("qualified_columns").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Shipments (ShipID INT, Zone STRING, Weight INT)").unwrap();
+        run(&mut tx_manager, "CREATE TABLE Zones (ZoneName STRING, Rate BIGINT)").unwrap();
+        for (id, zone, weight) in [(1, "north", 10), (2, "south", 20), (3, "north", 30), (4, "east", 40)] {
+            run(&mut tx_manager, &format!("INSERT INTO Shipments VALUES ({}, '{}', {})", id, zone, weight)).unwrap();
+        }
+        run(&mut tx_manager, "INSERT INTO Zones VALUES ('north', 5)").unwrap();
+        run(&mut tx_manager, "INSERT INTO Zones VALUES ('south', 7)").unwrap();
+        let int = |i: i32| Value::Int32(i);
+
+        assert_eq!(
+            run(
+                &mut tx_manager,
+                "SELECT Shipments.ShipID, COALESCE(Shipments.Weight, 0) FROM Shipments \
+                 WHERE Shipments.Zone = 'north' ORDER BY Shipments.ShipID DESC"
+            )
+            .unwrap(),
+            vec![vec![int(3), int(30)], vec![int(1), int(10)]]
+        );
+        assert_eq!(
+            run(
+                &mut tx_manager,
+                "SELECT Shipments.Zone, SUM(Shipments.Weight) FROM Shipments \
+                 WHERE Shipments.ShipID < 4 GROUP BY Shipments.Zone"
+            )
+            .unwrap()
+            .len(),
+            2
+        );
+        run(&mut tx_manager, "UPDATE Shipments SET Shipments.Weight = 25 WHERE Shipments.ShipID = 2").unwrap();
+        run(&mut tx_manager, "DELETE FROM Shipments WHERE Shipments.Zone = 'east'").unwrap();
+        assert_eq!(
+            run(&mut tx_manager, "SELECT Weight FROM Shipments WHERE ShipID >= 2").unwrap(),
+            vec![vec![int(25)], vec![int(30)]]
+        );
+        assert!(run(&mut tx_manager, "SELECT Zones.Rate FROM Shipments").is_err());
+
+        // In joins, qualifiers pick the side and unqualified names find the table that has them.
+        let result = {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(
+                query::parser::parse_query(
+                    "SELECT ShipID, Rate FROM Shipments JOIN Zones ON Shipments.Zone = Zones.ZoneName \
+                     WHERE Zones.Rate > 5",
+                )
+                .unwrap(),
+            );
+            tx_manager.commit_transaction_results(tx).unwrap().remove(0)
+        };
+        assert_eq!(result.columns[1].data_type, Some(DataType::Int64));
+        assert_eq!(result.rows, vec![vec![int(2), Value::Int64(7)]]);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_masked_columns() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("masking").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        let text = |s: &str| Value::String(s.to_string());
+        assert!(query::parser::parse_query("CREATE TABLE Bad (Pin INT MASKED WITH MASK_LAST4)").is_err());
+        run(
+            &mut tx_manager,
+            "CREATE TABLE Customers (CustID INT, Email STRING MASKED WITH MASK_EMAIL, Card STRING MASKED WITH MASK_LAST4)",
+        )
+        .unwrap();
+        run(&mut tx_manager, "INSERT INTO Customers VALUES (1, 'alice@example.com', '4111222233334444')").unwrap();
+        run(&mut tx_manager, "INSERT INTO Customers VALUES (2, 'bob@test.org', NULL)").unwrap();
+
+        // The functions work in any session; the policy only in restricted ones.
+        assert_eq!(
+            run(&mut tx_manager, "SELECT MASK_LAST4(Email) FROM Customers WHERE CustID = 2").unwrap(),
+            vec![vec![text("********.org")]]
+        );
+        let all = "SELECT * FROM Customers ORDER BY CustID";
+        assert_eq!(run(&mut tx_manager, all).unwrap()[0][1], text("alice@example.com"));
+
+        run(&mut tx_manager, "SET role = restricted").unwrap();
+        assert_eq!(
+            run(&mut tx_manager, all).unwrap(),
+            vec![
+                vec![Value::Int32(1), text("a***@example.com"), text("************4444")],
+                vec![Value::Int32(2), text("b***@test.org"), Value::Null],
+            ]
+        );
+        // Filtering still sees the stored values.
+        assert_eq!(
+            run(&mut tx_manager, "SELECT CustID, COALESCE(Card, 'none') FROM Customers WHERE Email = 'bob@test.org'")
+                .unwrap(),
+            vec![vec![Value::Int32(2), text("****")]]
+        );
+        assert_eq!(
+            run(&mut tx_manager, "SELECT MAX(Email) FROM Customers").unwrap(),
+            vec![vec![text("b***@test.org")]]
+        );
+        assert!(run(&mut tx_manager, "SELECT APPROX_COUNT_DISTINCT(Card) FROM Customers").is_err());
+        assert!(run(&mut tx_manager, "SET role = privileged").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_rle_long_runs() {
+        use crate::storage::compression::{compress, decompress, estimate_compressed_size};
+
+        let values: Vec<Value> = std::iter::repeat_n(Value::Int32(7), 1000).chain([Value::Int32(8)]).collect();
+        let data = compress(&values, CompressionType::Rle).unwrap();
+        // Four entries of at most 255 for the run of 7s, one for the 8.
+        assert_eq!(data.len(), 5 * 5);
+        assert_eq!(estimate_compressed_size(&values, CompressionType::Rle), data.len());
+        assert_eq!(decompress(&data, CompressionType::Rle, &DataType::Int32).unwrap(), values);
+        // Blocks written before the change decode the same way.
+        let mut old = vec![255u8];
+        old.extend(9i32.to_le_bytes());
+        old.push(2);
+        old.extend(9i32.to_le_bytes());
+        assert_eq!(
+            decompress(&old, CompressionType::Rle, &DataType::Int32).unwrap(),
+            vec![Value::Int32(9); 257]
+        );
+
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("rle_long_runs").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        let create = "CREATE TABLE Flags (FlagID INT, Active INT COMPRESSION RLE)";
+        tx.add_query(query::parser::parse_query(create).unwrap());
+        tx_manager.commit_transaction(tx).unwrap();
+        let rows = (0..2000).map(|i| vec![Value::Int32(i), Value::Int32(1)]).collect();
+        let mut guard = storage.lock().unwrap();
+        assert_eq!(guard.bulk_load("Flags", rows).unwrap(), 2000);
+        assert!(guard.columns["Flags"]["Active"].metadata.blocks.iter().all(|b| b.compression == CompressionType::Rle));
+        drop(guard);
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT FlagID FROM Flags WHERE Active = 1").unwrap());
+        assert_eq!(tx_manager.commit_transaction(tx).unwrap().len(), 2000);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_buffer_manager_lru() {
+        use crate::storage::buffer::{BlockKey, BufferManager, Decoded};
+
+        let key = |offset| BlockKey { file_path: "c.dat".to_string(), generation: 1, offset };
+        let block = Arc::new(vec![Value::Int64(1); 4]);
+        let block_bytes = std::mem::size_of_val(&block[..]);
+        let buffer = BufferManager::new(block_bytes * 2);
+        buffer.put(key(0), Decoded::Values(block.to_vec()));
+        buffer.put(key(1), Decoded::Values(block.to_vec()));
+        assert!(buffer.get(&key(0)).is_some());
+        // Full: the least recently used block makes room instead of the put failing.
+        buffer.put(key(2), Decoded::Values(block.to_vec()));
+        assert_eq!(buffer.len(), 2);
+        assert!(buffer.get(&key(1)).is_none());
+        assert!(buffer.get(&key(0)).is_some() && buffer.get(&key(2)).is_some());
+        assert!(buffer.used_bytes() <= block_bytes * 2);
+        buffer.set_max_size(block_bytes);
+        assert_eq!(buffer.len(), 1);
+
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("buffer_lru").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Gauges (GaugeID INT, Pressure INT)").unwrap();
+        for i in 0..6 {
+            run(&format!("INSERT INTO Gauges VALUES ({}, {})", i, i * 10)).unwrap();
+        }
+        assert_eq!(run("SELECT GaugeID FROM Gauges WHERE Pressure >= 0").unwrap().len(), 6);
+        let hits = storage.lock().unwrap().buffer.hits();
+        assert_eq!(run("SELECT GaugeID FROM Gauges WHERE Pressure >= 10").unwrap().len(), 5);
+        assert!(storage.lock().unwrap().buffer.hits() > hits);
+        // Rewritten columns are not served from the cache.
+        run("DELETE FROM Gauges WHERE Pressure < 30").unwrap();
+        let rows = run("SELECT GaugeID FROM Gauges WHERE Pressure >= 0").unwrap();
+        assert_eq!(rows, vec![vec![Value::Int32(3)], vec![Value::Int32(4)], vec![Value::Int32(5)]]);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_named_snapshots() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("named_snapshots").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Trades (TradeID INT, Qty INT)").unwrap();
+        for i in 1..=4 {
+            run(&format!("INSERT INTO Trades VALUES ({}, {})", i, i * 100)).unwrap();
+        }
+        run("CREATE SNAPSHOT eod").unwrap();
+        assert!(run("CREATE SNAPSHOT eod").is_err());
+
+        run("INSERT INTO Trades VALUES (5, 500)").unwrap();
+        run("UPDATE Trades SET Qty = 0 WHERE TradeID = 1").unwrap();
+        run("DELETE FROM Trades WHERE TradeID = 2").unwrap();
+        run("DROP TABLE Trades").unwrap();
+
+        let rows = run("SELECT TradeID, Qty FROM Trades AS OF SNAPSHOT eod WHERE Qty >= 200").unwrap();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Value::Int32(2), Value::Int32(200)],
+                vec![Value::Int32(3), Value::Int32(300)],
+                vec![Value::Int32(4), Value::Int32(400)],
+            ]
+        );
+        let rows = run("SELECT SUM(Qty) FROM Trades AS OF SNAPSHOT eod").unwrap();
+        assert_eq!(rows, vec![vec![Value::Int64(1000)]]);
+        assert!(run("SELECT * FROM Trades").is_err());
+        assert!(matches!(
+            query::parser::parse_query("SELECT * FROM Trades AS OF SNAPSHOT eod").unwrap(),
+            Query::AsOfSnapshot { .. }
+        ));
+        assert!(run("SELECT * FROM Trades AS OF SNAPSHOT missing").is_err());
+        run("DROP SNAPSHOT eod").unwrap();
+        assert!(run("SELECT * FROM Trades AS OF SNAPSHOT eod").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_flush_command() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("flush_command").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Pings (PingID INT, Latency INT)").unwrap();
+        for i in 0..2 {
+            run(&format!("INSERT INTO Pings VALUES ({}, {})", i, i * 5)).unwrap();
+        }
+        assert!(storage.lock().unwrap().columns["Pings"]["Latency"].metadata.blocks.is_empty());
+        assert_eq!(run("FLUSH").unwrap(), vec![vec![Value::Int32(2)]]);
+        assert_eq!(storage.lock().unwrap().columns["Pings"]["Latency"].metadata.blocks.len(), 1);
+        assert_eq!(run("FLUSH").unwrap(), vec![vec![Value::Int32(0)]]);
+
+        // Dropping the last handle flushes what is still buffered.
+        run("INSERT INTO Pings VALUES (2, 10)").unwrap();
+        drop(tx_manager);
+        drop(storage);
+        let storage = StorageManager::new(&data_dir, Schema::load(&data_dir).unwrap(), &test_options()).unwrap();
+        assert_eq!(storage.row_count("Pings"), Some(3));
+        drop(storage);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_storage_options() {
+        let data_dir = format!("test_data_storage_options_{}", Alphanumeric.sample_string(&mut rand::thread_rng(), 8));
+        let zero = StorageOptions { segment_rows: 0, ..StorageOptions::default() };
+        assert!(create_database(&data_dir, &zero).is_err());
+        cleanup_test_db(&data_dir);
+
+        let options = StorageOptions { segment_rows: 4, buffer_cache_bytes: 1 << 20, layout: DataLayout::Flat };
+        let (_schema, storage, mut tx_manager) = create_database(&data_dir, &options).unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Sensors (SensorID INT, Temp INT)").unwrap();
+        for i in 0..3 {
+            run(&format!("INSERT INTO Sensors VALUES ({}, 20)", i)).unwrap();
+        }
+        assert!(storage.lock().unwrap().columns["Sensors"]["Temp"].metadata.blocks.is_empty());
+        run("INSERT INTO Sensors VALUES (3, 21)").unwrap();
+        assert_eq!(storage.lock().unwrap().columns["Sensors"]["Temp"].metadata.blocks.len(), 1);
+        let settings = run("SHOW SETTINGS").unwrap();
+        assert!(settings.contains(&vec![
+            Value::String("buffer_cache_bytes".to_string()),
+            Value::String("1048576".to_string()),
+            Value::String("default".to_string()),
+        ]));
+
+        // SET changes the segment size for the rest of the session.
+        run("SET segment_rows = 2").unwrap();
+        run("INSERT INTO Sensors VALUES (4, 22)").unwrap();
+        run("INSERT INTO Sensors VALUES (5, 22)").unwrap();
+        assert_eq!(storage.lock().unwrap().columns["Sensors"]["Temp"].metadata.blocks.len(), 2);
+        assert_eq!(storage.lock().unwrap().options().segment_rows, 2);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_undrop_table() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("undrop_table").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Invoices (InvoiceID INT PRIMARY KEY, Amount INT)").unwrap();
+        for i in 1..=4 {
+            run(&format!("INSERT INTO Invoices VALUES ({}, {})", i, i * 10)).unwrap();
+        }
+        run("DROP TABLE Invoices").unwrap();
+        assert!(run("SELECT * FROM Invoices").is_err());
+        assert!(!fs::exists(format!("{}/columns/Invoices", data_dir)).unwrap());
+        assert!(storage.lock().unwrap().find_orphans().unwrap().is_empty());
+
+        // The buffered fourth row comes back with the flushed ones, and the key is still enforced.
+        run("UNDROP TABLE Invoices").unwrap();
+        assert_eq!(run("SELECT Amount FROM Invoices WHERE InvoiceID >= 3").unwrap().len(), 2);
+        assert_eq!(storage.lock().unwrap().row_count("Invoices"), Some(4));
+        assert!(run("INSERT INTO Invoices VALUES (2, 0)").is_err());
+        assert!(run("UNDROP TABLE Invoices").is_err());
+
+        run("DROP TABLE Invoices").unwrap();
+        run("CREATE TABLE Invoices (InvoiceID INT, Amount INT)").unwrap();
+        assert!(run("UNDROP TABLE Invoices").is_err());
+        run("DROP TABLE Invoices").unwrap();
+
+        // Entries past the retention window are purged.
+        let record = format!("{}/trash/Invoices/table.json", data_dir);
+        let mut entry: serde_json::Value = serde_json::from_str(&fs::read_to_string(&record).unwrap()).unwrap();
+        entry["dropped_at"] = serde_json::json!(0);
+        fs::write(&record, entry.to_string()).unwrap();
+        assert_eq!(storage.lock().unwrap().purge_trash().unwrap(), vec!["Invoices".to_string()]);
+        assert!(run("UNDROP TABLE Invoices").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_compact_table() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("compact_table").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        let blocks = |storage: &Arc<Mutex<StorageManager>>| {
+            storage.lock().unwrap().columns["Parcels"]["Weight"].metadata.blocks.len()
+        };
+        run("CREATE TABLE Parcels (ParcelID INT PRIMARY KEY, Weight INT)").unwrap();
+        for i in 0..7 {
+            run(&format!("INSERT INTO Parcels VALUES ({}, {})", i, i * 2)).unwrap();
+            run("FLUSH").unwrap();
+        }
+        run("UPDATE Parcels SET Weight = 100 WHERE ParcelID = 3").unwrap();
+        assert_eq!(blocks(&storage), 7);
+        let before = run("SELECT ParcelID, Weight FROM Parcels").unwrap();
+
+        let report = run("COMPACT Parcels").unwrap();
+        assert_eq!(report[0][..3], [Value::String("Parcels".to_string()), Value::Int32(7), Value::Int32(3)]);
+        assert!(report[0][4] < report[0][3]);
+        assert_eq!(blocks(&storage), 3);
+        assert_eq!(run("SELECT ParcelID, Weight FROM Parcels").unwrap(), before);
+        assert_eq!(run("SELECT Weight FROM Parcels WHERE ParcelID = 3").unwrap(), vec![vec![Value::Int32(100)]]);
+        assert!(run("INSERT INTO Parcels VALUES (5, 0)").is_err());
+        // Nothing left to merge.
+        assert_eq!(run("COMPACT TABLE Parcels").unwrap()[0][2], Value::Int32(3));
+
+        // Flushing a full segment compacts once merging would save compact_threshold blocks.
+        run("SET compact_threshold = 2").unwrap();
+        for i in 7..10 {
+            run(&format!("INSERT INTO Parcels VALUES ({}, 0)", i)).unwrap();
+            run("FLUSH").unwrap();
+        }
+        assert_eq!(blocks(&storage), 6);
+        for i in 10..13 {
+            run(&format!("INSERT INTO Parcels VALUES ({}, 0)", i)).unwrap();
+        }
+        assert_eq!(blocks(&storage), 5);
+        assert_eq!(storage.lock().unwrap().row_count("Parcels"), Some(13));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_external_csv_table() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("external_csv").unwrap();
+        let ext = format!("{}/ext", data_dir);
+        fs::create_dir_all(&ext).unwrap();
+        fs::write(format!("{}/a.csv", ext), "ReadingID,Station,Level\n1,north,10\n2,south,\n").unwrap();
+        fs::write(format!("{}/b.csv", ext), "Level,ReadingID,Station\n30,3,north\n").unwrap();
+        fs::write(format!("{}/notes.txt", ext), "ignored").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&format!(
+            "CREATE EXTERNAL TABLE Readings (ReadingID INT, Station STRING, Level INT) LOCATION '{}/*.csv'",
+            ext
+        ))
+        .unwrap();
+        assert_eq!(
+            run("SELECT ReadingID, Level FROM Readings").unwrap(),
+            vec![
+                vec![Value::Int32(1), Value::Int32(10)],
+                vec![Value::Int32(2), Value::Null],
+                vec![Value::Int32(3), Value::Int32(30)],
+            ]
+        );
+        assert_eq!(
+            run("SELECT ReadingID FROM Readings WHERE Station = \"north\"").unwrap(),
+            vec![vec![Value::Int32(1)], vec![Value::Int32(3)]]
+        );
+        assert_eq!(run("SELECT SUM(Level) FROM Readings").unwrap(), vec![vec![Value::Int64(40)]]);
+
+        // New files are picked up on the next scan and join with stored tables.
+        fs::write(format!("{}/c.csv", ext), "ReadingID,Station,Level\n4,east,5\n").unwrap();
+        assert_eq!(run("SELECT ReadingID, Level FROM Readings").unwrap().len(), 4);
+        assert_eq!(run("SELECT SUM(Level) FROM Readings").unwrap(), vec![vec![Value::Int64(45)]]);
+        run("CREATE TABLE Stations (StationName STRING, Region STRING)").unwrap();
+        run("INSERT INTO Stations VALUES (\"east\", \"coast\")").unwrap();
+        let join = "SELECT Readings.ReadingID, Stations.Region FROM Readings \
+                    JOIN Stations ON Readings.Station = Stations.StationName";
+        assert_eq!(
+            run(join).unwrap(),
+            vec![vec![Value::Int32(4), Value::String("coast".to_string())]]
+        );
+
+        assert!(run("INSERT INTO Readings VALUES (5, \"west\", 1)").is_err());
+        assert!(run("DELETE FROM Readings WHERE ReadingID = 1").is_err());
+        assert!(run("MAKE INDEX ON Readings (Level)").is_err());
+        assert!(storage.lock().unwrap().schema().get_table("Readings").unwrap().location.is_some());
+        run("DROP TABLE Readings").unwrap();
+        assert!(fs::metadata(format!("{}/a.csv", ext)).is_ok());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_external_parquet_table() {
+        use parquet::data_type::{ByteArray, ByteArrayType, FloatType, Int32Type};
+        use parquet::file::properties::WriterProperties;
+        use parquet::file::writer::SerializedFileWriter;
+        use parquet::schema::parser::parse_message_type;
+
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("external_parquet").unwrap();
+        let lake = format!("{}/lake", data_dir);
+        fs::create_dir_all(&lake).unwrap();
+        let message = "message schema { REQUIRED INT32 ProbeID; REQUIRED BINARY Site (UTF8); OPTIONAL FLOAT Depth; }";
+        let file_schema = Arc::new(parse_message_type(message).unwrap());
+        let file = fs::File::create(format!("{}/probes.parquet", lake)).unwrap();
+        let props = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(file, file_schema, props).unwrap();
+        // Two row groups: probes 1-3 at site "a", probes 4-6 at site "b".
+        for (ids, site) in [([1, 2, 3], "a"), ([4, 5, 6], "b")] {
+            let mut group = writer.next_row_group().unwrap();
+            let mut col = group.next_column().unwrap().unwrap();
+            col.typed::<Int32Type>().write_batch(&ids, None, None).unwrap();
+            col.close().unwrap();
+            let mut col = group.next_column().unwrap().unwrap();
+            col.typed::<ByteArrayType>().write_batch(&vec![ByteArray::from(site); 3], None, None).unwrap();
+            col.close().unwrap();
+            let mut col = group.next_column().unwrap().unwrap();
+            let depths: Vec<f32> = ids.iter().map(|&i| i as f32 * 1.5).collect();
+            // The last probe of each group has no depth.
+            col.typed::<FloatType>().write_batch(&depths[..2], Some(&[1, 1, 0]), None).unwrap();
+            col.close().unwrap();
+            group.close().unwrap();
+        }
+        writer.close().unwrap();
+
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&format!(
+            "CREATE EXTERNAL TABLE Probes (ProbeID INT, Site STRING, Depth FLOAT) LOCATION '{}/*.parquet'",
+            lake
+        ))
+        .unwrap();
+        assert_eq!(run("SELECT ProbeID FROM Probes").unwrap().len(), 6);
+        assert_eq!(
+            run("SELECT ProbeID, Depth FROM Probes WHERE Site = \"b\"").unwrap(),
+            vec![
+                vec![Value::Int32(4), Value::Float32(OrderedFloat(6.0))],
+                vec![Value::Int32(5), Value::Float32(OrderedFloat(7.5))],
+                vec![Value::Int32(6), Value::Null],
+            ]
+        );
+        assert_eq!(
+            run("SELECT ProbeID FROM Probes WHERE Depth IS NULL").unwrap(),
+            vec![vec![Value::Int32(3)], vec![Value::Int32(6)]]
+        );
+        assert_eq!(run("SELECT SUM(ProbeID) FROM Probes").unwrap(), vec![vec![Value::Int64(21)]]);
+        // A file added later is read by the next scan, not answered from the cache.
+        fs::copy(format!("{}/probes.parquet", lake), format!("{}/more.parquet", lake)).unwrap();
+        assert_eq!(run("SELECT ProbeID FROM Probes").unwrap().len(), 12);
+        assert_eq!(run("SELECT SUM(ProbeID) FROM Probes").unwrap(), vec![vec![Value::Int64(42)]]);
+        fs::remove_file(format!("{}/more.parquet", lake)).unwrap();
+
+        // Row-group statistics rule out whole groups before any value is decoded.
+        let table = storage.lock().unwrap().schema().get_table("Probes").unwrap().clone();
+        let path = std::path::Path::new(&lake).join("probes.parquet");
+        let mask = |condition: Condition| storage::external::row_group_mask(&path, &table, Some(&condition)).unwrap();
+        assert_eq!(mask(Condition::GreaterThan("ProbeID".to_string(), Value::Int32(3))), vec![false, true]);
+        assert_eq!(mask(Condition::Equal("Site".to_string(), Value::String("a".to_string()))), vec![true, false]);
+        assert_eq!(mask(Condition::LessThan("ProbeID".to_string(), Value::Int32(0))), vec![false, false]);
+        assert!(run("SELECT ProbeID FROM Probes WHERE ProbeID < 0").unwrap().is_empty());
+        assert!(run("UPDATE Probes SET Depth = 1.0 WHERE ProbeID = 1").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_per_table_column_files() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("per_table_files").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        // Both tables have an ID column; each keeps its own file.
+        run("CREATE TABLE Boats (ID INT, Hull STRING)").unwrap();
+        run("CREATE TABLE Docks (ID INT, Berths INT)").unwrap();
+        for i in 0..3 {
+            run(&format!("INSERT INTO Boats VALUES ({}, \"h{}\")", i, i)).unwrap();
+            run(&format!("INSERT INTO Docks VALUES ({}, {})", i + 10, i)).unwrap();
+        }
+        assert!(fs::exists(format!("{}/columns/Boats/c1.dat", data_dir)).unwrap());
+        assert!(fs::exists(format!("{}/metadata/Docks/c1.json", data_dir)).unwrap());
+        run("DROP TABLE Boats").unwrap();
+        assert!(!fs::exists(format!("{}/columns/Boats", data_dir)).unwrap());
+        assert_eq!(run("SELECT ID FROM Docks").unwrap(), (10..13).map(|i| vec![Value::Int32(i)]).collect::<Vec<_>>());
+        run("UNDROP TABLE Boats").unwrap();
+        assert_eq!(run("SELECT ID FROM Boats WHERE Hull = \"h2\"").unwrap(), vec![vec![Value::Int32(2)]]);
+        assert!(storage.lock().unwrap().find_orphans().unwrap().is_empty());
+        cleanup_test_db(&data_dir);
+
+        // A flat directory is migrated when opened for writing.
+        let flat = StorageOptions { layout: DataLayout::Flat, ..test_options() };
+        let (_schema, storage, mut tx_manager) = create_database(&data_dir, &flat).unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("CREATE TABLE Ferries (FerryID INT PRIMARY KEY, Crew INT)").unwrap());
+        for i in 0..4 {
+            let insert = format!("INSERT INTO Ferries VALUES ({}, {})", i, i * 2);
+            tx.add_query(query::parser::parse_query(&insert).unwrap());
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+        assert!(fs::exists(format!("{}/columns/Crew.dat", data_dir)).unwrap());
+        drop((storage, tx_manager));
+
+        let (_schema, storage, mut tx_manager) = create_database(&data_dir, &test_options()).unwrap();
+        assert_eq!(storage.lock().unwrap().layout(), DataLayout::PerTable);
+        assert!(!fs::exists(format!("{}/columns/Crew.dat", data_dir)).unwrap());
+        assert!(fs::exists(format!("{}/columns/Ferries/c2.dat", data_dir)).unwrap());
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT Crew FROM Ferries WHERE FerryID = 3").unwrap());
+        assert_eq!(tx_manager.commit_transaction(tx).unwrap(), vec![vec![Value::Int32(6)]]);
+        assert!(storage.lock().unwrap().find_orphans().unwrap().is_empty());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_scheduled_jobs() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("scheduled_jobs").unwrap();
+        let scheduler = Scheduler::new(tx_manager.session());
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE t (id INT)").unwrap();
+        run("INSERT INTO t VALUES (1)").unwrap();
+        run("CREATE JOB stats EVERY \"1 hour\" DO ANALYZE t").unwrap();
+        run("CREATE JOB broken EVERY \"2 hours\" DO ANALYZE missing").unwrap();
+        assert!(run("CREATE JOB stats EVERY \"1 hour\" DO ANALYZE t").is_err());
+        assert!(run("CREATE JOB bad EVERY \"1 hour\" DO COMMIT").is_err());
+        assert!(run("CREATE JOB bad EVERY \"0 hours\" DO ANALYZE t").is_err());
+
+        let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+        assert!(scheduler.run_due(now).unwrap().is_empty());
+        assert_eq!(scheduler.run_due(now + 3_600).unwrap(), vec!["stats"]);
+        // Runs are committed like any other transaction, so they are logged.
+        let entries = storage.lock().unwrap().query_log().entries();
+        assert_eq!(entries.last().unwrap().text, "ANALYZE t");
+        assert_eq!(scheduler.run_due(now + 7_200).unwrap(), vec!["stats", "broken"]);
+
+        let rows = run("SELECT name, every, next_run, last_status FROM information_schema.jobs").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][..3], [
+            Value::String("stats".to_string()),
+            Value::Int64(3_600),
+            Value::Timestamp((now + 10_800) as i64),
+        ]);
+        assert_eq!(rows[0][3], Value::String("ok".to_string()));
+        assert!(matches!(&rows[1][3], Value::String(s) if s.contains("missing")));
+
+        run("DROP JOB broken").unwrap();
+        let names = run("SELECT name FROM information_schema.jobs").unwrap();
+        assert_eq!(names, vec![vec![Value::String("stats".to_string())]]);
+        assert!(run("DROP JOB broken").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_multi_column_block_pruning() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("multi_column_pruning").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        // Block i holds IDs 3i..3i+2 and Depths 100i..100i+2.
+        run("CREATE TABLE Casts (ID INT, Depth INT)");
+        for i in 0..9 {
+            run(&format!("INSERT INTO Casts VALUES ({}, {})", i, i / 3 * 100 + i % 3));
+        }
+        let mask = |sql: &str| {
+            let Query::Select { condition, .. } = query::parser::parse_query(sql).unwrap() else {
+                unreachable!()
+            };
+            storage.lock().unwrap().block_mask("Casts", condition.as_ref()).unwrap()
+        };
+        let select = "SELECT ID FROM Casts WHERE";
+        assert_eq!(mask(&format!("{} ID = 1 OR Depth = 201", select)), vec![true, false, true]);
+        assert_eq!(mask(&format!("{} ID > 2 AND Depth < 200", select)), vec![false, true, false]);
+        assert_eq!(mask(&format!("{} ID = 0 AND Depth = 200", select)), vec![false, false, false]);
+        let ids = |rows: Vec<Vec<Value>>| rows.into_iter().map(|r| r[0].clone()).collect::<Vec<_>>();
+        assert_eq!(ids(run(&format!("{} ID = 1 OR Depth = 201", select))), vec![Value::Int32(1), Value::Int32(7)]);
+
+        run("MAKE INDEX ON Casts (Depth)");
+        assert_eq!(mask(&format!("{} ID < 3 OR Depth = 101", select)), vec![true, true, false]);
+        assert_eq!(ids(run(&format!("{} ID < 1 OR Depth = 101", select))), vec![Value::Int32(0), Value::Int32(4)]);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_query_admission() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("query_admission").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Panels (ID INT, Watts INT)").unwrap();
+        for i in 0..6 {
+            run(&format!("INSERT INTO Panels VALUES ({}, {})", i, i * 10)).unwrap();
+        }
+        let governor = storage.lock().unwrap().governor();
+        run("SET max_concurrent_queries = 1").unwrap();
+        run("SET queue_timeout = 1").unwrap();
+
+        // A read queues behind the running one and gives up after the timeout.
+        let limits = storage.lock().unwrap().settings().query_limits();
+        let running = governor.admit(0, limits, Priority::Interactive).unwrap();
+        let started = std::time::Instant::now();
+        let err = run("SELECT ID FROM Panels").unwrap_err();
+        assert!(err.to_string().contains("not admitted"), "{}", err);
+        assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+        // Writes are not governed.
+        run("INSERT INTO Panels VALUES (6, 60)").unwrap();
+        drop(running);
+        assert_eq!(run("SELECT ID FROM Panels WHERE Watts = 60").unwrap(), vec![vec![Value::Int32(6)]]);
+        assert_eq!(governor.running(), (0, 0));
+
+        // Pruned blocks don't count towards the scan estimate; buffered rows do.
+        let bytes = |condition: Option<&Condition>| storage.lock().unwrap().scan_bytes("Panels", condition);
+        let (block, pending) = (3 * 2 * std::mem::size_of::<Value>(), 2 * std::mem::size_of::<Value>());
+        assert_eq!(bytes(None), 2 * block + pending);
+        assert_eq!(bytes(Some(&Condition::LessThan("ID".to_string(), Value::Int32(2)))), block + pending);
+        run(&format!("SET query_memory_bytes = {}", block + pending)).unwrap();
+        assert!(run("SELECT ID FROM Panels").unwrap_err().to_string().contains("query_memory_bytes"));
+        assert_eq!(run("SELECT COUNT(*) FROM Panels WHERE ID < 2").unwrap(), vec![vec![Value::Int32(2)]]);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_in_not_equal_between_like() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("condition_operators").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run("CREATE TABLE Birds (ID INT, Name STRING)");
+        for (i, name) in ["wren", "robin", "raven", "rook", "swift", "swan", "heron"].iter().enumerate() {
+            run(&format!("INSERT INTO Birds VALUES ({}, \"{}\")", i, name));
+        }
+        let ids = |rows: Vec<Vec<Value>>| rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>().join(",");
+        assert_eq!(ids(run("SELECT ID FROM Birds WHERE ID IN (1, 4, 9)")), "1,4");
+        assert_eq!(ids(run("SELECT ID FROM Birds WHERE ID != 0 AND ID <> 6")), "1,2,3,4,5");
+        assert_eq!(ids(run("SELECT ID FROM Birds WHERE ID BETWEEN 2 AND 4")), "2,3,4");
+        assert_eq!(ids(run("SELECT ID FROM Birds WHERE ID BETWEEN 2 AND 4 AND Name LIKE \"r%\"")), "2,3");
+        assert_eq!(ids(run("SELECT ID FROM Birds WHERE Name LIKE \"sw_%\"")), "4,5");
+        assert_eq!(ids(run("SELECT ID FROM Birds WHERE Name LIKE \"%o%n\"")), "1,6");
+        assert_eq!(ids(run("SELECT ID FROM Birds WHERE Name IN (\"rook\", \"wren\")")), "0,3");
+
+        // Blocks hold IDs 0-2 and 3-5; row 6 is still buffered.
+        let mask = |sql: &str| {
+            let Query::Select { condition, .. } = query::parser::parse_query(sql).unwrap() else {
+                unreachable!()
+            };
+            storage.lock().unwrap().block_mask("Birds", condition.as_ref()).unwrap()
+        };
+        assert_eq!(mask("SELECT ID FROM Birds WHERE ID IN (0, 1)"), vec![true, false]);
+        assert_eq!(mask("SELECT ID FROM Birds WHERE ID BETWEEN 3 AND 9"), vec![false, true]);
+        run("MAKE INDEX ON Birds (ID)");
+        assert_eq!(mask("SELECT ID FROM Birds WHERE ID IN (1, 4)"), vec![true, true]);
+        assert_eq!(mask("SELECT ID FROM Birds WHERE ID IN (5, 9)"), vec![false, true]);
+        assert_eq!(ids(run("SELECT ID FROM Birds WHERE ID IN (5, 6)")), "5,6");
+
+        assert!(!query::pattern::like_match("a%b_", "axxbyz"));
+        assert!(query::pattern::like_match("a%%b_", "aXbbY"));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_query_priorities() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("query_priorities").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run("CREATE TABLE Hot (ID INT)");
+        run("CREATE TABLE Cold (ID INT)");
+        for i in 0..6 {
+            run(&format!("INSERT INTO Hot VALUES ({})", i));
+            run(&format!("INSERT INTO Cold VALUES ({})", i));
+        }
+
+        // A queued interactive read is admitted before a batch read queued after it.
+        run("SET max_concurrent_queries = 1");
+        let governor = storage.lock().unwrap().governor();
+        let limits = storage.lock().unwrap().settings().query_limits();
+        let running = governor.admit(0, limits, Priority::Interactive).unwrap();
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let queue = |priority: Priority| {
+            let (governor, order) = (governor.clone(), order.clone());
+            std::thread::spawn(move || {
+                let _permit = governor.admit(0, limits, priority).unwrap();
+                order.lock().unwrap().push(priority);
+                std::thread::sleep(std::time::Duration::from_millis(20));
+            })
+        };
+        let interactive = queue(Priority::Interactive);
+        while governor.interactive_queued() == 0 {
+            std::thread::yield_now();
+        }
+        let batch = queue(Priority::Batch);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        drop(running);
+        interactive.join().unwrap();
+        batch.join().unwrap();
+        assert_eq!(*order.lock().unwrap(), vec![Priority::Interactive, Priority::Batch]);
+
+        // Batch reads don't evict cached blocks to cache their own.
+        run("SELECT ID FROM Hot");
+        let (cached, misses) = {
+            let storage = storage.lock().unwrap();
+            (storage.buffer.used_bytes(), storage.buffer.misses())
+        };
+        run(&format!("SET buffer_cache_bytes = {}", cached));
+        run("SET priority = 'batch'");
+        assert_eq!(run("SELECT COUNT(*) FROM Cold WHERE ID >= 0"), vec![vec![Value::Int32(6)]]);
+        run("SELECT ID FROM Hot WHERE ID >= 0");
+        let after_batch = storage.lock().unwrap().buffer.misses();
+        // Cold's two blocks missed; Hot's were still cached.
+        assert_eq!(after_batch, misses + 2);
+
+        let mut engine = query::planner::QueryEngine::new(storage.clone()).with_priority(Priority::Interactive);
+        engine.execute(query::parser::parse_query("SELECT ID FROM Cold").unwrap()).unwrap();
+        run("SELECT ID FROM Hot WHERE ID >= 1");
+        assert_eq!(storage.lock().unwrap().buffer.misses(), after_batch + 4);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_not_and_parenthesized_conditions() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("not_and_parens").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run("CREATE TABLE Tides (ID INT, Port STRING, Height INT)");
+        let rows = [("a", 1), ("b", 5), ("a", 9), ("c", 2), ("b", 7), ("a", 4), ("c", 8)];
+        for (i, (port, height)) in rows.iter().enumerate() {
+            run(&format!("INSERT INTO Tides VALUES ({}, \"{}\", {})", i, port, height));
+        }
+        run("INSERT INTO Tides VALUES (7, NULL, NULL)");
+        let ids = |rows: Vec<Vec<Value>>| rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>().join(",");
+        let select = |condition: &str| format!("SELECT ID FROM Tides WHERE {}", condition);
+
+        assert_eq!(ids(run(&select("(Port = \"a\" OR Port = \"b\") AND Height > 4"))), "1,2,4");
+        assert_eq!(ids(run(&select("Port = \"a\" OR Port = \"b\" AND Height > 4"))), "0,1,2,4,5");
+        assert_eq!(ids(run(&select("NOT (Port = \"a\" OR Height < 3)"))), "1,4,6");
+        assert_eq!(ids(run(&select("NOT Port = \"a\" AND NOT Height >= 8"))), "1,3,4");
+        assert_eq!(ids(run(&select("((ID < 2) OR (ID > 5 AND (Port = \"c\")))"))), "0,1,6");
+        assert_eq!(ids(run(&select("Port NOT LIKE \"a%\" AND Height NOT BETWEEN 3 AND 7"))), "3,6");
+        assert_eq!(ids(run(&select("ID NOT IN (0, 1, 2) AND NOT NOT Port IN (\"b\", \"c\")"))), "3,4,6");
+        assert_eq!(ids(run(&select("NOT Port IS NULL AND ID >= 6"))), "6");
+        // NOT of a comparison with NULL is still unknown, so row 7 never matches.
+        assert_eq!(ids(run(&select("NOT (Height = 1 OR Port LIKE \"b%\") AND ID > 4"))), "5,6");
+
+        // Pushed-down NOTs still prune: blocks hold IDs 0-2, 3-5; 6 and 7 are buffered.
+        let Query::Select { condition, .. } = query::parser::parse_query(&select("NOT (ID > 2)")).unwrap() else {
+            unreachable!()
+        };
+        assert_eq!(storage.lock().unwrap().block_mask("Tides", condition.as_ref()), Some(vec![true, false]));
+        assert!(query::parser::parse_query(&select("(ID = 1")).is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_preload_columns() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("preload").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Meters (ID INT, Reading INT, Site STRING)").unwrap();
+        for i in 0..6 {
+            run(&format!("INSERT INTO Meters VALUES ({}, {}, \"s{}\")", i, i * 3, i)).unwrap();
+        }
+        storage.lock().unwrap().buffer.clear();
+        let rows = run("PRELOAD Meters (ID, Reading)").unwrap();
+        assert_eq!(rows[0][..3], [Value::String("Meters".to_string()), Value::Int32(2), Value::Int32(4)]);
+        assert_eq!(storage.lock().unwrap().buffer.len(), 4);
+
+        // The first query over preloaded columns is served from the cache.
+        let misses = storage.lock().unwrap().buffer.misses();
+        assert_eq!(run("SELECT ID FROM Meters WHERE Reading > 9").unwrap().len(), 2);
+        assert_eq!(storage.lock().unwrap().buffer.misses(), misses);
+
+        let report = storage.lock().unwrap().preload("Meters", &[]).unwrap();
+        assert_eq!((report.columns, report.blocks), (3, 6));
+        assert!(run("PRELOAD Meters (Missing)").is_err());
+        assert!(run("PRELOAD Nowhere").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_storage_report() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("storage_report").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Events (Seq INT COMPRESSION NONE, Kind STRING COMPRESSION ZSTD)").unwrap();
+        for i in 0..6 {
+            run(&format!("INSERT INTO Events VALUES ({}, \"click\")", i)).unwrap();
+        }
+        let rows = run("SHOW STORAGE REPORT Events").unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0][1..6], [
+            Value::String("Seq".to_string()),
+            Value::String("NONE".to_string()),
+            Value::Int32(2),
+            Value::Int32(6),
+            Value::Int64(24),
+        ]);
+        assert_eq!(rows[1][2], Value::String("ZSTD".to_string()));
+
+        let report = storage.lock().unwrap().storage_report(None).unwrap();
+        let kind = report.iter().find(|u| u.column == "Kind").unwrap();
+        assert_eq!((kind.blocks, kind.rows), (2, 6));
+        assert!(kind.raw_bytes > 0 && kind.compressed_bytes > 0);
+        assert_eq!(kind.average_block_bytes(), kind.compressed_bytes / 2);
+        assert!(run("SHOW STORAGE REPORT Nowhere").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_arithmetic_expressions() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("arithmetic").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Items (ID INT, Qty INT, Price FLOAT, Cost FLOAT)").unwrap();
+        run("INSERT INTO Items VALUES (1, 2, 10.0, 4.0)").unwrap();
+        run("INSERT INTO Items VALUES (2, 30, 5.0, 1.0)").unwrap();
+        run("INSERT INTO Items VALUES (3, 4, 50.0, 45.0)").unwrap();
+        run("INSERT INTO Items VALUES (4, NULL, 1.0, 1.0)").unwrap();
+
+        let rows = run("SELECT ID, Price - Cost, (Qty + 1) * 2, Qty / 4 FROM Items WHERE Qty * Price > 100").unwrap();
+        assert_eq!(rows, vec![
+            vec![Value::Int32(2), Value::Float32(OrderedFloat(4.0)), Value::Int32(62), Value::Int32(7)],
+            vec![Value::Int32(3), Value::Float32(OrderedFloat(5.0)), Value::Int32(10), Value::Int32(1)],
+        ]);
+        // Subtraction groups to the left; a NULL operand never matches.
+        let rows = run("SELECT ID FROM Items WHERE 10 - Qty - 2 = 4 OR Price > Cost * 2").unwrap();
+        assert_eq!(rows, vec![vec![Value::Int32(1)], vec![Value::Int32(2)], vec![Value::Int32(3)]]);
+        let rows = run("SELECT ID FROM Items WHERE NOT (Qty * -1 < -3)").unwrap();
+        assert_eq!(rows, vec![vec![Value::Int32(1)]]);
+
+        assert!(run("SELECT ID FROM Items WHERE Qty / 0 > 1").is_err());
+        assert!(run("SELECT ID + \"x\" FROM Items").is_err());
+        assert!(run("SELECT ID FROM Items WHERE Qty * > 1").is_err());
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT (Qty - 1) * 1.5, ID - (Qty - 1) FROM Items").unwrap());
+        let results = tx_manager.commit_transaction_results(tx).unwrap();
+        assert_eq!(results[0].column_names(), vec!["(Qty - 1) * 1.5", "ID - (Qty - 1)"]);
+        assert_eq!(results[0].columns[0].data_type, Some(DataType::Float32));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_column_and_table_aliases() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("aliases").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction_results(tx)
+        };
+        run("CREATE TABLE Staff (ID INT, Name STRING, Salary INT)").unwrap();
+        run("CREATE TABLE Teams (TeamID INT, TeamName STRING)").unwrap();
+        run("INSERT INTO Staff VALUES (1, \"Ada\", 100)").unwrap();
+        run("INSERT INTO Staff VALUES (2, \"Bo\", 80)").unwrap();
+        run("INSERT INTO Teams VALUES (1, \"Core\")").unwrap();
+        run("INSERT INTO Teams VALUES (2, \"Ops\")").unwrap();
+
+        let sql = "SELECT s.Name AS employee_name, Salary * 2 AS doubled FROM Staff s ORDER BY employee_name DESC";
+        let results = run(sql).unwrap();
+        assert_eq!(results[0].column_names(), vec!["employee_name", "doubled"]);
+        assert_eq!(results[0].columns[1].data_type, Some(DataType::Int32));
+        assert_eq!(results[0].rows[0], vec![Value::String("Bo".to_string()), Value::Int32(160)]);
+
+        // The ON clause may name the right table first.
+        let sql = "SELECT s.Name AS employee_name, t.TeamName AS team FROM Staff AS s JOIN Teams t ON t.TeamID = s.ID \
+                   WHERE t.TeamName = \"Ops\"";
+        let results = run(sql).unwrap();
+        assert_eq!(results[0].column_names(), vec!["employee_name", "team"]);
+        assert_eq!(results[0].columns[1].data_type, Some(DataType::String));
+        assert_eq!(results[0].rows, vec![vec![Value::String("Bo".to_string()), Value::String("Ops".to_string())]]);
+
+        assert!(run("SELECT x.Name FROM Staff s").is_err());
+        assert!(run("SELECT Name AS 1bad FROM Staff").is_err());
+        assert!(run("SELECT COUNT(*) AS n FROM Staff").is_err());
+        assert!(run("SELECT s.Name FROM Staff s JOIN Teams s ON s.ID = s.TeamID").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_index_saved_per_commit_and_rebuilt_after_crash() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("index_batching").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Codes (ID INT, Code STRING)").unwrap();
+        run(&mut tx_manager, "MAKE INDEX ON Codes (Code)").unwrap();
+        let index_path = format!("{}/indexes/Codes_c1.idx", data_dir);
+
+        // Three segment flushes leave the index file alone until the index is saved.
+        {
+            let mut storage = storage.lock().unwrap();
+            let before = fs::read(&index_path).ok();
+            for i in 0..9 {
+                storage.insert_row("Codes", vec![Value::Int32(i), Value::String(format!("c{}", i % 3))]).unwrap();
+            }
+            assert!(storage.indexes["Codes"]["ID"].is_dirty());
+            assert_eq!(fs::read(&index_path).ok(), before);
+            // The key column's index and the one on Code.
+            assert_eq!(storage.save_indexes().unwrap(), 2);
+            assert_eq!(storage.save_indexes().unwrap(), 0);
+        }
+        let stale = fs::read(&index_path).unwrap();
+        for i in 9..12 {
+            run(&mut tx_manager, &format!("INSERT INTO Codes VALUES ({}, \"late\")", i)).unwrap();
+        }
+        assert!(!storage.lock().unwrap().indexes["Codes"]["ID"].is_dirty());
+        drop(tx_manager);
+        drop(storage);
+
+        // A crash after the flush but before the index save: the WAL record is still there.
+        fs::write(&index_path, &stale).unwrap();
+        fs::write(format!("{}/wal/wal.log", data_dir), b"interrupted").unwrap();
+        let (_schema, _storage, mut tx_manager) = create_database(&data_dir, &test_options()).unwrap();
+        let rows = run(&mut tx_manager, "SELECT Code FROM Codes WHERE ID >= 9").unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(fs::metadata(format!("{}/wal/wal.log", data_dir)).unwrap().len(), 0);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_bloom_filter_duplicate_check() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("bloom_unique").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Keys (ID INT, Name STRING)").unwrap();
+        {
+            let mut storage = storage.lock().unwrap();
+            for i in 0..2000 {
+                storage.insert_row("Keys", vec![Value::Int32(i), Value::String(format!("k{}", i))]).unwrap();
+            }
+            let index = &storage.indexes["Keys"]["ID"];
+            // No false negatives, flushed or still buffered.
+            assert!((0..2000).all(|i| index.may_contain(&Value::Int32(i))));
+            let false_positives = (2000..12000).filter(|&i| index.may_contain(&Value::Int32(i))).count();
+            assert!(false_positives < 300, "{} false positives", false_positives);
+            // Duplicates of flushed and of buffered rows are still caught.
+            assert!(storage.insert_row("Keys", vec![Value::Int32(5), Value::String("dup".into())]).is_err());
+            assert!(storage.insert_row("Keys", vec![Value::Int32(1999), Value::String("dup".into())]).is_err());
+        }
+        assert!(run(&mut tx_manager, "INSERT INTO Keys VALUES (7, \"dup\")").is_err());
+        // A deleted key stays in the filter but may be inserted again.
+        run(&mut tx_manager, "DELETE FROM Keys WHERE ID = 7").unwrap();
+        run(&mut tx_manager, "INSERT INTO Keys VALUES (7, \"again\")").unwrap();
+        let rows = run(&mut tx_manager, "SELECT Name FROM Keys WHERE ID = 7").unwrap();
+        assert_eq!(rows, vec![vec![Value::String("again".into())]]);
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_buffer_manager_pins_and_threads() {
+        use crate::storage::buffer::{BlockKey, BufferManager, Decoded};
+
+        let key = |offset| BlockKey { file_path: "p.dat".to_string(), generation: 1, offset };
+        let block = Arc::new(vec![Value::Int64(1); 4]);
+        let block_bytes = std::mem::size_of_val(&block[..]);
+        let buffer = BufferManager::new(block_bytes * 2);
+        buffer.put(key(0), Decoded::Values(block.to_vec()));
+        buffer.put(key(1), Decoded::Values(block.to_vec()));
+        // Block 0 is the least recently used but pinned, so block 1 goes instead.
+        let pinned = buffer.get(&key(0)).unwrap();
+        buffer.get(&key(1));
+        buffer.put(key(2), Decoded::Values(block.to_vec()));
+        assert!(buffer.get(&key(1)).is_none());
+        // With the rest pinned, the new block is the only one that can go.
+        let pinned_2 = buffer.get(&key(2)).unwrap();
+        buffer.put(key(3), Decoded::Values(block.to_vec()));
+        assert_eq!(buffer.len(), 2);
+        assert_eq!(pinned.len(), 4);
+        drop((pinned, pinned_2));
+        buffer.put(key(4), Decoded::Values(block.to_vec()));
+        assert!(buffer.get(&key(0)).is_none());
+        assert!(buffer.get(&key(2)).is_some() && buffer.get(&key(4)).is_some());
+
+        // Shared across threads, the size bound holds once they are done.
+        let buffer = Arc::new(BufferManager::new(block_bytes * 8));
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let (buffer, block) = (Arc::clone(&buffer), Arc::clone(&block));
+                std::thread::spawn(move || {
+                    for i in 0..200 {
+                        let key = key(t * 1000 + i % 20);
+                        match buffer.get(&key) {
+                            Some(values) => assert_eq!(values.len(), 4),
+                            None => buffer.put(key, Decoded::Values(block.to_vec())),
+                        }
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        assert!(buffer.len() <= 8 && buffer.used_bytes() == buffer.len() * block_bytes);
+        assert_eq!(buffer.hits() + buffer.misses(), 800);
+    }
+
+    #[test]
+    fn test_select_distinct() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("distinct").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Visits (ID INT, City STRING, Day INT)").unwrap();
+        let visits = [("Oslo", 1), ("Rome", 1), ("Oslo", 1), ("Oslo", 2), ("Lima", 2), ("Rome", 1), ("Oslo", 2)];
+        for (i, (city, day)) in visits.iter().enumerate() {
+            run(&format!("INSERT INTO Visits VALUES ({}, \"{}\", {})", i, city, day)).unwrap();
+        }
+        run("INSERT INTO Visits VALUES (7, NULL, 3)").unwrap();
+        run("INSERT INTO Visits VALUES (8, NULL, 3)").unwrap();
+        let cities = |rows: Vec<Vec<Value>>| rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>().join(",");
+
+        assert_eq!(cities(run("SELECT DISTINCT City FROM Visits ORDER BY City").unwrap()), "Lima,Oslo,Rome,NULL");
+        assert_eq!(run("SELECT DISTINCT City, Day FROM Visits").unwrap().len(), 5);
+        // LIMIT and OFFSET count distinct rows, not the duplicates dropped.
+        let sql = "SELECT distinct City FROM Visits WHERE Day < 3 ORDER BY City DESC LIMIT 2 OFFSET 1";
+        assert_eq!(cities(run(sql).unwrap()), "Oslo,Lima");
+        assert_eq!(run("SELECT DISTINCT Day * 0 FROM Visits").unwrap(), vec![vec![Value::Int32(0)]]);
+
+        let rows = run("SELECT Day, COUNT(DISTINCT City), COUNT(*) FROM Visits GROUP BY Day").unwrap();
+        assert_eq!(rows[0], vec![Value::Int32(1), Value::Int32(2), Value::Int32(4)]);
+        assert_eq!(rows[2], vec![Value::Int32(3), Value::Int32(0), Value::Int32(2)]);
+        assert_eq!(run("SELECT COUNT(DISTINCT City) FROM Visits").unwrap(), vec![vec![Value::Int32(3)]]);
+
+        assert!(run("SELECT DISTINCT COUNT(*) FROM Visits").is_err());
+        assert!(run("SELECT COUNT(DISTINCT City, Day) FROM Visits").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_string_blocks_cached_decoded() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("string_block_cache").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Tags (ID INT, Tag STRING)").unwrap();
+        let tags = ["red", "red", "blue", "red", "blue", "blue", "red", "red", "red"];
+        for (i, tag) in tags.iter().enumerate() {
+            run(&format!("INSERT INTO Tags VALUES ({}, \"{}\")", i, tag)).unwrap();
+        }
+        run("INSERT INTO Tags VALUES (9, NULL)").unwrap();
+        for i in 10..12 {
+            run(&format!("INSERT INTO Tags VALUES ({}, \"green\")", i)).unwrap();
+        }
+        // Each query differs so none is answered from the query cache.
+        let red = |extra: &str| format!("SELECT ID FROM Tags WHERE Tag = \"red\"{}", extra);
+        let misses = || storage.lock().unwrap().buffer.misses();
+
+        storage.lock().unwrap().buffer.clear();
+        run("SET cache_string_blocks = off").unwrap();
+        assert_eq!(run(&red("")).unwrap().len(), 6);
+        // Only ID's blocks; the last block has no "red" and is pruned.
+        assert_eq!(storage.lock().unwrap().buffer.len(), 3);
+
+        run("SET cache_string_blocks = on").unwrap();
+        assert_eq!(run(&red(" AND ID >= 0")).unwrap().len(), 6);
+        assert_eq!(storage.lock().unwrap().buffer.len(), 6);
+        assert_eq!(run("SELECT ID FROM Tags WHERE Tag IS NULL").unwrap(), vec![vec![Value::Int32(9)]]);
+        let before = misses();
+        assert_eq!(run(&red(" AND ID >= 1")).unwrap().len(), 5);
+        assert_eq!(misses(), before);
+        // Row-wise reads take the cached dictionary blocks too.
+        let values = storage.lock().unwrap().read_column("Tags", "Tag", None).unwrap();
+        let tag = |s: &str| Value::String(s.to_string());
+        assert_eq!(values[..3], [tag("red"), tag("red"), tag("blue")]);
+        assert_eq!((values[9].clone(), values.len()), (Value::Null, 12));
+        assert_eq!(misses(), before);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_where_subqueries() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("subqueries").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Customers (ID INT, Name STRING)").unwrap();
+        run("CREATE TABLE Orders (ID INT, CustomerID INT, Amount INT)").unwrap();
+        for (i, name) in ["ann", "bob", "cy", "dee"].iter().enumerate() {
+            run(&format!("INSERT INTO Customers VALUES ({}, \"{}\")", i, name)).unwrap();
+        }
+        for (i, (customer, amount)) in [(0, 10), (0, 90), (1, 40), (2, 70), (0, 20)].iter().enumerate() {
+            run(&format!("INSERT INTO Orders VALUES ({}, {}, {})", i, customer, amount)).unwrap();
+        }
+        let names = |rows: Vec<Vec<Value>>| rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>().join(",");
+        let customers = |condition: &str| format!("SELECT Name FROM Customers WHERE {} ORDER BY ID", condition);
+
+        let big_spenders = customers("ID IN (SELECT CustomerID FROM Orders WHERE Amount > 50)");
+        assert_eq!(names(run(&big_spenders).unwrap()), "ann,cy");
+        assert_eq!(names(run(&customers("ID NOT IN (SELECT CustomerID FROM Orders)")).unwrap()), "dee");
+        // Long IN lists are matched through a set.
+        let ids = (1..40).step_by(2).map(|i| i.to_string()).collect::<Vec<_>>().join(", ");
+        assert_eq!(names(run(&customers(&format!("ID IN ({})", ids))).unwrap()), "bob,dee");
+        let sql = customers("ID IN (SELECT CustomerID FROM Orders WHERE Amount < 50) AND Name != \"ann\"");
+        assert_eq!(names(run(&sql).unwrap()), "bob");
+        // Scalar subqueries, on either side; AVG is a float compared with INT.
+        let sql = "SELECT ID FROM Orders WHERE Amount = (SELECT MAX(Amount) FROM Orders)";
+        assert_eq!(run(sql).unwrap(), vec![vec![Value::Int32(1)]]);
+        let sql = "SELECT ID FROM Orders WHERE (SELECT AVG(Amount) FROM Orders) < Amount ORDER BY ID";
+        assert_eq!(names(run(sql).unwrap()), "1,3");
+        let sql = customers("ID = (SELECT CustomerID FROM Orders WHERE Amount > 100)");
+        assert_eq!(names(run(&sql).unwrap()), "");
+        // Nested, and in a DELETE.
+        let sql = customers("ID IN (SELECT CustomerID FROM Orders WHERE Amount > (SELECT AVG(Amount) FROM Orders))");
+        assert_eq!(names(run(&sql).unwrap()), "ann,cy");
+        run("DELETE FROM Orders WHERE CustomerID IN (SELECT ID FROM Customers WHERE Name = \"ann\")").unwrap();
+        assert_eq!(run("SELECT COUNT(*) FROM Orders").unwrap(), vec![vec![Value::Int32(2)]]);
+
+        // NOT IN over a subquery that returned a NULL matches nothing.
+        run("INSERT INTO Orders VALUES (9, NULL, 5)").unwrap();
+        assert_eq!(names(run(&customers("ID NOT IN (SELECT CustomerID FROM Orders)")).unwrap()), "");
+        assert!(run(&customers("ID IN (SELECT ID, Amount FROM Orders)")).is_err());
+        assert!(run(&customers("ID = (SELECT CustomerID FROM Orders)")).is_err());
+        assert!(run("SELECT (SELECT MAX(ID) FROM Orders) FROM Customers").is_err());
+        let plan = run(&format!("EXPLAIN {}", big_spenders)).unwrap();
+        assert!(plan.iter().any(|row| row[0].to_string().contains("ID IN (subquery)")));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_bench_suite() {
+        let config = bench::BenchConfig {
+            data_dir: format!("test_data_bench_{}", Alphanumeric.sample_string(&mut rand::thread_rng(), 8)),
+            rows: 500,
+            cardinality: 7,
+            string_len: 5,
+            iterations: 1,
+            ..Default::default()
+        };
+        assert_eq!(bench::generate_facts(&config), bench::generate_facts(&config));
+        let report = bench::run(&config, &test_options()).unwrap();
+        let names: Vec<&str> = report.results.iter().map(|r| r.name.as_str()).collect();
+        assert_eq!(names, vec!["load", "scan", "filter", "join", "aggregate"]);
+        let out = |name: &str| report.results.iter().find(|r| r.name == name).unwrap().rows_out;
+        assert_eq!(out("load"), 507);
+        assert_eq!(out("scan"), 500);
+        assert_eq!(out("join"), 500);
+        assert!(out("aggregate") <= 7);
+        assert!(out("filter") < 500);
+        assert!(fs::metadata(&config.data_dir).is_err());
+    }
+
+    #[test]
+    fn test_insert_multiple_rows_with_column_list() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("insert_rows").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE People (ID INT PRIMARY KEY, Name STRING, City STRING DEFAULT \"Oslo\", Age INT)").unwrap();
+        run("INSERT INTO People VALUES (1, \"Ann\", \"Rome\", 30), (2, \"Bob, Jr.\", \"Lima\", 41)").unwrap();
+        let returned = run("INSERT INTO People (Name, ID) VALUES (\"Cy\", 3), ('Di', 4) RETURNING *").unwrap();
+        assert_eq!(
+            returned[1],
+            vec![Value::Int32(4), Value::String("Di".to_string()), Value::String("Oslo".to_string()), Value::Null]
+        );
+        // More rows than segment_rows; the buffer is flushed once, after the whole batch.
+        run("INSERT INTO People (ID) VALUES (5), (6), (7), (8), (9)").unwrap();
+        assert_eq!(storage.lock().unwrap().schema().get_table("People").unwrap().row_count, 9);
+        let rows = run("SELECT ID, Name, City FROM People WHERE ID < 3 ORDER BY ID").unwrap();
+        assert_eq!(rows[1][1], Value::String("Bob, Jr.".to_string()));
+        assert_eq!(run("SELECT COUNT(*) FROM People WHERE City = \"Oslo\"").unwrap(), vec![vec![Value::Int32(7)]]);
+
+        // A duplicate within the batch, or a bad column list, inserts nothing.
+        assert!(run("INSERT INTO People (ID) VALUES (10), (10)").is_err());
+        assert!(run("INSERT INTO People (ID) VALUES (11), (1)").is_err());
+        assert!(run("INSERT INTO People (ID, Nope) VALUES (12, 1)").is_err());
+        assert!(run("INSERT INTO People (ID, ID) VALUES (12, 13)").is_err());
+        assert!(run("INSERT INTO People (ID, Name) VALUES (12)").is_err());
+        assert_eq!(storage.lock().unwrap().schema().get_table("People").unwrap().row_count, 9);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_codec_round_trip_hooks() {
+        use crate::storage::testing::{self, Shape, SplitMix};
+        for data_type in [
+            DataType::Int32,
+            DataType::Int64,
+            DataType::Float32,
+            DataType::Boolean,
+            DataType::Date,
+            DataType::Timestamp,
+            DataType::String,
+        ] {
+            assert!(testing::check_codecs(&data_type, 7, 20).unwrap() > 0, "{:?}", data_type);
+        }
+
+        let mut rng = SplitMix::new(3);
+        let mut values = testing::generate(&mut rng, &DataType::String, 600, Shape::Runs { max_run: 400 });
+        values[0] = Value::Null;
+        values[599] = Value::Null;
+        testing::round_trip_block(&Column::new("Tag", DataType::String), &values).unwrap();
+        let rle = Column::new("Tag", DataType::String).with_compression(CompressionType::Rle);
+        testing::round_trip_block(&rle, &values).unwrap();
+
+        // A codec that truncates runs at one byte's worth is caught.
+        let err = testing::round_trip_with(
+            &vec![Value::Int32(1); 300],
+            |values| Ok(vec![values.len() as u8]),
+            |data| Ok(vec![Value::Int32(1); data[0] as usize]),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("row 44"), "{}", err);
+    }
+
+    #[test]
+    fn test_migrator_applies_pending_migrations() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("migrator").unwrap();
+        let v1 = Migrator::new()
+            .migration(2, "add_users_name", "ALTER TABLE Users ADD COLUMN Name STRING DEFAULT \"n/a\"")
+            .migration(
+                1,
+                "create_users",
+                "-- first release\nCREATE TABLE Users (ID INT PRIMARY KEY);\nINSERT INTO Users VALUES (1), (2);",
+            );
+        assert_eq!(v1.pending(&tx_manager).unwrap().len(), 2);
+        assert_eq!(v1.run(&mut tx_manager).unwrap(), vec![1, 2]);
+        assert_eq!(v1.run(&mut tx_manager).unwrap(), Vec::<u64>::new());
+
+        // A failing migration is rolled back and not recorded.
+        let v2 = v1
+            .clone()
+            .migration(3, "seed", "INSERT INTO Users VALUES (3, \"a; b\");\nINSERT INTO Users VALUES (1, \"dup\")");
+        assert!(v2.run(&mut tx_manager).is_err());
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT ID, Name FROM Users ORDER BY ID").unwrap());
+        let rows = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1], vec![Value::Int32(2), Value::String("n/a".to_string())]);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("SELECT version, name FROM information_schema.migrations").unwrap());
+        let rows = tx_manager.commit_transaction(tx).unwrap();
+        assert_eq!(rows[1], vec![Value::Int64(2), Value::String("add_users_name".to_string())]);
+
+        // Renamed or forgotten migrations are refused before anything runs.
+        let renamed = Migrator::new().migration(1, "create_people", "").migration(2, "add_users_name", "");
+        assert!(renamed.run(&mut tx_manager).is_err());
+        assert!(Migrator::new().migration(1, "create_users", "").pending(&tx_manager).is_err());
+        assert_eq!(storage.lock().unwrap().applied_migrations().unwrap().len(), 2);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_lenient_validation() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("lenient").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Feed (ID INT, Price FLOAT, Qty BIGINT, Live BOOL, Note STRING)").unwrap();
+        let messy = "INSERT INTO Feed VALUES (\" 1 \", 5, \"12\", \"yes\", 3.5)";
+        assert!(run(messy).is_err());
+
+        run("ALTER TABLE Feed SET VALIDATION LENIENT").unwrap();
+        run(messy).unwrap();
+        run("INSERT INTO Feed (ID, Price) VALUES (2.0, \"2.25\")").unwrap();
+        // Conversions that would lose data are still refused.
+        assert!(run("INSERT INTO Feed (ID) VALUES (2.5)").is_err());
+        assert!(run("INSERT INTO Feed (ID, Live) VALUES (3, \"maybe\")").is_err());
+
+        let rows = run("SELECT * FROM Feed ORDER BY ID").unwrap();
+        assert_eq!(
+            rows[0],
+            vec![
+                Value::Int32(1),
+                Value::Float32(5.0.into()),
+                Value::Int64(12),
+                Value::Boolean(true),
+                Value::String("3.5".to_string())
+            ]
+        );
+        assert_eq!(rows[1][1], Value::Float32(2.25.into()));
+
+        let csv_path = format!("{}/feed.csv", data_dir);
+        fs::write(&csv_path, "ID,Price,Qty,Live,Note\n4.0,1,7,no,x\n").unwrap();
+        run(&format!("COPY Feed FROM '{}'", csv_path)).unwrap();
+        assert_eq!(run("SELECT Live FROM Feed WHERE ID = 4").unwrap(), vec![vec![Value::Boolean(false)]]);
+
+        // The mode survives a restart and can be switched back.
+        drop(tx_manager);
+        drop(storage);
+        let (_schema, _storage, mut tx_manager) = create_database(&data_dir, &test_options()).unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("INSERT INTO Feed (ID) VALUES (\"5\")").unwrap());
+        tx.add_query(query::parser::parse_query("ALTER TABLE Feed SET VALIDATION STRICT").unwrap());
+        tx_manager.commit_transaction(tx).unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_query(query::parser::parse_query("INSERT INTO Feed (ID) VALUES (\"6\")").unwrap());
+        assert!(tx_manager.commit_transaction(tx).is_err());
+        assert!(query::parser::parse_query("ALTER TABLE Feed SET VALIDATION LOOSE").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_commit_manifest() {
+        use crate::storage::manifest::Manifest;
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("manifest").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Events (ID INT, Kind STRING)").unwrap();
+        assert_eq!(Manifest::load(&data_dir, "Events").unwrap().unwrap().row_count, 0);
+        run("INSERT INTO Events VALUES (1, \"a\"), (2, \"b\"), (3, \"a\")").unwrap();
+        let manifest = Manifest::load(&data_dir, "Events").unwrap().unwrap();
+        assert_eq!((manifest.version, manifest.row_count, manifest.segments.len()), (2, 3, 2));
+
+        // Reads and buffered rows leave it alone; a flush moves it on.
+        run("SELECT * FROM Events").unwrap();
+        run("INSERT INTO Events VALUES (4, \"c\")").unwrap();
+        assert_eq!(Manifest::load(&data_dir, "Events").unwrap().unwrap().version, 2);
+        run("INSERT INTO Events VALUES (5, \"c\"), (6, \"d\")").unwrap();
+        let manifest = Manifest::load(&data_dir, "Events").unwrap().unwrap();
+        assert_eq!((manifest.version, manifest.row_count, manifest.segments.len()), (3, 6, 4));
+        assert!(storage.lock().unwrap().verify_manifest("Events").unwrap().is_empty());
+
+        // Flipped bytes and lost blocks are both reported.
+        let (path, offset) = {
+            let storage = storage.lock().unwrap();
+            let col_store = &storage.columns["Events"]["ID"];
+            (col_store.file_path.clone(), col_store.metadata.blocks[1].offset as usize)
+        };
+        let mut bytes = fs::read(&path).unwrap();
+        bytes[offset] ^= 0xff;
+        fs::write(&path, bytes).unwrap();
+        let problems = storage.lock().unwrap().verify_manifest("Events").unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("checksum"), "{:?}", problems);
+        storage.lock().unwrap().columns.get_mut("Events").unwrap().get_mut("Kind").unwrap().metadata.blocks.pop();
+        assert_eq!(storage.lock().unwrap().verify_manifest("Events").unwrap().len(), 2);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_show_indexes() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("show_indexes").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Readings (ID INT, Sensor STRING)").unwrap();
+        run("CREATE TABLE Other (ID INT)").unwrap();
+        for i in 0..7 {
+            run(&format!("INSERT INTO Readings VALUES ({}, \"s{}\")", i, i % 2)).unwrap();
+        }
+        // ID is a key, so it is indexed from the start; the buffered seventh
+        // row is not in any block yet.
+        let rows = run("SHOW INDEXES ON Readings").unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0][1..4], [Value::String("ID".to_string()), Value::Int32(6), Value::Int32(6)]);
+
+        run("MAKE INDEX ON Readings (Sensor)").unwrap();
+        let rows = run("SHOW INDEXES").unwrap();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0][..2], [Value::String("Other".to_string()), Value::String("ID".to_string())]);
+        assert_eq!(rows[2][..4], [
+            Value::String("Readings".to_string()),
+            Value::String("Sensor".to_string()),
+            Value::Int32(2),
+            Value::Int32(6),
+        ]);
+        assert!(matches!(rows[2][4], Value::Int64(bytes) if bytes > 0));
+        assert_eq!(rows[2][5], Value::Boolean(false));
+        assert!(run("SHOW INDEXES ON Nowhere").is_err());
+
+        let stats = storage.lock().unwrap().index_stats(Some("Other")).unwrap();
+        assert_eq!((stats[0].keys, stats[0].entries), (0, 0));
+        run("UNMAKE INDEX Sensor ON Readings").unwrap();
+        assert_eq!(run("SHOW INDEXES ON Readings").unwrap().len(), 1);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_query_log() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("query_log").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_statement(sql, query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Items (ID INT, Label STRING)").unwrap();
+        run("INSERT INTO Items VALUES (1, \"a\"), (2, \"b\")").unwrap();
+        run("SELECT * FROM Items").unwrap();
+        assert!(run("SELECT Missing FROM Items").is_err());
+
+        let rows = run("SELECT id, text, rows, status, error FROM system.query_log").unwrap();
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[2][..4], [
+            Value::Int64(3),
+            Value::String("SELECT * FROM Items".to_string()),
+            Value::Int64(2),
+            Value::String("ok".to_string()),
+        ]);
+        assert_eq!(rows[2][4], Value::Null);
+        assert_eq!(rows[3][3], Value::String("error".to_string()));
+        assert!(matches!(&rows[3][4], Value::String(e) if e.contains("Missing")));
+        // Statements 2 to 5; the one running is logged once it finishes.
+        let timed = run("SELECT text FROM system.query_log WHERE duration_us >= 0 AND id > 1").unwrap();
+        assert_eq!(timed.len(), 4);
+
+        let log = query::log::QueryLog::new(2);
+        for i in 0..3 {
+            log.record(&format!("q{}", i), std::time::Duration::ZERO, Ok(i));
+        }
+        let entries = log.entries();
+        assert_eq!(entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 3]);
+        // A failed multi-statement transaction reloads the storage manager.
+        let mut tx = tx_manager.begin_transaction();
+        let sql = "INSERT INTO Items VALUES (3, \"c\")";
+        tx.add_statement(sql, query::parser::parse_query(sql).unwrap());
+        tx.add_query(query::parser::parse_query("INSERT INTO Nowhere VALUES (1)").unwrap());
+        assert!(tx_manager.commit_transaction(tx).is_err());
+        let entries = storage.lock().unwrap().query_log().entries();
+        assert_eq!(entries.len(), 8);
+        assert!(entries[7].error.is_some() && entries[7].text.contains("Nowhere"));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_interned_column() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("interned_column").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        assert!(run("CREATE TABLE Bad (N INT COMPRESSION INTERNED)").is_err());
+        run("CREATE TABLE Sales (ID INT, Region STRING COMPRESSION INTERNED, Amount INT)").unwrap();
+        let regions = ["north", "south", "north", "east", "north", "south", "NULL", "east", "north"];
+        for (i, region) in regions.iter().enumerate() {
+            let region = if *region == "NULL" { "NULL".to_string() } else { format!("\"{}\"", region) };
+            run(&format!("INSERT INTO Sales VALUES ({}, {}, {})", i, region, i * 10)).unwrap();
+        }
+        run("FLUSH").unwrap();
+
+        let intern_path = format!("{}/columns/Sales/c2.intern", data_dir);
+        let intern = storage::intern::InternTable::open(&intern_path).unwrap();
+        assert_eq!(intern.strings(), ["north", "south", "east"]);
+        {
+            let storage_guard = storage.lock().unwrap();
+            let blocks = &storage_guard.columns["Sales"]["Region"].metadata.blocks;
+            assert!(blocks.iter().all(|b| b.compression == CompressionType::Interned));
+        }
+
+        let rows = run("SELECT ID FROM Sales WHERE Region = \"north\"").unwrap();
+        assert_eq!(rows.len(), 4);
+        let rows = run("SELECT Region, COUNT(*), SUM(Amount) FROM Sales GROUP BY Region").unwrap();
+        assert_eq!(rows.len(), 4);
+        let north = [Value::String("north".to_string()), Value::Int32(4), Value::Int64(140)];
+        assert_eq!(rows[1][..3], north);
+        assert_eq!(rows[3][..2], [Value::Null, Value::Int32(1)]);
+        assert_eq!(run("SELECT * FROM Sales WHERE Region = \"west\"").unwrap().len(), 0);
+        let condition = Condition::Equal("Region".to_string(), Value::String("west".to_string()));
+        let scan = storage.lock().unwrap().block_scan("Sales", Some(&condition), true).unwrap();
+        assert_eq!(scan.read_blocks, 0);
+
+        // Existing ids keep their strings; new ones are appended to the file.
+        run("DELETE FROM Sales WHERE Region = \"south\"").unwrap();
+        run("INSERT INTO Sales VALUES (9, \"west\", 90)").unwrap();
+        run("ALTER TABLE Sales RENAME COLUMN Region TO Area").unwrap();
+        run("FLUSH").unwrap();
+        let intern = storage::intern::InternTable::open(&format!("{}/columns/Sales/c2.intern", data_dir)).unwrap();
+        assert_eq!(intern.strings(), ["north", "south", "east", "west"]);
+        let rows = run("SELECT ID FROM Sales WHERE Area IN (\"west\", \"east\")").unwrap();
+        assert_eq!(rows.len(), 3);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_database_facade() {
+        let random_suffix = Alphanumeric.sample_string(&mut rand::thread_rng(), 8);
+        let data_dir = format!("test_data_database_facade_{}", random_suffix);
+        let mut db = Database::open_with(&data_dir, &test_options()).unwrap();
+        db.execute("CREATE TABLE Users (ID INT, Name STRING)").unwrap();
+        let results = db
+            .transaction(|tx| {
+                tx.add_sql("INSERT INTO Users VALUES (1, \"ada\")")?;
+                tx.add_sql("INSERT INTO Users VALUES (2, \"grace\")")
+            })
+            .unwrap();
+        assert!(results.is_empty());
+        assert!(db.transaction(|tx| tx.add_sql("BEGIN")).is_err());
+        // A failing closure leaves nothing behind.
+        let failed = db.transaction(|tx| {
+            tx.add_sql("INSERT INTO Users VALUES (3, \"linus\")")?;
+            Err(DbError::QueryError("stop".to_string()))
+        });
+        assert!(failed.is_err());
+
+        let users = db.execute("SELECT Name FROM Users WHERE ID > 0").unwrap();
+        assert_eq!(users.column_names(), vec!["Name"]);
+        assert_eq!(users.rows, vec![
+            vec![Value::String("ada".to_string())],
+            vec![Value::String("grace".to_string())],
+        ]);
+        assert!(db.execute("DROP TABLE Missing").is_err());
+        db.close().unwrap();
+
+        let mut db = Database::open_with(&data_dir, &test_options()).unwrap();
+        assert_eq!(db.execute("SELECT * FROM Users").unwrap().rows.len(), 2);
+        db.close().unwrap();
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_string_prefix_pruning() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("string_prefix_pruning").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        run("CREATE TABLE Fruit (ID INT, Name STRING)");
+        let names = ["apple", "apricot", "avocado", "banana", "blueberry", "cherry", "date", "fig", "grape", "kiwi"];
+        for (i, name) in names.iter().enumerate() {
+            run(&format!("INSERT INTO Fruit VALUES ({}, \"{}\")", i, name));
+        }
+        // Three blocks of three names; "kiwi" is still buffered.
+        let mask = |sql: &str| {
+            let Query::Select { condition, .. } = query::parser::parse_query(sql).unwrap() else {
+                unreachable!()
+            };
+            storage.lock().unwrap().block_mask("Fruit", condition.as_ref()).unwrap()
+        };
+        assert_eq!(mask("SELECT ID FROM Fruit WHERE Name LIKE \"ap%\""), vec![true, false, false]);
+        assert_eq!(mask("SELECT ID FROM Fruit WHERE Name LIKE \"b_u%\""), vec![false, true, false]);
+        assert_eq!(mask("SELECT ID FROM Fruit WHERE Name LIKE \"cherry\""), vec![false, true, false]);
+        assert_eq!(mask("SELECT ID FROM Fruit WHERE Name LIKE \"%e\""), vec![true, true, true]);
+        assert_eq!(mask("SELECT ID FROM Fruit WHERE Name LIKE \"z%\""), vec![false, false, false]);
+        assert_eq!(mask("SELECT ID FROM Fruit WHERE Name >= \"c\" AND Name < \"d\""), vec![false, true, false]);
+        assert_eq!(mask("SELECT ID FROM Fruit WHERE Name > \"grape\""), vec![false, false, false]);
+
+        let names = |rows: Vec<Vec<Value>>| rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>().join(",");
+        assert_eq!(names(run("SELECT Name FROM Fruit WHERE Name LIKE \"b%\"")), "banana,blueberry");
+        assert_eq!(names(run("SELECT Name FROM Fruit WHERE Name LIKE \"k%\" OR Name > \"f\"")), "fig,grape,kiwi");
+
+        assert!(query::pattern::like_may_match("ab%", "aa", "ab"));
+        assert!(query::pattern::like_may_match("ab%", "abz", "b"));
+        assert!(!query::pattern::like_may_match("ab%", "ac", "b"));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_streaming_select() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("streaming_select").unwrap();
+        for sql in ["CREATE TABLE Events (ID INT, Kind STRING)", "SELECT * FROM Events"] {
+            let stream = tx_manager.execute_streaming(sql).unwrap();
+            assert_eq!(stream.count(), 0);
+        }
+        for i in 0..10 {
+            let kind = if i % 2 == 0 { "even" } else { "odd" };
+            tx_manager.execute_streaming(&format!("INSERT INTO Events VALUES ({}, \"{}\")", i, kind)).unwrap();
+        }
+        let collect = |stream: query::stream::RowStream| stream.collect::<Result<Vec<_>, _>>().unwrap();
+        let ids = |rows: Vec<Vec<Value>>| rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>().join(",");
+
+        // Three blocks and one buffered row.
+        let stream = tx_manager.execute_streaming("SELECT ID, Kind FROM Events").unwrap();
+        assert_eq!(stream.column_names(), vec!["ID", "Kind"]);
+        assert_eq!(ids(collect(stream)), "0,1,2,3,4,5,6,7,8,9");
+        let sql = "SELECT ID FROM Events WHERE Kind = \"odd\" AND ID > 2 LIMIT 3 OFFSET 1";
+        assert_eq!(ids(collect(tx_manager.execute_streaming(sql).unwrap())), "5,7,9");
+        let sql = "SELECT ID + 100 FROM Events WHERE ID IN (2, 9)";
+        assert_eq!(ids(collect(tx_manager.execute_streaming(sql).unwrap())), "102,109");
+        let sql = "SELECT ID FROM Events ORDER BY ID DESC LIMIT 2";
+        assert_eq!(ids(collect(tx_manager.execute_streaming(sql).unwrap())), "9,8");
+        assert!(tx_manager.execute_streaming("SELECT Missing FROM Events").is_err());
+        assert!(tx_manager.execute_streaming("COMMIT").is_err());
+
+        // Rows written while a stream is open are not part of it.
+        let mut stream = tx_manager.execute_streaming("SELECT ID FROM Events").unwrap();
+        assert_eq!(stream.next().unwrap().unwrap(), vec![Value::Int32(0)]);
+        tx_manager.execute_streaming("INSERT INTO Events VALUES (10, \"even\")").unwrap();
+        assert_eq!(stream.by_ref().count(), 9);
+        drop(stream);
+        let sql = "SELECT rows FROM system.query_log WHERE text = \"SELECT ID FROM Events\"";
+        assert_eq!(collect(tx_manager.execute_streaming(sql).unwrap()), vec![vec![Value::Int64(10)]]);
+        let result = tx_manager.execute_streaming("SELECT ID FROM Events").unwrap().collect_result().unwrap();
+        assert_eq!(result.rows.len(), 11);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_repl_meta_commands() {
+        use crate::repl::MetaCommand;
+        assert_eq!(MetaCommand::parse("set pagesize 25").unwrap(), MetaCommand::SetPageSize(25));
+        assert_eq!(MetaCommand::parse("set PAGESIZE  0").unwrap(), MetaCommand::SetPageSize(0));
+        assert!(MetaCommand::parse("set pagesize -1").is_err());
+        assert!(MetaCommand::parse("set pagesize").is_err());
+        assert!(matches!(MetaCommand::parse("unset x"), Err(DbError::QueryError(e)) if e.contains("\\unset x")));
+
+        use crate::repl::is_command;
+        assert!(is_command("exit", "EXIT") && is_command("EXIT;", "EXIT") && is_command("help ;;", "HELP"));
+        assert!(!is_command("EXIT 1;", "EXIT") && !is_command("HELPS;", "HELP"));
+    }
+
+    #[test]
+    fn test_pending_byte_limits() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("pending_byte_limits").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx).unwrap()
+        };
+        let long = "x".repeat(1000);
+        run("CREATE TABLE Notes (ID INT, Body STRING)");
+        run("CREATE TABLE Logs (ID INT, Line STRING)");
+        run("SET segment_rows = 1000");
+        run("SET segment_bytes = 3000");
+        let blocks = |table: &str| storage.lock().unwrap().columns[table]["ID"].metadata.blocks.len();
+
+        // Two 1 KB rows stay buffered; the third passes segment_bytes.
+        run(&format!("INSERT INTO Notes VALUES (1, \"{}\"), (2, \"{}\")", long, long));
+        assert_eq!(blocks("Notes"), 0);
+        assert!(storage.lock().unwrap().pending_table_bytes("Notes") > 2000);
+        run(&format!("INSERT INTO Notes VALUES (3, \"{}\")", long));
+        assert_eq!(blocks("Notes"), 1);
+        assert_eq!(storage.lock().unwrap().pending_table_bytes("Notes"), 0);
+
+        // Past pending_memory_bytes the table buffering most is flushed.
+        run("SET segment_bytes = 1000000");
+        run("SET pending_memory_bytes = 5000");
+        run(&format!("INSERT INTO Notes VALUES (4, \"{}\"), (5, \"{}\"), (6, \"{}\")", long, long, long));
+        run("INSERT INTO Logs VALUES (1, \"started\")");
+        assert_eq!((blocks("Notes"), blocks("Logs")), (1, 0));
+        run(&format!("INSERT INTO Logs VALUES (2, \"{}\"), (3, \"{}\")", long, long));
+        assert_eq!((blocks("Notes"), blocks("Logs")), (2, 0));
+        assert!(storage.lock().unwrap().pending_table_bytes("Logs") > 2000);
+
+        assert_eq!(run("SELECT ID FROM Notes").len(), 6);
+        assert_eq!(run("SELECT ID FROM Logs").len(), 3);
+        assert!(matches!(&run("SHOW SETTINGS")[2][..2], [Value::String(n), Value::String(v)]
+            if n == "pending_memory_bytes" && v == "5000"));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_strict_reads_and_salvage() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("strict_reads").unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql)?);
+            tx_manager.commit_transaction(tx)
+        };
+        run("CREATE TABLE Orders (ID INT, Item STRING)").unwrap();
+        for i in 0..10 {
+            run(&format!("INSERT INTO Orders VALUES ({}, \"item{}\")", i, i)).unwrap();
+        }
+        // Three blocks of three rows; flip a byte in the middle block of Item.
+        {
+            let storage_guard = storage.lock().unwrap();
+            let col_store = &storage_guard.columns["Orders"]["Item"];
+            let offset = col_store.metadata.blocks[1].offset;
+            let mut bytes = fs::read(&col_store.file_path).unwrap();
+            bytes[offset as usize] ^= 0xff;
+            fs::write(&col_store.file_path, bytes).unwrap();
+        }
+        let ids = |rows: Vec<Vec<Value>>| rows.iter().map(|r| r[0].to_string()).collect::<Vec<_>>().join(",");
+        assert_eq!(ids(run("SELECT Item FROM Orders").unwrap()), "item0,item1,item2,item6,item7,item8,item9");
+        run("SET strict_reads = on").unwrap();
+        let err = run("SELECT Item FROM Orders WHERE ID > 0").unwrap_err().to_string();
+        assert!(err.contains("Unreadable block") && err.contains("checksum"), "{}", err);
+        // Blocks pruned away are never read.
+        assert_eq!(ids(run("SELECT Item FROM Orders WHERE ID < 2").unwrap()), "item0,item1");
+
+        let report = run("SALVAGE TABLE Orders INTO Recovered").unwrap();
+        assert_eq!(report, vec![vec![Value::String("Recovered".to_string()), Value::Int32(7), Value::Int32(1)]]);
+        assert_eq!(ids(run("SELECT ID FROM Recovered").unwrap()), "0,1,2,6,7,8,9");
+        assert!(run("SALVAGE TABLE Orders INTO Recovered").is_err());
+        assert!(query::parser::parse_query("SALVAGE Orders").is_err());
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_script_execution() {
+        use crate::repl::script::{source_path, split_statements, ScriptStatement};
+        let statements = split_statements(
+            "-- setup\nCREATE TABLE T (ID INT);\n\nINSERT INTO T VALUES (1); SELECT \"a;b\"\n",
+        );
+        assert_eq!(
+            statements,
+            vec![
+                ScriptStatement { line: 2, sql: "CREATE TABLE T (ID INT)".to_string() },
+                ScriptStatement { line: 4, sql: "INSERT INTO T VALUES (1)".to_string() },
+                ScriptStatement { line: 4, sql: "SELECT \"a;b\"".to_string() },
+            ]
+        );
+        assert_eq!(source_path("source 'init.sql';").as_deref(), Some("init.sql"));
+        assert_eq!(source_path("SOURCE init.sql").as_deref(), Some("init.sql"));
+        assert_eq!(source_path("SELECT 1"), None);
+
+        let (data_dir, _schema, storage, tx_manager) = setup_test_db("script_execution").unwrap();
+        let mut repl = Repl::new(tx_manager);
+        let script = |name: &str, text: &str| {
+            let path = format!("{}/{}", data_dir, name);
+            std::fs::write(&path, text).unwrap();
+            path
+        };
+        let setup = script("setup.sql", "CREATE TABLE Users (ID INT);\nINSERT INTO Users VALUES (1), (2);\n");
+        repl.run_file(&setup).unwrap();
+        assert_eq!(storage.lock().unwrap().row_count("Users"), Some(2));
+
+        // The failing statement is reported by line, and the script's open
+        // transaction is rolled back.
+        let bad = script(
+            "bad.sql",
+            "START TRANSACTION;\nINSERT INTO Users VALUES (3);\nCOMMIT;\nSELECT * FROM Missing;\n",
+        );
+        let err = repl.run_file(&bad).unwrap_err().to_string();
+        assert!(err.contains(&format!("{}:4:", bad)), "{}", err);
+        assert_eq!(storage.lock().unwrap().row_count("Users"), Some(3));
+        let open = script("open.sql", "START TRANSACTION;\nINSERT INTO Users VALUES (4);\nINSERT INTO;\n");
+        assert!(repl.run_file(&open).unwrap_err().to_string().contains(":3:"));
+        repl.run_file(&setup.replace("setup", "missing")).unwrap_err();
+
+        let nested = script("nested.sql", &format!("SOURCE '{}';\nINSERT INTO Users VALUES (5);\n", open));
+        assert!(repl.run_file(&nested).unwrap_err().to_string().contains("nested.sql:1:"));
+        let looping = format!("{}/loop.sql", data_dir);
+        std::fs::write(&looping, format!("SOURCE '{}'", looping)).unwrap();
+        assert!(repl.run_file(&looping).unwrap_err().to_string().contains("nested too deeply"));
+        repl.close().unwrap();
+        assert_eq!(storage.lock().unwrap().row_count("Users"), Some(3));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_column_ids() {
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("column_ids").unwrap();
+        let run = |tx_manager: &mut TransactionManager, sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_query(query::parser::parse_query(sql).unwrap());
+            tx_manager.commit_transaction(tx)
+        };
+        run(&mut tx_manager, "CREATE TABLE Parts (ID INT, Code STRING, Qty INT)").unwrap();
+        for i in 0..4 {
+            run(&mut tx_manager, &format!("INSERT INTO Parts VALUES ({}, \"p{}\", {})", i, i, i * 5)).unwrap();
+        }
+        let ids = |storage: &Arc<Mutex<StorageManager>>, table: &str| -> Vec<Option<u32>> {
+            storage.lock().unwrap().schema().get_table(table).unwrap().columns.iter().map(|c| c.id).collect()
+        };
+        assert_eq!(ids(&storage, "Parts"), vec![Some(1), Some(2), Some(3)]);
+
+        // Renaming moves no files, and a dropped column's id is not reused.
+        let code_file = format!("{}/columns/Parts/c2.dat", data_dir);
+        let before = fs::read(&code_file).unwrap();
+        run(&mut tx_manager, "ALTER TABLE Parts RENAME COLUMN Code TO Sku").unwrap();
+        assert_eq!(fs::read(&code_file).unwrap(), before);
+        run(&mut tx_manager, "ALTER TABLE Parts DROP COLUMN Qty").unwrap();
+        run(&mut tx_manager, "ALTER TABLE Parts ADD COLUMN Qty INT DEFAULT 1").unwrap();
+        assert_eq!(ids(&storage, "Parts"), vec![Some(1), Some(2), Some(4)]);
+        assert!(fs::exists(format!("{}/columns/Parts/c4.dat", data_dir)).unwrap());
+        // Per-table files let another table use the same column name.
+        run(&mut tx_manager, "CREATE TABLE Bins (ID INT)").unwrap();
+        run(&mut tx_manager, "ALTER TABLE Bins ADD COLUMN Sku STRING").unwrap();
+        assert!(run(&mut tx_manager, "ALTER TABLE Parts ADD COLUMN Sku STRING").is_err());
+        run(&mut tx_manager, "FLUSH").unwrap();
+        assert!(storage.lock().unwrap().find_orphans().unwrap().is_empty());
+        drop((storage, tx_manager));
+
+        // A directory from before column ids gets them on its next writable open.
+        let schema_path = format!("{}/schema.json", data_dir);
+        let mut tables: serde_json::Value = serde_json::from_str(&fs::read_to_string(&schema_path).unwrap()).unwrap();
+        let parts = &mut tables["Parts"];
+        parts["next_column_id"] = serde_json::json!(0);
+        for (col, name) in parts["columns"].as_array_mut().unwrap().iter_mut().zip(["ID", "Sku", "Qty"]) {
+            let key = format!("c{}", col["id"]);
+            col["id"] = serde_json::Value::Null;
+            for file in ["columns/Parts/{}.dat", "metadata/Parts/{}.json", "indexes/Parts_{}.idx"] {
+                let from = format!("{}/{}", data_dir, file.replace("{}", &key));
+                if fs::exists(&from).unwrap() {
+                    fs::rename(from, format!("{}/{}", data_dir, file.replace("{}", name))).unwrap();
+                }
+            }
+        }
+        fs::write(&schema_path, serde_json::to_string(&tables).unwrap()).unwrap();
+        let (_schema, storage, mut tx_manager) = create_database(&data_dir, &test_options()).unwrap();
+        assert_eq!(ids(&storage, "Parts"), vec![Some(1), Some(2), Some(3)]);
+        assert!(fs::exists(format!("{}/indexes/Parts_c1.idx", data_dir)).unwrap());
+        assert!(!fs::exists(format!("{}/columns/Parts/Sku.dat", data_dir)).unwrap());
+        assert!(storage.lock().unwrap().find_orphans().unwrap().is_empty());
+        assert_eq!(
+            run(&mut tx_manager, "SELECT Sku, Qty FROM Parts WHERE ID = 3").unwrap(),
+            vec![vec![Value::String("p3".to_string()), Value::Int32(1)]]
+        );
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_repl_statement_buffering() {
+        use crate::repl::script::{split_terminated, ScriptStatement};
+        // A statement typed over several lines waits for its semicolon.
+        let (done, rest) = split_terminated("CREATE TABLE Ships (\nID INT,");
+        assert!(done.is_empty());
+        assert_eq!(rest.unwrap().sql, "CREATE TABLE Ships (\nID INT,");
+        let (done, rest) = split_terminated("CREATE TABLE Ships (\nID INT,\nName STRING);");
+        let sql = "CREATE TABLE Ships (\nID INT,\nName STRING)".to_string();
+        assert_eq!(done, vec![ScriptStatement { line: 1, sql }]);
+        assert!(rest.is_none());
+        // Semicolons in strings do not end it; text after the last one is kept.
+        let (done, rest) = split_terminated("INSERT INTO Ships VALUES (1, \"a;\"); SELECT *");
+        assert_eq!(done.len(), 1);
+        assert_eq!(rest.unwrap().sql, "SELECT *");
+        let (done, rest) = split_terminated("SELECT \"open;");
+        assert!(done.is_empty() && rest.is_some());
+        let commit = ScriptStatement { line: 1, sql: "COMMIT".to_string() };
+        assert_eq!(split_terminated("COMMIT; -- done"), (vec![commit], None));
+    }
+
+    #[test]
+    fn test_wal_framing() {
+        use crate::transaction::wal::{crc32, read_wal, Wal};
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("wal_framing").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("CREATE TABLE Notes (ID INT, Body STRING)").unwrap();
+        tx_manager.commit_transaction(tx).unwrap();
+        let mut big = tx_manager.begin_transaction();
+        let rows: Vec<String> = (0..200).map(|i| format!("({}, \"the same long note body\")", i)).collect();
+        big.add_sql(&format!("INSERT INTO Notes VALUES {}", rows.join(", "))).unwrap();
+
+        let plain_path = format!("{}/plain.log", data_dir);
+        let mut plain = Wal::open(&plain_path).unwrap();
+        plain.append(&big, false, false).unwrap();
+        let mut packed = Wal::open(&format!("{}/packed.log", data_dir)).unwrap();
+        packed.append(&big, true, false).unwrap();
+        assert!(packed.len().unwrap() * 4 < plain.len().unwrap());
+        let contents = read_wal(packed.path()).unwrap();
+        assert_eq!(contents.transactions.len(), 1);
+        assert_eq!(format!("{:?}", contents.transactions[0].queries()), format!("{:?}", big.queries()));
+
+        // A record cut short, or one failing its CRC, ends what is read.
+        plain.append(&big, true, false).unwrap();
+        let whole = plain.len().unwrap();
+        plain.append(&big, false, false).unwrap();
+        let full = fs::read(&plain_path).unwrap();
+        fs::write(&plain_path, &full[..full.len() - 10]).unwrap();
+        let contents = read_wal(&plain_path).unwrap();
+        assert_eq!(contents.transactions.iter().map(|tx| tx.id()).collect::<Vec<_>>(), vec![big.id(), big.id()]);
+        assert_eq!((contents.valid_bytes, contents.torn_bytes), (whole, full.len() as u64 - 10 - whole));
+        let mut corrupt = full.clone();
+        corrupt[20] ^= 0xff;
+        fs::write(&plain_path, &corrupt).unwrap();
+        assert!(read_wal(&plain_path).unwrap().transactions.is_empty());
+
+        // A torn tail left by a crash is dropped when the database reopens.
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("SET wal_compression = on").unwrap();
+        tx_manager.commit_transaction(tx).unwrap();
+        tx_manager.commit_transaction(big).unwrap();
+        drop((storage, tx_manager));
+        let wal_path = format!("{}/wal/wal.log", data_dir);
+        fs::write(&wal_path, &full[..full.len() - 10]).unwrap();
+        let (_schema, storage, mut tx_manager) = create_database(&data_dir, &test_options()).unwrap();
+        assert_eq!(fs::metadata(&wal_path).unwrap().len(), whole);
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("SELECT COUNT(*) FROM Notes").unwrap();
+        assert_eq!(tx_manager.commit_transaction(tx).unwrap(), vec![vec![Value::Int32(200)]]);
+        assert_eq!(fs::metadata(&wal_path).unwrap().len(), 0);
+        drop(storage);
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_aggregate_pushdown() {
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("aggregate_pushdown").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("CREATE TABLE Sales (ID INT, Amount INT, Region STRING)").unwrap();
+        for i in 1..=8 {
+            let amount = if i % 4 == 0 { "NULL".to_string() } else { (i * 10).to_string() };
+            tx.add_sql(&format!("INSERT INTO Sales VALUES ({}, {}, \"r{}\")", i, amount, i % 3)).unwrap();
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+        let mut run = |sql: &str| {
+            let mut tx = tx_manager.begin_transaction();
+            tx.add_sql(sql)?;
+            tx_manager.commit_transaction(tx)
+        };
+        let float = |v: f32| Value::Float32(OrderedFloat(v));
+
+        // Amounts 10..70 with 40 and 80 NULL, over two flushed blocks and two buffered rows.
+        assert_eq!(
+            run("SELECT COUNT(*), SUM(Amount), AVG(Amount), MIN(Amount), MAX(Amount) FROM Sales").unwrap(),
+            vec![vec![Value::Int32(8), Value::Int64(240), float(40.0), Value::Int32(10), Value::Int32(70)]]
+        );
+        assert_eq!(
+            run("SELECT COUNT(*), SUM(Amount), AVG(Amount), MIN(Region), MAX(Amount) FROM Sales WHERE ID > 4").unwrap(),
+            vec![vec![
+                Value::Int32(4),
+                Value::Int64(180),
+                float(60.0),
+                Value::String("r0".to_string()),
+                Value::Int32(70)
+            ]]
+        );
+        assert_eq!(
+            run("SELECT COUNT(*), SUM(Amount), MIN(Amount) FROM Sales WHERE ID > 100").unwrap(),
+            vec![vec![Value::Int32(0), Value::Int64(0), float(0.0)]]
+        );
+        assert!(run("SELECT SUM(Region) FROM Sales").is_err());
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_concurrent_sessions() {
+        use crate::transaction::wal::{read_wal, Wal};
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("concurrent_sessions").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("CREATE TABLE Events (Worker INT, Seq INT)").unwrap();
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let sessions: Vec<Session> = (0..4).map(|_| tx_manager.session()).collect();
+        let mut ids: Vec<u64> = std::thread::scope(|scope| {
+            let workers: Vec<_> = sessions
+                .iter()
+                .enumerate()
+                .map(|(worker, session)| {
+                    scope.spawn(move || {
+                        (0..10)
+                            .map(|seq| {
+                                let mut tx = session.begin();
+                                tx.add_sql(&format!("INSERT INTO Events VALUES ({}, {})", worker, seq)).unwrap();
+                                // Two writes, so the commit stages a workspace.
+                                tx.add_sql(&format!("INSERT INTO Events VALUES ({}, {})", worker, seq + 100)).unwrap();
+                                let id = tx.id();
+                                session.commit(tx).unwrap();
+                                id
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers.into_iter().flat_map(|w| w.join().unwrap()).collect()
+        });
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), 40);
+        let mut session_ids: Vec<u64> = sessions.iter().map(Session::id).collect();
+        session_ids.push(tx_manager.session().id());
+        session_ids.sort();
+        session_ids.dedup();
+        assert_eq!(session_ids.len(), 5);
+
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("SELECT COUNT(*) FROM Events").unwrap();
+        assert_eq!(tx_manager.commit_transaction(tx).unwrap(), vec![vec![Value::Int32(80)]]);
+        let wal_path = format!("{}/wal/wal.log", data_dir);
+        assert!(read_wal(&wal_path).unwrap().transactions.is_empty());
+
+        // Each WAL record names the session that began its transaction.
+        let tx = sessions[2].begin();
+        let mut wal = Wal::open(&format!("{}/session.log", data_dir)).unwrap();
+        wal.append(&tx, false, false).unwrap();
+        let logged = read_wal(wal.path()).unwrap().transactions;
+        assert_eq!((logged[0].session(), logged[0].id()), (sessions[2].id(), tx.id()));
+
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_numeric_column_vectors() {
+        use crate::query::batch::ColumnVector;
+        let (data_dir, _schema, storage, mut tx_manager) = setup_test_db("numeric_vectors").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("CREATE TABLE Readings (ID INT, Level FLOAT, Total BIGINT, Day DATE, Ok BOOLEAN)").unwrap();
+        for i in 0..8 {
+            let level = if i % 3 == 0 { Value::Null } else { Value::Float32(OrderedFloat(i as f32 + 0.5)) };
+            let ok = if i == 4 { Value::Null } else { Value::Boolean(true) };
+            tx.add_query(Query::Insert {
+                table: "Readings".to_string(),
+                values: vec![Value::Int32(i), level, Value::Int64(i as i64 * 1000), Value::Date(i + 19000), ok],
+                returning: Vec::new(),
+            });
+        }
+        tx_manager.commit_transaction(tx).unwrap();
+
+        // Blocks decode straight into typed vectors, NULLs and unflushed rows included,
+        // first from the files and then from the buffer.
+        for _ in 0..2 {
+            let mut storage = storage.lock().unwrap();
+            for (column, data_type) in [
+                ("ID", DataType::Int32),
+                ("Level", DataType::Float32),
+                ("Total", DataType::Int64),
+                ("Day", DataType::Date),
+                ("Ok", DataType::Boolean),
+            ] {
+                let vector = storage.read_column_vector("Readings", column, None, None, true).unwrap();
+                let values = storage.read_column("Readings", column, None).unwrap();
+                assert_eq!(vector, ColumnVector::from_values(&values, &data_type).unwrap());
+            }
+        }
+        let vector = storage.lock().unwrap().read_column_vector("Readings", "Level", None, None, true).unwrap();
+        assert_eq!(vector.nulls().unwrap().iter().filter(|&&n| n).count(), 3);
+        assert_eq!(vector.value(1), Value::Float32(OrderedFloat(1.5)));
+        cleanup_test_db(&data_dir);
+    }
+
+    #[test]
+    fn test_uncommitted_writes_hidden() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+        let (data_dir, _schema, _storage, mut tx_manager) = setup_test_db("uncommitted_hidden").unwrap();
+        let mut tx = tx_manager.begin_transaction();
+        tx.add_sql("CREATE TABLE Items (ID INT)").unwrap();
+        tx_manager.commit_transaction(tx).unwrap();
+
+        let (writer, reader) = (tx_manager.session(), tx_manager.session());
+        let done = AtomicBool::new(false);
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let mut tx = writer.begin();
+                for id in 0..300 {
+                    tx.add_sql(&format!("INSERT INTO Items VALUES ({})", id)).unwrap();
+                }
+                tx.add_sql("INSERT INTO Missing VALUES (1)").unwrap();
+                assert!(writer.commit(tx).is_err());
+                done.store(true, Ordering::SeqCst);
+            });
+            // A snapshot taken meanwhile holds none of its rows either.
+            let snapshots = reader.clone();
+            let done = &done;
+            scope.spawn(move || {
+                while !done.load(Ordering::SeqCst) {
+                    let mut read = snapshots.begin_read().unwrap();
+                    let select = query::parser::parse_query("SELECT ID FROM Items").unwrap();
+                    let rows = read.execute(select).unwrap().rows;
+                    assert!(rows.is_empty(), "snapshot holds {} rows of an uncommitted transaction", rows.len());
+                }
+            });
+            while !done.load(Ordering::SeqCst) {
+                let rows = reader.execute_streaming("SELECT ID FROM Items").unwrap().collect_result().unwrap().rows;
+                assert!(rows.is_empty(), "read {} rows of an uncommitted transaction", rows.len());
+            }
+        });
+        assert!(reader.execute_streaming("SELECT ID FROM Items").unwrap().collect_result().unwrap().rows.is_empty());
+        cleanup_test_db(&data_dir);
+    }
 }
@@ -1,9 +1,81 @@
-use vddb::{create_database, DbError, Repl};
+use std::time::Duration;
+use vddb::bench::{self, BenchConfig};
+use vddb::{create_database, open_database_read_only, DbError, Repl, Scheduler, StorageOptions};
 
 fn main() -> Result<(), DbError> {
-    let data_dir = "data";
-    let (_schema, _storage, tx_manager) = create_database(data_dir)?;
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("bench") {
+        return run_bench(&args[2..]);
+    }
+    let data_dir = flag_value(&args, "--data-dir")?.unwrap_or("data");
+    let script = flag_value(&args, "--file")?;
+    let read_only = args.iter().any(|arg| arg == "--read-only");
+    let server = args.iter().any(|arg| arg == "--server");
+    let options = StorageOptions::default();
+    let (_schema, _storage, tx_manager) = if read_only {
+        open_database_read_only(data_dir, &options)?
+    } else {
+        create_database(data_dir, &options)?
+    };
+    // Scheduled jobs commit through a session of their own, taking turns with
+    // the REPL's commits, until the REPL or script is done.
+    let scheduler = (server && !read_only)
+        .then(|| Scheduler::new(tx_manager.session()).spawn(Duration::from_secs(1)));
     let mut repl = Repl::new(tx_manager);
-    repl.run()?;
+    if let Some(script) = script {
+        let outcome = repl.run_file(script);
+        drop(scheduler);
+        repl.close()?;
+        if let Err(e) = outcome {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let outcome = repl.run();
+    drop(scheduler);
+    outcome
+}
+
+/// The value after `flag`, as in `--data-dir PATH`.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Result<Option<&'a str>, DbError> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) => args
+            .get(i + 1)
+            .map(|value| Some(value.as_str()))
+            .ok_or_else(|| DbError::InvalidData(format!("Missing value for {}", flag))),
+        None => Ok(None),
+    }
+}
+
+/// `vddb bench [--rows N] [--cardinality N] [--string-len N] [--iterations N] [--seed N] [--dir PATH] [--keep]`
+fn run_bench(args: &[String]) -> Result<(), DbError> {
+    let mut config = BenchConfig::default();
+    let mut args = args.iter();
+    while let Some(flag) = args.next() {
+        if flag == "--keep" {
+            config.keep_data = true;
+            continue;
+        }
+        let value = args
+            .next()
+            .ok_or_else(|| DbError::InvalidData(format!("Missing value for {}", flag)))?;
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| DbError::InvalidData(format!("Invalid value for {}: {}", flag, value)))
+        };
+        match flag.as_str() {
+            "--rows" => config.rows = number()? as usize,
+            "--cardinality" => config.cardinality = number()? as usize,
+            "--string-len" => config.string_len = number()? as usize,
+            "--iterations" => config.iterations = number()? as usize,
+            "--seed" => config.seed = number()?,
+            "--dir" => config.data_dir = value.clone(),
+            _ => return Err(DbError::InvalidData(format!("Unknown bench option {}", flag))),
+        }
+    }
+    let report = bench::run(&config, &StorageOptions::default())?;
+    print!("{}", report);
     Ok(())
-}
\ No newline at end of file
+}
@@ -0,0 +1,126 @@
+use crate::query::parser::parse_query;
+use crate::transaction::TransactionManager;
+use crate::types::DbError;
+
+/// One versioned step of a schema's history: DDL and data fixes, separated
+/// by `;`. Lines starting with `--` are comments.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: u64,
+    pub name: String,
+    pub script: String,
+}
+
+/// Brings a database up to date by applying the migrations it has not seen,
+/// in version order. Each migration runs as one transaction and is recorded
+/// in `information_schema.migrations` once it commits; one that fails is
+/// rolled back and stops the run. Applied migrations must keep their
+/// version and name in later releases.
+#[derive(Debug, Clone, Default)]
+pub struct Migrator {
+    migrations: Vec<Migration>,
+}
+
+impl Migrator {
+    pub fn new() -> Self {
+        Migrator::default()
+    }
+
+    pub fn migration(mut self, version: u64, name: &str, script: &str) -> Self {
+        self.migrations.push(Migration {
+            version,
+            name: name.to_string(),
+            script: script.to_string(),
+        });
+        self
+    }
+
+    /// Migrations not yet applied to the database, in version order.
+    pub fn pending(&self, tx_manager: &TransactionManager) -> Result<Vec<&Migration>, DbError> {
+        let mut migrations: Vec<&Migration> = self.migrations.iter().collect();
+        migrations.sort_by_key(|m| m.version);
+        if let Some(pair) = migrations.windows(2).find(|pair| pair[0].version == pair[1].version) {
+            return Err(DbError::InvalidData(format!("Migration version {} is used twice", pair[0].version)));
+        }
+        let applied = tx_manager.storage().lock().unwrap().applied_migrations()?;
+        for done in &applied {
+            match migrations.iter().find(|m| m.version == done.version) {
+                Some(m) if m.name == done.name => {}
+                Some(m) => {
+                    return Err(DbError::InvalidData(format!(
+                        "Migration {} was applied as {}, not {}",
+                        done.version, done.name, m.name
+                    )));
+                }
+                None => {
+                    return Err(DbError::InvalidData(format!(
+                        "Migration {} ({}) is applied but unknown",
+                        done.version, done.name
+                    )));
+                }
+            }
+        }
+        migrations.retain(|m| applied.iter().all(|done| done.version != m.version));
+        Ok(migrations)
+    }
+
+    /// Applies the pending migrations and returns their versions.
+    pub fn run(&self, tx_manager: &mut TransactionManager) -> Result<Vec<u64>, DbError> {
+        let mut ran = Vec::new();
+        for migration in self.pending(tx_manager)? {
+            let fail = |e: DbError| {
+                DbError::TransactionError(format!("Migration {} ({}) failed: {}", migration.version, migration.name, e))
+            };
+            let mut tx = tx_manager.begin_transaction();
+            for statement in split_script(&migration.script) {
+                tx.add_statement(&statement, parse_query(&statement).map_err(fail)?);
+            }
+            tx_manager.commit_transaction(tx).map_err(fail)?;
+            tx_manager
+                .storage()
+                .lock()
+                .unwrap()
+                .record_migration(migration.version, &migration.name)?;
+            log::info!("Applied migration {} ({})", migration.version, migration.name);
+            ran.push(migration.version);
+        }
+        Ok(ran)
+    }
+}
+
+/// Statements of a script: split at `;` outside quotes, without comments,
+/// with line breaks and runs of spaces outside quotes made single spaces.
+fn split_script(script: &str) -> Vec<String> {
+    let text: String = script
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("--"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut statements = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    for ch in text.chars() {
+        match ch {
+            '"' | '\'' if quote.is_none() => quote = Some(ch),
+            _ if quote == Some(ch) => quote = None,
+            ';' if quote.is_none() => {
+                statements.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ if quote.is_none() && ch.is_whitespace() => {
+                if !current.ends_with(' ') {
+                    current.push(' ');
+                }
+                continue;
+            }
+            _ => {}
+        }
+        current.push(ch);
+    }
+    statements.push(current);
+    statements
+        .into_iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect()
+}
@@ -0,0 +1,509 @@
+use crate::query::pattern::like_match;
+use crate::query::{collect_condition_columns, unmaterialized, Condition};
+use crate::types::{Collation, DataType, DbError, Value};
+use ordered_float::OrderedFloat;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// IN lists longer than this are matched through a hash set.
+const IN_SCAN_LIMIT: usize = 16;
+
+/// A column of a batch stored as a typed vector. Strings are kept as
+/// dictionary codes so predicates run once per distinct value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ColumnVector {
+    Int32(Vec<i32>),
+    Float32(Vec<f32>),
+    Int64(Vec<i64>),
+    Boolean(Vec<bool>),
+    Date(Vec<i32>),
+    Timestamp(Vec<i64>),
+    Dictionary { codes: Vec<u32>, dictionary: Vec<String> },
+    /// A vector with NULLs; `values` holds a placeholder at each NULL row.
+    Nullable { values: Box<ColumnVector>, nulls: Vec<bool> },
+}
+
+impl ColumnVector {
+    pub fn from_values(values: &[Value], data_type: &DataType) -> Result<Self, DbError> {
+        let vector = match data_type {
+            DataType::Int32 => ColumnVector::Int32(typed(values, |v| match v {
+                Value::Int32(i) => Some(*i),
+                _ => None,
+            })?),
+            DataType::Float32 => ColumnVector::Float32(typed(values, |v| match v {
+                Value::Float32(f) => Some(f.0),
+                _ => None,
+            })?),
+            DataType::Int64 => ColumnVector::Int64(typed(values, |v| match v {
+                Value::Int64(i) => Some(*i),
+                _ => None,
+            })?),
+            DataType::Boolean => ColumnVector::Boolean(typed(values, |v| match v {
+                Value::Boolean(b) => Some(*b),
+                _ => None,
+            })?),
+            DataType::Date => ColumnVector::Date(typed(values, |v| match v {
+                Value::Date(d) => Some(*d),
+                _ => None,
+            })?),
+            DataType::Timestamp => ColumnVector::Timestamp(typed(values, |v| match v {
+                Value::Timestamp(t) => Some(*t),
+                _ => None,
+            })?),
+            DataType::String => {
+                let mut builder = DictionaryBuilder::default();
+                for v in values {
+                    match v {
+                        Value::String(s) => builder.push(s),
+                        Value::Null => builder.push_null(),
+                        _ => return Err(DbError::TypeMismatch),
+                    }
+                }
+                return Ok(builder.finish());
+            }
+        };
+        Ok(with_nulls(vector, values.iter().map(Value::is_null).collect()))
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ColumnVector::Int32(v) => v.len(),
+            ColumnVector::Float32(v) => v.len(),
+            ColumnVector::Int64(v) | ColumnVector::Timestamp(v) => v.len(),
+            ColumnVector::Boolean(v) => v.len(),
+            ColumnVector::Date(v) => v.len(),
+            ColumnVector::Dictionary { codes, .. } => codes.len(),
+            ColumnVector::Nullable { nulls, .. } => nulls.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The codes and dictionary of a string vector; see `nulls` for its NULLs.
+    pub fn dictionary_codes(&self) -> Option<(&[u32], &[String])> {
+        match self {
+            ColumnVector::Dictionary { codes, dictionary } => Some((codes, dictionary)),
+            ColumnVector::Nullable { values, .. } => values.dictionary_codes(),
+            _ => None,
+        }
+    }
+
+    /// Which rows are NULL, if any can be.
+    pub fn nulls(&self) -> Option<&[bool]> {
+        match self {
+            ColumnVector::Nullable { nulls, .. } => Some(nulls),
+            _ => None,
+        }
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        match self {
+            ColumnVector::Int32(v) => v.truncate(len),
+            ColumnVector::Float32(v) => v.truncate(len),
+            ColumnVector::Int64(v) | ColumnVector::Timestamp(v) => v.truncate(len),
+            ColumnVector::Boolean(v) => v.truncate(len),
+            ColumnVector::Date(v) => v.truncate(len),
+            ColumnVector::Dictionary { codes, .. } => codes.truncate(len),
+            ColumnVector::Nullable { values, nulls } => {
+                values.truncate(len);
+                nulls.truncate(len);
+            }
+        }
+    }
+
+    pub fn value(&self, row: usize) -> Value {
+        match self {
+            ColumnVector::Int32(v) => Value::Int32(v[row]),
+            ColumnVector::Float32(v) => Value::Float32(OrderedFloat(v[row])),
+            ColumnVector::Int64(v) => Value::Int64(v[row]),
+            ColumnVector::Boolean(v) => Value::Boolean(v[row]),
+            ColumnVector::Date(v) => Value::Date(v[row]),
+            ColumnVector::Timestamp(v) => Value::Timestamp(v[row]),
+            ColumnVector::Dictionary { codes, dictionary } => Value::String(dictionary[codes[row] as usize].clone()),
+            ColumnVector::Nullable { nulls, .. } if nulls[row] => Value::Null,
+            ColumnVector::Nullable { values, .. } => values.value(row),
+        }
+    }
+
+    /// Narrows `selection` to rows that are (or are not) NULL.
+    fn select_nulls(&self, want_null: bool, selection: &[u32]) -> Vec<u32> {
+        match self {
+            ColumnVector::Nullable { nulls, .. } => selection
+                .iter()
+                .copied()
+                .filter(|&i| nulls[i as usize] == want_null)
+                .collect(),
+            _ if want_null => Vec::new(),
+            _ => selection.to_vec(),
+        }
+    }
+
+    /// Narrows `selection` to the rows where `cmp(row value, literal)` matches `accept`.
+    /// A literal of another type matches nothing, as in row-at-a-time evaluation.
+    fn select(&self, literal: &Value, accept: fn(Ordering) -> bool, collation: Collation, selection: &[u32]) -> Vec<u32> {
+        match (self, literal) {
+            // NULL compares as unknown, so those rows never match.
+            (ColumnVector::Nullable { values, .. }, _) => values.select(literal, accept, collation, &self.select_nulls(false, selection)),
+            (ColumnVector::Int32(v), Value::Int32(lit)) => select_by(v, lit, accept, selection),
+            (ColumnVector::Float32(v), Value::Float32(lit)) => selection
+                .iter()
+                .copied()
+                .filter(|&i| accept(OrderedFloat(v[i as usize]).cmp(lit)))
+                .collect(),
+            (ColumnVector::Int64(v), Value::Int64(lit)) => select_by(v, lit, accept, selection),
+            (ColumnVector::Boolean(v), Value::Boolean(lit)) => select_by(v, lit, accept, selection),
+            (ColumnVector::Date(v), Value::Date(lit)) => select_by(v, lit, accept, selection),
+            (ColumnVector::Timestamp(v), Value::Timestamp(lit)) => select_by(v, lit, accept, selection),
+            (ColumnVector::Dictionary { codes, dictionary }, Value::String(lit)) => {
+                let matches: Vec<bool> = dictionary.iter().map(|s| accept(collation.compare_str(s, lit))).collect();
+                selection
+                    .iter()
+                    .copied()
+                    .filter(|&i| matches[codes[i as usize] as usize])
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Narrows `selection` to the string rows `accept` holds for, testing
+    /// each dictionary entry once. Non-string columns match nothing.
+    fn select_strings(&self, accept: impl Fn(&str) -> bool, selection: &[u32]) -> Vec<u32> {
+        match self {
+            ColumnVector::Nullable { values, .. } => values.select_strings(accept, &self.select_nulls(false, selection)),
+            ColumnVector::Dictionary { codes, dictionary } => {
+                let matches: Vec<bool> = dictionary.iter().map(|s| accept(s)).collect();
+                selection
+                    .iter()
+                    .copied()
+                    .filter(|&i| matches[codes[i as usize] as usize])
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Unwraps values of one fixed-width type, with `T::default()` standing in
+/// for NULL until the caller wraps the vector in `Nullable`.
+fn typed<T: Default>(values: &[Value], extract: fn(&Value) -> Option<T>) -> Result<Vec<T>, DbError> {
+    values
+        .iter()
+        .map(|v| match v {
+            Value::Null => Ok(T::default()),
+            v => extract(v).ok_or(DbError::TypeMismatch),
+        })
+        .collect()
+}
+
+fn select_by<T: Ord>(values: &[T], literal: &T, accept: fn(Ordering) -> bool, selection: &[u32]) -> Vec<u32> {
+    selection
+        .iter()
+        .copied()
+        .filter(|&i| accept(values[i as usize].cmp(literal)))
+        .collect()
+}
+
+/// Builds a dictionary vector, interning each distinct string once.
+#[derive(Default)]
+pub struct DictionaryBuilder {
+    codes: Vec<u32>,
+    dictionary: Vec<String>,
+    lookup: HashMap<String, u32>,
+    nulls: Vec<bool>,
+}
+
+/// The rows of a block stored without its NULL rows: None at each position
+/// in `nulls`, the next of `present` elsewhere.
+pub fn with_null_rows<'a, T: 'a>(
+    present: impl IntoIterator<Item = T> + 'a,
+    nulls: &'a [u32],
+) -> impl Iterator<Item = Option<T>> + 'a {
+    let mut present = present.into_iter();
+    let mut nulls = nulls.iter().peekable();
+    let mut row = 0;
+    std::iter::from_fn(move || {
+        let item = match nulls.next_if(|&&n| n == row) {
+            Some(_) => Some(None),
+            None => present.next().map(Some),
+        };
+        row += 1;
+        item
+    })
+}
+
+/// Wraps `vector` in `Nullable` if any entry of `nulls` is set.
+pub(crate) fn with_nulls(vector: ColumnVector, nulls: Vec<bool>) -> ColumnVector {
+    if nulls.iter().any(|&n| n) {
+        ColumnVector::Nullable {
+            values: Box::new(vector),
+            nulls,
+        }
+    } else {
+        vector
+    }
+}
+
+impl DictionaryBuilder {
+    fn intern(&mut self, s: &str) -> u32 {
+        if let Some(&code) = self.lookup.get(s) {
+            return code;
+        }
+        let code = self.dictionary.len() as u32;
+        self.dictionary.push(s.to_string());
+        self.lookup.insert(s.to_string(), code);
+        code
+    }
+
+    pub fn push(&mut self, s: &str) {
+        let code = self.intern(s);
+        self.codes.push(code);
+        self.nulls.push(false);
+    }
+
+    /// Appends a NULL row, stored as code 0 and masked out on read.
+    pub fn push_null(&mut self) {
+        self.codes.push(0);
+        self.nulls.push(true);
+    }
+
+    /// Starts the dictionary with `strings`, so each one's code is its
+    /// position, as an interned column's ids are. False, and nothing is
+    /// added, once the builder holds anything.
+    pub fn seed(&mut self, strings: &[String]) -> bool {
+        if !self.codes.is_empty() || !self.dictionary.is_empty() {
+            return false;
+        }
+        for s in strings {
+            self.intern(s);
+        }
+        true
+    }
+
+    /// Appends rows by code, each one below the dictionary's size.
+    pub fn extend_codes(&mut self, codes: impl IntoIterator<Item = u32>) -> Result<(), DbError> {
+        let size = self.dictionary.len() as u32;
+        for code in codes {
+            if code >= size {
+                return Err(DbError::SerializationError(format!("Invalid dictionary code: {}", code)));
+            }
+            self.codes.push(code);
+        }
+        self.nulls.resize(self.codes.len(), false);
+        Ok(())
+    }
+
+    /// Appends a dictionary-encoded block: each block entry is interned once
+    /// and the per-row ids are remapped without touching the strings again.
+    /// `ids` cover the block's rows other than its NULL rows, `nulls`.
+    pub fn extend_encoded(&mut self, ids: &[u64], dictionary: &[Option<String>], nulls: &[u32]) -> Result<(), DbError> {
+        let remap: Vec<Option<u32>> = dictionary
+            .iter()
+            .map(|entry| entry.as_deref().map(|s| self.intern(s)))
+            .collect();
+        self.codes.reserve(ids.len() + nulls.len());
+        for id in with_null_rows(ids, nulls) {
+            let Some(&id) = id else {
+                self.push_null();
+                continue;
+            };
+            let code = remap
+                .get(id as usize)
+                .copied()
+                .flatten()
+                .ok_or_else(|| DbError::SerializationError(format!("Invalid dictionary ID: {}", id)))?;
+            self.codes.push(code);
+            self.nulls.push(false);
+        }
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    pub fn finish(self) -> ColumnVector {
+        let vector = ColumnVector::Dictionary {
+            codes: self.codes,
+            dictionary: self.dictionary,
+        };
+        with_nulls(vector, self.nulls)
+    }
+}
+
+/// Row positions still alive in a batch, in ascending order.
+pub type Selection = Vec<u32>;
+
+/// A set of equally long columns read from one table.
+#[derive(Clone, Debug, Default)]
+pub struct Batch {
+    pub columns: HashMap<String, ColumnVector>,
+    pub row_count: usize,
+    /// Collations of string columns that do not compare binary.
+    pub collations: HashMap<String, Collation>,
+}
+
+impl Batch {
+    pub fn new(row_count: usize) -> Self {
+        Batch {
+            columns: HashMap::new(),
+            row_count,
+            collations: HashMap::new(),
+        }
+    }
+
+    /// Adds a column, truncating its values to the batch length.
+    pub fn add_column(&mut self, name: &str, values: &[Value], data_type: &DataType) -> Result<(), DbError> {
+        let values = &values[..self.row_count.min(values.len())];
+        self.columns
+            .insert(name.to_string(), ColumnVector::from_values(values, data_type)?);
+        Ok(())
+    }
+
+    pub fn add_vector(&mut self, name: &str, mut vector: ColumnVector) {
+        vector.truncate(self.row_count);
+        self.columns.insert(name.to_string(), vector);
+    }
+
+    pub fn column(&self, name: &str) -> Result<&ColumnVector, DbError> {
+        self.columns
+            .get(name)
+            .ok_or_else(|| DbError::QueryError(format!("Column {} not found in condition evaluation", name)))
+    }
+
+    pub fn collation(&self, name: &str) -> Collation {
+        self.collations.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn select_all(&self) -> Selection {
+        (0..self.row_count as u32).collect()
+    }
+
+    /// Rows of `selection` matching `condition`.
+    pub fn filter(&self, condition: &Condition, selection: &[u32]) -> Result<Selection, DbError> {
+        Ok(match condition {
+            Condition::Equal(col, val) => self.column(col)?.select(val, Ordering::is_eq, self.collation(col), selection),
+            Condition::GreaterThan(col, val) => self.column(col)?.select(val, Ordering::is_gt, self.collation(col), selection),
+            Condition::LessThan(col, val) => self.column(col)?.select(val, Ordering::is_lt, self.collation(col), selection),
+            Condition::GreaterThanOrEqual(col, val) => self.column(col)?.select(val, Ordering::is_ge, self.collation(col), selection),
+            Condition::LessThanOrEqual(col, val) => self.column(col)?.select(val, Ordering::is_le, self.collation(col), selection),
+            Condition::NotEqual(col, val) => self.column(col)?.select(val, Ordering::is_ne, self.collation(col), selection),
+            // Many values: one lookup per row rather than a pass per value.
+            Condition::In(col, values) if values.len() > IN_SCAN_LIMIT => {
+                let collation = self.collation(col);
+                let keys: HashSet<Value> = values.iter().map(|v| collation.key(v)).collect();
+                let column = self.column(col)?;
+                selection
+                    .iter()
+                    .copied()
+                    .filter(|&i| {
+                        let value = column.value(i as usize);
+                        !value.is_null() && keys.contains(&collation.key(&value))
+                    })
+                    .collect()
+            }
+            Condition::In(col, values) => {
+                let mut matched = Selection::new();
+                for val in values {
+                    let rows = self.column(col)?.select(val, Ordering::is_eq, self.collation(col), selection);
+                    matched = merge_selections(&matched, &rows);
+                }
+                matched
+            }
+            Condition::Between(col, low, high) => {
+                let column = self.column(col)?;
+                let rows = column.select(low, Ordering::is_ge, self.collation(col), selection);
+                column.select(high, Ordering::is_le, self.collation(col), &rows)
+            }
+            Condition::Like(col, pattern) => self.column(col)?.select_strings(|s| like_match(pattern, s), selection),
+            Condition::IsNull(col) => self.column(col)?.select_nulls(true, selection),
+            Condition::IsNotNull(col) => self.column(col)?.select_nulls(false, selection),
+            Condition::Regexp(col, pattern) => self.column(col)?.select_strings(|s| pattern.is_match(s), selection),
+            Condition::InTuple(cols, tuples) => {
+                let mut matched = Selection::new();
+                for tuple in tuples {
+                    let mut rows = selection.to_vec();
+                    for (col, val) in cols.iter().zip(tuple) {
+                        rows = self.column(col)?.select(val, Ordering::is_eq, self.collation(col), &rows);
+                    }
+                    matched = merge_selections(&matched, &rows);
+                }
+                matched
+            }
+            Condition::Compare(left, op, right) => {
+                let mut rows = Selection::new();
+                for &i in selection {
+                    let column = |col: &str| Ok(self.column(col)?.value(i as usize));
+                    if op.holds(&left.evaluate_with(&column)?, &right.evaluate_with(&column)?) {
+                        rows.push(i);
+                    }
+                }
+                rows
+            }
+            Condition::And(left, right) => {
+                let left = self.filter(left, selection)?;
+                self.filter(right, &left)?
+            }
+            Condition::Or(left, right) => merge_selections(&self.filter(left, selection)?, &self.filter(right, selection)?),
+            Condition::InSubquery(..) | Condition::CompareSubquery(..) => return Err(unmaterialized()),
+            Condition::Not(inner) => {
+                let matched = self.filter(inner, selection)?;
+                let mut rows: Selection =
+                    selection.iter().copied().filter(|i| matched.binary_search(i).is_err()).collect();
+                for col in collect_condition_columns(inner) {
+                    rows = self.column(&col)?.select_nulls(false, &rows);
+                }
+                rows
+            }
+        })
+    }
+
+    /// Materializes the selected rows of `columns` in order.
+    pub fn rows(&self, columns: &[String], selection: &[u32]) -> Result<Vec<Vec<Value>>, DbError> {
+        let vectors = columns
+            .iter()
+            .map(|c| self.column(c))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(selection
+            .iter()
+            .map(|&i| vectors.iter().map(|v| v.value(i as usize)).collect())
+            .collect())
+    }
+
+    pub fn values(&self, column: &str, selection: &[u32]) -> Result<Vec<Value>, DbError> {
+        let vector = self.column(column)?;
+        Ok(selection.iter().map(|&i| vector.value(i as usize)).collect())
+    }
+}
+
+/// Sorted union of two ascending selections.
+fn merge_selections(a: &[u32], b: &[u32]) -> Selection {
+    let mut out = Vec::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => {
+                out.push(a[i]);
+                i += 1;
+            }
+            Ordering::Greater => {
+                out.push(b[j]);
+                j += 1;
+            }
+            Ordering::Equal => {
+                out.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    out.extend_from_slice(&a[i..]);
+    out.extend_from_slice(&b[j..]);
+    out
+}
@@ -0,0 +1,480 @@
+use crate::query::expr::Expr;
+use crate::query::system::system_table;
+use crate::query::{Aggregation, Condition, GroupKey, Hint, Query};
+use crate::schema::{Schema, Table};
+use crate::types::{DbError, Value};
+
+/// Converts literals to the types of the columns they are stored in or
+/// compared with, so `"2024-01-01"` can be inserted into a DATE column and
+/// `5` compared with an INT64 one. Values written to a table must convert;
+/// a condition literal that does not is left as is and matches nothing.
+/// An INSERT that leaves columns out gets their defaults, see `bind_row`.
+/// `table.column` references are resolved first, see `resolve_columns`.
+pub fn bind_literals(query: Query, schema: &Schema) -> Result<Query, DbError> {
+    let query = resolve_columns(query)?;
+    Ok(match query {
+        Query::Insert { table, values, returning } => {
+            let values = match schema.get_table(&table) {
+                Some(table_def) => bind_row(table_def, &[], values)?,
+                None => values,
+            };
+            Query::Insert { table, values, returning }
+        }
+        Query::InsertRows { table, columns, rows, returning } => match schema.get_table(&table) {
+            Some(table_def) => {
+                let rows = rows
+                    .into_iter()
+                    .map(|row| bind_row(table_def, &columns, row))
+                    .collect::<Result<_, _>>()?;
+                Query::InsertRows { table, columns: Vec::new(), rows, returning }
+            }
+            None => Query::InsertRows { table, columns, rows, returning },
+        },
+        Query::Update {
+            table,
+            assignments,
+            condition,
+        } => {
+            let table_def = schema.get_table(&table);
+            let assignments = assignments
+                .into_iter()
+                .map(|(column, value)| {
+                    match table_def.and_then(|t| t.get_column(&column)) {
+                        Some(col) => value.coerce(&col.data_type),
+                        None => Ok(value),
+                    }
+                    .map(|value| (column, value))
+                })
+                .collect::<Result<_, _>>()?;
+            let condition = condition.map(|c| bind_condition(c, &[table_def]));
+            Query::Update {
+                table,
+                assignments,
+                condition,
+            }
+        }
+        Query::Select {
+            table,
+            columns,
+            distinct,
+            condition,
+            sample,
+            order_by,
+            limit,
+            offset,
+            hints,
+        } => {
+            let system = system_table(&table);
+            let condition = condition.map(|c| bind_condition(c, &[schema.get_table(&table).or(system.as_ref())]));
+            Query::Select {
+                table,
+                columns,
+                distinct,
+                condition,
+                sample,
+                order_by,
+                limit,
+                offset,
+                hints,
+            }
+        }
+        Query::SelectAggregate {
+            table,
+            aggregations,
+            condition,
+            sample,
+            group_by,
+            hints,
+        } => {
+            let system = system_table(&table);
+            let condition = condition.map(|c| bind_condition(c, &[schema.get_table(&table).or(system.as_ref())]));
+            Query::SelectAggregate {
+                table,
+                aggregations,
+                condition,
+                sample,
+                group_by,
+                hints,
+            }
+        }
+        Query::Delete {
+            table,
+            condition,
+            returning,
+        } => {
+            let condition = condition.map(|c| bind_condition(c, &[schema.get_table(&table)]));
+            Query::Delete {
+                table,
+                condition,
+                returning,
+            }
+        }
+        Query::Join {
+            left_table,
+            right_table,
+            left_column,
+            right_column,
+            operator,
+            columns,
+            condition,
+            hints,
+        } => {
+            let tables = [schema.get_table(&left_table), schema.get_table(&right_table)];
+            let condition = condition.map(|c| bind_condition(c, &tables));
+            Query::Join {
+                left_table,
+                right_table,
+                left_column,
+                right_column,
+                operator,
+                columns,
+                condition,
+                hints,
+            }
+        }
+        Query::VerifyTable { table, condition, limit } => {
+            let condition = bind_condition(condition, &[schema.get_table(&table)]);
+            Query::VerifyTable { table, condition, limit }
+        }
+        other => other,
+    })
+}
+
+/// Widens an inserted row to the table's columns. Without a column list,
+/// a row that stops short of trailing columns with defaults gets them and
+/// any other row is left for the storage layer to reject. With one, each
+/// omitted column gets its default, or NULL if it has none.
+fn bind_row(table_def: &Table, columns: &[String], row: Vec<Value>) -> Result<Vec<Value>, DbError> {
+    if columns.is_empty() {
+        if table_def.columns.len() < row.len() || table_def.columns[row.len()..].iter().any(|c| c.default.is_none()) {
+            return Ok(row);
+        }
+        let mut bound = Vec::with_capacity(table_def.columns.len());
+        for (i, col) in table_def.columns.iter().enumerate() {
+            bound.push(match row.get(i) {
+                Some(value) => table_def.validation.coerce(value.clone(), &col.data_type)?,
+                None => col.default_value()?,
+            });
+        }
+        return Ok(bound);
+    }
+    if columns.len() != row.len() {
+        return Err(DbError::QueryError(format!(
+            "INSERT lists {} columns but a row has {} values",
+            columns.len(),
+            row.len()
+        )));
+    }
+    for (i, name) in columns.iter().enumerate() {
+        if !table_def.columns.iter().any(|c| c.name == *name) {
+            return Err(DbError::QueryError(format!("Column {}.{} not found", table_def.name, name)));
+        }
+        if columns[..i].contains(name) {
+            return Err(DbError::QueryError(format!("Column {} listed twice in INSERT", name)));
+        }
+    }
+    table_def
+        .columns
+        .iter()
+        .map(|col| match columns.iter().position(|name| *name == col.name) {
+            Some(i) => table_def.validation.coerce(row[i].clone(), &col.data_type),
+            None if col.default.is_some() => col.default_value(),
+            None => Ok(Value::Null),
+        })
+        .collect()
+}
+
+/// Drops the qualifier from `table.column` references in statements that read
+/// a single table, so the select list, WHERE, GROUP BY and ORDER BY all
+/// accept them. A qualifier naming another table is an error. Joins keep
+/// their qualifiers, which pick the side a column is read from.
+pub fn resolve_columns(query: Query) -> Result<Query, DbError> {
+    Ok(match query {
+        Query::Select { table, columns, distinct, condition, sample, order_by, limit, offset, hints } => {
+            let name = |col: String| unqualified(&table, col);
+            Query::Select {
+                columns: columns.into_iter().map(|e| map_expr(e, &name)).collect::<Result<_, _>>()?,
+                distinct,
+                condition: condition.map(|c| map_condition(c, &name)).transpose()?,
+                order_by: order_by
+                    .into_iter()
+                    .map(|(col, order)| Ok((name(col)?, order)))
+                    .collect::<Result<_, DbError>>()?,
+                table,
+                sample,
+                limit,
+                offset,
+                hints,
+            }
+        }
+        Query::SelectAggregate { table, aggregations, condition, sample, group_by, hints } => {
+            let name = |col: String| unqualified(&table, col);
+            Query::SelectAggregate {
+                aggregations: aggregations
+                    .into_iter()
+                    .map(|agg| map_aggregation(agg, &name))
+                    .collect::<Result<_, _>>()?,
+                condition: condition.map(|c| map_condition(c, &name)).transpose()?,
+                group_by: group_by.into_iter().map(|key| map_group_key(key, &name)).collect::<Result<_, _>>()?,
+                table,
+                sample,
+                hints,
+            }
+        }
+        Query::Update { table, assignments, condition } => {
+            let name = |col: String| unqualified(&table, col);
+            Query::Update {
+                assignments: assignments
+                    .into_iter()
+                    .map(|(col, value)| Ok((name(col)?, value)))
+                    .collect::<Result<_, DbError>>()?,
+                condition: condition.map(|c| map_condition(c, &name)).transpose()?,
+                table,
+            }
+        }
+        Query::Delete { table, condition, returning } => {
+            let name = |col: String| unqualified(&table, col);
+            Query::Delete {
+                condition: condition.map(|c| map_condition(c, &name)).transpose()?,
+                returning: returning.into_iter().map(name).collect::<Result<_, _>>()?,
+                table,
+            }
+        }
+        Query::VerifyTable { table, condition, limit } => {
+            let condition = map_condition(condition, &|col| unqualified(&table, col))?;
+            Query::VerifyTable { table, condition, limit }
+        }
+        Query::Explain { query, analyze, format } => Query::Explain {
+            query: Box::new(resolve_columns(*query)?),
+            analyze,
+            format,
+        },
+        other => other,
+    })
+}
+
+/// Rewrites `alias.column` references to `table.column` for the table
+/// aliases a FROM clause declared, given as `(alias, table)` pairs.
+pub fn resolve_table_aliases(query: Query, aliases: &[(String, String)]) -> Result<Query, DbError> {
+    if aliases.is_empty() {
+        return Ok(query);
+    }
+    let name = |col: String| -> Result<String, DbError> {
+        let table = col
+            .rsplit_once('.')
+            .and_then(|(qualifier, column)| Some((aliases.iter().find(|(alias, _)| alias == qualifier)?, column)));
+        Ok(match table {
+            Some(((_, table), column)) => format!("{}.{}", table, column),
+            None => col,
+        })
+    };
+    // `NO_INDEX(e)` may name a table by its alias too.
+    let hint = |hint: Hint| match hint {
+        Hint::NoIndex(table) => match aliases.iter().find(|(alias, _)| *alias == table) {
+            Some((_, table)) => Hint::NoIndex(table.clone()),
+            None => Hint::NoIndex(table),
+        },
+        other => other,
+    };
+    Ok(match query {
+        Query::Select { table, columns, distinct, condition, sample, order_by, limit, offset, hints } => Query::Select {
+            columns: columns.into_iter().map(|e| map_expr(e, &name)).collect::<Result<_, _>>()?,
+            distinct,
+            condition: condition.map(|c| map_condition(c, &name)).transpose()?,
+            order_by: order_by
+                .into_iter()
+                .map(|(col, order)| Ok((name(col)?, order)))
+                .collect::<Result<_, DbError>>()?,
+            hints: hints.into_iter().map(hint).collect(),
+            table,
+            sample,
+            limit,
+            offset,
+        },
+        Query::SelectAggregate { table, aggregations, condition, sample, group_by, hints } => Query::SelectAggregate {
+            aggregations: aggregations
+                .into_iter()
+                .map(|agg| map_aggregation(agg, &name))
+                .collect::<Result<_, _>>()?,
+            condition: condition.map(|c| map_condition(c, &name)).transpose()?,
+            group_by: group_by.into_iter().map(|key| map_group_key(key, &name)).collect::<Result<_, _>>()?,
+            hints: hints.into_iter().map(hint).collect(),
+            table,
+            sample,
+        },
+        Query::Join { left_table, right_table, left_column, right_column, operator, columns, condition, hints } => {
+            Query::Join {
+                columns: columns.into_iter().map(|e| map_expr(e, &name)).collect::<Result<_, _>>()?,
+                condition: condition.map(|c| map_condition(c, &name)).transpose()?,
+                hints: hints.into_iter().map(hint).collect(),
+                left_table,
+                right_table,
+                left_column,
+                right_column,
+                operator,
+            }
+        }
+        other => other,
+    })
+}
+
+fn unqualified(table: &str, column: String) -> Result<String, DbError> {
+    // Table names may contain dots themselves, column names do not.
+    match column.rsplit_once('.') {
+        Some((qualifier, col)) if qualifier == table => Ok(col.to_string()),
+        Some((qualifier, _)) => Err(DbError::QueryError(format!("Table {} is not part of the query", qualifier))),
+        None => Ok(column),
+    }
+}
+
+type Rename<'a> = &'a dyn Fn(String) -> Result<String, DbError>;
+
+fn map_expr(expr: Expr, name: Rename) -> Result<Expr, DbError> {
+    Ok(match expr {
+        Expr::Column(col) => Expr::Column(name(col)?),
+        Expr::Coalesce(args) => {
+            Expr::Coalesce(args.into_iter().map(|e| map_expr(e, name)).collect::<Result<_, _>>()?)
+        }
+        Expr::NullIf(value, other) => {
+            Expr::NullIf(Box::new(map_expr(*value, name)?), Box::new(map_expr(*other, name)?))
+        }
+        Expr::Mask(mask, value) => Expr::Mask(mask, Box::new(map_expr(*value, name)?)),
+        Expr::Alias(value, alias) => Expr::Alias(Box::new(map_expr(*value, name)?), alias),
+        Expr::Arithmetic(op, left, right) => {
+            Expr::Arithmetic(op, Box::new(map_expr(*left, name)?), Box::new(map_expr(*right, name)?))
+        }
+        other @ (Expr::Literal(_) | Expr::CurrentTimestamp | Expr::CurrentDate) => other,
+    })
+}
+
+fn map_group_key(key: GroupKey, name: Rename) -> Result<GroupKey, DbError> {
+    Ok(match key {
+        GroupKey::Column(col) => GroupKey::Column(name(col)?),
+        GroupKey::TimeBucket { column, seconds } => GroupKey::TimeBucket {
+            column: name(column)?,
+            seconds,
+        },
+    })
+}
+
+fn map_aggregation(aggregation: Aggregation, name: Rename) -> Result<Aggregation, DbError> {
+    Ok(match aggregation {
+        Aggregation::Count => Aggregation::Count,
+        Aggregation::CountColumn(col) => Aggregation::CountColumn(name(col)?),
+        Aggregation::CountDistinct(col) => Aggregation::CountDistinct(name(col)?),
+        Aggregation::Sum(col) => Aggregation::Sum(name(col)?),
+        Aggregation::Avg(col) => Aggregation::Avg(name(col)?),
+        Aggregation::Min(col) => Aggregation::Min(name(col)?),
+        Aggregation::Max(col) => Aggregation::Max(name(col)?),
+        Aggregation::ApproxCountDistinct(col) => Aggregation::ApproxCountDistinct(name(col)?),
+        Aggregation::ApproxQuantile(col, q) => Aggregation::ApproxQuantile(name(col)?, q),
+        Aggregation::Percentile(col, p) => Aggregation::Percentile(name(col)?, p),
+        Aggregation::Histogram(col, buckets) => Aggregation::Histogram(name(col)?, buckets),
+        Aggregation::First(col, order) => Aggregation::First(name(col)?, name(order)?),
+        Aggregation::Last(col, order) => Aggregation::Last(name(col)?, name(order)?),
+    })
+}
+
+fn map_condition(condition: Condition, name: Rename) -> Result<Condition, DbError> {
+    Ok(match condition {
+        Condition::Equal(col, val) => Condition::Equal(name(col)?, val),
+        Condition::GreaterThan(col, val) => Condition::GreaterThan(name(col)?, val),
+        Condition::LessThan(col, val) => Condition::LessThan(name(col)?, val),
+        Condition::LessThanOrEqual(col, val) => Condition::LessThanOrEqual(name(col)?, val),
+        Condition::GreaterThanOrEqual(col, val) => Condition::GreaterThanOrEqual(name(col)?, val),
+        Condition::NotEqual(col, val) => Condition::NotEqual(name(col)?, val),
+        Condition::In(col, values) => Condition::In(name(col)?, values),
+        Condition::Between(col, low, high) => Condition::Between(name(col)?, low, high),
+        Condition::Like(col, pattern) => Condition::Like(name(col)?, pattern),
+        Condition::IsNull(col) => Condition::IsNull(name(col)?),
+        Condition::IsNotNull(col) => Condition::IsNotNull(name(col)?),
+        Condition::InTuple(cols, tuples) => {
+            Condition::InTuple(cols.into_iter().map(name).collect::<Result<_, _>>()?, tuples)
+        }
+        Condition::Regexp(col, pattern) => Condition::Regexp(name(col)?, pattern),
+        Condition::Compare(left, op, right) => Condition::Compare(map_expr(left, name)?, op, map_expr(right, name)?),
+        // The subquery names its own tables and is resolved on its own.
+        Condition::InSubquery(col, query) => Condition::InSubquery(name(col)?, query),
+        Condition::CompareSubquery(left, op, query) => Condition::CompareSubquery(map_expr(left, name)?, op, query),
+        Condition::And(left, right) => {
+            Condition::And(Box::new(map_condition(*left, name)?), Box::new(map_condition(*right, name)?))
+        }
+        Condition::Or(left, right) => {
+            Condition::Or(Box::new(map_condition(*left, name)?), Box::new(map_condition(*right, name)?))
+        }
+        Condition::Not(inner) => Condition::Not(Box::new(map_condition(*inner, name)?)),
+    })
+}
+
+fn bind_condition(condition: Condition, tables: &[Option<&Table>]) -> Condition {
+    let bind = |column: &str, value: Value| {
+        // Join conditions keep their `table.column` qualifiers.
+        let col = match column.rsplit_once('.') {
+            Some((table, column)) => {
+                tables.iter().flatten().find(|t| t.name == table).and_then(|t| t.get_column(column))
+            }
+            None => tables.iter().flatten().find_map(|t| t.get_column(column)),
+        };
+        match col {
+            Some(col) => value.clone().coerce(&col.data_type).unwrap_or(value),
+            None => value,
+        }
+    };
+    match condition {
+        Condition::Equal(col, val) => {
+            let val = bind(&col, val);
+            Condition::Equal(col, val)
+        }
+        Condition::GreaterThan(col, val) => {
+            let val = bind(&col, val);
+            Condition::GreaterThan(col, val)
+        }
+        Condition::LessThan(col, val) => {
+            let val = bind(&col, val);
+            Condition::LessThan(col, val)
+        }
+        Condition::LessThanOrEqual(col, val) => {
+            let val = bind(&col, val);
+            Condition::LessThanOrEqual(col, val)
+        }
+        Condition::GreaterThanOrEqual(col, val) => {
+            let val = bind(&col, val);
+            Condition::GreaterThanOrEqual(col, val)
+        }
+        Condition::NotEqual(col, val) => {
+            let val = bind(&col, val);
+            Condition::NotEqual(col, val)
+        }
+        Condition::In(col, values) => {
+            let values = values.into_iter().map(|val| bind(&col, val)).collect();
+            Condition::In(col, values)
+        }
+        Condition::Between(col, low, high) => {
+            let (low, high) = (bind(&col, low), bind(&col, high));
+            Condition::Between(col, low, high)
+        }
+        Condition::InTuple(cols, tuples) => {
+            let tuples = tuples
+                .into_iter()
+                .map(|tuple| cols.iter().zip(tuple).map(|(col, val)| bind(col, val)).collect())
+                .collect();
+            Condition::InTuple(cols, tuples)
+        }
+        Condition::And(left, right) => Condition::And(
+            Box::new(bind_condition(*left, tables)),
+            Box::new(bind_condition(*right, tables)),
+        ),
+        Condition::Or(left, right) => Condition::Or(
+            Box::new(bind_condition(*left, tables)),
+            Box::new(bind_condition(*right, tables)),
+        ),
+        Condition::Not(inner) => Condition::Not(Box::new(bind_condition(*inner, tables))),
+        other @ (Condition::IsNull(_)
+        | Condition::IsNotNull(_)
+        | Condition::Regexp(..)
+        | Condition::Like(..)
+        | Condition::Compare(..)
+        | Condition::InSubquery(..)
+        | Condition::CompareSubquery(..)) => other,
+    }
+}
@@ -0,0 +1,104 @@
+use crate::types::Value;
+use std::collections::{HashMap, VecDeque};
+
+struct CacheEntry {
+    table_versions: Vec<(String, u64)>,
+    rows: Vec<Vec<Value>>,
+}
+
+pub struct QueryCache {
+    entries: HashMap<String, CacheEntry>,
+    order: VecDeque<String>,
+    max_entries: usize,
+    hits: u64,
+    misses: u64,
+}
+
+impl QueryCache {
+    pub fn new(max_entries: usize) -> Self {
+        QueryCache {
+            entries: HashMap::new(),
+            order: VecDeque::new(),
+            max_entries,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    /// Returns the cached rows for `key` if every table it read is still at
+    /// the version recorded when the result was cached.
+    pub fn get(&mut self, key: &str, table_versions: &[(String, u64)]) -> Option<Vec<Vec<Value>>> {
+        match self.entries.get(key) {
+            Some(entry) if entry.table_versions == table_versions => {
+                self.hits += 1;
+                Some(entry.rows.clone())
+            }
+            Some(_) => {
+                self.remove(key);
+                self.misses += 1;
+                None
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    pub fn put(&mut self, key: String, table_versions: Vec<(String, u64)>, rows: Vec<Vec<Value>>) {
+        if self.max_entries == 0 {
+            return;
+        }
+        if self.entries.contains_key(&key) {
+            self.remove(&key);
+        }
+        while self.entries.len() >= self.max_entries {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+        self.order.push_back(key.clone());
+        self.entries.insert(key, CacheEntry { table_versions, rows });
+    }
+
+    pub fn invalidate_table(&mut self, table: &str) {
+        let stale: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.table_versions.iter().any(|(t, _)| t == table))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in stale {
+            self.remove(&key);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.order.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses
+    }
+
+    fn remove(&mut self, key: &str) {
+        self.entries.remove(key);
+        self.order.retain(|k| k != key);
+    }
+}
@@ -1,49 +1,54 @@
-use crate::query::Condition;
+use crate::query::pattern::{like_match, like_may_match};
+use crate::query::{collect_condition_columns, unmaterialized, Condition};
 use crate::schema::metadata::BlockInfo;
-use crate::types::{DbError, Value};
+use crate::types::{Collation, DbError, Value};
+use std::cmp::Ordering;
+use std::collections::HashMap;
 
 pub fn evaluate_condition_block(condition: &Condition, column_name: &str, block: &BlockInfo) -> bool {
     match condition {
         Condition::Equal(col, val) if col == column_name => {
-            match (&block.min, &block.max, val) {
-                (Value::Int32(min), Value::Int32(max), Value::Int32(v)) => min <= v && v <= max,
-                (Value::Float32(min), Value::Float32(max), Value::Float32(v)) => min <= v && v <= max,
-                (Value::String(min), Value::String(max), Value::String(v)) => min <= v && v <= max,
-                _ => false,
-            }
+            block.min.compare(val).is_some_and(Ordering::is_le) && block.max.compare(val).is_some_and(Ordering::is_ge)
         }
         Condition::GreaterThan(col, val) if col == column_name => {
-            match (&block.max, val) {
-                (Value::Int32(max), Value::Int32(v)) => max > v,
-                (Value::Float32(max), Value::Float32(v)) => max > v,
-                (Value::String(max), Value::String(v)) => max > v,
-                _ => false,
-            }
+            block.max.compare(val).is_some_and(Ordering::is_gt)
         }
         Condition::LessThan(col, val) if col == column_name => {
-            match (&block.min, val) {
-                (Value::Int32(min), Value::Int32(v)) => min < v,
-                (Value::Float32(min), Value::Float32(v)) => min < v,
-                (Value::String(min), Value::String(v)) => min < v,
-                _ => false,
-            }
+            block.min.compare(val).is_some_and(Ordering::is_lt)
         }
+        // A block can hold a match if its smallest value is at most `val`...
         Condition::LessThanOrEqual(col, val) if col == column_name => {
-            match (&block.max, val) {
-                (Value::Int32(max), Value::Int32(v)) => max <= v,
-                (Value::Float32(max), Value::Float32(v)) => max <= v,
-                (Value::String(max), Value::String(v)) => max <= v,
-                _ => false,
-            }
+            block.min.compare(val).is_some_and(Ordering::is_le)
         }
+        // ...or, for >=, if its largest value is at least `val`.
         Condition::GreaterThanOrEqual(col, val) if col == column_name => {
-            match (&block.min, val) {
-                (Value::Int32(min), Value::Int32(v)) => min >= v,
-                (Value::Float32(min), Value::Float32(v)) => min >= v,
-                (Value::String(min), Value::String(v)) => min >= v,
-                _ => false,
-            }
+            block.max.compare(val).is_some_and(Ordering::is_ge)
+        }
+        // Only a block holding nothing but `val` can be skipped.
+        Condition::NotEqual(col, val) if col == column_name => {
+            !(block.min.compare(val).is_some_and(Ordering::is_eq)
+                && block.max.compare(val).is_some_and(Ordering::is_eq))
         }
+        Condition::In(col, values) if col == column_name => values.iter().any(|val| {
+            block.min.compare(val).is_some_and(Ordering::is_le) && block.max.compare(val).is_some_and(Ordering::is_ge)
+        }),
+        Condition::Between(col, low, high) if col == column_name => {
+            block.max.compare(low).is_some_and(Ordering::is_ge) && block.min.compare(high).is_some_and(Ordering::is_le)
+        }
+        // NULL or non-string bounds hold no string LIKE could match.
+        Condition::Like(col, pattern) if col == column_name => match (&block.min, &block.max) {
+            (Value::String(min), Value::String(max)) => like_may_match(pattern, min, max),
+            _ => false,
+        },
+        Condition::IsNull(col) if col == column_name => !block.nulls.is_empty(),
+        Condition::IsNotNull(col) if col == column_name => block.nulls.len() < block.row_count,
+        Condition::InTuple(cols, tuples) => match cols.iter().position(|c| c == column_name) {
+            Some(pos) => tuples.iter().any(|tuple| {
+                block.min.compare(&tuple[pos]).is_some_and(Ordering::is_le)
+                    && block.max.compare(&tuple[pos]).is_some_and(Ordering::is_ge)
+            }),
+            None => true,
+        },
         Condition::And(left, right) => {
             evaluate_condition_block(left, column_name, block)
                 && evaluate_condition_block(right, column_name, block)
@@ -56,65 +61,93 @@ pub fn evaluate_condition_block(condition: &Condition, column_name: &str, block:
     }
 }
 
+/// Whether rows at one block position can match `condition`, given that
+/// position's block of each column. Unlike `evaluate_condition_block`, an OR
+/// over different columns prunes. `block` returns `None` for a column whose
+/// bounds can't be used.
+pub fn evaluate_condition_blocks<'a>(condition: &Condition, block: &impl Fn(&str) -> Option<&'a BlockInfo>) -> bool {
+    match condition {
+        Condition::And(left, right) => {
+            evaluate_condition_blocks(left, block) && evaluate_condition_blocks(right, block)
+        }
+        Condition::Or(left, right) => evaluate_condition_blocks(left, block) || evaluate_condition_blocks(right, block),
+        leaf => collect_condition_columns(leaf)
+            .iter()
+            .all(|col| block(col).is_none_or(|b| evaluate_condition_block(leaf, col, b))),
+    }
+}
+
+/// Evaluates `condition` on one row. String columns listed in `collations`
+/// compare under that collation, all others binary.
 pub fn evaluate_condition_row(
     condition: &Condition,
-    column_values: &std::collections::HashMap<String, Vec<Value>>,
+    column_values: &HashMap<String, Vec<Value>>,
+    collations: &HashMap<String, Collation>,
     row_index: usize,
 ) -> Result<bool, DbError> {
+    let value = |col: &str| -> Result<Option<&Value>, DbError> {
+        let values = column_values
+            .get(col)
+            .ok_or_else(|| DbError::QueryError(format!("Column {} not found in condition evaluation", col)))?;
+        Ok(values.get(row_index))
+    };
+    // NULL compares as unknown, so it never satisfies a comparison.
+    let compare = |col: &str, val: &Value, accept: fn(Ordering) -> bool| -> Result<bool, DbError> {
+        let collation = collations.get(col).copied().unwrap_or_default();
+        Ok(value(col)?.is_some_and(|v| collation.compare(v, val).is_some_and(accept)))
+    };
     match condition {
-        Condition::Equal(col, val) => {
-            let values = column_values
-                .get(col)
-                .ok_or_else(|| DbError::QueryError(format!("Column {} not found in condition evaluation", col)))?;
-            Ok(values.get(row_index).map_or(false, |v| v == val))
-        }
-        Condition::GreaterThan(col, val) => {
-            let values = column_values
-                .get(col)
-                .ok_or_else(|| DbError::QueryError(format!("Column {} not found in condition evaluation", col)))?;
-            Ok(values.get(row_index).map_or(false, |v| match (v, val) {
-                (Value::Int32(a), Value::Int32(b)) => a > b,
-                (Value::Float32(a), Value::Float32(b)) => a > b,
-                (Value::String(a), Value::String(b)) => a > b,
-                _ => false,
-            }))
-        }
-        Condition::LessThan(col, val) => {
-            let values = column_values
-                .get(col)
-                .ok_or_else(|| DbError::QueryError(format!("Column {} not found in condition evaluation", col)))?;
-            Ok(values.get(row_index).map_or(false, |v| match (v, val) {
-                (Value::Int32(a), Value::Int32(b)) => a < b,
-                (Value::Float32(a), Value::Float32(b)) => a < b,
-                (Value::String(a), Value::String(b)) => a < b,
-                _ => false,
-            }))
-        }
-        Condition::LessThanOrEqual(col, val) => {
-            let values = column_values
-                .get(col)
-                .ok_or_else(|| DbError::QueryError(format!("Column {} not found in condition evaluation", col)))?;
-            Ok(values.get(row_index).map_or(false, |v| match (v, val) {
-                (Value::Int32(a), Value::Int32(b)) => a <= b,
-                (Value::Float32(a), Value::Float32(b)) => a <= b,
-                (Value::String(a), Value::String(b)) => a <= b,
-                _ => false,
-            }))
-        }
-        Condition::GreaterThanOrEqual(col, val) => {
-            let values = column_values
-                .get(col)
-                .ok_or_else(|| DbError::QueryError(format!("Column {} not found in condition evaluation", col)))?;
-            Ok(values.get(row_index).map_or(false, |v| match (v, val) {
-                (Value::Int32(a), Value::Int32(b)) => a >= b,
-                (Value::Float32(a), Value::Float32(b)) => a >= b,
-                (Value::String(a), Value::String(b)) => a >= b,
-                _ => false,
-            }))
-        }
-        Condition::And(left, right) => Ok(evaluate_condition_row(left, column_values, row_index)?
-            && evaluate_condition_row(right, column_values, row_index)?),
-        Condition::Or(left, right) => Ok(evaluate_condition_row(left, column_values, row_index)?
-            || evaluate_condition_row(right, column_values, row_index)?),
+        Condition::Equal(col, val) => compare(col, val, Ordering::is_eq),
+        Condition::GreaterThan(col, val) => compare(col, val, Ordering::is_gt),
+        Condition::LessThan(col, val) => compare(col, val, Ordering::is_lt),
+        Condition::LessThanOrEqual(col, val) => compare(col, val, Ordering::is_le),
+        Condition::GreaterThanOrEqual(col, val) => compare(col, val, Ordering::is_ge),
+        Condition::NotEqual(col, val) => compare(col, val, Ordering::is_ne),
+        Condition::In(col, values) => {
+            for val in values {
+                if compare(col, val, Ordering::is_eq)? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        Condition::Between(col, low, high) => {
+            Ok(compare(col, low, Ordering::is_ge)? && compare(col, high, Ordering::is_le)?)
+        }
+        Condition::Like(col, pattern) => Ok(matches!(value(col)?, Some(Value::String(s)) if like_match(pattern, s))),
+        Condition::IsNull(col) | Condition::IsNotNull(col) => {
+            let is_null = value(col)?.is_some_and(Value::is_null);
+            Ok(is_null == matches!(condition, Condition::IsNull(_)))
+        }
+        Condition::Regexp(col, pattern) => Ok(matches!(value(col)?, Some(Value::String(s)) if pattern.is_match(s))),
+        Condition::InTuple(cols, tuples) => {
+            for tuple in tuples {
+                let mut matched = true;
+                for (col, val) in cols.iter().zip(tuple) {
+                    matched = matched && compare(col, val, Ordering::is_eq)?;
+                }
+                if matched {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+        Condition::Compare(left, op, right) => {
+            let column = |col: &str| Ok(value(col)?.cloned().unwrap_or(Value::Null));
+            Ok(op.holds(&left.evaluate_with(&column)?, &right.evaluate_with(&column)?))
+        }
+        Condition::InSubquery(..) | Condition::CompareSubquery(..) => Err(unmaterialized()),
+        Condition::And(left, right) => Ok(evaluate_condition_row(left, column_values, collations, row_index)?
+            && evaluate_condition_row(right, column_values, collations, row_index)?),
+        Condition::Or(left, right) => Ok(evaluate_condition_row(left, column_values, collations, row_index)?
+            || evaluate_condition_row(right, column_values, collations, row_index)?),
+        Condition::Not(inner) => {
+            for col in collect_condition_columns(inner) {
+                if value(&col)?.is_none_or(Value::is_null) {
+                    return Ok(false);
+                }
+            }
+            Ok(!evaluate_condition_row(inner, column_values, collations, row_index)?)
+        }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,133 @@
+use crate::query::result::expr_name;
+use crate::query::{Condition, JoinOperator};
+use crate::types::Value;
+use serde::{Deserialize, Serialize};
+
+/// How `EXPLAIN` prints its plan.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ExplainFormat {
+    /// One indented line per operator.
+    Text,
+    /// The whole plan as a single JSON document, for external tooling.
+    Json,
+}
+
+/// One operator of a query plan. Rows flow from the children up to the
+/// parent. `actual_rows` is only known under `EXPLAIN ANALYZE`; block counts
+/// only for scans of stored tables.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PlanNode {
+    pub operator: String,
+    pub table: Option<String>,
+    /// What the operator does beyond its name: columns, sort keys, join keys.
+    pub detail: Option<String>,
+    /// Condition a `Filter` keeps rows for.
+    pub predicate: Option<String>,
+    pub estimated_rows: Option<f64>,
+    pub actual_rows: Option<usize>,
+    #[serde(default)]
+    pub blocks_read: Option<usize>,
+    #[serde(default)]
+    pub blocks_total: Option<usize>,
+    pub children: Vec<PlanNode>,
+}
+
+impl PlanNode {
+    pub fn new(operator: &str) -> Self {
+        PlanNode {
+            operator: operator.to_string(),
+            table: None,
+            detail: None,
+            predicate: None,
+            estimated_rows: None,
+            actual_rows: None,
+            blocks_read: None,
+            blocks_total: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// One line per operator, children indented below their parent.
+    pub fn to_text(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        self.write_text(0, &mut lines);
+        lines
+    }
+
+    fn write_text(&self, depth: usize, lines: &mut Vec<String>) {
+        let mut line = format!("{}{}", "  ".repeat(depth), self.operator);
+        if let Some(table) = &self.table {
+            line.push_str(&format!(" on {}", table));
+        }
+        if let Some(detail) = &self.detail {
+            line.push_str(&format!(": {}", detail));
+        }
+        if let Some(predicate) = &self.predicate {
+            line.push_str(&format!(" [{}]", predicate));
+        }
+        let mut counts = Vec::new();
+        if let (Some(read), Some(total)) = (self.blocks_read, self.blocks_total) {
+            counts.push(format!("blocks: {} of {}", read, total));
+        }
+        let estimated = self.estimated_rows.map_or("?".to_string(), |rows| format!("{:.0}", rows));
+        counts.push(format!("estimated rows: {}", estimated));
+        if let Some(actual) = self.actual_rows {
+            counts.push(format!("actual rows: {}", actual));
+        }
+        line.push_str(&format!(" ({})", counts.join(", ")));
+        lines.push(line);
+        for child in &self.children {
+            child.write_text(depth + 1, lines);
+        }
+    }
+}
+
+fn literal(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("\"{}\"", s),
+        other => other.to_string(),
+    }
+}
+
+/// The SQL a condition would be written as.
+pub fn condition_text(condition: &Condition) -> String {
+    match condition {
+        Condition::Equal(col, v) => format!("{} = {}", col, literal(v)),
+        Condition::GreaterThan(col, v) => format!("{} > {}", col, literal(v)),
+        Condition::LessThan(col, v) => format!("{} < {}", col, literal(v)),
+        Condition::LessThanOrEqual(col, v) => format!("{} <= {}", col, literal(v)),
+        Condition::GreaterThanOrEqual(col, v) => format!("{} >= {}", col, literal(v)),
+        Condition::NotEqual(col, v) => format!("{} != {}", col, literal(v)),
+        Condition::In(col, values) => {
+            format!("{} IN ({})", col, values.iter().map(literal).collect::<Vec<_>>().join(", "))
+        }
+        Condition::Between(col, low, high) => format!("{} BETWEEN {} AND {}", col, literal(low), literal(high)),
+        Condition::Like(col, pattern) => format!("{} LIKE '{}'", col, pattern),
+        Condition::IsNull(col) => format!("{} IS NULL", col),
+        Condition::IsNotNull(col) => format!("{} IS NOT NULL", col),
+        Condition::InTuple(cols, tuples) => {
+            let tuples: Vec<String> = tuples
+                .iter()
+                .map(|t| format!("({})", t.iter().map(literal).collect::<Vec<_>>().join(", ")))
+                .collect();
+            format!("({}) IN ({})", cols.join(", "), tuples.join(", "))
+        }
+        Condition::Regexp(col, pattern) => format!("{} REGEXP '{}'", col, pattern.as_str()),
+        Condition::And(left, right) => format!("({} AND {})", condition_text(left), condition_text(right)),
+        Condition::Or(left, right) => format!("({} OR {})", condition_text(left), condition_text(right)),
+        Condition::Compare(left, op, right) => format!("{} {} {}", expr_name(left), op.symbol(), expr_name(right)),
+        Condition::Not(inner) => format!("NOT {}", condition_text(inner)),
+        Condition::InSubquery(col, _) => format!("{} IN (subquery)", col),
+        Condition::CompareSubquery(left, op, _) => format!("{} {} (subquery)", expr_name(left), op.symbol()),
+    }
+}
+
+pub fn join_operator_text(operator: JoinOperator) -> &'static str {
+    match operator {
+        JoinOperator::Eq => "=",
+        JoinOperator::Lt => "<",
+        JoinOperator::Le => "<=",
+        JoinOperator::Gt => ">",
+        JoinOperator::Ge => ">=",
+    }
+}
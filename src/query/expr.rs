@@ -0,0 +1,188 @@
+use crate::datetime::SECONDS_PER_DAY;
+use crate::query::mask::MaskFunction;
+use crate::types::{DbError, Value};
+use ordered_float::OrderedFloat;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A scalar expression: a column default, or one output column of a SELECT.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    /// `NOW()` / `CURRENT_TIMESTAMP`: seconds since the epoch at evaluation.
+    CurrentTimestamp,
+    /// `CURRENT_DATE`: days since the epoch at evaluation.
+    CurrentDate,
+    /// The current row's value of a column.
+    Column(String),
+    /// `COALESCE(a, b, ...)` and `IFNULL(a, b)`: the first argument that is not NULL.
+    Coalesce(Vec<Expr>),
+    /// `NULLIF(a, b)`: NULL when `a` equals `b`, otherwise `a`.
+    NullIf(Box<Expr>, Box<Expr>),
+    /// `MASK_EMAIL(a)` / `MASK_LAST4(a)`: `a` with most of its text hidden.
+    Mask(MaskFunction, Box<Expr>),
+    /// `a + b`, `a - b`, `a * b` or `a / b` over numbers; see `arithmetic`.
+    Arithmetic(ArithmeticOp, Box<Expr>, Box<Expr>),
+    /// `expr AS name` in a select list: `expr` under another output name.
+    Alias(Box<Expr>, String),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ArithmeticOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+impl ArithmeticOp {
+    /// Multiplication and division bind tighter than addition and subtraction.
+    pub fn precedence(self) -> u8 {
+        match self {
+            ArithmeticOp::Add | ArithmeticOp::Subtract => 1,
+            ArithmeticOp::Multiply | ArithmeticOp::Divide => 2,
+        }
+    }
+}
+
+impl fmt::Display for ArithmeticOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            ArithmeticOp::Add => "+",
+            ArithmeticOp::Subtract => "-",
+            ArithmeticOp::Multiply => "*",
+            ArithmeticOp::Divide => "/",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl Expr {
+    /// Evaluates an expression that references no columns.
+    pub fn evaluate(&self) -> Result<Value, DbError> {
+        self.evaluate_with(&|col| {
+            Err(DbError::QueryError(format!("Column {} cannot be used in this expression", col)))
+        })
+    }
+
+    /// Evaluates against one row, reading columns through `column`.
+    pub fn evaluate_with(&self, column: &dyn Fn(&str) -> Result<Value, DbError>) -> Result<Value, DbError> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::CurrentTimestamp => Ok(Value::Timestamp(now_seconds()?)),
+            Expr::CurrentDate => Ok(Value::Date(now_seconds()?.div_euclid(SECONDS_PER_DAY) as i32)),
+            Expr::Column(name) => column(name),
+            Expr::Coalesce(args) => {
+                for arg in args {
+                    let value = arg.evaluate_with(column)?;
+                    if !value.is_null() {
+                        return Ok(value);
+                    }
+                }
+                Ok(Value::Null)
+            }
+            Expr::NullIf(value, other) => {
+                let value = value.evaluate_with(column)?;
+                let other = other.evaluate_with(column)?;
+                if value.compare(&other) == Some(Ordering::Equal) {
+                    Ok(Value::Null)
+                } else {
+                    Ok(value)
+                }
+            }
+            Expr::Mask(mask, value) => Ok(mask.apply(&value.evaluate_with(column)?)),
+            Expr::Arithmetic(op, left, right) => {
+                arithmetic(*op, &left.evaluate_with(column)?, &right.evaluate_with(column)?)
+            }
+            Expr::Alias(expr, _) => expr.evaluate_with(column),
+        }
+    }
+
+    /// Columns the expression reads, in order of appearance.
+    pub fn columns(&self) -> Vec<String> {
+        match self {
+            Expr::Column(name) => vec![name.clone()],
+            Expr::Coalesce(args) => args.iter().flat_map(Expr::columns).collect(),
+            Expr::NullIf(value, other) => value.columns().into_iter().chain(other.columns()).collect(),
+            Expr::Mask(_, value) | Expr::Alias(value, _) => value.columns(),
+            Expr::Arithmetic(_, left, right) => left.columns().into_iter().chain(right.columns()).collect(),
+            Expr::Literal(_) | Expr::CurrentTimestamp | Expr::CurrentDate => Vec::new(),
+        }
+    }
+}
+
+/// Promotes two numbers to the wider of their types: Int32 widens to Int64,
+/// and either integer to Float32. `None` unless both are numbers.
+pub fn promote(left: &Value, right: &Value) -> Option<(Value, Value)> {
+    let rank = |value: &Value| match value {
+        Value::Int32(_) => Some(0),
+        Value::Int64(_) => Some(1),
+        Value::Float32(_) => Some(2),
+        _ => None,
+    };
+    let rank = rank(left)?.max(rank(right)?);
+    let widen = |value: &Value| match (value, rank) {
+        (Value::Int32(i), 1) => Value::Int64(*i as i64),
+        (Value::Int32(i), 2) => Value::Float32(OrderedFloat(*i as f32)),
+        (Value::Int64(i), 2) => Value::Float32(OrderedFloat(*i as f32)),
+        (value, _) => value.clone(),
+    };
+    Some((widen(left), widen(right)))
+}
+
+/// `Value::compare`, except that numbers of different types compare after `promote`.
+pub fn compare_values(left: &Value, right: &Value) -> Option<Ordering> {
+    match promote(left, right) {
+        Some((left, right)) => left.compare(&right),
+        None => left.compare(right),
+    }
+}
+
+/// Applies `op` to two numbers promoted to a common type. NULL on either
+/// side gives NULL; integer overflow and division by zero are errors.
+pub fn arithmetic(op: ArithmeticOp, left: &Value, right: &Value) -> Result<Value, DbError> {
+    if left.is_null() || right.is_null() {
+        return Ok(Value::Null);
+    }
+    let Some(operands) = promote(left, right) else {
+        return Err(DbError::QueryError(format!("Cannot apply {} to {} and {}", op, left, right)));
+    };
+    if op == ArithmeticOp::Divide && matches!(operands.1, Value::Int32(0) | Value::Int64(0)) {
+        return Err(DbError::QueryError(format!("Division by zero: {} / {}", left, right)));
+    }
+    let overflow = || DbError::QueryError(format!("Integer overflow: {} {} {}", left, op, right));
+    let integer = |a: i64, b: i64| match op {
+        ArithmeticOp::Add => a.checked_add(b),
+        ArithmeticOp::Subtract => a.checked_sub(b),
+        ArithmeticOp::Multiply => a.checked_mul(b),
+        ArithmeticOp::Divide => a.checked_div(b),
+    };
+    match operands {
+        (Value::Int32(a), Value::Int32(b)) => integer(a as i64, b as i64)
+            .and_then(|i| i32::try_from(i).ok())
+            .map(Value::Int32)
+            .ok_or_else(overflow),
+        (Value::Int64(a), Value::Int64(b)) => integer(a, b).map(Value::Int64).ok_or_else(overflow),
+        (Value::Float32(a), Value::Float32(b)) => {
+            if op == ArithmeticOp::Divide && b.0 == 0.0 {
+                return Err(DbError::QueryError(format!("Division by zero: {} / {}", left, right)));
+            }
+            Ok(Value::Float32(OrderedFloat(match op {
+                ArithmeticOp::Add => a.0 + b.0,
+                ArithmeticOp::Subtract => a.0 - b.0,
+                ArithmeticOp::Multiply => a.0 * b.0,
+                ArithmeticOp::Divide => a.0 / b.0,
+            })))
+        }
+        _ => unreachable!("promote returns numbers of one type"),
+    }
+}
+
+fn now_seconds() -> Result<i64, DbError> {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .map_err(|e| DbError::InvalidData(format!("System clock is before the epoch: {}", e)))
+}
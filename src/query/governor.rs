@@ -0,0 +1,121 @@
+use crate::storage::settings::Priority;
+use crate::types::DbError;
+use std::cell::Cell;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+thread_local! {
+    /// Priority of the read running on this thread, set while its permit is held.
+    static CURRENT: Cell<Priority> = const { Cell::new(Priority::Interactive) };
+}
+
+/// Priority of the read the calling thread is running; interactive outside one.
+pub fn current_priority() -> Priority {
+    CURRENT.with(Cell::get)
+}
+
+/// Admission control for the reads sharing one database: at most
+/// `max_concurrent_queries` run at once, and together they may scan at most
+/// `query_memory_bytes`. A read over either cap queues until running ones
+/// finish, and fails once it has waited `queue_timeout` seconds. Queued
+/// interactive reads go before batch ones.
+#[derive(Debug, Default)]
+pub struct Governor {
+    admitted: Mutex<Admitted>,
+    released: Condvar,
+}
+
+#[derive(Debug, Default)]
+struct Admitted {
+    queries: usize,
+    bytes: usize,
+    /// Interactive reads queued now; batch reads wait until there are none.
+    interactive_queued: usize,
+}
+
+/// The caps a query is admitted under, taken from the settings when it starts.
+#[derive(Clone, Copy, Debug)]
+pub struct Limits {
+    pub max_queries: usize,
+    pub max_bytes: usize,
+    pub timeout: Duration,
+}
+
+/// A running query's share of the caps, given back when dropped.
+#[derive(Debug)]
+pub struct Permit {
+    governor: Arc<Governor>,
+    bytes: usize,
+    /// The thread's priority before this permit, restored on drop.
+    outer: Priority,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        CURRENT.with(|current| current.set(self.outer));
+        let mut admitted = self.governor.admitted.lock().unwrap();
+        admitted.queries -= 1;
+        admitted.bytes -= self.bytes;
+        self.governor.released.notify_all();
+    }
+}
+
+impl Governor {
+    /// Admits a query estimated to scan `bytes`, waiting for room if needed.
+    /// A query that would not fit even alone is rejected straight away. Batch
+    /// reads leave one of several slots to interactive ones.
+    pub fn admit(self: &Arc<Self>, bytes: usize, limits: Limits, priority: Priority) -> Result<Permit, DbError> {
+        if bytes > limits.max_bytes {
+            return Err(DbError::QueryError(format!(
+                "Query would scan about {} bytes, more than query_memory_bytes ({})",
+                bytes, limits.max_bytes
+            )));
+        }
+        let interactive = priority == Priority::Interactive;
+        let max_queries = if interactive { limits.max_queries } else { limits.max_queries.saturating_sub(1).max(1) };
+        let mut admitted = self.admitted.lock().unwrap();
+        if interactive {
+            admitted.interactive_queued += 1;
+        }
+        let (mut admitted, wait) = self
+            .released
+            .wait_timeout_while(admitted, limits.timeout, |a| {
+                a.queries >= max_queries
+                    || a.bytes + bytes > limits.max_bytes
+                    || (!interactive && a.interactive_queued > 0)
+            })
+            .unwrap();
+        if interactive {
+            admitted.interactive_queued -= 1;
+            // Batch reads held back by this one may go now.
+            self.released.notify_all();
+        }
+        if wait.timed_out() {
+            return Err(DbError::QueryError(format!(
+                "Query not admitted within {} seconds: {} queries are scanning {} bytes",
+                limits.timeout.as_secs(),
+                admitted.queries,
+                admitted.bytes
+            )));
+        }
+        admitted.queries += 1;
+        admitted.bytes += bytes;
+        let outer = CURRENT.with(|current| current.replace(priority));
+        Ok(Permit {
+            governor: Arc::clone(self),
+            bytes,
+            outer,
+        })
+    }
+
+    /// Queries running now and the bytes they were admitted to scan.
+    pub fn running(&self) -> (usize, usize) {
+        let admitted = self.admitted.lock().unwrap();
+        (admitted.queries, admitted.bytes)
+    }
+
+    /// Interactive reads waiting for admission.
+    pub fn interactive_queued(&self) -> usize {
+        self.admitted.lock().unwrap().interactive_queued
+    }
+}
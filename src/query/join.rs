@@ -0,0 +1,96 @@
+use crate::query::JoinOperator;
+use crate::schema::Column;
+use crate::types::{Collation, DataType, DbError, Value};
+use std::collections::HashMap;
+
+/// Brings both sides' join keys to one comparable form, so equal keys are
+/// also equal as hash keys: integers of different widths meet as Int64 and
+/// strings compare case-insensitively when either column is NOCASE. Keys of
+/// types that never compare are a `TypeMismatch`.
+pub fn join_keys(
+    left: Vec<Value>,
+    left_column: &Column,
+    right: Vec<Value>,
+    right_column: &Column,
+) -> Result<(Vec<Value>, Vec<Value>), DbError> {
+    let data_type = match (&left_column.data_type, &right_column.data_type) {
+        (l, r) if l == r => l.clone(),
+        (DataType::Int32, DataType::Int64) | (DataType::Int64, DataType::Int32) => DataType::Int64,
+        _ => return Err(DbError::TypeMismatch),
+    };
+    let collation = if left_column.collation == Collation::NoCase || right_column.collation == Collation::NoCase {
+        Collation::NoCase
+    } else {
+        Collation::Binary
+    };
+    let normalize = |keys: Vec<Value>| -> Result<Vec<Value>, DbError> {
+        keys.into_iter()
+            .map(|key| match key {
+                Value::Null => Ok(key),
+                key => key.coerce(&data_type).map(|key| collation.key(&key)),
+            })
+            .collect()
+    };
+    Ok((normalize(left)?, normalize(right)?))
+}
+
+/// Row pairs `(left, right)` whose keys are equal, in left then right row
+/// order. Every left row is paired with every matching right row, so keys
+/// repeated on both sides give their full cross product. The hash table is
+/// built on the smaller side and compares whole keys; NULL never matches.
+pub fn hash_join(left: &[Value], right: &[Value]) -> Vec<(u32, u32)> {
+    let build_left = left.len() <= right.len();
+    let (build, probe) = if build_left { (left, right) } else { (right, left) };
+    let mut table: HashMap<&Value, Vec<u32>> = HashMap::new();
+    for (row, key) in build.iter().enumerate() {
+        if !key.is_null() {
+            table.entry(key).or_default().push(row as u32);
+        }
+    }
+
+    let mut pairs = Vec::new();
+    for (row, key) in probe.iter().enumerate() {
+        if let Some(matches) = table.get(key) {
+            let row = row as u32;
+            pairs.extend(matches.iter().map(|&m| if build_left { (m, row) } else { (row, m) }));
+        }
+    }
+    // Probing the right side yields right-major order.
+    if build_left {
+        pairs.sort_unstable();
+    }
+    pairs
+}
+
+/// Row pairs satisfying `left op right`. The right side is sorted once, so
+/// each left key matches one contiguous run of it.
+pub fn sort_merge_join(left: &[Value], right: &[Value], operator: JoinOperator) -> Result<Vec<(u32, u32)>, DbError> {
+    let mut sorted: Vec<u32> = (0..right.len() as u32).filter(|&j| !right[j as usize].is_null()).collect();
+    sorted.sort_by(|&a, &b| right[a as usize].cmp(&right[b as usize]));
+
+    let mut pairs = Vec::new();
+    for (row, key) in left.iter().enumerate() {
+        if key.is_null() {
+            continue;
+        }
+        if let Some(&first) = sorted.first()
+            && key.compare(&right[first as usize]).is_none()
+        {
+            return Err(DbError::TypeMismatch);
+        }
+        // Sorted positions of the first right key above (or not below) `key`.
+        let above = sorted.partition_point(|&j| right[j as usize] <= *key);
+        let not_below = sorted.partition_point(|&j| right[j as usize] < *key);
+        let run = match operator {
+            JoinOperator::Eq => &sorted[not_below..above],
+            JoinOperator::Lt => &sorted[above..],
+            JoinOperator::Le => &sorted[not_below..],
+            JoinOperator::Gt => &sorted[..not_below],
+            JoinOperator::Ge => &sorted[..above],
+        };
+        let mut matches = run.to_vec();
+        matches.sort_unstable();
+        pairs.extend(matches.into_iter().map(|m| (row as u32, m)));
+    }
+    Ok(pairs)
+}
@@ -0,0 +1,79 @@
+use crate::storage::trash::now_seconds;
+use crate::types::DbError;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Statements kept before the oldest is dropped.
+pub const QUERY_LOG_CAPACITY: usize = 1000;
+
+/// Longer statement text is cut to this many characters.
+const MAX_TEXT_CHARS: usize = 4096;
+
+/// One statement that ran, as `system.query_log` shows it.
+#[derive(Clone, Debug)]
+pub struct LoggedQuery {
+    pub id: u64,
+    /// Seconds since the epoch when the statement started.
+    pub started_at: u64,
+    pub text: String,
+    pub duration: Duration,
+    /// Rows the statement returned.
+    pub rows: usize,
+    /// The error the statement failed with; None if it succeeded.
+    pub error: Option<String>,
+}
+
+/// The most recent statements of a database, in memory only. Shared with
+/// snapshots like the governor, so read transactions log to it too.
+#[derive(Debug)]
+pub struct QueryLog {
+    capacity: usize,
+    entries: Mutex<(u64, VecDeque<LoggedQuery>)>,
+}
+
+impl Default for QueryLog {
+    fn default() -> Self {
+        QueryLog::new(QUERY_LOG_CAPACITY)
+    }
+}
+
+impl QueryLog {
+    pub fn new(capacity: usize) -> Self {
+        QueryLog {
+            capacity,
+            entries: Mutex::new((0, VecDeque::new())),
+        }
+    }
+
+    /// Adds a statement that took `duration` and ended in `outcome`, dropping
+    /// the oldest one once the log is full.
+    pub fn record(&self, text: &str, duration: Duration, outcome: Result<usize, &DbError>) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut guard = self.entries.lock().unwrap();
+        let (next_id, entries) = &mut *guard;
+        *next_id += 1;
+        if entries.len() == self.capacity {
+            entries.pop_front();
+        }
+        let (rows, error) = match outcome {
+            Ok(rows) => (rows, None),
+            Err(e) => (0, Some(e.to_string())),
+        };
+        entries.push_back(LoggedQuery {
+            id: *next_id,
+            started_at: now_seconds().saturating_sub(duration.as_secs()),
+            text: text.chars().take(MAX_TEXT_CHARS).collect(),
+            duration,
+            rows,
+            error,
+        });
+    }
+
+    /// Logged statements, oldest first.
+    pub fn entries(&self) -> Vec<LoggedQuery> {
+        self.entries.lock().unwrap().1.iter().cloned().collect()
+    }
+}
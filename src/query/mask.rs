@@ -0,0 +1,163 @@
+use crate::query::expr::Expr;
+use crate::query::{Aggregation, GroupKey, Query};
+use crate::schema::{Schema, Table};
+use crate::types::{DbError, Value};
+use serde::{Deserialize, Serialize};
+
+/// Hides most of a sensitive string while keeping enough to recognize it.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum MaskFunction {
+    /// `MASK_EMAIL`: `alice@example.com` becomes `a***@example.com`.
+    Email,
+    /// `MASK_LAST4`: every character but the last four becomes `*`.
+    Last4,
+}
+
+impl MaskFunction {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "MASK_EMAIL" => Some(MaskFunction::Email),
+            "MASK_LAST4" => Some(MaskFunction::Last4),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MaskFunction::Email => "MASK_EMAIL",
+            MaskFunction::Last4 => "MASK_LAST4",
+        }
+    }
+
+    /// The masked text of `value`; NULL stays NULL.
+    pub fn apply(self, value: &Value) -> Value {
+        let text = match value {
+            Value::Null => return Value::Null,
+            Value::String(s) => s.clone(),
+            other => other.to_string(),
+        };
+        Value::String(match self {
+            MaskFunction::Email => match text.split_once('@') {
+                Some((local, domain)) => format!("{}***@{}", local.chars().next().unwrap_or('*'), domain),
+                None => "***".to_string(),
+            },
+            MaskFunction::Last4 => {
+                let len = text.chars().count();
+                // Short values would be shown whole, so they are hidden whole.
+                let shown = if len > 4 { 4 } else { 0 };
+                text.chars().enumerate().map(|(i, c)| if i < len - shown { '*' } else { c }).collect()
+            }
+        })
+    }
+}
+
+fn column_mask(table: Option<&Table>, name: &str) -> Option<MaskFunction> {
+    table.and_then(|t| t.get_column(name)).and_then(|c| c.mask)
+}
+
+fn table_masks(table: Option<&Table>) -> Vec<Option<MaskFunction>> {
+    table.map(|t| t.columns.iter().map(|c| c.mask).collect()).unwrap_or_default()
+}
+
+/// Masked columns an expression reads; its output is masked like the first.
+fn expr_mask(expr: &Expr, table: Option<&Table>) -> Option<MaskFunction> {
+    expr.columns().iter().find_map(|col| column_mask(table, col))
+}
+
+fn masked_input(table: Option<&Table>, name: &str) -> Result<(), DbError> {
+    match column_mask(table, name) {
+        Some(_) => Err(DbError::QueryError(format!("Column {} is masked and cannot be aggregated", name))),
+        None => Ok(()),
+    }
+}
+
+/// The masking policy of each output column of `query` for a restricted
+/// session, or `None` when no output column needs masking. Aggregates that
+/// would reveal a masked column's values, such as SUM, are rejected.
+pub fn output_masks(query: &Query, schema: &Schema) -> Result<Option<Vec<Option<MaskFunction>>>, DbError> {
+    let masks = match query {
+        Query::Select { table, columns, .. } => {
+            let table = schema.get_table(table);
+            if columns.is_empty() {
+                table_masks(table)
+            } else {
+                columns.iter().map(|expr| expr_mask(expr, table)).collect()
+            }
+        }
+        Query::VerifyTable { table, .. } => table_masks(schema.get_table(table)),
+        // Copies of the files would bypass every policy.
+        Query::Backup { .. } | Query::ExportTable { .. } => {
+            return Err(DbError::TransactionError("A restricted session cannot copy table files".to_string()));
+        }
+        Query::SelectAggregate { table, aggregations, group_by, .. } => {
+            let table = schema.get_table(table);
+            let mut masks = Vec::new();
+            for key in group_by {
+                match key {
+                    GroupKey::Column(name) => masks.push(column_mask(table, name)),
+                    GroupKey::TimeBucket { column, .. } => {
+                        masked_input(table, column)?;
+                        masks.push(None);
+                    }
+                }
+            }
+            for aggregation in aggregations {
+                masks.push(match aggregation {
+                    Aggregation::Count => None,
+                    // These return one of the column's own values.
+                    Aggregation::Min(col)
+                    | Aggregation::Max(col)
+                    | Aggregation::First(col, _)
+                    | Aggregation::Last(col, _) => column_mask(table, col),
+                    Aggregation::Sum(col)
+                    | Aggregation::Avg(col)
+                    | Aggregation::CountColumn(col)
+                    | Aggregation::CountDistinct(col)
+                    | Aggregation::ApproxCountDistinct(col)
+                    | Aggregation::ApproxQuantile(col, _)
+                    | Aggregation::Percentile(col, _)
+                    | Aggregation::Histogram(col, _) => {
+                        masked_input(table, col)?;
+                        None
+                    }
+                });
+            }
+            masks
+        }
+        Query::Join { left_table, right_table, columns, .. } => {
+            let (left, right) = (schema.get_table(left_table), schema.get_table(right_table));
+            let mask = |name: &str| match name.split_once('.') {
+                Some((table, col)) if table == left_table => column_mask(left, col),
+                Some((table, col)) if table == right_table => column_mask(right, col),
+                Some(_) => None,
+                None => column_mask(left, name).or_else(|| column_mask(right, name)),
+            };
+            columns.iter().map(|expr| expr.columns().iter().find_map(|name| mask(name))).collect()
+        }
+        Query::Insert { table, returning, .. }
+        | Query::InsertRows { table, returning, .. }
+        | Query::Delete { table, returning, .. } => {
+            let table = schema.get_table(table);
+            returning
+                .iter()
+                .flat_map(|name| match name.as_str() {
+                    "*" => table_masks(table),
+                    _ => vec![column_mask(table, name)],
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    Ok(masks.iter().any(Option::is_some).then_some(masks))
+}
+
+pub fn apply_masks(mut rows: Vec<Vec<Value>>, masks: &[Option<MaskFunction>]) -> Vec<Vec<Value>> {
+    for row in &mut rows {
+        for (value, mask) in row.iter_mut().zip(masks) {
+            if let Some(mask) = mask {
+                *value = mask.apply(value);
+            }
+        }
+    }
+    rows
+}
@@ -1,9 +1,31 @@
-use crate::types::{DataType, Value};
+use crate::schema::{Column, Validation};
+use crate::query::explain::ExplainFormat;
+use crate::query::expr::{compare_values, Expr};
+use crate::query::pattern::Pattern;
+use crate::types::{DbError, Value};
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 
+pub mod batch;
+pub mod binder;
+pub mod cache;
 pub mod evaluator;
+pub mod explain;
+pub mod expr;
+pub mod governor;
+pub mod join;
+pub mod log;
+pub mod mask;
 pub mod parser;
+pub mod pattern;
 pub mod planner;
+pub mod prepared;
+pub mod pushdown;
+pub mod result;
+pub mod sketch;
+pub mod sort;
+pub mod stream;
+pub mod system;
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Condition {
@@ -12,54 +34,357 @@ pub enum Condition {
     LessThan(String, Value),
     LessThanOrEqual(String, Value),
     GreaterThanOrEqual(String, Value),
+    /// `col != v` or `col <> v`.
+    NotEqual(String, Value),
+    /// `col IN (v1, v2, ...)`.
+    In(String, Vec<Value>),
+    /// `col BETWEEN low AND high`, both ends included.
+    Between(String, Value, Value),
+    /// `col LIKE 'pattern'`: `%` matches any run of characters, `_` one.
+    Like(String, String),
+    IsNull(String),
+    IsNotNull(String),
+    /// `(a, b) IN ((1, "x"), (2, "y"))`: the columns equal one of the tuples.
+    InTuple(Vec<String>, Vec<Vec<Value>>),
+    /// `col REGEXP 'pattern'`: string values the pattern matches anywhere in.
+    Regexp(String, Pattern),
     And(Box<Condition>, Box<Condition>),
     Or(Box<Condition>, Box<Condition>),
+    /// `qty * price > 100`: a comparison that is not a column against a
+    /// value. Evaluated row by row; it never prunes blocks.
+    Compare(Expr, Comparison, Expr),
+    /// `NOT` of a LIKE or REGEXP. Parsing pushes every other NOT down into
+    /// the predicates, which keeps them prunable. False where the column is NULL.
+    Not(Box<Condition>),
+    /// `col IN (SELECT x FROM ...)`. The engine runs the subquery first and
+    /// replaces this with an `In` over its values.
+    InSubquery(String, Box<Query>),
+    /// `expr > (SELECT MAX(x) FROM ...)`: a comparison with a scalar
+    /// subquery, the subquery always on the right. Replaced like `InSubquery`.
+    CompareSubquery(Expr, Comparison, Box<Query>),
+}
+
+impl Condition {
+    /// `NOT self` with the negation pushed down to the predicates. Like
+    /// SQL's NOT of an unknown, each rewrite stays false on rows where a
+    /// compared value is NULL.
+    pub fn negated(self) -> Condition {
+        let and = |l, r| Condition::And(Box::new(l), Box::new(r));
+        let or = |l, r| Condition::Or(Box::new(l), Box::new(r));
+        match self {
+            Condition::Equal(col, val) => Condition::NotEqual(col, val),
+            Condition::NotEqual(col, val) => Condition::Equal(col, val),
+            Condition::GreaterThan(col, val) => Condition::LessThanOrEqual(col, val),
+            Condition::LessThan(col, val) => Condition::GreaterThanOrEqual(col, val),
+            Condition::LessThanOrEqual(col, val) => Condition::GreaterThan(col, val),
+            Condition::GreaterThanOrEqual(col, val) => Condition::LessThan(col, val),
+            Condition::IsNull(col) => Condition::IsNotNull(col),
+            Condition::IsNotNull(col) => Condition::IsNull(col),
+            Condition::Compare(left, op, right) => Condition::Compare(left, op.negated(), right),
+            Condition::CompareSubquery(left, op, query) => Condition::CompareSubquery(left, op.negated(), query),
+            Condition::Between(col, low, high) => {
+                or(Condition::LessThan(col.clone(), low), Condition::GreaterThan(col, high))
+            }
+            Condition::In(col, values) if !values.is_empty() => values
+                .into_iter()
+                .map(|val| Condition::NotEqual(col.clone(), val))
+                .reduce(and)
+                .unwrap(),
+            Condition::InTuple(cols, tuples) if !tuples.is_empty() => tuples
+                .into_iter()
+                .map(|tuple| {
+                    cols.iter()
+                        .zip(tuple)
+                        .map(|(col, val)| Condition::NotEqual(col.clone(), val))
+                        .reduce(or)
+                        .unwrap()
+                })
+                .reduce(and)
+                .unwrap(),
+            Condition::And(left, right) => or(left.negated(), right.negated()),
+            Condition::Or(left, right) => and(left.negated(), right.negated()),
+            Condition::Not(inner) => *inner,
+            other => Condition::Not(Box::new(other)),
+        }
+    }
+}
+
+/// Conditions are only evaluated once the engine has replaced their subqueries.
+pub(crate) fn unmaterialized() -> DbError {
+    DbError::QueryError("Subquery was not run before its condition".to_string())
+}
+
+/// The operator of a `Condition::Compare`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    pub fn from_symbol(symbol: &str) -> Option<Comparison> {
+        Some(match symbol {
+            "=" => Comparison::Eq,
+            "!=" | "<>" => Comparison::Ne,
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            ">=" => Comparison::Ge,
+            _ => return None,
+        })
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Comparison::Eq => "=",
+            Comparison::Ne => "!=",
+            Comparison::Lt => "<",
+            Comparison::Le => "<=",
+            Comparison::Gt => ">",
+            Comparison::Ge => ">=",
+        }
+    }
+
+    pub fn negated(self) -> Comparison {
+        match self {
+            Comparison::Eq => Comparison::Ne,
+            Comparison::Ne => Comparison::Eq,
+            Comparison::Lt => Comparison::Ge,
+            Comparison::Le => Comparison::Gt,
+            Comparison::Gt => Comparison::Le,
+            Comparison::Ge => Comparison::Lt,
+        }
+    }
+
+    /// The operator with its operands swapped: `a < b` is `b > a`.
+    pub fn flipped(self) -> Comparison {
+        match self {
+            Comparison::Lt => Comparison::Gt,
+            Comparison::Le => Comparison::Ge,
+            Comparison::Gt => Comparison::Lt,
+            Comparison::Ge => Comparison::Le,
+            other => other,
+        }
+    }
+
+    /// `column op value` as the plain predicate zone maps and indexes prune with.
+    pub fn with_value(self, column: String, value: Value) -> Condition {
+        match self {
+            Comparison::Eq => Condition::Equal(column, value),
+            Comparison::Ne => Condition::NotEqual(column, value),
+            Comparison::Lt => Condition::LessThan(column, value),
+            Comparison::Le => Condition::LessThanOrEqual(column, value),
+            Comparison::Gt => Condition::GreaterThan(column, value),
+            Comparison::Ge => Condition::GreaterThanOrEqual(column, value),
+        }
+    }
+
+    /// False when either side is NULL or the two don't compare.
+    pub fn holds(self, left: &Value, right: &Value) -> bool {
+        compare_values(left, right).is_some_and(|ordering| match self {
+            Comparison::Eq => ordering == Ordering::Equal,
+            Comparison::Ne => ordering != Ordering::Equal,
+            Comparison::Lt => ordering == Ordering::Less,
+            Comparison::Le => ordering != Ordering::Greater,
+            Comparison::Gt => ordering == Ordering::Greater,
+            Comparison::Ge => ordering != Ordering::Less,
+        })
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Aggregation {
     Count,
+    /// `COUNT(col)`: number of non-NULL values of `col`.
+    CountColumn(String),
+    /// `COUNT(DISTINCT col)`: exact number of distinct non-NULL values.
+    CountDistinct(String),
     Sum(String),
     Avg(String),
     Min(String),
     Max(String),
+    ApproxCountDistinct(String),
+    ApproxQuantile(String, f64),
+    Percentile(String, f64),
+    Histogram(String, usize),
+    /// `FIRST(col ORDER BY ord)`: value of `col` on the row with the smallest `ord`.
+    First(String, String),
+    /// `LAST(col ORDER BY ord)`: value of `col` on the row with the largest `ord`.
+    Last(String, String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum GroupKey {
+    Column(String),
+    /// `TIME_BUCKET(interval, col)` / `DATE_TRUNC(unit, col)` over epoch-second values.
+    TimeBucket { column: String, seconds: i64 },
+}
+
+impl GroupKey {
+    pub fn column(&self) -> &str {
+        match self {
+            GroupKey::Column(col) | GroupKey::TimeBucket { column: col, .. } => col,
+        }
+    }
+
+    pub fn apply(&self, value: &Value) -> Result<Value, DbError> {
+        match (self, value) {
+            (GroupKey::Column(_), v) => Ok(v.clone()),
+            (GroupKey::TimeBucket { seconds, .. }, Value::Int32(ts)) => {
+                let ts = *ts as i64;
+                Ok(Value::Int32((ts - ts.rem_euclid(*seconds)) as i32))
+            }
+            (GroupKey::TimeBucket { seconds, .. }, Value::Int64(ts)) => Ok(Value::Int64(ts - ts.rem_euclid(*seconds))),
+            (GroupKey::TimeBucket { seconds, .. }, Value::Timestamp(ts)) => {
+                Ok(Value::Timestamp(ts - ts.rem_euclid(*seconds)))
+            }
+            (GroupKey::TimeBucket { .. }, Value::Null) => Ok(Value::Null),
+            (GroupKey::TimeBucket { column, .. }, _) => Err(DbError::QueryError(format!(
+                "Time bucketing requires an integer timestamp column, got {}",
+                column
+            ))),
+        }
+    }
+}
+
+/// How the ON clause compares the left key with the right key.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum JoinOperator {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Optimizer hints from a `SELECT /*+ ... */` comment, overriding the
+/// planner when statistics mislead it.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Hint {
+    /// `NO_INDEX(t)`: prune `t`'s blocks with zone maps only, never its indexes.
+    NoIndex(String),
+    /// `HASH_JOIN`: build a hash table on the smaller side; equality joins only.
+    HashJoin,
+    /// `MERGE_JOIN`: sort the right side and merge, for any join operator.
+    MergeJoin,
+}
+
+/// False if `hints` rule out using `table`'s indexes.
+pub fn uses_indexes(hints: &[Hint], table: &str) -> bool {
+    !hints.iter().any(|h| matches!(h, Hint::NoIndex(t) if t == table))
+}
+
+/// File format of `COPY`.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum FileFormat {
+    /// Comma-separated values with a header line.
+    Csv,
+    /// One JSON object per line, keyed by column name; export only.
+    Json,
+}
+
+/// The change `ALTER TABLE` makes to a table's columns.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AlterAction {
+    /// Existing rows get the column's default, or NULL without one.
+    AddColumn(Column),
+    DropColumn(String),
+    RenameColumn { from: String, to: String },
+    SetValidation(Validation),
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Query {
     Select {
         table: String,
-        columns: Vec<String>,
+        /// Output expressions; empty for `SELECT *`.
+        columns: Vec<Expr>,
+        /// `SELECT DISTINCT`: only the first of equal output rows is kept.
+        distinct: bool,
         condition: Option<Condition>,
+        sample: Option<f64>,
+        order_by: Vec<(String, SortOrder)>,
+        limit: Option<usize>,
+        offset: Option<usize>,
+        hints: Vec<Hint>,
     },
     SelectAggregate {
         table: String,
         aggregations: Vec<Aggregation>,
         condition: Option<Condition>,
+        sample: Option<f64>,
+        group_by: Vec<GroupKey>,
+        hints: Vec<Hint>,
     },
     Join {
         left_table: String,
         right_table: String,
         left_column: String,
         right_column: String,
-        columns: Vec<String>,
+        operator: JoinOperator,
+        /// Output expressions over `table.column` references.
+        columns: Vec<Expr>,
         condition: Option<Condition>,
+        hints: Vec<Hint>,
     },
     Insert {
         table: String,
         values: Vec<Value>,
+        /// `RETURNING` columns; `*` stands for every column, empty for none.
+        returning: Vec<String>,
+    },
+    /// `INSERT INTO t (a, b) VALUES (..), (..)`: several rows, optionally
+    /// over a subset of the columns. Binding fills in the omitted columns
+    /// and leaves `columns` empty.
+    InsertRows {
+        table: String,
+        columns: Vec<String>,
+        rows: Vec<Vec<Value>>,
+        returning: Vec<String>,
     },
     CreateTable {
         table: String,
-        columns: Vec<(String, DataType)>,
+        columns: Vec<Column>,
+    },
+    /// `CREATE EXTERNAL TABLE t (...) LOCATION 'dir/*.csv'`: a read-only table
+    /// whose rows are read from CSV or `.parquet` files on every scan.
+    CreateExternalTable {
+        table: String,
+        columns: Vec<Column>,
+        location: String,
+    },
+    Update {
+        table: String,
+        assignments: Vec<(String, Value)>,
+        condition: Option<Condition>,
     },
     Delete {
         table: String,
         condition: Option<Condition>,
+        returning: Vec<String>,
     },
     DropTable {
         table: String,
     },
+    /// `UNDROP TABLE t`: restores a dropped table from the trash.
+    UndropTable {
+        table: String,
+    },
+    /// `ALTER TABLE t ADD|DROP|RENAME COLUMN ...`
+    AlterTable {
+        table: String,
+        action: AlterAction,
+    },
     MakeIndex {
         table: String,
         column: String,
@@ -68,11 +393,182 @@ pub enum Query {
         table: String,
         column: String,
     },
+    ShowTableSize {
+        table: String,
+    },
+    ShowDatabaseSize,
+    /// `SHOW STORAGE REPORT [table]`: per column and codec, raw against
+    /// compressed bytes and block sizes.
+    ShowStorageReport {
+        table: Option<String>,
+    },
+    ShowTables,
+    /// `SHOW INDEXES [ON table]`: indexed columns with their key and entry
+    /// counts and file sizes.
+    ShowIndexes {
+        table: Option<String>,
+    },
+    Cleanup {
+        dry_run: bool,
+    },
+    Backup {
+        path: String,
+    },
+    ExportTable {
+        table: String,
+        path: String,
+    },
+    ImportTable {
+        path: String,
+    },
+    Analyze {
+        table: String,
+    },
+    /// `COMPACT table`: merges small adjacent blocks and reclaims dead file space.
+    Compact {
+        table: String,
+    },
+    /// `SALVAGE TABLE table INTO target`: copies the rows of every readable
+    /// block of `table` into a new table.
+    SalvageTable {
+        table: String,
+        target: String,
+    },
+    /// `PRELOAD table [(col, ...)]`: loads the columns' blocks into the buffer cache.
+    Preload {
+        table: String,
+        /// Every column when empty.
+        columns: Vec<String>,
+    },
+    /// `COPY t FROM 'path' [FORMAT csv]`: appends the file's rows to `t`.
+    CopyFrom {
+        table: String,
+        path: String,
+        format: FileFormat,
+    },
+    /// `COPY (SELECT ...) TO 'path' [FORMAT csv|json]`: writes the query's rows to a file.
+    CopyTo {
+        query: Box<Query>,
+        path: String,
+        format: FileFormat,
+    },
+    /// `EXPLAIN [ANALYZE] [FORMAT TEXT|JSON] query`: the plan `query` runs as;
+    /// ANALYZE also runs it to count the rows each operator produced.
+    Explain {
+        query: Box<Query>,
+        analyze: bool,
+        format: ExplainFormat,
+    },
+    /// `SET name = value`: overrides a setting for the session.
+    Set {
+        name: String,
+        value: String,
+    },
+    /// `SHOW SETTINGS`: every setting's effective value and where it came from.
+    ShowSettings,
+    /// `VERIFY TABLE t CHECK (condition) [LIMIT n]`: rows the condition does not hold for.
+    VerifyTable {
+        table: String,
+        condition: Condition,
+        limit: Option<usize>,
+    },
+    /// `CREATE JOB name EVERY '1 hour' DO statement`: runs the statement on a
+    /// schedule while the database is served.
+    CreateJob {
+        name: String,
+        /// Seconds between runs.
+        every: u64,
+        statement: String,
+    },
+    DropJob {
+        name: String,
+    },
+    /// `CREATE SNAPSHOT name`: records the current blocks of every table.
+    CreateSnapshot {
+        name: String,
+    },
+    DropSnapshot {
+        name: String,
+    },
+    /// `SELECT ... AS OF SNAPSHOT name`: runs `query` against the tables as
+    /// the snapshot recorded them.
+    AsOfSnapshot {
+        name: String,
+        query: Box<Query>,
+    },
+    /// `FLUSH`: writes every table's buffered rows to disk.
+    Flush,
     StartTransaction,
     Commit,
     Rollback,
 }
 
+impl Query {
+    /// True for statements that change the database's files.
+    pub fn is_write(&self) -> bool {
+        matches!(
+            self,
+            Query::Insert { .. }
+                | Query::InsertRows { .. }
+                | Query::CreateTable { .. }
+                | Query::CreateExternalTable { .. }
+                | Query::Update { .. }
+                | Query::Delete { .. }
+                | Query::DropTable { .. }
+                | Query::UndropTable { .. }
+                | Query::AlterTable { .. }
+                | Query::MakeIndex { .. }
+                | Query::DropIndex { .. }
+                | Query::Cleanup { dry_run: false }
+                | Query::ImportTable { .. }
+                | Query::CopyFrom { .. }
+                | Query::Analyze { .. }
+                | Query::Compact { .. }
+                | Query::SalvageTable { .. }
+                | Query::CreateSnapshot { .. }
+                | Query::DropSnapshot { .. }
+                | Query::CreateJob { .. }
+                | Query::DropJob { .. }
+                | Query::Flush
+        )
+    }
+
+    /// Tables whose files a write may change; database files such as
+    /// `jobs.json` aside. `None` when the statement alone does not tell,
+    /// as for IMPORT TABLE.
+    pub fn written_tables(&self) -> Option<Vec<String>> {
+        match self {
+            Query::Insert { table, .. }
+            | Query::InsertRows { table, .. }
+            | Query::CreateTable { table, .. }
+            | Query::CreateExternalTable { table, .. }
+            | Query::Update { table, .. }
+            | Query::Delete { table, .. }
+            | Query::DropTable { table }
+            | Query::UndropTable { table }
+            | Query::AlterTable { table, .. }
+            | Query::MakeIndex { table, .. }
+            | Query::DropIndex { table, .. }
+            | Query::CopyFrom { table, .. }
+            | Query::Analyze { table }
+            | Query::Compact { table } => Some(vec![table.clone()]),
+            Query::SalvageTable { table, target } => Some(vec![table.clone(), target.clone()]),
+            Query::ImportTable { .. } => None,
+            _ => Some(Vec::new()),
+        }
+    }
+
+    /// Directories at the top of the data directory that a write may change
+    /// besides the tables' own. Their files are only added or removed.
+    pub fn written_dirs(&self) -> &'static [&'static str] {
+        match self {
+            Query::DropTable { .. } | Query::UndropTable { .. } => &["trash"],
+            Query::CreateSnapshot { .. } | Query::DropSnapshot { .. } => &["snapshots"],
+            _ => &[],
+        }
+    }
+}
+
 pub fn collect_condition_columns(condition: &Condition) -> std::collections::HashSet<String> {
     let mut columns = std::collections::HashSet::new();
     match condition {
@@ -80,13 +576,25 @@ pub fn collect_condition_columns(condition: &Condition) -> std::collections::Has
         Condition::GreaterThan(col, _) | 
         Condition::LessThan(col, _) | 
         Condition::LessThanOrEqual(col, _) | 
-        Condition::GreaterThanOrEqual(col, _) => {
+        Condition::GreaterThanOrEqual(col, _) |
+        Condition::NotEqual(col, _) |
+        Condition::In(col, _) |
+        Condition::Between(col, ..) |
+        Condition::Like(col, _) |
+        Condition::IsNull(col) |
+        Condition::IsNotNull(col) |
+        Condition::Regexp(col, _) |
+        Condition::InSubquery(col, _) => {
             columns.insert(col.clone());
         }
+        Condition::InTuple(cols, _) => columns.extend(cols.iter().cloned()),
+        Condition::Compare(left, _, right) => columns.extend(left.columns().into_iter().chain(right.columns())),
+        Condition::CompareSubquery(left, ..) => columns.extend(left.columns()),
         Condition::And(left, right) | Condition::Or(left, right) => {
             columns.extend(collect_condition_columns(left));
             columns.extend(collect_condition_columns(right));
         }
+        Condition::Not(inner) => columns.extend(collect_condition_columns(inner)),
     }
     columns
 }
\ No newline at end of file
@@ -1,5 +1,13 @@
-use crate::query::{Aggregation, Condition, Query};
-use crate::types::{DataType, DbError, Value};
+use crate::query::binder::resolve_table_aliases;
+use crate::query::explain::ExplainFormat;
+use crate::query::mask::MaskFunction;
+use crate::query::expr::{ArithmeticOp, Expr};
+use crate::query::pattern::Pattern;
+use crate::query::{
+    Aggregation, AlterAction, Comparison, Condition, FileFormat, GroupKey, Hint, JoinOperator, Query, SortOrder,
+};
+use crate::schema::{Column, Validation};
+use crate::types::{Collation, CompressionType, DataType, DbError, Value};
 use ordered_float::OrderedFloat;
 
 pub fn parse_query(input: &str) -> Result<Query, DbError> {
@@ -10,16 +18,39 @@ pub fn parse_query(input: &str) -> Result<Query, DbError> {
     }
 
     match parts[0].to_uppercase().as_str() {
+        "CREATE" if parts.get(1).is_some_and(|p| p.eq_ignore_ascii_case("SNAPSHOT")) => parse_snapshot(&parts),
+        "CREATE" | "DROP" if parts.get(1).is_some_and(|p| p.eq_ignore_ascii_case("JOB")) => parse_job(input),
+        "CREATE" if parts.get(1).is_some_and(|p| p.eq_ignore_ascii_case("EXTERNAL")) => {
+            parse_create_external_table(input)
+        }
         "CREATE" => parse_create_table(input),
         "INSERT" => parse_insert(input),
+        "UPDATE" => parse_update(input),
         "SELECT" => parse_select(input),
         "DELETE" => parse_delete(input),
+        "DROP" if parts.get(1).is_some_and(|p| p.eq_ignore_ascii_case("SNAPSHOT")) => parse_snapshot(&parts),
         "DROP" => parse_drop_table(input),
+        "UNDROP" => parse_drop_table(input),
+        "ALTER" => parse_alter_table(input),
         "START" => parse_start_transaction(input),
         "COMMIT" => parse_commit(input),
         "ROLLBACK" => parse_rollback(input),
         "UNMAKE" => parse_drop_index(input),
         "MAKE" => parse_make_index(input),
+        "SHOW" => parse_show(input),
+        "ANALYZE" => parse_analyze(input),
+        "COMPACT" => parse_compact(input),
+        "SALVAGE" => parse_salvage(input),
+        "PRELOAD" => parse_preload(input),
+        "CLEANUP" => parse_cleanup(input),
+        "BACKUP" => parse_backup(input),
+        "EXPORT" => parse_export(input),
+        "IMPORT" => parse_import(input),
+        "VERIFY" => parse_verify(input),
+        "COPY" => parse_copy(input),
+        "SET" => parse_set(input),
+        "EXPLAIN" => parse_explain(input),
+        "FLUSH" if parts.len() == 1 => Ok(Query::Flush),
         _ => Err(DbError::QueryError(format!("Unknown command: {}", parts[0]))),
     }
 }
@@ -36,136 +67,852 @@ fn parse_create_table(input: &str) -> Result<Query, DbError> {
     let col_defs_end = input
         .rfind(')')
         .ok_or_else(|| DbError::QueryError("Missing closing parenthesis".to_string()))?;
-    let col_defs = input[col_defs_start + 1..col_defs_end]
-        .split(',')
-        .map(|s| s.trim())
-        .collect::<Vec<_>>();
-    let mut columns = Vec::new();
-    for col_def in col_defs {
-        let col_parts = col_def.split_whitespace().collect::<Vec<_>>();
-        if col_parts.len() != 2 {
-            return Err(DbError::QueryError("Invalid column definition".to_string()));
-        }
-        let data_type = match col_parts[1].to_uppercase().as_str() {
-            "INT" => DataType::Int32,
-            "FLOAT" => DataType::Float32,
-            "STRING" => DataType::String,
-            _ => return Err(DbError::QueryError(format!("Invalid data type: {}", col_parts[1]))),
-        };
-        columns.push((col_parts[0].to_string(), data_type));
-    }
+    let col_defs = split_top_level(&input[col_defs_start + 1..col_defs_end]);
+    let columns = col_defs.iter().map(|c| parse_column_def(c)).collect::<Result<Vec<_>, _>>()?;
     Ok(Query::CreateTable { table, columns })
 }
 
+/// `CREATE EXTERNAL TABLE t (col TYPE, ...) LOCATION 'path'`.
+fn parse_create_external_table(input: &str) -> Result<Query, DbError> {
+    let upper = input.to_uppercase();
+    let location_at = upper
+        .rfind(" LOCATION ")
+        .ok_or_else(|| DbError::QueryError("CREATE EXTERNAL TABLE needs a LOCATION".to_string()))?;
+    let location = input[location_at + " LOCATION ".len()..].trim().trim_matches('\'').to_string();
+    if location.is_empty() {
+        return Err(DbError::QueryError("Empty LOCATION".to_string()));
+    }
+    // The rest reads like CREATE TABLE once EXTERNAL is dropped.
+    let definition = input[..location_at].trim();
+    let table_at = upper
+        .find(" TABLE ")
+        .ok_or_else(|| DbError::QueryError("Invalid CREATE EXTERNAL TABLE syntax".to_string()))?;
+    match parse_create_table(&format!("CREATE{}", &definition[table_at..]))? {
+        Query::CreateTable { table, columns } => Ok(Query::CreateExternalTable { table, columns, location }),
+        _ => unreachable!("parse_create_table only returns CreateTable"),
+    }
+}
+
+/// `name TYPE [NOT NULL | NULL] [DEFAULT expr] [COLLATE name] [PRIMARY KEY | UNIQUE]`.
+fn parse_column_def(col_def: &str) -> Result<Column, DbError> {
+    let col_parts = col_def.split_whitespace().collect::<Vec<_>>();
+    let mut not_null = false;
+    let mut primary_key = false;
+    let mut unique = false;
+    let mut default = None;
+    let mut collation = Collation::Binary;
+    let mut compression = None;
+    let mut mask = None;
+    let mut rest = col_parts.get(2..).unwrap_or_default();
+    while let Some(keyword) = rest.first() {
+        match (keyword.to_uppercase().as_str(), rest.get(1)) {
+            ("NOT", Some(null)) if null.eq_ignore_ascii_case("NULL") => {
+                not_null = true;
+                rest = &rest[2..];
+            }
+            ("NULL", _) => rest = &rest[1..],
+            ("PRIMARY", Some(key)) if key.eq_ignore_ascii_case("KEY") => {
+                primary_key = true;
+                rest = &rest[2..];
+            }
+            ("UNIQUE", _) => {
+                unique = true;
+                rest = &rest[1..];
+            }
+            ("DEFAULT", Some(expr)) => {
+                default = Some(parse_expr(expr)?);
+                rest = &rest[2..];
+            }
+            ("COLLATE", Some(name)) => {
+                collation = match name.to_uppercase().as_str() {
+                    "BINARY" => Collation::Binary,
+                    "NOCASE" => Collation::NoCase,
+                    _ => return Err(DbError::QueryError(format!("Unknown collation: {}", name))),
+                };
+                rest = &rest[2..];
+            }
+            ("MASKED", Some(with)) if with.eq_ignore_ascii_case("WITH") => {
+                let name = rest.get(2).copied().unwrap_or_default();
+                mask = Some(
+                    MaskFunction::from_name(name)
+                        .ok_or_else(|| DbError::QueryError(format!("Unknown masking function: {}", name)))?,
+                );
+                rest = &rest[3..];
+            }
+            ("COMPRESSION", Some(name)) => {
+                compression = Some(match name.to_uppercase().as_str() {
+                    "NONE" => CompressionType::None,
+                    "RLE" => CompressionType::Rle,
+                    "DICTIONARY" => CompressionType::Dictionary,
+                    "LZ4" => CompressionType::Lz4,
+                    "ZSTD" => CompressionType::Zstd,
+                    "INTERNED" => CompressionType::Interned,
+                    _ => return Err(DbError::QueryError(format!("Unknown compression: {}", name))),
+                });
+                rest = &rest[2..];
+            }
+            _ => return Err(DbError::QueryError(format!("Invalid column definition: {}", col_def))),
+        }
+    }
+    if col_parts.len() < 2 {
+        return Err(DbError::QueryError("Invalid column definition".to_string()));
+    }
+    let data_type = match col_parts[1].to_uppercase().as_str() {
+        "INT" => DataType::Int32,
+        "FLOAT" => DataType::Float32,
+        "STRING" => DataType::String,
+        "BIGINT" | "INT64" => DataType::Int64,
+        "BOOL" | "BOOLEAN" => DataType::Boolean,
+        "DATE" => DataType::Date,
+        "TIMESTAMP" => DataType::Timestamp,
+        _ => return Err(DbError::QueryError(format!("Invalid data type: {}", col_parts[1]))),
+    };
+    let mut column = Column::new(col_parts[0], data_type);
+    if not_null {
+        column = column.not_null();
+    }
+    if primary_key {
+        column = column.primary_key();
+    }
+    if unique {
+        column = column.unique();
+    }
+    if let Some(default) = default {
+        column = column.with_default(default);
+    }
+    if collation != Collation::Binary {
+        if column.data_type != DataType::String {
+            return Err(DbError::QueryError(format!("COLLATE applies only to STRING columns: {}", col_def)));
+        }
+        column = column.with_collation(collation);
+    }
+    if let Some(compression) = compression {
+        let strings_only = matches!(compression, CompressionType::Dictionary | CompressionType::Interned);
+        if strings_only && column.data_type != DataType::String {
+            return Err(DbError::QueryError(format!(
+                "{} compression applies only to STRING columns: {}",
+                compression, col_def
+            )));
+        }
+        column = column.with_compression(compression);
+    }
+    if let Some(mask) = mask {
+        if column.data_type != DataType::String {
+            return Err(DbError::QueryError(format!("MASKED applies only to STRING columns: {}", col_def)));
+        }
+        column = column.with_mask(mask);
+    }
+    Ok(column)
+}
+
+/// `INSERT INTO t [(col, ...)] VALUES (...)[, (...)]`. A single row
+/// without a column list stays a plain `Query::Insert`.
 fn parse_insert(input: &str) -> Result<Query, DbError> {
-    let parts = input.split_whitespace().collect::<Vec<_>>();
-    if parts.len() < 4 || parts[1].to_uppercase() != "INTO" || parts[3].to_uppercase() != "VALUES" {
-        return Err(DbError::QueryError("Invalid INSERT syntax".to_string()));
+    let invalid = || DbError::QueryError("Invalid INSERT syntax".to_string());
+    let (body, returning) = split_returning(input)?;
+    let values_pos =
+        find_keyword(body, "VALUES").ok_or_else(|| DbError::QueryError("Missing VALUES clause".to_string()))?;
+    // Skip INSERT, which is six bytes.
+    let target = match body[6..values_pos].trim().split_once(char::is_whitespace) {
+        Some((into, rest)) if into.eq_ignore_ascii_case("INTO") => rest.trim(),
+        _ => return Err(invalid()),
+    };
+    let (table, columns) = match target.split_once('(') {
+        Some((table, list)) => {
+            let list = list.strip_suffix(')').ok_or_else(invalid)?;
+            (table.trim(), Some(split_top_level(list)))
+        }
+        None => (target, None),
+    };
+    let empty_column = columns.as_ref().is_some_and(|c| c.contains(&String::new()));
+    if table.is_empty() || table.contains(char::is_whitespace) || empty_column {
+        return Err(invalid());
     }
-    let table = parts[2].to_string();
-    let values_start = input
-        .find("VALUES")
-        .ok_or_else(|| DbError::QueryError("Missing VALUES clause".to_string()))?
-        + 6;
-    let values_str = input[values_start..].trim();
-    let values = values_str[1..values_str.len() - 1]
-        .split(',')
-        .map(|s| s.trim())
-        .map(|s| {
-            if s.starts_with('"') && s.ends_with('"') {
-                Ok(Value::String(s[1..s.len() - 1].to_string()))
-            } else if s.contains('.') {
-                s.parse::<f32>()
-                    .map(|f| Value::Float32(OrderedFloat(f)))
-                    .map_err(|_| DbError::QueryError(format!("Invalid float value: {}", s)))
-            } else {
-                s.parse::<i32>()
-                    .map(|i| Value::Int32(i))
-                    .map_err(|_| DbError::QueryError(format!("Invalid integer value: {}", s)))
+    let mut rows = Vec::new();
+    for tuple in split_top_level(&body[values_pos + 8..]) {
+        let inner = tuple.strip_prefix('(').and_then(|t| t.strip_suffix(')')).ok_or_else(invalid)?;
+        rows.push(split_top_level(inner).iter().map(|s| parse_value(s)).collect::<Result<Vec<_>, _>>()?);
+    }
+    let table = table.to_string();
+    match columns {
+        None if rows.len() == 1 => Ok(Query::Insert { table, values: rows.remove(0), returning }),
+        columns => Ok(Query::InsertRows { table, columns: columns.unwrap_or_default(), rows, returning }),
+    }
+}
+
+/// Parses a literal: `"text"` or `'text'`, a float if it contains a dot,
+/// otherwise an integer.
+fn parse_value(s: &str) -> Result<Value, DbError> {
+    if s.len() >= 2 && (s.starts_with('"') && s.ends_with('"') || s.starts_with('\'') && s.ends_with('\'')) {
+        Ok(Value::String(s[1..s.len() - 1].to_string()))
+    } else if s.eq_ignore_ascii_case("NULL") {
+        Ok(Value::Null)
+    } else if s.eq_ignore_ascii_case("TRUE") || s.eq_ignore_ascii_case("FALSE") {
+        Ok(Value::Boolean(s.eq_ignore_ascii_case("TRUE")))
+    } else if s.contains('.') {
+        s.parse::<f32>()
+            .map(|f| Value::Float32(OrderedFloat(f)))
+            .map_err(|_| DbError::QueryError(format!("Invalid float value: {}", s)))
+    } else if let Ok(i) = s.parse::<i32>() {
+        Ok(Value::Int32(i))
+    } else {
+        // Literals outside the Int32 range are Int64.
+        s.parse::<i64>()
+            .map(Value::Int64)
+            .map_err(|_| DbError::QueryError(format!("Invalid integer value: {}", s)))
+    }
+}
+
+/// Parses a scalar expression: `NOW()`, `CURRENT_TIMESTAMP`, `CURRENT_DATE`,
+/// `COALESCE(..)`, `IFNULL(a, b)`, `NULLIF(a, b)`, `MASK_EMAIL(a)`,
+/// `MASK_LAST4(a)`, a literal or a column, combined with `+ - * /` and
+/// parentheses.
+fn parse_expr(s: &str) -> Result<Expr, DbError> {
+    let s = s.trim();
+    if let Some((pos, op)) = arithmetic_split(s) {
+        let (left, right) = (s[..pos].trim(), s[pos + 1..].trim());
+        if left.is_empty() || right.is_empty() {
+            return Err(DbError::QueryError(format!("Missing operand in expression: {}", s)));
+        }
+        return Ok(Expr::Arithmetic(op, Box::new(parse_expr(left)?), Box::new(parse_expr(right)?)));
+    }
+    if let Some(inner) = enclosed(s) {
+        if parse_subquery(s)?.is_some() {
+            return Err(DbError::QueryError(format!(
+                "A subquery can only be compared with or follow IN: {}",
+                s
+            )));
+        }
+        return parse_expr(inner);
+    }
+    let upper = s.to_uppercase();
+    let function = upper.split('(').next().unwrap_or_default().trim_end();
+    match upper.as_str() {
+        "NOW()" | "CURRENT_TIMESTAMP" | "CURRENT_TIMESTAMP()" => return Ok(Expr::CurrentTimestamp),
+        "CURRENT_DATE" | "CURRENT_DATE()" => return Ok(Expr::CurrentDate),
+        _ => {}
+    }
+    if let Some(mask) = MaskFunction::from_name(function)
+        && upper.ends_with(')')
+    {
+        return match function_args(s)?.as_slice() {
+            [arg] => Ok(Expr::Mask(mask, Box::new(parse_expr(arg)?))),
+            _ => Err(DbError::QueryError(format!("Wrong number of arguments: {}", s))),
+        };
+    }
+    if matches!(function, "COALESCE" | "IFNULL" | "NULLIF") && upper.ends_with(')') {
+        let mut args = function_args(s)?
+            .iter()
+            .map(|a| parse_expr(a))
+            .collect::<Result<Vec<_>, _>>()?;
+        return match (function, args.len()) {
+            ("COALESCE", n) if n > 0 => Ok(Expr::Coalesce(args)),
+            ("IFNULL", 2) => Ok(Expr::Coalesce(args)),
+            ("NULLIF", 2) => {
+                let other = args.pop().unwrap();
+                Ok(Expr::NullIf(Box::new(args.pop().unwrap()), Box::new(other)))
             }
+            _ => Err(DbError::QueryError(format!("Wrong number of arguments: {}", s))),
+        };
+    }
+    match parse_value(s) {
+        Ok(value) => Ok(Expr::Literal(value)),
+        Err(_) if is_identifier(s) => Ok(Expr::Column(s.to_string())),
+        Err(e) => Err(e),
+    }
+}
+
+/// The operator an expression splits at: the last one outside parentheses and
+/// quotes of the loosest precedence present, so that operators of equal
+/// precedence group to the left. A `-` after another operator or at the
+/// start is a sign, not a subtraction.
+fn arithmetic_split(s: &str) -> Option<(usize, ArithmeticOp)> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut operand_before = false;
+    let mut split: Option<(usize, ArithmeticOp)> = None;
+    for (pos, ch) in s.char_indices() {
+        let op = match ch {
+            '"' | '\'' if quote.is_none() => {
+                quote = Some(ch);
+                None
+            }
+            _ if quote == Some(ch) => {
+                quote = None;
+                None
+            }
+            _ if quote.is_some() => None,
+            '(' => {
+                depth += 1;
+                None
+            }
+            ')' => {
+                depth -= 1;
+                None
+            }
+            '+' => Some(ArithmeticOp::Add),
+            '-' if operand_before => Some(ArithmeticOp::Subtract),
+            '*' => Some(ArithmeticOp::Multiply),
+            '/' => Some(ArithmeticOp::Divide),
+            _ => None,
+        };
+        if let Some(op) = op
+            && depth == 0
+            && split.is_none_or(|(_, loosest)| op.precedence() <= loosest.precedence())
+        {
+            split = Some((pos, op));
+        }
+        if !ch.is_whitespace() {
+            operand_before = op.is_none() && ch != '(' && ch != '-' || ch == ')';
+        }
+    }
+    split
+}
+
+/// A column name such as `Salary` or `Employees.Salary`.
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+}
+
+fn parse_update(input: &str) -> Result<Query, DbError> {
+    let upper = input.to_uppercase();
+    let parts = input.split_whitespace().collect::<Vec<_>>();
+    let set_pos = upper.find(" SET ");
+    if parts.len() < 4 || parts[2].to_uppercase() != "SET" || set_pos.is_none() {
+        return Err(DbError::QueryError(
+            "Invalid UPDATE syntax. Expected: UPDATE table SET col = value[, ...] [WHERE condition]".to_string(),
+        ));
+    }
+    let table = parts[1].to_string();
+    let set_start = set_pos.unwrap() + 5;
+    let where_pos = upper.find(" WHERE ");
+    let set_clause = &input[set_start..where_pos.unwrap_or(input.len())];
+    let assignments = split_top_level(set_clause)
+        .iter()
+        .map(|assignment| {
+            let (column, value) = assignment
+                .split_once('=')
+                .ok_or_else(|| DbError::QueryError(format!("Invalid assignment: {}", assignment.trim())))?;
+            Ok((column.trim().to_string(), parse_value(value.trim())?))
         })
-        .collect::<Result<Vec<_>, _>>()?;
-    Ok(Query::Insert { table, values })
+        .collect::<Result<Vec<_>, DbError>>()?;
+    let condition = match where_pos {
+        Some(pos) => Some(parse_condition(input[pos + 7..].trim())?),
+        None => None,
+    };
+    Ok(Query::Update {
+        table,
+        assignments,
+        condition,
+    })
+}
+
+/// Splits a `/*+ hint ... */` comment directly after SELECT off the statement.
+fn split_hints(input: &str) -> Result<(String, Vec<Hint>), DbError> {
+    let Some(comment) = input[6..].trim_start().strip_prefix("/*+") else {
+        return Ok((input.to_string(), Vec::new()));
+    };
+    let (hints, rest) = comment
+        .split_once("*/")
+        .ok_or_else(|| DbError::QueryError("Unterminated optimizer hint comment".to_string()))?;
+    Ok((format!("{} {}", &input[..6], rest.trim_start()), parse_hints(hints)?))
+}
+
+/// `NO_INDEX(table ...)`, `HASH_JOIN` and `MERGE_JOIN`, separated by whitespace.
+fn parse_hints(input: &str) -> Result<Vec<Hint>, DbError> {
+    let mut hints = Vec::new();
+    let mut rest = input.trim();
+    while !rest.is_empty() {
+        let name_end = rest.find(|c: char| c == '(' || c.is_whitespace()).unwrap_or(rest.len());
+        let name = rest[..name_end].to_uppercase();
+        rest = rest[name_end..].trim_start();
+        let mut args = Vec::new();
+        if let Some(inner) = rest.strip_prefix('(') {
+            let (list, after) = inner
+                .split_once(')')
+                .ok_or_else(|| DbError::QueryError(format!("Missing closing parenthesis in hint {}", name)))?;
+            args = list.split([',', ' ']).filter(|a| !a.is_empty()).map(str::to_string).collect();
+            rest = after.trim_start();
+        }
+        match (name.as_str(), args.is_empty()) {
+            ("NO_INDEX", false) => hints.extend(args.into_iter().map(Hint::NoIndex)),
+            ("HASH_JOIN", true) => hints.push(Hint::HashJoin),
+            ("MERGE_JOIN", true) => hints.push(Hint::MergeJoin),
+            _ => return Err(DbError::QueryError(format!("Unknown optimizer hint: {}", name))),
+        }
+    }
+    Ok(hints)
+}
+
+/// Removes an `AS OF SNAPSHOT name` clause from a SELECT, returning the rest
+/// of the statement and the snapshot's name.
+fn split_as_of_snapshot(input: &str) -> Result<Option<(String, String)>, DbError> {
+    let Some(pos) = find_keyword(input, "AS OF SNAPSHOT") else {
+        return Ok(None);
+    };
+    let after = input[pos + " AS OF SNAPSHOT ".len()..].trim_start();
+    let (name, rest) = after.split_once(char::is_whitespace).unwrap_or((after, ""));
+    if name.is_empty() {
+        return Err(DbError::QueryError("AS OF SNAPSHOT requires a snapshot name".to_string()));
+    }
+    Ok(Some((format!("{} {}", &input[..pos], rest).trim_end().to_string(), name.to_string())))
 }
 
 fn parse_select(input: &str) -> Result<Query, DbError> {
+    if let Some((input, name)) = split_as_of_snapshot(input)? {
+        let query = Box::new(parse_select(&input)?);
+        return Ok(Query::AsOfSnapshot { name, query });
+    }
+    let (input, hints) = split_hints(input)?;
+    let input = input.as_str();
     let columns_end = input
         .find("FROM")
         .ok_or_else(|| DbError::QueryError("Missing FROM clause".to_string()))?;
-    let columns_str = input[6..columns_end].trim();
+    let mut columns_str = input[6..columns_end].trim();
+    let distinct = strip_distinct(columns_str).is_some();
+    if let Some(rest) = strip_distinct(columns_str) {
+        columns_str = rest;
+    }
     let from_clause = input[columns_end + 4..].trim();
     
     // Check for JOIN
-    if from_clause.to_uppercase().contains(" JOIN ") {
-        return parse_join(input);
+    if find_keyword(from_clause, "JOIN").is_some() {
+        if distinct {
+            return Err(DbError::QueryError("DISTINCT is not supported with JOIN".to_string()));
+        }
+        return parse_join(input, hints);
     }
 
-    let from_end = input.find("WHERE").unwrap_or(input.len());
-    let table = input[columns_end + 4..from_end].trim().to_string();
-    let condition = if from_end < input.len() {
-        Some(parse_condition(&input[from_end + 5..].trim())?)
+    // ORDER BY / LIMIT / OFFSET close the statement, in that order.
+    let order_pos = find_keyword(input, "ORDER BY");
+    let limit_pos = find_keyword(input, "LIMIT");
+    let offset_pos = find_keyword(input, "OFFSET");
+    let tail_start = [order_pos, limit_pos, offset_pos].into_iter().flatten().min();
+    let statement_end = tail_start.unwrap_or(input.len());
+    let group_pos = find_keyword(input, "GROUP BY");
+    let clauses_end = group_pos.unwrap_or(statement_end);
+    let from_end = input.find("WHERE").unwrap_or(clauses_end);
+    let (table, sample) = parse_table_sample(input[columns_end + 4..from_end].trim())?;
+    let (table, alias) = parse_table_ref(&table)?;
+    let aliases: Vec<(String, String)> = alias.into_iter().map(|a| (a, table.clone())).collect();
+    let condition = if from_end < clauses_end {
+        Some(parse_condition(input[from_end + 5..clauses_end].trim())?)
     } else {
         None
     };
+    let group_by = match group_pos {
+        Some(pos) => split_top_level(&input[pos + 10..statement_end])
+            .iter()
+            .map(|k| parse_group_key(k))
+            .collect::<Result<Vec<_>, _>>()?,
+        None => Vec::new(),
+    };
 
     let columns = if columns_str == "*" {
         Vec::new() // Will be expanded in planner
     } else {
-        columns_str
-            .split(',')
-            .map(|s| s.trim().to_string())
-            .collect::<Vec<_>>()
-    };
-
-    if columns.iter().any(|c| {
-        c.to_uppercase().contains("COUNT")
-            || c.to_uppercase().contains("SUM")
-            || c.to_uppercase().contains("AVG")
-            || c.to_uppercase().contains("MIN")
-            || c.to_uppercase().contains("MAX")
-    }) {
-        let aggregations = columns
-            .iter()
-            .map(|c| {
-                let c_upper = c.to_uppercase();
-                if c_upper.starts_with("COUNT") {
-                    Aggregation::Count
-                } else if c_upper.starts_with("SUM") {
-                    Aggregation::Sum(c[4..c.len() - 1].to_string())
-                } else if c_upper.starts_with("AVG") {
-                    Aggregation::Avg(c[4..c.len() - 1].to_string())
-                } else if c_upper.starts_with("MIN") {
-                    Aggregation::Min(c[4..c.len() - 1].to_string())
-                } else if c_upper.starts_with("MAX") {
-                    Aggregation::Max(c[4..c.len() - 1].to_string())
-                } else {
-                    Aggregation::Count // Fallback
+        split_top_level(columns_str)
+    };
+
+    let clause_end = |start: usize| {
+        [order_pos, limit_pos, offset_pos]
+            .into_iter()
+            .flatten()
+            .filter(|&p| p > start)
+            .min()
+            .unwrap_or(input.len())
+    };
+    let order_by = match order_pos {
+        Some(pos) => parse_order_by(&input[pos + 10..clause_end(pos)])?,
+        None => Vec::new(),
+    };
+    let limit = limit_pos
+        .map(|pos| parse_count("LIMIT", &input[pos + 7..clause_end(pos)]))
+        .transpose()?;
+    let offset = offset_pos
+        .map(|pos| parse_count("OFFSET", &input[pos + 8..clause_end(pos)]))
+        .transpose()?;
+
+    if !group_by.is_empty() || columns.iter().any(|c| is_aggregate(c)) {
+        if distinct {
+            return Err(DbError::QueryError("DISTINCT is not supported with aggregations".to_string()));
+        }
+        if tail_start.is_some() {
+            return Err(DbError::QueryError(
+                "ORDER BY, LIMIT and OFFSET are not supported with aggregations".to_string(),
+            ));
+        }
+        let mut aggregations = Vec::new();
+        for c in &columns {
+            if find_keyword(c, "AS").is_some() {
+                return Err(DbError::QueryError(format!("Column aliases are not supported with aggregations: {}", c)));
+            }
+            if !is_aggregate(c) {
+                if !group_by.contains(&parse_group_key(c)?) {
+                    return Err(DbError::QueryError(format!(
+                        "Column {} must appear in GROUP BY or be aggregated",
+                        c
+                    )));
                 }
-            })
-            .collect();
-        Ok(Query::SelectAggregate {
+                continue;
+            }
+            aggregations.push(parse_aggregation(c)?);
+        }
+        let query = Query::SelectAggregate {
             table,
             aggregations,
             condition,
-        })
+            sample,
+            group_by,
+            hints,
+        };
+        resolve_table_aliases(query, &aliases)
     } else {
-        Ok(Query::Select {
+        let columns = columns.iter().map(|c| parse_select_item(c)).collect::<Result<Vec<_>, _>>()?;
+        // ORDER BY may name a column by the alias the select list gave it.
+        let order_by = order_by
+            .into_iter()
+            .map(|(name, order)| match columns.iter().find(|c| matches!(c, Expr::Alias(_, a) if *a == name)) {
+                Some(Expr::Alias(expr, _)) => match expr.as_ref() {
+                    Expr::Column(col) => Ok((col.clone(), order)),
+                    _ => Err(DbError::QueryError(format!("ORDER BY {} must name a column, not an expression", name))),
+                },
+                _ => Ok((name, order)),
+            })
+            .collect::<Result<_, DbError>>()?;
+        let query = Query::Select {
             table,
             columns,
+            distinct,
             condition,
+            sample,
+            order_by,
+            limit,
+            offset,
+            hints,
+        };
+        resolve_table_aliases(query, &aliases)
+    }
+}
+
+/// A select list item: an expression, optionally renamed with `AS name`.
+fn parse_select_item(s: &str) -> Result<Expr, DbError> {
+    let Some(pos) = find_keyword(s, "AS") else {
+        return parse_expr(s);
+    };
+    let alias = s[pos + 4..].trim();
+    if !is_identifier(alias) || alias.contains('.') {
+        return Err(DbError::QueryError(format!("Invalid column alias: {}", alias)));
+    }
+    Ok(Expr::Alias(Box::new(parse_expr(&s[..pos])?), alias.to_string()))
+}
+
+/// `Employees`, `Employees e` or `Employees AS e`: a table and its alias.
+fn parse_table_ref(s: &str) -> Result<(String, Option<String>), DbError> {
+    let parts = s.split_whitespace().collect::<Vec<_>>();
+    let alias = match parts.as_slice() {
+        [table] => return Ok((table.to_string(), None)),
+        [_, alias] => alias,
+        [_, as_, alias] if as_.eq_ignore_ascii_case("AS") => alias,
+        _ => return Err(DbError::QueryError(format!("Invalid table reference: {}", s))),
+    };
+    if !is_identifier(alias) || alias.contains('.') {
+        return Err(DbError::QueryError(format!("Invalid table alias: {}", alias)));
+    }
+    Ok((parts[0].to_string(), Some(alias.to_string())))
+}
+
+/// `col [ASC|DESC], ...`
+fn parse_order_by(input: &str) -> Result<Vec<(String, SortOrder)>, DbError> {
+    split_top_level(input)
+        .iter()
+        .map(|key| {
+            let parts = key.split_whitespace().collect::<Vec<_>>();
+            let order = match parts.get(1).map(|p| p.to_uppercase()).as_deref() {
+                None | Some("ASC") => SortOrder::Asc,
+                Some("DESC") => SortOrder::Desc,
+                Some(other) => return Err(DbError::QueryError(format!("Invalid sort order: {}", other))),
+            };
+            match parts.as_slice() {
+                [col] | [col, _] => Ok((col.to_string(), order)),
+                _ => Err(DbError::QueryError(format!("Invalid ORDER BY key: {}", key))),
+            }
         })
+        .collect()
+}
+
+fn parse_count(clause: &str, s: &str) -> Result<usize, DbError> {
+    s.trim()
+        .parse::<usize>()
+        .map_err(|_| DbError::QueryError(format!("Invalid {} value: {}", clause, s.trim())))
+}
+
+/// Splits a trailing `RETURNING col, ...` (or `RETURNING *`) off a DML statement.
+fn split_returning(input: &str) -> Result<(&str, Vec<String>), DbError> {
+    let Some(pos) = find_keyword(input, "RETURNING") else {
+        return Ok((input, Vec::new()));
+    };
+    let columns = split_top_level(&input[pos + 11..]);
+    if columns.iter().any(|c| c.is_empty()) {
+        return Err(DbError::QueryError("Invalid RETURNING clause".to_string()));
+    }
+    Ok((input[..pos].trim_end(), columns))
+}
+
+/// Byte position of the space before `keyword` outside quotes and
+/// parentheses, so `FIRST(x ORDER BY ts)` does not end the statement.
+fn find_keyword(input: &str, keyword: &str) -> Option<usize> {
+    let upper = input.to_ascii_uppercase();
+    let pattern = format!(" {} ", keyword);
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, ch) in upper.char_indices() {
+        match ch {
+            '"' | '\'' if quote.is_none() => quote = Some(ch),
+            _ if quote == Some(ch) => quote = None,
+            '(' if quote.is_none() => depth += 1,
+            ')' if quote.is_none() => depth -= 1,
+            ' ' if depth == 0 && quote.is_none() && upper[i..].starts_with(&pattern) => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn parse_aggregation(c: &str) -> Result<Aggregation, DbError> {
+    let c_upper = c.to_uppercase();
+    if c_upper.starts_with("APPROX_COUNT_DISTINCT") {
+        let args = function_args(c)?;
+        if args.len() != 1 {
+            return Err(DbError::QueryError(format!("Invalid aggregation: {}", c)));
+        }
+        Ok(Aggregation::ApproxCountDistinct(args[0].clone()))
+    } else if c_upper.starts_with("APPROX_QUANTILE") {
+        let args = function_args(c)?;
+        if args.len() != 2 {
+            return Err(DbError::QueryError(format!("Invalid aggregation: {}", c)));
+        }
+        let q = args[1].parse::<f64>().map_err(|_| {
+            DbError::QueryError(format!("Invalid quantile: {}", args[1]))
+        })?;
+        if !(0.0..=1.0).contains(&q) {
+            return Err(DbError::QueryError(format!(
+                "Quantile must be between 0 and 1, got {}",
+                q
+            )));
+        }
+        Ok(Aggregation::ApproxQuantile(args[0].clone(), q))
+    } else if c_upper.starts_with("PERCENTILE") {
+        let args = function_args(c)?;
+        if args.len() != 2 {
+            return Err(DbError::QueryError(format!("Invalid aggregation: {}", c)));
+        }
+        let p = args[1].parse::<f64>().map_err(|_| {
+            DbError::QueryError(format!("Invalid percentile: {}", args[1]))
+        })?;
+        if !(0.0..=100.0).contains(&p) {
+            return Err(DbError::QueryError(format!(
+                "Percentile must be between 0 and 100, got {}",
+                p
+            )));
+        }
+        Ok(Aggregation::Percentile(args[0].clone(), p))
+    } else if c_upper.starts_with("HISTOGRAM") {
+        let args = function_args(c)?;
+        if args.len() != 2 {
+            return Err(DbError::QueryError(format!("Invalid aggregation: {}", c)));
+        }
+        let buckets = args[1].parse::<usize>().map_err(|_| {
+            DbError::QueryError(format!("Invalid bucket count: {}", args[1]))
+        })?;
+        if buckets == 0 {
+            return Err(DbError::QueryError("HISTOGRAM needs at least one bucket".to_string()));
+        }
+        Ok(Aggregation::Histogram(args[0].clone(), buckets))
+    } else if c_upper.starts_with("FIRST") || c_upper.starts_with("LAST") {
+        let args = function_args(c)?;
+        let arg_upper = args[0].to_uppercase();
+        let order_pos = arg_upper.find(" ORDER BY ").filter(|_| args.len() == 1).ok_or_else(|| {
+            DbError::QueryError(format!("Invalid aggregation: {}. Expected: FIRST/LAST(col ORDER BY col)", c))
+        })?;
+        let column = args[0][..order_pos].trim().to_string();
+        let order_by = args[0][order_pos + 10..].trim().to_string();
+        if c_upper.starts_with("FIRST") {
+            Ok(Aggregation::First(column, order_by))
+        } else {
+            Ok(Aggregation::Last(column, order_by))
+        }
+    } else if c_upper.starts_with("COUNT") {
+        let args = function_args(c).unwrap_or_default();
+        if let Some(column) = args.first().and_then(|arg| strip_distinct(arg)) {
+            if args.len() != 1 || !is_identifier(column) {
+                return Err(DbError::QueryError(format!("Invalid aggregation: {}. Expected: COUNT(DISTINCT col)", c)));
+            }
+            return Ok(Aggregation::CountDistinct(column.to_string()));
+        }
+        if let [column] = &args[..]
+            && is_identifier(column.trim())
+        {
+            return Ok(Aggregation::CountColumn(column.trim().to_string()));
+        }
+        Ok(Aggregation::Count)
+    } else if c_upper.starts_with("SUM") {
+        Ok(Aggregation::Sum(c[4..c.len() - 1].to_string()))
+    } else if c_upper.starts_with("AVG") {
+        Ok(Aggregation::Avg(c[4..c.len() - 1].to_string()))
+    } else if c_upper.starts_with("MIN") {
+        Ok(Aggregation::Min(c[4..c.len() - 1].to_string()))
+    } else if c_upper.starts_with("MAX") {
+        Ok(Aggregation::Max(c[4..c.len() - 1].to_string()))
+    } else {
+        Ok(Aggregation::Count) // Fallback
+    }
+}
+
+/// What follows a leading `DISTINCT` keyword, if there is one.
+fn strip_distinct(s: &str) -> Option<&str> {
+    let s = s.trim_start();
+    let rest = s.get(..8).filter(|k| k.eq_ignore_ascii_case("DISTINCT")).map(|_| &s[8..])?;
+    rest.starts_with(char::is_whitespace).then(|| rest.trim())
+}
+
+fn is_aggregate(item: &str) -> bool {
+    let upper = item.trim().to_uppercase();
+    if upper == "COUNT" {
+        return true;
+    }
+    [
+        "APPROX_COUNT_DISTINCT", "APPROX_QUANTILE", "PERCENTILE", "HISTOGRAM", "COUNT", "SUM", "AVG",
+        "MIN", "MAX", "FIRST", "LAST",
+    ]
+    .iter()
+    .any(|name| upper.starts_with(name) && upper[name.len()..].trim_start().starts_with('('))
+}
+
+fn parse_group_key(input: &str) -> Result<GroupKey, DbError> {
+    let upper = input.to_uppercase();
+    if upper.starts_with("TIME_BUCKET") || upper.starts_with("DATE_TRUNC") {
+        let args = function_args(input)?;
+        if args.len() != 2 {
+            return Err(DbError::QueryError(format!("Invalid time bucket: {}", input)));
+        }
+        let seconds = parse_interval(&args[0])?;
+        return Ok(GroupKey::TimeBucket {
+            column: args[1].clone(),
+            seconds,
+        });
+    }
+    Ok(GroupKey::Column(input.trim().to_string()))
+}
+
+/// Parses `'15 minutes'`, `'hour'`, `"1 day"` and similar into seconds.
+fn parse_interval(input: &str) -> Result<i64, DbError> {
+    let literal = input.trim().trim_matches(|c| c == '\'' || c == '"');
+    let parts = literal.split_whitespace().collect::<Vec<_>>();
+    let (count, unit) = match parts.as_slice() {
+        [unit] => (1, *unit),
+        [count, unit] => (
+            count
+                .parse::<i64>()
+                .map_err(|_| DbError::QueryError(format!("Invalid interval: {}", literal)))?,
+            *unit,
+        ),
+        _ => return Err(DbError::QueryError(format!("Invalid interval: {}", literal))),
+    };
+    let unit_seconds = match unit.to_lowercase().trim_end_matches('s') {
+        "second" => 1,
+        "minute" => 60,
+        "hour" => 3_600,
+        "day" => 86_400,
+        "week" => 604_800,
+        _ => return Err(DbError::QueryError(format!("Unknown interval unit: {}", unit))),
+    };
+    if count <= 0 {
+        return Err(DbError::QueryError(format!("Interval must be positive: {}", literal)));
+    }
+    Ok(count * unit_seconds)
+}
+
+/// Splits a comma-separated list, ignoring commas nested inside parentheses
+/// or quoted strings.
+fn split_top_level(input: &str) -> Vec<String> {
+    let mut parts = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut current = String::new();
+    for ch in input.chars() {
+        match ch {
+            '"' | '\'' if quote.is_none() => quote = Some(ch),
+            _ if quote == Some(ch) => quote = None,
+            '(' if quote.is_none() => depth += 1,
+            ')' if quote.is_none() => depth -= 1,
+            ',' if depth == 0 && quote.is_none() => {
+                parts.push(current.trim().to_string());
+                current.clear();
+                continue;
+            }
+            _ => {}
+        }
+        current.push(ch);
+    }
+    parts.push(current.trim().to_string());
+    parts
+}
+
+/// Arguments of a function call such as `APPROX_QUANTILE(Salary, 0.5)`.
+fn function_args(call: &str) -> Result<Vec<String>, DbError> {
+    let open = call
+        .find('(')
+        .ok_or_else(|| DbError::QueryError(format!("Missing arguments in {}", call)))?;
+    let close = call
+        .rfind(')')
+        .ok_or_else(|| DbError::QueryError(format!("Missing closing parenthesis in {}", call)))?;
+    if close < open {
+        return Err(DbError::QueryError(format!("Invalid function call: {}", call)));
+    }
+    Ok(split_top_level(&call[open + 1..close]))
+}
+
+/// Splits `t TABLESAMPLE (n PERCENT)` into the table name and the sample percentage.
+fn parse_table_sample(from: &str) -> Result<(String, Option<f64>), DbError> {
+    let upper = from.to_uppercase();
+    let Some(pos) = upper.find(" TABLESAMPLE") else {
+        return Ok((from.to_string(), None));
+    };
+    let table = from[..pos].trim().to_string();
+    let spec = from[pos + 12..].trim();
+    if !spec.starts_with('(') || !spec.ends_with(')') {
+        return Err(DbError::QueryError(
+            "Invalid TABLESAMPLE syntax. Expected: TABLESAMPLE (n PERCENT)".to_string(),
+        ));
+    }
+    let spec_parts = spec[1..spec.len() - 1].split_whitespace().collect::<Vec<_>>();
+    if spec_parts.len() != 2 || spec_parts[1].to_uppercase() != "PERCENT" {
+        return Err(DbError::QueryError(
+            "Invalid TABLESAMPLE syntax. Expected: TABLESAMPLE (n PERCENT)".to_string(),
+        ));
+    }
+    let percent = spec_parts[0]
+        .parse::<f64>()
+        .map_err(|_| DbError::QueryError(format!("Invalid sample percentage: {}", spec_parts[0])))?;
+    if !(0.0..=100.0).contains(&percent) {
+        return Err(DbError::QueryError(format!(
+            "Sample percentage must be between 0 and 100, got {}",
+            percent
+        )));
     }
+    Ok((table, Some(percent)))
 }
 
-fn parse_join(input: &str) -> Result<Query, DbError> {
+fn parse_join(input: &str, hints: Vec<Hint>) -> Result<Query, DbError> {
     let columns_end = input
         .find("FROM")
         .ok_or_else(|| DbError::QueryError("Missing FROM clause".to_string()))?;
-    let columns_str = input[6..columns_end].trim();
-    let columns = columns_str
-        .split(',')
-        .map(|s| s.trim().to_string())
-        .collect::<Vec<_>>();
+    let columns = split_top_level(input[6..columns_end].trim())
+        .iter()
+        .map(|c| parse_select_item(c))
+        .collect::<Result<Vec<_>, _>>()?;
 
     let from_clause = input[columns_end + 4..].trim();
     let join_pos = from_clause.to_uppercase().find(" JOIN ").ok_or_else(|| {
@@ -176,35 +923,74 @@ fn parse_join(input: &str) -> Result<Query, DbError> {
     })?;
     let where_pos = from_clause.to_uppercase().find(" WHERE ");
 
-    let left_table = from_clause[..join_pos].trim().to_string();
-    let right_table = from_clause[join_pos + 6..on_pos].trim().to_string();
+    let (left_table, left_alias) = parse_table_ref(from_clause[..join_pos].trim())?;
+    let (right_table, right_alias) = parse_table_ref(from_clause[join_pos + 6..on_pos].trim())?;
+    let mut aliases = Vec::new();
+    for (alias, table) in [(left_alias, &left_table), (right_alias, &right_table)] {
+        if let Some(alias) = alias {
+            if aliases.iter().any(|(a, _)| *a == alias) {
+                return Err(DbError::QueryError(format!("Table alias {} is used twice", alias)));
+            }
+            aliases.push((alias, table.clone()));
+        }
+    }
     let on_clause = if let Some(wp) = where_pos {
         from_clause[on_pos + 4..wp].trim()
     } else {
         from_clause[on_pos + 4..].trim()
     };
 
-    let on_parts = on_clause.split('=').map(|s| s.trim()).collect::<Vec<_>>();
-    if on_parts.len() != 2 {
+    // Two-character operators first so `<=` isn't read as `<`.
+    let (operator, symbol) = [
+        (JoinOperator::Le, "<="),
+        (JoinOperator::Ge, ">="),
+        (JoinOperator::Lt, "<"),
+        (JoinOperator::Gt, ">"),
+        (JoinOperator::Eq, "="),
+    ]
+    .into_iter()
+    .find(|(_, symbol)| on_clause.contains(symbol))
+    .ok_or_else(|| DbError::QueryError("Invalid ON clause".to_string()))?;
+    let on_parts = on_clause.split(symbol).map(|s| s.trim()).collect::<Vec<_>>();
+    if on_parts.len() != 2 || on_parts.iter().any(|p| p.is_empty()) {
         return Err(DbError::QueryError("Invalid ON clause".to_string()));
     }
-    let left_column = on_parts[0].split('.').last().unwrap().to_string();
-    let right_column = on_parts[1].split('.').last().unwrap().to_string();
+    let mut left_column = on_parts[0].split('.').next_back().unwrap().to_string();
+    let mut right_column = on_parts[1].split('.').next_back().unwrap().to_string();
+    // `ON d.DeptID = e.ID` names the right table first.
+    let names_table = |part: &str, table: &str| {
+        part.rsplit_once('.').is_some_and(|(q, _)| q == table || aliases.iter().any(|(a, t)| a == q && t == table))
+    };
+    let operator = if names_table(on_parts[0], &right_table) && names_table(on_parts[1], &left_table) {
+        std::mem::swap(&mut left_column, &mut right_column);
+        match operator {
+            JoinOperator::Lt => JoinOperator::Gt,
+            JoinOperator::Le => JoinOperator::Ge,
+            JoinOperator::Gt => JoinOperator::Lt,
+            JoinOperator::Ge => JoinOperator::Le,
+            JoinOperator::Eq => JoinOperator::Eq,
+        }
+    } else {
+        operator
+    };
 
     let condition = if let Some(wp) = where_pos {
-        Some(parse_condition(&from_clause[wp + 6..].trim())?)
+        Some(parse_condition(from_clause[wp + 6..].trim())?)
     } else {
         None
     };
 
-    Ok(Query::Join {
+    let query = Query::Join {
         left_table,
         right_table,
         left_column,
         right_column,
+        operator,
         columns,
         condition,
-    })
+        hints,
+    };
+    resolve_table_aliases(query, &aliases)
 }
 
 fn parse_delete(input: &str) -> Result<Query, DbError> {
@@ -213,24 +999,121 @@ fn parse_delete(input: &str) -> Result<Query, DbError> {
         return Err(DbError::QueryError("Invalid DELETE syntax".to_string()));
     }
     let table = parts[2].to_string();
+    let (input, returning) = split_returning(input)?;
     let condition = if input.to_uppercase().contains("WHERE") {
         let where_pos = input.to_uppercase().find("WHERE").unwrap();
-        Some(parse_condition(&input[where_pos + 5..].trim())?)
+        Some(parse_condition(input[where_pos + 5..].trim())?)
     } else {
         None
     };
-    Ok(Query::Delete { table, condition })
+    Ok(Query::Delete {
+        table,
+        condition,
+        returning,
+    })
 }
 
+/// `CREATE SNAPSHOT name` or `DROP SNAPSHOT name`.
+fn parse_snapshot(parts: &[&str]) -> Result<Query, DbError> {
+    match parts {
+        [verb, _, name] if verb.eq_ignore_ascii_case("CREATE") => Ok(Query::CreateSnapshot { name: name.to_string() }),
+        [_, _, name] => Ok(Query::DropSnapshot { name: name.to_string() }),
+        [verb, ..] => Err(DbError::QueryError(format!(
+            "Invalid {} SNAPSHOT syntax",
+            verb.to_uppercase()
+        ))),
+        [] => Err(DbError::QueryError("Empty command".to_string())),
+    }
+}
+
+/// `CREATE JOB name EVERY interval DO statement` or `DROP JOB name`. The
+/// statement is checked here but kept as text.
+fn parse_job(input: &str) -> Result<Query, DbError> {
+    let parts = input.split_whitespace().collect::<Vec<_>>();
+    if parts[0].eq_ignore_ascii_case("DROP") {
+        return match parts.as_slice() {
+            [_, _, name] => Ok(Query::DropJob { name: name.to_string() }),
+            _ => Err(DbError::QueryError("Invalid DROP JOB syntax".to_string())),
+        };
+    }
+    let upper = input.to_uppercase();
+    let (Some(every_at), Some(do_at)) = (upper.find(" EVERY "), upper.find(" DO ")) else {
+        return Err(DbError::QueryError("CREATE JOB needs EVERY interval DO statement".to_string()));
+    };
+    if parts.len() < 4 || !parts[3].eq_ignore_ascii_case("EVERY") || do_at < every_at {
+        return Err(DbError::QueryError("Invalid CREATE JOB syntax".to_string()));
+    }
+    let every = parse_interval(&input[every_at + " EVERY ".len()..do_at])?;
+    if every <= 0 {
+        return Err(DbError::QueryError("A job must run at a positive interval".to_string()));
+    }
+    let statement = input[do_at + " DO ".len()..].trim().to_string();
+    match parse_query(&statement)? {
+        Query::StartTransaction | Query::Commit | Query::Rollback | Query::CreateJob { .. } | Query::DropJob { .. } => {
+            Err(DbError::QueryError(format!("A job cannot run {}", statement)))
+        }
+        _ => Ok(Query::CreateJob {
+            name: parts[2].to_string(),
+            every: every as u64,
+            statement,
+        }),
+    }
+}
+
+/// `DROP TABLE t` or `UNDROP TABLE t`.
 fn parse_drop_table(input: &str) -> Result<Query, DbError> {
     let parts = input.split_whitespace().collect::<Vec<_>>();
     if parts.len() != 3 || parts[1].to_uppercase() != "TABLE" {
-        return Err(DbError::QueryError("Invalid DROP TABLE syntax".to_string()));
+        return Err(DbError::QueryError(format!("Invalid {} TABLE syntax", parts[0].to_uppercase())));
     }
     let table = parts[2].to_string();
+    if parts[0].eq_ignore_ascii_case("UNDROP") {
+        return Ok(Query::UndropTable { table });
+    }
     Ok(Query::DropTable { table })
 }
 
+/// `ALTER TABLE t ADD [COLUMN] definition`, `ALTER TABLE t DROP [COLUMN] name`
+/// or `ALTER TABLE t RENAME [COLUMN] old TO new`.
+fn parse_alter_table(input: &str) -> Result<Query, DbError> {
+    let syntax = || DbError::QueryError("Invalid ALTER TABLE syntax".to_string());
+    let parts = input.split_whitespace().collect::<Vec<_>>();
+    if parts.len() < 5 || !parts[1].eq_ignore_ascii_case("TABLE") {
+        return Err(syntax());
+    }
+    if parts[3].eq_ignore_ascii_case("SET") {
+        let action = match parts[4..] {
+            [keyword, mode] if keyword.eq_ignore_ascii_case("VALIDATION") => match mode.to_uppercase().as_str() {
+                "STRICT" => AlterAction::SetValidation(Validation::Strict),
+                "LENIENT" => AlterAction::SetValidation(Validation::Lenient),
+                _ => return Err(syntax()),
+            },
+            _ => return Err(syntax()),
+        };
+        return Ok(Query::AlterTable {
+            table: parts[2].to_string(),
+            action,
+        });
+    }
+    let mut rest = &parts[4..];
+    if rest[0].eq_ignore_ascii_case("COLUMN") {
+        rest = &rest[1..];
+    }
+    let action = match parts[3].to_uppercase().as_str() {
+        "ADD" if !rest.is_empty() => AlterAction::AddColumn(parse_column_def(&rest.join(" "))?),
+        "DROP" if rest.len() == 1 => AlterAction::DropColumn(rest[0].to_string()),
+        "RENAME" if rest.len() == 3 && rest[1].eq_ignore_ascii_case("TO") => AlterAction::RenameColumn {
+            from: rest[0].to_string(),
+            to: rest[2].to_string(),
+        },
+        _ => return Err(syntax()),
+    };
+    Ok(Query::AlterTable {
+        table: parts[2].to_string(),
+        action,
+    })
+}
+
 fn parse_start_transaction(input: &str) -> Result<Query, DbError> {
     if input.to_uppercase() == "START TRANSACTION" {
         Ok(Query::StartTransaction)
@@ -275,54 +1158,524 @@ fn parse_drop_index(input: &str) -> Result<Query, DbError> {
     Ok(Query::DropIndex { table, column })
 }
 
+fn parse_analyze(input: &str) -> Result<Query, DbError> {
+    let parts = input.split_whitespace().collect::<Vec<_>>();
+    if parts.len() != 2 {
+        return Err(DbError::QueryError("Invalid ANALYZE syntax. Expected: ANALYZE table_name".to_string()));
+    }
+    Ok(Query::Analyze {
+        table: parts[1].to_string(),
+    })
+}
+
+/// `COMPACT table` or `COMPACT TABLE table`.
+fn parse_compact(input: &str) -> Result<Query, DbError> {
+    match input.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [_, table] | [_, _, table] if !table.eq_ignore_ascii_case("TABLE") => Ok(Query::Compact {
+            table: table.to_string(),
+        }),
+        _ => Err(DbError::QueryError("Invalid COMPACT syntax. Expected: COMPACT table_name".to_string())),
+    }
+}
+
+/// `SALVAGE TABLE table INTO target`.
+fn parse_salvage(input: &str) -> Result<Query, DbError> {
+    match input.split_whitespace().collect::<Vec<_>>().as_slice() {
+        [_, keyword, table, into, target]
+            if keyword.eq_ignore_ascii_case("TABLE") && into.eq_ignore_ascii_case("INTO") =>
+        {
+            Ok(Query::SalvageTable {
+                table: table.to_string(),
+                target: target.to_string(),
+            })
+        }
+        _ => Err(DbError::QueryError(
+            "Invalid SALVAGE syntax. Expected: SALVAGE TABLE table_name INTO new_table".to_string(),
+        )),
+    }
+}
+
+/// `PRELOAD table` or `PRELOAD table (col, ...)`.
+fn parse_preload(input: &str) -> Result<Query, DbError> {
+    let syntax = || {
+        DbError::QueryError("Invalid PRELOAD syntax. Expected: PRELOAD table_name [(col, ...)]".to_string())
+    };
+    let rest = input[7..].trim();
+    let (table, columns) = match rest.find('(') {
+        Some(pos) => (rest[..pos].trim(), parenthesized(&rest[pos..])?),
+        None => (rest, Vec::new()),
+    };
+    if table.is_empty() || table.contains(char::is_whitespace) || columns.iter().any(|c| c.is_empty()) {
+        return Err(syntax());
+    }
+    Ok(Query::Preload {
+        table: table.to_string(),
+        columns,
+    })
+}
+
+/// `SET name = value` or `SET name TO value`; the value may be quoted.
+fn parse_set(input: &str) -> Result<Query, DbError> {
+    let syntax = || DbError::QueryError("Invalid SET syntax. Expected: SET name = value".to_string());
+    let rest = input[3..].trim();
+    let (name, value) = match rest.split_once('=') {
+        Some((name, value)) => (name.trim(), value.trim()),
+        None => match rest.split_whitespace().collect::<Vec<_>>().as_slice() {
+            [name, to, value] if to.eq_ignore_ascii_case("TO") => (*name, *value),
+            _ => return Err(syntax()),
+        },
+    };
+    if name.is_empty() || name.contains(char::is_whitespace) || value.is_empty() {
+        return Err(syntax());
+    }
+    Ok(Query::Set {
+        name: name.to_string(),
+        value: parse_quoted_path(value).unwrap_or_else(|| value.to_string()),
+    })
+}
+
+/// `EXPLAIN [ANALYZE] [FORMAT TEXT|JSON] query`.
+fn parse_explain(input: &str) -> Result<Query, DbError> {
+    let mut rest = input[7..].trim_start();
+    let mut analyze = false;
+    let mut format = ExplainFormat::Text;
+    loop {
+        let (word, after) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        match word.to_uppercase().as_str() {
+            "ANALYZE" => analyze = true,
+            "FORMAT" => {
+                let (name, after) = after.trim_start().split_once(char::is_whitespace).unwrap_or((after, ""));
+                format = match name.to_uppercase().as_str() {
+                    "TEXT" => ExplainFormat::Text,
+                    "JSON" => ExplainFormat::Json,
+                    _ => return Err(DbError::QueryError(format!("Unsupported EXPLAIN format: {}", name))),
+                };
+                rest = after.trim_start();
+                continue;
+            }
+            _ => break,
+        }
+        rest = after.trim_start();
+    }
+    let query = parse_query(rest)?;
+    if !matches!(query, Query::Select { .. } | Query::SelectAggregate { .. } | Query::Join { .. }) {
+        return Err(DbError::QueryError("EXPLAIN supports SELECT and JOIN queries".to_string()));
+    }
+    Ok(Query::Explain {
+        query: Box::new(query),
+        analyze,
+        format,
+    })
+}
+
+/// `COPY table FROM 'file' [FORMAT csv]` or `COPY (SELECT ...) TO 'file' [FORMAT csv|json]`.
+fn parse_copy(input: &str) -> Result<Query, DbError> {
+    let syntax = || {
+        DbError::QueryError(
+            "Invalid COPY syntax. Expected: COPY table FROM 'file' [FORMAT csv] or COPY (SELECT ...) TO 'file' [FORMAT csv|json]"
+                .to_string(),
+        )
+    };
+    let format_pos = find_keyword(input, "FORMAT");
+    let format = match format_pos.map(|pos| input[pos + 8..].trim().to_uppercase()) {
+        None => FileFormat::Csv,
+        Some(name) if name == "CSV" => FileFormat::Csv,
+        Some(name) if name == "JSON" => FileFormat::Json,
+        Some(name) => return Err(DbError::QueryError(format!("Unsupported COPY format: {}", name))),
+    };
+    let statement_end = format_pos.unwrap_or(input.len());
+
+    if let Some(to_pos) = find_keyword(input, "TO") {
+        let inner = input[4..to_pos].trim();
+        let inner = inner.strip_prefix('(').and_then(|s| s.strip_suffix(')')).ok_or_else(syntax)?;
+        let query = parse_query(inner.trim())?;
+        if !matches!(query, Query::Select { .. } | Query::SelectAggregate { .. } | Query::Join { .. }) {
+            return Err(DbError::QueryError("COPY ... TO needs a SELECT".to_string()));
+        }
+        let path = parse_quoted_path(&input[to_pos + 4..statement_end]).ok_or_else(syntax)?;
+        return Ok(Query::CopyTo {
+            query: Box::new(query),
+            path,
+            format,
+        });
+    }
+
+    let parts = input.split_whitespace().collect::<Vec<_>>();
+    if parts.len() < 4 || !parts[2].eq_ignore_ascii_case("FROM") {
+        return Err(syntax());
+    }
+    if format != FileFormat::Csv {
+        return Err(DbError::QueryError("COPY FROM supports only the csv format".to_string()));
+    }
+    let from_pos = find_keyword(input, "FROM").ok_or_else(syntax)?;
+    let path = parse_quoted_path(&input[from_pos + 6..statement_end]).ok_or_else(syntax)?;
+    Ok(Query::CopyFrom {
+        table: parts[1].to_string(),
+        path,
+        format,
+    })
+}
+
+fn parse_verify(input: &str) -> Result<Query, DbError> {
+    let syntax = || {
+        DbError::QueryError(
+            "Invalid VERIFY syntax. Expected: VERIFY TABLE table_name CHECK (condition) [LIMIT n]".to_string(),
+        )
+    };
+    let parts = input.split_whitespace().collect::<Vec<_>>();
+    if parts.len() < 5 || !parts[1].eq_ignore_ascii_case("TABLE") || !parts[3].eq_ignore_ascii_case("CHECK") {
+        return Err(syntax());
+    }
+    let check_pos = find_keyword(input, "CHECK").ok_or_else(syntax)?;
+    let limit_pos = find_keyword(input, "LIMIT");
+    let check = input[check_pos + 7..limit_pos.unwrap_or(input.len())].trim();
+    let condition = check.strip_prefix('(').and_then(|s| s.strip_suffix(')')).ok_or_else(syntax)?;
+    Ok(Query::VerifyTable {
+        table: parts[2].to_string(),
+        condition: parse_condition(condition)?,
+        limit: limit_pos.map(|pos| parse_count("LIMIT", &input[pos + 7..])).transpose()?,
+    })
+}
+
+/// A file or directory path in single or double quotes.
+fn parse_quoted_path(input: &str) -> Option<String> {
+    let input = input.trim();
+    let quoted = input.len() >= 2
+        && ((input.starts_with('\'') && input.ends_with('\'')) || (input.starts_with('"') && input.ends_with('"')));
+    quoted.then(|| input[1..input.len() - 1].to_string())
+}
+
+fn parse_backup(input: &str) -> Result<Query, DbError> {
+    let parts = input.splitn(3, char::is_whitespace).collect::<Vec<_>>();
+    match parts.as_slice() {
+        [_, to, path] if to.eq_ignore_ascii_case("TO") => parse_quoted_path(path)
+            .map(|path| Query::Backup { path })
+            .ok_or_else(|| DbError::QueryError("Backup path must be quoted".to_string())),
+        _ => Err(DbError::QueryError("Invalid BACKUP syntax. Expected: BACKUP TO 'dir'".to_string())),
+    }
+}
+
+fn parse_export(input: &str) -> Result<Query, DbError> {
+    let parts = input.splitn(5, char::is_whitespace).collect::<Vec<_>>();
+    match parts.as_slice() {
+        [_, kw, table, to, path] if kw.eq_ignore_ascii_case("TABLE") && to.eq_ignore_ascii_case("TO") => {
+            parse_quoted_path(path)
+                .map(|path| Query::ExportTable {
+                    table: table.to_string(),
+                    path,
+                })
+                .ok_or_else(|| DbError::QueryError("Export path must be quoted".to_string()))
+        }
+        _ => Err(DbError::QueryError(
+            "Invalid EXPORT syntax. Expected: EXPORT TABLE table TO 'file.vddb'".to_string(),
+        )),
+    }
+}
+
+fn parse_import(input: &str) -> Result<Query, DbError> {
+    let parts = input.splitn(4, char::is_whitespace).collect::<Vec<_>>();
+    match parts.as_slice() {
+        [_, kw, from, path] if kw.eq_ignore_ascii_case("TABLE") && from.eq_ignore_ascii_case("FROM") => {
+            parse_quoted_path(path)
+                .map(|path| Query::ImportTable { path })
+                .ok_or_else(|| DbError::QueryError("Import path must be quoted".to_string()))
+        }
+        _ => Err(DbError::QueryError(
+            "Invalid IMPORT syntax. Expected: IMPORT TABLE FROM 'file.vddb'".to_string(),
+        )),
+    }
+}
+
+fn parse_cleanup(input: &str) -> Result<Query, DbError> {
+    let parts = input.split_whitespace().map(|p| p.to_uppercase()).collect::<Vec<_>>();
+    match parts.iter().map(|p| p.as_str()).collect::<Vec<_>>().as_slice() {
+        ["CLEANUP"] => Ok(Query::Cleanup { dry_run: false }),
+        ["CLEANUP", "DRY", "RUN"] => Ok(Query::Cleanup { dry_run: true }),
+        _ => Err(DbError::QueryError("Invalid CLEANUP syntax. Expected: CLEANUP [DRY RUN]".to_string())),
+    }
+}
+
+fn parse_show(input: &str) -> Result<Query, DbError> {
+    let parts = input.split_whitespace().collect::<Vec<_>>();
+    let keywords = parts.iter().map(|p| p.to_uppercase()).collect::<Vec<_>>();
+    match keywords.iter().map(|k| k.as_str()).collect::<Vec<_>>().as_slice() {
+        ["SHOW", "TABLE", "SIZE", _] => Ok(Query::ShowTableSize {
+            table: parts[3].to_string(),
+        }),
+        ["SHOW", "DATABASE", "SIZE"] => Ok(Query::ShowDatabaseSize),
+        ["SHOW", "STORAGE", "REPORT"] => Ok(Query::ShowStorageReport { table: None }),
+        ["SHOW", "STORAGE", "REPORT", _] => Ok(Query::ShowStorageReport {
+            table: Some(parts[3].to_string()),
+        }),
+        ["SHOW", "TABLES"] => Ok(Query::ShowTables),
+        ["SHOW", "INDEXES"] => Ok(Query::ShowIndexes { table: None }),
+        ["SHOW", "INDEXES", "ON", _] => Ok(Query::ShowIndexes {
+            table: Some(parts[3].to_string()),
+        }),
+        ["SHOW", "SETTINGS"] => Ok(Query::ShowSettings),
+        _ => Err(DbError::QueryError(
+            "Invalid SHOW syntax. Expected: SHOW TABLES, SHOW SETTINGS, SHOW TABLE SIZE table_name, SHOW DATABASE SIZE, \
+             SHOW STORAGE REPORT [table_name] or SHOW INDEXES [ON table_name]"
+                .to_string(),
+        )),
+    }
+}
+
+/// Contents of a parenthesized list, split on top-level commas.
+fn parenthesized(input: &str) -> Result<Vec<String>, DbError> {
+    let input = input.trim();
+    match input.strip_prefix('(').and_then(|s| s.strip_suffix(')')) {
+        Some(inner) => Ok(split_top_level(inner)),
+        None => Err(DbError::QueryError(format!("Expected a parenthesized list: {}", input))),
+    }
+}
+
+/// Parses `(a, b) IN ((1, "x"), (2, "y"))` from its two sides.
+fn parse_in_tuple(columns: &str, tuples: &str) -> Result<Condition, DbError> {
+    let columns = parenthesized(columns)?;
+    if columns.iter().any(|c| c.is_empty() || c.contains(char::is_whitespace)) {
+        return Err(DbError::QueryError("Invalid column list in IN condition".to_string()));
+    }
+    let tuples = parenthesized(tuples)?
+        .iter()
+        .map(|tuple| {
+            let values = parenthesized(tuple)?
+                .iter()
+                .map(|v| parse_value(v))
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.len() != columns.len() {
+                return Err(DbError::QueryError(format!(
+                    "IN tuple has {} values, expected {}",
+                    values.len(),
+                    columns.len()
+                )));
+            }
+            Ok(values)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Condition::InTuple(columns, tuples))
+}
+
+/// Positions of `keyword` outside quotes and parentheses, where it stands
+/// between whitespace or parentheses.
+fn keyword_positions(input: &str, keyword: &str) -> Vec<usize> {
+    let upper = input.to_ascii_uppercase();
+    let mut positions = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    for (i, ch) in upper.char_indices() {
+        match ch {
+            '"' | '\'' if quote.is_none() => quote = Some(ch),
+            _ if quote == Some(ch) => quote = None,
+            '(' if quote.is_none() => depth += 1,
+            ')' if quote.is_none() => depth -= 1,
+            _ if depth == 0 && quote.is_none() && upper[i..].starts_with(keyword) => {
+                let before = upper[..i].chars().next_back();
+                let after = upper[i + keyword.len()..].chars().next();
+                let boundary = |c: char, paren: char| c.is_whitespace() || c == paren;
+                if before.is_some_and(|c| boundary(c, ')')) && after.is_some_and(|c| boundary(c, '(')) {
+                    positions.push(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    positions
+}
+
+/// Splits `input` around the keywords at `positions`, each `len` bytes long.
+fn split_at_keywords<'a>(input: &'a str, positions: &[usize], len: usize) -> Vec<&'a str> {
+    let mut parts = Vec::with_capacity(positions.len() + 1);
+    let mut start = 0;
+    for &pos in positions {
+        parts.push(&input[start..pos]);
+        start = pos + len;
+    }
+    parts.push(&input[start..]);
+    parts
+}
+
+/// The inside of `input` if one pair of parentheses encloses all of it.
+fn enclosed(input: &str) -> Option<&str> {
+    let inner = input.strip_prefix('(')?.strip_suffix(')')?;
+    let mut depth = 0;
+    let mut quote = None;
+    for ch in inner.chars() {
+        match ch {
+            '"' | '\'' if quote.is_none() => quote = Some(ch),
+            _ if quote == Some(ch) => quote = None,
+            '(' if quote.is_none() => depth += 1,
+            // `(a) OR (b)`: the first parenthesis closes before the end.
+            ')' if quote.is_none() && depth == 0 => return None,
+            ')' if quote.is_none() => depth -= 1,
+            _ => {}
+        }
+    }
+    Some(inner)
+}
+
+/// Parses a WHERE condition. OR binds loosest, then AND, then NOT, and
+/// parentheses group to any depth. NOT is pushed down by `Condition::negated`.
 fn parse_condition(input: &str) -> Result<Condition, DbError> {
     let input = input.trim();
-    if input.contains(" AND ") {
-        let parts = input.split(" AND ").collect::<Vec<_>>();
-        if parts.len() != 2 {
-            return Err(DbError::QueryError("Invalid AND condition syntax".to_string()));
+    let ors = keyword_positions(input, "OR");
+    if !ors.is_empty() {
+        let parts = split_at_keywords(input, &ors, 2).into_iter().map(parse_condition);
+        return parts.reduce(|l, r| Ok(Condition::Or(Box::new(l?), Box::new(r?)))).unwrap();
+    }
+    // Each BETWEEN takes the next AND as its own.
+    let mut betweens = keyword_positions(input, "BETWEEN").into_iter().peekable();
+    let mut open = 0;
+    let ands: Vec<usize> = keyword_positions(input, "AND")
+        .into_iter()
+        .filter(|&and| {
+            while betweens.next_if(|&b| b < and).is_some() {
+                open += 1;
+            }
+            open == 0 || {
+                open -= 1;
+                false
+            }
+        })
+        .collect();
+    if !ands.is_empty() {
+        let parts = split_at_keywords(input, &ands, 3).into_iter().map(parse_condition);
+        return parts.reduce(|l, r| Ok(Condition::And(Box::new(l?), Box::new(r?)))).unwrap();
+    }
+    if input.len() > 3
+        && input[..3].eq_ignore_ascii_case("NOT")
+        && input[3..].starts_with(|c: char| c.is_whitespace() || c == '(')
+    {
+        return Ok(parse_condition(&input[3..])?.negated());
+    }
+    match enclosed(input) {
+        Some(inner) => parse_condition(inner),
+        None => parse_predicate(input),
+    }
+}
+
+/// One comparison, such as `a = 1`, `a IS NULL` or `a NOT LIKE 'x%'`.
+fn parse_predicate(input: &str) -> Result<Condition, DbError> {
+    let words = input.splitn(3, char::is_whitespace).collect::<Vec<_>>();
+    if let [column, not, rest] = words.as_slice()
+        && not.eq_ignore_ascii_case("NOT")
+    {
+        let operator = rest.split(|c: char| c.is_whitespace() || c == '(').next().unwrap_or_default();
+        if ["LIKE", "REGEXP", "IN", "BETWEEN"].iter().any(|k| operator.eq_ignore_ascii_case(k)) {
+            return Ok(parse_predicate(&format!("{} {}", column, rest))?.negated());
+        }
+    }
+    if let Some(pos) = find_keyword(input, "REGEXP") {
+        let column = input[..pos].trim();
+        let pattern = match parse_value(input[pos + 8..].trim())? {
+            Value::String(pattern) if !column.is_empty() && !column.contains(char::is_whitespace) => pattern,
+            _ => return Err(DbError::QueryError(format!("Invalid REGEXP condition: {}", input))),
+        };
+        return Ok(Condition::Regexp(column.to_string(), Pattern::new(&pattern)?));
+    }
+    if let Some(pos) = find_keyword(input, "LIKE") {
+        let column = input[..pos].trim();
+        return match parse_value(input[pos + 6..].trim())? {
+            Value::String(pattern) if !column.is_empty() && !column.contains(char::is_whitespace) => {
+                Ok(Condition::Like(column.to_string(), pattern))
+            }
+            _ => Err(DbError::QueryError(format!("Invalid LIKE condition: {}", input))),
+        };
+    }
+    if let Some(pos) = find_keyword(input, "BETWEEN") {
+        let column = input[..pos].trim();
+        let bounds = &input[pos + 9..];
+        let Some(and) = bounds.to_ascii_uppercase().find(" AND ") else {
+            return Err(DbError::QueryError(format!("BETWEEN needs low AND high: {}", input)));
+        };
+        if column.is_empty() || column.contains(char::is_whitespace) {
+            return Err(DbError::QueryError(format!("Invalid BETWEEN condition: {}", input)));
         }
-        let left = parse_condition(parts[0])?;
-        let right = parse_condition(parts[1])?;
-        return Ok(Condition::And(Box::new(left), Box::new(right)));
+        let low = parse_value(bounds[..and].trim())?;
+        let high = parse_value(bounds[and + 5..].trim())?;
+        return Ok(Condition::Between(column.to_string(), low, high));
     }
-    if input.contains(" OR ") {
-        let parts = input.split(" OR ").collect::<Vec<_>>();
-        if parts.len() != 2 {
-            return Err(DbError::QueryError("Invalid OR condition syntax".to_string()));
+    if let Some(pos) = find_keyword(input, "IN") {
+        if input.starts_with('(') {
+            return parse_in_tuple(&input[..pos], &input[pos + 4..]);
+        }
+        let column = input[..pos].trim();
+        if column.is_empty() || column.contains(char::is_whitespace) {
+            return Err(DbError::QueryError(format!("Invalid IN condition: {}", input)));
+        }
+        if let Some(subquery) = parse_subquery(&input[pos + 4..])? {
+            return Ok(Condition::InSubquery(column.to_string(), Box::new(subquery)));
         }
-        let left = parse_condition(parts[0])?;
-        let right = parse_condition(parts[1])?;
-        return Ok(Condition::Or(Box::new(left), Box::new(right)));
+        let values = parenthesized(&input[pos + 4..])?
+            .iter()
+            .map(|v| parse_value(v))
+            .collect::<Result<Vec<_>, _>>()?;
+        return Ok(Condition::In(column.to_string(), values));
     }
 
     let parts = input.split_whitespace().collect::<Vec<_>>();
-    if parts.len() != 3 {
-        return Err(DbError::QueryError(format!(
-            "Invalid condition syntax: expected 3 parts, got {}",
-            parts.len()
-        )));
+    let keywords = parts.iter().map(|p| p.to_uppercase()).collect::<Vec<_>>();
+    match keywords.iter().map(|k| k.as_str()).collect::<Vec<_>>().as_slice() {
+        [_, "IS", "NULL"] => return Ok(Condition::IsNull(parts[0].to_string())),
+        [_, "IS", "NOT", "NULL"] => return Ok(Condition::IsNotNull(parts[0].to_string())),
+        _ => {}
     }
-    let column = parts[0].to_string();
-    let operator = parts[1];
-    let value = if parts[2].starts_with('"') && parts[2].ends_with('"') {
-        Value::String(parts[2][1..parts[2].len() - 1].to_string())
-    } else if parts[2].contains('.') {
-        Value::Float32(OrderedFloat(parts[2].parse::<f32>().map_err(|_| {
-            DbError::QueryError(format!("Invalid float value: {}", parts[2]))
-        })?))
-    } else {
-        Value::Int32(parts[2].parse::<i32>().map_err(|_| {
-            DbError::QueryError(format!("Invalid integer value: {}", parts[2]))
-        })?)
+    let (pos, symbol) = comparison_operator(input)?;
+    let op = Comparison::from_symbol(symbol).unwrap();
+    let (left, right) = (input[..pos].trim(), input[pos + symbol.len()..].trim());
+    if let Some(subquery) = parse_subquery(right)? {
+        return Ok(Condition::CompareSubquery(parse_expr(left)?, op, Box::new(subquery)));
+    }
+    if let Some(subquery) = parse_subquery(left)? {
+        return Ok(Condition::CompareSubquery(parse_expr(right)?, op.flipped(), Box::new(subquery)));
+    }
+    // A column against a value stays a plain predicate, which zone maps and indexes can prune with.
+    if is_identifier(left)
+        && let Ok(value) = parse_value(right)
+    {
+        return Ok(op.with_value(left.to_string(), value));
+    }
+    Ok(Condition::Compare(parse_expr(left)?, op, parse_expr(right)?))
+}
+
+/// The query of a parenthesized `(SELECT ...)`, which must read rows.
+fn parse_subquery(input: &str) -> Result<Option<Query>, DbError> {
+    let Some(inner) = enclosed(input.trim()).map(str::trim) else {
+        return Ok(None);
     };
+    if !inner.get(..6).is_some_and(|k| k.eq_ignore_ascii_case("SELECT")) {
+        return Ok(None);
+    }
+    match parse_query(inner)? {
+        query @ (Query::Select { .. } | Query::SelectAggregate { .. } | Query::Join { .. }) => Ok(Some(query)),
+        _ => Err(DbError::QueryError(format!("Unsupported subquery: {}", inner))),
+    }
+}
 
-    match operator {
-        "=" => Ok(Condition::Equal(column, value)),
-        ">" => Ok(Condition::GreaterThan(column, value)),
-        "<" => Ok(Condition::LessThan(column, value)),
-        "<=" => Ok(Condition::LessThanOrEqual(column, value)),
-        ">=" => Ok(Condition::GreaterThanOrEqual(column, value)),
-        _ => Err(DbError::QueryError(format!("Invalid operator: {}", operator))),
+/// Position and symbol of the comparison operator outside quotes and parentheses.
+fn comparison_operator(input: &str) -> Result<(usize, &'static str), DbError> {
+    let mut depth = 0i32;
+    let mut quote = None;
+    let mut found = None;
+    for (pos, ch) in input.char_indices() {
+        match ch {
+            '"' | '\'' if quote.is_none() => quote = Some(ch),
+            _ if quote == Some(ch) => quote = None,
+            _ if quote.is_some() => {}
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '=' | '<' | '>' | '!' if depth == 0 && found.is_none() => {
+                let symbol = ["<=", ">=", "!=", "<>", "=", "<", ">"]
+                    .into_iter()
+                    .find(|s| input[pos..].starts_with(s))
+                    .ok_or_else(|| DbError::QueryError(format!("Invalid operator in condition: {}", input)))?;
+                found = Some((pos, symbol));
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return Err(DbError::QueryError(format!("Unbalanced parentheses in condition: {}", input)));
     }
+    found.ok_or_else(|| DbError::QueryError(format!("Invalid condition syntax: no comparison in {}", input)))
 }
\ No newline at end of file
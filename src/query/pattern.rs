@@ -0,0 +1,87 @@
+use crate::types::DbError;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// A regular expression compiled once when the query is parsed and shared
+/// by every row it is matched against. Serialized as its source text.
+#[derive(Clone)]
+pub struct Pattern {
+    regex: Regex,
+}
+
+impl Pattern {
+    pub fn new(source: &str) -> Result<Self, DbError> {
+        Regex::new(source)
+            .map(|regex| Pattern { regex })
+            .map_err(|e| DbError::QueryError(format!("Invalid regular expression {}: {}", source, e)))
+    }
+
+    pub fn as_str(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// True if the pattern matches anywhere in `text`.
+    pub fn is_match(&self, text: &str) -> bool {
+        self.regex.is_match(text)
+    }
+}
+
+impl fmt::Debug for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Pattern({:?})", self.as_str())
+    }
+}
+
+impl Serialize for Pattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Pattern::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+/// SQL `LIKE` over the whole of `text`: `%` matches any run of characters
+/// and `_` exactly one.
+pub fn like_match(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) = (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // The last `%` seen and where in `text` it stopped matching; on a
+    // mismatch it takes one more character.
+    let mut wildcard = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '_' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '%' {
+            wildcard = Some((p, t));
+            p += 1;
+        } else if let Some((wp, wt)) = wildcard {
+            wildcard = Some((wp, wt + 1));
+            p = wp + 1;
+            t = wt + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '%')
+}
+
+/// The literal text every match of a `LIKE` pattern starts with, up to its
+/// first wildcard.
+pub fn like_prefix(pattern: &str) -> &str {
+    pattern.find(['%', '_']).map_or(pattern, |end| &pattern[..end])
+}
+
+/// Whether some string between `min` and `max` can match `pattern`. The
+/// strings starting with its prefix sort together, so a range that holds
+/// none lies wholly before or after them.
+pub fn like_may_match(pattern: &str, min: &str, max: &str) -> bool {
+    let prefix = like_prefix(pattern);
+    max >= prefix && (min <= prefix || min.starts_with(prefix))
+}
@@ -1,92 +1,734 @@
-use crate::query::{Aggregation, Condition, Query};
-use crate::schema::Table;
+use crate::query::batch::Batch;
+use crate::query::binder::bind_literals;
+use crate::query::cache::QueryCache;
+use crate::query::sketch::{HyperLogLog, TDigest};
+use crate::query::sort::order_selection;
+use crate::query::stream::{BlockReader, RowStream};
+use crate::query::system::{is_system_table, system_rows, system_table};
+use crate::query::explain::{condition_text, join_operator_text, ExplainFormat, PlanNode};
+use crate::query::expr::Expr;
+use crate::query::governor::Permit;
+use crate::query::join::{hash_join, join_keys, sort_merge_join};
+use crate::query::mask::{apply_masks, output_masks};
+use crate::query::pushdown;
+use crate::query::result::{result_columns, QueryResult};
+use crate::query::{
+    uses_indexes, Aggregation, Condition, FileFormat, GroupKey, Hint, JoinOperator, Query, SortOrder,
+};
+use crate::schema::statistics::estimate_join_rows;
+use crate::schema::{Schema, Table, Validation};
+use crate::storage::column::block_sampled;
+use crate::storage::csv::CsvLoadOptions;
 use crate::storage::index::Index;
+use crate::storage::settings::{Priority, Role, SETTING_NAMES};
+use crate::storage::writer::write_rows;
 use crate::storage::StorageManager;
-use crate::types::{DbError, Value};
+use crate::types::{Collation, DbError, Value};
 use crate::DataType;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 
 pub struct QueryEngine {
     storage: Arc<Mutex<StorageManager>>,
+    cache: Option<Arc<Mutex<QueryCache>>>,
+    /// Overrides the `priority` setting for this engine's reads.
+    priority: Option<Priority>,
 }
 
 impl QueryEngine {
     pub fn new(storage: Arc<Mutex<StorageManager>>) -> Self {
-        QueryEngine { storage }
+        QueryEngine {
+            storage,
+            cache: None,
+            priority: None,
+        }
+    }
+
+    pub fn with_cache(storage: Arc<Mutex<StorageManager>>, cache: Arc<Mutex<QueryCache>>) -> Self {
+        QueryEngine {
+            storage,
+            cache: Some(cache),
+            priority: None,
+        }
+    }
+
+    /// Runs this engine's reads at `priority` whatever the setting says.
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
     }
 
     pub fn execute(&mut self, query: Query) -> Result<Vec<Vec<Value>>, DbError> {
+        if let Query::AsOfSnapshot { name, query } = query {
+            return self.snapshot_engine(&name)?.execute(*query);
+        }
+        let query = self.run_subqueries(query)?;
+        let (query, masks) = {
+            let storage_guard = self.storage.lock().unwrap();
+            let query = bind_literals(query, storage_guard.schema())?;
+            let masks = match storage_guard.settings().role {
+                Role::Restricted => output_masks(&query, storage_guard.schema())?,
+                Role::Privileged => None,
+            };
+            (query, masks)
+        };
+        let _permit = self.admit(&query)?;
+        // Rows are cached as stored and masked on the way out.
+        let rows = self.execute_bound(query)?;
+        Ok(match masks {
+            Some(masks) => apply_masks(rows, &masks),
+            None => rows,
+        })
+    }
+
+    /// Runs the subqueries in `query`'s condition and puts their results in
+    /// their place, so the rest of the engine only sees plain predicates.
+    fn run_subqueries(&mut self, mut query: Query) -> Result<Query, DbError> {
+        let tables: Vec<Table> = {
+            let storage_guard = self.storage.lock().unwrap();
+            let schema = storage_guard.schema();
+            read_tables(&query).iter().filter_map(|t| schema.get_table(t).cloned()).collect()
+        };
+        match &mut query {
+            Query::Select { condition, .. }
+            | Query::SelectAggregate { condition, .. }
+            | Query::Join { condition, .. }
+            | Query::Update { condition, .. }
+            | Query::Delete { condition, .. } => {
+                if let Some(cond) = condition.take() {
+                    *condition = Some(self.materialize(cond, &tables)?);
+                }
+            }
+            Query::VerifyTable { condition, .. } => *condition = self.materialize(condition.clone(), &tables)?,
+            _ => {}
+        }
+        Ok(query)
+    }
+
+    fn materialize(&mut self, condition: Condition, tables: &[Table]) -> Result<Condition, DbError> {
+        let and = |l, r| Condition::And(Box::new(l), Box::new(r));
+        let or = |l, r| Condition::Or(Box::new(l), Box::new(r));
+        Ok(match condition {
+            Condition::InSubquery(col, query) => Condition::In(col, self.subquery_values(*query)?.0),
+            Condition::Not(inner) => match *inner {
+                // SQL's NOT IN: no row matches when the subquery returned a
+                // NULL, and every row, NULL or not, when it returned nothing.
+                Condition::InSubquery(col, query) => match self.subquery_values(*query)? {
+                    (_, true) => Condition::In(col, Vec::new()),
+                    (values, false) if values.is_empty() => {
+                        or(Condition::IsNull(col.clone()), Condition::IsNotNull(col))
+                    }
+                    (values, false) => Condition::Not(Box::new(Condition::In(col, values))),
+                },
+                inner => Condition::Not(Box::new(self.materialize(inner, tables)?)),
+            },
+            Condition::CompareSubquery(left, op, query) => {
+                let rows = self.subquery_rows(*query)?;
+                if rows.len() > 1 {
+                    return Err(DbError::QueryError(format!(
+                        "Subquery returned {} rows where one value was expected",
+                        rows.len()
+                    )));
+                }
+                let value = rows.into_iter().next().map_or(Value::Null, |mut row| row.remove(0));
+                // A column against a value of its own type can prune; anything
+                // else compares row by row, which promotes numbers.
+                let column_type = |col: &str| {
+                    let (table, name) = col.rsplit_once('.').map_or((None, col), |(t, c)| (Some(t), c));
+                    let mut tables = tables.iter().filter(|t| table.is_none_or(|table| t.name == table));
+                    tables.find_map(|t| t.get_column(name)).map(|c| c.data_type.clone())
+                };
+                let typed = match &left {
+                    Expr::Column(col) if !value.is_null() => {
+                        column_type(col).and_then(|data_type| value.clone().coerce(&data_type).ok())
+                    }
+                    _ => None,
+                };
+                match (left, typed) {
+                    (Expr::Column(col), Some(value)) => op.with_value(col, value),
+                    (left, _) => Condition::Compare(left, op, Expr::Literal(value)),
+                }
+            }
+            Condition::And(left, right) => and(self.materialize(*left, tables)?, self.materialize(*right, tables)?),
+            Condition::Or(left, right) => or(self.materialize(*left, tables)?, self.materialize(*right, tables)?),
+            other => other,
+        })
+    }
+
+    /// A subquery's rows, which must have a single column.
+    fn subquery_rows(&mut self, query: Query) -> Result<Vec<Vec<Value>>, DbError> {
+        let columns = result_columns(&query, self.storage.lock().unwrap().schema()).len();
+        if columns != 1 {
+            return Err(DbError::QueryError(format!("Subquery must return one column, not {}", columns)));
+        }
+        self.execute(query)
+    }
+
+    /// The distinct non-NULL values of an IN subquery, and whether it also
+    /// returned a NULL.
+    fn subquery_values(&mut self, query: Query) -> Result<(Vec<Value>, bool), DbError> {
+        let mut seen = HashSet::new();
+        let mut has_null = false;
+        let mut values = Vec::new();
+        for value in self.subquery_rows(query)?.into_iter().flatten() {
+            if value.is_null() {
+                has_null = true;
+            } else if seen.insert(value.clone()) {
+                values.push(value);
+            }
+        }
+        Ok((values, has_null))
+    }
+
+    /// Queues a read until the governor admits it; the permit is held while it
+    /// runs. Other statements are not governed.
+    fn admit(&self, query: &Query) -> Result<Option<Permit>, DbError> {
+        let tables = read_tables(query);
+        if tables.is_empty() {
+            return Ok(None);
+        }
+        let (governor, bytes, limits, priority) = {
+            let storage_guard = self.storage.lock().unwrap();
+            let condition = match query {
+                Query::Select { condition, .. } | Query::SelectAggregate { condition, .. } => condition.as_ref(),
+                _ => None,
+            };
+            let bytes = tables.iter().map(|t| storage_guard.scan_bytes(t, condition)).sum();
+            let settings = storage_guard.settings();
+            (storage_guard.governor(), bytes, settings.query_limits(), self.priority.unwrap_or(settings.priority))
+        };
+        // The storage lock is released while waiting so running reads can finish.
+        governor.admit(bytes, limits, priority).map(Some)
+    }
+
+    fn execute_bound(&mut self, query: Query) -> Result<Vec<Vec<Value>>, DbError> {
+        let tables = read_tables(&query);
+        if tables.is_empty() || self.cache.is_none() || tables.iter().any(|t| is_system_table(t)) {
+            return self.execute_uncached(query);
+        }
+
+        // The setting changes which rows a LIMIT keeps, so it is part of the key.
+        let key = format!("{}:{}", self.deterministic_order(), serde_json::to_string(&query)?);
+        let versions = {
+            let storage_guard = self.storage.lock().unwrap();
+            // Files of an external table change without any write bumping its version.
+            let external = |t: &String| storage_guard.schema().get_table(t).is_some_and(|t| t.location.is_some());
+            if tables.iter().any(external) {
+                drop(storage_guard);
+                return self.execute_uncached(query);
+            }
+            tables
+                .iter()
+                .map(|t| (t.clone(), storage_guard.table_version(t)))
+                .collect::<Vec<_>>()
+        };
+        let cache = self.cache.clone().unwrap();
+        if let Some(rows) = cache.lock().unwrap().get(&key, &versions) {
+            return Ok(rows);
+        }
+        let rows = self.execute_uncached(query)?;
+        cache.lock().unwrap().put(key, versions, rows.clone());
+        Ok(rows)
+    }
+
+    /// The definition of a stored or system table.
+    fn table_def(&self, table: &str) -> Result<Table, DbError> {
+        self.storage
+            .lock()
+            .unwrap()
+            .schema()
+            .get_table(table)
+            .cloned()
+            .or_else(|| system_table(table))
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))
+    }
+
+    fn deterministic_order(&self) -> bool {
+        self.storage.lock().unwrap().settings().deterministic_order
+    }
+
+    /// An engine over the tables as snapshot `name` recorded them.
+    fn snapshot_engine(&self, name: &str) -> Result<QueryEngine, DbError> {
+        let snapshot = self.storage.lock().unwrap().open_named_snapshot(name)?;
+        Ok(QueryEngine {
+            priority: self.priority,
+            ..QueryEngine::new(Arc::new(Mutex::new(snapshot)))
+        })
+    }
+
+    /// Executes `query` and names and types the columns of the rows it returns.
+    pub fn execute_with_metadata(&mut self, query: Query) -> Result<QueryResult, DbError> {
+        // Named from the snapshot's schema, which may differ from the live one.
+        if let Query::AsOfSnapshot { name, query } = query {
+            return self.snapshot_engine(&name)?.execute_with_metadata(*query);
+        }
+        // Resolved first: DROP and DELETE change the schema the names come from.
+        let columns = result_columns(&query, self.storage.lock().unwrap().schema());
+        let rows = self.execute(query)?;
+        Ok(QueryResult::new(columns, rows))
+    }
+
+    /// Executes `query`, returning its rows as a stream. A SELECT of a stored
+    /// table without ORDER BY or DISTINCT reads one block per step from a
+    /// snapshot taken now, so later writes don't show up in it. Holding a
+    /// block at a time, it isn't queued by the governor or cached. Any other
+    /// statement runs in full first.
+    pub fn execute_streaming(&mut self, query: Query) -> Result<RowStream, DbError> {
+        if let Query::AsOfSnapshot { name, query } = query {
+            return self.snapshot_engine(&name)?.execute_streaming(*query);
+        }
+        let streams = match &query {
+            Query::Select { table, order_by, distinct, .. } => {
+                let storage_guard = self.storage.lock().unwrap();
+                order_by.is_empty()
+                    && !distinct
+                    && !storage_guard.settings().deterministic_order
+                    && storage_guard.schema().get_table(table).is_some_and(|t| t.location.is_none())
+            }
+            _ => false,
+        };
+        if !streams {
+            return Ok(RowStream::from(self.execute_with_metadata(query)?));
+        }
+        let result_columns = result_columns(&query, self.storage.lock().unwrap().schema());
+        let query = self.run_subqueries(query)?;
+        let storage_guard = self.storage.lock().unwrap();
+        let query = bind_literals(query, storage_guard.schema())?;
+        let masks = match storage_guard.settings().role {
+            Role::Restricted => output_masks(&query, storage_guard.schema())?,
+            Role::Privileged => None,
+        };
+        let Query::Select { table, columns, condition, sample, limit, offset, hints, .. } = query else {
+            unreachable!("only a SELECT streams")
+        };
+        let table_def = storage_guard
+            .schema()
+            .get_table(&table)
+            .cloned()
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?;
+        let columns = if columns.is_empty() {
+            table_def.columns.iter().map(|c| Expr::Column(c.name.clone())).collect()
+        } else {
+            columns
+        };
+        let required = select_columns(&table_def, &columns, condition.as_ref(), &[])?;
+        let keep = storage_guard.block_mask_with(&table, condition.as_ref(), uses_indexes(&hints, &table));
+        let blocks: Vec<usize> = (0..=storage_guard.block_count(&table))
+            .filter(|&i| sample.is_none_or(|percent| block_sampled(i, percent)))
+            .filter(|&i| keep.as_ref().is_none_or(|k| k.get(i).copied().unwrap_or(true)))
+            .collect();
+        let reader = BlockReader {
+            storage: storage_guard.snapshot()?,
+            table: table_def,
+            columns,
+            required,
+            condition,
+            blocks: blocks.into_iter(),
+            skip: offset.unwrap_or(0),
+            limit,
+            masks,
+            ready: Vec::new().into_iter(),
+        };
+        Ok(RowStream::blocks(result_columns, reader))
+    }
+
+    /// Estimated output rows for a SELECT or JOIN using ANALYZE statistics;
+    /// `None` when the tables involved have not been analyzed.
+    pub fn estimate_rows(&self, query: &Query) -> Result<Option<f64>, DbError> {
+        let storage_guard = self.storage.lock().unwrap();
+        match query {
+            Query::Select { table, condition, .. } | Query::SelectAggregate { table, condition, .. } => {
+                Ok(storage_guard
+                    .table_statistics(table)?
+                    .map(|stats| stats.estimate_rows(condition.as_ref())))
+            }
+            Query::Join {
+                left_table,
+                right_table,
+                left_column,
+                right_column,
+                condition,
+                ..
+            } => {
+                let left = storage_guard.table_statistics(left_table)?;
+                let right = storage_guard.table_statistics(right_table)?;
+                let schema = storage_guard.schema();
+                let (Some(l), Some(r), Some(left_def), Some(right_def)) =
+                    (left, right, schema.get_table(left_table), schema.get_table(right_table))
+                else {
+                    return Ok(None);
+                };
+                let [left_filter, right_filter] = join_side_filters(condition.as_ref(), [left_def, right_def]);
+                Ok(Some(estimate_join_rows(
+                    (&l, left_column, left_filter.as_ref()),
+                    (&r, right_column, right_filter.as_ref()),
+                )))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn execute_uncached(&mut self, query: Query) -> Result<Vec<Vec<Value>>, DbError> {
         match query {
             Query::Select {
                 table,
                 columns,
+                distinct,
                 condition,
+                sample,
+                order_by,
+                limit,
+                offset,
+                hints,
             } => {
+                let table_columns: Vec<String> = self.table_def(&table)?.columns.into_iter().map(|c| c.name).collect();
                 let columns = if columns.is_empty() {
-                    let storage_guard = self.storage.lock().unwrap();
-                    storage_guard
-                        .schema()
-                        .get_table(&table)
-                        .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?
-                        .columns
-                        .iter()
-                        .map(|c| c.name.clone())
-                        .collect()
+                    table_columns.iter().map(|c| Expr::Column(c.clone())).collect()
                 } else {
                     columns
                 };
-                self.execute_select(&table, &columns, condition)
+                let mut paging = Paging { order_by, limit, offset, distinct };
+                // Ordering on every column fixes the rows a LIMIT keeps, not just their order.
+                if paging.order_by.is_empty() && self.deterministic_order() {
+                    paging.order_by = table_columns.into_iter().map(|c| (c, SortOrder::Asc)).collect();
+                }
+                let use_indexes = uses_indexes(&hints, &table);
+                self.execute_select(&table, &columns, condition, sample, &paging, use_indexes)
             }
             Query::SelectAggregate {
                 table,
                 aggregations,
                 condition,
-            } => self.execute_aggregate(&table, &aggregations, condition),
+                sample,
+                group_by,
+                hints,
+            } => {
+                let use_indexes = uses_indexes(&hints, &table);
+                self.execute_aggregate(&table, &aggregations, condition, sample, &group_by, use_indexes)
+            }
             Query::Join {
                 left_table,
                 right_table,
                 left_column,
                 right_column,
+                operator,
                 columns,
                 condition,
-            } => self.execute_join(
-                &left_table,
-                &right_table,
-                &left_column,
-                &right_column,
-                &columns,
-                condition,
-            ),
-            Query::Insert { table, values } => {
-                self.storage.lock().unwrap().insert_row(&table, values)?;
-                Ok(vec![])
+                hints,
+            } => {
+                let mut rows = self.execute_join(
+                    &left_table,
+                    &right_table,
+                    &left_column,
+                    &right_column,
+                    operator,
+                    &columns,
+                    condition,
+                    &hints,
+                )?;
+                if self.deterministic_order() {
+                    rows.sort();
+                }
+                Ok(rows)
+            }
+            Query::Insert { table, values, returning } => {
+                let mut storage_guard = self.storage.lock().unwrap();
+                let returned = project_returning(storage_guard.schema(), &table, &returning, vec![values.clone()])?;
+                storage_guard.insert_row(&table, values)?;
+                Ok(returned)
+            }
+            Query::InsertRows { table, rows, returning, .. } => {
+                let mut storage_guard = self.storage.lock().unwrap();
+                let returned = project_returning(storage_guard.schema(), &table, &returning, rows.clone())?;
+                storage_guard.insert_rows(&table, rows)?;
+                Ok(returned)
             }
             Query::CreateTable { table, columns } => {
+                // Reject defaults that can never produce a value of the column's type.
+                for col in columns.iter().filter(|c| c.default.is_some()) {
+                    col.default_value()?;
+                }
                 let table_def = Table {
                     name: table.clone(),
-                    columns: columns
-                        .into_iter()
-                        .map(|(name, data_type)| crate::schema::Column { name, data_type })
-                        .collect(),
+                    columns,
                     row_count: 0,
+                    location: None,
+                    validation: Validation::Strict,
+                    next_column_id: 0,
                 };
                 self.storage.lock().unwrap().create_table(&table_def)?;
                 Ok(vec![])
             }
-            Query::Delete { table, condition } => {
-                self.storage.lock().unwrap().delete_rows(&table, condition.as_ref())?;
+            Query::CreateExternalTable { table, columns, location } => {
+                let table_def = Table {
+                    name: table,
+                    columns,
+                    row_count: 0,
+                    location: Some(location),
+                    validation: Validation::Strict,
+                    next_column_id: 0,
+                };
+                self.storage.lock().unwrap().create_table(&table_def)?;
                 Ok(vec![])
             }
+            Query::Update {
+                table,
+                assignments,
+                condition,
+            } => {
+                let rows = self
+                    .storage
+                    .lock()
+                    .unwrap()
+                    .update_rows(&table, &assignments, condition.as_ref())?;
+                Ok(vec![vec![Value::String(table), Value::Int32(rows as i32)]])
+            }
+            Query::Delete {
+                table,
+                condition,
+                returning,
+            } => {
+                let mut storage_guard = self.storage.lock().unwrap();
+                // Reject a bad RETURNING list before any rows are removed.
+                project_returning(storage_guard.schema(), &table, &returning, Vec::new())?;
+                let removed = storage_guard.delete_rows(&table, condition.as_ref())?;
+                project_returning(storage_guard.schema(), &table, &returning, removed)
+            }
             Query::DropTable { table } => {
                 self.storage.lock().unwrap().drop_table(&table)?;
                 Ok(vec![])
             }
+            Query::UndropTable { table } => {
+                self.storage.lock().unwrap().undrop_table(&table)?;
+                Ok(vec![])
+            }
+            Query::AlterTable { table, action } => {
+                self.storage.lock().unwrap().alter_table(&table, action)?;
+                Ok(vec![])
+            }
+            Query::ShowTableSize { table } => {
+                Ok(self.storage.lock().unwrap().table_stats(&table)?.to_rows())
+            }
+            Query::ShowDatabaseSize => {
+                let stats = self.storage.lock().unwrap().database_stats()?;
+                Ok(stats
+                    .iter()
+                    .map(|t| {
+                        vec![
+                            Value::String(t.table.clone()),
+                            Value::Int32(t.block_count() as i32),
+                            Value::Int64(t.on_disk_bytes() as i64),
+                            Value::Int64(t.compressed_bytes() as i64),
+                            Value::Int64(t.uncompressed_bytes() as i64),
+                            Value::Int64(t.index_bytes() as i64),
+                        ]
+                    })
+                    .collect())
+            }
+            Query::ShowStorageReport { table } => {
+                let report = self.storage.lock().unwrap().storage_report(table.as_deref())?;
+                Ok(report
+                    .iter()
+                    .map(|usage| {
+                        vec![
+                            Value::String(usage.table.clone()),
+                            Value::String(usage.column.clone()),
+                            Value::String(usage.codec.to_string()),
+                            Value::Int32(usage.blocks as i32),
+                            Value::Int32(usage.rows as i32),
+                            Value::Int64(usage.raw_bytes as i64),
+                            Value::Int64(usage.compressed_bytes as i64),
+                            Value::Float32(ordered_float::OrderedFloat(usage.ratio() as f32)),
+                            Value::Int64(usage.average_block_bytes() as i64),
+                        ]
+                    })
+                    .collect())
+            }
+            Query::ShowIndexes { table } => {
+                let stats = self.storage.lock().unwrap().index_stats(table.as_deref())?;
+                Ok(stats
+                    .iter()
+                    .map(|index| {
+                        vec![
+                            Value::String(index.table.clone()),
+                            Value::String(index.column.clone()),
+                            Value::Int32(index.keys as i32),
+                            Value::Int32(index.entries as i32),
+                            Value::Int64(index.disk_bytes as i64),
+                            Value::Boolean(index.unsaved),
+                        ]
+                    })
+                    .collect())
+            }
+            Query::ShowTables => {
+                let storage_guard = self.storage.lock().unwrap();
+                let mut tables: Vec<&Table> = storage_guard.schema().tables().collect();
+                tables.sort_by(|a, b| a.name.cmp(&b.name));
+                Ok(tables
+                    .iter()
+                    .map(|t| {
+                        vec![
+                            Value::String(t.name.clone()),
+                            Value::Int32(t.columns.len() as i32),
+                            Value::Int32(t.row_count as i32),
+                        ]
+                    })
+                    .collect())
+            }
+            Query::Backup { path } => {
+                // Only taking the image holds the storage lock, not copying the column data.
+                let image = self.storage.lock().unwrap().begin_backup(&path)?;
+                Ok(vec![image.finish()?.to_row(path)])
+            }
+            Query::ExportTable { table, path } => {
+                let rows = self.storage.lock().unwrap().export_table(&table, &path)?;
+                Ok(vec![vec![Value::String(table), Value::Int32(rows as i32)]])
+            }
+            Query::ImportTable { path } => {
+                let mut storage_guard = self.storage.lock().unwrap();
+                let table = storage_guard.import_table(&path)?;
+                let rows = storage_guard.row_count(&table).unwrap_or(0);
+                Ok(vec![vec![Value::String(table), Value::Int32(rows as i32)]])
+            }
+            Query::CopyFrom { table, path, format } => {
+                let mut storage_guard = self.storage.lock().unwrap();
+                let options = CsvLoadOptions {
+                    threads: storage_guard.settings().parallelism,
+                    ..Default::default()
+                };
+                let rows = match format {
+                    FileFormat::Csv => storage_guard.load_csv(&table, &path, &options)?,
+                    FileFormat::Json => {
+                        return Err(DbError::QueryError("COPY FROM supports only the csv format".to_string()));
+                    }
+                };
+                Ok(vec![vec![Value::String(table), Value::Int32(rows as i32)]])
+            }
+            Query::CopyTo { query, path, format } => {
+                let result = self.execute_with_metadata(*query)?;
+                let columns: Vec<String> = result.columns.into_iter().map(|c| c.name).collect();
+                let rows = write_rows(&path, format, &columns, result.rows)?;
+                Ok(vec![vec![Value::String(path), Value::Int32(rows as i32)]])
+            }
+            Query::Explain { query, analyze, format } => {
+                let plan = self.explain_plan(*query, analyze)?;
+                Ok(match format {
+                    ExplainFormat::Text => plan.to_text().into_iter().map(|line| vec![Value::String(line)]).collect(),
+                    ExplainFormat::Json => vec![vec![Value::String(serde_json::to_string(&plan)?)]],
+                })
+            }
+            Query::Set { name, value } => {
+                self.storage.lock().unwrap().set_setting(&name, &value)?;
+                Ok(vec![])
+            }
+            Query::ShowSettings => {
+                let storage_guard = self.storage.lock().unwrap();
+                let settings = storage_guard.settings();
+                Ok(SETTING_NAMES
+                    .iter()
+                    .map(|name| {
+                        vec![
+                            Value::String(name.to_string()),
+                            Value::String(settings.get(name).unwrap_or_default()),
+                            Value::String(settings.source(name).to_string()),
+                        ]
+                    })
+                    .collect())
+            }
+            Query::Cleanup { dry_run } => {
+                let storage_guard = self.storage.lock().unwrap();
+                let (files, action) = if dry_run {
+                    (storage_guard.find_orphans()?, "orphaned")
+                } else {
+                    (storage_guard.remove_orphans()?, "removed")
+                };
+                Ok(files
+                    .into_iter()
+                    .map(|f| vec![Value::String(f), Value::String(action.to_string())])
+                    .collect())
+            }
+            Query::Analyze { table } => {
+                let stats = self.storage.lock().unwrap().analyze_table(&table)?;
+                let table_def = self
+                    .storage
+                    .lock()
+                    .unwrap()
+                    .schema()
+                    .get_table(&table)
+                    .cloned()
+                    .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?;
+                Ok(table_def
+                    .columns
+                    .iter()
+                    .map(|col| {
+                        let col_stats = &stats.columns[&col.name];
+                        let empty = Value::String(String::new());
+                        vec![
+                            Value::String(col.name.clone()),
+                            Value::Int32(col_stats.distinct as i32),
+                            col_stats.min.clone().unwrap_or_else(|| empty.clone()),
+                            col_stats.max.clone().unwrap_or(empty),
+                        ]
+                    })
+                    .collect())
+            }
+            Query::VerifyTable { table, condition, limit } => self.execute_verify(&table, &condition, limit),
+            Query::CreateSnapshot { name } => {
+                self.storage.lock().unwrap().create_named_snapshot(&name)?;
+                Ok(vec![])
+            }
+            Query::DropSnapshot { name } => {
+                self.storage.lock().unwrap().drop_named_snapshot(&name)?;
+                Ok(vec![])
+            }
+            Query::CreateJob { name, every, statement } => {
+                self.storage.lock().unwrap().create_job(&name, every, &statement)?;
+                Ok(vec![])
+            }
+            Query::DropJob { name } => {
+                self.storage.lock().unwrap().drop_job(&name)?;
+                Ok(vec![])
+            }
+            Query::AsOfSnapshot { name, query } => self.snapshot_engine(&name)?.execute(*query),
+            Query::Compact { table } => {
+                let report = self.storage.lock().unwrap().compact_table(&table)?;
+                Ok(vec![vec![
+                    Value::String(table),
+                    Value::Int32(report.blocks_before as i32),
+                    Value::Int32(report.blocks_after as i32),
+                    Value::Int32(report.bytes_before as i32),
+                    Value::Int32(report.bytes_after as i32),
+                ]])
+            }
+            Query::SalvageTable { table, target } => {
+                let report = self.storage.lock().unwrap().salvage_table(&table, &target)?;
+                Ok(vec![vec![
+                    Value::String(target),
+                    Value::Int32(report.rows as i32),
+                    Value::Int32(report.skipped_blocks as i32),
+                ]])
+            }
+            Query::Preload { table, columns } => {
+                let report = self.storage.lock().unwrap().preload(&table, &columns)?;
+                Ok(vec![vec![
+                    Value::String(table),
+                    Value::Int32(report.columns as i32),
+                    Value::Int32(report.blocks as i32),
+                    Value::Int32(report.cached_bytes as i32),
+                ]])
+            }
+            Query::Flush => {
+                let mut storage_guard = self.storage.lock().unwrap();
+                storage_guard.ensure_writable()?;
+                Ok(vec![vec![Value::Int32(storage_guard.flush_all()? as i32)]])
+            }
             Query::StartTransaction | Query::Commit | Query::Rollback => {
                 Ok(vec![])
             }
             Query::MakeIndex { table, column } => {
                 let mut storage_guard = self.storage.lock().unwrap();
+                storage_guard.ensure_writable()?;
+                storage_guard.ensure_stored(&table)?;
                 let table_def = storage_guard
                     .schema()
                     .get_table(&table)
@@ -97,14 +739,18 @@ impl QueryEngine {
                     .get_column(&column)
                     .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table, column)))?;
                 
-                let index_path = format!("{}/indexes/{}_{}.idx", storage_guard.data_dir(), table, column);
-                let mut index = Index::new(&index_path, col_def.data_type.clone())?;
+                let index_path =
+                    format!("{}/{}", storage_guard.data_dir(), storage_guard.layout().index_file(&table, col_def));
+                let mut index = Index::new(&index_path, col_def.data_type.clone(), col_def.collation)?;
                 
-                // Populate the index with existing data
-                let values = storage_guard.read_column(&table, &column, None)?;
-                if !values.is_empty() {
-                    index.append(&values, 0)?;
-                }
+                // Index the flushed blocks; pending rows are indexed when they are flushed.
+                let storage = &mut *storage_guard;
+                let col_store = storage
+                    .columns
+                    .get(&table)
+                    .and_then(|cols| cols.get(&column))
+                    .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table, column)))?;
+                col_store.reindex(&mut index, &storage.buffer)?;
                 
                 storage_guard
                     .indexes
@@ -116,6 +762,13 @@ impl QueryEngine {
             }
             Query::DropIndex { table, column } => {
                 let mut storage_guard = self.storage.lock().unwrap();
+                storage_guard.ensure_writable()?;
+                if storage_guard.schema().get_table(&table).is_some_and(|t| t.is_unique(&column)) {
+                    return Err(DbError::InvalidData(format!(
+                        "Index on {}.{} enforces its uniqueness and cannot be dropped",
+                        table, column
+                    )));
+                }
                 let table_indexes = storage_guard
                     .indexes
                     .get_mut(&table)
@@ -129,9 +782,12 @@ impl QueryEngine {
                 index.clear()?;
                 
                 // Remove the index file
-                let index_path = format!("{}/indexes/{}_{}.idx", storage_guard.data_dir(), table, column);
-                if std::path::Path::new(&index_path).exists() {
-                    std::fs::remove_file(&index_path)?;
+                if let Some(col) = storage_guard.schema().get_table(&table).and_then(|t| t.get_column(&column)) {
+                    let index_file = storage_guard.layout().index_file(&table, col);
+                    let index_path = format!("{}/{}", storage_guard.data_dir(), index_file);
+                    if std::path::Path::new(&index_path).exists() {
+                        std::fs::remove_file(&index_path)?;
+                    }
                 }
                 
                 Ok(vec![])
@@ -139,68 +795,280 @@ impl QueryEngine {
         }
     }
 
-    fn execute_select(
+    /// The operators `query` runs as, estimated from table sizes and ANALYZE
+    /// statistics. With `analyze` the query runs, and so does the input of each
+    /// filter and join, to count the rows every operator produced.
+    fn explain_plan(&mut self, query: Query, analyze: bool) -> Result<PlanNode, DbError> {
+        let query = bind_literals(query, self.storage.lock().unwrap().schema())?;
+        let output = result_columns(&query, self.storage.lock().unwrap().schema());
+        let output = output.iter().map(|c| c.name.as_str()).collect::<Vec<_>>().join(", ");
+        let returned = if analyze { Some(self.execute(query.clone())?.len()) } else { None };
+        let estimated = self.estimate_rows(&query)?;
+
+        let plan = match &query {
+            Query::Select {
+                table,
+                distinct,
+                condition,
+                sample,
+                order_by,
+                limit,
+                offset,
+                hints,
+                ..
+            } => {
+                let use_indexes = uses_indexes(hints, table);
+                let mut node = self.plan_filter(table, condition.as_ref(), *sample, use_indexes, estimated, analyze)?;
+                if *distinct {
+                    node = plan_parent("Distinct", output.clone(), node);
+                    node.estimated_rows = None;
+                }
+                if !order_by.is_empty() {
+                    let keys = order_by.iter().map(|(col, order)| match order {
+                        SortOrder::Asc => format!("{} ASC", col),
+                        SortOrder::Desc => format!("{} DESC", col),
+                    });
+                    node = plan_parent("Sort", keys.collect::<Vec<_>>().join(", "), node);
+                }
+                if limit.is_some() || offset.is_some() {
+                    let skip = offset.unwrap_or(0);
+                    let mut detail = limit.map_or(String::new(), |n| format!("LIMIT {}", n));
+                    if skip > 0 {
+                        detail = format!("{} OFFSET {}", detail, skip).trim_start().to_string();
+                    }
+                    node = plan_parent("Limit", detail, node);
+                    node.estimated_rows = node.estimated_rows.map(|rows| {
+                        let rows = (rows - skip as f64).max(0.0);
+                        limit.map_or(rows, |n| rows.min(n as f64))
+                    });
+                    node.actual_rows = returned;
+                }
+                plan_parent("Project", output, node)
+            }
+            Query::SelectAggregate {
+                table,
+                aggregations,
+                condition,
+                sample,
+                group_by,
+                hints,
+            } => {
+                let use_indexes = uses_indexes(hints, table);
+                let node = self.plan_filter(table, condition.as_ref(), *sample, use_indexes, estimated, analyze)?;
+                let mut node = plan_parent("Aggregate", output, node);
+                let single_row = group_by.is_empty() && !matches!(aggregations.as_slice(), [Aggregation::Histogram(..)]);
+                node.estimated_rows = single_row.then_some(1.0);
+                node
+            }
+            Query::Join {
+                left_table,
+                right_table,
+                left_column,
+                right_column,
+                operator,
+                condition,
+                hints,
+                ..
+            } => {
+                let strategy = if *operator == JoinOperator::Eq && !hints.contains(&Hint::MergeJoin) {
+                    "Hash Join"
+                } else {
+                    "Merge Join"
+                };
+                let mut node = PlanNode::new(strategy);
+                node.detail = Some(format!(
+                    "{}.{} {} {}.{}",
+                    left_table,
+                    left_column,
+                    join_operator_text(*operator),
+                    right_table,
+                    right_column
+                ));
+                node.children = vec![
+                    self.plan_scan(left_table, None, None, true, analyze)?,
+                    self.plan_scan(right_table, None, None, true, analyze)?,
+                ];
+                if let Some(cond) = condition {
+                    let unfiltered = Query::Join {
+                        left_table: left_table.clone(),
+                        right_table: right_table.clone(),
+                        left_column: left_column.clone(),
+                        right_column: right_column.clone(),
+                        operator: *operator,
+                        columns: vec![Expr::Column(format!("{}.{}", left_table, left_column))],
+                        condition: None,
+                        hints: hints.clone(),
+                    };
+                    node.estimated_rows = self.estimate_rows(&unfiltered)?;
+                    node.actual_rows = if analyze { Some(self.execute(unfiltered)?.len()) } else { None };
+                    let mut filter = plan_parent("Filter", String::new(), node);
+                    filter.predicate = Some(condition_text(cond));
+                    filter.estimated_rows = None;
+                    filter.actual_rows = returned;
+                    node = filter;
+                } else {
+                    node.estimated_rows = estimated;
+                    node.actual_rows = returned;
+                }
+                plan_parent("Project", output, node)
+            }
+            _ => return Err(DbError::QueryError("EXPLAIN supports SELECT and JOIN queries".to_string())),
+        };
+        Ok(PlanNode { actual_rows: returned, ..plan })
+    }
+
+    /// A scan of `table`, under a filter when there is a condition.
+    fn plan_filter(
         &mut self,
         table: &str,
-        columns: &[String],
-        condition: Option<Condition>,
-    ) -> Result<Vec<Vec<Value>>, DbError> {
-        let table_def = {
-            let storage_guard = self.storage.lock().unwrap();
-            storage_guard
-                .schema()
-                .get_table(table)
-                .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?
-                .clone()
+        condition: Option<&Condition>,
+        sample: Option<f64>,
+        use_indexes: bool,
+        estimated: Option<f64>,
+        analyze: bool,
+    ) -> Result<PlanNode, DbError> {
+        let scan = self.plan_scan(table, condition, sample, use_indexes, analyze)?;
+        let Some(cond) = condition else {
+            return Ok(scan);
         };
-
-        for col in columns {
-            if !table_def.columns.iter().any(|c| c.name == *col) {
-                return Err(DbError::InvalidData(format!("Column {}.{} not found", table, col)));
+        let mut filter = plan_parent("Filter", String::new(), scan);
+        filter.predicate = Some(condition_text(cond));
+        filter.estimated_rows = estimated;
+        filter.actual_rows = None;
+        if analyze {
+            let count = Query::SelectAggregate {
+                table: table.to_string(),
+                aggregations: vec![Aggregation::Count],
+                condition: Some(cond.clone()),
+                sample,
+                group_by: Vec::new(),
+                hints: Vec::new(),
+            };
+            if let Some(Value::Int32(n)) = self.execute(count)?.first().and_then(|row| row.first()) {
+                filter.actual_rows = Some(*n as usize);
             }
         }
+        Ok(filter)
+    }
 
-        let mut required_columns = columns.to_vec();
-        if let Some(ref cond) = condition {
-            let condition_columns = crate::query::collect_condition_columns(cond);
-            for col in condition_columns {
-                if !table_def.columns.iter().any(|c| c.name == col) {
-                    return Err(DbError::InvalidData(format!("Column {}.{} not found in condition", table, col)));
-                }
-                if !required_columns.contains(&col) {
-                    required_columns.push(col);
-                }
+    /// A full scan, or an index scan when an index on a condition column
+    /// narrows the blocks read. Zone maps prune blocks either way.
+    fn plan_scan(
+        &self,
+        table: &str,
+        condition: Option<&Condition>,
+        sample: Option<f64>,
+        use_indexes: bool,
+        analyze: bool,
+    ) -> Result<PlanNode, DbError> {
+        let storage_guard = self.storage.lock().unwrap();
+        let rows = storage_guard
+            .schema()
+            .get_table(table)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?
+            .row_count as f64;
+        let blocks = storage_guard.block_scan(table, condition, use_indexes);
+        drop(storage_guard);
+        let mut scan = PlanNode::new("Scan");
+        let mut detail = Vec::new();
+        if let Some(blocks) = blocks {
+            if !blocks.indexes.is_empty() {
+                scan.operator = "Index Scan".to_string();
+                detail.push(format!("index on {}", blocks.indexes.join(", ")));
             }
+            scan.blocks_read = Some(blocks.read_blocks);
+            scan.blocks_total = Some(blocks.total_blocks);
         }
+        detail.extend(sample.map(|percent| format!("SAMPLE {}%", percent)));
+        scan.table = Some(table.to_string());
+        scan.detail = (!detail.is_empty()).then(|| detail.join(", "));
+        scan.estimated_rows = Some(sample.map_or(rows, |percent| rows * percent / 100.0));
+        if analyze && sample.is_none() {
+            scan.actual_rows = Some(rows as usize);
+        }
+        Ok(scan)
+    }
+
+    fn execute_select(
+        &mut self,
+        table: &str,
+        columns: &[Expr],
+        condition: Option<Condition>,
+        sample: Option<f64>,
+        paging: &Paging,
+        use_indexes: bool,
+    ) -> Result<Vec<Vec<Value>>, DbError> {
+        let table_def = self.table_def(table)?;
+        let required_columns = select_columns(&table_def, columns, condition.as_ref(), &paging.order_by)?;
 
         let mut storage_guard = self.storage.lock().unwrap();
-        let mut column_values = HashMap::new();
-        let mut min_row_count = usize::MAX;
-        for col in &required_columns {
-            let values = storage_guard.read_column(table, col, condition.as_ref())?;
-            min_row_count = min_row_count.min(values.len());
-            column_values.insert(col.clone(), values);
+        let batch = if is_system_table(table) {
+            system_batch(&storage_guard, &table_def)?
+        } else {
+            read_batch(
+                &mut storage_guard,
+                &table_def,
+                &required_columns,
+                condition.as_ref(),
+                sample,
+                use_indexes,
+            )?
+        };
+        let mut selection = batch.select_all();
+        if let Some(cond) = &condition {
+            selection = batch.filter(cond, &selection)?;
+        }
+        let offset = paging.offset.unwrap_or(0);
+        if !paging.order_by.is_empty() {
+            // Duplicates dropped later would leave a kept prefix short.
+            let keep = paging.limit.filter(|_| !paging.distinct).map(|limit| offset.saturating_add(limit));
+            selection = order_selection(&batch, &paging.order_by, &selection, keep)?;
         }
+        let project = |selection: &[u32]| project_rows(&batch, columns, selection);
+        let page = |len: usize| {
+            let end = paging.limit.map_or(len, |limit| offset.saturating_add(limit).min(len));
+            offset.min(end)..end
+        };
+        if paging.distinct {
+            let mut seen = HashSet::new();
+            let mut rows = project(&selection)?;
+            rows.retain(|row| seen.insert(row.clone()));
+            let page = page(rows.len());
+            return Ok(rows.drain(page).collect());
+        }
+        project(&selection[page(selection.len())])
+    }
 
-        let mut result = Vec::new();
-        for i in 0..min_row_count {
-            if let Some(cond) = &condition {
-                if crate::query::evaluator::evaluate_condition_row(cond, &column_values, i)? {
-                    let row = columns
-                        .iter()
-                        .map(|col| column_values.get(col).unwrap()[i].clone())
-                        .collect();
-                    result.push(row);
-                }
-            } else {
-                let row = columns
-                    .iter()
-                    .map(|col| column_values.get(col).unwrap()[i].clone())
-                    .collect();
-                result.push(row);
+    /// Every column of the rows `condition` does not hold for, NULL
+    /// comparisons included, in storage order and at most `limit` of them.
+    fn execute_verify(
+        &mut self,
+        table: &str,
+        condition: &Condition,
+        limit: Option<usize>,
+    ) -> Result<Vec<Vec<Value>>, DbError> {
+        let mut storage_guard = self.storage.lock().unwrap();
+        let table_def = storage_guard
+            .schema()
+            .get_table(table)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?
+            .clone();
+        for col in crate::query::collect_condition_columns(condition) {
+            if table_def.get_column(&col).is_none() {
+                return Err(DbError::InvalidData(format!("Column {}.{} not found in condition", table, col)));
             }
         }
-        Ok(result)
+        let columns: Vec<String> = table_def.columns.iter().map(|c| c.name.clone()).collect();
+        // No block pruning: the blocks it would skip are the ones with violations.
+        let batch = read_batch(&mut storage_guard, &table_def, &columns, None, None, true)?;
+        let all = batch.select_all();
+        let passing = batch.filter(condition, &all)?;
+        let violating: Vec<u32> = all
+            .into_iter()
+            .filter(|row| passing.binary_search(row).is_err())
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        batch.rows(&columns, &violating)
     }
 
     fn execute_aggregate(
@@ -208,6 +1076,9 @@ impl QueryEngine {
         table: &str,
         aggregations: &[Aggregation],
         condition: Option<Condition>,
+        sample: Option<f64>,
+        group_by: &[GroupKey],
+        use_indexes: bool,
     ) -> Result<Vec<Vec<Value>>, DbError> {
         let table_def = {
             let storage_guard = self.storage.lock().unwrap();
@@ -218,126 +1089,738 @@ impl QueryEngine {
                 .clone()
         };
 
+        if aggregations.len() > 1 && aggregations.iter().any(|a| matches!(a, Aggregation::Histogram(..))) {
+            return Err(DbError::QueryError(
+                "HISTOGRAM cannot be combined with other aggregations".to_string(),
+            ));
+        }
+
+        // FIRST/LAST need two aligned columns, which only the grouped path reads.
+        let needs_rows = aggregations
+            .iter()
+            .any(|a| matches!(a, Aggregation::First(..) | Aggregation::Last(..)));
+        if !group_by.is_empty() || needs_rows {
+            return self.execute_grouped_aggregate(&table_def, aggregations, condition, sample, group_by, use_indexes);
+        }
+
+        if condition.is_none()
+            && sample.is_none()
+            && let Some(row) = self.zone_map_aggregate(&table_def, aggregations)?
+        {
+            return Ok(vec![row]);
+        }
+        if sample.is_none() && table_def.location.is_none() && pushdown::pushable(aggregations) {
+            let storage_guard = self.storage.lock().unwrap();
+            let row =
+                pushdown::aggregate_blocks(&storage_guard, &table_def, aggregations, condition.as_ref(), use_indexes)?;
+            return Ok(vec![row]);
+        }
+
+        let mut required_columns: Vec<String> = aggregations.iter().map(aggregation_column).collect();
+        if let Some(ref cond) = condition {
+            required_columns.extend(crate::query::collect_condition_columns(cond));
+        }
+        required_columns.sort();
+        required_columns.dedup();
+
         let mut storage_guard = self.storage.lock().unwrap();
+        let batch = read_batch(
+            &mut storage_guard,
+            &table_def,
+            &required_columns,
+            condition.as_ref(),
+            sample,
+            use_indexes,
+        )?;
+        let mut selection = batch.select_all();
+        if let Some(cond) = &condition {
+            selection = batch.filter(cond, &selection)?;
+        }
         let mut results = Vec::new();
         for agg in aggregations {
-            let column = match agg {
-                Aggregation::Count => "ID".to_string(),
-                Aggregation::Sum(col) | Aggregation::Avg(col) | Aggregation::Min(col) | Aggregation::Max(col) => col.clone(),
-            };
+            let column = aggregation_column(agg);
             let col_def = table_def
                 .get_column(&column)
                 .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table, column)))?;
-            let values = storage_guard.read_column(table, &column, condition.as_ref())?;
-
-            let result = match agg {
-                Aggregation::Count => Value::Int32(values.len() as i32),
-                Aggregation::Sum(_) => {
-                    if col_def.data_type != DataType::Float32 && col_def.data_type != DataType::Int32 {
-                        return Err(DbError::InvalidData(format!(
-                            "SUM not supported for type {:?}", col_def.data_type
-                        )));
+            let values = batch.values(&column, &selection)?;
+            match compute_aggregate(agg, &col_def.data_type, &values)? {
+                AggregateOutput::Value(result) => results.push(result),
+                AggregateOutput::Rows(rows) => return Ok(rows),
+            }
+        }
+        Ok(vec![results])
+    }
+
+    /// Answers unfiltered COUNT/MIN/MAX from the table row count and block
+    /// min/max metadata. `None` if any aggregation needs the data itself.
+    fn zone_map_aggregate(&self, table_def: &Table, aggregations: &[Aggregation]) -> Result<Option<Vec<Value>>, DbError> {
+        // External tables keep neither row counts nor zone maps.
+        if table_def.location.is_some()
+            || !aggregations
+            .iter()
+            .all(|a| matches!(a, Aggregation::Count | Aggregation::Min(_) | Aggregation::Max(_)))
+        {
+            return Ok(None);
+        }
+        let storage_guard = self.storage.lock().unwrap();
+        let mut row = Vec::new();
+        for agg in aggregations {
+            let value = match agg {
+                Aggregation::Count => Value::Int32(table_def.row_count as i32),
+                Aggregation::Min(col) | Aggregation::Max(col) => {
+                    if table_def.get_column(col).is_none() {
+                        return Err(DbError::InvalidData(format!("Column {}.{} not found", table_def.name, col)));
                     }
-                    values.iter().fold(Value::Float32(ordered_float::OrderedFloat(0.0)), |acc, v| {
-                        match (acc.clone(), v) {
-                            (Value::Float32(a), Value::Float32(b)) => Value::Float32(a + b),
-                            (Value::Float32(a), Value::Int32(b)) => {
-                                Value::Float32(a + ordered_float::OrderedFloat(*b as f32))
-                            }
-                            _ => acc,
-                        }
-                    })
+                    let empty = Value::Float32(ordered_float::OrderedFloat(0.0));
+                    match storage_guard.zone_map_bounds(&table_def.name, col)? {
+                        Some((min, _)) if matches!(agg, Aggregation::Min(_)) => min,
+                        Some((_, max)) => max,
+                        None => empty,
+                    }
+                }
+                _ => unreachable!("checked above"),
+            };
+            row.push(value);
+        }
+        Ok(Some(row))
+    }
+
+    /// Aggregates per distinct group-key tuple; each output row is the group
+    /// keys (in GROUP BY order) followed by the aggregation results.
+    fn execute_grouped_aggregate(
+        &mut self,
+        table_def: &Table,
+        aggregations: &[Aggregation],
+        condition: Option<Condition>,
+        sample: Option<f64>,
+        group_by: &[GroupKey],
+        use_indexes: bool,
+    ) -> Result<Vec<Vec<Value>>, DbError> {
+        let table = table_def.name.as_str();
+        if aggregations.iter().any(|a| matches!(a, Aggregation::Histogram(..))) {
+            return Err(DbError::QueryError("HISTOGRAM cannot be used with GROUP BY".to_string()));
+        }
+
+        let mut required_columns: Vec<String> = group_by.iter().map(|k| k.column().to_string()).collect();
+        for agg in aggregations {
+            match agg {
+                Aggregation::Count => {}
+                Aggregation::First(col, order_by) | Aggregation::Last(col, order_by) => {
+                    required_columns.push(col.clone());
+                    required_columns.push(order_by.clone());
                 }
-                Aggregation::Avg(_) => {
-                    if col_def.data_type != DataType::Float32 && col_def.data_type != DataType::Int32 {
-                        return Err(DbError::InvalidData(format!(
-                            "AVG not supported for type {:?}", col_def.data_type
-                        )));
+                _ => required_columns.push(aggregation_column(agg)),
+            }
+        }
+        if let Some(ref cond) = condition {
+            required_columns.extend(crate::query::collect_condition_columns(cond));
+        }
+        required_columns.sort();
+        required_columns.dedup();
+        for col in &required_columns {
+            if table_def.get_column(col).is_none() {
+                return Err(DbError::InvalidData(format!("Column {}.{} not found", table, col)));
+            }
+        }
+
+        let mut storage_guard = self.storage.lock().unwrap();
+        let batch = read_batch(
+            &mut storage_guard,
+            table_def,
+            &required_columns,
+            condition.as_ref(),
+            sample,
+            use_indexes,
+        )?;
+        let mut selection = batch.select_all();
+        if let Some(cond) = &condition {
+            selection = batch.filter(cond, &selection)?;
+        }
+
+        let groups = group_rows(&batch, group_by, &selection)?;
+        let mut result = Vec::new();
+        for (key, rows) in groups {
+            let mut row = key;
+            for agg in aggregations {
+                let value = match agg {
+                    Aggregation::Count => Value::Int32(rows.len() as i32),
+                    Aggregation::First(col, order_by) => {
+                        let order = batch.column(order_by)?;
+                        let pick = rows.iter().filter(|&&i| !order.value(i).is_null()).min_by_key(|&&i| order.value(i));
+                        let column = batch.column(col)?;
+                        pick.map_or(Value::Null, |&i| column.value(i))
                     }
-                    let sum = values.iter().fold(Value::Float32(ordered_float::OrderedFloat(0.0)), |acc, v| {
-                        match (acc.clone(), v) {
-                            (Value::Float32(a), Value::Float32(b)) => Value::Float32(a + b),
-                            (Value::Float32(a), Value::Int32(b)) => {
-                                Value::Float32(a + ordered_float::OrderedFloat(*b as f32))
-                            }
-                            _ => acc,
-                        }
-                    });
-                    match sum {
-                        Value::Float32(s) if values.len() > 0 => {
-                            Value::Float32(ordered_float::OrderedFloat(s.0 / values.len() as f32))
+                    Aggregation::Last(col, order_by) => {
+                        let order = batch.column(order_by)?;
+                        let pick = rows.iter().filter(|&&i| !order.value(i).is_null()).max_by_key(|&&i| order.value(i));
+                        let column = batch.column(col)?;
+                        pick.map_or(Value::Null, |&i| column.value(i))
+                    }
+                    _ => {
+                        let column = aggregation_column(agg);
+                        let data_type = &table_def.get_column(&column).unwrap().data_type;
+                        let vector = batch.column(&column)?;
+                        let values: Vec<Value> = rows.iter().map(|&i| vector.value(i)).collect();
+                        match compute_aggregate(agg, data_type, &values)? {
+                            AggregateOutput::Value(v) => v,
+                            AggregateOutput::Rows(_) => unreachable!("HISTOGRAM rejected above"),
                         }
-                        _ => Value::Float32(ordered_float::OrderedFloat(0.0)),
                     }
-                }
-                Aggregation::Min(_) => values
-                    .iter()
-                    .min_by(|a, b| a.cmp(b))
-                    .cloned()
-                    .unwrap_or(Value::Float32(ordered_float::OrderedFloat(0.0))),
-                Aggregation::Max(_) => values
-                    .iter()
-                    .max_by(|a, b| a.cmp(b))
-                    .cloned()
-                    .unwrap_or(Value::Float32(ordered_float::OrderedFloat(0.0))),
-            };
-            results.push(result);
+                };
+                row.push(value);
+            }
+            result.push(row);
         }
-        Ok(vec![results])
+        Ok(result)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn execute_join(
         &mut self,
         left_table: &str,
         right_table: &str,
         left_column: &str,
         right_column: &str,
-        columns: &[String],
+        operator: JoinOperator,
+        columns: &[Expr],
         condition: Option<Condition>,
+        hints: &[Hint],
     ) -> Result<Vec<Vec<Value>>, DbError> {
         let mut storage_guard = self.storage.lock().unwrap();
-        let left_values = storage_guard.read_column(left_table, left_column, condition.as_ref())?;
-        let right_values = storage_guard.read_column(right_table, right_column, condition.as_ref())?;
-
-        let mut column_values = HashMap::new();
-        let mut min_row_count_left = usize::MAX;
-        let mut min_row_count_right = usize::MAX;
-        for col in columns {
-            let (table, col_name) = if col.contains('.') {
-                let parts = col.split('.').collect::<Vec<_>>();
-                (parts[0], parts[1])
-            } else {
-                (left_table, col.as_str())
-            };
-            let values = storage_guard.read_column(table, col_name, condition.as_ref())?;
-            if table == right_table {
-                min_row_count_right = min_row_count_right.min(values.len());
-            } else {
-                min_row_count_left = min_row_count_left.min(values.len());
+        let mut table_defs = Vec::with_capacity(2);
+        for table in [left_table, right_table] {
+            let table_def = storage_guard
+                .schema()
+                .get_table(table)
+                .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?
+                .clone();
+            table_defs.push(table_def);
+        }
+
+        // Every column the output and the WHERE clause refer to, by side.
+        let mut referenced: Vec<String> = columns.iter().flat_map(Expr::columns).collect();
+        if let Some(cond) = &condition {
+            referenced.extend(crate::query::collect_condition_columns(cond));
+        }
+        let mut needed = [vec![left_column.to_string()], vec![right_column.to_string()]];
+        let mut resolved = Vec::with_capacity(referenced.len());
+        for name in referenced {
+            let (side, col) = join_column_side(&name, [&table_defs[0], &table_defs[1]]).ok_or_else(|| {
+                let table = name.split_once('.').map_or(name.as_str(), |(table, _)| table);
+                DbError::QueryError(format!("Table {} is not part of the join", table))
+            })?;
+            if !needed[side].contains(&col) {
+                needed[side].push(col.clone());
             }
-            column_values.insert(col.clone(), values);
+            resolved.push((name, side, col));
         }
 
-        let mut result = Vec::new();
-        for (i, left_val) in left_values.iter().enumerate().take(min_row_count_left) {
-            for (j, right_val) in right_values.iter().enumerate().take(min_row_count_right) {
-                if left_val == right_val {
-                    let row = columns.iter().map(|col| {
-                        let values = column_values.get(col).unwrap();
-                        let index = if col.starts_with(right_table) { j } else { i };
-                        if index < values.len() {
-                            Ok(values[index].clone())
-                        } else {
-                            Err(DbError::InvalidData(format!(
-                                "Index {} out of bounds for column {} (len: {})",
-                                index, col, values.len()
-                            )))
+        // Each side is read whole so its columns stay row-aligned; the WHERE
+        // clause can span both tables and is applied to the joined rows.
+        let sides = [
+            read_batch(&mut storage_guard, &table_defs[0], &needed[0], None, None, true)?,
+            read_batch(&mut storage_guard, &table_defs[1], &needed[1], None, None, true)?,
+        ];
+        drop(storage_guard);
+        let key_column = |side: usize, name: &str| {
+            table_defs[side]
+                .get_column(name)
+                .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table_defs[side].name, name)))
+        };
+        let (left_keys, right_keys) = join_keys(
+            sides[0].values(left_column, &sides[0].select_all())?,
+            key_column(0, left_column)?,
+            sides[1].values(right_column, &sides[1].select_all())?,
+            key_column(1, right_column)?,
+        )?;
+        let hash = hints.contains(&Hint::HashJoin);
+        let merge = hints.contains(&Hint::MergeJoin);
+        let pairs = match operator {
+            _ if hash && merge => {
+                return Err(DbError::QueryError("HASH_JOIN and MERGE_JOIN cannot both be used".to_string()));
+            }
+            JoinOperator::Eq if !merge => hash_join(&left_keys, &right_keys),
+            _ if hash => {
+                return Err(DbError::QueryError("HASH_JOIN requires an equality join condition".to_string()));
+            }
+            _ => sort_merge_join(&left_keys, &right_keys, operator)?,
+        };
+
+        let mut joined = Batch::new(pairs.len());
+        for (name, side, col) in &resolved {
+            if joined.columns.contains_key(name) {
+                continue;
+            }
+            let vector = sides[*side].column(col)?;
+            let values: Vec<Value> = pairs
+                .iter()
+                .map(|&(l, r)| vector.value(if *side == 0 { l } else { r } as usize))
+                .collect();
+            let col_def = table_defs[*side]
+                .get_column(col)
+                .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table_defs[*side].name, col)))?;
+            joined.add_column(name, &values, &col_def.data_type)?;
+            if col_def.collation != Collation::Binary {
+                joined.collations.insert(name.clone(), col_def.collation);
+            }
+        }
+        let mut selection = joined.select_all();
+        if let Some(cond) = &condition {
+            selection = joined.filter(cond, &selection)?;
+        }
+        match plain_columns(columns) {
+            Some(names) => joined.rows(&names, &selection),
+            None => selection
+                .iter()
+                .map(|&row| {
+                    let value = |col: &str| Ok(joined.column(col)?.value(row as usize));
+                    columns.iter().map(|expr| expr.evaluate_with(&value)).collect()
+                })
+                .collect(),
+        }
+    }
+}
+/// `operator` over `child`, passing on the child's row counts.
+fn plan_parent(operator: &str, detail: String, child: PlanNode) -> PlanNode {
+    PlanNode {
+        detail: (!detail.is_empty()).then_some(detail),
+        estimated_rows: child.estimated_rows,
+        actual_rows: child.actual_rows,
+        children: vec![child],
+        ..PlanNode::new(operator)
+    }
+}
+
+enum AggregateOutput {
+    Value(Value),
+    Rows(Vec<Vec<Value>>),
+}
+
+/// Picks the `RETURNING` columns out of full rows in table column order.
+/// Returns no rows when there is no RETURNING clause.
+fn project_returning(
+    schema: &Schema,
+    table: &str,
+    returning: &[String],
+    rows: Vec<Vec<Value>>,
+) -> Result<Vec<Vec<Value>>, DbError> {
+    if returning.is_empty() {
+        return Ok(Vec::new());
+    }
+    let table_def = schema
+        .get_table(table)
+        .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?;
+    let mut positions = Vec::new();
+    for col in returning {
+        if col == "*" {
+            positions.extend(0..table_def.columns.len());
+            continue;
+        }
+        let position = table_def
+            .columns
+            .iter()
+            .position(|c| c.name == *col)
+            .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found in RETURNING", table, col)))?;
+        positions.push(position);
+    }
+    Ok(rows
+        .into_iter()
+        .map(|row| positions.iter().map(|&i| row.get(i).cloned().unwrap_or(Value::Null)).collect())
+        .collect())
+}
+
+/// The columns a SELECT of `columns` from `table_def` reads, those its
+/// condition and ORDER BY need included. Unknown columns are an error.
+pub(crate) fn select_columns(
+    table_def: &Table,
+    columns: &[Expr],
+    condition: Option<&Condition>,
+    order_by: &[(String, SortOrder)],
+) -> Result<Vec<String>, DbError> {
+    let table = &table_def.name;
+    let mut required_columns: Vec<String> = Vec::new();
+    for col in columns.iter().flat_map(Expr::columns) {
+        if !table_def.columns.iter().any(|c| c.name == col) {
+            return Err(DbError::InvalidData(format!("Column {}.{} not found", table, col)));
+        }
+        if !required_columns.contains(&col) {
+            required_columns.push(col);
+        }
+    }
+    if let Some(cond) = condition {
+        let condition_columns = crate::query::collect_condition_columns(cond);
+        for col in condition_columns {
+            if !table_def.columns.iter().any(|c| c.name == col) {
+                return Err(DbError::InvalidData(format!("Column {}.{} not found in condition", table, col)));
+            }
+            if !required_columns.contains(&col) {
+                required_columns.push(col);
+            }
+        }
+    }
+    for (col, _) in order_by {
+        if table_def.get_column(col).is_none() {
+            return Err(DbError::InvalidData(format!("Column {}.{} not found in ORDER BY", table, col)));
+        }
+        if !required_columns.contains(col) {
+            required_columns.push(col.clone());
+        }
+    }
+    Ok(required_columns)
+}
+
+/// The selected rows of `batch` with `columns` evaluated on each.
+pub(crate) fn project_rows(batch: &Batch, columns: &[Expr], selection: &[u32]) -> Result<Vec<Vec<Value>>, DbError> {
+    match plain_columns(columns) {
+        Some(names) => batch.rows(&names, selection),
+        None => selection
+            .iter()
+            .map(|&row| {
+                let value = |col: &str| Ok(batch.column(col)?.value(row as usize));
+                columns.iter().map(|expr| expr.evaluate_with(&value)).collect()
+            })
+            .collect(),
+    }
+}
+
+/// Names of the projected columns when every expression is a bare column.
+fn plain_columns(columns: &[Expr]) -> Option<Vec<String>> {
+    columns
+        .iter()
+        .map(|expr| match expr {
+            Expr::Column(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// ORDER BY / LIMIT / OFFSET of a plain SELECT, and whether DISTINCT drops
+/// duplicate rows before LIMIT and OFFSET count them.
+struct Paging {
+    order_by: Vec<(String, SortOrder)>,
+    limit: Option<usize>,
+    offset: Option<usize>,
+    distinct: bool,
+}
+
+/// A system table's rows as a batch of every column.
+fn system_batch(storage: &StorageManager, table_def: &Table) -> Result<Batch, DbError> {
+    let rows = system_rows(storage, &table_def.name)?;
+    let mut batch = Batch::new(rows.len());
+    for (i, col) in table_def.columns.iter().enumerate() {
+        let values: Vec<Value> = rows.iter().map(|row| row[i].clone()).collect();
+        batch.add_column(&col.name, &values, &col.data_type)?;
+    }
+    Ok(batch)
+}
+
+/// The join side a WHERE column reads and its unqualified name; None for a table outside the join.
+fn join_column_side(name: &str, tables: [&Table; 2]) -> Option<(usize, String)> {
+    match name.split_once('.') {
+        Some((table, col)) => tables.iter().position(|t| t.name == table).map(|side| (side, col.to_string())),
+        None if tables[0].get_column(name).is_none() && tables[1].get_column(name).is_some() => {
+            Some((1, name.to_string()))
+        }
+        None => Some((0, name.to_string())),
+    }
+}
+
+/// The conjuncts of a join's WHERE clause that read only one side, ANDed
+/// per side, so each side's rows can be estimated before the join.
+fn join_side_filters(condition: Option<&Condition>, tables: [&Table; 2]) -> [Option<Condition>; 2] {
+    let mut conjuncts = Vec::new();
+    let mut pending: Vec<&Condition> = condition.into_iter().collect();
+    while let Some(cond) = pending.pop() {
+        match cond {
+            Condition::And(left, right) => pending.extend([right.as_ref(), left.as_ref()]),
+            _ => conjuncts.push(cond),
+        }
+    }
+    let mut filters: [Option<Condition>; 2] = [None, None];
+    for conjunct in conjuncts {
+        let sides: HashSet<Option<usize>> = crate::query::collect_condition_columns(conjunct)
+            .iter()
+            .map(|name| join_column_side(name, tables).map(|(side, _)| side))
+            .collect();
+        if let [Some(side)] = sides.into_iter().collect::<Vec<_>>()[..] {
+            filters[side] = Some(match filters[side].take() {
+                Some(filter) => Condition::And(Box::new(filter), Box::new(conjunct.clone())),
+                None => conjunct.clone(),
+            });
+        }
+    }
+    filters
+}
+
+/// Reads `columns` into one batch, truncated to the shortest column so rows line up.
+fn read_batch(
+    storage: &mut StorageManager,
+    table_def: &Table,
+    columns: &[String],
+    condition: Option<&Condition>,
+    sample: Option<f64>,
+    use_indexes: bool,
+) -> Result<Batch, DbError> {
+    let mut vectors = Vec::with_capacity(columns.len());
+    for col in columns {
+        if table_def.get_column(col).is_none() {
+            return Err(DbError::InvalidData(format!("Column {}.{} not found", table_def.name, col)));
+        }
+        vectors.push(storage.read_column_vector(&table_def.name, col, condition, sample, use_indexes)?);
+    }
+    let row_count = vectors.iter().map(|v| v.len()).min().unwrap_or(0);
+    let mut batch = Batch::new(row_count);
+    batch.collations = table_def.collations();
+    for (col, vector) in columns.iter().zip(vectors) {
+        batch.add_vector(col, vector);
+    }
+    Ok(batch)
+}
+
+/// The rows of `selection` by group-key tuple. Each key is first mapped to a
+/// small id per row; string keys by dictionary code, so a key value is built
+/// once per distinct string rather than once per row.
+fn group_rows(
+    batch: &Batch,
+    group_by: &[GroupKey],
+    selection: &[u32],
+) -> Result<BTreeMap<Vec<Value>, Vec<usize>>, DbError> {
+    let mut row_ids: Vec<Vec<u32>> = vec![Vec::with_capacity(group_by.len()); selection.len()];
+    let mut key_values: Vec<Vec<Value>> = Vec::with_capacity(group_by.len());
+    for key in group_by {
+        let column = batch.column(key.column())?;
+        let mut values: Vec<Value> = Vec::new();
+        let mut ids: HashMap<Value, u32> = HashMap::new();
+        let mut id_of = |value: Value| {
+            *ids.entry(value.clone()).or_insert_with(|| {
+                values.push(value);
+                values.len() as u32 - 1
+            })
+        };
+        match column.dictionary_codes() {
+            Some((codes, dictionary)) => {
+                let nulls = column.nulls();
+                // The last slot stands for NULL.
+                let mut by_code: Vec<Option<u32>> = vec![None; dictionary.len() + 1];
+                for (row, &i) in row_ids.iter_mut().zip(selection) {
+                    let i = i as usize;
+                    let code = match nulls {
+                        Some(nulls) if nulls[i] => dictionary.len(),
+                        _ => codes[i] as usize,
+                    };
+                    let id = match by_code[code] {
+                        Some(id) => id,
+                        None => {
+                            let value = dictionary.get(code).map_or(Value::Null, |s| Value::String(s.clone()));
+                            *by_code[code].insert(id_of(key.apply(&value)?))
                         }
-                    }).collect::<Result<Vec<Value>, DbError>>()?;
-                    result.push(row);
+                    };
+                    row.push(id);
+                }
+            }
+            None => {
+                for (row, &i) in row_ids.iter_mut().zip(selection) {
+                    row.push(id_of(key.apply(&column.value(i as usize))?));
                 }
             }
         }
-        Ok(result)
+        key_values.push(values);
+    }
+    let mut by_ids: HashMap<Vec<u32>, Vec<usize>> = HashMap::new();
+    for (ids, &i) in row_ids.into_iter().zip(selection) {
+        by_ids.entry(ids).or_default().push(i as usize);
+    }
+    Ok(by_ids
+        .into_iter()
+        .map(|(ids, rows)| {
+            let key = ids.iter().zip(&key_values).map(|(&id, values)| values[id as usize].clone()).collect();
+            (key, rows)
+        })
+        .collect())
+}
+
+fn aggregation_column(agg: &Aggregation) -> String {
+    match agg {
+        Aggregation::Count => "ID".to_string(),
+        Aggregation::Sum(col)
+        | Aggregation::Avg(col)
+        | Aggregation::Min(col)
+        | Aggregation::Max(col)
+        | Aggregation::CountColumn(col)
+        | Aggregation::CountDistinct(col)
+        | Aggregation::ApproxCountDistinct(col)
+        | Aggregation::ApproxQuantile(col, _)
+        | Aggregation::Percentile(col, _)
+        | Aggregation::Histogram(col, _)
+        | Aggregation::First(col, _)
+        | Aggregation::Last(col, _) => col.clone(),
+    }
+}
+
+fn compute_aggregate(agg: &Aggregation, data_type: &DataType, values: &[Value]) -> Result<AggregateOutput, DbError> {
+    // Every aggregate except COUNT(*) ignores NULLs.
+    let present: Vec<Value>;
+    let values = if matches!(agg, Aggregation::Count) || !values.iter().any(Value::is_null) {
+        values
+    } else {
+        present = values.iter().filter(|v| !v.is_null()).cloned().collect();
+        &present
+    };
+    let result = match agg {
+        Aggregation::Count | Aggregation::CountColumn(_) => Value::Int32(values.len() as i32),
+        Aggregation::First(..) | Aggregation::Last(..) => {
+            return Err(DbError::QueryError(
+                "FIRST/LAST need the ORDER BY column and are evaluated per group".to_string(),
+            ));
+        }
+        Aggregation::Sum(_) => {
+            if !data_type.is_numeric() {
+                return Err(DbError::InvalidData(format!(
+                    "SUM not supported for type {:?}", data_type
+                )));
+            }
+            match data_type {
+                DataType::Int32 | DataType::Int64 => bigint_sum(values.iter().filter_map(integer).sum())?,
+                _ => Value::Float32(ordered_float::OrderedFloat(numeric_sum(values) as f32)),
+            }
+        }
+        Aggregation::Avg(_) => {
+            if !data_type.is_numeric() {
+                return Err(DbError::InvalidData(format!(
+                    "AVG not supported for type {:?}", data_type
+                )));
+            }
+            if values.is_empty() {
+                Value::Float32(ordered_float::OrderedFloat(0.0))
+            } else {
+                Value::Float32(ordered_float::OrderedFloat((numeric_sum(values) / values.len() as f64) as f32))
+            }
+        }
+        Aggregation::Min(_) => values
+            .iter()
+            .min_by(|a, b| a.cmp(b))
+            .cloned()
+            .unwrap_or(Value::Float32(ordered_float::OrderedFloat(0.0))),
+        Aggregation::Max(_) => values
+            .iter()
+            .max_by(|a, b| a.cmp(b))
+            .cloned()
+            .unwrap_or(Value::Float32(ordered_float::OrderedFloat(0.0))),
+        Aggregation::CountDistinct(_) => Value::Int32(values.iter().collect::<HashSet<_>>().len() as i32),
+        Aggregation::ApproxCountDistinct(_) => {
+            let mut hll = HyperLogLog::new();
+            for v in values {
+                hll.insert(v);
+            }
+            Value::Int32(hll.estimate() as i32)
+        }
+        Aggregation::ApproxQuantile(_, q) => {
+            if !data_type.is_numeric() {
+                return Err(DbError::InvalidData(format!(
+                    "APPROX_QUANTILE not supported for type {:?}", data_type
+                )));
+            }
+            let mut digest = TDigest::new(100.0);
+            for v in values.iter().filter_map(Value::as_f64) {
+                digest.insert(v);
+            }
+            Value::Float32(ordered_float::OrderedFloat(digest.quantile(*q).unwrap_or(0.0) as f32))
+        }
+        Aggregation::Percentile(_, p) => {
+            if !data_type.is_numeric() {
+                return Err(DbError::InvalidData(format!(
+                    "PERCENTILE not supported for type {:?}", data_type
+                )));
+            }
+            let mut sorted: Vec<f64> = values.iter().filter_map(Value::as_f64).collect();
+            sorted.sort_by(|a, b| a.total_cmp(b));
+            Value::Float32(ordered_float::OrderedFloat(percentile(&sorted, *p) as f32))
+        }
+        Aggregation::Histogram(_, buckets) => {
+            if !data_type.is_numeric() {
+                return Err(DbError::InvalidData(format!(
+                    "HISTOGRAM not supported for type {:?}", data_type
+                )));
+            }
+            let numbers: Vec<f64> = values.iter().filter_map(Value::as_f64).collect();
+            return Ok(AggregateOutput::Rows(histogram(&numbers, *buckets)));
+        }
+    };
+    Ok(AggregateOutput::Value(result))
+}
+
+fn numeric_sum(values: &[Value]) -> f64 {
+    values.iter().filter_map(Value::as_f64).sum()
+}
+
+/// An INT or BIGINT value widened for summing.
+pub(crate) fn integer(value: &Value) -> Option<i128> {
+    match value {
+        Value::Int32(i) => Some(*i as i128),
+        Value::Int64(i) => Some(*i as i128),
+        _ => None,
+    }
+}
+
+/// The SUM of an integer column, which is a BIGINT and fails rather than wraps.
+pub(crate) fn bigint_sum(sum: i128) -> Result<Value, DbError> {
+    i64::try_from(sum)
+        .map(Value::Int64)
+        .map_err(|_| DbError::InvalidData("SUM out of range for BIGINT".to_string()))
+}
+
+/// Exact percentile `p` (0-100) of sorted input using linear interpolation between ranks.
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
     }
-}
\ No newline at end of file
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// Equal-width histogram over [min, max]; one `[low, high, count]` row per bucket.
+fn histogram(values: &[f64], buckets: usize) -> Vec<Vec<Value>> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let width = (max - min) / buckets as f64;
+    let mut counts = vec![0usize; buckets];
+    for v in values {
+        let bucket = if width == 0.0 {
+            0
+        } else {
+            (((v - min) / width) as usize).min(buckets - 1)
+        };
+        counts[bucket] += 1;
+    }
+    counts
+        .into_iter()
+        .enumerate()
+        .map(|(i, count)| {
+            vec![
+                Value::Float32(ordered_float::OrderedFloat((min + width * i as f64) as f32)),
+                Value::Float32(ordered_float::OrderedFloat((min + width * (i + 1) as f64) as f32)),
+                Value::Int32(count as i32),
+            ]
+        })
+        .collect()
+}
+
+/// Tables a read-only query depends on; empty for statements that are never cached.
+fn read_tables(query: &Query) -> Vec<String> {
+    match query {
+        Query::Select { table, .. } | Query::SelectAggregate { table, .. } | Query::VerifyTable { table, .. } => {
+            vec![table.clone()]
+        }
+        Query::Join {
+            left_table,
+            right_table,
+            ..
+        } => vec![left_table.clone(), right_table.clone()],
+        _ => Vec::new(),
+    }
+}
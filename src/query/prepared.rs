@@ -0,0 +1,197 @@
+use crate::query::expr::Expr;
+use crate::query::parser::parse_query;
+use crate::query::pattern::Pattern;
+use crate::query::{Condition, Query};
+use crate::types::{DbError, Value};
+
+/// A statement parsed once with `?` placeholders where literals go, bound to
+/// values for each execution. Bound values are never re-parsed, so strings
+/// need no quoting or escaping.
+#[derive(Clone, Debug)]
+pub struct PreparedQuery {
+    template: Query,
+    param_count: usize,
+}
+
+/// Stands in for placeholder `index` while parsing: a string literal with a
+/// NUL prefix, which typed SQL cannot contain.
+fn marker(index: usize) -> String {
+    format!("\u{0}{}", index)
+}
+
+fn marker_index(s: &str) -> Option<usize> {
+    s.strip_prefix('\u{0}')?.parse().ok()
+}
+
+impl PreparedQuery {
+    pub fn new(sql: &str) -> Result<Self, DbError> {
+        let mut rewritten = String::with_capacity(sql.len());
+        let mut quote = None;
+        let mut param_count = 0;
+        for ch in sql.chars() {
+            match ch {
+                '"' | '\'' if quote.is_none() => quote = Some(ch),
+                _ if quote == Some(ch) => quote = None,
+                '?' if quote.is_none() => {
+                    rewritten.push_str(&format!("\"{}\"", marker(param_count)));
+                    param_count += 1;
+                    continue;
+                }
+                _ => {}
+            }
+            rewritten.push(ch);
+        }
+
+        let mut template = parse_query(&rewritten)?;
+        let mut found = 0;
+        visit_query(&mut template, &mut |value| {
+            if let Value::String(s) = value
+                && marker_index(s).is_some()
+            {
+                found += 1;
+            }
+            Ok(())
+        })?;
+        if found != param_count {
+            return Err(DbError::QueryError(
+                "Placeholders are only allowed where a literal value can appear".to_string(),
+            ));
+        }
+        Ok(PreparedQuery { template, param_count })
+    }
+
+    /// Number of `?` placeholders, which `bind` expects as many values for.
+    pub fn param_count(&self) -> usize {
+        self.param_count
+    }
+
+    /// The statement with the `i`th placeholder replaced by `params[i]`.
+    pub fn bind(&self, params: &[Value]) -> Result<Query, DbError> {
+        if params.len() != self.param_count {
+            return Err(DbError::QueryError(format!(
+                "Expected {} parameters, got {}",
+                self.param_count,
+                params.len()
+            )));
+        }
+        let mut query = self.template.clone();
+        visit_query(&mut query, &mut |value| {
+            if let Value::String(s) = value
+                && let Some(index) = marker_index(s)
+            {
+                *value = params[index].clone();
+            }
+            Ok(())
+        })?;
+        Ok(query)
+    }
+}
+
+type Visitor<'a> = dyn FnMut(&mut Value) -> Result<(), DbError> + 'a;
+
+/// Calls `f` on every literal in a statement that a placeholder can stand for.
+fn visit_query(query: &mut Query, f: &mut Visitor) -> Result<(), DbError> {
+    match query {
+        Query::Select { columns, condition, .. } => {
+            for expr in columns {
+                visit_expr(expr, f)?;
+            }
+            visit_optional(condition, f)
+        }
+        Query::SelectAggregate { condition, .. }
+        | Query::Join { condition, .. }
+        | Query::Delete { condition, .. } => visit_optional(condition, f),
+        Query::Insert { values, .. } => values.iter_mut().try_for_each(&mut *f),
+        Query::InsertRows { rows, .. } => rows.iter_mut().flatten().try_for_each(&mut *f),
+        Query::Update {
+            assignments, condition, ..
+        } => {
+            for (_, value) in assignments {
+                f(value)?;
+            }
+            visit_optional(condition, f)
+        }
+        Query::VerifyTable { condition, .. } => visit_condition(condition, f),
+        _ => Ok(()),
+    }
+}
+
+fn visit_optional(condition: &mut Option<Condition>, f: &mut Visitor) -> Result<(), DbError> {
+    match condition {
+        Some(condition) => visit_condition(condition, f),
+        None => Ok(()),
+    }
+}
+
+fn visit_condition(condition: &mut Condition, f: &mut Visitor) -> Result<(), DbError> {
+    match condition {
+        Condition::Equal(_, value)
+        | Condition::GreaterThan(_, value)
+        | Condition::LessThan(_, value)
+        | Condition::LessThanOrEqual(_, value)
+        | Condition::GreaterThanOrEqual(_, value)
+        | Condition::NotEqual(_, value) => f(value),
+        Condition::In(_, values) => values.iter_mut().try_for_each(&mut *f),
+        Condition::Between(_, low, high) => {
+            f(low)?;
+            f(high)
+        }
+        Condition::Like(_, pattern) => {
+            let mut source = Value::String(std::mem::take(pattern));
+            f(&mut source)?;
+            match source {
+                Value::String(s) => {
+                    *pattern = s;
+                    Ok(())
+                }
+                other => Err(DbError::QueryError(format!("LIKE pattern must be a string, got {:?}", other))),
+            }
+        }
+        Condition::IsNull(_) | Condition::IsNotNull(_) => Ok(()),
+        Condition::InTuple(_, tuples) => tuples.iter_mut().flatten().try_for_each(&mut *f),
+        Condition::Regexp(_, pattern) => {
+            // The pattern was compiled from the marker; recompile from the bound string.
+            let mut source = Value::String(pattern.as_str().to_string());
+            f(&mut source)?;
+            match source {
+                Value::String(s) if s == pattern.as_str() => Ok(()),
+                Value::String(s) => {
+                    *pattern = Pattern::new(&s)?;
+                    Ok(())
+                }
+                other => Err(DbError::QueryError(format!("REGEXP pattern must be a string, got {:?}", other))),
+            }
+        }
+        Condition::And(left, right) | Condition::Or(left, right) => {
+            visit_condition(left, f)?;
+            visit_condition(right, f)
+        }
+        Condition::Compare(left, _, right) => {
+            visit_expr(left, f)?;
+            visit_expr(right, f)
+        }
+        Condition::Not(inner) => visit_condition(inner, f),
+        Condition::InSubquery(_, query) => visit_query(query, f),
+        Condition::CompareSubquery(left, _, query) => {
+            visit_expr(left, f)?;
+            visit_query(query, f)
+        }
+    }
+}
+
+fn visit_expr(expr: &mut Expr, f: &mut Visitor) -> Result<(), DbError> {
+    match expr {
+        Expr::Literal(value) => f(value),
+        Expr::Coalesce(args) => args.iter_mut().try_for_each(|arg| visit_expr(arg, f)),
+        Expr::NullIf(value, other) => {
+            visit_expr(value, f)?;
+            visit_expr(other, f)
+        }
+        Expr::Mask(_, value) | Expr::Alias(value, _) => visit_expr(value, f),
+        Expr::Arithmetic(_, left, right) => {
+            visit_expr(left, f)?;
+            visit_expr(right, f)
+        }
+        Expr::CurrentTimestamp | Expr::CurrentDate | Expr::Column(_) => Ok(()),
+    }
+}
@@ -0,0 +1,180 @@
+use crate::query::batch::Batch;
+use crate::query::planner::{bigint_sum, integer};
+use crate::query::{Aggregation, Condition};
+use crate::schema::metadata::BlockInfo;
+use crate::schema::Table;
+use crate::storage::StorageManager;
+use crate::types::{DataType, DbError, Value};
+use ordered_float::OrderedFloat;
+
+/// What one aggregation has seen of the blocks read so far.
+#[derive(Default)]
+struct Partial {
+    rows: usize,
+    /// Non-NULL values, and their sum when numeric.
+    present: usize,
+    sum: f64,
+    /// The exact sum of INT and BIGINT values.
+    integer_sum: i128,
+    min: Option<Value>,
+    max: Option<Value>,
+}
+
+impl Partial {
+    fn add_values(&mut self, rows: usize, values: &[Value]) {
+        self.rows += rows;
+        for value in values.iter().filter(|v| !v.is_null()) {
+            self.present += 1;
+            self.sum += value.as_f64().unwrap_or(0.0);
+            self.integer_sum += integer(value).unwrap_or(0);
+            self.add_bounds(value, value);
+        }
+    }
+
+    /// Takes a block's row count and bounds from its metadata, unread.
+    fn add_stats(&mut self, block: &BlockInfo) {
+        self.rows += block.row_count;
+        self.present += block.row_count - block.nulls.len();
+        if !block.min.is_null() {
+            self.add_bounds(&block.min, &block.max);
+        }
+    }
+
+    fn add_bounds(&mut self, min: &Value, max: &Value) {
+        if self.min.as_ref().is_none_or(|m| min < m) {
+            self.min = Some(min.clone());
+        }
+        if self.max.as_ref().is_none_or(|m| max > m) {
+            self.max = Some(max.clone());
+        }
+    }
+
+    /// The aggregate's value, as `compute_aggregate` gives it for the same rows.
+    fn finish(self, agg: &Aggregation, data_type: Option<&DataType>) -> Result<Value, DbError> {
+        let float = |v: f64| Value::Float32(OrderedFloat(v as f32));
+        Ok(match agg {
+            Aggregation::Count => Value::Int32(self.rows as i32),
+            Aggregation::CountColumn(_) => Value::Int32(self.present as i32),
+            Aggregation::Sum(_) if matches!(data_type, Some(DataType::Int32 | DataType::Int64)) => {
+                bigint_sum(self.integer_sum)?
+            }
+            Aggregation::Sum(_) => float(self.sum),
+            Aggregation::Avg(_) if self.present == 0 => float(0.0),
+            Aggregation::Avg(_) => float(self.sum / self.present as f64),
+            Aggregation::Min(_) => self.min.unwrap_or_else(|| float(0.0)),
+            Aggregation::Max(_) => self.max.unwrap_or_else(|| float(0.0)),
+            _ => unreachable!("only pushable aggregations reach a partial"),
+        })
+    }
+}
+
+/// True if every aggregation combines from per-block partials.
+pub(crate) fn pushable(aggregations: &[Aggregation]) -> bool {
+    aggregations.iter().all(|a| matches!(a, Aggregation::Count) || column(a).is_some())
+}
+
+/// The column a pushable aggregation reads; None for COUNT(*).
+fn column(agg: &Aggregation) -> Option<&String> {
+    match agg {
+        Aggregation::CountColumn(col)
+        | Aggregation::Sum(col)
+        | Aggregation::Avg(col)
+        | Aggregation::Min(col)
+        | Aggregation::Max(col) => Some(col),
+        _ => None,
+    }
+}
+
+/// Computes SUM/COUNT/AVG/MIN/MAX of a stored table one block at a time,
+/// so only a block's worth of the columns is decoded at once. Blocks the
+/// condition rules out are skipped; without a condition, COUNT/MIN/MAX take
+/// each flushed block's row count and bounds from its metadata instead.
+pub(crate) fn aggregate_blocks(
+    storage: &StorageManager,
+    table_def: &Table,
+    aggregations: &[Aggregation],
+    condition: Option<&Condition>,
+    use_indexes: bool,
+) -> Result<Vec<Value>, DbError> {
+    let table = &table_def.name;
+    for agg in aggregations {
+        let Some(col) = column(agg) else { continue };
+        let col_def = table_def
+            .get_column(col)
+            .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table, col)))?;
+        let name = match agg {
+            Aggregation::Sum(_) => "SUM",
+            Aggregation::Avg(_) => "AVG",
+            _ => continue,
+        };
+        if !col_def.data_type.is_numeric() {
+            return Err(DbError::InvalidData(format!("{} not supported for type {:?}", name, col_def.data_type)));
+        }
+    }
+    let condition_columns = condition.map(crate::query::collect_condition_columns).unwrap_or_default();
+    let table_cols = &storage.columns[table];
+    // The unflushed rows have no metadata, so are always read.
+    let stats = |block: usize, agg: &Aggregation| {
+        let col = match agg {
+            Aggregation::Count => &table_def.columns[0].name,
+            Aggregation::CountColumn(col) | Aggregation::Min(col) | Aggregation::Max(col) => col,
+            _ => return None,
+        };
+        table_cols.get(col).and_then(|c| c.metadata.blocks.get(block))
+    };
+
+    let mut partials: Vec<Partial> = aggregations.iter().map(|_| Partial::default()).collect();
+    let keep = storage.block_mask_with(table, condition, use_indexes);
+    for block in 0..=storage.block_count(table) {
+        if keep.as_ref().is_some_and(|k| !k.get(block).copied().unwrap_or(true)) {
+            continue;
+        }
+        let mut read = Vec::new();
+        for (i, agg) in aggregations.iter().enumerate() {
+            match stats(block, agg).filter(|_| condition.is_none()) {
+                Some(info) => partials[i].add_stats(info),
+                None => read.push(i),
+            }
+        }
+        if read.is_empty() {
+            continue;
+        }
+        let mut required: Vec<String> = read.iter().filter_map(|&i| column(&aggregations[i])).cloned().collect();
+        required.extend(condition_columns.iter().cloned());
+        if required.is_empty() {
+            // COUNT(*) alone still needs a column to count the rows of.
+            required.push(table_def.columns[0].name.clone());
+        }
+        required.sort();
+        required.dedup();
+        let mut vectors = Vec::with_capacity(required.len());
+        for col in &required {
+            vectors.push(storage.read_block_vector(table, col, block)?);
+        }
+        let row_count = vectors.iter().map(|v| v.len()).min().unwrap_or(0);
+        let mut batch = Batch::new(row_count);
+        batch.collations = table_def.collations();
+        for (col, vector) in required.iter().zip(vectors) {
+            batch.add_vector(col, vector);
+        }
+        let mut selection = batch.select_all();
+        if let Some(cond) = condition {
+            selection = batch.filter(cond, &selection)?;
+        }
+        for i in read {
+            let values = match column(&aggregations[i]) {
+                Some(col) => batch.values(col, &selection)?,
+                None => Vec::new(),
+            };
+            partials[i].add_values(selection.len(), &values);
+        }
+    }
+    partials
+        .into_iter()
+        .zip(aggregations)
+        .map(|(partial, agg)| {
+            let data_type = column(agg).and_then(|col| table_def.get_column(col)).map(|c| &c.data_type);
+            partial.finish(agg, data_type)
+        })
+        .collect()
+}
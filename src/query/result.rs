@@ -0,0 +1,303 @@
+use crate::query::expr::Expr;
+use crate::query::system::system_table;
+use crate::query::{Aggregation, GroupKey, Query};
+use crate::schema::{Schema, Table};
+use crate::types::{DataType, Value};
+use serde::{Deserialize, Serialize};
+
+/// Name and type of one output column. The type is `None` when neither the
+/// schema nor the rows tell, e.g. an expression that is NULL in every row.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ResultColumn {
+    pub name: String,
+    pub data_type: Option<DataType>,
+}
+
+/// The rows a statement returned, with the columns they are made of.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<ResultColumn>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl QueryResult {
+    pub fn new(mut columns: Vec<ResultColumn>, rows: Vec<Vec<Value>>) -> Self {
+        // Computed columns take the type of the values they produced, if they agree.
+        for (i, column) in columns.iter_mut().enumerate() {
+            if column.data_type.is_none() {
+                let mut types = rows.iter().filter_map(|row| row.get(i).and_then(Value::data_type));
+                let first = types.next();
+                column.data_type = first.filter(|first| types.all(|t| t == *first));
+            }
+        }
+        QueryResult { columns, rows }
+    }
+
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+}
+
+fn column(name: &str, data_type: Option<DataType>) -> ResultColumn {
+    ResultColumn {
+        name: name.to_string(),
+        data_type,
+    }
+}
+
+fn fixed(columns: &[(&str, DataType)]) -> Vec<ResultColumn> {
+    columns.iter().map(|(name, data_type)| column(name, Some(data_type.clone()))).collect()
+}
+
+fn declared_type(table: Option<&Table>, name: &str) -> Option<DataType> {
+    table.and_then(|t| t.get_column(name)).map(|c| c.data_type.clone())
+}
+
+fn table_columns(table: Option<&Table>) -> Vec<ResultColumn> {
+    table
+        .map(|t| t.columns.iter().map(|c| column(&c.name, Some(c.data_type.clone()))).collect())
+        .unwrap_or_default()
+}
+
+fn returning_columns(table: Option<&Table>, returning: &[String]) -> Vec<ResultColumn> {
+    returning
+        .iter()
+        .flat_map(|name| match name.as_str() {
+            "*" => table_columns(table),
+            _ => vec![column(name, declared_type(table, name))],
+        })
+        .collect()
+}
+
+/// The SQL text an expression was written as, used as its column name.
+pub(crate) fn expr_name(expr: &Expr) -> String {
+    match expr {
+        Expr::Literal(Value::String(s)) => format!("\"{}\"", s),
+        Expr::Literal(value) => value.to_string(),
+        Expr::CurrentTimestamp => "NOW()".to_string(),
+        Expr::CurrentDate => "CURRENT_DATE".to_string(),
+        Expr::Column(name) => name.clone(),
+        Expr::Coalesce(args) => {
+            format!("COALESCE({})", args.iter().map(expr_name).collect::<Vec<_>>().join(", "))
+        }
+        Expr::NullIf(value, other) => format!("NULLIF({}, {})", expr_name(value), expr_name(other)),
+        Expr::Mask(mask, value) => format!("{}({})", mask.name(), expr_name(value)),
+        Expr::Alias(_, alias) => alias.clone(),
+        Expr::Arithmetic(op, left, right) => {
+            // Parentheses only where the operands would otherwise regroup.
+            let operand = |expr: &Expr, right_side: bool| match expr {
+                Expr::Arithmetic(inner, ..)
+                    if inner.precedence() < op.precedence() || right_side && inner.precedence() == op.precedence() =>
+                {
+                    format!("({})", expr_name(expr))
+                }
+                _ => expr_name(expr),
+            };
+            format!("{} {} {}", operand(left, false), op, operand(right, true))
+        }
+    }
+}
+
+/// The type `expr` produces, given the declared type of each column it reads.
+fn expr_type(expr: &Expr, column_type: &dyn Fn(&str) -> Option<DataType>) -> Option<DataType> {
+    match expr {
+        Expr::Literal(value) => value.data_type(),
+        Expr::CurrentTimestamp => Some(DataType::Timestamp),
+        Expr::CurrentDate => Some(DataType::Date),
+        Expr::Column(name) => column_type(name),
+        Expr::Coalesce(args) => args.iter().find_map(|arg| expr_type(arg, column_type)),
+        Expr::NullIf(value, _) => expr_type(value, column_type),
+        Expr::Mask(..) => Some(DataType::String),
+        Expr::Alias(value, _) => expr_type(value, column_type),
+        // Numbers promote as in `expr::promote`.
+        Expr::Arithmetic(_, left, right) => match (expr_type(left, column_type), expr_type(right, column_type)) {
+            (Some(DataType::Float32), _) | (_, Some(DataType::Float32)) => Some(DataType::Float32),
+            (Some(DataType::Int64), _) | (_, Some(DataType::Int64)) => Some(DataType::Int64),
+            (left, right) => left.or(right),
+        },
+    }
+}
+
+fn aggregation_column(aggregation: &Aggregation, table: Option<&Table>) -> ResultColumn {
+    match aggregation {
+        Aggregation::Count => column("COUNT(*)", Some(DataType::Int32)),
+        Aggregation::CountColumn(col) => column(&format!("COUNT({})", col), Some(DataType::Int32)),
+        Aggregation::CountDistinct(col) => column(&format!("COUNT(DISTINCT {})", col), Some(DataType::Int32)),
+        Aggregation::Sum(col) => {
+            let integer = matches!(declared_type(table, col), Some(DataType::Int32 | DataType::Int64));
+            column(&format!("SUM({})", col), integer.then_some(DataType::Int64))
+        }
+        Aggregation::Avg(col) => column(&format!("AVG({})", col), None),
+        Aggregation::Min(col) => column(&format!("MIN({})", col), declared_type(table, col)),
+        Aggregation::Max(col) => column(&format!("MAX({})", col), declared_type(table, col)),
+        Aggregation::ApproxCountDistinct(col) => column(&format!("APPROX_COUNT_DISTINCT({})", col), None),
+        Aggregation::ApproxQuantile(col, q) => column(&format!("APPROX_QUANTILE({}, {})", col, q), None),
+        Aggregation::Percentile(col, p) => column(&format!("PERCENTILE({}, {})", col, p), None),
+        Aggregation::Histogram(col, buckets) => column(&format!("HISTOGRAM({}, {})", col, buckets), None),
+        Aggregation::First(col, order_by) => {
+            column(&format!("FIRST({} ORDER BY {})", col, order_by), declared_type(table, col))
+        }
+        Aggregation::Last(col, order_by) => {
+            column(&format!("LAST({} ORDER BY {})", col, order_by), declared_type(table, col))
+        }
+    }
+}
+
+/// Output columns of `query`, resolved against `schema` as it is before the
+/// statement runs; empty for statements that return no rows.
+pub fn result_columns(query: &Query, schema: &Schema) -> Vec<ResultColumn> {
+    match query {
+        Query::Select { table, columns, .. } => {
+            let system = system_table(table);
+            let table = schema.get_table(table).or(system.as_ref());
+            if columns.is_empty() {
+                return table_columns(table);
+            }
+            let column_type = |name: &str| declared_type(table, name);
+            columns.iter().map(|expr| column(&expr_name(expr), expr_type(expr, &column_type))).collect()
+        }
+        Query::SelectAggregate {
+            table,
+            aggregations,
+            group_by,
+            ..
+        } => {
+            let table = schema.get_table(table);
+            if let [Aggregation::Histogram(col, _)] = aggregations.as_slice() {
+                let bound = declared_type(table, col).map(|_| DataType::Float32);
+                return vec![
+                    column("lower", bound.clone()),
+                    column("upper", bound),
+                    column("count", Some(DataType::Int32)),
+                ];
+            }
+            let keys = group_by.iter().map(|key| match key {
+                GroupKey::Column(name) => column(name, declared_type(table, name)),
+                GroupKey::TimeBucket { column: name, seconds } => {
+                    column(&format!("TIME_BUCKET({}s, {})", seconds, name), declared_type(table, name))
+                }
+            });
+            keys.chain(aggregations.iter().map(|a| aggregation_column(a, table))).collect()
+        }
+        Query::Join {
+            left_table,
+            right_table,
+            columns,
+            ..
+        } => {
+            let column_type = |name: &str| match name.split_once('.') {
+                Some((table, col)) if table == left_table || table == right_table => {
+                    declared_type(schema.get_table(table), col)
+                }
+                Some(_) => None,
+                None => declared_type(schema.get_table(left_table), name)
+                    .or_else(|| declared_type(schema.get_table(right_table), name)),
+            };
+            columns.iter().map(|expr| column(&expr_name(expr), expr_type(expr, &column_type))).collect()
+        }
+        Query::Insert { table, returning, .. }
+        | Query::InsertRows { table, returning, .. }
+        | Query::Delete { table, returning, .. } => {
+            returning_columns(schema.get_table(table), returning)
+        }
+        Query::VerifyTable { table, .. } => table_columns(schema.get_table(table)),
+        Query::ShowTableSize { .. } => fixed(&[
+            ("column", DataType::String),
+            ("blocks", DataType::Int32),
+            ("rows", DataType::Int32),
+            ("disk_bytes", DataType::Int64),
+            ("compressed_bytes", DataType::Int64),
+            ("raw_bytes", DataType::Int64),
+            ("index_bytes", DataType::Int64),
+        ]),
+        Query::ShowDatabaseSize => fixed(&[
+            ("table", DataType::String),
+            ("blocks", DataType::Int32),
+            ("disk_bytes", DataType::Int64),
+            ("compressed_bytes", DataType::Int64),
+            ("raw_bytes", DataType::Int64),
+            ("index_bytes", DataType::Int64),
+        ]),
+        Query::ShowStorageReport { .. } => fixed(&[
+            ("table", DataType::String),
+            ("column", DataType::String),
+            ("codec", DataType::String),
+            ("blocks", DataType::Int32),
+            ("rows", DataType::Int32),
+            ("raw_bytes", DataType::Int64),
+            ("compressed_bytes", DataType::Int64),
+            ("ratio", DataType::Float32),
+            ("avg_block_bytes", DataType::Int64),
+        ]),
+        Query::ShowIndexes { .. } => fixed(&[
+            ("table", DataType::String),
+            ("column", DataType::String),
+            ("keys", DataType::Int32),
+            ("entries", DataType::Int32),
+            ("disk_bytes", DataType::Int64),
+            ("unsaved", DataType::Boolean),
+        ]),
+        Query::ShowTables => fixed(&[
+            ("table", DataType::String),
+            ("columns", DataType::Int32),
+            ("rows", DataType::Int32),
+        ]),
+        Query::Backup { .. } => fixed(&[
+            ("path", DataType::String),
+            ("files", DataType::Int64),
+            ("bytes", DataType::Int64),
+        ]),
+        Query::ExportTable { .. } | Query::ImportTable { .. } | Query::CopyFrom { .. } | Query::Update { .. } => {
+            fixed(&[("table", DataType::String), ("rows", DataType::Int32)])
+        }
+        Query::Explain { .. } => fixed(&[("plan", DataType::String)]),
+        Query::AsOfSnapshot { query, .. } => result_columns(query, schema),
+        Query::CopyTo { .. } => fixed(&[("path", DataType::String), ("rows", DataType::Int32)]),
+        Query::ShowSettings => fixed(&[
+            ("setting", DataType::String),
+            ("value", DataType::String),
+            ("source", DataType::String),
+        ]),
+        Query::Flush => fixed(&[("rows", DataType::Int32)]),
+        Query::Compact { .. } => fixed(&[
+            ("table", DataType::String),
+            ("blocks_before", DataType::Int32),
+            ("blocks_after", DataType::Int32),
+            ("bytes_before", DataType::Int32),
+            ("bytes_after", DataType::Int32),
+        ]),
+        Query::SalvageTable { .. } => fixed(&[
+            ("table", DataType::String),
+            ("rows", DataType::Int32),
+            ("skipped_blocks", DataType::Int32),
+        ]),
+        Query::Preload { .. } => fixed(&[
+            ("table", DataType::String),
+            ("columns", DataType::Int32),
+            ("blocks", DataType::Int32),
+            ("cached_bytes", DataType::Int32),
+        ]),
+        Query::Cleanup { .. } => fixed(&[("file", DataType::String), ("action", DataType::String)]),
+        Query::Analyze { .. } => vec![
+            column("column", Some(DataType::String)),
+            column("distinct", Some(DataType::Int32)),
+            column("min", None),
+            column("max", None),
+        ],
+        Query::CreateTable { .. }
+        | Query::CreateExternalTable { .. }
+        | Query::DropTable { .. }
+        | Query::UndropTable { .. }
+        | Query::AlterTable { .. }
+        | Query::MakeIndex { .. }
+        | Query::DropIndex { .. }
+        | Query::Set { .. }
+        | Query::CreateSnapshot { .. }
+        | Query::DropSnapshot { .. }
+        | Query::CreateJob { .. }
+        | Query::DropJob { .. }
+        | Query::StartTransaction
+        | Query::Commit
+        | Query::Rollback => Vec::new(),
+    }
+}
@@ -0,0 +1,139 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const HLL_PRECISION: u32 = 12;
+const HLL_REGISTERS: usize = 1 << HLL_PRECISION;
+
+/// HyperLogLog distinct-count sketch with 2^12 registers (~1.6% standard error).
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HyperLogLog {
+    pub fn new() -> Self {
+        HyperLogLog {
+            registers: vec![0; HLL_REGISTERS],
+        }
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, value: &T) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        let index = (hash >> (64 - HLL_PRECISION)) as usize;
+        let rest = hash << HLL_PRECISION;
+        let rank = (rest.leading_zeros().min(64 - HLL_PRECISION) + 1) as u8;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = HLL_REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = self.registers.iter().map(|&r| 2f64.powi(-(r as i32))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            // Linear counting is far more accurate for small cardinalities.
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            raw.round() as u64
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Centroid {
+    mean: f64,
+    count: f64,
+}
+
+/// Merging t-digest for approximate quantiles over numeric columns.
+pub struct TDigest {
+    compression: f64,
+    centroids: Vec<Centroid>,
+    buffer: Vec<f64>,
+    total: f64,
+}
+
+impl TDigest {
+    pub fn new(compression: f64) -> Self {
+        TDigest {
+            compression,
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            total: 0.0,
+        }
+    }
+
+    pub fn insert(&mut self, value: f64) {
+        self.buffer.push(value);
+        if self.buffer.len() as f64 >= self.compression * 10.0 {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut incoming: Vec<Centroid> = self
+            .centroids
+            .drain(..)
+            .chain(self.buffer.drain(..).map(|v| Centroid { mean: v, count: 1.0 }))
+            .collect();
+        incoming.sort_by(|a, b| a.mean.total_cmp(&b.mean));
+        self.total = incoming.iter().map(|c| c.count).sum();
+
+        let mut merged: Vec<Centroid> = Vec::new();
+        let mut cumulative = 0.0;
+        for c in incoming {
+            if let Some(last) = merged.last_mut() {
+                let q = (cumulative + (last.count + c.count) / 2.0) / self.total;
+                let limit = (4.0 * self.total * q * (1.0 - q) / self.compression).max(1.0);
+                if last.count + c.count <= limit {
+                    last.mean += (c.mean - last.mean) * c.count / (last.count + c.count);
+                    last.count += c.count;
+                    continue;
+                }
+                cumulative += last.count;
+            }
+            merged.push(c);
+        }
+        self.centroids = merged;
+    }
+
+    /// Estimated value at quantile `q` in [0, 1], or `None` if nothing was inserted.
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        self.flush();
+        let first = self.centroids.first()?;
+        if self.centroids.len() == 1 {
+            return Some(first.mean);
+        }
+        let target = q.clamp(0.0, 1.0) * self.total;
+        let mut cumulative = 0.0;
+        let mut prev: Option<(f64, f64)> = None;
+        for c in &self.centroids {
+            let center = cumulative + c.count / 2.0;
+            if target <= center {
+                return Some(match prev {
+                    Some((prev_center, prev_mean)) => {
+                        let t = (target - prev_center) / (center - prev_center);
+                        prev_mean + t * (c.mean - prev_mean)
+                    }
+                    None => c.mean,
+                });
+            }
+            prev = Some((center, c.mean));
+            cumulative += c.count;
+        }
+        self.centroids.last().map(|c| c.mean)
+    }
+}
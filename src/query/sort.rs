@@ -0,0 +1,64 @@
+use crate::query::batch::{Batch, Selection};
+use crate::query::SortOrder;
+use crate::types::{DbError, Value};
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// One ORDER BY key. Every row has the same direction at a given key
+/// position, so the derived ordering never compares `Asc` with `Desc`.
+/// NULL sorts last ascending and first descending.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum SortKey {
+    Asc(Value),
+    Desc(Reverse<Value>),
+}
+
+/// Orders `selection` by `order_by`, ties kept in row order. With `keep`
+/// only the first `keep` rows are produced, using a bounded heap instead of
+/// sorting every row.
+pub fn order_selection(
+    batch: &Batch,
+    order_by: &[(String, SortOrder)],
+    selection: &[u32],
+    keep: Option<usize>,
+) -> Result<Selection, DbError> {
+    let vectors = order_by
+        .iter()
+        .map(|(col, order)| batch.column(col).map(|v| (v, order, batch.collation(col))))
+        .collect::<Result<Vec<_>, _>>()?;
+    let key = |row: u32| -> (Vec<SortKey>, u32) {
+        let keys = vectors
+            .iter()
+            .map(|(vector, order, collation)| {
+                let value = collation.key(&vector.value(row as usize));
+                match order {
+                    SortOrder::Asc => SortKey::Asc(value),
+                    SortOrder::Desc => SortKey::Desc(Reverse(value)),
+                }
+            })
+            .collect();
+        (keys, row)
+    };
+
+    match keep {
+        Some(k) if k < selection.len() => {
+            // Max-heap of the k smallest keys seen so far.
+            let mut heap = BinaryHeap::with_capacity(k + 1);
+            for &row in selection {
+                let entry = key(row);
+                if heap.len() < k {
+                    heap.push(entry);
+                } else if heap.peek().is_some_and(|top| entry < *top) {
+                    heap.pop();
+                    heap.push(entry);
+                }
+            }
+            Ok(heap.into_sorted_vec().into_iter().map(|(_, row)| row).collect())
+        }
+        _ => {
+            let mut keyed: Vec<_> = selection.iter().map(|&row| key(row)).collect();
+            keyed.sort_unstable();
+            Ok(keyed.into_iter().map(|(_, row)| row).collect())
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::query::batch::Batch;
+use crate::query::expr::Expr;
+use crate::query::log::QueryLog;
+use crate::query::mask::{apply_masks, MaskFunction};
+use crate::query::planner::project_rows;
+use crate::query::result::{QueryResult, ResultColumn};
+use crate::query::Condition;
+use crate::schema::Table;
+use crate::storage::StorageManager;
+use crate::types::{DbError, Value};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// The rows of one statement, produced as they are read. Iterating yields
+/// each row or the error that ended the statement; nothing follows an error.
+pub struct RowStream {
+    columns: Vec<ResultColumn>,
+    source: Source,
+    rows: usize,
+    /// Where the statement is logged once its rows run out or the stream is
+    /// dropped, with its text and start.
+    log: Option<(Arc<QueryLog>, String, Instant)>,
+}
+
+enum Source {
+    /// Rows of a statement that ran in full before its stream was returned.
+    Rows(std::vec::IntoIter<Vec<Value>>),
+    Blocks(Box<BlockReader>),
+}
+
+impl RowStream {
+    pub(crate) fn blocks(columns: Vec<ResultColumn>, reader: BlockReader) -> Self {
+        RowStream {
+            columns,
+            source: Source::Blocks(Box::new(reader)),
+            rows: 0,
+            log: None,
+        }
+    }
+
+    /// Logs the statement as `text` started at `start` when it finishes.
+    pub(crate) fn logged(mut self, log: Arc<QueryLog>, text: &str, start: Instant) -> Self {
+        self.log = Some((log, text.to_string(), start));
+        self
+    }
+
+    pub fn columns(&self) -> &[ResultColumn] {
+        &self.columns
+    }
+
+    pub fn column_names(&self) -> Vec<&str> {
+        self.columns.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    /// Reads the remaining rows into a result.
+    pub fn collect_result(mut self) -> Result<QueryResult, DbError> {
+        let rows = self.by_ref().collect::<Result<Vec<_>, _>>()?;
+        Ok(QueryResult::new(std::mem::take(&mut self.columns), rows))
+    }
+
+    fn finish(&mut self, error: Option<&DbError>) {
+        if let Some((log, text, start)) = self.log.take() {
+            log.record(&text, start.elapsed(), error.map_or(Ok(self.rows), Err));
+        }
+    }
+}
+
+impl From<QueryResult> for RowStream {
+    fn from(result: QueryResult) -> Self {
+        RowStream {
+            columns: result.columns,
+            source: Source::Rows(result.rows.into_iter()),
+            rows: 0,
+            log: None,
+        }
+    }
+}
+
+impl Iterator for RowStream {
+    type Item = Result<Vec<Value>, DbError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = match &mut self.source {
+            Source::Rows(rows) => rows.next().map(Ok),
+            Source::Blocks(reader) => reader.next(),
+        };
+        match &item {
+            Some(Ok(_)) => self.rows += 1,
+            Some(Err(e)) => self.finish(Some(e)),
+            None => self.finish(None),
+        }
+        item
+    }
+}
+
+impl Drop for RowStream {
+    fn drop(&mut self) {
+        self.finish(None);
+    }
+}
+
+/// A plain SELECT run a block at a time against a snapshot, so only one
+/// block's rows are in memory however large the table is.
+pub(crate) struct BlockReader {
+    pub storage: StorageManager,
+    pub table: Table,
+    pub columns: Vec<Expr>,
+    /// Columns read from each block: the projected ones and the condition's.
+    pub required: Vec<String>,
+    pub condition: Option<Condition>,
+    /// Block positions left to read; the last may be the unflushed rows.
+    pub blocks: std::vec::IntoIter<usize>,
+    /// Matching rows still to skip for OFFSET, and to return for LIMIT.
+    pub skip: usize,
+    pub limit: Option<usize>,
+    pub masks: Option<Vec<Option<MaskFunction>>>,
+    pub ready: std::vec::IntoIter<Vec<Value>>,
+}
+
+impl BlockReader {
+    fn next(&mut self) -> Option<Result<Vec<Value>, DbError>> {
+        loop {
+            if let Some(row) = self.ready.next() {
+                return Some(Ok(row));
+            }
+            if self.limit == Some(0) {
+                return None;
+            }
+            let block = self.blocks.next()?;
+            match self.read(block) {
+                Ok(rows) => self.ready = rows.into_iter(),
+                Err(e) => {
+                    self.blocks = Vec::new().into_iter();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+
+    /// The rows of block `block` that match and fall within OFFSET and LIMIT.
+    fn read(&mut self, block: usize) -> Result<Vec<Vec<Value>>, DbError> {
+        let mut vectors = Vec::with_capacity(self.required.len());
+        for col in &self.required {
+            vectors.push(self.storage.read_block_vector(&self.table.name, col, block)?);
+        }
+        let row_count = vectors.iter().map(|v| v.len()).min().unwrap_or(0);
+        let mut batch = Batch::new(row_count);
+        batch.collations = self.table.collations();
+        for (col, vector) in self.required.iter().zip(vectors) {
+            batch.add_vector(col, vector);
+        }
+        let mut selection = batch.select_all();
+        if let Some(cond) = &self.condition {
+            selection = batch.filter(cond, &selection)?;
+        }
+        let skipped = self.skip.min(selection.len());
+        selection.drain(..skipped);
+        self.skip -= skipped;
+        if let Some(limit) = &mut self.limit {
+            selection.truncate(*limit);
+            *limit -= selection.len();
+        }
+        let rows = project_rows(&batch, &self.columns, &selection)?;
+        Ok(match &self.masks {
+            Some(masks) => apply_masks(rows, masks),
+            None => rows,
+        })
+    }
+}
@@ -0,0 +1,157 @@
+use crate::schema::{Column, Table, Validation};
+use crate::storage::settings::Role;
+use crate::storage::StorageManager;
+use crate::types::{DataType, DbError, Value};
+
+/// One row per histogram bucket and per frequent value of every analyzed column.
+pub const COLUMN_STATS: &str = "information_schema.column_stats";
+
+/// One row per job created with `CREATE JOB`.
+pub const JOBS: &str = "information_schema.jobs";
+
+/// One row per migration a `Migrator` has applied.
+pub const MIGRATIONS: &str = "information_schema.migrations";
+
+/// One row per recent statement, oldest first; see `QueryLog`.
+pub const QUERY_LOG: &str = "system.query_log";
+
+/// Read-only tables the database derives from its own metadata. They are
+/// queried like any table but have no files and are never cached.
+pub fn is_system_table(name: &str) -> bool {
+    [COLUMN_STATS, JOBS, MIGRATIONS, QUERY_LOG].iter().any(|table| name.eq_ignore_ascii_case(table))
+}
+
+pub fn system_table(name: &str) -> Option<Table> {
+    let columns = if name.eq_ignore_ascii_case(COLUMN_STATS) {
+        vec![
+            Column::new("table", DataType::String),
+            Column::new("column", DataType::String),
+            // "histogram" or "top_value".
+            Column::new("kind", DataType::String),
+            Column::new("position", DataType::Int32),
+            Column::new("lower", DataType::String),
+            Column::new("upper", DataType::String),
+            Column::new("rows", DataType::Int32),
+        ]
+    } else if name.eq_ignore_ascii_case(JOBS) {
+        vec![
+            Column::new("name", DataType::String),
+            // Seconds between runs.
+            Column::new("every", DataType::Int64),
+            Column::new("statement", DataType::String),
+            Column::new("next_run", DataType::Timestamp),
+            Column::new("last_run", DataType::Timestamp),
+            Column::new("last_status", DataType::String),
+        ]
+    } else if name.eq_ignore_ascii_case(MIGRATIONS) {
+        vec![
+            Column::new("version", DataType::Int64),
+            Column::new("name", DataType::String),
+            Column::new("applied_at", DataType::Timestamp),
+        ]
+    } else if name.eq_ignore_ascii_case(QUERY_LOG) {
+        vec![
+            Column::new("id", DataType::Int64),
+            Column::new("started_at", DataType::Timestamp),
+            Column::new("text", DataType::String),
+            Column::new("duration_us", DataType::Int64),
+            Column::new("rows", DataType::Int64),
+            // "ok" or "error".
+            Column::new("status", DataType::String),
+            Column::new("error", DataType::String),
+        ]
+    } else {
+        return None;
+    };
+    Some(Table {
+        name: name.to_string(),
+        columns,
+        row_count: 0,
+        location: None,
+        validation: Validation::Strict,
+        next_column_id: 0,
+    })
+}
+
+/// Rows of a system table, in the column order of `system_table`. Values of
+/// differently typed columns share `lower` and `upper`, so they are text.
+pub fn system_rows(storage: &StorageManager, name: &str) -> Result<Vec<Vec<Value>>, DbError> {
+    let mut rows = Vec::new();
+    if name.eq_ignore_ascii_case(JOBS) {
+        let timestamp = |seconds: Option<u64>| seconds.map_or(Value::Null, |s| Value::Timestamp(s as i64));
+        for job in storage.jobs()? {
+            rows.push(vec![
+                Value::String(job.name),
+                Value::Int64(job.every as i64),
+                Value::String(job.statement),
+                timestamp(Some(job.next_run)),
+                timestamp(job.last_run),
+                job.last_status.map_or(Value::Null, Value::String),
+            ]);
+        }
+        return Ok(rows);
+    }
+    if name.eq_ignore_ascii_case(MIGRATIONS) {
+        for migration in storage.applied_migrations()? {
+            rows.push(vec![
+                Value::Int64(migration.version as i64),
+                Value::String(migration.name),
+                Value::Timestamp(migration.applied_at as i64),
+            ]);
+        }
+        return Ok(rows);
+    }
+    if name.eq_ignore_ascii_case(QUERY_LOG) {
+        for query in storage.query_log().entries() {
+            rows.push(vec![
+                Value::Int64(query.id as i64),
+                Value::Timestamp(query.started_at as i64),
+                Value::String(query.text),
+                Value::Int64(query.duration.as_micros() as i64),
+                Value::Int64(query.rows as i64),
+                Value::String(if query.error.is_some() { "error" } else { "ok" }.to_string()),
+                query.error.map_or(Value::Null, Value::String),
+            ]);
+        }
+        return Ok(rows);
+    }
+    if !name.eq_ignore_ascii_case(COLUMN_STATS) {
+        return Ok(rows);
+    }
+    let mut tables: Vec<&Table> = storage.schema().tables().collect();
+    tables.sort_by(|a, b| a.name.cmp(&b.name));
+    for table in tables {
+        let Some(stats) = storage.table_statistics(&table.name)? else {
+            continue;
+        };
+        for col in &table.columns {
+            let Some(col_stats) = stats.columns.get(&col.name) else {
+                continue;
+            };
+            // Frequent values and bucket bounds are column values too.
+            let mask = col.mask.filter(|_| storage.settings().role == Role::Restricted);
+            let text = |value: &Value| match mask {
+                Some(mask) => mask.apply(value),
+                None => Value::String(value.to_string()),
+            };
+            let row = |kind: &str, position: usize, lower: &Value, upper: &Value, count: u64| {
+                vec![
+                    Value::String(table.name.clone()),
+                    Value::String(col.name.clone()),
+                    Value::String(kind.to_string()),
+                    Value::Int32(position as i32 + 1),
+                    text(lower),
+                    text(upper),
+                    Value::Int32(count as i32),
+                ]
+            };
+            for (i, bucket) in col_stats.histogram.iter().enumerate() {
+                rows.push(row("histogram", i, &bucket.lower, &bucket.upper, bucket.count));
+            }
+            for (i, (value, count)) in col_stats.top_values.iter().enumerate() {
+                rows.push(row("top_value", i, value, value, *count));
+            }
+        }
+    }
+    Ok(rows)
+}
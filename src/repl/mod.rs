@@ -1,15 +1,62 @@
+pub mod script;
+
 use crate::query::parser::parse_query;
+use crate::query::result::QueryResult;
+use crate::query::stream::RowStream;
+use crate::repl::script::{source_path, split_statements, split_terminated};
 use crate::query::Query;
 use crate::transaction::{Transaction, TransactionManager};
+use crate::datetime::{format_date, format_timestamp};
 use crate::types::{DbError, Value};
 use prettytable::{format, row, Table};
 use rustyline::{error::ReadlineError, Editor};
 use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal};
 use colored::*;
 
+/// Rows printed per table when the pager is off.
+const PAGE_ROWS: usize = 1000;
+
+/// Rows per page until `\set pagesize` changes it.
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Scripts SOURCE may nest, so one that sources itself fails.
+const MAX_SOURCE_DEPTH: usize = 16;
+
 pub struct Repl {
     tx_manager: TransactionManager,
     active_transaction: Option<Transaction>,
+    /// Rows shown before the pager waits; 0 turns it off.
+    page_size: usize,
+    /// Scripts being run by SOURCE, one inside the other.
+    sourcing: usize,
+}
+
+/// A backslash command, which changes the REPL itself rather than the database.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MetaCommand {
+    SetPageSize(usize),
+}
+
+impl MetaCommand {
+    /// Parses `input` without its leading backslash.
+    pub fn parse(input: &str) -> Result<Self, DbError> {
+        let words: Vec<&str> = input.split_whitespace().collect();
+        match words.as_slice() {
+            ["set", name, value] if name.eq_ignore_ascii_case("pagesize") => value
+                .parse()
+                .map(MetaCommand::SetPageSize)
+                .map_err(|_| DbError::QueryError(format!("Invalid page size: {}", value))),
+            _ => Err(DbError::QueryError(format!("Unknown command: \\{}", input))),
+        }
+    }
+}
+
+/// Whether `input` is the REPL command `name`, in any case and with or
+/// without the `;` that ends SQL statements.
+pub fn is_command(input: &str, name: &str) -> bool {
+    input.trim_end_matches(';').trim_end().eq_ignore_ascii_case(name)
 }
 
 // Implement Display for Value to match your enum variants
@@ -19,6 +66,11 @@ impl fmt::Display for Value {
             Value::Int32(i) => write!(f, "{}", i),
             Value::Float32(fl) => write!(f, "{}", fl.0),
             Value::String(s) => write!(f, "{}", s),
+            Value::Int64(i) => write!(f, "{}", i),
+            Value::Boolean(b) => write!(f, "{}", if *b { "TRUE" } else { "FALSE" }),
+            Value::Date(d) => write!(f, "{}", format_date(*d)),
+            Value::Timestamp(t) => write!(f, "{}", format_timestamp(*t)),
+            Value::Null => write!(f, "NULL"),
         }
     }
 }
@@ -28,11 +80,13 @@ impl Repl {
         Repl {
             tx_manager,
             active_transaction: None,
+            page_size: DEFAULT_PAGE_SIZE,
+            sourcing: 0,
         }
     }
 
     pub fn run(&mut self) -> Result<(), DbError> {
-        println!("{}","VDDB REPL (type EXIT to quit, type HELP for help)".cyan().bold());
+        println!("{}","VDDB REPL (end statements with ;, type EXIT to quit, type HELP for help)".cyan().bold());
         
         // Initialize rustyline editor with history
         let mut rl = Editor::<()>::new().map_err(|e| DbError::TransactionError(e.to_string()))?;
@@ -40,22 +94,34 @@ impl Repl {
             println!("No previous history found");
         }
 
+        // Lines of a statement whose `;` has not been typed yet.
+        let mut buffer = String::new();
         loop {
-            let readline = rl.readline(&"vddb> ".blue().bold().to_string());
+            let prompt = if buffer.is_empty() { "vddb> " } else { "...> " };
+            let readline = rl.readline(&prompt.blue().bold().to_string());
             match readline {
                 Ok(input) => {
                     let input = input.trim();
-                    if input.eq_ignore_ascii_case("EXIT") {
+                    if !buffer.is_empty() {
+                        if !input.is_empty() {
+                            buffer.push('\n');
+                            buffer.push_str(input);
+                            self.run_buffered(&mut buffer, &mut rl);
+                        }
+                        continue;
+                    }
+                    if is_command(input, "EXIT") {
                         if let Some(tx) = self.active_transaction.take() {
                             self.tx_manager.rollback_transaction(tx)?;
                             println!("{}","Active transaction rolled back.".green());
                         }
                         rl.save_history("vddb_history.txt")
                             .map_err(|e| DbError::TransactionError(e.to_string()))?;
+                        self.tx_manager.flush()?;
                         break;
                     }
                     
-                    if input.eq_ignore_ascii_case("HELP") {
+                    if is_command(input, "HELP") {
                         self.print_help();
                         continue;
                     }
@@ -64,60 +130,23 @@ impl Repl {
                         continue;
                     }
 
-                    // Add to history
-                    rl.add_history_entry(input);
-
-                    match parse_query(input) {
-                        Ok(query) => {
-                            match query {
-                                Query::StartTransaction => {
-                                    if self.active_transaction.is_some() {
-                                        println!("{}","Error: Transaction already active".red());
-                                        continue;
-                                    }
-                                    self.active_transaction = Some(self.tx_manager.begin_transaction());
-                                    println!("{}", "Transaction started.".green());
-                                }
-                                Query::Commit => {
-                                    if let Some(tx) = self.active_transaction.take() {
-                                        match self.tx_manager.commit_transaction(tx) {
-                                            Ok(results) => {
-                                                self.print_results(&results);
-                                                println!("{}", "Transaction committed.".green());
-                                            }
-                                            Err(e) => println!("{}: {}", "Error".red().bold(), e),
-                                        }
-                                    } else {
-                                        println!("{}", "Error: No active transaction".red());
-                                    }
-                                }
-                                Query::Rollback => {
-                                    if let Some(tx) = self.active_transaction.take() {
-                                        self.tx_manager.rollback_transaction(tx)?;
-                                        println!("{}", "Transaction rolled back.".green());
-                                    } else {
-                                        println!("{}", "Error: No active transaction".red());
-                                    }
-                                }
-                                _ => {
-                                    if let Some(ref mut tx) = self.active_transaction {
-                                        tx.add_query(query);
-                                    } else {
-                                        let mut tx = self.tx_manager.begin_transaction();
-                                        tx.add_query(query);
-                                        match self.tx_manager.commit_transaction(tx) {
-                                            Ok(results) => {
-                                                self.print_results(&results);
-                                            }
-                                            Err(e) => println!("{}: {}", "Error".red().bold(), e),
-                                        }
-                                    }
-                                }
+                    if let Some(command) = input.strip_prefix('\\') {
+                        rl.add_history_entry(input);
+                        match MetaCommand::parse(command) {
+                            Ok(MetaCommand::SetPageSize(rows)) => {
+                                self.page_size = rows;
+                                println!("{}", format!("Page size set to {}.", rows).green());
                             }
+                            Err(e) => println!("{}: {}", "Error".red().bold(), e),
                         }
-                        Err(e) => println!("{}: {}", "Error".red().bold(), e),
+                        continue;
                     }
+
+                    buffer.push_str(input);
+                    self.run_buffered(&mut buffer, &mut rl);
                 },
+                // Discards a statement being typed; at the prompt, quits.
+                Err(ReadlineError::Interrupted) if !buffer.is_empty() => buffer.clear(),
                 Err(ReadlineError::Interrupted) => {
                     println!("{}", "CTRL-C".yellow());
                     break
@@ -135,25 +164,134 @@ impl Repl {
         Ok(())
     }
 
-    fn print_results(&self, results: &[Vec<Value>]) {
-        if results.is_empty() {
+    /// Runs the statements of `buffer` that are terminated, stopping at the
+    /// first that fails, and leaves in it the start of the next one.
+    fn run_buffered(&mut self, buffer: &mut String, rl: &mut Editor<()>) {
+        let (statements, rest) = split_terminated(buffer);
+        if statements.is_empty() && rest.is_some() {
             return;
         }
+        rl.add_history_entry(buffer.as_str());
+        *buffer = rest.map(|s| s.sql).unwrap_or_default();
+        for statement in statements {
+            if let Err(e) = self.execute(&statement.sql, Some(&mut *rl)) {
+                println!("{}: {}", "Error".red().bold(), e);
+                break;
+            }
+        }
+    }
 
-        let mut table = Table::new();
-        // Set a compact format
-        table.set_format(*format::consts::FORMAT_BOX_CHARS);
-
-        // Add data rows
-        for row in results {
-            let mut table_row = vec![];
-            for cell in row {
-                table_row.push(cell.to_string());
+    /// Runs one statement and prints what it returns. Statements inside a
+    /// transaction only run once it commits. `rl` pages long results.
+    fn execute(&mut self, input: &str, rl: Option<&mut Editor<()>>) -> Result<(), DbError> {
+        if let Some(path) = source_path(input) {
+            return self.run_file(&path);
+        }
+        match parse_query(input)? {
+            Query::StartTransaction => {
+                if self.active_transaction.is_some() {
+                    return Err(DbError::TransactionError("Transaction already active".to_string()));
+                }
+                self.active_transaction = Some(self.tx_manager.begin_transaction());
+                println!("{}", "Transaction started.".green());
             }
-            table.add_row(table_row.into());
+            Query::Commit => {
+                let tx = self.active_transaction.take().ok_or_else(no_transaction)?;
+                let results = self.tx_manager.commit_transaction_results(tx)?;
+                self.print_results(&results);
+                println!("{}", "Transaction committed.".green());
+            }
+            Query::Rollback => {
+                let tx = self.active_transaction.take().ok_or_else(no_transaction)?;
+                self.tx_manager.rollback_transaction(tx)?;
+                println!("{}", "Transaction rolled back.".green());
+            }
+            query => match &mut self.active_transaction {
+                Some(tx) => tx.add_statement(input, query),
+                None => {
+                    let stream = self.tx_manager.execute_streaming(input)?;
+                    self.print_stream(stream, rl)?;
+                }
+            },
         }
+        Ok(())
+    }
 
-        table.printstd();
+    /// Runs the statements of a script in order, stopping at the first that
+    /// fails; the error names its line. A transaction the script began and
+    /// left open when it failed is rolled back.
+    pub fn run_file(&mut self, path: &str) -> Result<(), DbError> {
+        if self.sourcing == MAX_SOURCE_DEPTH {
+            return Err(DbError::QueryError(format!("{}: SOURCE nested too deeply", path)));
+        }
+        let text = fs::read_to_string(path)
+            .map_err(|e| DbError::IoError(io::Error::new(e.kind(), format!("{}: {}", path, e))))?;
+        let outer_transaction = self.active_transaction.is_some();
+        self.sourcing += 1;
+        let mut outcome = Ok(());
+        for statement in split_statements(&text) {
+            if let Err(e) = self.execute(&statement.sql, None) {
+                outcome = Err(DbError::QueryError(format!("{}:{}: {}", path, statement.line, e)));
+                break;
+            }
+        }
+        self.sourcing -= 1;
+        if outcome.is_err()
+            && !outer_transaction
+            && let Some(tx) = self.active_transaction.take()
+        {
+            self.tx_manager.rollback_transaction(tx)?;
+        }
+        outcome
+    }
+
+    /// Rolls back an open transaction and writes buffered rows, as EXIT does.
+    pub fn close(mut self) -> Result<(), DbError> {
+        if let Some(tx) = self.active_transaction.take() {
+            self.tx_manager.rollback_transaction(tx)?;
+        }
+        self.tx_manager.flush()?;
+        Ok(())
+    }
+
+    fn print_results(&self, results: &[QueryResult]) {
+        for result in results {
+            print_table(&result.column_names(), &result.rows);
+        }
+    }
+
+    /// Prints a statement's rows as they arrive, a table per page, so a
+    /// large result is never held whole. At a terminal the pager waits after
+    /// each page that more rows follow; `q` stops reading them. A statement
+    /// without columns prints nothing.
+    fn print_stream(&self, mut stream: RowStream, mut rl: Option<&mut Editor<()>>) -> Result<(), DbError> {
+        let titles: Vec<String> = stream.column_names().into_iter().map(String::from).collect();
+        let titles: Vec<&str> = titles.iter().map(String::as_str).collect();
+        let page_rows = if self.page_size == 0 { PAGE_ROWS } else { self.page_size };
+        let pager = self.page_size > 0 && io::stdin().is_terminal() && io::stdout().is_terminal() && rl.is_some();
+        let mut page = Vec::new();
+        let mut printed = false;
+        for row in &mut stream {
+            match row {
+                Ok(row) => {
+                    // Only asked once another row is known to follow.
+                    if pager && printed && page.is_empty() && rl.as_deref_mut().is_some_and(|rl| !more(rl)) {
+                        return Ok(());
+                    }
+                    page.push(row);
+                }
+                Err(e) => return Err(e),
+            }
+            if page.len() == page_rows {
+                print_table(&titles, &page);
+                page.clear();
+                printed = true;
+            }
+        }
+        if !page.is_empty() || (!printed && !titles.is_empty()) {
+            print_table(&titles, &page);
+        }
+        Ok(())
     }
 
     fn print_help(&self) {
@@ -165,18 +303,114 @@ impl Repl {
         table.add_row(row!["START TRANSACTION".green(), "Begin a new transaction"]);
         table.add_row(row!["COMMIT".green(), "Commit the active transaction"]);
         table.add_row(row!["ROLLBACK".green(), "Rollback the active transaction"]);
-        table.add_row(row!["EXIT".yellow(), "Exit the REPL"]);
+        table.add_row(row!["EXIT".yellow(), "Write buffered rows to disk and exit the REPL"]);
         table.add_row(row!["HELP".yellow(), "Show this help message"]);
         table.add_row(row!["", ""]);
         table.add_row(row![bFg => "SQL Commands".cyan().bold(), "".cyan().bold()]);
         table.add_row(row!["SELECT ...".green(), "Query data"]);
+        table.add_row(row!["SELECT ... ORDER BY col [ASC|DESC] LIMIT n OFFSET m".green(), "Sort and page results"]);
+        table.add_row(row!["SELECT COALESCE(a, b, ...), IFNULL(a, b), NULLIF(a, b) ...".green(), "Substitute or produce NULLs inline"]);
+        table.add_row(row!["... WHERE col [NOT] IN (SELECT x ...) | col > (SELECT MAX(x) ...)".green(), "Filter on a subquery's values, or on a single value it returns"]);
+        table.add_row(row!["SELECT a * 1.1, b - c FROM t WHERE a * b > 100".green(), "Arithmetic in projections and conditions"]);
+        table.add_row(row!["SELECT MASK_EMAIL(a), MASK_LAST4(a) ...".green(), "Hide all but the first letter of an email, or all but the last 4 characters"]);
+        table.add_row(row!["SELECT /*+ NO_INDEX(t) | HASH_JOIN | MERGE_JOIN */ ...".green(), "Override the planner's index and join choices"]);
+        table.add_row(row!["SELECT ... FROM t TABLESAMPLE (n PERCENT)".green(), "Query a sample of whole blocks"]);
+        table.add_row(row!["SELECT DISTINCT a, b ... | COUNT(DISTINCT col)".green(), "Drop duplicate rows; count distinct values exactly"]);
+        table.add_row(row!["SELECT APPROX_COUNT_DISTINCT(col) ...".green(), "Approximate distinct count (HyperLogLog)"]);
+        table.add_row(row!["SELECT APPROX_QUANTILE(col, q) ...".green(), "Approximate quantile (t-digest)"]);
+        table.add_row(row!["SELECT PERCENTILE(col, p) ...".green(), "Exact percentile, p between 0 and 100"]);
+        table.add_row(row!["SELECT HISTOGRAM(col, buckets) ...".green(), "Equal-width histogram rows: low, high, count"]);
+        table.add_row(row!["SELECT FIRST(col ORDER BY ts) ...".green(), "Value at earliest/latest ts (also LAST)"]);
+        table.add_row(row!["... GROUP BY TIME_BUCKET('1 hour', ts)".green(), "Roll up epoch-second timestamps per interval (also DATE_TRUNC)"]);
+        table.add_row(row!["... WHERE col IS [NOT] NULL".green(), "Filter on missing values"]);
+        table.add_row(row!["... WHERE NOT (a = 1 OR b = 2) AND c > 3".green(), "Combine conditions; NOT binds tightest, then AND, then OR"]);
+        table.add_row(row!["... WHERE col REGEXP 'pattern'".green(), "Match strings against a regular expression"]);
+        table.add_row(row!["... WHERE col LIKE 'ab%_'".green(), "Match strings; % is any run of characters, _ one"]);
+        table.add_row(row!["... WHERE col IN (1, 2) / col BETWEEN 1 AND 5 / col != 1".green(), "Match a list, an inclusive range or all but a value"]);
+        table.add_row(row!["... WHERE (a, b) IN ((1, \"x\"), (2, \"y\"))".green(), "Match composite keys"]);
+        table.add_row(row!["SELECT ... FROM a JOIN b ON a.x = b.y".green(), "Hash join; <, <=, >, >= use a sort-merge join"]);
+        table.add_row(row!["SELECT e.Name AS name FROM Employees e JOIN ...".green(), "Rename output columns and refer to tables by alias"]);
         table.add_row(row!["INSERT ...".green(), "Insert data"]);
-        table.add_row(row!["UPDATE ...".green(), "Update data"]);
+        table.add_row(row![
+            "INSERT INTO t (col, ...) VALUES (...), (...)".green(),
+            "Insert several rows; omitted columns get their default or NULL"
+        ]);
+        table.add_row(row!["UPDATE table SET col = value WHERE ...".green(), "Update matching rows"]);
         table.add_row(row!["DELETE ...".green(), "Delete data"]);
-        table.add_row(row!["CREATE TABLE ...".green(), "Create a new table"]);
+        table.add_row(row!["INSERT/DELETE ... RETURNING col, ... | *".green(), "Return inserted or deleted rows"]);
+        table.add_row(row!["CREATE TABLE t (col TYPE [NOT NULL] [DEFAULT expr] [PRIMARY KEY | UNIQUE], ...)".green(), "Create a new table; DEFAULT takes a literal, NOW() or CURRENT_DATE; key columns are indexed"]);
+        table.add_row(row!["CREATE EXTERNAL TABLE t (col TYPE, ...) LOCATION 'dir/*.csv'".green(), "Query CSV files with a header row, or .parquet files, in place; the table is read-only"]);
+        table.add_row(row![
+            "ALTER TABLE t SET VALIDATION STRICT|LENIENT".green(),
+            "Lenient tables convert numbers between types and parse numbers and booleans from text on insert"
+        ]);
+        table.add_row(row!["ALTER TABLE t ADD|DROP|RENAME COLUMN ...".green(), "Add a column (backfilled with its default or NULL), drop one, or rename one with RENAME COLUMN a TO b"]);
+        table.add_row(row!["  TYPE".green(), "INT, BIGINT, FLOAT, BOOL, STRING, DATE, TIMESTAMP"]);
+        table.add_row(row!["  col STRING COLLATE NOCASE".green(), "Compare, sort and index the column case-insensitively"]);
+        table.add_row(row!["  col TYPE COMPRESSION NONE|RLE|DICTIONARY|LZ4|ZSTD|INTERNED".green(), "Encode the column's blocks this way; DICTIONARY and INTERNED are for STRING"]);
+        table.add_row(row!["  col STRING MASKED WITH MASK_EMAIL|MASK_LAST4".green(), "Sessions with role restricted only see the masked values"]);
+        table.add_row(row!["DROP TABLE t / UNDROP TABLE t".green(), "Move a table to the trash, or restore it within trash_retention seconds"]);
         table.add_row(row!["MAKE INDEX ON table (column)".green(), "Create an index on a column"]);
         table.add_row(row!["UNMAKE INDEX column ON table".green(), "Drop an index from a column"]);
+        table.add_row(row!["SHOW TABLES".green(), "List tables with column and row counts"]);
+        table.add_row(row!["SHOW SETTINGS".green(), "List settings with their values and sources (default, config, session)"]);
+        table.add_row(row!["SET name = value".green(), "Override a setting listed by SHOW SETTINGS for the session"]);
+        table.add_row(row!["COMPACT table".green(), "Merge small blocks and reclaim space left by updates and deletes"]);
+        table.add_row(row!["FLUSH".green(), "Write rows still buffered in memory to disk"]);
+        table.add_row(row!["PRELOAD table [(col, ...)]".green(), "Load columns into the buffer cache ahead of the first query"]);
+        table.add_row(row!["BACKUP TO 'dir'".green(), "Copy a consistent snapshot of the open database"]);
+        table.add_row(row!["CREATE SNAPSHOT name / DROP SNAPSHOT name".green(), "Record or forget the current state of every table"]);
+        table.add_row(row!["SELECT ... FROM t AS OF SNAPSHOT name ...".green(), "Query the tables as a snapshot recorded them"]);
+        table.add_row(row!["EXPORT TABLE table TO 'file.vddb'".green(), "Write one table as a portable bundle"]);
+        table.add_row(row!["IMPORT TABLE FROM 'file.vddb'".green(), "Create a table from a bundle"]);
+        table.add_row(row!["COPY table FROM 'file.csv' [FORMAT csv]".green(), "Bulk-load CSV rows (with a header line) into a table"]);
+        table.add_row(row!["COPY (SELECT ...) TO 'file' [FORMAT csv|json]".green(), "Write query results as CSV or newline-delimited JSON"]);
+        table.add_row(row!["EXPLAIN [ANALYZE] [FORMAT TEXT|JSON] query".green(), "Show the query plan: index or full scans, blocks read after pruning, join strategy, estimated (and actual) rows"]);
+        table.add_row(row!["CLEANUP [DRY RUN]".green(), "Remove (or list) files not referenced by the schema"]);
+        table.add_row(row!["SHOW TABLE SIZE table".green(), "Per-column blocks, rows, disk/compressed/raw/index bytes"]);
+        table.add_row(row!["ANALYZE table".green(), "Collect column and column-pair statistics, histograms and frequent values"]);
+        table.add_row(row!["SELECT ... FROM information_schema.column_stats".green(), "Histogram buckets and frequent values of analyzed columns"]);
+        table.add_row(row!["CREATE JOB name EVERY \"1 hour\" DO statement".green(), "Run a maintenance statement on a schedule (with --server)"]);
+        table.add_row(row!["DROP JOB name".green(), "Stop and forget a scheduled job"]);
+        table.add_row(row!["SELECT ... FROM information_schema.jobs".green(), "Scheduled jobs with their next run and last outcome"]);
+        table.add_row(row!["SELECT ... FROM system.query_log".green(), "Recent statements with duration, rows and status"]);
+        table.add_row(row!["VERIFY TABLE table CHECK (condition) [LIMIT n]".green(), "List rows that violate a condition"]);
+        table.add_row(row!["SHOW DATABASE SIZE".green(), "Per-table blocks and disk/compressed/raw/index bytes"]);
+        table.add_row(row!["SHOW STORAGE REPORT [table]".green(), "Per column and codec: blocks, raw/compressed bytes, ratio"]);
+        table.add_row(row!["SHOW INDEXES [ON table]".green(), "Indexed columns with key/entry counts and index file size"]);
+        table.add_row(row!["SALVAGE TABLE table INTO new_table".green(), "Copy the rows of every readable block into a new table"]);
+        table.add_row(row!["SOURCE 'file.sql'".green(), "Run the semicolon-separated statements of a file"]);
+        table.add_row(row!["\\set pagesize N".green(), "Rows shown before -- more -- (0 turns the pager off)"]);
 
         table.printstd();
     }
-}
\ No newline at end of file
+}
+fn no_transaction() -> DbError {
+    DbError::TransactionError("No active transaction".to_string())
+}
+
+/// Waits at `-- more --`; false if the user asked to stop.
+fn more(rl: &mut Editor<()>) -> bool {
+    match rl.readline(&"-- more -- (Enter to continue, q to stop) ".yellow().to_string()) {
+        Ok(answer) => !answer.trim().eq_ignore_ascii_case("q"),
+        Err(_) => false,
+    }
+}
+
+fn print_table(titles: &[&str], rows: &[Vec<Value>]) {
+    let mut table = Table::new();
+    // Set a compact format
+    table.set_format(*format::consts::FORMAT_BOX_CHARS);
+    table.set_titles(titles.iter().map(|name| name.cyan().bold()).collect());
+
+    // Add data rows
+    for row in rows {
+        let mut table_row = vec![];
+        for cell in row {
+            table_row.push(cell.to_string());
+        }
+        table.add_row(table_row.into());
+    }
+
+    table.printstd();
+}
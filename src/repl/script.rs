@@ -0,0 +1,69 @@
+/// One statement of a script and the line it starts on, counting from 1.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScriptStatement {
+    pub line: usize,
+    pub sql: String,
+}
+
+/// Splits a script into its statements at the semicolons outside string
+/// literals. `--` comments are dropped, and so are empty statements; the
+/// last statement needs no semicolon.
+pub fn split_statements(text: &str) -> Vec<ScriptStatement> {
+    let (mut statements, rest) = split_terminated(text);
+    statements.extend(rest);
+    statements
+}
+
+/// Like `split_statements`, but a last statement without its semicolon is
+/// returned apart, as the REPL waits for the rest of it.
+pub fn split_terminated(text: &str) -> (Vec<ScriptStatement>, Option<ScriptStatement>) {
+    let mut statements = Vec::new();
+    let mut sql = String::new();
+    let mut start = None;
+    let mut line = 1;
+    let mut quote = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, '-') if chars.peek() == Some(&'-') => {
+                while chars.next_if(|&c| c != '\n').is_some() {}
+                continue;
+            }
+            (None, ';') => {
+                if let Some(start) = start.take() {
+                    statements.push(ScriptStatement { line: start, sql: sql.trim().to_string() });
+                }
+                sql.clear();
+                continue;
+            }
+            _ => {}
+        }
+        if start.is_none() && !c.is_whitespace() {
+            start = Some(line);
+        }
+        if c == '\n' {
+            line += 1;
+        }
+        sql.push(c);
+    }
+    let rest = start.map(|start| ScriptStatement { line: start, sql: sql.trim().to_string() });
+    (statements, rest)
+}
+
+/// The file of a `SOURCE 'file.sql'` command, quoted or not; None for
+/// anything else.
+pub fn source_path(input: &str) -> Option<String> {
+    let (keyword, rest) = input.trim().split_once(char::is_whitespace)?;
+    if !keyword.eq_ignore_ascii_case("SOURCE") {
+        return None;
+    }
+    let path = rest.trim().trim_end_matches(';').trim_end();
+    let unquoted = ['\'', '"']
+        .iter()
+        .find_map(|&q| path.strip_prefix(q).and_then(|p| p.strip_suffix(q)))
+        .unwrap_or(path);
+    (!unquoted.is_empty()).then(|| unquoted.to_string())
+}
@@ -0,0 +1,78 @@
+use crate::storage::settings::Priority;
+use crate::storage::trash::now_seconds;
+use crate::transaction::Session;
+use crate::types::DbError;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Runs the jobs created with `CREATE JOB` once they are due. Each run is
+/// one statement committed as a transaction of the scheduler's session, so it
+/// is logged to the WAL and waits for other sessions' commits; reads run at
+/// batch priority. The outcome is recorded on the job and shown in
+/// `information_schema.jobs`.
+pub struct Scheduler {
+    session: Session,
+}
+
+/// Stops the scheduler thread when dropped, after any run in progress.
+pub struct SchedulerHandle {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for SchedulerHandle {
+    fn drop(&mut self) {
+        // Closing the channel wakes the thread up.
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Scheduler {
+    pub fn new(session: Session) -> Self {
+        Scheduler {
+            session: session.with_priority(Priority::Batch),
+        }
+    }
+
+    /// Runs every job due at `now`, seconds since the epoch, and returns
+    /// their names. A failing job is recorded and does not stop the others.
+    pub fn run_due(&self, now: u64) -> Result<Vec<String>, DbError> {
+        let storage = self.session.storage();
+        let jobs = storage.lock().unwrap().jobs()?;
+        let mut ran = Vec::new();
+        for job in jobs.into_iter().filter(|j| j.next_run <= now) {
+            let mut tx = self.session.begin();
+            let outcome = tx.add_sql(&job.statement).and_then(|()| self.session.commit_results(tx));
+            let status = match outcome {
+                Ok(_) => "ok".to_string(),
+                Err(e) => {
+                    log::warn!("Job {} failed: {}", job.name, e);
+                    e.to_string()
+                }
+            };
+            storage.lock().unwrap().record_job_run(&job.name, now, &status)?;
+            ran.push(job.name);
+        }
+        Ok(ran)
+    }
+
+    /// Checks for due jobs every `tick` on a background thread.
+    pub fn spawn(self, tick: Duration) -> SchedulerHandle {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(tick) {
+                if let Err(e) = self.run_due(now_seconds()) {
+                    log::error!("Scheduler: {}", e);
+                }
+            }
+        });
+        SchedulerHandle {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::types::{CompressionType, DataType, DbError, Value};
 use crate::query::Condition;
+use crate::storage::atomic::write_atomic;
 use crate::query::evaluator::evaluate_condition_block;
 use serde::{Serialize, Deserialize};
 use std::fs;
@@ -14,39 +15,53 @@ pub struct BlockInfo {
     pub compression: CompressionType,
     pub serialized_size: Option<usize>,
     pub segment_path: Option<String>,
+    /// Row positions holding NULL. Those rows are left out of the encoded data.
+    #[serde(default)]
+    pub nulls: Vec<u32>,
+    /// `manifest::checksum` of the encoded bytes; missing for blocks written
+    /// before checksums were kept, until a manifest fills it in.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<u64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BlockMetadata {
     pub column_name: String,
     pub data_type: DataType,
     pub blocks: Vec<BlockInfo>,
     pub data_dir: String, // Added to store data_dir
+    /// The file this metadata is saved to, which depends on the data layout.
+    #[serde(skip)]
+    pub path: String,
 }
 
 impl BlockMetadata {
-    pub fn new(column_name: &str, data_type: DataType, data_dir: &str) -> Self {
+    pub fn new(column_name: &str, data_type: DataType, data_dir: &str, path: &str) -> Self {
         BlockMetadata {
             column_name: column_name.to_string(),
             data_type,
             blocks: Vec::new(),
             data_dir: data_dir.to_string(),
+            path: path.to_string(),
         }
     }
 
-    pub fn load(column_name: &str, data_type: DataType, data_dir: &str) -> Result<Self, DbError> {
-        let metadata_path = format!("{}/metadata/{}.json", data_dir, column_name);
-        if Path::new(&metadata_path).exists() {
-            let contents = fs::read_to_string(&metadata_path)
-                .map_err(|e| DbError::IoError(e))?;
-            let metadata: BlockMetadata = serde_json::from_str(&contents)
+    pub fn load(column_name: &str, data_type: DataType, data_dir: &str, path: &str) -> Result<Self, DbError> {
+        if Path::new(path).exists() {
+            let contents = fs::read_to_string(path)
+                .map_err(DbError::IoError)?;
+            let mut metadata: BlockMetadata = serde_json::from_str(&contents)
                 .map_err(|e| DbError::SerializationError(e.to_string()))?;
+            // The stored directory is stale if the files were copied elsewhere.
+            metadata.data_dir = data_dir.to_string();
+            metadata.path = path.to_string();
             Ok(metadata)
         } else {
-            Ok(Self::new(column_name, data_type, data_dir))
+            Ok(Self::new(column_name, data_type, data_dir, path))
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn add_block(
         &mut self,
         min: Value,
@@ -65,19 +80,26 @@ impl BlockMetadata {
             compression,
             serialized_size: Some(serialized_size),
             segment_path: Some(segment_path.to_string()),
+            nulls: Vec::new(),
+            checksum: None,
         });
         self.save()?;
         Ok(())
     }
 
+    /// Appends several blocks with a single metadata write.
+    pub fn add_blocks(&mut self, blocks: Vec<BlockInfo>) -> Result<(), DbError> {
+        self.blocks.extend(blocks);
+        self.save()
+    }
+
     pub fn save(&self) -> Result<(), DbError> {
-        fs::create_dir_all(format!("{}/metadata", self.data_dir))?;
-        let metadata_path = format!("{}/metadata/{}.json", self.data_dir, self.column_name);
+        if let Some(parent) = Path::new(&self.path).parent() {
+            fs::create_dir_all(parent)?;
+        }
         let contents = serde_json::to_string_pretty(self)
             .map_err(|e| DbError::SerializationError(e.to_string()))?;
-        fs::write(&metadata_path, contents)
-            .map_err(|e| DbError::IoError(e))?;
-        Ok(())
+        write_atomic(&self.path, contents.as_bytes())
     }
 
     pub fn get_blocks(&self, condition: Option<&Condition>) -> Vec<&BlockInfo> {
@@ -91,4 +113,40 @@ impl BlockMetadata {
             })
             .collect()
     }
-}
\ No newline at end of file
+}
+/// Per-table counters kept next to the column metadata, rewritten when a
+/// segment is flushed rather than on every insert.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct TableMetadata {
+    pub table: String,
+    pub row_count: u64,
+}
+
+impl TableMetadata {
+    fn path(data_dir: &str, table: &str) -> String {
+        format!("{}/metadata/{}.table.json", data_dir, table)
+    }
+
+    pub fn load(data_dir: &str, table: &str) -> Result<Option<Self>, DbError> {
+        let path = Self::path(data_dir, table);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)?;
+        Ok(Some(serde_json::from_str(&contents)?))
+    }
+
+    pub fn save(&self, data_dir: &str) -> Result<(), DbError> {
+        fs::create_dir_all(format!("{}/metadata", data_dir))?;
+        let contents = serde_json::to_string_pretty(self)?;
+        write_atomic(Self::path(data_dir, &self.table), contents.as_bytes())
+    }
+
+    pub fn remove(data_dir: &str, table: &str) -> Result<(), DbError> {
+        let path = Self::path(data_dir, table);
+        if Path::new(&path).exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+}
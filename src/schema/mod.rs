@@ -1,4 +1,7 @@
-use crate::types::{DataType, DbError, Value};
+use crate::query::expr::Expr;
+use crate::query::mask::MaskFunction;
+use crate::storage::atomic::write_atomic;
+use crate::types::{Collation, CompressionType, DataType, DbError, Value};
 use fs2::FileExt;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
@@ -6,11 +9,110 @@ use std::fs;
 use std::path::Path;
 
 pub mod metadata;
+pub mod statistics;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct Column {
     pub name: String,
+    /// Unique within the table and kept across renames; per-table files are
+    /// named after it. None until the table is created, and for columns of
+    /// older schemas until the database is next opened for writing.
+    #[serde(default)]
+    pub id: Option<u32>,
     pub data_type: DataType,
+    /// Columns from schemas written before NULL support are NOT NULL.
+    #[serde(default)]
+    pub nullable: bool,
+    /// Evaluated for each inserted row that leaves the column out.
+    #[serde(default)]
+    pub default: Option<Expr>,
+    #[serde(default)]
+    pub collation: Collation,
+    /// `PRIMARY KEY`: unique and NOT NULL; at most one per table.
+    #[serde(default)]
+    pub primary_key: bool,
+    /// `UNIQUE`: no two rows share a non-NULL value.
+    #[serde(default)]
+    pub unique: bool,
+    /// `COMPRESSION`: how every block of the column is encoded. When unset,
+    /// strings use a dictionary and other types run-length encoding.
+    #[serde(default)]
+    pub compression: Option<CompressionType>,
+    /// `MASKED WITH f`: restricted sessions only ever see `f` of the values.
+    #[serde(default)]
+    pub mask: Option<MaskFunction>,
+}
+
+impl Column {
+    /// A nullable column, except `ID`, which is the table's key.
+    pub fn new(name: &str, data_type: DataType) -> Self {
+        Column {
+            name: name.to_string(),
+            id: None,
+            data_type,
+            nullable: name != "ID",
+            default: None,
+            collation: Collation::Binary,
+            primary_key: false,
+            unique: false,
+            compression: None,
+            mask: None,
+        }
+    }
+
+    pub fn primary_key(mut self) -> Self {
+        self.primary_key = true;
+        self.nullable = false;
+        self
+    }
+
+    pub fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    pub fn not_null(mut self) -> Self {
+        self.nullable = false;
+        self
+    }
+
+    pub fn with_default(mut self, default: Expr) -> Self {
+        self.default = Some(default);
+        self
+    }
+
+    pub fn with_compression(mut self, compression: CompressionType) -> Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    pub fn with_mask(mut self, mask: MaskFunction) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    pub fn with_collation(mut self, collation: Collation) -> Self {
+        self.collation = collation;
+        self
+    }
+
+    /// Evaluates the default for a row that leaves this column out.
+    pub fn default_value(&self) -> Result<Value, DbError> {
+        match &self.default {
+            Some(expr) => expr.evaluate()?.coerce(&self.data_type),
+            None => Err(DbError::InvalidData(format!("Column {} has no default", self.name))),
+        }
+    }
+
+    pub fn check_value(&self, value: &Value) -> Result<(), DbError> {
+        if value.is_null() && !self.nullable {
+            return Err(DbError::InvalidData(format!("Column {} cannot be NULL", self.name)));
+        }
+        if !value.fits(&self.data_type) {
+            return Err(DbError::TypeMismatch);
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -18,6 +120,39 @@ pub struct Table {
     pub name: String,
     pub columns: Vec<Column>,
     pub row_count: u64,
+    /// Files an external table scans instead of storing rows, e.g. `lake/*.parquet`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub location: Option<String>,
+    #[serde(default, skip_serializing_if = "Validation::is_strict")]
+    pub validation: Validation,
+    /// The id the next added column gets. Ids are never reused, so a new
+    /// column cannot pick up files a dropped one left behind.
+    #[serde(default)]
+    pub next_column_id: u32,
+}
+
+/// How inserts treat a value whose type is not its column's; set with
+/// `ALTER TABLE t SET VALIDATION STRICT | LENIENT`.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Validation {
+    /// Only the conversions of `Value::coerce`; anything else is a `TypeMismatch`.
+    #[default]
+    Strict,
+    /// Also those of `Value::coerce_lenient`, for messy CSV and JSON feeds.
+    Lenient,
+}
+
+impl Validation {
+    pub fn is_strict(&self) -> bool {
+        *self == Validation::Strict
+    }
+
+    pub fn coerce(self, value: Value, data_type: &DataType) -> Result<Value, DbError> {
+        match self {
+            Validation::Strict => value.coerce(data_type),
+            Validation::Lenient => value.coerce_lenient(data_type),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -58,14 +193,19 @@ impl Schema {
                 ));
             }
         }
-        self.tables.insert(
-            name.to_string(),
-            Table {
-                name: name.to_string(),
-                columns,
-                row_count: 0,
-            },
-        );
+        if columns.iter().filter(|c| c.primary_key).count() > 1 {
+            return Err(DbError::InvalidData(format!("Table {} has more than one PRIMARY KEY", name)));
+        }
+        let mut table = Table {
+            name: name.to_string(),
+            columns,
+            row_count: 0,
+            location: None,
+            validation: Validation::Strict,
+            next_column_id: 0,
+        };
+        table.assign_column_ids();
+        self.tables.insert(name.to_string(), table);
         self.save()?;
         Ok(())
     }
@@ -88,9 +228,7 @@ impl Schema {
         }
 
         for (value, col) in values.iter().zip(table_def.columns.iter()) {
-            if value.data_type() != col.data_type {
-                return Err(DbError::TypeMismatch);
-            }
+            col.check_value(value)?;
         }
 
         Ok(())
@@ -100,24 +238,33 @@ impl Schema {
         self.tables.values()
     }
 
+    /// Writes schema.json via a temp file and rename, so a crash leaves
+    /// either the old or the new schema, never a truncated one. Only DDL
+    /// calls this; row counts are kept in per-table metadata.
     pub fn save(&self) -> Result<(), DbError> {
         let path = format!("{}/schema.json", self.data_dir);
-        let file = std::fs::OpenOptions::new()
+        let lock = std::fs::OpenOptions::new()
             .write(true)
             .create(true)
-            .open(&path)?;
-        file.lock_exclusive()?;
+            .truncate(false)
+            .open(format!("{}/schema.lock", self.data_dir))?;
+        lock.lock_exclusive()?;
         let json = serde_json::to_string_pretty(&self.tables)
             .map_err(|e| DbError::SerializationError(e.to_string()))?;
-        fs::write(&path, json)?;
-        fs2::FileExt::unlock(&file)?;
+        write_atomic(&path, json.as_bytes())?;
+        fs2::FileExt::unlock(&lock)?;
         Ok(())
     }
 
     pub fn load(data_dir: &str) -> Result<Self, DbError> {
         let path = format!("{}/schema.json", data_dir);
+        // No table has been created yet. (`new_schema` would call back here.)
         if !std::path::Path::new(&path).exists() {
-            return Self::new_schema(data_dir);
+            fs::create_dir_all(data_dir)?;
+            return Ok(Schema {
+                tables: HashMap::new(),
+                data_dir: data_dir.to_string(),
+            });
         }
         let json = fs::read_to_string(&path)?;
         let tables: HashMap<String, Table> =
@@ -134,6 +281,47 @@ impl Table {
         self.columns.iter().find(|c| c.name == name)
     }
 
+    /// Takes the next unused column id.
+    pub fn new_column_id(&mut self) -> u32 {
+        let used = self.columns.iter().filter_map(|c| c.id).max().map_or(1, |id| id + 1);
+        let id = self.next_column_id.max(used);
+        self.next_column_id = id + 1;
+        id
+    }
+
+    /// Gives every column without an id a new one, in column order.
+    pub fn assign_column_ids(&mut self) {
+        for i in 0..self.columns.len() {
+            if self.columns[i].id.is_none() {
+                self.columns[i].id = Some(self.new_column_id());
+            }
+        }
+    }
+
+    /// True if no two rows may share a non-NULL value of `column`: it is
+    /// declared PRIMARY KEY or UNIQUE. A table that declares no primary key
+    /// treats a column named ID as one, as schemas did before constraints.
+    pub fn is_unique(&self, column: &str) -> bool {
+        match self.get_column(column) {
+            Some(col) if col.primary_key || col.unique => true,
+            Some(col) => col.name == "ID" && !self.columns.iter().any(|c| c.primary_key),
+            None => false,
+        }
+    }
+
+    pub fn unique_columns(&self) -> impl Iterator<Item = &Column> {
+        self.columns.iter().filter(|c| self.is_unique(&c.name))
+    }
+
+    /// Collations of the columns that do not compare binary.
+    pub fn collations(&self) -> HashMap<String, Collation> {
+        self.columns
+            .iter()
+            .filter(|c| c.collation != Collation::Binary)
+            .map(|c| (c.name.clone(), c.collation))
+            .collect()
+    }
+
     pub fn increment_row_count(&mut self) {
         self.row_count += 1;
     }
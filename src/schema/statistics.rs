@@ -0,0 +1,314 @@
+use crate::query::{collect_condition_columns, Condition};
+use crate::storage::atomic::write_atomic;
+use crate::types::{DbError, Value};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Selectivity assumed for range predicates when min/max are unknown or non-numeric.
+const DEFAULT_RANGE_SELECTIVITY: f64 = 1.0 / 3.0;
+/// Selectivity assumed for `IS NULL` on a column that was never analyzed.
+const DEFAULT_NULL_SELECTIVITY: f64 = 0.1;
+/// Selectivity assumed for pattern matches, which statistics can't estimate.
+const DEFAULT_PATTERN_SELECTIVITY: f64 = 0.1;
+/// Buckets in a column's equi-depth histogram.
+pub const HISTOGRAM_BUCKETS: usize = 10;
+/// Most frequent values kept per column.
+pub const TOP_VALUES: usize = 10;
+
+/// A run of sorted non-NULL values; buckets hold about the same number of rows.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct HistogramBucket {
+    pub lower: Value,
+    pub upper: Value,
+    pub count: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ColumnStatistics {
+    pub distinct: u64,
+    pub min: Option<Value>,
+    pub max: Option<Value>,
+    #[serde(default)]
+    pub null_count: u64,
+    #[serde(default)]
+    pub histogram: Vec<HistogramBucket>,
+    /// The most frequent non-NULL values with their row counts, most frequent first.
+    #[serde(default)]
+    pub top_values: Vec<(Value, u64)>,
+}
+
+impl ColumnStatistics {
+    /// Builds the histogram and frequent values from a column's non-NULL values.
+    pub fn distribution(mut present: Vec<Value>) -> (Vec<HistogramBucket>, Vec<(Value, u64)>) {
+        present.sort();
+        let mut counts: Vec<(Value, u64)> = Vec::new();
+        for value in &present {
+            match counts.last_mut() {
+                Some((last, count)) if last == value => *count += 1,
+                _ => counts.push((value.clone(), 1)),
+            }
+        }
+        // Stable, so equally frequent values stay in ascending order.
+        counts.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+        counts.truncate(TOP_VALUES);
+
+        let depth = present.len().div_ceil(HISTOGRAM_BUCKETS).max(1);
+        let histogram = present
+            .chunks(depth)
+            .map(|chunk| HistogramBucket {
+                lower: chunk[0].clone(),
+                upper: chunk[chunk.len() - 1].clone(),
+                count: chunk.len() as u64,
+            })
+            .collect();
+        (histogram, counts)
+    }
+}
+
+/// Joint distinct count of two columns, used to detect correlation.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ColumnPairStatistics {
+    pub left: String,
+    pub right: String,
+    pub distinct: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TableStatistics {
+    pub table: String,
+    pub row_count: u64,
+    pub columns: HashMap<String, ColumnStatistics>,
+    pub column_pairs: Vec<ColumnPairStatistics>,
+}
+
+impl TableStatistics {
+    fn path(data_dir: &str, table: &str) -> String {
+        format!("{}/metadata/{}.stats.json", data_dir, table)
+    }
+
+    pub fn load(data_dir: &str, table: &str) -> Result<Option<Self>, DbError> {
+        let path = Self::path(data_dir, table);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        let contents = fs::read_to_string(&path)?;
+        Ok(Some(serde_json::from_str(&contents)?))
+    }
+
+    pub fn save(&self, data_dir: &str) -> Result<(), DbError> {
+        fs::create_dir_all(format!("{}/metadata", data_dir))?;
+        let contents = serde_json::to_string_pretty(self)?;
+        write_atomic(Self::path(data_dir, &self.table), contents.as_bytes())
+    }
+
+    pub fn remove(data_dir: &str, table: &str) -> Result<(), DbError> {
+        let path = Self::path(data_dir, table);
+        if Path::new(&path).exists() {
+            fs::remove_file(&path)?;
+        }
+        Ok(())
+    }
+
+    /// Statistics of `column`, which may be qualified with the table name.
+    fn column(&self, column: &str) -> Option<&ColumnStatistics> {
+        self.columns.get(self.unqualified(column))
+    }
+
+    fn unqualified<'a>(&self, column: &'a str) -> &'a str {
+        match column.split_once('.') {
+            Some((table, name)) if table == self.table => name,
+            _ => column,
+        }
+    }
+
+    pub fn distinct(&self, column: &str) -> Option<u64> {
+        self.column(column).map(|c| c.distinct.max(1))
+    }
+
+    pub fn pair_distinct(&self, a: &str, b: &str) -> Option<u64> {
+        let (a, b) = (self.unqualified(a), self.unqualified(b));
+        self.column_pairs
+            .iter()
+            .find(|p| (p.left == a && p.right == b) || (p.left == b && p.right == a))
+            .map(|p| p.distinct.max(1))
+    }
+
+    /// 0.0 when two columns look independent, 1.0 when one determines the other.
+    pub fn correlation(&self, a: &str, b: &str) -> Option<f64> {
+        if self.unqualified(a) == self.unqualified(b) {
+            return Some(1.0);
+        }
+        let (da, db, joint) = (self.distinct(a)?, self.distinct(b)?, self.pair_distinct(a, b)?);
+        let independent = (da * db).min(self.row_count.max(1)) as f64;
+        let dependent = da.max(db) as f64;
+        if independent <= dependent {
+            return Some(0.0);
+        }
+        Some(((independent - joint as f64) / (independent - dependent)).clamp(0.0, 1.0))
+    }
+
+    /// Estimated fraction of rows matching `condition`. Conjunctions of
+    /// equalities on two analyzed columns use their joint distinct count
+    /// instead of multiplying the individual selectivities; other conjunctions
+    /// of two columns lean from the product towards the smaller selectivity
+    /// as far as the columns are correlated.
+    pub fn selectivity(&self, condition: &Condition) -> f64 {
+        match condition {
+            Condition::Equal(col, val) => {
+                if let Some(stats) = self.column(col)
+                    && let (Some(min), Some(max)) = (&stats.min, &stats.max)
+                    && (val < min || val > max)
+                {
+                    return 0.0;
+                }
+                if let Some(stats) = self.column(col)
+                    && let Some((_, count)) = stats.top_values.iter().find(|(v, _)| v == val)
+                {
+                    return *count as f64 / self.row_count.max(1) as f64;
+                }
+                self.distinct(col).map_or(0.1, |d| 1.0 / d as f64)
+            }
+            Condition::GreaterThan(col, val) | Condition::GreaterThanOrEqual(col, val) => {
+                self.range_fraction(col, val).map_or(DEFAULT_RANGE_SELECTIVITY, |f| 1.0 - f)
+            }
+            Condition::LessThan(col, val) | Condition::LessThanOrEqual(col, val) => {
+                self.range_fraction(col, val).unwrap_or(DEFAULT_RANGE_SELECTIVITY)
+            }
+            Condition::NotEqual(col, val) => 1.0 - self.selectivity(&Condition::Equal(col.clone(), val.clone())),
+            Condition::In(col, values) => values
+                .iter()
+                .map(|val| self.selectivity(&Condition::Equal(col.clone(), val.clone())))
+                .sum::<f64>()
+                .min(1.0),
+            Condition::Between(col, low, high) => {
+                match (self.range_fraction(col, low), self.range_fraction(col, high)) {
+                    (Some(low), Some(high)) => (high - low).max(0.0),
+                    _ => DEFAULT_RANGE_SELECTIVITY,
+                }
+            }
+            Condition::Like(..) => DEFAULT_PATTERN_SELECTIVITY,
+            Condition::IsNull(col) => self.null_fraction(col).unwrap_or(DEFAULT_NULL_SELECTIVITY),
+            Condition::IsNotNull(col) => 1.0 - self.null_fraction(col).unwrap_or(DEFAULT_NULL_SELECTIVITY),
+            Condition::Regexp(..) => DEFAULT_PATTERN_SELECTIVITY,
+            Condition::Compare(..) | Condition::CompareSubquery(..) => DEFAULT_RANGE_SELECTIVITY,
+            Condition::InSubquery(..) => DEFAULT_RANGE_SELECTIVITY,
+            Condition::InTuple(cols, tuples) => {
+                let matches: f64 = tuples
+                    .iter()
+                    .map(|tuple| {
+                        cols.iter()
+                            .zip(tuple)
+                            .map(|(col, val)| self.selectivity(&Condition::Equal(col.clone(), val.clone())))
+                            .product::<f64>()
+                    })
+                    .sum();
+                matches.min(1.0)
+            }
+            Condition::And(left, right) => {
+                if let (Condition::Equal(a, _), Condition::Equal(b, _)) = (left.as_ref(), right.as_ref())
+                    && a != b
+                    && let Some(joint) = self.pair_distinct(a, b)
+                {
+                    return 1.0 / joint as f64;
+                }
+                let (l, r) = (self.selectivity(left), self.selectivity(right));
+                let correlation = match (single_column(left), single_column(right)) {
+                    (Some(a), Some(b)) if a != b => self.correlation(&a, &b).unwrap_or(0.0),
+                    _ => 0.0,
+                };
+                (1.0 - correlation) * l * r + correlation * l.min(r)
+            }
+            Condition::Or(left, right) => {
+                let (l, r) = (self.selectivity(left), self.selectivity(right));
+                l + r - l * r
+            }
+            Condition::Not(inner) => 1.0 - self.selectivity(inner),
+        }
+    }
+
+    pub fn estimate_rows(&self, condition: Option<&Condition>) -> f64 {
+        let rows = self.row_count as f64;
+        condition.map_or(rows, |c| rows * self.selectivity(c))
+    }
+
+    fn null_fraction(&self, column: &str) -> Option<f64> {
+        let stats = self.column(column)?;
+        Some(stats.null_count as f64 / self.row_count.max(1) as f64)
+    }
+
+    /// Fraction of the column's non-NULL values below `val`, in [0, 1]: read
+    /// off the histogram, or the position of `val` between min and max
+    /// for tables analyzed without one.
+    fn range_fraction(&self, column: &str, val: &Value) -> Option<f64> {
+        let stats = self.column(column)?;
+        if !stats.histogram.is_empty() {
+            return histogram_fraction(&stats.histogram, val);
+        }
+        let (min, max, v) = (stats.min.as_ref()?.as_f64()?, stats.max.as_ref()?.as_f64()?, val.as_f64()?);
+        if max <= min {
+            return Some(if v < min { 0.0 } else { 1.0 });
+        }
+        Some(((v - min) / (max - min)).clamp(0.0, 1.0))
+    }
+}
+
+fn histogram_fraction(histogram: &[HistogramBucket], val: &Value) -> Option<f64> {
+    let total: u64 = histogram.iter().map(|b| b.count).sum();
+    let mut below = 0.0;
+    for bucket in histogram {
+        if val <= &bucket.lower {
+            break;
+        }
+        if val > &bucket.upper {
+            below += bucket.count as f64;
+            continue;
+        }
+        // Inside the bucket: interpolate numbers, assume the middle otherwise.
+        let within = match (bucket.lower.as_f64(), bucket.upper.as_f64(), val.as_f64()) {
+            (Some(l), Some(u), Some(v)) if u > l => (v - l) / (u - l),
+            _ => 0.5,
+        };
+        below += bucket.count as f64 * within;
+        break;
+    }
+    Some(below / total.max(1) as f64)
+}
+
+/// The one column `condition` reads, if it reads only one.
+fn single_column(condition: &Condition) -> Option<String> {
+    let columns = collect_condition_columns(condition);
+    match columns.len() {
+        1 => columns.into_iter().next(),
+        _ => None,
+    }
+}
+
+/// Rows of one side of a join left by that side's own filter, and the
+/// distinct join keys among them. A filter on a column independent of the
+/// key keeps every key; one on a correlated column drops keys with the rows.
+fn join_side(stats: &TableStatistics, key: &str, filter: Option<&Condition>) -> (f64, f64) {
+    let keys = stats.distinct(key).unwrap_or(1) as f64;
+    let Some(filter) = filter else {
+        return (stats.row_count as f64, keys);
+    };
+    let selectivity = stats.selectivity(filter);
+    let correlation = collect_condition_columns(filter)
+        .iter()
+        .filter_map(|col| stats.correlation(key, col))
+        .fold(0.0, f64::max);
+    let kept = keys * (1.0 - correlation + correlation * selectivity);
+    (stats.row_count as f64 * selectivity, kept.max(1.0))
+}
+
+/// Estimated equi-join output size: |L| * |R| / max(ndv(left), ndv(right)),
+/// over the rows each side's filter leaves.
+pub fn estimate_join_rows(
+    (left, left_column, left_filter): (&TableStatistics, &str, Option<&Condition>),
+    (right, right_column, right_filter): (&TableStatistics, &str, Option<&Condition>),
+) -> f64 {
+    let (left_rows, left_keys) = join_side(left, left_column, left_filter);
+    let (right_rows, right_keys) = join_side(right, right_column, right_filter);
+    left_rows * right_rows / left_keys.max(right_keys)
+}
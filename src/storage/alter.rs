@@ -0,0 +1,216 @@
+use crate::query::AlterAction;
+use crate::schema::statistics::TableStatistics;
+use crate::schema::Column;
+use crate::storage::bulk::encode_columns;
+use crate::storage::column::ColumnStore;
+use crate::storage::index::Index;
+use crate::storage::settings::DataLayout;
+use crate::storage::StorageManager;
+use crate::types::{DbError, Value};
+use std::fs;
+use std::path::Path;
+
+fn remove_if_exists(path: &str) -> Result<(), DbError> {
+    if Path::new(path).exists() {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+impl StorageManager {
+    pub fn alter_table(&mut self, table_name: &str, action: AlterAction) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        self.ensure_stored(table_name)?;
+        if self.schema.get_table(table_name).is_none() {
+            return Err(DbError::InvalidData(format!("Table {} not found", table_name)));
+        }
+        match action {
+            AlterAction::AddColumn(column) => self.add_column(table_name, column)?,
+            AlterAction::DropColumn(name) => self.drop_column(table_name, &name)?,
+            AlterAction::RenameColumn { from, to } => self.rename_column(table_name, &from, &to)?,
+            // Rows and statistics stay as they are.
+            AlterAction::SetValidation(validation) => {
+                self.schema.tables.get_mut(table_name).unwrap().validation = validation;
+                return self.schema.save();
+            }
+        }
+        // Statistics describe the old columns; ANALYZE rebuilds them.
+        TableStatistics::remove(&self.data_dir, table_name)?;
+        self.schema.save()?;
+        self.bump_table_version(table_name);
+        Ok(())
+    }
+
+    /// Flat column files are named after the column alone, so there a name can
+    /// only be used by one table.
+    fn check_new_column_name(&self, table_name: &str, name: &str) -> Result<(), DbError> {
+        let flat = self.layout == DataLayout::Flat;
+        if let Some(owner) =
+            self.schema.tables().find(|t| (flat || t.name == table_name) && t.get_column(name).is_some())
+        {
+            return Err(DbError::InvalidData(if owner.name == table_name {
+                format!("Column {}.{} already exists", table_name, name)
+            } else {
+                format!("Column {} is already used by table {}", name, owner.name)
+            }));
+        }
+        Ok(())
+    }
+
+    /// Adds `column` with every existing row set to its default, or NULL
+    /// when it has none. The backfill uses the same block boundaries as the
+    /// other columns so blocks stay row-aligned.
+    fn add_column(&mut self, table_name: &str, mut column: Column) -> Result<(), DbError> {
+        self.check_new_column_name(table_name, &column.name)?;
+        let table_def = self.schema.get_table(table_name).unwrap().clone();
+        let fill = match column.default {
+            Some(_) => column.default_value()?,
+            None if !column.nullable && table_def.row_count > 0 => {
+                return Err(DbError::InvalidData(format!(
+                    "Column {} is NOT NULL without a default, but {} has rows",
+                    column.name, table_name
+                )));
+            }
+            None => Value::Null,
+        };
+        column.check_value(&fill)?;
+        if column.primary_key {
+            return Err(DbError::InvalidData(format!(
+                "Cannot add PRIMARY KEY column {}; declare it in CREATE TABLE",
+                column.name
+            )));
+        }
+        if column.unique && !fill.is_null() && table_def.row_count > 1 {
+            return Err(DbError::InvalidData(format!(
+                "Column {} is UNIQUE, but its default would repeat in every row of {}",
+                column.name, table_name
+            )));
+        }
+
+        column.id = Some(self.schema.tables.get_mut(table_name).unwrap().new_column_id());
+        let table_cols = self.columns.get_mut(table_name).unwrap();
+        let block_rows: Vec<usize> = table_cols[&table_def.columns[0].name]
+            .metadata
+            .blocks
+            .iter()
+            .map(|b| b.row_count)
+            .collect();
+        let mut store = ColumnStore::new(&column, table_name, &self.data_dir, self.layout)?;
+        let mut blocks = Vec::with_capacity(block_rows.len());
+        for &rows in &block_rows {
+            let encoded = encode_columns(std::slice::from_ref(&column), vec![vec![fill.clone(); rows]])?;
+            blocks.extend(encoded.blocks);
+        }
+        let offsets = store.append_encoded(blocks)?;
+        table_cols.insert(column.name.clone(), store);
+        if column.unique {
+            let index_path = format!("{}/{}", self.data_dir, self.layout.index_file(table_name, &column));
+            let mut index = Index::new(&index_path, column.data_type.clone(), column.collation)?;
+            for (offset, rows) in offsets.into_iter().zip(block_rows) {
+                index.append(&vec![fill.clone(); rows], offset)?;
+            }
+            self.indexes.get_mut(table_name).unwrap().insert(column.name.clone(), index);
+        }
+
+        // Buffered rows are filled in place and flushed with the rest of their row.
+        if let Some(table_pending) = self.pending_rows.get_mut(table_name) {
+            let pending = table_pending.values().next().map_or(0, Vec::len);
+            table_pending.insert(column.name.clone(), vec![fill; pending]);
+        }
+        self.schema.tables.get_mut(table_name).unwrap().columns.push(column);
+        Ok(())
+    }
+
+    /// Removes the column's data, block metadata and index.
+    fn drop_column(&mut self, table_name: &str, name: &str) -> Result<(), DbError> {
+        let table = self.schema.tables.get_mut(table_name).unwrap();
+        let position = table
+            .columns
+            .iter()
+            .position(|c| c.name == name)
+            .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table_name, name)))?;
+        if table.columns.len() == 1 {
+            return Err(DbError::InvalidData(format!("Cannot drop {}, the only column of {}", name, table_name)));
+        }
+        let column = table.columns.remove(position);
+
+        if let Some(store) = self.columns.get_mut(table_name).and_then(|cols| cols.remove(name)) {
+            remove_if_exists(&store.file_path)?;
+            remove_if_exists(&store.metadata.path)?;
+        }
+        remove_if_exists(&format!("{}/{}", self.data_dir, self.layout.intern_file(table_name, &column)))?;
+        self.indexes.get_mut(table_name).and_then(|indexes| indexes.remove(name));
+        remove_if_exists(&format!("{}/{}", self.data_dir, self.layout.index_file(table_name, &column)))?;
+        if let Some(table_pending) = self.pending_rows.get_mut(table_name) {
+            table_pending.remove(name);
+        }
+        Ok(())
+    }
+
+    /// Renames the column. Files named by its id stay put; files named after
+    /// it, as in a flat layout, move along.
+    fn rename_column(&mut self, table_name: &str, from: &str, to: &str) -> Result<(), DbError> {
+        let column = self
+            .schema
+            .get_table(table_name)
+            .unwrap()
+            .get_column(from)
+            .cloned()
+            .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table_name, from)))?;
+        self.check_new_column_name(table_name, to)?;
+        let renamed = Column { name: to.to_string(), ..column.clone() };
+        let moved = self.layout.file_key(&column) != self.layout.file_key(&renamed);
+        let path = |file: String| format!("{}/{}", self.data_dir, file);
+
+        let table_cols = self.columns.get_mut(table_name).unwrap();
+        let mut store = table_cols
+            .remove(from)
+            .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table_name, from)))?;
+        store.column.name = to.to_string();
+        store.metadata.column_name = to.to_string();
+        if moved {
+            let file_path = path(self.layout.column_file(table_name, &renamed));
+            fs::rename(&store.file_path, &file_path)?;
+            let intern_path = path(self.layout.intern_file(table_name, &column));
+            if Path::new(&intern_path).exists() {
+                fs::rename(&intern_path, path(self.layout.intern_file(table_name, &renamed)))?;
+            }
+            store.move_to(file_path.clone());
+            for block in &mut store.metadata.blocks {
+                block.segment_path = Some(file_path.clone());
+            }
+            let old_metadata =
+                std::mem::replace(&mut store.metadata.path, path(self.layout.metadata_file(table_name, &renamed)));
+            store.metadata.save()?;
+            remove_if_exists(&old_metadata)?;
+        } else {
+            store.metadata.save()?;
+        }
+        table_cols.insert(to.to_string(), store);
+
+        let table_indexes = self.indexes.get_mut(table_name).unwrap();
+        if let Some(mut index) = table_indexes.remove(from) {
+            if moved {
+                index.save()?;
+                let index_path = path(self.layout.index_file(table_name, &renamed));
+                let old_path = path(self.layout.index_file(table_name, &column));
+                if Path::new(&old_path).exists() {
+                    fs::rename(&old_path, &index_path)?;
+                }
+                index = Index::new(&index_path, column.data_type.clone(), column.collation)?;
+            }
+            table_indexes.insert(to.to_string(), index);
+        }
+        if let Some(table_pending) = self.pending_rows.get_mut(table_name)
+            && let Some(values) = table_pending.remove(from)
+        {
+            table_pending.insert(to.to_string(), values);
+        }
+
+        let table = self.schema.tables.get_mut(table_name).unwrap();
+        if let Some(column) = table.columns.iter_mut().find(|c| c.name == from) {
+            column.name = to.to_string();
+        }
+        Ok(())
+    }
+}
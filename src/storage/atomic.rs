@@ -0,0 +1,18 @@
+use crate::types::DbError;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::Path;
+
+/// Replaces `path` with `contents` by writing `<path>.tmp`, syncing it and
+/// renaming it over the target. Readers and crashes see either the old file
+/// or the complete new one.
+pub fn write_atomic(path: impl AsRef<Path>, contents: &[u8]) -> Result<(), DbError> {
+    let path = path.as_ref();
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
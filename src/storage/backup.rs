@@ -0,0 +1,178 @@
+use crate::storage::layout::DATABASE_FILES;
+use crate::storage::{do_flush_pending_rows, StorageManager};
+use crate::types::{DbError, Value};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupReport {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+impl BackupReport {
+    /// The row `BACKUP TO path` returns.
+    pub fn to_row(&self, path: String) -> Vec<Value> {
+        vec![Value::String(path), Value::Int64(self.files as i64), Value::Int64(self.bytes as i64)]
+    }
+}
+
+/// A backup `begin_backup` started: all but the column data is copied, and
+/// each column file is held open at the length its committed blocks end at.
+pub struct BackupImage {
+    columns: Vec<(File, u64, PathBuf)>,
+    report: BackupReport,
+}
+
+impl BackupImage {
+    /// Copies the column data. No lock is needed: appends land past the held
+    /// lengths, and rewrites replace a file instead of changing it.
+    pub fn finish(mut self) -> Result<BackupReport, DbError> {
+        for (file, len, to) in self.columns {
+            let mut out = File::create(&to)?;
+            self.report.bytes += std::io::copy(&mut file.take(len), &mut out)?;
+            self.report.files += 1;
+        }
+        Ok(self.report)
+    }
+}
+
+/// Opens every column file under `from`, to be copied to the same place under `to`.
+fn open_columns(from: &Path, to: &Path, columns: &mut Vec<(File, u64, PathBuf)>) -> Result<(), DbError> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_dir() {
+            open_columns(&entry.path(), &to.join(&name), columns)?;
+            continue;
+        }
+        if !entry.file_type()?.is_file() || name.to_string_lossy().ends_with(".tmp") {
+            continue;
+        }
+        let file = File::open(entry.path())?;
+        let len = file.metadata()?.len();
+        columns.push((file, len, to.join(&name)));
+    }
+    Ok(())
+}
+
+pub(crate) fn copy_dir(from: &Path, to: &Path, report: &mut BackupReport) -> Result<(), DbError> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(&name), report)?;
+            continue;
+        }
+        if !entry.file_type()?.is_file() || name.to_string_lossy().ends_with(".tmp") {
+            continue;
+        }
+        report.bytes += fs::copy(entry.path(), to.join(&name))?;
+        report.files += 1;
+    }
+    Ok(())
+}
+
+impl StorageManager {
+    /// Writes every table's buffered rows to disk so that the files alone hold
+    /// all committed data. Returns the number of rows written.
+    pub fn flush_all(&mut self) -> Result<usize, DbError> {
+        let tables: Vec<String> = self.pending_rows.keys().cloned().collect();
+        let mut flushed = 0;
+        for table_name in tables {
+            flushed += self.flush_table(&table_name)?;
+        }
+        self.save_indexes()?;
+        Ok(flushed)
+    }
+
+    /// Writes every index changed since it was last saved, and returns how
+    /// many there were. Index files are only written here, at commits and
+    /// checkpoints, so one transaction rewrites each index at most once.
+    pub fn save_indexes(&mut self) -> Result<usize, DbError> {
+        let mut saved = 0;
+        for index in self.indexes.values_mut().flat_map(|t| t.values_mut()) {
+            if index.save()? {
+                saved += 1;
+            }
+        }
+        Ok(saved)
+    }
+
+    /// Rebuilds every index from its column's blocks. Used after a crash
+    /// between a commit's flush and its index save, which left the WAL record
+    /// in place and the index files behind the columns.
+    pub fn rebuild_indexes(&mut self) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        for (table, indexes) in self.indexes.iter_mut() {
+            for (column, index) in indexes.iter_mut() {
+                let col_store = self.columns.get(table).and_then(|cols| cols.get(column)).ok_or_else(|| {
+                    DbError::InvalidData(format!("Column {}.{} not found", table, column))
+                })?;
+                col_store.reindex(index, &self.buffer)?;
+                index.save()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes one table's buffered rows to disk and returns how many there were.
+    pub(crate) fn flush_table(&mut self, table_name: &str) -> Result<usize, DbError> {
+        let Some(table_def) = self.schema.get_table(table_name).cloned() else {
+            return Ok(0);
+        };
+        let (Some(table_cols), Some(table_indexes)) =
+            (self.columns.get_mut(table_name), self.indexes.get_mut(table_name))
+        else {
+            return Ok(0);
+        };
+        let Some(pending) = self.pending_rows.get(table_name) else {
+            return Ok(0);
+        };
+        let flushed = pending.values().next().map_or(0, Vec::len);
+        do_flush_pending_rows(&mut self.pending_rows, table_name, table_cols, table_indexes, &table_def)?;
+        self.save_row_count(table_name)?;
+        Ok(flushed)
+    }
+
+    /// Copies a consistent image of the database into `dest`, which must not
+    /// exist or be empty, holding `self` for the whole copy.
+    pub fn backup_to(&mut self, dest: &str) -> Result<BackupReport, DbError> {
+        self.begin_backup(dest)?.finish()
+    }
+
+    /// Starts a backup into `dest`, which must not exist or be empty. Buffered
+    /// rows are flushed first, as at a checkpoint, so the files hold every
+    /// committed row. Block metadata, indexes and the database files are
+    /// copied now; the column data, by far the most of it, is copied by
+    /// `BackupImage::finish` once the caller has let go of the storage lock.
+    pub fn begin_backup(&mut self, dest: &str) -> Result<BackupImage, DbError> {
+        let dest_path = Path::new(dest);
+        if dest_path.exists() && fs::read_dir(dest_path)?.next().is_some() {
+            return Err(DbError::InvalidData(format!("Backup directory {} is not empty", dest)));
+        }
+        if !self.is_read_only() {
+            self.flush_all()?;
+            // A database with no tables yet has no schema.json to copy.
+            self.schema.save()?;
+        }
+
+        let source = Path::new(self.data_dir());
+        let mut report = BackupReport { files: 0, bytes: 0 };
+        let mut columns = Vec::new();
+        open_columns(&source.join("columns"), &dest_path.join("columns"), &mut columns)?;
+        for dir in ["metadata", "indexes"] {
+            copy_dir(&source.join(dir), &dest_path.join(dir), &mut report)?;
+        }
+        for file in DATABASE_FILES {
+            if source.join(file).exists() {
+                report.bytes += fs::copy(source.join(file), dest_path.join(file))?;
+                report.files += 1;
+            }
+        }
+        Ok(BackupImage { columns, report })
+    }
+}
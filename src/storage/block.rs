@@ -52,12 +52,10 @@ impl Block {
 
 fn estimate_block_size(data_type: &DataType, compression: CompressionType) -> usize {
     match (data_type, compression) {
-        (DataType::Int32, CompressionType::Rle) => 5, // 1 byte run length + 4 bytes value
-        (DataType::Float32, CompressionType::Rle) => 5,
         (DataType::String, CompressionType::Rle) => 9, // 1 byte run length + 8 bytes length + min 1 byte string
-        (DataType::Int32, CompressionType::None) => 4,
-        (DataType::Float32, CompressionType::None) => 4,
         (DataType::String, CompressionType::None) => 9,
+        (_, CompressionType::Rle) => 1 + data_type.fixed_width().unwrap_or(0), // 1 byte run length + value
+        (_, CompressionType::None) => data_type.fixed_width().unwrap_or(1),
         (DataType::String, CompressionType::Dictionary) => 8, // At least one ID
         _ => 1, // Fallback for invalid combinations
     }
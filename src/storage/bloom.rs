@@ -0,0 +1,91 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const BITS_PER_KEY: usize = 10;
+/// With 10 bits per key, about 1% false positives.
+const HASHES: u64 = 7;
+const INITIAL_KEYS: usize = 1024;
+
+/// One fixed-size bloom filter sized for `capacity` keys.
+#[derive(Clone)]
+struct Layer {
+    bits: Vec<u64>,
+    capacity: usize,
+    len: usize,
+}
+
+impl Layer {
+    fn new(capacity: usize) -> Self {
+        Layer {
+            bits: vec![0; (capacity * BITS_PER_KEY).div_ceil(64)],
+            capacity,
+            len: 0,
+        }
+    }
+
+    /// Bit positions of a hash, by double hashing its two halves.
+    fn positions(&self, hash: u64) -> impl Iterator<Item = usize> + '_ {
+        let m = (self.bits.len() * 64) as u64;
+        let (h1, h2) = (hash & 0xffff_ffff, (hash >> 32) | 1);
+        (0..HASHES).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % m) as usize)
+    }
+
+    fn insert(&mut self, hash: u64) {
+        let positions: Vec<usize> = self.positions(hash).collect();
+        for pos in positions {
+            self.bits[pos / 64] |= 1 << (pos % 64);
+        }
+        self.len += 1;
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        self.positions(hash).all(|pos| self.bits[pos / 64] & (1 << (pos % 64)) != 0)
+    }
+}
+
+/// Set membership with false positives but no false negatives: `contains`
+/// is false only for keys never inserted. Grows by adding a layer twice the
+/// size of the last once that one is full, so keys never need rehashing.
+#[derive(Clone)]
+pub struct BloomFilter {
+    layers: Vec<Layer>,
+}
+
+impl Default for BloomFilter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BloomFilter {
+    pub fn new() -> Self {
+        BloomFilter {
+            layers: vec![Layer::new(INITIAL_KEYS)],
+        }
+    }
+
+    pub fn insert<T: Hash + ?Sized>(&mut self, key: &T) {
+        let hash = hash(key);
+        let last = self.layers.last().unwrap();
+        if last.len >= last.capacity {
+            let capacity = last.capacity * 2;
+            self.layers.push(Layer::new(capacity));
+        }
+        self.layers.last_mut().unwrap().insert(hash);
+    }
+
+    pub fn contains<T: Hash + ?Sized>(&self, key: &T) -> bool {
+        let hash = hash(key);
+        self.layers.iter().any(|layer| layer.contains(hash))
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::new();
+    }
+}
+
+fn hash<T: Hash + ?Sized>(key: &T) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
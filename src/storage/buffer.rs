@@ -1,38 +1,287 @@
-use crate::types::DbError;
-use std::collections::HashMap;
+use crate::query::batch::{with_null_rows, ColumnVector};
+use crate::query::governor::current_priority;
+use crate::storage::settings::Priority;
+use crate::types::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
 
+const SHARDS: usize = 16;
+
+/// Identifies one stored block. The generation changes whenever a column
+/// file is rewritten from the start, so offsets of the old file never hit.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct BlockKey {
+    pub file_path: String,
+    pub generation: u64,
+    pub offset: u64,
+}
+
+/// A block's decoded contents.
+pub enum Decoded {
+    Values(Vec<Value>),
+    /// A string block as per-row ids into its dictionary, which a batch read
+    /// takes without building a `Value` per row. `ids` skip the NULL rows.
+    Dictionary {
+        ids: Vec<u64>,
+        dictionary: Vec<Option<String>>,
+        nulls: Vec<u32>,
+    },
+    /// A fixed-width block as the typed vector a batch read decoded it into.
+    Vector(ColumnVector),
+}
+
+impl Decoded {
+    pub fn len(&self) -> usize {
+        match self {
+            Decoded::Values(values) => values.len(),
+            Decoded::Dictionary { ids, nulls, .. } => ids.len() + nulls.len(),
+            Decoded::Vector(vector) => vector.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Appends the block's values to `out`.
+    pub fn extend_values(&self, out: &mut Vec<Value>) {
+        match self {
+            Decoded::Values(values) => out.extend(values.iter().cloned()),
+            Decoded::Dictionary { ids, dictionary, nulls } => {
+                out.extend(with_null_rows(ids, nulls).map(|id| {
+                    match id.and_then(|&id| dictionary.get(id as usize)).and_then(|s| s.clone()) {
+                        Some(s) => Value::String(s),
+                        None => Value::Null,
+                    }
+                }))
+            }
+            Decoded::Vector(vector) => out.extend((0..vector.len()).map(|row| vector.value(row))),
+        }
+    }
+
+    /// Approximate memory the block takes.
+    fn size(&self) -> usize {
+        match self {
+            Decoded::Values(values) => decoded_size(values),
+            Decoded::Dictionary { ids, dictionary, nulls } => {
+                let heap: usize = dictionary.iter().flatten().map(String::len).sum();
+                let ids = std::mem::size_of_val(&ids[..]) + std::mem::size_of_val(&nulls[..]);
+                ids + std::mem::size_of_val(&dictionary[..]) + heap
+            }
+            // At most eight bytes a row, plus its NULL flag.
+            Decoded::Vector(vector) => vector.len() * (std::mem::size_of::<u64>() + 1),
+        }
+    }
+}
+
+struct CachedBlock {
+    values: Arc<Decoded>,
+    bytes: usize,
+    last_used: u64,
+    /// Readers holding a `PinnedBlock` of this entry.
+    pins: Arc<AtomicUsize>,
+}
+
+#[derive(Default)]
+struct Shard {
+    blocks: HashMap<BlockKey, CachedBlock>,
+    /// `last_used` tick to block, oldest first.
+    recency: BTreeMap<u64, BlockKey>,
+}
+
+impl Shard {
+    fn remove(&mut self, key: &BlockKey) -> usize {
+        match self.blocks.remove(key) {
+            Some(block) => {
+                self.recency.remove(&block.last_used);
+                block.bytes
+            }
+            None => 0,
+        }
+    }
+
+    /// Tick of the least recently used block no reader has pinned.
+    fn oldest_unpinned(&self) -> Option<u64> {
+        self.recency
+            .iter()
+            .find(|(_, key)| self.blocks[*key].pins.load(Ordering::Acquire) == 0)
+            .map(|(tick, _)| *tick)
+    }
+}
+
+/// A cached block in use. While any pin of an entry is alive the entry is
+/// not evicted.
+pub struct PinnedBlock {
+    values: Arc<Decoded>,
+    pins: Arc<AtomicUsize>,
+}
+
+impl Deref for PinnedBlock {
+    type Target = Decoded;
+
+    fn deref(&self) -> &Decoded {
+        &self.values
+    }
+}
+
+impl Drop for PinnedBlock {
+    fn drop(&mut self) {
+        self.pins.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Decoded blocks kept in memory, least recently used evicted first once
+/// they take more than `max_size` bytes. Blocks are spread over shards,
+/// each behind its own lock, so readers on different threads rarely wait
+/// on each other; recency ticks are global, so eviction order is too.
 pub struct BufferManager {
-    buffers: HashMap<String, Vec<u8>>,
-    max_size: usize,
+    shards: Vec<Mutex<Shard>>,
+    tick: AtomicU64,
+    used: AtomicUsize,
+    max_size: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// Approximate memory a decoded block takes.
+pub(crate) fn decoded_size(values: &[Value]) -> usize {
+    let heap: usize = values
+        .iter()
+        .map(|v| match v {
+            Value::String(s) => s.len(),
+            _ => 0,
+        })
+        .sum();
+    std::mem::size_of_val(values) + heap
 }
 
 impl BufferManager {
     pub fn new(max_size: usize) -> Self {
         BufferManager {
-            buffers: HashMap::new(),
-            max_size,
+            shards: (0..SHARDS).map(|_| Mutex::new(Shard::default())).collect(),
+            tick: AtomicU64::new(0),
+            used: AtomicUsize::new(0),
+            max_size: AtomicUsize::new(max_size),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
         }
     }
 
-    pub fn get_buffer(&self, key: &str) -> Result<&Vec<u8>, DbError> {
-        self.buffers
-            .get(key)
-            .ok_or_else(|| DbError::InvalidData(format!("Buffer {} not found", key)))
+    fn shard(&self, key: &BlockKey) -> MutexGuard<'_, Shard> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shard = &self.shards[hasher.finish() as usize % SHARDS];
+        shard.lock().unwrap()
+    }
+
+    fn next_tick(&self) -> u64 {
+        self.tick.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    /// The cached block, pinned until the returned guard is dropped.
+    pub fn get(&self, key: &BlockKey) -> Option<PinnedBlock> {
+        let tick = self.next_tick();
+        let mut shard = self.shard(key);
+        let Some(block) = shard.blocks.get_mut(key) else {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+            return None;
+        };
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        block.pins.fetch_add(1, Ordering::AcqRel);
+        let pinned = PinnedBlock { values: Arc::clone(&block.values), pins: Arc::clone(&block.pins) };
+        let last_used = std::mem::replace(&mut block.last_used, tick);
+        shard.recency.remove(&last_used);
+        shard.recency.insert(tick, key.clone());
+        Some(pinned)
+    }
+
+    /// Caches a block, evicting the least recently used unpinned ones to
+    /// make room. A block larger than the whole cache is not kept, and a
+    /// batch read only keeps blocks that fit without evicting any.
+    pub fn put(&self, key: BlockKey, values: Decoded) {
+        let bytes = values.size();
+        let max_size = self.max_size.load(Ordering::Relaxed);
+        let batch = current_priority() == Priority::Batch;
+        if bytes > max_size || (batch && self.used.load(Ordering::Relaxed) + bytes > max_size) {
+            return;
+        }
+        {
+            let tick = self.next_tick();
+            let mut shard = self.shard(&key);
+            let replaced = shard.remove(&key);
+            self.used.fetch_sub(replaced, Ordering::AcqRel);
+            self.used.fetch_add(bytes, Ordering::AcqRel);
+            shard.recency.insert(tick, key.clone());
+            let pins = Arc::new(AtomicUsize::new(0));
+            let block = CachedBlock { values: Arc::new(values), bytes, last_used: tick, pins };
+            shard.blocks.insert(key, block);
+        }
+        self.evict();
     }
 
-    pub fn put_buffer(&mut self, key: String, data: Vec<u8>) -> Result<(), DbError> {
-        if data.len() > self.max_size {
-            return Err(DbError::InvalidData(format!(
-                "Buffer size {} exceeds maximum {}",
-                data.len(),
-                self.max_size
-            )));
+    /// Evicts until the cache fits, or only pinned blocks are left. Holds at
+    /// most one shard lock at a time.
+    fn evict(&self) {
+        while self.used.load(Ordering::Acquire) > self.max_size.load(Ordering::Relaxed) {
+            let oldest = self
+                .shards
+                .iter()
+                .enumerate()
+                .filter_map(|(i, shard)| Some((shard.lock().unwrap().oldest_unpinned()?, i)))
+                .min();
+            let Some((tick, i)) = oldest else {
+                break;
+            };
+            let mut shard = self.shards[i].lock().unwrap();
+            // Another thread may have used or evicted it since.
+            if let Some(key) = shard.recency.get(&tick).cloned()
+                && shard.blocks[&key].pins.load(Ordering::Acquire) == 0
+            {
+                let bytes = shard.remove(&key);
+                self.used.fetch_sub(bytes, Ordering::AcqRel);
+            }
         }
-        self.buffers.insert(key, data);
-        Ok(())
     }
 
-    pub fn clear(&mut self) {
-        self.buffers.clear();
+    pub fn set_max_size(&self, max_size: usize) {
+        self.max_size.store(max_size, Ordering::Relaxed);
+        self.evict();
+    }
+
+    /// Bytes the cached blocks take.
+    pub fn used_bytes(&self) -> usize {
+        self.used.load(Ordering::Acquire)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.lock().unwrap().blocks.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
-}
\ No newline at end of file
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    /// Drops every block, pinned ones included; their readers keep their
+    /// own reference to the values.
+    pub fn clear(&self) {
+        for shard in &self.shards {
+            let mut shard = shard.lock().unwrap();
+            let bytes: usize = shard.blocks.values().map(|block| block.bytes).sum();
+            shard.blocks.clear();
+            shard.recency.clear();
+            self.used.fetch_sub(bytes, Ordering::AcqRel);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use crate::schema::{Column, Table};
+use crate::storage::column::{bounds, encode, split_nulls, EncodedBlock};
+use crate::storage::{do_flush_pending_rows, StorageManager};
+use crate::types::{DbError, Value};
+use std::collections::HashSet;
+use std::thread;
+
+/// Rows per block written by bulk loads.
+pub const BULK_CHUNK_ROWS: usize = 1024;
+
+/// One compressed block per column plus the raw values that the index and
+/// duplicate checks need.
+pub(crate) struct EncodedChunk {
+    pub blocks: Vec<EncodedBlock>,
+    pub values: Vec<Vec<Value>>,
+}
+
+/// Encodes column-major `values` (one vector per column) into one block per column.
+pub(crate) fn encode_columns(columns: &[Column], values: Vec<Vec<Value>>) -> Result<EncodedChunk, DbError> {
+    let mut blocks = Vec::with_capacity(columns.len());
+    for (col, col_values) in columns.iter().zip(&values) {
+        let (present, nulls) = split_nulls(col_values);
+        let (compression, data) = encode(col, &present)?;
+        let (min, max) = bounds(&present);
+        blocks.push(EncodedBlock {
+            min,
+            max,
+            row_count: col_values.len(),
+            compression,
+            data,
+            nulls,
+        });
+    }
+    Ok(EncodedChunk { blocks, values })
+}
+
+impl StorageManager {
+    /// Inserts `rows` as whole blocks instead of one buffered row at a time.
+    /// Values are coerced to the column types first; the first row that does
+    /// not fit fails the load, numbered from 1, and nothing is written.
+    pub fn bulk_load(&mut self, table_name: &str, rows: Vec<Vec<Value>>) -> Result<usize, DbError> {
+        self.ensure_writable()?;
+        let table_def = self
+            .schema
+            .get_table(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?
+            .clone();
+        let columns = &table_def.columns;
+        let mut encoded = Vec::new();
+        let mut chunk: Vec<Vec<Value>> = vec![Vec::new(); columns.len()];
+        let row_total = rows.len();
+        for (row_no, row) in rows.into_iter().enumerate() {
+            if row.len() != columns.len() {
+                return Err(DbError::InvalidData(format!(
+                    "Row {}: expected {} columns, got {}",
+                    row_no + 1,
+                    columns.len(),
+                    row.len()
+                )));
+            }
+            for (i, (value, col)) in row.into_iter().zip(columns).enumerate() {
+                let value = table_def
+                    .validation
+                    .coerce(value, &col.data_type)
+                    .and_then(|v| col.check_value(&v).map(|_| v))
+                    .map_err(|e| DbError::InvalidData(format!("Row {}: {}", row_no + 1, e)))?;
+                chunk[i].push(value);
+            }
+            if chunk[0].len() == BULK_CHUNK_ROWS || row_no + 1 == row_total {
+                let full = std::mem::replace(&mut chunk, vec![Vec::new(); columns.len()]);
+                encoded.push(encode_columns(columns, full)?);
+            }
+        }
+        self.append_chunks(&table_def, encoded)
+    }
+
+    /// Appends encoded chunks as new blocks, after rejecting unique column
+    /// values that repeat within them or already exist. Returns the number of rows written.
+    pub(crate) fn append_chunks(&mut self, table_def: &Table, encoded: Vec<EncodedChunk>) -> Result<usize, DbError> {
+        let table_name = table_def.name.as_str();
+        self.ensure_stored(table_name)?;
+        let columns = &table_def.columns;
+        let row_count: usize = encoded.iter().map(|c| c.values.first().map_or(0, Vec::len)).sum();
+        if row_count == 0 {
+            return Ok(0);
+        }
+        for (pos, col) in columns.iter().enumerate().filter(|(_, c)| table_def.is_unique(&c.name)) {
+            let mut seen = HashSet::new();
+            for value in encoded.iter().flat_map(|chunk| &chunk.values[pos]) {
+                if !value.is_null() && !seen.insert(col.collation.key(value)) {
+                    return Err(DbError::InvalidData(format!("Duplicate {}: {:?}", col.name, value)));
+                }
+                self.check_unique(table_name, col, value)?;
+            }
+        }
+        let table_cols = self
+            .columns
+            .get_mut(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?;
+        let table_indexes = self
+            .indexes
+            .get_mut(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?;
+
+        do_flush_pending_rows(&mut self.pending_rows, table_name, table_cols, table_indexes, table_def)?;
+
+        let mut per_column: Vec<Vec<EncodedBlock>> = columns.iter().map(|_| Vec::new()).collect();
+        let mut per_column_values: Vec<Vec<Vec<Value>>> = columns.iter().map(|_| Vec::new()).collect();
+        for chunk in encoded {
+            for (i, (block, values)) in chunk.blocks.into_iter().zip(chunk.values).enumerate() {
+                per_column[i].push(block);
+                per_column_values[i].push(values);
+            }
+        }
+
+        // Column files are independent, so write them concurrently.
+        let mut stores: Vec<_> = columns
+            .iter()
+            .map(|c| table_cols.remove_entry(&c.name).expect("column store exists"))
+            .collect();
+        let offsets = thread::scope(|scope| {
+            let writers: Vec<_> = stores
+                .iter_mut()
+                .zip(per_column)
+                .map(|((_, store), blocks)| scope.spawn(move || store.append_encoded(blocks)))
+                .collect();
+            writers
+                .into_iter()
+                .map(|w| w.join().expect("bulk load writer panicked"))
+                .collect::<Vec<_>>()
+        });
+        table_cols.extend(stores);
+        let offsets = offsets.into_iter().collect::<Result<Vec<_>, _>>()?;
+
+        for ((col, col_offsets), col_values) in columns.iter().zip(offsets).zip(per_column_values) {
+            if let Some(index) = table_indexes.get_mut(&col.name) {
+                for (offset, values) in col_offsets.into_iter().zip(col_values) {
+                    index.append(&values, offset)?;
+                }
+            }
+        }
+
+        if let Some(table) = self.schema.tables.get_mut(table_name) {
+            table.row_count += row_count as u64;
+        }
+        self.save_row_count(table_name)?;
+        self.bump_table_version(table_name);
+        Ok(row_count)
+    }
+}
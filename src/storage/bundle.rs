@@ -0,0 +1,125 @@
+use crate::schema::{Column, Table, Validation};
+use crate::storage::atomic::write_atomic;
+use crate::storage::column::EncodedBlock;
+use crate::storage::compression::decompress;
+use crate::storage::index::Index;
+use crate::storage::{do_flush_pending_rows, StorageManager};
+use crate::types::DbError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+const BUNDLE_MAGIC: &[u8; 4] = b"VDDB";
+const BUNDLE_VERSION: u32 = 4;
+
+#[derive(Serialize, Deserialize)]
+struct ColumnBundle {
+    column: Column,
+    blocks: Vec<EncodedBlock>,
+    indexed: bool,
+}
+
+/// A single table packed into one file: its definition plus every column's
+/// compressed blocks, so it can be loaded into another database as is.
+#[derive(Serialize, Deserialize)]
+struct TableBundle {
+    table: String,
+    row_count: u64,
+    columns: Vec<ColumnBundle>,
+}
+
+impl StorageManager {
+    /// Writes `table_name` to `path` as a bundle and returns the rows exported.
+    pub fn export_table(&mut self, table_name: &str, path: &str) -> Result<u64, DbError> {
+        let table_def = self
+            .schema
+            .get_table(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?
+            .clone();
+        self.ensure_stored(table_name)?;
+        if !self.is_read_only() {
+            let table_cols = self.columns.get_mut(table_name).unwrap();
+            let table_indexes = self.indexes.get_mut(table_name).unwrap();
+            do_flush_pending_rows(&mut self.pending_rows, table_name, table_cols, table_indexes, &table_def)?;
+            self.save_row_count(table_name)?;
+        }
+
+        let mut columns = Vec::with_capacity(table_def.columns.len());
+        for col in &table_def.columns {
+            let col_store = &self.columns[table_name][&col.name];
+            columns.push(ColumnBundle {
+                column: col.clone(),
+                blocks: col_store.read_encoded()?,
+                indexed: self.indexes[table_name].contains_key(&col.name),
+            });
+        }
+        let bundle = TableBundle {
+            table: table_name.to_string(),
+            row_count: table_def.row_count,
+            columns,
+        };
+
+        let mut contents = BUNDLE_MAGIC.to_vec();
+        contents.extend_from_slice(&BUNDLE_VERSION.to_le_bytes());
+        contents.extend(bincode::serialize(&bundle).map_err(|e| DbError::SerializationError(e.to_string()))?);
+        write_atomic(path, &contents)?;
+        Ok(bundle.row_count)
+    }
+
+    /// Creates a table from a bundle written by `export_table` and returns its
+    /// name. The table must not already exist.
+    pub fn import_table(&mut self, path: &str) -> Result<String, DbError> {
+        self.ensure_writable()?;
+        let contents = fs::read(path)?;
+        if contents.len() < 8 || &contents[..4] != BUNDLE_MAGIC {
+            return Err(DbError::InvalidData(format!("{} is not a table bundle", path)));
+        }
+        let version = u32::from_le_bytes(contents[4..8].try_into().unwrap());
+        if version != BUNDLE_VERSION {
+            return Err(DbError::InvalidData(format!("Unsupported bundle version {}", version)));
+        }
+        let bundle: TableBundle =
+            bincode::deserialize(&contents[8..]).map_err(|e| DbError::SerializationError(e.to_string()))?;
+        if self.schema.get_table(&bundle.table).is_some() {
+            return Err(DbError::InvalidData(format!("Table {} already exists", bundle.table)));
+        }
+
+        let table = Table {
+            name: bundle.table.clone(),
+            columns: bundle.columns.iter().map(|c| c.column.clone()).collect(),
+            row_count: 0,
+            location: None,
+            validation: Validation::Strict,
+            next_column_id: 0,
+        };
+        self.create_table(&table)?;
+        let data_dir = self.data_dir.clone();
+        for col in bundle.columns {
+            let col_store = self
+                .columns
+                .get_mut(&table.name)
+                .and_then(|cols| cols.get_mut(&col.column.name))
+                .unwrap();
+            let decoded = col
+                .blocks
+                .iter()
+                .map(|b| decompress(&b.data, b.compression.clone(), &col.column.data_type))
+                .collect::<Result<Vec<_>, _>>()?;
+            let offsets = col_store.append_encoded(col.blocks)?;
+
+            let table_indexes = self.indexes.get_mut(&table.name).unwrap();
+            if col.indexed && !table_indexes.contains_key(&col.column.name) {
+                let index_path = format!("{}/{}", data_dir, self.layout.index_file(&table.name, &col.column));
+                table_indexes.insert(col.column.name.clone(), Index::new(&index_path, col.column.data_type.clone(), col.column.collation)?);
+            }
+            if let Some(index) = table_indexes.get_mut(&col.column.name) {
+                for (values, offset) in decoded.iter().zip(offsets) {
+                    index.append(values, offset)?;
+                }
+            }
+        }
+        self.set_row_count(&table.name, bundle.row_count);
+        self.save_row_count(&table.name)?;
+        self.bump_table_version(&table.name);
+        Ok(table.name)
+    }
+}
@@ -0,0 +1,57 @@
+use crate::storage::layout::{table_files, TABLE_DIRS};
+use crate::storage::StorageManager;
+use crate::types::DbError;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+impl StorageManager {
+    /// Files under the data directory that no table in the schema accounts
+    /// for: column data, block metadata, statistics, manifests and indexes of
+    /// dropped tables or columns, plus temp files left by interrupted writes.
+    pub fn find_orphans(&self) -> Result<Vec<String>, DbError> {
+        let data_dir = self.data_dir();
+        let mut expected = HashSet::new();
+        for table in self.schema().tables() {
+            expected.extend(table_files(table, self.layout()));
+        }
+
+        let mut orphans = Vec::new();
+        // Table directories are walked too; per-table files live one level down.
+        let mut dirs: Vec<String> = TABLE_DIRS.iter().map(|dir| dir.to_string()).collect();
+        while let Some(dir) = dirs.pop() {
+            let path = format!("{}/{}", data_dir, dir);
+            if !Path::new(&path).exists() {
+                continue;
+            }
+            for entry in fs::read_dir(&path)? {
+                let entry = entry?;
+                let relative = format!("{}/{}", dir, entry.file_name().to_string_lossy());
+                if entry.file_type()?.is_dir() {
+                    dirs.push(relative);
+                } else if !expected.contains(&relative) {
+                    orphans.push(relative);
+                }
+            }
+        }
+        // LOCK and schema.lock are expected at the top level; only temp files are stray.
+        for entry in fs::read_dir(data_dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.ends_with(".tmp") {
+                orphans.push(name);
+            }
+        }
+        orphans.sort();
+        Ok(orphans)
+    }
+
+    /// Deletes the files reported by `find_orphans` and returns their paths.
+    pub fn remove_orphans(&self) -> Result<Vec<String>, DbError> {
+        self.ensure_writable()?;
+        let orphans = self.find_orphans()?;
+        for relative in &orphans {
+            fs::remove_file(format!("{}/{}", self.data_dir(), relative))?;
+        }
+        Ok(orphans)
+    }
+}
@@ -1,110 +1,742 @@
 use crate::schema::metadata::{BlockMetadata, BlockInfo};
 use crate::storage::block::Block;
-use crate::storage::buffer::BufferManager;
-use crate::storage::compression::compress;
-use crate::types::{CompressionType, DbError, Value};
+use crate::storage::buffer::{BlockKey, BufferManager, Decoded};
+use crate::storage::index::Index;
+use crate::storage::intern::InternTable;
+use crate::storage::manifest::checksum;
+use crate::storage::settings::DataLayout;
+use crate::query::batch::{with_nulls, ColumnVector, DictionaryBuilder};
+use crate::storage::compression::{
+    compress, decode_dictionary, decompress, decompress_into, decompress_primitive_into, DecodeScratch, Primitive,
+};
+use crate::types::{CompressionType, DataType, DbError, Value};
 use crate::schema::Column;
 use crate::query::Condition;
+use serde::{Deserialize, Serialize};
 use std::fs::{self, File, OpenOptions};
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_GENERATION: AtomicU64 = AtomicU64::new(1);
+
+fn next_generation() -> u64 {
+    NEXT_GENERATION.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Encodes a block's non-NULL values the way `column` asks for. Interned
+/// columns get a dictionary block here, which their column store converts
+/// to intern ids when it appends it.
+pub fn encode(column: &Column, present: &[Value]) -> Result<(CompressionType, Vec<u8>), DbError> {
+    let compression = match (&column.compression, &column.data_type) {
+        _ if present.is_empty() => return Ok((CompressionType::None, Vec::new())),
+        (Some(CompressionType::Interned), _) => CompressionType::Dictionary,
+        (Some(compression), _) => compression.clone(),
+        (None, DataType::String) => CompressionType::Dictionary,
+        (None, _) => CompressionType::Rle,
+    };
+    let data = compress(present, compression.clone())?;
+    Ok((compression, data))
+}
+
+/// A block already compressed off the write path, e.g. by a bulk loader thread.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncodedBlock {
+    pub min: Value,
+    pub max: Value,
+    pub row_count: usize,
+    pub compression: CompressionType,
+    pub data: Vec<u8>,
+    #[serde(default)]
+    pub nulls: Vec<u32>,
+}
+
+/// Splits NULLs out of a block's values, returning the values to encode and
+/// the row positions that were NULL.
+pub fn split_nulls(values: &[Value]) -> (Vec<Value>, Vec<u32>) {
+    let mut present = Vec::with_capacity(values.len());
+    let mut nulls = Vec::new();
+    for (row, value) in values.iter().enumerate() {
+        if value.is_null() {
+            nulls.push(row as u32);
+        } else {
+            present.push(value.clone());
+        }
+    }
+    (present, nulls)
+}
+
+/// Min and max of a block's non-NULL values; NULL for an all-NULL block.
+pub fn bounds(values: &[Value]) -> (Value, Value) {
+    let min = values.iter().min().cloned().unwrap_or(Value::Null);
+    let max = values.iter().max().cloned().unwrap_or(Value::Null);
+    (min, max)
+}
 
 pub struct ColumnStore {
     pub column: Column,
     pub metadata: BlockMetadata,
     pub data_dir: String,
     pub file_path: String, // Single file for this column
+    /// Handle opened when a snapshot was taken. Reads go through it so they
+    /// still see the file a later `clear` unlinked.
+    pinned: Option<File>,
+    /// Part of every cached block's key; renewed when the file is rewritten.
+    generation: u64,
+    /// Set for `COMPRESSION INTERNED` columns.
+    intern: Option<InternTable>,
 }
 
 impl ColumnStore {
-    pub fn new(column: &Column, data_dir: &str) -> Result<Self, DbError> {
-        let file_path = format!("{}/columns/{}.dat", data_dir, column.name);
-        let metadata = BlockMetadata::load(&column.name, column.data_type.clone(), data_dir)?;
+    pub fn new(column: &Column, table: &str, data_dir: &str, layout: DataLayout) -> Result<Self, DbError> {
+        let file_path = format!("{}/{}", data_dir, layout.column_file(table, column));
+        let metadata_path = format!("{}/{}", data_dir, layout.metadata_file(table, column));
+        let metadata = BlockMetadata::load(&column.name, column.data_type.clone(), data_dir, &metadata_path)?;
         if !Path::new(&file_path).exists() {
-            fs::create_dir_all(format!("{}/columns", data_dir))?;
+            if let Some(parent) = Path::new(&file_path).parent() {
+                fs::create_dir_all(parent)?;
+            }
             File::create(&file_path)?;
         }
+        let intern = match column.compression {
+            Some(CompressionType::Interned) => Some(InternTable::open(&intern_path(&file_path))?),
+            _ => None,
+        };
         Ok(ColumnStore {
             column: column.clone(),
             metadata,
             data_dir: data_dir.to_string(),
             file_path,
+            pinned: None,
+            generation: next_generation(),
+            intern,
+        })
+    }
+
+    /// A copy of this column as it is now, for reading without the storage lock.
+    pub fn pin(&self) -> Result<Self, DbError> {
+        Ok(ColumnStore {
+            column: self.column.clone(),
+            metadata: self.metadata.clone(),
+            data_dir: self.data_dir.clone(),
+            file_path: self.file_path.clone(),
+            pinned: Some(File::open(&self.file_path)?),
+            generation: self.generation,
+            intern: self.intern.clone(),
         })
     }
 
-    pub fn append(
-        &mut self,
-        values: &[Value],
-        compression: CompressionType,
-    ) -> Result<u64, DbError> {
+    /// The intern table of a `COMPRESSION INTERNED` column.
+    pub fn intern(&self) -> Option<&InternTable> {
+        self.intern.as_ref()
+    }
+
+    /// Points an interned column at its data file's new path, after a rename.
+    pub(crate) fn move_to(&mut self, file_path: String) {
+        if let Some(intern) = &mut self.intern {
+            intern.set_path(intern_path(&file_path));
+        }
+        self.file_path = file_path;
+    }
+
+    /// `encode`, but interning the strings of an interned column and saving
+    /// any new ones before the block that uses them is written.
+    fn encode_present(&mut self, present: &[Value]) -> Result<(CompressionType, Vec<u8>), DbError> {
+        let Some(intern) = self.intern.as_mut().filter(|_| !present.is_empty()) else {
+            return encode(&self.column, present);
+        };
+        let ids = present
+            .iter()
+            .map(|value| match value {
+                Value::String(s) => Ok(Value::Int32(intern.intern(s) as i32)),
+                _ => Err(DbError::TypeMismatch),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        intern.save()?;
+        Ok((CompressionType::Interned, compress(&ids, CompressionType::Interned)?))
+    }
+
+    /// Replaces decoded intern ids with their strings.
+    fn resolve_interned(&self, values: &mut [Value]) -> Result<(), DbError> {
+        let intern = self.intern.as_ref().ok_or_else(|| {
+            DbError::InvalidData(format!("{} has interned blocks but no intern table", self.file_path))
+        })?;
+        for value in values {
+            let Value::Int32(id) = value else {
+                return Err(DbError::SerializationError("Interned block holds a non-id".to_string()));
+            };
+            *value = Value::String(intern.get(*id as u32)?.to_string());
+        }
+        Ok(())
+    }
+
+    fn open_file(&self) -> std::io::Result<File> {
+        match &self.pinned {
+            Some(file) => file.try_clone(),
+            None => File::open(&self.file_path),
+        }
+    }
+
+    pub fn append(&mut self, values: &[Value]) -> Result<u64, DbError> {
         for value in values {
-            if value.data_type() != self.column.data_type {
+            if !value.fits(&self.column.data_type) {
                 return Err(DbError::TypeMismatch);
             }
         }
-        let block = Block::new(values.to_vec(), compression.clone())?;
-        let min = values.iter().min_by(|a, b| a.cmp(b)).cloned().unwrap_or(Value::Int32(0));
-        let max = values.iter().max_by(|a, b| a.cmp(b)).cloned().unwrap_or(Value::Int32(0));
-        let serialized = compress(&block.values, compression.clone())?;
+        let (present, nulls) = split_nulls(values);
+        let (min, max) = bounds(&present);
+        // An all-NULL block has no encoded data at all.
+        let (compression, serialized) = self.encode_present(&present)?;
         let serialized_size = serialized.len();
 
         let mut file = OpenOptions::new()
-            .write(true)
             .append(true)
             .open(&self.file_path)?;
         let offset = file.seek(SeekFrom::End(0))?;
         file.write_all(&serialized)?;
         file.flush()?;
 
-        self.metadata.add_block(
+        self.metadata.add_blocks(vec![BlockInfo {
             min,
             max,
             offset,
-            values.len(),
+            row_count: values.len(),
             compression,
-            serialized_size,
-            &self.file_path,
-        )?;
+            serialized_size: Some(serialized_size),
+            segment_path: Some(self.file_path.clone()),
+            nulls,
+            checksum: Some(checksum(&serialized)),
+        }])?;
         Ok(offset)
     }
 
-    pub fn read(&self, condition: Option<&Condition>, buffer: &mut BufferManager) -> Result<Vec<Value>, DbError> {
+    /// Writes pre-compressed blocks back to back and records them in one
+    /// metadata update. Returns the file offset of each block. An interned
+    /// column re-encodes blocks that come in any other form.
+    pub fn append_encoded(&mut self, mut blocks: Vec<EncodedBlock>) -> Result<Vec<u64>, DbError> {
+        if self.intern.is_some() {
+            let foreign = |b: &&mut EncodedBlock| b.compression != CompressionType::Interned && !b.data.is_empty();
+            for block in blocks.iter_mut().filter(foreign) {
+                let values = decompress(&block.data, block.compression.clone(), &self.column.data_type)?;
+                (block.compression, block.data) = self.encode_present(&values)?;
+            }
+        }
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.file_path)?;
+        let mut offset = file.seek(SeekFrom::End(0))?;
+        let mut offsets = Vec::with_capacity(blocks.len());
+        let mut infos = Vec::with_capacity(blocks.len());
+        for block in blocks {
+            if !block.min.fits(&self.column.data_type) {
+                return Err(DbError::TypeMismatch);
+            }
+            file.write_all(&block.data)?;
+            offsets.push(offset);
+            infos.push(BlockInfo {
+                min: block.min,
+                max: block.max,
+                offset,
+                row_count: block.row_count,
+                compression: block.compression,
+                serialized_size: Some(block.data.len()),
+                segment_path: Some(self.file_path.clone()),
+                nulls: block.nulls,
+                checksum: Some(checksum(&block.data)),
+            });
+            offset += block.data.len() as u64;
+        }
+        file.flush()?;
+        self.metadata.add_blocks(infos)?;
+        Ok(offsets)
+    }
+
+    pub fn read(&self, condition: Option<&Condition>, buffer: &BufferManager) -> Result<Vec<Value>, DbError> {
         let blocks = self.metadata.get_blocks(condition);
+        self.read_blocks(blocks, buffer, false)
+    }
+
+    /// Reads the blocks whose position in this column satisfies `keep`.
+    /// Blocks that can't be read are skipped.
+    pub fn read_selected(
+        &self,
+        keep: impl Fn(usize) -> bool,
+        buffer: &BufferManager,
+    ) -> Result<Vec<Value>, DbError> {
+        self.read_selected_with(keep, buffer, false)
+    }
+
+    /// `read_selected`, but when `strict` a block that fails its checksum
+    /// or can't be decoded fails the read instead of being skipped.
+    pub fn read_selected_with(
+        &self,
+        keep: impl Fn(usize) -> bool,
+        buffer: &BufferManager,
+        strict: bool,
+    ) -> Result<Vec<Value>, DbError> {
+        let blocks = self
+            .metadata
+            .blocks
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| keep(*i))
+            .map(|(_, block)| block)
+            .collect();
+        self.read_blocks(blocks, buffer, strict)
+    }
+
+    /// Reads a string column as dictionary codes. Dictionary-encoded blocks
+    /// are merged entry by entry, so no string is materialized per row; an
+    /// interned column's ids are taken as the codes themselves.
+    /// Like `read_selected_with`, straight into a dictionary builder. With a
+    /// `buffer`, blocks are taken from and kept in it; dictionary-encoded
+    /// ones stay in their encoded form.
+    pub fn read_selected_dictionary(
+        &self,
+        keep: impl Fn(usize) -> bool,
+        builder: &mut DictionaryBuilder,
+        buffer: Option<&BufferManager>,
+        strict: bool,
+    ) -> Result<(), DbError> {
+        let blocks: Vec<&BlockInfo> = self
+            .metadata
+            .blocks
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| keep(*i))
+            .map(|(_, block)| block)
+            .collect();
+        if blocks.is_empty() {
+            return Ok(());
+        }
+        // Codes are intern ids only if the builder starts from the intern table.
+        let seeded = self.intern.as_ref().is_some_and(|intern| builder.seed(intern.strings()));
+        let push_values = |builder: &mut DictionaryBuilder, values: &[Value]| {
+            for v in values {
+                match v {
+                    Value::String(s) => builder.push(s),
+                    Value::Null => builder.push_null(),
+                    _ => {}
+                }
+            }
+        };
+        // Opened on the first block that is not cached.
+        let mut file = None;
+        let mut data = Vec::new();
         let mut values = Vec::new();
+        let mut scratch = DecodeScratch::default();
         for block_info in blocks {
-            match self.read_block(block_info, buffer) {
-                Ok(block) => values.extend(block.values),
+            let key = self.block_key(block_info);
+            if let Some(cached) = buffer.and_then(|buffer| buffer.get(&key)) {
+                match &*cached {
+                    Decoded::Dictionary { ids, dictionary, nulls } => builder.extend_encoded(ids, dictionary, nulls)?,
+                    Decoded::Values(values) => push_values(builder, values),
+                    Decoded::Vector(_) => {
+                        values.clear();
+                        cached.extend_values(&mut values);
+                        push_values(builder, &values);
+                    }
+                }
+                continue;
+            }
+            let file = match &mut file {
+                Some(file) => file,
+                None => file.insert(self.open_file()?),
+            };
+            let ids_only = block_info.compression == CompressionType::Interned && block_info.nulls.is_empty();
+            let result = if ids_only && seeded {
+                // Not cached: the ids decode faster than the strings would be copied.
+                values.clear();
+                let ids = CompressionType::Interned;
+                self.read_block_data(file, block_info, &mut data, true)
+                    .and_then(|_| decompress_into(&data, ids, &DataType::Int32, &mut values, &mut scratch))
+                    .and_then(|_| {
+                        builder.extend_codes(values.iter().map(|v| match v {
+                            Value::Int32(id) => *id as u32,
+                            _ => u32::MAX,
+                        }))
+                    })
+            } else if block_info.compression == CompressionType::Dictionary
+                && block_info.nulls.len() < block_info.row_count
+            {
+                let nulls = &block_info.nulls;
+                self.read_block_data(file, block_info, &mut data, true)
+                    .and_then(|_| decode_dictionary(&data, &mut scratch))
+                    .and_then(|_| builder.extend_encoded(scratch.ids(), scratch.dictionary(), nulls))
+                    .map(|_| {
+                        if let Some(buffer) = buffer {
+                            let (ids, dictionary) = (scratch.ids().to_vec(), scratch.dictionary().to_vec());
+                            buffer.put(key, Decoded::Dictionary { ids, dictionary, nulls: nulls.clone() });
+                        }
+                    })
+            } else {
+                values.clear();
+                self.decode_block_into(file, block_info, &mut data, &mut values, &mut scratch).map(|_| {
+                    push_values(builder, &values);
+                    if let Some(buffer) = buffer {
+                        buffer.put(key, Decoded::Values(values.clone()));
+                    }
+                })
+            };
+            match result {
+                Err(e) if strict => return Err(self.unreadable(block_info, e)),
+                Err(e) => log::warn!("Failed to read block at offset {}: {}", block_info.offset, e),
+                Ok(()) => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `read_selected_with` for a fixed-width column, decoding blocks
+    /// straight into `out`. Each NULL row gets a default placeholder and a
+    /// `true` in `nulls`. Blocks read from the file are kept in `buffer` as
+    /// the vector `wrap` builds.
+    pub fn read_selected_primitive<T: Primitive>(
+        &self,
+        keep: impl Fn(usize) -> bool,
+        out: &mut Vec<T>,
+        nulls: &mut Vec<bool>,
+        wrap: fn(Vec<T>) -> ColumnVector,
+        buffer: &BufferManager,
+        strict: bool,
+    ) -> Result<(), DbError> {
+        let mut file = None;
+        let mut data = Vec::new();
+        for (_, block_info) in self.metadata.blocks.iter().enumerate().filter(|(i, _)| keep(*i)) {
+            let key = self.block_key(block_info);
+            if let Some(cached) = buffer.get(&key) {
+                match &*cached {
+                    Decoded::Vector(vector) if let Some(values) = T::values(vector) => {
+                        out.extend_from_slice(values);
+                        match vector.nulls() {
+                            Some(vector_nulls) => nulls.extend_from_slice(vector_nulls),
+                            None => nulls.resize(out.len(), false),
+                        }
+                    }
+                    _ => {
+                        let mut values = Vec::with_capacity(cached.len());
+                        cached.extend_values(&mut values);
+                        for v in &values {
+                            out.push(T::from_value(v).unwrap_or_default());
+                            nulls.push(v.is_null());
+                        }
+                    }
+                }
+                continue;
+            }
+            let file = match &mut file {
+                Some(file) => file,
+                None => file.insert(self.open_for_read()?),
+            };
+            let start = out.len();
+            let result = if block_info.nulls.len() < block_info.row_count {
+                self.read_block_data(file, block_info, &mut data, true)
+                    .and_then(|_| decompress_primitive_into(&data, block_info.compression.clone(), out))
+                    .and_then(|decoded| match decoded {
+                        0 => Err(DbError::SerializationError("No values deserialized".to_string())),
+                        _ => Ok(()),
+                    })
+            } else {
+                Ok(())
+            };
+            match result {
+                Ok(()) if block_info.nulls.is_empty() => nulls.resize(out.len(), false),
+                Ok(()) => {
+                    let mut present = out.split_off(start).into_iter();
+                    let mut block_nulls = block_info.nulls.iter().peekable();
+                    for row in 0..block_info.row_count as u32 {
+                        let null = block_nulls.next_if_eq(&&row).is_some();
+                        out.push(if null { T::default() } else { present.next().unwrap_or_default() });
+                        nulls.push(null);
+                    }
+                }
+                Err(e) if strict => return Err(self.unreadable(block_info, e)),
                 Err(e) => {
+                    out.truncate(start);
                     log::warn!("Failed to read block at offset {}: {}", block_info.offset, e);
                     continue;
                 }
             }
+            let vector = with_nulls(wrap(out[start..].to_vec()), nulls[start..].to_vec());
+            buffer.put(key, Decoded::Vector(vector));
         }
-        Ok(values)
+        Ok(())
+    }
+
+    /// The error a strict read fails with, naming the block.
+    fn unreadable(&self, block_info: &BlockInfo, e: DbError) -> DbError {
+        DbError::SerializationError(format!(
+            "Unreadable block of {} at offset {} ({} rows): {}",
+            self.file_path, block_info.offset, block_info.row_count, e
+        ))
     }
 
-    pub fn read_block(&self, block_info: &BlockInfo, _buffer: &mut BufferManager) -> Result<Block, DbError> {
-        let mut file = File::open(&self.file_path).map_err(|e| {
+    fn block_key(&self, block_info: &BlockInfo) -> BlockKey {
+        BlockKey {
+            file_path: self.file_path.clone(),
+            generation: self.generation,
+            offset: block_info.offset,
+        }
+    }
+
+    fn open_for_read(&self) -> Result<File, DbError> {
+        self.open_file().map_err(|e| {
             DbError::IoError(std::io::Error::new(
                 e.kind(),
                 format!("Failed to open column file {}: {}", self.file_path, e),
             ))
-        })?;
-        file.seek(SeekFrom::Start(block_info.offset))?;
+        })
+    }
+
+    /// Decodes `blocks` in order, taking each from `buffer` when it is cached
+    /// there and caching the ones that were read from the file.
+    fn read_blocks(
+        &self,
+        blocks: Vec<&BlockInfo>,
+        buffer: &BufferManager,
+        strict: bool,
+    ) -> Result<Vec<Value>, DbError> {
+        let mut values = Vec::with_capacity(blocks.iter().map(|b| b.row_count).sum());
+        // Opened on the first block that is not cached.
+        let mut file = None;
+        // One read buffer and one decode scratch for the whole scan.
+        let mut data = Vec::new();
+        let mut scratch = DecodeScratch::default();
+        for block_info in blocks {
+            let key = self.block_key(block_info);
+            if let Some(cached) = buffer.get(&key) {
+                cached.extend_values(&mut values);
+                continue;
+            }
+            let file = match &mut file {
+                Some(file) => file,
+                None => file.insert(self.open_for_read()?),
+            };
+            let start = values.len();
+            match self.decode_block_into(file, block_info, &mut data, &mut values, &mut scratch) {
+                Ok(()) => buffer.put(key, Decoded::Values(values[start..].to_vec())),
+                Err(e) if strict => return Err(self.unreadable(block_info, e)),
+                Err(e) => {
+                    // A block that failed part way may have decoded some values.
+                    values.truncate(start);
+                    log::warn!("Failed to read block at offset {}: {}", block_info.offset, e)
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Decodes a block onto `values`, NULLs included, once its bytes match their checksum.
+    fn decode_block_into(
+        &self,
+        file: &mut File,
+        block_info: &BlockInfo,
+        data: &mut Vec<u8>,
+        values: &mut Vec<Value>,
+        scratch: &mut DecodeScratch,
+    ) -> Result<(), DbError> {
+        let start = values.len();
+        if block_info.nulls.len() < block_info.row_count {
+            self.read_block_data(file, block_info, data, true)?;
+            let decoded = decompress_into(
+                data,
+                block_info.compression.clone(),
+                &self.column.data_type,
+                values,
+                scratch,
+            )?;
+            if decoded == 0 {
+                return Err(DbError::SerializationError("No values deserialized".to_string()));
+            }
+            if block_info.compression == CompressionType::Interned {
+                self.resolve_interned(&mut values[start..])?;
+            }
+        }
+        if !block_info.nulls.is_empty() {
+            let mut present = values.split_off(start).into_iter();
+            let mut nulls = block_info.nulls.iter().peekable();
+            for row in 0..block_info.row_count as u32 {
+                if nulls.next_if_eq(&&row).is_some() {
+                    values.push(Value::Null);
+                } else if let Some(value) = present.next() {
+                    values.push(value);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Every block exactly as stored, still compressed.
+    pub fn read_encoded(&self) -> Result<Vec<EncodedBlock>, DbError> {
+        let mut blocks = Vec::with_capacity(self.metadata.blocks.len());
+        if self.metadata.blocks.is_empty() {
+            return Ok(blocks);
+        }
+        let mut file = self.open_file()?;
+        for block_info in &self.metadata.blocks {
+            let mut data = Vec::new();
+            let mut compression = block_info.compression.clone();
+            if block_info.nulls.len() < block_info.row_count {
+                self.read_block_data(&mut file, block_info, &mut data, false)?;
+            }
+            // Ids mean nothing without this column's intern table.
+            if compression == CompressionType::Interned {
+                let mut values = decompress(&data, compression, &DataType::Int32)?;
+                self.resolve_interned(&mut values)?;
+                (compression, data) = (CompressionType::Dictionary, compress(&values, CompressionType::Dictionary)?);
+            }
+            blocks.push(EncodedBlock {
+                min: block_info.min.clone(),
+                max: block_info.max.clone(),
+                row_count: block_info.row_count,
+                compression,
+                data,
+                nulls: block_info.nulls.clone(),
+            });
+        }
+        Ok(blocks)
+    }
+
+    /// The block's encoded bytes as stored; empty for an all-NULL block.
+    pub fn read_block_bytes(&self, block_info: &BlockInfo) -> Result<Vec<u8>, DbError> {
+        let mut data = Vec::new();
+        if block_info.serialized_size.unwrap_or(0) > 0 {
+            self.read_block_data(&mut self.open_file()?, block_info, &mut data, false)?;
+        }
+        Ok(data)
+    }
+
+    /// Reads a block's stored bytes; with `verify`, checks them against the
+    /// checksum recorded when the block was written, if there is one.
+    fn read_block_data(
+        &self,
+        file: &mut File,
+        block_info: &BlockInfo,
+        data: &mut Vec<u8>,
+        verify: bool,
+    ) -> Result<(), DbError> {
         let size = block_info.serialized_size.ok_or_else(|| {
             DbError::InvalidData("Serialized size missing".to_string())
         })?;
-        let mut data = vec![0u8; size];
-        file.read_exact(&mut data)?;
-        Block::deserialize(&data, &self.column.data_type, block_info.compression.clone())
+        if size == 0 {
+            return Err(DbError::SerializationError("Empty block data".to_string()));
+        }
+        file.seek(SeekFrom::Start(block_info.offset))?;
+        data.resize(size, 0);
+        file.read_exact(data)?;
+        if verify
+            && let Some(expected) = block_info.checksum
+            && checksum(data) != expected
+        {
+            return Err(DbError::SerializationError(format!(
+                "checksum {:016x} does not match the stored {:016x}",
+                checksum(data),
+                expected
+            )));
+        }
+        Ok(())
+    }
+
+    /// Decodes block `index` from the file, bypassing the buffer cache, and
+    /// fails unless it matches its checksum and row count.
+    pub fn read_block_verified(&self, index: usize) -> Result<Vec<Value>, DbError> {
+        let block_info = self
+            .metadata
+            .blocks
+            .get(index)
+            .ok_or_else(|| DbError::InvalidData(format!("{} has no block {}", self.file_path, index)))?;
+        let mut values = Vec::with_capacity(block_info.row_count);
+        let mut file = self.open_for_read()?;
+        let mut scratch = DecodeScratch::default();
+        self.decode_block_into(&mut file, block_info, &mut Vec::new(), &mut values, &mut scratch)
+            .map_err(|e| self.unreadable(block_info, e))?;
+        if values.len() != block_info.row_count {
+            let e = DbError::SerializationError(format!("decoded {} values", values.len()));
+            return Err(self.unreadable(block_info, e));
+        }
+        Ok(values)
+    }
+
+    /// The block's non-NULL values, through `buffer`.
+    pub fn read_block(&self, block_info: &BlockInfo, buffer: &BufferManager) -> Result<Block, DbError> {
+        let key = self.block_key(block_info);
+        let mut values = Vec::with_capacity(block_info.row_count);
+        match buffer.get(&key) {
+            Some(pinned) => pinned.extend_values(&mut values),
+            None => {
+                let mut file = self.open_for_read()?;
+                let mut scratch = DecodeScratch::default();
+                self.decode_block_into(&mut file, block_info, &mut Vec::new(), &mut values, &mut scratch)?;
+                buffer.put(key, Decoded::Values(values.clone()));
+            }
+        }
+        values.retain(|v| !v.is_null());
+        Block::new(values, block_info.compression.clone())
+    }
+
+    /// Writes a new version of block `index` at the end of the file and points
+    /// the metadata at it. The old bytes stay in place until the file is rewritten.
+    pub fn replace_block(&mut self, index: usize, values: &[Value]) -> Result<u64, DbError> {
+        if index >= self.metadata.blocks.len() {
+            return Err(DbError::InvalidData(format!("Block {} out of range", index)));
+        }
+        if values.iter().any(|v| !v.fits(&self.column.data_type)) {
+            return Err(DbError::TypeMismatch);
+        }
+        let (present, nulls) = split_nulls(values);
+        let (compression, serialized) = self.encode_present(&present)?;
+        let mut file = OpenOptions::new().append(true).open(&self.file_path)?;
+        let offset = file.seek(SeekFrom::End(0))?;
+        file.write_all(&serialized)?;
+        file.flush()?;
+
+        let block = &mut self.metadata.blocks[index];
+        (block.min, block.max) = bounds(&present);
+        block.nulls = nulls;
+        block.offset = offset;
+        block.row_count = values.len();
+        block.compression = compression;
+        block.serialized_size = Some(serialized.len());
+        block.checksum = Some(checksum(&serialized));
+        self.metadata.save()?;
+        Ok(offset)
+    }
+
+    /// Rebuilds `index` from this column's blocks, keyed to each block's offset.
+    pub fn reindex(&self, index: &mut Index, buffer: &BufferManager) -> Result<(), DbError> {
+        index.clear()?;
+        for (i, block) in self.metadata.blocks.iter().enumerate() {
+            let values = self.read_selected(|j| j == i, buffer)?;
+            index.append(&values, block.offset)?;
+        }
+        Ok(())
     }
 
     pub fn clear(&mut self) -> Result<(), DbError> {
         self.metadata.blocks.clear();
         self.metadata.save()?;
+        self.generation = next_generation();
 
-        // Truncate the column file
+        // Unlink rather than truncate: snapshots keep reading the old file.
+        fs::remove_file(&self.file_path)?;
         File::create(&self.file_path)?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+/// Where the intern table of the column stored in `file_path` lives.
+fn intern_path(file_path: &str) -> String {
+    match file_path.strip_suffix(".dat") {
+        Some(stem) => format!("{}.intern", stem),
+        None => format!("{}.intern", file_path),
+    }
+}
+
+/// Systematic block sampling: spreads `percent` of the blocks evenly across
+/// the column instead of picking them at random, which keeps samples stable.
+/// The first block is always taken, so a small table still returns rows.
+pub fn block_sampled(block_index: usize, percent: f64) -> bool {
+    let fraction = (percent / 100.0).clamp(0.0, 1.0);
+    let before = (block_index as f64 * fraction).ceil();
+    let after = ((block_index + 1) as f64 * fraction).ceil();
+    after > before
+}
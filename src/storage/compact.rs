@@ -0,0 +1,119 @@
+use crate::storage::StorageManager;
+use crate::types::DbError;
+use std::fs;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CompactReport {
+    pub blocks_before: usize,
+    pub blocks_after: usize,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+/// Ranges of adjacent blocks to merge, each holding at most `target` rows
+/// unless it is a single block that was already larger.
+fn merge_groups(row_counts: &[usize], target: usize) -> Vec<std::ops::Range<usize>> {
+    let mut groups = Vec::new();
+    let (mut start, mut rows) = (0, 0);
+    for (i, &count) in row_counts.iter().enumerate() {
+        if i > start && rows + count > target {
+            groups.push(start..i);
+            (start, rows) = (i, 0);
+        }
+        rows += count;
+    }
+    if start < row_counts.len() {
+        groups.push(start..row_counts.len());
+    }
+    groups
+}
+
+impl StorageManager {
+    fn table_file_bytes(&self, table_name: &str) -> u64 {
+        self.columns
+            .get(table_name)
+            .into_iter()
+            .flat_map(|cols| cols.values())
+            .map(|store| fs::metadata(&store.file_path).map_or(0, |m| m.len()))
+            .sum()
+    }
+
+    /// `COMPACT table`: merges runs of adjacent blocks into blocks of up to
+    /// `segment_rows` rows and rewrites the column files without the bytes
+    /// that replaced or deleted blocks left behind. Indexes are rebuilt.
+    pub fn compact_table(&mut self, table_name: &str) -> Result<CompactReport, DbError> {
+        self.ensure_writable()?;
+        self.ensure_stored(table_name)?;
+        let table_def = self
+            .schema
+            .get_table(table_name)
+            .cloned()
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?;
+        let table_cols = &self.columns[table_name];
+        // Block i of every column holds the same rows, so one column decides the grouping.
+        let row_counts: Vec<usize> = table_cols[&table_def.columns[0].name]
+            .metadata
+            .blocks
+            .iter()
+            .map(|b| b.row_count)
+            .collect();
+        if table_cols.values().any(|store| {
+            store.metadata.blocks.len() != row_counts.len()
+                || store.metadata.blocks.iter().zip(&row_counts).any(|(b, &rows)| b.row_count != rows)
+        }) {
+            return Err(DbError::InvalidData(format!("Columns of {} have different block layouts", table_name)));
+        }
+
+        let groups = merge_groups(&row_counts, self.settings.segment_rows);
+        let bytes_before = self.table_file_bytes(table_name);
+        let live_bytes: u64 = table_cols
+            .values()
+            .flat_map(|store| &store.metadata.blocks)
+            .map(|b| b.serialized_size.unwrap_or(0) as u64)
+            .sum();
+        let mut report = CompactReport {
+            blocks_before: row_counts.len(),
+            blocks_after: groups.len(),
+            bytes_before,
+            bytes_after: bytes_before,
+        };
+        if groups.len() == row_counts.len() && live_bytes == bytes_before {
+            return Ok(report);
+        }
+
+        let table_cols = self.columns.get_mut(table_name).unwrap();
+        let table_indexes = self.indexes.entry(table_name.to_string()).or_default();
+        for col in &table_def.columns {
+            let store = table_cols.get_mut(&col.name).unwrap();
+            let mut merged = Vec::with_capacity(groups.len());
+            for group in &groups {
+                merged.push(store.read_selected(|i| group.contains(&i), &self.buffer)?);
+            }
+            store.clear()?;
+            for values in merged {
+                store.append(&values)?;
+            }
+            if let Some(index) = table_indexes.get_mut(&col.name) {
+                store.reindex(index, &self.buffer)?;
+            }
+        }
+        self.bump_table_version(table_name);
+        report.bytes_after = self.table_file_bytes(table_name);
+        Ok(report)
+    }
+
+    /// Compacts the table once merging its blocks would remove at least
+    /// `compact_threshold` of them.
+    pub(crate) fn compact_if_fragmented(&mut self, table_name: &str) -> Result<(), DbError> {
+        let Some(store) = self.columns.get(table_name).and_then(|cols| cols.values().next()) else {
+            return Ok(());
+        };
+        let row_counts: Vec<usize> = store.metadata.blocks.iter().map(|b| b.row_count).collect();
+        let saved = row_counts.len() - merge_groups(&row_counts, self.settings.segment_rows).len();
+        if saved >= self.settings.compact_threshold {
+            let report = self.compact_table(table_name)?;
+            log::info!("Compacted {} from {} to {} blocks", table_name, report.blocks_before, report.blocks_after);
+        }
+        Ok(())
+    }
+}
@@ -1,21 +1,18 @@
+use crate::query::batch::ColumnVector;
 use crate::types::{CompressionType, DbError, Value, DataType};
 use std::collections::HashMap;
 use byteorder::{LittleEndian, WriteBytesExt, ReadBytesExt};
 use std::io::{Read, Cursor};
 
+/// Zstandard level for `CompressionType::Zstd`; the library default.
+const ZSTD_LEVEL: i32 = 3;
+
 pub fn compress(values: &[Value], compression: CompressionType) -> Result<Vec<u8>, DbError> {
     match compression {
         CompressionType::None => {
             let mut buffer = Vec::new();
             for value in values {
-                match value {
-                    Value::Int32(i) => buffer.write_i32::<LittleEndian>(*i)?,
-                    Value::Float32(f) => buffer.write_f32::<LittleEndian>(f.0)?,
-                    Value::String(s) => {
-                        buffer.write_u64::<LittleEndian>(s.len() as u64)?;
-                        buffer.extend_from_slice(s.as_bytes());
-                    }
-                }
+                write_value(&mut buffer, value)?;
             }
             Ok(buffer)
         }
@@ -63,166 +60,401 @@ pub fn compress(values: &[Value], compression: CompressionType) -> Result<Vec<u8
             }
             Ok(buffer)
         }
+        CompressionType::Lz4 => Ok(lz4_flex::compress_prepend_size(&compress(values, CompressionType::None)?)),
+        CompressionType::Zstd => Ok(zstd::encode_all(&compress(values, CompressionType::None)?[..], ZSTD_LEVEL)?),
+        // `values` are the INT ids the column store interned the strings as.
+        CompressionType::Interned => {
+            if values.iter().any(|v| !matches!(v, Value::Int32(_))) {
+                return Err(DbError::InvalidData("Interned blocks hold intern ids".to_string()));
+            }
+            compress(values, CompressionType::Rle)
+        }
     }
 }
 
-fn write_rle_value(buffer: &mut Vec<u8>, value: &Value, count: usize) -> Result<(), DbError> {
-    if count > 255 {
-        return Err(DbError::InvalidData("RLE run length exceeds 255".to_string()));
+/// Undoes the byte codec of an LZ4 or Zstd block, leaving its plain encoding.
+fn decompress_bytes(data: &[u8], compression: &CompressionType) -> Result<Vec<u8>, DbError> {
+    match compression {
+        CompressionType::Lz4 => lz4_flex::decompress_size_prepended(data)
+            .map_err(|e| DbError::SerializationError(format!("LZ4: {}", e))),
+        CompressionType::Zstd => zstd::decode_all(data)
+            .map_err(|e| DbError::SerializationError(format!("Zstd: {}", e))),
+        _ => Ok(data.to_vec()),
     }
-    buffer.write_u8(count as u8)?;
+}
+
+/// Longest run one RLE entry holds; its length is a single byte.
+const MAX_RLE_RUN: usize = u8::MAX as usize;
+
+/// Writes a run as consecutive entries of at most `MAX_RLE_RUN` values, so
+/// the block format stays the one older blocks were written in.
+fn write_rle_value(buffer: &mut Vec<u8>, value: &Value, mut count: usize) -> Result<(), DbError> {
+    while count > 0 {
+        let run = count.min(MAX_RLE_RUN);
+        buffer.write_u8(run as u8)?;
+        write_value(buffer, value)?;
+        count -= run;
+    }
+    Ok(())
+}
+
+fn write_value(buffer: &mut Vec<u8>, value: &Value) -> Result<(), DbError> {
     match value {
-        Value::Int32(i) => buffer.write_i32::<LittleEndian>(*i)?,
+        Value::Int32(i) | Value::Date(i) => buffer.write_i32::<LittleEndian>(*i)?,
+        Value::Int64(i) | Value::Timestamp(i) => buffer.write_i64::<LittleEndian>(*i)?,
         Value::Float32(f) => buffer.write_f32::<LittleEndian>(f.0)?,
+        Value::Boolean(b) => buffer.write_u8(*b as u8)?,
         Value::String(s) => {
             buffer.write_u64::<LittleEndian>(s.len() as u64)?;
             buffer.extend_from_slice(s.as_bytes());
         }
+        Value::Null => return Err(null_in_block()),
+    }
+    Ok(())
+}
+
+/// Bytes `write_value` produces for `value`.
+fn encoded_len(value: &Value) -> usize {
+    match value {
+        Value::String(s) => 8 + s.len(),
+        Value::Null => 0,
+        other => other.data_type().and_then(|t| t.fixed_width()).unwrap_or(0),
+    }
+}
+
+fn null_in_block() -> DbError {
+    DbError::InvalidData("NULL values are stored in block metadata, not in encoded data".to_string())
+}
+
+/// Scratch space reused across `decompress_into` calls so hot scans don't
+/// reallocate id and dictionary tables for every block.
+#[derive(Default)]
+pub struct DecodeScratch {
+    ids: Vec<u64>,
+    dictionary: Vec<Option<String>>,
+    bytes: Vec<u8>,
+}
+
+impl DecodeScratch {
+    /// Per-row dictionary ids of the last block passed to `decode_dictionary`.
+    pub fn ids(&self) -> &[u64] {
+        &self.ids
+    }
+
+    /// Dictionary of the last decoded block, indexed by id.
+    pub fn dictionary(&self) -> &[Option<String>] {
+        &self.dictionary
+    }
+
+    pub fn entry(&self, id: u64) -> Result<&str, DbError> {
+        self.dictionary
+            .get(id as usize)
+            .and_then(|s| s.as_deref())
+            .ok_or_else(|| DbError::SerializationError(format!("Invalid dictionary ID: {}", id)))
+    }
+}
+
+/// Decodes a dictionary block's ids and entries into `scratch` without
+/// materializing a string per row.
+pub fn decode_dictionary(data: &[u8], scratch: &mut DecodeScratch) -> Result<(), DbError> {
+    let mut cursor = Cursor::new(data);
+    scratch.ids.clear();
+    scratch.dictionary.clear();
+    let value_count = cursor.read_u64::<LittleEndian>()
+        .map_err(|e| DbError::SerializationError(format!("Failed to read value count: {}", e)))? as usize;
+    if value_count == 0 {
+        return Ok(());
+    }
+    for _ in 0..value_count {
+        let id = cursor.read_u64::<LittleEndian>()
+            .map_err(|e| DbError::SerializationError(format!("Failed to read ID: {}", e)))?;
+        scratch.ids.push(id);
+    }
+    let dict_size = cursor.read_u64::<LittleEndian>()
+        .map_err(|e| DbError::SerializationError(format!("Failed to read dict size: {}", e)))? as usize;
+    // Ids are assigned densely from 0, so the dictionary is a plain table.
+    scratch.dictionary.resize(dict_size, None);
+    for _ in 0..dict_size {
+        let id = cursor.read_u64::<LittleEndian>()
+            .map_err(|e| DbError::SerializationError(format!("Failed to read dict ID: {}", e)))? as usize;
+        let s = read_string(&mut cursor, scratch)
+            .map_err(|e| DbError::SerializationError(format!("Failed to read string data: {}", e)))?;
+        let slot = scratch.dictionary.get_mut(id).ok_or_else(|| {
+            DbError::SerializationError(format!("Invalid dictionary ID: {}", id))
+        })?;
+        *slot = Some(s);
     }
     Ok(())
 }
 
 pub fn decompress(data: &[u8], compression: CompressionType, data_type: &DataType) -> Result<Vec<Value>, DbError> {
+    let mut values = Vec::new();
+    decompress_into(data, compression, data_type, &mut values, &mut DecodeScratch::default())?;
+    Ok(values)
+}
+
+fn read_string(cursor: &mut Cursor<&[u8]>, scratch: &mut DecodeScratch) -> Result<String, DbError> {
+    let len = cursor.read_u64::<LittleEndian>()
+        .map_err(|e| DbError::SerializationError(e.to_string()))? as usize;
+    scratch.bytes.resize(len, 0);
+    cursor.read_exact(&mut scratch.bytes)?;
+    std::str::from_utf8(&scratch.bytes)
+        .map(str::to_owned)
+        .map_err(|e| DbError::SerializationError(e.to_string()))
+}
+
+fn read_fixed(cursor: &mut Cursor<&[u8]>, data_type: &DataType) -> Result<Value, DbError> {
+    match data_type {
+        DataType::Int32 => cursor.read_i32::<LittleEndian>()
+            .map(Value::Int32)
+            .map_err(|e| DbError::SerializationError(e.to_string())),
+        DataType::Float32 => cursor.read_f32::<LittleEndian>()
+            .map(|f| Value::Float32(ordered_float::OrderedFloat(f)))
+            .map_err(|e| DbError::SerializationError(e.to_string())),
+        DataType::Int64 => cursor.read_i64::<LittleEndian>()
+            .map(Value::Int64)
+            .map_err(|e| DbError::SerializationError(e.to_string())),
+        DataType::Timestamp => cursor.read_i64::<LittleEndian>()
+            .map(Value::Timestamp)
+            .map_err(|e| DbError::SerializationError(e.to_string())),
+        DataType::Date => cursor.read_i32::<LittleEndian>()
+            .map(Value::Date)
+            .map_err(|e| DbError::SerializationError(e.to_string())),
+        DataType::Boolean => cursor.read_u8()
+            .map(|b| Value::Boolean(b != 0))
+            .map_err(|e| DbError::SerializationError(e.to_string())),
+        DataType::String => Err(DbError::TypeMismatch),
+    }
+}
+
+/// Decodes a block and appends its values to `out`, returning how many were
+/// added. On error `out` is left as it was. Each string becomes a `Value` of
+/// its own; scans building typed batches take a dictionary block's ids and
+/// entries from `decode_dictionary` instead.
+pub fn decompress_into(
+    data: &[u8],
+    compression: CompressionType,
+    data_type: &DataType,
+    out: &mut Vec<Value>,
+    scratch: &mut DecodeScratch,
+) -> Result<usize, DbError> {
+    let start = out.len();
+    let result = decode_block(data, compression, data_type, out, scratch);
+    if result.is_err() {
+        out.truncate(start);
+    }
+    result.map(|_| out.len() - start)
+}
+
+fn decode_block(
+    data: &[u8],
+    compression: CompressionType,
+    data_type: &DataType,
+    out: &mut Vec<Value>,
+    scratch: &mut DecodeScratch,
+) -> Result<(), DbError> {
+    let mut cursor = Cursor::new(data);
     match compression {
         CompressionType::None => {
-            let mut values = Vec::new();
-            let mut cursor = Cursor::new(data);
             while cursor.position() < data.len() as u64 {
-                match data_type {
-                    DataType::Int32 => {
-                        let value = cursor.read_i32::<LittleEndian>()
-                            .map_err(|e| DbError::SerializationError(e.to_string()))?;
-                        values.push(Value::Int32(value));
-                    }
-                    DataType::Float32 => {
-                        let value = cursor.read_f32::<LittleEndian>()
-                            .map_err(|e| DbError::SerializationError(e.to_string()))?;
-                        values.push(Value::Float32(ordered_float::OrderedFloat(value)));
-                    }
-                    DataType::String => {
-                        let len = cursor.read_u64::<LittleEndian>()
-                            .map_err(|e| DbError::SerializationError(e.to_string()))? as usize;
-                        let mut string_data = vec![0u8; len];
-                        cursor.read_exact(&mut string_data)?;
-                        let s = String::from_utf8(string_data)
-                            .map_err(|e| DbError::SerializationError(e.to_string()))?;
-                        values.push(Value::String(s));
-                    }
-                }
+                let value = match data_type {
+                    DataType::String => Value::String(read_string(&mut cursor, scratch)?),
+                    _ => read_fixed(&mut cursor, data_type)?,
+                };
+                out.push(value);
             }
-            Ok(values)
+            Ok(())
         }
         CompressionType::Rle => {
-            let mut values = Vec::new();
-            let mut cursor = Cursor::new(data);
             while cursor.position() < data.len() as u64 {
                 let count = cursor.read_u8()
                     .map_err(|e| DbError::SerializationError(e.to_string()))? as usize;
                 if count == 0 {
                     return Err(DbError::SerializationError("Invalid RLE run length".to_string()));
                 }
-                match data_type {
-                    DataType::Int32 => {
-                        let value = cursor.read_i32::<LittleEndian>()
-                            .map_err(|e| DbError::SerializationError(e.to_string()))?;
-                        for _ in 0..count {
-                            values.push(Value::Int32(value));
-                        }
-                    }
-                    DataType::Float32 => {
-                        let value = cursor.read_f32::<LittleEndian>()
-                            .map_err(|e| DbError::SerializationError(e.to_string()))?;
-                        for _ in 0..count {
-                            values.push(Value::Float32(ordered_float::OrderedFloat(value)));
-                        }
-                    }
-                    DataType::String => {
-                        let len = cursor.read_u64::<LittleEndian>()
-                            .map_err(|e| DbError::SerializationError(e.to_string()))? as usize;
-                        let mut string_data = vec![0u8; len];
-                        cursor.read_exact(&mut string_data)?;
-                        let s = String::from_utf8(string_data)
-                            .map_err(|e| DbError::SerializationError(e.to_string()))?;
-                        for _ in 0..count {
-                            values.push(Value::String(s.clone()));
-                        }
-                    }
-                }
+                let value = match data_type {
+                    DataType::String => Value::String(read_string(&mut cursor, scratch)?),
+                    _ => read_fixed(&mut cursor, data_type)?,
+                };
+                out.extend(std::iter::repeat_n(value, count));
             }
-            Ok(values)
+            Ok(())
         }
         CompressionType::Dictionary => {
-            let mut cursor = Cursor::new(data);
-            let value_count = cursor.read_u64::<LittleEndian>()
-                .map_err(|e| DbError::SerializationError(format!("Failed to read value count: {}", e)))? as usize;
-            if value_count == 0 {
-                return Ok(Vec::new());
-            }
-            let mut ids = Vec::with_capacity(value_count);
-            for _ in 0..value_count {
-                let id = cursor.read_u64::<LittleEndian>()
-                    .map_err(|e| DbError::SerializationError(format!("Failed to read ID: {}", e)))?;
-                ids.push(id);
+            decode_dictionary(data, scratch)?;
+            out.reserve(scratch.ids.len());
+            for &id in &scratch.ids {
+                out.push(Value::String(scratch.entry(id)?.to_string()));
             }
-            let dict_size = cursor.read_u64::<LittleEndian>()
-                .map_err(|e| DbError::SerializationError(format!("Failed to read dict size: {}", e)))? as usize;
-            let mut dictionary = HashMap::with_capacity(dict_size);
-            for _ in 0..dict_size {
-                let id = cursor.read_u64::<LittleEndian>()
-                    .map_err(|e| DbError::SerializationError(format!("Failed to read dict ID: {}", e)))?;
-                let len = cursor.read_u64::<LittleEndian>()
-                    .map_err(|e| DbError::SerializationError(format!("Failed to read string len: {}", e)))? as usize;
-                let mut string_data = vec![0u8; len];
-                cursor.read_exact(&mut string_data)
-                    .map_err(|e| DbError::SerializationError(format!("Failed to read string data: {}", e)))?;
-                let s = String::from_utf8(string_data)
-                    .map_err(|e| DbError::SerializationError(e.to_string()))?;
-                dictionary.insert(id, s);
+            Ok(())
+        }
+        CompressionType::Lz4 | CompressionType::Zstd => {
+            let plain = decompress_bytes(data, &compression)?;
+            decode_block(&plain, CompressionType::None, data_type, out, scratch)
+        }
+        // Ids only; the column store maps them back to its strings.
+        CompressionType::Interned => decode_block(data, CompressionType::Rle, &DataType::Int32, out, scratch),
+    }
+}
+
+/// A fixed-width column type that blocks decode to directly, without a
+/// `Value` per row.
+pub trait Primitive: Copy + Default {
+    fn read(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Self>;
+    fn from_value(value: &Value) -> Option<Self>;
+    /// The values of a vector of this type, NULL placeholders included.
+    fn values(vector: &ColumnVector) -> Option<&[Self]>;
+}
+
+impl Primitive for i32 {
+    fn read(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Self> {
+        cursor.read_i32::<LittleEndian>()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int32(i) | Value::Date(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn values(vector: &ColumnVector) -> Option<&[Self]> {
+        match vector {
+            ColumnVector::Int32(v) | ColumnVector::Date(v) => Some(v),
+            ColumnVector::Nullable { values, .. } => Self::values(values),
+            _ => None,
+        }
+    }
+}
+
+impl Primitive for i64 {
+    fn read(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Self> {
+        cursor.read_i64::<LittleEndian>()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Int64(i) | Value::Timestamp(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    fn values(vector: &ColumnVector) -> Option<&[Self]> {
+        match vector {
+            ColumnVector::Int64(v) | ColumnVector::Timestamp(v) => Some(v),
+            ColumnVector::Nullable { values, .. } => Self::values(values),
+            _ => None,
+        }
+    }
+}
+
+impl Primitive for f32 {
+    fn read(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Self> {
+        cursor.read_f32::<LittleEndian>()
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Float32(f) => Some(f.0),
+            _ => None,
+        }
+    }
+
+    fn values(vector: &ColumnVector) -> Option<&[Self]> {
+        match vector {
+            ColumnVector::Float32(v) => Some(v),
+            ColumnVector::Nullable { values, .. } => Self::values(values),
+            _ => None,
+        }
+    }
+}
+
+impl Primitive for bool {
+    fn read(cursor: &mut Cursor<&[u8]>) -> std::io::Result<Self> {
+        cursor.read_u8().map(|b| b != 0)
+    }
+
+    fn from_value(value: &Value) -> Option<Self> {
+        match value {
+            Value::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    fn values(vector: &ColumnVector) -> Option<&[Self]> {
+        match vector {
+            ColumnVector::Boolean(v) => Some(v),
+            ColumnVector::Nullable { values, .. } => Self::values(values),
+            _ => None,
+        }
+    }
+}
+
+/// Like `decompress_into` for a fixed-width column, appending the raw
+/// values to `out`. On error `out` is left as it was.
+pub fn decompress_primitive_into<T: Primitive>(
+    data: &[u8],
+    compression: CompressionType,
+    out: &mut Vec<T>,
+) -> Result<usize, DbError> {
+    let start = out.len();
+    let result = decode_primitive(data, compression, out);
+    if result.is_err() {
+        out.truncate(start);
+    }
+    result.map(|_| out.len() - start)
+}
+
+fn decode_primitive<T: Primitive>(data: &[u8], compression: CompressionType, out: &mut Vec<T>) -> Result<(), DbError> {
+    let mut cursor = Cursor::new(data);
+    match compression {
+        CompressionType::None => {
+            while cursor.position() < data.len() as u64 {
+                out.push(T::read(&mut cursor).map_err(|e| DbError::SerializationError(e.to_string()))?);
             }
-            let mut values = Vec::with_capacity(value_count);
-            for id in ids {
-                let s = dictionary.get(&id).ok_or_else(|| {
-                    DbError::SerializationError(format!("Invalid dictionary ID: {}", id))
-                })?.clone();
-                values.push(Value::String(s));
+            Ok(())
+        }
+        CompressionType::Rle => {
+            while cursor.position() < data.len() as u64 {
+                let count = cursor.read_u8()
+                    .map_err(|e| DbError::SerializationError(e.to_string()))? as usize;
+                if count == 0 {
+                    return Err(DbError::SerializationError("Invalid RLE run length".to_string()));
+                }
+                let value = T::read(&mut cursor).map_err(|e| DbError::SerializationError(e.to_string()))?;
+                out.extend(std::iter::repeat_n(value, count));
             }
-            Ok(values)
+            Ok(())
         }
+        CompressionType::Lz4 | CompressionType::Zstd => {
+            let plain = decompress_bytes(data, &compression)?;
+            decode_primitive(&plain, CompressionType::None, out)
+        }
+        CompressionType::Dictionary | CompressionType::Interned => Err(DbError::TypeMismatch),
     }
 }
 
 pub fn estimate_compressed_size(values: &[Value], compression: CompressionType) -> usize {
     match compression {
-        CompressionType::None => values.iter().map(|v| match v {
-            Value::Int32(_) => 4,
-            Value::Float32(_) => 4,
-            Value::String(s) => 8 + s.len(),
-        }).sum(),
+        CompressionType::None => values.iter().map(encoded_len).sum(),
         CompressionType::Rle => {
             if values.is_empty() {
                 return 0;
             }
+            let entries = |value: &Value, count: usize| count.div_ceil(MAX_RLE_RUN) * (1 + encoded_len(value));
             let mut size = 0;
             let mut current = &values[0];
-            let mut _count = 1;
+            let mut count = 1;
             for value in values.iter().skip(1) {
                 if value != current {
-                    size += 1 + match current {
-                        Value::Int32(_) => 4,
-                        Value::Float32(_) => 4,
-                        Value::String(s) => 8 + s.len(),
-                    };
+                    size += entries(current, count);
                     current = value;
-                    _count = 1;
+                    count = 1;
                 } else {
-                    _count += 1;
+                    count += 1;
                 }
             }
-            size + 1 + match current {
-                Value::Int32(_) => 4,
-                Value::Float32(_) => 4,
-                Value::String(s) => 8 + s.len(),
-            }
+            size + entries(current, count)
         }
         CompressionType::Dictionary => {
             let mut dictionary: HashMap<&String, u64> = HashMap::new();
@@ -236,7 +468,26 @@ pub fn estimate_compressed_size(values: &[Value], compression: CompressionType)
                     });
                 }
             }
-            8 + (values.len() * 8) + dictionary.iter().map(|(s, _)| 8 + 8 + s.len()).sum::<usize>()
+            // Value count and dictionary size, then the ids and entries.
+            16 + (values.len() * 8) + dictionary.keys().map(|s| 8 + 8 + s.len()).sum::<usize>()
+        }
+        // Byte codecs depend on the data too much to guess; run them.
+        CompressionType::Lz4 | CompressionType::Zstd => compress(values, compression)
+            .map_or_else(|_| estimate_compressed_size(values, CompressionType::None), |data| data.len()),
+        CompressionType::Interned => {
+            // The ids run where the strings do; any two adjacent ones differ.
+            let mut id = 0;
+            let ids: Vec<Value> = values
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    if i > 0 && *value != values[i - 1] {
+                        id ^= 1;
+                    }
+                    Value::Int32(id)
+                })
+                .collect();
+            estimate_compressed_size(&ids, CompressionType::Rle)
         }
     }
 }
\ No newline at end of file
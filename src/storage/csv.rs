@@ -0,0 +1,169 @@
+use crate::schema::{Column, Validation};
+use crate::storage::bulk::{encode_columns, EncodedChunk, BULK_CHUNK_ROWS};
+use crate::storage::StorageManager;
+use crate::types::{DataType, DbError, Value};
+use ordered_float::OrderedFloat;
+use std::fs;
+use std::thread;
+
+#[derive(Clone, Debug)]
+pub struct CsvLoadOptions {
+    pub has_header: bool,
+    pub delimiter: char,
+    /// Rows per encoded block; each chunk is parsed and compressed by one worker.
+    pub chunk_rows: usize,
+    pub threads: usize,
+}
+
+impl Default for CsvLoadOptions {
+    fn default() -> Self {
+        CsvLoadOptions {
+            has_header: true,
+            delimiter: ',',
+            chunk_rows: BULK_CHUNK_ROWS,
+            threads: thread::available_parallelism().map_or(1, |n| n.get()),
+        }
+    }
+}
+
+/// Splits one CSV record, honouring double quotes and `""` escapes.
+pub fn split_csv_line(line: &str, delimiter: char) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    let mut chars = line.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if in_quotes && chars.peek() == Some(&'"') => {
+                current.push('"');
+                chars.next();
+            }
+            '"' => in_quotes = !in_quotes,
+            c if c == delimiter && !in_quotes => {
+                fields.push(std::mem::take(&mut current));
+            }
+            c => current.push(c),
+        }
+    }
+    fields.push(current);
+    fields
+}
+
+pub fn parse_csv_value(field: &str, data_type: &DataType) -> Result<Value, DbError> {
+    let field = field.trim();
+    match data_type {
+        DataType::Int32 => field
+            .parse::<i32>()
+            .map(Value::Int32)
+            .map_err(|_| DbError::InvalidData(format!("Invalid integer value: {}", field))),
+        DataType::Float32 => field
+            .parse::<f32>()
+            .map(|f| Value::Float32(OrderedFloat(f)))
+            .map_err(|_| DbError::InvalidData(format!("Invalid float value: {}", field))),
+        DataType::String => Ok(Value::String(field.to_string())),
+        DataType::Int64 => field
+            .parse::<i64>()
+            .map(Value::Int64)
+            .map_err(|_| DbError::InvalidData(format!("Invalid integer value: {}", field))),
+        DataType::Boolean => match field.to_ascii_lowercase().as_str() {
+            "true" | "t" | "1" => Ok(Value::Boolean(true)),
+            "false" | "f" | "0" => Ok(Value::Boolean(false)),
+            _ => Err(DbError::InvalidData(format!("Invalid boolean value: {}", field))),
+        },
+        // Timestamps may also be given as epoch seconds.
+        DataType::Date | DataType::Timestamp => match field.parse::<i64>() {
+            Ok(seconds) if *data_type == DataType::Timestamp => Ok(Value::Timestamp(seconds)),
+            _ => Value::String(field.to_string()).coerce(data_type),
+        },
+    }
+}
+
+fn encode_chunk(
+    lines: &[(usize, &str)],
+    columns: &[Column],
+    validation: Validation,
+    delimiter: char,
+) -> Result<EncodedChunk, DbError> {
+    let mut values: Vec<Vec<Value>> = vec![Vec::with_capacity(lines.len()); columns.len()];
+    for (line_no, line) in lines {
+        let fields = split_csv_line(line, delimiter);
+        if fields.len() != columns.len() {
+            return Err(DbError::InvalidData(format!(
+                "Line {}: expected {} columns, got {}",
+                line_no,
+                columns.len(),
+                fields.len()
+            )));
+        }
+        for (i, (field, col)) in fields.iter().zip(columns).enumerate() {
+            // An empty field is NULL, except in string columns where it is "".
+            let value = if col.nullable && col.data_type != DataType::String && field.trim().is_empty() {
+                Ok(Value::Null)
+            } else {
+                parse_csv_value(field, &col.data_type).or_else(|e| match validation {
+                    Validation::Strict => Err(e),
+                    Validation::Lenient => Value::String(field.clone()).coerce_lenient(&col.data_type),
+                })
+            }
+            .and_then(|v| col.check_value(&v).map(|_| v))
+            .map_err(|e| DbError::InvalidData(format!("Line {}: {}", line_no, e)))?;
+            values[i].push(value);
+        }
+    }
+    encode_columns(columns, values)
+}
+
+impl StorageManager {
+    /// Bulk-loads a CSV file: worker threads parse, validate and compress
+    /// chunks, then each column file is written by its own thread.
+    /// Returns the number of rows loaded; nothing is written if any row fails.
+    pub fn load_csv(&mut self, table_name: &str, path: &str, options: &CsvLoadOptions) -> Result<usize, DbError> {
+        self.ensure_writable()?;
+        let table_def = self
+            .schema
+            .get_table(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?
+            .clone();
+        let contents = fs::read_to_string(path)?;
+        let lines: Vec<(usize, &str)> = contents
+            .lines()
+            .enumerate()
+            .skip(usize::from(options.has_header))
+            .map(|(i, l)| (i + 1, l))
+            .filter(|(_, l)| !l.trim().is_empty())
+            .collect();
+        if lines.is_empty() {
+            return Ok(0);
+        }
+
+        // Parse → validate → compress, one chunk per block, spread across workers.
+        let chunks: Vec<&[(usize, &str)]> = lines.chunks(options.chunk_rows.max(1)).collect();
+        let threads = options.threads.max(1).min(chunks.len());
+        let columns = &table_def.columns;
+        let mut encoded: Vec<Option<Result<EncodedChunk, DbError>>> = (0..chunks.len()).map(|_| None).collect();
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..threads)
+                .map(|worker| {
+                    let chunks = &chunks;
+                    scope.spawn(move || {
+                        (worker..chunks.len())
+                            .step_by(threads)
+                            .map(|i| (i, encode_chunk(chunks[i], columns, table_def.validation, options.delimiter)))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            for worker in workers {
+                for (i, chunk) in worker.join().expect("CSV worker panicked") {
+                    encoded[i] = Some(chunk);
+                }
+            }
+        });
+        let encoded = encoded
+            .into_iter()
+            .map(|chunk| chunk.expect("every chunk is encoded"))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.append_chunks(&table_def, encoded)
+    }
+}
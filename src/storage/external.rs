@@ -0,0 +1,287 @@
+use crate::query::evaluator::evaluate_condition_blocks;
+use crate::query::Condition;
+use crate::schema::metadata::BlockInfo;
+use crate::schema::{Column, Table};
+use crate::storage::column::block_sampled;
+use crate::storage::csv::{parse_csv_value, split_csv_line};
+use crate::storage::StorageManager;
+use crate::types::{Collation, CompressionType, DataType, DbError, Value};
+use ordered_float::OrderedFloat;
+use parquet::basic::Type as PhysicalType;
+use parquet::file::reader::{FileReader, SerializedFileReader};
+use parquet::file::statistics::Statistics;
+use parquet::record::Field;
+use parquet::schema::types::{ColumnDescPtr, Type};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+fn parquet_error(path: &Path, e: parquet::errors::ParquetError) -> DbError {
+    DbError::InvalidData(format!("{}: {}", path.display(), e))
+}
+
+fn is_parquet(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e.eq_ignore_ascii_case("parquet"))
+}
+
+/// Matches `name` against a pattern where `*` stands for any run of
+/// characters and `?` for exactly one.
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| wildcard_match(rest, &name[skip..])),
+        Some((&p, rest)) => {
+            name.split_first().is_some_and(|(&c, tail)| (p == '?' || p == c) && wildcard_match(rest, tail))
+        }
+    }
+}
+
+/// Files an external table's `LOCATION` names, in name order. Wildcards are
+/// only allowed in the last path component.
+pub fn external_files(location: &str) -> Result<Vec<PathBuf>, DbError> {
+    let path = Path::new(location);
+    let file_pattern = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    if !file_pattern.contains(['*', '?']) {
+        return Ok(vec![path.to_path_buf()]);
+    }
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let pattern: Vec<char> = file_pattern.chars().collect();
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name: Vec<char> = entry.file_name().to_string_lossy().chars().collect();
+        if entry.file_type()?.is_file() && wildcard_match(&pattern, &name) {
+            files.push(entry.path());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Parses one column out of a CSV file with a header row, leaving the other
+/// fields as text.
+fn read_csv_column(path: &Path, table: &Table, column_name: &str) -> Result<Vec<Value>, DbError> {
+    let col = table
+        .get_column(column_name)
+        .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table.name, column_name)))?;
+    let contents = fs::read_to_string(path)?;
+    let mut lines = contents.lines().enumerate().filter(|(_, l)| !l.trim().is_empty());
+    let header = lines.next().map(|(_, l)| split_csv_line(l, ',')).unwrap_or_default();
+    let Some(position) = header.iter().position(|h| h.trim() == column_name) else {
+        return Err(DbError::InvalidData(format!("{} has no column {}", path.display(), column_name)));
+    };
+    let mut values = Vec::new();
+    for (line_no, line) in lines {
+        let fields = split_csv_line(line, ',');
+        let field = fields.get(position).map_or("", String::as_str);
+        // An empty field is NULL, except in string columns where it is "".
+        let value = if col.nullable && col.data_type != DataType::String && field.trim().is_empty() {
+            Ok(Value::Null)
+        } else {
+            parse_csv_value(field, &col.data_type)
+        }
+        .and_then(|v| col.check_value(&v).map(|_| v))
+        .map_err(|e| DbError::InvalidData(format!("{} line {}: {}", path.display(), line_no + 1, e)))?;
+        values.push(value);
+    }
+    Ok(values)
+}
+
+/// Converts a Parquet value to the column's type. Integers are narrowed to
+/// INT when they fit and timestamps are truncated to seconds.
+fn from_field(field: Field, data_type: &DataType) -> Result<Value, DbError> {
+    let value = match field {
+        Field::Null => return Ok(Value::Null),
+        Field::Bool(b) => Value::Boolean(b),
+        Field::Byte(i) => Value::Int32(i.into()),
+        Field::Short(i) => Value::Int32(i.into()),
+        Field::Int(i) => Value::Int32(i),
+        Field::UByte(i) => Value::Int32(i.into()),
+        Field::UShort(i) => Value::Int32(i.into()),
+        Field::UInt(i) => Value::Int64(i.into()),
+        Field::Long(i) => Value::Int64(i),
+        Field::Float(f) => Value::Float32(OrderedFloat(f)),
+        Field::Double(f) => Value::Float32(OrderedFloat(f as f32)),
+        Field::Str(s) => Value::String(s),
+        Field::Date(days) => Value::Date(days),
+        Field::TimestampMillis(ms) => Value::Timestamp(ms.div_euclid(1_000)),
+        Field::TimestampMicros(us) => Value::Timestamp(us.div_euclid(1_000_000)),
+        other => return Err(DbError::InvalidData(format!("Unsupported Parquet value: {}", other))),
+    };
+    match (value, data_type) {
+        (Value::Int64(i), DataType::Int32) => i32::try_from(i).map(Value::Int32).map_err(|_| DbError::TypeMismatch),
+        (value, _) => value.coerce(data_type),
+    }
+}
+
+/// A row group's statistics for one column as a zone map, or `None` when
+/// the writer left out its bounds or null count.
+fn row_group_block(stats: &Statistics, descr: &ColumnDescPtr, col: &Column, row_count: usize) -> Option<BlockInfo> {
+    let data_type = &col.data_type;
+    let convert = |min: Field, max: Field| Some((from_field(min, data_type).ok()?, from_field(max, data_type).ok()?));
+    let (min, max) = match stats {
+        Statistics::Boolean(s) => {
+            convert(Field::convert_bool(descr, *s.min_opt()?), Field::convert_bool(descr, *s.max_opt()?))
+        }
+        Statistics::Int32(s) => {
+            convert(Field::convert_int32(descr, *s.min_opt()?), Field::convert_int32(descr, *s.max_opt()?))
+        }
+        Statistics::Int64(s) => {
+            convert(Field::convert_int64(descr, *s.min_opt()?), Field::convert_int64(descr, *s.max_opt()?))
+        }
+        Statistics::Float(s) => {
+            convert(Field::convert_float(descr, *s.min_opt()?), Field::convert_float(descr, *s.max_opt()?))
+        }
+        Statistics::Double(s) => {
+            convert(Field::convert_double(descr, *s.min_opt()?), Field::convert_double(descr, *s.max_opt()?))
+        }
+        Statistics::ByteArray(s) => convert(
+            Field::convert_byte_array(descr, s.min_opt()?.clone()).ok()?,
+            Field::convert_byte_array(descr, s.max_opt()?.clone()).ok()?,
+        ),
+        _ => None,
+    }?;
+    let null_count = stats.null_count_opt()?;
+    Some(BlockInfo {
+        min,
+        max,
+        offset: 0,
+        row_count,
+        compression: CompressionType::None,
+        serialized_size: None,
+        segment_path: None,
+        // Only the count is known; zone map checks never look at the positions.
+        nulls: (0..null_count as u32).collect(),
+        checksum: None,
+    })
+}
+
+/// The leaf column `name` of a flat Parquet schema.
+fn parquet_column(reader: &SerializedFileReader<File>, name: &str) -> Option<(usize, ColumnDescPtr)> {
+    let schema = reader.metadata().file_metadata().schema_descr();
+    (0..schema.num_columns())
+        .map(|i| (i, schema.column(i)))
+        .find(|(_, descr)| descr.path().parts().len() == 1 && descr.name() == name)
+}
+
+fn open_parquet(path: &Path) -> Result<SerializedFileReader<File>, DbError> {
+    SerializedFileReader::new(File::open(path)?).map_err(|e| parquet_error(path, e))
+}
+
+/// Row groups of a Parquet file whose statistics leave room for rows that
+/// match `condition`, decided the way zone maps prune stored blocks.
+pub fn row_group_mask(path: &Path, table: &Table, condition: Option<&Condition>) -> Result<Vec<bool>, DbError> {
+    let reader = open_parquet(path)?;
+    let row_groups = reader.metadata().row_groups();
+    let Some(condition) = condition else {
+        return Ok(vec![true; row_groups.len()]);
+    };
+    // Statistics hold binary bounds, which don't bound other collations.
+    let columns = table
+        .columns
+        .iter()
+        .filter(|c| c.collation == Collation::Binary)
+        .filter_map(|col| parquet_column(&reader, &col.name).map(|(index, descr)| (col, index, descr)))
+        .collect::<Vec<_>>();
+    let keep = row_groups
+        .iter()
+        .map(|group| {
+            let blocks = columns
+                .iter()
+                .filter_map(|(col, index, descr)| {
+                    let stats = group.column(*index).statistics()?;
+                    Some((col.name.as_str(), row_group_block(stats, descr, col, group.num_rows() as usize)?))
+                })
+                .collect::<HashMap<_, _>>();
+            evaluate_condition_blocks(condition, &|col| blocks.get(col))
+        })
+        .collect();
+    Ok(keep)
+}
+
+/// Reads one column out of the row groups `keep` selects, decoding no other
+/// column.
+fn read_parquet_column(path: &Path, col: &Column, keep: &[bool]) -> Result<Vec<Value>, DbError> {
+    let reader = open_parquet(path)?;
+    let Some((index, descr)) = parquet_column(&reader, &col.name) else {
+        return Err(DbError::InvalidData(format!("{} has no column {}", path.display(), col.name)));
+    };
+    if descr.physical_type() == PhysicalType::FIXED_LEN_BYTE_ARRAY || descr.physical_type() == PhysicalType::INT96 {
+        return Err(DbError::InvalidData(format!("{}: column {} has an unsupported type", path.display(), col.name)));
+    }
+    let schema = reader.metadata().file_metadata().schema_descr();
+    let field = schema.root_schema().get_fields()[schema.get_column_root_idx(index)].clone();
+    let projection = Type::group_type_builder("schema")
+        .with_fields(vec![Arc::clone(&field)])
+        .build()
+        .map_err(|e| parquet_error(path, e))?;
+
+    let mut values = Vec::new();
+    for (i, _) in keep.iter().enumerate().filter(|(_, k)| **k) {
+        let group = reader.get_row_group(i).map_err(|e| parquet_error(path, e))?;
+        for row in group.get_row_iter(Some(projection.clone())).map_err(|e| parquet_error(path, e))? {
+            let row = row.map_err(|e| parquet_error(path, e))?;
+            let field = row.into_columns().into_iter().next().map_or(Field::Null, |(_, f)| f);
+            let value = from_field(field, &col.data_type)
+                .and_then(|v| col.check_value(&v).map(|_| v))
+                .map_err(|e| DbError::InvalidData(format!("{} row group {}: {}", path.display(), i, e)))?;
+            values.push(value);
+        }
+    }
+    Ok(values)
+}
+
+impl StorageManager {
+    /// The definition of `table_name` if it is an external table.
+    pub(crate) fn external_table(&self, table_name: &str) -> Option<Table> {
+        self.schema.get_table(table_name).filter(|t| t.location.is_some()).cloned()
+    }
+
+    /// Rejects changes to the rows or layout of an external table.
+    pub(crate) fn ensure_stored(&self, table_name: &str) -> Result<(), DbError> {
+        if self.external_table(table_name).is_some() {
+            return Err(DbError::InvalidData(format!("Table {} is external and read-only", table_name)));
+        }
+        Ok(())
+    }
+
+    /// Reads one column of an external table by scanning its files, only
+    /// parsing that column. A CSV file counts as one block and a Parquet row
+    /// group as one; row groups whose statistics rule out `condition` are
+    /// skipped, so every column read with the same condition stays aligned.
+    pub(crate) fn read_external_column(
+        &self,
+        table: &Table,
+        column_name: &str,
+        condition: Option<&Condition>,
+        sample: Option<f64>,
+    ) -> Result<Vec<Value>, DbError> {
+        let col = table
+            .get_column(column_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table.name, column_name)))?;
+        let location = table.location.as_deref().unwrap_or_default();
+        let sampled = |i: usize| sample.is_none_or(|percent| block_sampled(i, percent));
+        let mut block = 0;
+        let mut values = Vec::new();
+        for path in external_files(location)? {
+            if is_parquet(&path) {
+                let mut keep = row_group_mask(&path, table, condition)?;
+                for k in &mut keep {
+                    *k &= sampled(block);
+                    block += 1;
+                }
+                values.extend(read_parquet_column(&path, col, &keep)?);
+            } else {
+                if sampled(block) {
+                    values.extend(read_csv_column(&path, table, column_name)?);
+                }
+                block += 1;
+            }
+        }
+        Ok(values)
+    }
+}
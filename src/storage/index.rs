@@ -1,21 +1,38 @@
-use crate::types::{DataType, DbError, Value};
+use crate::storage::atomic::write_atomic;
+use crate::storage::bloom::BloomFilter;
+use crate::types::{Collation, DataType, DbError, Value};
 use std::collections::BTreeMap;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use std::ops::Bound;
+use std::fs::File;
+use std::io::Read;
 use bincode;
 
+/// An in-memory map from values to the offsets of the blocks holding them.
+/// Changes are kept in memory until `save`, which the storage manager calls
+/// once per commit and checkpoint rather than once per flushed segment.
+#[derive(Clone)]
 pub struct Index {
     path: String,
     data_type: DataType,
+    /// Keys are stored as `collation.key(value)`.
+    collation: Collation,
     map: BTreeMap<Value, Vec<u64>>,
+    /// The map has changed since it was last written to `path`.
+    dirty: bool,
+    /// Every key of the map, plus the keys of rows still buffered for it.
+    /// Not saved; rebuilt from the map on load.
+    filter: BloomFilter,
 }
 
 impl Index {
-    pub fn new(path: &str, data_type: DataType) -> Result<Self, DbError> {
+    pub fn new(path: &str, data_type: DataType, collation: Collation) -> Result<Self, DbError> {
         let mut index = Index {
             path: path.to_string(),
             data_type,
+            collation,
             map: BTreeMap::new(),
+            dirty: false,
+            filter: BloomFilter::new(),
         };
         if std::path::Path::new(path).exists() {
             index.load()?;
@@ -25,61 +42,117 @@ impl Index {
 
     pub fn append(&mut self, values: &[Value], offset: u64) -> Result<(), DbError> {
         for value in values {
-            if value.data_type() != self.data_type {
+            if !value.fits(&self.data_type) {
                 return Err(DbError::TypeMismatch);
             }
-            self.map
-                .entry(value.clone())
-                .or_insert_with(Vec::new)
-                .push(offset);
+            // NULL never equals anything, so it is not indexed.
+            if value.is_null() {
+                continue;
+            }
+            let key = self.collation.key(value);
+            self.filter.insert(&key);
+            self.map.entry(key).or_default().push(offset);
         }
-        self.save()?;
+        self.dirty = true;
         Ok(())
     }
 
+    /// False if no indexed or noted row can hold `value`; true may be wrong.
+    pub fn may_contain(&self, value: &Value) -> bool {
+        self.filter.contains(&self.collation.key(value))
+    }
+
+    /// Records a value of a row buffered ahead of its block, so that
+    /// `may_contain` covers it before `append` does.
+    pub fn note(&mut self, value: &Value) {
+        if !value.is_null() {
+            self.filter.insert(&self.collation.key(value));
+        }
+    }
+
     pub fn lookup(&self, value: &Value) -> Result<Vec<u64>, DbError> {
-        if value.data_type() != self.data_type {
+        if !value.fits(&self.data_type) {
             return Err(DbError::TypeMismatch);
         }
-        Ok(self.map.get(value).cloned().unwrap_or_default())
+        Ok(self.map.get(&self.collation.key(value)).cloned().unwrap_or_default())
     }
 
     pub fn range_lookup(&self, min: &Value, max: &Value) -> Result<Vec<u64>, DbError> {
-        if min.data_type() != self.data_type || max.data_type() != self.data_type {
+        if min.data_type() != Some(self.data_type.clone()) || max.data_type() != Some(self.data_type.clone()) {
             return Err(DbError::TypeMismatch);
         }
         let mut offsets = Vec::new();
+        let (min, max) = (self.collation.key(min), self.collation.key(max));
+        if min > max {
+            return Ok(offsets);
+        }
         for (_value, offs) in self.map.range(min..=max) {
             offsets.extend(offs);
         }
         Ok(offsets)
     }
 
+    /// Offsets of the keys between `lower` and `upper`.
+    pub fn scan(&self, lower: Bound<&Value>, upper: Bound<&Value>) -> Result<Vec<u64>, DbError> {
+        let key = |bound: Bound<&Value>| -> Result<Bound<Value>, DbError> {
+            match bound {
+                Bound::Included(v) | Bound::Excluded(v) if v.data_type() != Some(self.data_type.clone()) => {
+                    Err(DbError::TypeMismatch)
+                }
+                Bound::Included(v) => Ok(Bound::Included(self.collation.key(v))),
+                Bound::Excluded(v) => Ok(Bound::Excluded(self.collation.key(v))),
+                Bound::Unbounded => Ok(Bound::Unbounded),
+            }
+        };
+        let (lower, upper) = (key(lower)?, key(upper)?);
+        let mut offsets = Vec::new();
+        // BTreeMap::range panics on an inverted or empty excluded range.
+        if let (Bound::Included(lo) | Bound::Excluded(lo), Bound::Included(hi) | Bound::Excluded(hi)) = (&lower, &upper)
+            && (lo > hi || (lo == hi && !matches!((&lower, &upper), (Bound::Included(_), Bound::Included(_)))))
+        {
+            return Ok(offsets);
+        }
+        for (_value, offs) in self.map.range((lower, upper)) {
+            offsets.extend(offs);
+        }
+        Ok(offsets)
+    }
+
     pub fn clear(&mut self) -> Result<(), DbError> {
-        // Clear the in-memory map
         self.map.clear();
-
-        // Truncate the index file
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.path)?;
-        file.flush()?;
+        self.filter.clear();
+        self.dirty = true;
         Ok(())
     }
 
-    fn save(&self) -> Result<(), DbError> {
-        let mut file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .truncate(true)
-            .open(&self.path)?;
+    /// Distinct indexed values.
+    pub fn key_count(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Value and block pairs, one per indexed non-NULL row.
+    pub fn entry_count(&self) -> usize {
+        self.map.values().map(|offsets| offsets.len()).sum()
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Writes the map to the index file if it changed; returns whether it did.
+    pub fn save(&mut self) -> Result<bool, DbError> {
+        if !self.dirty {
+            return Ok(false);
+        }
         let serialized = bincode::serialize(&self.map)
             .map_err(|e| DbError::SerializationError(e.to_string()))?;
-        file.write_all(&serialized)?;
-        file.flush()?;
-        Ok(())
+        write_atomic(&self.path, &serialized)?;
+        self.dirty = false;
+        Ok(true)
     }
 
     fn load(&mut self) -> Result<(), DbError> {
@@ -90,6 +163,10 @@ impl Index {
             self.map = bincode::deserialize(&contents)
                 .map_err(|e| DbError::SerializationError(e.to_string()))?;
         }
+        self.filter.clear();
+        for key in self.map.keys() {
+            self.filter.insert(key);
+        }
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,112 @@
+use crate::types::DbError;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// The distinct strings of a `COMPRESSION INTERNED` column, numbered in the
+/// order they were first written. Blocks store these ids instead of the
+/// strings. The file is only ever appended to, so an id stays valid for
+/// every block written before it, even ones a snapshot still reads.
+#[derive(Clone, Debug, Default)]
+pub struct InternTable {
+    path: String,
+    strings: Vec<String>,
+    ids: HashMap<String, u32>,
+    /// Strings already in the file, and the bytes they take up there.
+    saved: usize,
+    saved_bytes: u64,
+}
+
+impl InternTable {
+    /// Loads the table at `path`, empty if there is no file yet. A string
+    /// cut short by a crash was never referenced by a block and is dropped.
+    pub fn open(path: &str) -> Result<Self, DbError> {
+        let mut table = InternTable {
+            path: path.to_string(),
+            ..InternTable::default()
+        };
+        if !Path::new(path).exists() {
+            return Ok(table);
+        }
+        let data = fs::read(path)?;
+        let mut cursor = Cursor::new(&data[..]);
+        while let Ok(len) = cursor.read_u32::<LittleEndian>() {
+            let mut bytes = vec![0; len as usize];
+            if cursor.read_exact(&mut bytes).is_err() {
+                break;
+            }
+            let s = String::from_utf8(bytes).map_err(|e| DbError::SerializationError(e.to_string()))?;
+            table.ids.insert(s.clone(), table.strings.len() as u32);
+            table.strings.push(s);
+            table.saved_bytes = cursor.position();
+        }
+        if table.saved_bytes < data.len() as u64 {
+            log::warn!("Ignoring {} trailing bytes of {}", data.len() as u64 - table.saved_bytes, path);
+        }
+        table.saved = table.strings.len();
+        Ok(table)
+    }
+
+    pub(crate) fn set_path(&mut self, path: String) {
+        self.path = path;
+    }
+
+    pub fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.strings.is_empty()
+    }
+
+    /// Every interned string, indexed by id.
+    pub fn strings(&self) -> &[String] {
+        &self.strings
+    }
+
+    pub fn id(&self, s: &str) -> Option<u32> {
+        self.ids.get(s).copied()
+    }
+
+    pub fn get(&self, id: u32) -> Result<&str, DbError> {
+        self.strings
+            .get(id as usize)
+            .map(String::as_str)
+            .ok_or_else(|| DbError::SerializationError(format!("Invalid intern id {} in {}", id, self.path)))
+    }
+
+    /// The id of `s`, adding it if it is new. New strings reach the file on `save`.
+    pub fn intern(&mut self, s: &str) -> u32 {
+        if let Some(id) = self.id(s) {
+            return id;
+        }
+        let id = self.strings.len() as u32;
+        self.strings.push(s.to_string());
+        self.ids.insert(s.to_string(), id);
+        id
+    }
+
+    /// Appends the strings added since the last save. Called before a block
+    /// using them is written, so no block refers to an id the file lacks.
+    pub fn save(&mut self) -> Result<(), DbError> {
+        if self.saved == self.strings.len() {
+            return Ok(());
+        }
+        let mut buffer = Vec::new();
+        for s in &self.strings[self.saved..] {
+            buffer.write_u32::<LittleEndian>(s.len() as u32)?;
+            buffer.extend_from_slice(s.as_bytes());
+        }
+        let mut file = OpenOptions::new().write(true).create(true).truncate(false).open(&self.path)?;
+        // Overwrites whatever a torn earlier append left behind.
+        file.set_len(self.saved_bytes)?;
+        file.seek(SeekFrom::Start(self.saved_bytes))?;
+        file.write_all(&buffer)?;
+        file.flush()?;
+        self.saved = self.strings.len();
+        self.saved_bytes += buffer.len() as u64;
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+use crate::storage::atomic::write_atomic;
+use crate::storage::trash::now_seconds;
+use crate::storage::StorageManager;
+use crate::types::DbError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// A statement `CREATE JOB` scheduled to run every `every` seconds.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Job {
+    pub name: String,
+    pub every: u64,
+    /// The statement as written; it is parsed again for every run.
+    pub statement: String,
+    /// Seconds since the epoch at which the job is due next.
+    pub next_run: u64,
+    pub last_run: Option<u64>,
+    /// `ok`, or the error the last run failed with.
+    pub last_status: Option<String>,
+}
+
+fn jobs_path(data_dir: &str) -> String {
+    format!("{}/jobs.json", data_dir)
+}
+
+impl StorageManager {
+    /// Every scheduled job, in creation order.
+    pub fn jobs(&self) -> Result<Vec<Job>, DbError> {
+        let path = jobs_path(self.data_dir());
+        if !Path::new(&path).exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    fn save_jobs(&self, jobs: &[Job]) -> Result<(), DbError> {
+        write_atomic(jobs_path(self.data_dir()), serde_json::to_string_pretty(jobs)?.as_bytes())
+    }
+
+    /// `CREATE JOB`: the job first runs one interval from now.
+    pub fn create_job(&mut self, name: &str, every: u64, statement: &str) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(DbError::QueryError(format!("Invalid job name: {}", name)));
+        }
+        let mut jobs = self.jobs()?;
+        if jobs.iter().any(|j| j.name == name) {
+            return Err(DbError::InvalidData(format!("Job {} already exists", name)));
+        }
+        jobs.push(Job {
+            name: name.to_string(),
+            every,
+            statement: statement.to_string(),
+            next_run: now_seconds() + every,
+            last_run: None,
+            last_status: None,
+        });
+        self.save_jobs(&jobs)
+    }
+
+    pub fn drop_job(&mut self, name: &str) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        let mut jobs = self.jobs()?;
+        let before = jobs.len();
+        jobs.retain(|j| j.name != name);
+        if jobs.len() == before {
+            return Err(DbError::InvalidData(format!("Job {} not found", name)));
+        }
+        self.save_jobs(&jobs)
+    }
+
+    /// Records a run that started at `started` and schedules the next one.
+    /// A job dropped while it ran is left dropped.
+    pub fn record_job_run(&mut self, name: &str, started: u64, status: &str) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        let mut jobs = self.jobs()?;
+        let Some(job) = jobs.iter_mut().find(|j| j.name == name) else {
+            return Ok(());
+        };
+        job.last_run = Some(started);
+        job.last_status = Some(status.to_string());
+        job.next_run = started + job.every;
+        self.save_jobs(&jobs)
+    }
+}
@@ -0,0 +1,167 @@
+use crate::schema::metadata::BlockMetadata;
+use crate::schema::{Column, Schema, Table};
+use crate::storage::settings::DataLayout;
+use crate::types::DbError;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// Directories under the data directory that hold the tables' files.
+pub const TABLE_DIRS: [&str; 3] = ["columns", "metadata", "indexes"];
+
+/// Files at the top of the data directory that belong to the database as a
+/// whole. Any of them but the schema may be missing.
+pub const DATABASE_FILES: [&str; 4] = ["schema.json", "config.json", "jobs.json", "migrations.json"];
+
+/// Files a table may own, relative to the data directory. An external table
+/// has no column files of its own.
+pub fn table_files(table: &Table, layout: DataLayout) -> Vec<String> {
+    let mut files = vec![
+        format!("metadata/{}.table.json", table.name),
+        format!("metadata/{}.stats.json", table.name),
+        format!("metadata/{}.manifest.json", table.name),
+    ];
+    if table.location.is_some() {
+        return files;
+    }
+    for col in &table.columns {
+        files.push(layout.column_file(&table.name, col));
+        files.push(layout.metadata_file(&table.name, col));
+        files.push(layout.intern_file(&table.name, col));
+        files.push(layout.index_file(&table.name, col));
+    }
+    files
+}
+
+/// The layout the files under `data_dir` were written in, or `None` for a
+/// directory without column files yet. Data files directly under `columns/`
+/// mean a flat layout, even if a migration was cut short.
+pub fn detect(data_dir: &str) -> Result<Option<DataLayout>, DbError> {
+    let columns = Path::new(data_dir).join("columns");
+    if !columns.exists() {
+        return Ok(None);
+    }
+    let mut layout = None;
+    for entry in fs::read_dir(&columns)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            layout = Some(DataLayout::PerTable);
+        } else if entry.path().extension().is_some_and(|e| e == "dat") {
+            return Ok(Some(DataLayout::Flat));
+        }
+    }
+    Ok(layout)
+}
+
+fn move_or_copy(from: &Path, to: &Path, shared: bool) -> Result<(), DbError> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if shared {
+        fs::copy(from, to)?;
+    } else {
+        fs::rename(from, to)?;
+    }
+    Ok(())
+}
+
+/// Moves a column's files from where `from` keeps them to where `to` does,
+/// copying them instead if `shared`. Each data file moves after its
+/// metadata, so rerunning after a crash picks up where it stopped.
+fn move_column(
+    data_dir: &str,
+    table: &str,
+    (from, from_layout): (&Column, DataLayout),
+    (to, to_layout): (&Column, DataLayout),
+    shared: bool,
+) -> Result<(), DbError> {
+    let root = Path::new(data_dir);
+    let old_meta = root.join(from_layout.metadata_file(table, from));
+    let meta = root.join(to_layout.metadata_file(table, to));
+    let data = root.join(to_layout.column_file(table, to));
+    if old_meta.exists() && !meta.exists() {
+        move_or_copy(&old_meta, &meta, shared)?;
+        // Blocks record the file they were written to.
+        let mut metadata = BlockMetadata::load(&to.name, to.data_type.clone(), data_dir, &meta.to_string_lossy())?;
+        for block in &mut metadata.blocks {
+            block.segment_path = Some(data.to_string_lossy().into_owned());
+        }
+        metadata.save()?;
+    }
+    let files = [
+        (from_layout.column_file(table, from), to_layout.column_file(table, to)),
+        (from_layout.intern_file(table, from), to_layout.intern_file(table, to)),
+    ];
+    for (old, new) in files {
+        if root.join(&old).exists() && !root.join(&new).exists() {
+            move_or_copy(&root.join(old), &root.join(new), shared)?;
+        }
+    }
+    // Indexes are per table in every layout, so only ever moved.
+    let old_index = root.join(from_layout.index_file(table, from));
+    let index = root.join(to_layout.index_file(table, to));
+    if old_index != index && old_index.exists() && !index.exists() {
+        move_or_copy(&old_index, &index, false)?;
+    }
+    Ok(())
+}
+
+/// Moves every column of a flat data directory to its table's directory.
+/// A file that several tables' columns shared is copied to each of them, as
+/// its blocks can no longer be told apart.
+pub fn migrate_flat(data_dir: &str, schema: &Schema) -> Result<(), DbError> {
+    let mut owners: HashMap<&str, usize> = HashMap::new();
+    for table in schema.tables().filter(|t| t.location.is_none()) {
+        for col in &table.columns {
+            *owners.entry(col.name.as_str()).or_default() += 1;
+        }
+    }
+    for table in schema.tables().filter(|t| t.location.is_none()) {
+        for col in &table.columns {
+            let shared = owners[col.name.as_str()] > 1;
+            if shared {
+                log::warn!("Column {} is shared by several tables; each gets a copy of its file", col.name);
+            }
+            move_column(data_dir, &table.name, (col, DataLayout::Flat), (col, DataLayout::PerTable), shared)?;
+        }
+    }
+    // Shared files were copied; the originals go once every table has its own.
+    let root = Path::new(data_dir);
+    for col in schema.tables().filter(|t| t.location.is_none()).flat_map(|t| &t.columns) {
+        let flat = [DataLayout::Flat.metadata_file("", col), DataLayout::Flat.column_file("", col)];
+        for file in flat.into_iter().chain([DataLayout::Flat.intern_file("", col)]) {
+            let path = root.join(file);
+            if path.exists() {
+                fs::remove_file(path)?;
+            }
+        }
+    }
+    log::info!("Migrated {} to per-table column files", data_dir);
+    Ok(())
+}
+
+/// Gives the columns of an older per-table directory ids and renames their
+/// files to match. The schema is saved last; until then a rerun assigns the
+/// same ids and finishes the moves.
+pub fn assign_column_ids(data_dir: &str, schema: &mut Schema) -> Result<(), DbError> {
+    let mut assigned = false;
+    for table in schema.tables.values_mut() {
+        if table.columns.iter().all(|c| c.id.is_some()) {
+            continue;
+        }
+        let named: Vec<Column> = table.columns.clone();
+        table.assign_column_ids();
+        assigned = true;
+        if table.location.is_some() {
+            continue;
+        }
+        for (old, col) in named.iter().zip(&table.columns).filter(|(old, _)| old.id.is_none()) {
+            move_column(data_dir, &table.name, (old, DataLayout::PerTable), (col, DataLayout::PerTable), false)?;
+        }
+    }
+    if assigned {
+        schema.save()?;
+        log::info!("Assigned column ids in {}", data_dir);
+    }
+    Ok(())
+}
@@ -0,0 +1,37 @@
+use crate::types::DbError;
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// Exclusive lock on a data directory, held for as long as the value lives.
+/// The OS drops the lock if the process dies, so a stale LOCK file is harmless.
+pub struct DirLock {
+    _file: File,
+}
+
+impl DirLock {
+    pub fn acquire(data_dir: &str) -> Result<Self, DbError> {
+        let path = format!("{}/LOCK", data_dir);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if file.try_lock_exclusive().is_err() {
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+            let pid = pid.trim();
+            return Err(DbError::TransactionError(format!(
+                "Database {} is locked by PID {}",
+                data_dir,
+                if pid.is_empty() { "unknown" } else { pid }
+            )));
+        }
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        write!(file, "{}", std::process::id())?;
+        file.sync_all()?;
+        Ok(DirLock { _file: file })
+    }
+}
@@ -0,0 +1,182 @@
+use crate::storage::atomic::write_atomic;
+use crate::storage::StorageManager;
+use crate::types::DbError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// FNV-1a over a block's encoded bytes: cheap, and stable across builds and
+/// platforms, unlike the standard library's hasher.
+pub fn checksum(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// One flushed block of one column.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Segment {
+    pub column: String,
+    pub offset: u64,
+    pub size: usize,
+    pub rows: usize,
+    pub checksum: u64,
+}
+
+impl Segment {
+    /// The same block in the same place, whatever its bytes now hold.
+    fn same_block(&self, other: &Segment) -> bool {
+        self.column == other.column && self.offset == other.offset && self.size == other.size && self.rows == other.rows
+    }
+}
+
+/// What a table's files held after the last commit that changed them: its
+/// blocks in column and file order, their row count and a checksum over
+/// them all. Rows still buffered in memory are in neither.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Manifest {
+    pub table: String,
+    /// Goes up by one every time the manifest is rewritten.
+    pub version: u64,
+    pub row_count: u64,
+    pub segments: Vec<Segment>,
+    pub checksum: u64,
+}
+
+impl Manifest {
+    fn path(data_dir: &str, table: &str) -> String {
+        format!("{}/metadata/{}.manifest.json", data_dir, table)
+    }
+
+    pub fn load(data_dir: &str, table: &str) -> Result<Option<Self>, DbError> {
+        let path = Self::path(data_dir, table);
+        if !Path::new(&path).exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_str(&fs::read_to_string(&path)?)?))
+    }
+
+    fn save(&self, data_dir: &str) -> Result<(), DbError> {
+        fs::create_dir_all(format!("{}/metadata", data_dir))?;
+        write_atomic(Self::path(data_dir, &self.table), serde_json::to_string_pretty(self)?.as_bytes())
+    }
+
+    /// Same blocks and rows, whatever the version.
+    fn same_contents(&self, other: &Manifest) -> bool {
+        self.row_count == other.row_count && self.segments == other.segments
+    }
+}
+
+impl StorageManager {
+    /// The manifest of `table` as its column metadata stands now. Blocks
+    /// written before checksums were kept are read once to fill theirs in.
+    pub fn current_manifest(&mut self, table: &str) -> Result<Manifest, DbError> {
+        let table_def = self
+            .schema
+            .get_table(table)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table)))?
+            .clone();
+        let read_only = self.is_read_only();
+        let mut segments = Vec::new();
+        let mut row_count = None;
+        for col in &table_def.columns {
+            let Some(col_store) = self.columns.get_mut(table).and_then(|cols| cols.get_mut(&col.name)) else {
+                continue;
+            };
+            let mut backfilled = false;
+            for i in 0..col_store.metadata.blocks.len() {
+                if col_store.metadata.blocks[i].checksum.is_none() {
+                    let data = col_store.read_block_bytes(&col_store.metadata.blocks[i])?;
+                    col_store.metadata.blocks[i].checksum = Some(checksum(&data));
+                    backfilled = true;
+                }
+                let block = &col_store.metadata.blocks[i];
+                segments.push(Segment {
+                    column: col.name.clone(),
+                    offset: block.offset,
+                    size: block.serialized_size.unwrap_or(0),
+                    rows: block.row_count,
+                    checksum: block.checksum.unwrap_or_default(),
+                });
+            }
+            if backfilled && !read_only {
+                col_store.metadata.save()?;
+            }
+            row_count.get_or_insert(col_store.metadata.blocks.iter().map(|b| b.row_count as u64).sum());
+        }
+        let combined = segments.iter().flat_map(|s| s.checksum.to_le_bytes()).collect::<Vec<u8>>();
+        Ok(Manifest {
+            table: table.to_string(),
+            version: 0,
+            row_count: row_count.unwrap_or(0),
+            checksum: checksum(&combined),
+            segments,
+        })
+    }
+
+    /// Rewrites the manifest of every stored table whose blocks changed
+    /// since its last one. Runs at the end of each commit; returns how many
+    /// manifests were written, none on a read-only database.
+    pub fn save_manifests(&mut self) -> Result<usize, DbError> {
+        if self.is_read_only() {
+            return Ok(0);
+        }
+        let mut tables: Vec<String> = self
+            .schema
+            .tables()
+            .filter(|t| t.location.is_none() && self.columns.contains_key(&t.name))
+            .map(|t| t.name.clone())
+            .collect();
+        tables.sort();
+        let mut saved = 0;
+        for table in tables {
+            let mut manifest = self.current_manifest(&table)?;
+            let previous = Manifest::load(&self.data_dir, &table)?;
+            if previous.as_ref().is_some_and(|p| p.same_contents(&manifest)) {
+                continue;
+            }
+            manifest.version = previous.map_or(1, |p| p.version + 1);
+            manifest.save(&self.data_dir)?;
+            saved += 1;
+        }
+        Ok(saved)
+    }
+
+    /// Compares `table`'s files with its manifest and returns what differs:
+    /// blocks added, dropped or moved since, a different row count, or
+    /// bytes that no longer match their checksum. Empty when all agree.
+    pub fn verify_manifest(&mut self, table: &str) -> Result<Vec<String>, DbError> {
+        let Some(manifest) = Manifest::load(&self.data_dir, table)? else {
+            return Ok(vec![format!("Table {} has no manifest", table)]);
+        };
+        let current = self.current_manifest(table)?;
+        let mut problems = Vec::new();
+        if current.row_count != manifest.row_count {
+            problems.push(format!("{} rows, manifest has {}", current.row_count, manifest.row_count));
+        }
+        for segment in &manifest.segments {
+            if !current.segments.iter().any(|s| s.same_block(segment)) {
+                problems.push(format!("Block of {} at offset {} is missing", segment.column, segment.offset));
+            }
+        }
+        for segment in &current.segments {
+            if !manifest.segments.iter().any(|m| m.same_block(segment)) {
+                let (column, offset) = (&segment.column, segment.offset);
+                problems.push(format!("Block of {} at offset {} is not in the manifest", column, offset));
+            }
+        }
+        let cols = self.columns.get(table);
+        for segment in &manifest.segments {
+            let Some(col_store) = cols.and_then(|cols| cols.get(&segment.column)) else {
+                continue;
+            };
+            let Some(block) = col_store.metadata.blocks.iter().find(|b| b.offset == segment.offset) else {
+                continue;
+            };
+            if checksum(&col_store.read_block_bytes(block)?) != segment.checksum {
+                problems.push(format!("Block of {} at offset {} fails its checksum", segment.column, segment.offset));
+            }
+        }
+        Ok(problems)
+    }
+}
@@ -0,0 +1,46 @@
+use crate::storage::atomic::write_atomic;
+use crate::storage::trash::now_seconds;
+use crate::storage::StorageManager;
+use crate::types::DbError;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// A migration `Migrator` has applied to this data directory.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AppliedMigration {
+    pub version: u64,
+    pub name: String,
+    /// Seconds since the epoch.
+    pub applied_at: u64,
+}
+
+fn migrations_path(data_dir: &str) -> String {
+    format!("{}/migrations.json", data_dir)
+}
+
+impl StorageManager {
+    /// Applied migrations, in version order.
+    pub fn applied_migrations(&self) -> Result<Vec<AppliedMigration>, DbError> {
+        let path = migrations_path(self.data_dir());
+        if !Path::new(&path).exists() {
+            return Ok(Vec::new());
+        }
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn record_migration(&mut self, version: u64, name: &str) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        let mut applied = self.applied_migrations()?;
+        if applied.iter().any(|m| m.version == version) {
+            return Err(DbError::InvalidData(format!("Migration {} already applied", version)));
+        }
+        applied.push(AppliedMigration {
+            version,
+            name: name.to_string(),
+            applied_at: now_seconds(),
+        });
+        applied.sort_by_key(|m| m.version);
+        write_atomic(migrations_path(self.data_dir()), serde_json::to_string_pretty(&applied)?.as_bytes())
+    }
+}
@@ -1,20 +1,56 @@
-use crate::schema::{Schema, Table};
+use crate::schema::metadata::TableMetadata;
+use crate::schema::{Column, Schema, Table};
 use crate::storage::{
-    buffer::BufferManager,
-    column::ColumnStore,
+    buffer::{decoded_size, BufferManager},
+    compression::Primitive,
+    column::{block_sampled, ColumnStore},
     index::Index,
+    lock::DirLock,
+    settings::{DataLayout, SettingSource, Settings, StorageOptions},
 };
-use crate::types::{CompressionType, DbError, Value};
+use crate::types::{Collation, DbError, Value};
+use crate::query::batch::{with_nulls, ColumnVector, DictionaryBuilder};
+use crate::query::evaluator::evaluate_condition_block;
+use crate::query::governor::Governor;
+use crate::query::log::QueryLog;
 use crate::{Condition, DataType};
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::Path;
+use std::ops::Bound;
+use std::sync::Arc;
 
+pub mod alter;
+pub mod atomic;
+pub mod backup;
 pub mod block;
+pub mod bloom;
 pub mod buffer;
+pub mod bulk;
+pub mod bundle;
+pub mod cleanup;
 pub mod column;
+pub mod compact;
 pub mod compression;
+pub mod csv;
+pub mod external;
 pub mod index;
+pub mod intern;
+pub mod jobs;
+pub mod layout;
+pub mod lock;
+pub mod manifest;
+pub mod migrations;
+pub mod preload;
+pub mod salvage;
+pub mod settings;
+pub mod snapshot;
+pub mod stats;
+pub mod testing;
+pub mod trash;
+pub mod update;
+pub mod workspace;
+pub mod writer;
 
 // Standalone function to flush pending rows
 fn do_flush_pending_rows(
@@ -32,11 +68,7 @@ fn do_flush_pending_rows(
         })?;
         let values = table_pending.get(col_name).cloned().unwrap_or_default();
         if !values.is_empty() {
-            let compression = match col.data_type {
-                DataType::String => CompressionType::Dictionary,
-                _ => CompressionType::Rle,
-            };
-            let offset = col_store.append(&values, compression)?;
+            let offset = col_store.append(&values)?;
             if let Some(index) = table_indexes.get_mut(col_name) {
                 index.append(&values, offset)?;
             }
@@ -45,6 +77,29 @@ fn do_flush_pending_rows(
     Ok(())
 }
 
+/// Rows `indices` of `column_values`, columns in table order.
+fn table_rows(columns: &[Column], column_values: &HashMap<String, Vec<Value>>, indices: &[usize]) -> Vec<Vec<Value>> {
+    indices
+        .iter()
+        .map(|&i| {
+            columns
+                .iter()
+                .map(|c| column_values[&c.name].get(i).cloned().unwrap_or(Value::Null))
+                .collect()
+        })
+        .collect()
+}
+
+/// What a scan under a condition reads, for `EXPLAIN`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BlockScan {
+    pub total_blocks: usize,
+    /// Blocks left once zone maps and indexes have pruned the rest.
+    pub read_blocks: usize,
+    /// Indexed columns of the condition whose indexes did the pruning.
+    pub indexes: Vec<String>,
+}
+
 pub struct StorageManager {
     data_dir: String,
     pub columns: HashMap<String, HashMap<String, ColumnStore>>,
@@ -52,44 +107,132 @@ pub struct StorageManager {
     pub buffer: BufferManager,
     schema: Schema,
     pending_rows: HashMap<String, HashMap<String, Vec<Value>>>,
-    max_rows_per_segment: usize,
+    /// Estimated bytes of each table's buffered rows, and how many rows
+    /// there were then; recounted once the rows changed any other way.
+    pending_bytes: HashMap<String, (usize, usize)>,
+    settings: Settings,
+    table_versions: HashMap<String, u64>,
+    layout: DataLayout,
+    lock: Option<DirLock>,
+    /// Shared with snapshots, whose reads count against the same caps.
+    governor: Arc<Governor>,
+    /// Shared with snapshots too.
+    query_log: Arc<QueryLog>,
+}
+
+/// Rows still buffered in memory would be lost with the process.
+impl Drop for StorageManager {
+    fn drop(&mut self) {
+        if self.is_read_only() {
+            return;
+        }
+        if let Err(e) = self.flush_all() {
+            log::error!("Failed to flush buffered rows of {}: {}", self.data_dir, e);
+        }
+    }
 }
 
 impl StorageManager {
-    pub fn new(data_dir: &str, schema: Schema) -> Result<Self, DbError> {
+    /// Opens the data directory for reading and writing, taking its LOCK file.
+    /// Fails if another process already has it open.
+    pub fn new(data_dir: &str, schema: Schema, options: &StorageOptions) -> Result<Self, DbError> {
         fs::create_dir_all(format!("{}/columns", data_dir))?;
         fs::create_dir_all(format!("{}/indexes", data_dir))?;
         fs::create_dir_all(format!("{}/metadata", data_dir))?;
+        let lock = DirLock::acquire(data_dir)?;
+        // A transaction cut short by a crash is rolled back before anything reads the files.
+        let schema = if workspace::recover(data_dir)? { Schema::load(data_dir)? } else { schema };
+        Self::open(data_dir, schema, Some(lock), options)
+    }
+
+    /// Opens the data directory without taking the lock. Every write is
+    /// rejected, so this is safe next to a process that holds the lock.
+    pub fn new_read_only(data_dir: &str, schema: Schema, options: &StorageOptions) -> Result<Self, DbError> {
+        Self::open(data_dir, schema, None, options)
+    }
+
+    fn open(
+        data_dir: &str,
+        mut schema: Schema,
+        lock: Option<DirLock>,
+        options: &StorageOptions,
+    ) -> Result<Self, DbError> {
+        // Row counts live in per-table metadata; schema.json only seeds older databases.
+        for table in schema.tables.values_mut() {
+            if let Some(meta) = TableMetadata::load(data_dir, &table.name)? {
+                table.row_count = meta.row_count;
+            }
+        }
+        // Existing files decide the layout; a flat directory opened for writing
+        // is first moved to per-table files.
+        let layout = match layout::detect(data_dir)? {
+            Some(DataLayout::Flat) if lock.is_some() && options.layout == DataLayout::PerTable => {
+                layout::migrate_flat(data_dir, &schema)?;
+                DataLayout::PerTable
+            }
+            Some(found) => found,
+            None => options.layout,
+        };
+        if lock.is_some() && layout == DataLayout::PerTable {
+            layout::assign_column_ids(data_dir, &mut schema)?;
+        }
         let mut columns = HashMap::new();
         let mut indexes = HashMap::new();
         for table in schema.tables() {
             let mut table_cols = HashMap::new();
             let mut table_indexes = HashMap::new();
-            for col in &table.columns {
+            for col in table.columns.iter().filter(|_| table.location.is_none()) {
                 table_cols.insert(
                     col.name.clone(),
-                    ColumnStore::new(col, data_dir)?,
+                    ColumnStore::new(col, &table.name, data_dir, layout)?,
                 );
-                if col.name == "ID"{
-                    let index_path = format!("{}/indexes/{}_{}.idx", data_dir, table.name, col.name);
+                if table.is_unique(&col.name) {
+                    let index_path = format!("{}/{}", data_dir, layout.index_file(&table.name, col));
                     table_indexes.insert(
                         col.name.clone(),
-                        Index::new(&index_path, col.data_type.clone())?,
+                        Index::new(&index_path, col.data_type.clone(), col.collation)?,
                     );
                 }
             }
             columns.insert(table.name.clone(), table_cols);
             indexes.insert(table.name.clone(), table_indexes);
         }
-        Ok(StorageManager {
+        let settings = Settings::load(data_dir, options)?;
+        let storage = StorageManager {
             data_dir: data_dir.to_string(),
             columns,
             indexes,
-            buffer: BufferManager::new(100_000_000),
+            buffer: BufferManager::new(settings.buffer_cache_bytes),
             schema,
             pending_rows: HashMap::new(),
-            max_rows_per_segment: 3, // Increased for batching
-        })
+            pending_bytes: HashMap::new(),
+            settings,
+            table_versions: HashMap::new(),
+            layout,
+            lock,
+            governor: Arc::default(),
+            query_log: Arc::default(),
+        };
+        // Only report at startup; CLEANUP removes them.
+        for orphan in storage.find_orphans()? {
+            log::warn!("Orphaned file in {}: {}", data_dir, orphan);
+        }
+        storage.purge_trash()?;
+        Ok(storage)
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.lock.is_none()
+    }
+
+    pub fn ensure_writable(&self) -> Result<(), DbError> {
+        if self.is_read_only() {
+            return Err(DbError::TransactionError(format!(
+                "Database {} is open read-only",
+                self.data_dir
+            )));
+        }
+        Ok(())
     }
 
     pub fn data_dir(&self) -> &str {
@@ -100,80 +243,235 @@ impl StorageManager {
         &self.schema
     }
 
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn governor(&self) -> Arc<Governor> {
+        Arc::clone(&self.governor)
+    }
+
+    pub fn query_log(&self) -> Arc<QueryLog> {
+        Arc::clone(&self.query_log)
+    }
+
+    pub fn layout(&self) -> DataLayout {
+        self.layout
+    }
+
+    /// The options this database would be reopened with to behave as it does now.
+    pub fn options(&self) -> StorageOptions {
+        StorageOptions {
+            segment_rows: self.settings.segment_rows,
+            buffer_cache_bytes: self.settings.buffer_cache_bytes,
+            layout: self.layout,
+        }
+    }
+
+    /// Overrides a setting for the rest of the session.
+    pub fn set_setting(&mut self, name: &str, value: &str) -> Result<(), DbError> {
+        self.settings.set(name, value, SettingSource::Session)?;
+        self.buffer.set_max_size(self.settings.buffer_cache_bytes);
+        Ok(())
+    }
+
+    /// Monotonic counter bumped on every change to a table's data or definition.
+    pub fn table_version(&self, table_name: &str) -> u64 {
+        self.table_versions.get(table_name).copied().unwrap_or(0)
+    }
+
+    pub(crate) fn bump_table_version(&mut self, table_name: &str) {
+        *self.table_versions.entry(table_name.to_string()).or_insert(0) += 1;
+    }
+
+    pub fn row_count(&self, table_name: &str) -> Option<u64> {
+        self.schema.get_table(table_name).map(|t| t.row_count)
+    }
+
+    fn set_row_count(&mut self, table_name: &str, row_count: u64) {
+        if let Some(table) = self.schema.tables.get_mut(table_name) {
+            table.row_count = row_count;
+        }
+    }
+
+    /// Persists the table's row count. Called once rows are durable (after a
+    /// flush, delete or bulk load) so a restart never counts unflushed rows.
+    fn save_row_count(&self, table_name: &str) -> Result<(), DbError> {
+        let pending = self
+            .pending_rows
+            .get(table_name)
+            .and_then(|p| p.values().next())
+            .map_or(0, |v| v.len() as u64);
+        let row_count = self.row_count(table_name).unwrap_or(0).saturating_sub(pending);
+        TableMetadata {
+            table: table_name.to_string(),
+            row_count,
+        }
+        .save(&self.data_dir)
+    }
+
     pub fn create_table(&mut self, table: &Table) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        // Validates the definition before any existing table's stores could be replaced.
+        self.schema.add_table(&table.name, table.columns.clone())?;
+        if let Some(location) = &table.location {
+            // External tables own no files; their rows are read on each scan.
+            self.schema.tables.get_mut(&table.name).unwrap().location = Some(location.clone());
+            self.schema.save()?;
+            self.columns.insert(table.name.clone(), HashMap::new());
+            self.indexes.insert(table.name.clone(), HashMap::new());
+            self.bump_table_version(&table.name);
+            return Ok(());
+        }
+        let table_def = self.schema.get_table(&table.name).unwrap().clone();
         let mut table_cols = HashMap::new();
         let mut table_indexes = HashMap::new();
-        for col in &table.columns {
+        for col in &table_def.columns {
             table_cols.insert(
                 col.name.clone(),
-                ColumnStore::new(col, &self.data_dir)?,
+                ColumnStore::new(col, &table.name, &self.data_dir, self.layout)?,
             );
-            if col.name == "ID" || col.name == "Name" {
-                let index_path = format!("{}/indexes/{}_{}.idx", self.data_dir, table.name, col.name);
+            // Key columns need an index to enforce uniqueness.
+            if table_def.is_unique(&col.name) || col.name == "Name" {
+                let index_path = format!("{}/{}", self.data_dir, self.layout.index_file(&table.name, col));
                 table_indexes.insert(
                     col.name.clone(),
-                    Index::new(&index_path, col.data_type.clone())?,
+                    Index::new(&index_path, col.data_type.clone(), col.collation)?,
                 );
             }
         }
         self.columns.insert(table.name.clone(), table_cols);
         self.indexes.insert(table.name.clone(), table_indexes);
-        self.schema.add_table(&table.name, table.columns.clone())?;
+        self.save_row_count(&table.name)?;
+        self.bump_table_version(&table.name);
         Ok(())
     }
 
     pub fn insert_row(&mut self, table_name: &str, row: Vec<Value>) -> Result<(), DbError> {
+        self.insert_rows(table_name, vec![row])
+    }
+
+    /// Inserts `rows` as one batch: every row is validated, unique columns
+    /// included, before any is buffered, and the buffer is flushed at most
+    /// once, after the last row.
+    pub fn insert_rows(&mut self, table_name: &str, rows: Vec<Vec<Value>>) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        self.ensure_stored(table_name)?;
         // Validate and get references
         let table_def = self.schema.get_table(table_name).ok_or_else(|| {
             DbError::InvalidData(format!("Table {} not found", table_name))
         })?.clone();
-        self.schema.validate_row(table_name, &row)?;
+        for row in &rows {
+            self.schema.validate_row(table_name, row)?;
+        }
 
-        // Check for duplicate ID
-        {
-            let table_indexes = self.indexes.get_mut(table_name).ok_or_else(|| {
-                DbError::InvalidData(format!("Table {} not found", table_name))
-            })?;
-            if let Some(id_index) = table_indexes.get("ID") {
-                let id_value = &row[0];
-                let existing = id_index.lookup(id_value)?;
-                if !existing.is_empty() {
-                    return Err(DbError::InvalidData(format!("Duplicate ID: {:?}", id_value)));
+        for (i, col) in table_def.columns.iter().enumerate() {
+            if !table_def.is_unique(&col.name) {
+                continue;
+            }
+            let mut batch = HashSet::new();
+            for value in rows.iter().map(|row| &row[i]) {
+                self.check_unique(table_name, col, value)?;
+                if !value.is_null() && !batch.insert(col.collation.key(value)) {
+                    return Err(DbError::InvalidData(format!("Duplicate {}: {:?}", col.name, value)));
                 }
             }
         }
 
-        // Buffer the row
-        let table_pending = self.pending_rows.entry(table_name.to_string()).or_insert_with(HashMap::new);
-        for (value, col) in row.into_iter().zip(table_def.columns.iter()) {
-            let col_name = &col.name;
-            let col_values = table_pending.entry(col_name.clone()).or_insert_with(Vec::new);
-            col_values.push(value);
+        self.bump_table_version(table_name);
+        if let Some(table_indexes) = self.indexes.get_mut(table_name) {
+            for row in &rows {
+                for (value, col) in row.iter().zip(&table_def.columns) {
+                    if let Some(index) = table_indexes.get_mut(&col.name) {
+                        index.note(value);
+                    }
+                }
+            }
+        }
+
+        // Buffer the rows
+        let row_count = rows.len();
+        let buffered_bytes = self.pending_table_bytes(table_name) + rows.iter().map(|r| decoded_size(r)).sum::<usize>();
+        let table_pending = self.pending_rows.entry(table_name.to_string()).or_default();
+        for row in rows {
+            for (value, col) in row.into_iter().zip(table_def.columns.iter()) {
+                let col_name = &col.name;
+                let col_values = table_pending.entry(col_name.clone()).or_default();
+                col_values.push(value);
+            }
+        }
+
+        if let Some(table) = self.schema.tables.get_mut(table_name) {
+            table.row_count += row_count as u64;
         }
 
         // Flush if buffer is full
-        if table_pending.values().next().map_or(0, |v| v.len()) >= self.max_rows_per_segment {
-            let mut table_cols = self.columns.get_mut(table_name).ok_or_else(|| {
-                DbError::InvalidData(format!("Table {} not found", table_name))
-            })?;
-            let mut table_indexes = self.indexes.get_mut(table_name).ok_or_else(|| {
-                DbError::InvalidData(format!("Table {} not found", table_name))
-            })?;
-            do_flush_pending_rows(
-                &mut self.pending_rows,
-                table_name,
-                &mut table_cols,
-                &mut table_indexes,
-                &table_def,
-            )?;
-        }
-
-        // Increment row count
-        if let Some(table) = self.schema.get_table(table_name) {
-            let mut table = table.clone();
-            table.increment_row_count();
-            self.schema.tables.insert(table_name.to_string(), table);
-            self.schema.save()?;
+        let pending = table_pending.values().next().map_or(0, |v| v.len());
+        self.pending_bytes.insert(table_name.to_string(), (pending, buffered_bytes));
+        if pending >= self.settings.segment_rows || buffered_bytes >= self.settings.segment_bytes {
+            self.flush_table(table_name)?;
+            self.compact_if_fragmented(table_name)?;
+        }
+        self.limit_pending_memory()
+    }
+
+    /// Estimated memory of a table's buffered rows.
+    pub fn pending_table_bytes(&mut self, table_name: &str) -> usize {
+        let Some(table_pending) = self.pending_rows.get(table_name) else {
+            return 0;
+        };
+        let rows = table_pending.values().next().map_or(0, Vec::len);
+        match self.pending_bytes.get(table_name) {
+            Some(&(counted, bytes)) if counted == rows => bytes,
+            _ => {
+                let bytes = table_pending.values().map(|values| decoded_size(values)).sum();
+                self.pending_bytes.insert(table_name.to_string(), (rows, bytes));
+                bytes
+            }
+        }
+    }
+
+    /// Flushes the tables buffering the most until what all of them buffer
+    /// fits `pending_memory_bytes`.
+    fn limit_pending_memory(&mut self) -> Result<(), DbError> {
+        let tables: Vec<String> = self.pending_rows.keys().cloned().collect();
+        let mut sizes: Vec<(usize, String)> = tables.into_iter().map(|t| (self.pending_table_bytes(&t), t)).collect();
+        let mut total: usize = sizes.iter().map(|(bytes, _)| bytes).sum();
+        sizes.sort();
+        while total > self.settings.pending_memory_bytes
+            && let Some((bytes, table_name)) = sizes.pop()
+        {
+            log::debug!("Flushing {} to keep buffered rows under pending_memory_bytes", table_name);
+            self.flush_table(&table_name)?;
+            self.compact_if_fragmented(&table_name)?;
+            total -= bytes;
+        }
+        Ok(())
+    }
+
+    /// Fails if a flushed or buffered row of the table already has `value`
+    /// in unique column `col`. NULLs never conflict. The index's bloom filter
+    /// answers the common case, a value never seen, without a lookup.
+    pub(crate) fn check_unique(&self, table_name: &str, col: &Column, value: &Value) -> Result<(), DbError> {
+        if value.is_null() {
+            return Ok(());
+        }
+        let index = self.indexes.get(table_name).and_then(|indexes| indexes.get(&col.name));
+        if index.is_some_and(|index| !index.may_contain(value)) {
+            return Ok(());
+        }
+        let flushed = match index {
+            Some(index) => !index.lookup(value)?.is_empty(),
+            None => false,
+        };
+        let key = col.collation.key(value);
+        let buffered = self
+            .pending_rows
+            .get(table_name)
+            .and_then(|pending| pending.get(&col.name))
+            .is_some_and(|values| values.iter().any(|v| col.collation.key(v) == key));
+        if flushed || buffered {
+            return Err(DbError::InvalidData(format!("Duplicate {}: {:?}", col.name, value)));
         }
         Ok(())
     }
@@ -183,6 +481,38 @@ impl StorageManager {
         table_name: &str,
         column_name: &str,
         condition: Option<&Condition>,
+    ) -> Result<Vec<Value>, DbError> {
+        self.read_column_sampled(table_name, column_name, condition, None, true)
+    }
+
+    /// Like `read_column`, but with `sample` set only whole blocks chosen by
+    /// `column::block_sampled` are read. Unflushed rows count as one extra block.
+    /// Without `use_indexes`, only zone maps prune blocks.
+    pub fn read_column_sampled(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+        condition: Option<&Condition>,
+        sample: Option<f64>,
+        use_indexes: bool,
+    ) -> Result<Vec<Value>, DbError> {
+        if let Some(table_def) = self.external_table(table_name) {
+            return self.read_external_column(&table_def, column_name, condition, sample);
+        }
+        let keep = self.block_mask_with(table_name, condition, use_indexes);
+        let sampled = |i: usize| sample.is_none_or(|percent| block_sampled(i, percent));
+        self.read_column_where(table_name, column_name, |i| {
+            sampled(i) && keep.as_ref().is_none_or(|k| k.get(i).copied().unwrap_or(true))
+        })
+    }
+
+    /// The values of the blocks at the positions `keep` accepts. The position
+    /// after the last block stands for the unflushed rows.
+    fn read_column_where(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        keep: impl Fn(usize) -> bool,
     ) -> Result<Vec<Value>, DbError> {
         let col_store = self
             .columns
@@ -191,24 +521,319 @@ impl StorageManager {
             .ok_or_else(|| {
                 DbError::InvalidData(format!("Column {}.{} not found", table_name, column_name))
             })?;
-        let mut values = col_store.read(condition, &mut self.buffer)?;
+        let mut values = col_store.read_selected_with(&keep, &self.buffer, self.settings.strict_reads)?;
 
         // Append pending rows
-        if let Some(table_pending) = self.pending_rows.get(table_name) {
-            if let Some(pending_values) = table_pending.get(column_name) {
-                values.extend(pending_values.iter().cloned());
-            }
+        if keep(col_store.metadata.blocks.len())
+            && let Some(table_pending) = self.pending_rows.get(table_name)
+            && let Some(pending_values) = table_pending.get(column_name)
+        {
+            values.extend(pending_values.iter().cloned());
         }
 
         Ok(values)
     }
 
-    pub fn delete_rows(&mut self, table_name: &str, condition: Option<&Condition>) -> Result<(), DbError> {
+    /// Like `read_column_sampled`, but returns a typed vector. String columns
+    /// come back dictionary-encoded straight from their dictionary blocks.
+    pub fn read_column_vector(
+        &mut self,
+        table_name: &str,
+        column_name: &str,
+        condition: Option<&Condition>,
+        sample: Option<f64>,
+        use_indexes: bool,
+    ) -> Result<ColumnVector, DbError> {
+        if let Some(table_def) = self.external_table(table_name) {
+            let col = table_def
+                .get_column(column_name)
+                .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table_name, column_name)))?;
+            let values = self.read_external_column(&table_def, column_name, condition, sample)?;
+            return ColumnVector::from_values(&values, &col.data_type);
+        }
+        let keep = self.block_mask_with(table_name, condition, use_indexes);
+        let sampled = |i: usize| sample.is_none_or(|percent| block_sampled(i, percent));
+        self.read_vector_where(table_name, column_name, |i| {
+            sampled(i) && keep.as_ref().is_none_or(|k| k.get(i).copied().unwrap_or(true))
+        })
+    }
+
+    /// One block of a stored column as a typed vector; `block` equal to the
+    /// block count reads the unflushed rows instead.
+    pub fn read_block_vector(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        block: usize,
+    ) -> Result<ColumnVector, DbError> {
+        self.read_vector_where(table_name, column_name, |i| i == block)
+    }
+
+    /// `read_column_where` as a typed vector.
+    fn read_vector_where(
+        &self,
+        table_name: &str,
+        column_name: &str,
+        keep: impl Fn(usize) -> bool,
+    ) -> Result<ColumnVector, DbError> {
+        let col_store = self
+            .columns
+            .get(table_name)
+            .and_then(|cols| cols.get(column_name))
+            .ok_or_else(|| {
+                DbError::InvalidData(format!("Column {}.{} not found", table_name, column_name))
+            })?;
+        let pending = keep(col_store.metadata.blocks.len())
+            .then(|| self.pending_rows.get(table_name).and_then(|p| p.get(column_name)))
+            .flatten();
+        match col_store.column.data_type {
+            DataType::Int32 => return self.read_primitive(col_store, &keep, pending, ColumnVector::Int32),
+            DataType::Float32 => return self.read_primitive(col_store, &keep, pending, ColumnVector::Float32),
+            DataType::Int64 => return self.read_primitive(col_store, &keep, pending, ColumnVector::Int64),
+            DataType::Boolean => return self.read_primitive(col_store, &keep, pending, ColumnVector::Boolean),
+            DataType::Date => return self.read_primitive(col_store, &keep, pending, ColumnVector::Date),
+            DataType::Timestamp => return self.read_primitive(col_store, &keep, pending, ColumnVector::Timestamp),
+            DataType::String => {}
+        }
+
+        let mut builder = DictionaryBuilder::default();
+        let cache = self.settings.cache_string_blocks.then_some(&self.buffer);
+        col_store.read_selected_dictionary(&keep, &mut builder, cache, self.settings.strict_reads)?;
+        if let Some(pending_values) = pending {
+            for v in pending_values {
+                match v {
+                    Value::String(s) => builder.push(s),
+                    Value::Null => builder.push_null(),
+                    _ => {}
+                }
+            }
+        }
+        Ok(builder.finish())
+    }
+
+    /// The selected blocks and `pending` rows of a fixed-width column, decoded
+    /// into the typed vector `wrap` builds.
+    fn read_primitive<T: Primitive>(
+        &self,
+        col_store: &ColumnStore,
+        keep: impl Fn(usize) -> bool,
+        pending: Option<&Vec<Value>>,
+        wrap: fn(Vec<T>) -> ColumnVector,
+    ) -> Result<ColumnVector, DbError> {
+        let (mut values, mut nulls) = (Vec::new(), Vec::new());
+        let strict = self.settings.strict_reads;
+        col_store.read_selected_primitive(keep, &mut values, &mut nulls, wrap, &self.buffer, strict)?;
+        for v in pending.into_iter().flatten() {
+            values.push(T::from_value(v).unwrap_or_default());
+            nulls.push(v.is_null());
+        }
+        Ok(with_nulls(wrap(values), nulls))
+    }
+
+    /// Column min/max from block metadata (zone maps) plus unflushed rows,
+    /// without reading any data blocks. `None` when the column is empty.
+    pub fn zone_map_bounds(&self, table_name: &str, column_name: &str) -> Result<Option<(Value, Value)>, DbError> {
+        let col_store = self
+            .columns
+            .get(table_name)
+            .and_then(|cols| cols.get(column_name))
+            .ok_or_else(|| {
+                DbError::InvalidData(format!("Column {}.{} not found", table_name, column_name))
+            })?;
+        let pending = self
+            .pending_rows
+            .get(table_name)
+            .and_then(|p| p.get(column_name))
+            .map(|v| v.as_slice())
+            .unwrap_or_default();
+        // All-NULL blocks carry NULL bounds; NULLs never count towards MIN/MAX.
+        let min = col_store.metadata.blocks.iter().map(|b| &b.min).chain(pending).filter(|v| !v.is_null()).min();
+        let max = col_store.metadata.blocks.iter().map(|b| &b.max).chain(pending).filter(|v| !v.is_null()).max();
+        Ok(min.cloned().zip(max.cloned()))
+    }
+
+    /// Block positions that may hold rows matching `condition`. All columns of
+    /// a table are flushed together, so block `i` covers the same rows in every
+    /// column and pruning must be decided per position, not per column.
+    pub fn block_mask(&self, table_name: &str, condition: Option<&Condition>) -> Option<Vec<bool>> {
+        self.block_mask_with(table_name, condition, true)
+    }
+
+    /// `block_mask`, consulting the table's indexes only if `use_indexes`.
+    pub fn block_mask_with(
+        &self,
+        table_name: &str,
+        condition: Option<&Condition>,
+        use_indexes: bool,
+    ) -> Option<Vec<bool>> {
+        let condition = condition?;
+        let block_count = self.columns.get(table_name)?.values().map(|c| c.metadata.blocks.len()).max()?;
+        Some(self.condition_mask(table_name, condition, block_count, use_indexes))
+    }
+
+    /// Stored blocks of the table, the same for each of its columns.
+    pub fn block_count(&self, table_name: &str) -> usize {
+        self.columns
+            .get(table_name)
+            .and_then(|cols| cols.values().map(|c| c.metadata.blocks.len()).max())
+            .unwrap_or(0)
+    }
+
+    /// Prunes each side of an AND or OR on its own columns, then intersects
+    /// or unites the positions, so conditions over several columns prune
+    /// as well as single-column ones. A side that already decides every
+    /// block spares the other its index lookups.
+    fn condition_mask(
+        &self,
+        table_name: &str,
+        condition: &Condition,
+        block_count: usize,
+        use_indexes: bool,
+    ) -> Vec<bool> {
+        match condition {
+            Condition::And(left, right) => {
+                let mut keep = self.condition_mask(table_name, left, block_count, use_indexes);
+                if keep.contains(&true) {
+                    let right = self.condition_mask(table_name, right, block_count, use_indexes);
+                    keep.iter_mut().zip(right).for_each(|(k, r)| *k &= r);
+                }
+                keep
+            }
+            Condition::Or(left, right) => {
+                let mut keep = self.condition_mask(table_name, left, block_count, use_indexes);
+                if keep.contains(&false) {
+                    let right = self.condition_mask(table_name, right, block_count, use_indexes);
+                    keep.iter_mut().zip(right).for_each(|(k, r)| *k |= r);
+                }
+                keep
+            }
+            leaf => {
+                let mut keep = vec![true; block_count];
+                for name in crate::query::collect_condition_columns(leaf) {
+                    // Zone maps hold binary bounds, which don't bound other collations.
+                    let Some(col_store) = self.columns[table_name].get(&name) else {
+                        continue;
+                    };
+                    if col_store.column.collation != Collation::Binary {
+                        continue;
+                    }
+                    for (k, block) in keep.iter_mut().zip(&col_store.metadata.blocks) {
+                        *k &= evaluate_condition_block(leaf, &name, block);
+                    }
+                    // A string the column never interned is in none of its blocks.
+                    if let Condition::Equal(_, Value::String(s)) = leaf
+                        && col_store.intern().is_some_and(|intern| intern.id(s).is_none())
+                    {
+                        keep.fill(false);
+                    }
+                }
+                if use_indexes
+                    && keep.contains(&true)
+                    && let Some(indexed) = self.index_mask(table_name, leaf, block_count)
+                {
+                    keep.iter_mut().zip(indexed).for_each(|(k, i)| *k &= i);
+                }
+                keep
+            }
+        }
+    }
+
+    /// The blocks `read_column` would read for `condition`, or `None` for a
+    /// table without stored blocks such as an external one.
+    pub fn block_scan(&self, table_name: &str, condition: Option<&Condition>, use_indexes: bool) -> Option<BlockScan> {
+        if self.external_table(table_name).is_some() {
+            return None;
+        }
+        let total_blocks = self.columns.get(table_name)?.values().map(|c| c.metadata.blocks.len()).max()?;
+        let read_blocks = self
+            .block_mask_with(table_name, condition, use_indexes)
+            .map_or(total_blocks, |keep| keep.iter().filter(|k| **k).count());
+        let mut indexes = Vec::new();
+        if let Some(condition) = condition
+            && use_indexes
+            && self.index_mask(table_name, condition, total_blocks).is_some()
+        {
+            let table_indexes = &self.indexes[table_name];
+            indexes = crate::query::collect_condition_columns(condition)
+                .into_iter()
+                .filter(|c| table_indexes.contains_key(c))
+                .collect();
+            indexes.sort();
+        }
+        Some(BlockScan { total_blocks, read_blocks, indexes })
+    }
+
+    /// Approximate memory the blocks a read of `table_name` under `condition`
+    /// decodes take, counting every column and the buffered rows.
+    pub fn scan_bytes(&self, table_name: &str, condition: Option<&Condition>) -> usize {
+        let Some(table_cols) = self.columns.get(table_name) else {
+            return 0;
+        };
+        let keep = self.block_mask(table_name, condition);
+        let kept = |i: usize| keep.as_ref().is_none_or(|k| k.get(i).copied().unwrap_or(true));
+        let rows: usize = table_cols
+            .values()
+            .flat_map(|c| c.metadata.blocks.iter().enumerate())
+            .filter(|(i, _)| kept(*i))
+            .map(|(_, block)| block.row_count)
+            .sum();
+        let pending: usize = self.pending_rows.get(table_name).map_or(0, |p| p.values().map(Vec::len).sum());
+        (rows + pending) * std::mem::size_of::<Value>()
+    }
+
+    /// Block positions holding keys that satisfy `condition` according to
+    /// the table's indexes; `None` when no index applies.
+    fn index_mask(&self, table_name: &str, condition: &Condition, block_count: usize) -> Option<Vec<bool>> {
+        let lookup = |col: &str, lower: Bound<&Value>, upper: Bound<&Value>| -> Option<Vec<bool>> {
+            let index = self.indexes.get(table_name)?.get(col)?;
+            let col_store = self.columns.get(table_name)?.get(col)?;
+            // A literal of another type can't be looked up; fall back to scanning.
+            let offsets: HashSet<u64> = index.scan(lower, upper).ok()?.into_iter().collect();
+            let mut keep = vec![true; block_count];
+            for (k, block) in keep.iter_mut().zip(&col_store.metadata.blocks) {
+                *k = offsets.contains(&block.offset);
+            }
+            Some(keep)
+        };
+        match condition {
+            Condition::Equal(col, val) => lookup(col, Bound::Included(val), Bound::Included(val)),
+            Condition::GreaterThan(col, val) => lookup(col, Bound::Excluded(val), Bound::Unbounded),
+            Condition::GreaterThanOrEqual(col, val) => lookup(col, Bound::Included(val), Bound::Unbounded),
+            Condition::LessThan(col, val) => lookup(col, Bound::Unbounded, Bound::Excluded(val)),
+            Condition::LessThanOrEqual(col, val) => lookup(col, Bound::Unbounded, Bound::Included(val)),
+            Condition::Between(col, low, high) => lookup(col, Bound::Included(low), Bound::Included(high)),
+            Condition::In(col, values) => values.iter().try_fold(vec![false; block_count], |keep, val| {
+                let found = lookup(col, Bound::Included(val), Bound::Included(val))?;
+                Some(keep.iter().zip(found).map(|(a, b)| *a || b).collect())
+            }),
+            Condition::And(left, right) => match (
+                self.index_mask(table_name, left, block_count),
+                self.index_mask(table_name, right, block_count),
+            ) {
+                (Some(l), Some(r)) => Some(l.iter().zip(r).map(|(a, b)| *a && b).collect()),
+                (l, r) => l.or(r),
+            },
+            Condition::Or(left, right) => {
+                let l = self.index_mask(table_name, left, block_count)?;
+                let r = self.index_mask(table_name, right, block_count)?;
+                Some(l.iter().zip(r).map(|(a, b)| *a || b).collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// Deletes the rows matching `condition` (all rows without one) and
+    /// returns them, columns in table order.
+    pub fn delete_rows(&mut self, table_name: &str, condition: Option<&Condition>) -> Result<Vec<Vec<Value>>, DbError> {
+        self.ensure_writable()?;
+        self.ensure_stored(table_name)?;
         let table_def = self
             .schema
             .get_table(table_name)
             .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?;
         let columns = table_def.columns.clone();
+        let collations = table_def.collations();
+        self.bump_table_version(table_name);
 
         let mut column_values = HashMap::new();
         let mut min_row_count = usize::MAX;
@@ -218,25 +843,29 @@ impl StorageManager {
             column_values.insert(col.name.clone(), values);
         }
 
-        let keep_indices = match condition {
+        let (keep_indices, removed_indices) = match condition {
             Some(cond) => {
                 let cond_columns = crate::query::collect_condition_columns(cond);
                 for col in cond_columns {
-                    if !column_values.contains_key(&col) {
-                        let values = self.read_column(table_name, &col, None)?;
+                    if let Entry::Vacant(entry) = column_values.entry(col) {
+                        let values = self.read_column(table_name, entry.key(), None)?;
                         min_row_count = min_row_count.min(values.len());
-                        column_values.insert(col, values);
+                        entry.insert(values);
                     }
                 }
-                let mut indices = Vec::new();
+                let (mut keep, mut removed) = (Vec::new(), Vec::new());
                 for i in 0..min_row_count {
-                    if !crate::query::evaluator::evaluate_condition_row(cond, &column_values, i)? {
-                        indices.push(i);
+                    if crate::query::evaluator::evaluate_condition_row(cond, &column_values, &collations, i)? {
+                        removed.push(i);
+                    } else {
+                        keep.push(i);
                     }
                 }
-                indices
+                (keep, removed)
             }
             None => {
+                let removed = (0..min_row_count).collect::<Vec<_>>();
+                let removed_rows = table_rows(&columns, &column_values, &removed);
                 let table_cols = self.columns.get_mut(table_name).ok_or_else(|| {
                     DbError::InvalidData(format!("Table {} not found", table_name))
                 })?;
@@ -251,15 +880,12 @@ impl StorageManager {
                     }
                 }
                 self.pending_rows.remove(table_name);
-                if let Some(table) = self.schema.get_table(table_name) {
-                    let mut table = table.clone();
-                    table.row_count = 0;
-                    self.schema.tables.insert(table_name.to_string(), table);
-                    self.schema.save()?;
-                }
-                return Ok(());
+                self.set_row_count(table_name, 0);
+                self.save_row_count(table_name)?;
+                return Ok(removed_rows);
             }
         };
+        let removed_rows = table_rows(&columns, &column_values, &removed_indices);
 
         let table_cols = self.columns.get_mut(table_name).ok_or_else(|| {
             DbError::InvalidData(format!("Table {} not found", table_name))
@@ -273,7 +899,7 @@ impl StorageManager {
             let values = column_values
                 .get(&col.name)
                 .cloned()
-                .unwrap_or_else(|| col_store.read(None, &mut self.buffer).unwrap_or_default());
+                .unwrap_or_else(|| col_store.read(None, &self.buffer).unwrap_or_default());
             let filtered_values: Vec<Value> = keep_indices
                 .iter()
                 .filter(|&&i| i < values.len())
@@ -281,64 +907,38 @@ impl StorageManager {
                 .collect();
             col_store.clear()?;
             if !filtered_values.is_empty() {
-                let compression = match col.data_type {
-                    DataType::String => CompressionType::Dictionary,
-                    _ => CompressionType::Rle,
-                };
-                col_store.append(&filtered_values, compression)?;
+                col_store.append(&filtered_values)?;
             }
             if let Some(index) = table_indexes.get_mut(&col.name) {
-                index.clear()?;
-                if !filtered_values.is_empty() {
-                    index.append(&filtered_values, 0)?;
-                }
+                col_store.reindex(index, &self.buffer)?;
             }
         }
         self.pending_rows.remove(table_name);
-
-        if let Some(table) = self.schema.get_table(table_name) {
-            let mut table = table.clone();
-            table.row_count = keep_indices.len() as u64;
-            self.schema.tables.insert(table_name.to_string(), table);
-            self.schema.save()?;
-        }
-        Ok(())
+        self.set_row_count(table_name, keep_indices.len() as u64);
+        self.save_row_count(table_name)?;
+        Ok(removed_rows)
     }
 
+    /// Moves the table to the trash, buffered rows included; `UNDROP TABLE`
+    /// restores it until the `trash_retention` window passes.
     pub fn drop_table(&mut self, table_name: &str) -> Result<(), DbError> {
-        if !self.schema.tables.contains_key(table_name) {
-            return Err(DbError::InvalidData(format!("Table {} not found", table_name)));
-        }
-
-        let table_cols = self.columns.remove(table_name).ok_or_else(|| {
-            DbError::InvalidData(format!("Table {} not found", table_name))
-        })?;
-        for (col_name, _) in table_cols {
-            let file_path = format!("{}/columns/{}.dat", self.data_dir, col_name);
-            if Path::new(&file_path).exists() {
-                fs::remove_file(&file_path)?;
-            }
-        }
-
-        let table_indexes = self.indexes.remove(table_name).ok_or_else(|| {
-            DbError::InvalidData(format!("Table {} not found", table_name))
-        })?;
-        for (col_name, _) in table_indexes {
-            let index_path = format!("{}/indexes/{}_{}.idx", self.data_dir, table_name, col_name);
-            if Path::new(&index_path).exists() {
-                fs::remove_file(&index_path)?;
-            }
-        }
+        self.ensure_writable()?;
+        let table_def = self
+            .schema
+            .get_table(table_name)
+            .cloned()
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?;
 
-        let metadata_dir = format!("{}/metadata", self.data_dir);
-        for col in self.schema.get_table(table_name).unwrap().columns.iter() {
-            let metadata_path = format!("{}/{}.json", metadata_dir, col.name);
-            if Path::new(&metadata_path).exists() {
-                fs::remove_file(&metadata_path)?;
-            }
+        self.flush_table(table_name)?;
+        self.columns.remove(table_name);
+        // UNDROP reloads the indexes from the files moved to the trash.
+        for index in self.indexes.remove(table_name).unwrap_or_default().values_mut() {
+            index.save()?;
         }
+        self.move_to_trash(&table_def)?;
 
         self.pending_rows.remove(table_name);
+        self.bump_table_version(table_name);
         self.schema.tables.remove(table_name);
         self.schema.save()?;
         Ok(())
@@ -0,0 +1,44 @@
+use crate::storage::StorageManager;
+use crate::types::DbError;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PreloadReport {
+    pub columns: usize,
+    pub blocks: usize,
+    /// Bytes the buffer cache holds afterwards.
+    pub cached_bytes: usize,
+}
+
+impl StorageManager {
+    /// `PRELOAD table [(col, ...)]`: decodes every block of `columns`, or of
+    /// all the table's columns when empty, into the buffer cache so the first
+    /// queries over them don't wait on the files. Blocks beyond the cache size
+    /// push out the least recently used ones as usual.
+    pub fn preload(&mut self, table_name: &str, columns: &[String]) -> Result<PreloadReport, DbError> {
+        let table_def = self
+            .schema
+            .get_table(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?;
+        if table_def.location.is_some() {
+            return Err(DbError::InvalidData(format!("Table {} is external and cannot be preloaded", table_name)));
+        }
+        let names = match columns {
+            [] => table_def.columns.iter().map(|c| c.name.clone()).collect(),
+            columns => columns.to_vec(),
+        };
+        let table_cols = &self.columns[table_name];
+        let mut blocks = 0;
+        for name in &names {
+            let col_store = table_cols
+                .get(name)
+                .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table_name, name)))?;
+            col_store.read_selected(|_| true, &self.buffer)?;
+            blocks += col_store.metadata.blocks.len();
+        }
+        Ok(PreloadReport {
+            columns: names.len(),
+            blocks,
+            cached_bytes: self.buffer.used_bytes(),
+        })
+    }
+}
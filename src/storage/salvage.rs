@@ -0,0 +1,82 @@
+use crate::schema::Table;
+use crate::storage::StorageManager;
+use crate::types::{DbError, Value};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SalvageReport {
+    /// Rows copied into the new table.
+    pub rows: usize,
+    /// Block positions left out because a column's block there was unreadable.
+    pub skipped_blocks: usize,
+}
+
+impl StorageManager {
+    /// `SALVAGE TABLE table INTO target`: creates `target` with the columns of
+    /// `table` and copies into it the rows of every block position that reads
+    /// back in all columns, checksums included, plus the buffered rows.
+    /// Positions where any column's block fails are skipped and logged.
+    pub fn salvage_table(&mut self, table_name: &str, target: &str) -> Result<SalvageReport, DbError> {
+        self.ensure_writable()?;
+        self.ensure_stored(table_name)?;
+        let table_def = self
+            .schema
+            .get_table(table_name)
+            .cloned()
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?;
+        if self.schema.get_table(target).is_some() {
+            return Err(DbError::InvalidData(format!("Table {} already exists", target)));
+        }
+        self.create_table(&Table {
+            name: target.to_string(),
+            columns: table_def.columns.clone(),
+            row_count: 0,
+            location: None,
+            validation: table_def.validation,
+            next_column_id: 0,
+        })?;
+
+        let mut report = SalvageReport { rows: 0, skipped_blocks: 0 };
+        for block in 0..self.block_count(table_name) {
+            let table_cols = &self.columns[table_name];
+            let columns: Result<Vec<Vec<Value>>, DbError> = table_def
+                .columns
+                .iter()
+                .map(|col| match table_cols.get(&col.name) {
+                    Some(col_store) => col_store.read_block_verified(block),
+                    None => Err(DbError::InvalidData(format!("Column {}.{} not found", table_name, col.name))),
+                })
+                .collect();
+            match columns {
+                Ok(columns) => report.rows += self.insert_columns(target, columns)?,
+                Err(e) => {
+                    log::warn!("Salvage of {} skips block {}: {}", table_name, block, e);
+                    report.skipped_blocks += 1;
+                }
+            }
+        }
+        if let Some(pending) = self.pending_rows.get(table_name) {
+            let columns = table_def
+                .columns
+                .iter()
+                .map(|col| pending.get(&col.name).cloned().unwrap_or_default())
+                .collect();
+            report.rows += self.insert_columns(target, columns)?;
+        }
+        Ok(report)
+    }
+
+    /// Inserts one block's worth of column values as rows.
+    fn insert_columns(&mut self, table_name: &str, columns: Vec<Vec<Value>>) -> Result<usize, DbError> {
+        let row_count = columns.iter().map(Vec::len).min().unwrap_or(0);
+        let mut rows = vec![Vec::with_capacity(columns.len()); row_count];
+        for values in columns {
+            for (row, value) in rows.iter_mut().zip(values) {
+                row.push(value);
+            }
+        }
+        if !rows.is_empty() {
+            self.insert_rows(table_name, rows)?;
+        }
+        Ok(row_count)
+    }
+}
@@ -0,0 +1,385 @@
+use crate::query::governor::Limits;
+use crate::schema::Column;
+use crate::types::DbError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::thread;
+use std::time::Duration;
+
+/// Setting names, in the order SHOW SETTINGS lists them.
+pub const SETTING_NAMES: [&str; 17] = [
+    "segment_rows",
+    "segment_bytes",
+    "pending_memory_bytes",
+    "buffer_cache_bytes",
+    "cache_string_blocks",
+    "strict_reads",
+    "sync_mode",
+    "wal_compression",
+    "parallelism",
+    "deterministic_order",
+    "role",
+    "trash_retention",
+    "compact_threshold",
+    "max_concurrent_queries",
+    "query_memory_bytes",
+    "queue_timeout",
+    "priority",
+];
+
+/// Where a setting's effective value came from. Later sources override
+/// earlier ones: built-in default, then `config.json`, then `SET`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SettingSource {
+    Default,
+    Config,
+    Session,
+}
+
+impl fmt::Display for SettingSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingSource::Default => write!(f, "default"),
+            SettingSource::Config => write!(f, "config"),
+            SettingSource::Session => write!(f, "session"),
+        }
+    }
+}
+
+/// How a commit makes its WAL record durable.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncMode {
+    /// Hand the record to the OS; a power loss can drop the latest commits.
+    Normal,
+    /// fsync the WAL before the transaction's statements run.
+    Full,
+}
+
+impl fmt::Display for SyncMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SyncMode::Normal => write!(f, "normal"),
+            SyncMode::Full => write!(f, "full"),
+        }
+    }
+}
+
+/// What a session may see of masked columns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// Sees every value as stored.
+    Privileged,
+    /// Sees masked columns only through their masking function.
+    Restricted,
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Privileged => write!(f, "privileged"),
+            Role::Restricted => write!(f, "restricted"),
+        }
+    }
+}
+
+/// Which queries win when reads compete for admission and the buffer cache.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// Admitted ahead of batch reads and caches what it reads.
+    #[default]
+    Interactive,
+    /// Waits while interactive reads queue, leaves them one slot, and never
+    /// evicts cached blocks to cache its own.
+    Batch,
+}
+
+impl fmt::Display for Priority {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Priority::Interactive => write!(f, "interactive"),
+            Priority::Batch => write!(f, "batch"),
+        }
+    }
+}
+
+/// How a data directory arranges its column files. Indexes are always
+/// `indexes/{table}_{key}.idx`, where the key is as `file_key` gives it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DataLayout {
+    /// `columns/{column}.dat` and `metadata/{column}.json`, as older databases
+    /// were written. Tables with a column of the same name share its files.
+    Flat,
+    /// `columns/{table}/c{id}.dat` and `metadata/{table}/c{id}.json`, so
+    /// renaming a column moves no files. Columns without an id use their name.
+    #[default]
+    PerTable,
+}
+
+impl DataLayout {
+    /// The name a column's files go by.
+    pub fn file_key(self, column: &Column) -> String {
+        match (self, column.id) {
+            (DataLayout::PerTable, Some(id)) => format!("c{}", id),
+            _ => column.name.clone(),
+        }
+    }
+
+    /// A column's data file, relative to the data directory.
+    pub fn column_file(self, table: &str, column: &Column) -> String {
+        match self {
+            DataLayout::Flat => format!("columns/{}.dat", column.name),
+            DataLayout::PerTable => format!("columns/{}/{}.dat", table, self.file_key(column)),
+        }
+    }
+
+    /// The intern table of a `COMPRESSION INTERNED` column, next to its data.
+    pub fn intern_file(self, table: &str, column: &Column) -> String {
+        match self {
+            DataLayout::Flat => format!("columns/{}.intern", column.name),
+            DataLayout::PerTable => format!("columns/{}/{}.intern", table, self.file_key(column)),
+        }
+    }
+
+    /// A column's block metadata, relative to the data directory.
+    pub fn metadata_file(self, table: &str, column: &Column) -> String {
+        match self {
+            DataLayout::Flat => format!("metadata/{}.json", column.name),
+            DataLayout::PerTable => format!("metadata/{}/{}.json", table, self.file_key(column)),
+        }
+    }
+
+    /// The index on a column, relative to the data directory.
+    pub fn index_file(self, table: &str, column: &Column) -> String {
+        format!("indexes/{}_{}.idx", table, self.file_key(column))
+    }
+}
+
+/// Options a database is opened with. They are the defaults `config.json`
+/// and `SET` override.
+#[derive(Clone, Debug)]
+pub struct StorageOptions {
+    /// Buffered rows per table before they are flushed as a block.
+    pub segment_rows: usize,
+    /// Memory the buffer manager may hold decoded blocks in.
+    pub buffer_cache_bytes: usize,
+    pub layout: DataLayout,
+}
+
+impl Default for StorageOptions {
+    fn default() -> Self {
+        StorageOptions {
+            segment_rows: 1024,
+            buffer_cache_bytes: 100_000_000,
+            layout: DataLayout::PerTable,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// Buffered rows per table before they are flushed as a block.
+    pub segment_rows: usize,
+    /// Estimated bytes of a table's buffered rows that flush them early.
+    pub segment_bytes: usize,
+    /// Estimated bytes all tables may buffer together; past it the tables
+    /// buffering most are flushed first.
+    pub pending_memory_bytes: usize,
+    /// Memory the buffer manager may hold decoded blocks in.
+    pub buffer_cache_bytes: usize,
+    /// Cache string blocks read into batches, dictionary-encoded ones as
+    /// their ids and dictionary, rather than decompress them on every query.
+    pub cache_string_blocks: bool,
+    /// Fail a query on a block that doesn't match its checksum or can't be
+    /// decoded, rather than leave the block's rows out.
+    pub strict_reads: bool,
+    pub sync_mode: SyncMode,
+    /// Compress WAL records with zstd, for commits with large payloads.
+    pub wal_compression: bool,
+    /// Worker threads for bulk loads.
+    pub parallelism: usize,
+    /// Sort SELECT and JOIN rows that have no ORDER BY, so results do not
+    /// depend on block layout.
+    pub deterministic_order: bool,
+    /// A session can drop to `restricted` but only `config.json` grants `privileged` back.
+    pub role: Role,
+    /// Seconds a dropped table stays in the trash for `UNDROP TABLE`.
+    pub trash_retention: u64,
+    /// Blocks a merge must remove before a table is compacted automatically.
+    pub compact_threshold: usize,
+    /// Reads that may run at once; more queue.
+    pub max_concurrent_queries: usize,
+    /// Estimated decoded bytes the running reads may scan together.
+    pub query_memory_bytes: usize,
+    /// Seconds a queued read waits before it fails.
+    pub queue_timeout: u64,
+    pub priority: Priority,
+    /// Sources of the settings that are not at their default.
+    sources: HashMap<String, SettingSource>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        let options = StorageOptions::default();
+        Settings {
+            segment_rows: options.segment_rows,
+            segment_bytes: 16_000_000,
+            pending_memory_bytes: 256_000_000,
+            buffer_cache_bytes: options.buffer_cache_bytes,
+            cache_string_blocks: true,
+            strict_reads: false,
+            sync_mode: SyncMode::Normal,
+            wal_compression: false,
+            parallelism: thread::available_parallelism().map_or(1, |n| n.get()),
+            deterministic_order: false,
+            role: Role::Privileged,
+            trash_retention: 7 * 24 * 60 * 60,
+            compact_threshold: 64,
+            max_concurrent_queries: 16,
+            query_memory_bytes: 4_000_000_000,
+            queue_timeout: 30,
+            priority: Priority::Interactive,
+            sources: HashMap::new(),
+        }
+    }
+}
+
+fn parse_flag(name: &str, value: &str) -> Result<bool, DbError> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "on" => Ok(true),
+        "false" | "off" => Ok(false),
+        _ => Err(DbError::InvalidData(format!("Setting {} must be on or off, got {}", name, value))),
+    }
+}
+
+fn parse_count(name: &str, value: &str) -> Result<usize, DbError> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(DbError::InvalidData(format!("Setting {} must be a positive integer, got {}", name, value))),
+    }
+}
+
+impl Settings {
+    /// `options` overridden by `{data_dir}/config.json`, a JSON object of
+    /// setting names to values, when that file exists.
+    pub fn load(data_dir: &str, options: &StorageOptions) -> Result<Self, DbError> {
+        if options.segment_rows == 0 || options.buffer_cache_bytes == 0 {
+            return Err(DbError::InvalidData("segment_rows and buffer_cache_bytes must be positive".to_string()));
+        }
+        let mut settings = Settings {
+            segment_rows: options.segment_rows,
+            buffer_cache_bytes: options.buffer_cache_bytes,
+            ..Settings::default()
+        };
+        let path = format!("{}/config.json", data_dir);
+        if !std::path::Path::new(&path).exists() {
+            return Ok(settings);
+        }
+        let config: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| DbError::SerializationError(format!("{}: {}", path, e)))?;
+        for (name, value) in config {
+            let value = match value {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            };
+            settings.set(&name, &value, SettingSource::Config)?;
+        }
+        Ok(settings)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str, source: SettingSource) -> Result<(), DbError> {
+        let name = name.to_ascii_lowercase();
+        let value = value.trim();
+        match name.as_str() {
+            "segment_rows" => self.segment_rows = parse_count(&name, value)?,
+            "segment_bytes" => self.segment_bytes = parse_count(&name, value)?,
+            "pending_memory_bytes" => self.pending_memory_bytes = parse_count(&name, value)?,
+            "buffer_cache_bytes" => self.buffer_cache_bytes = parse_count(&name, value)?,
+            "parallelism" => self.parallelism = parse_count(&name, value)?,
+            "trash_retention" => self.trash_retention = parse_count(&name, value)? as u64,
+            "compact_threshold" => self.compact_threshold = parse_count(&name, value)?,
+            "max_concurrent_queries" => self.max_concurrent_queries = parse_count(&name, value)?,
+            "query_memory_bytes" => self.query_memory_bytes = parse_count(&name, value)?,
+            "queue_timeout" => self.queue_timeout = parse_count(&name, value)? as u64,
+            "deterministic_order" => self.deterministic_order = parse_flag(&name, value)?,
+            "cache_string_blocks" => self.cache_string_blocks = parse_flag(&name, value)?,
+            "strict_reads" => self.strict_reads = parse_flag(&name, value)?,
+            "wal_compression" => self.wal_compression = parse_flag(&name, value)?,
+            "sync_mode" => {
+                self.sync_mode = match value.to_ascii_lowercase().as_str() {
+                    "normal" => SyncMode::Normal,
+                    "full" => SyncMode::Full,
+                    _ => return Err(DbError::InvalidData(format!("sync_mode must be normal or full, got {}", value))),
+                }
+            }
+            "priority" => {
+                self.priority = match value.to_ascii_lowercase().as_str() {
+                    "interactive" => Priority::Interactive,
+                    "batch" => Priority::Batch,
+                    _ => {
+                        return Err(DbError::InvalidData(format!(
+                            "priority must be interactive or batch, got {}",
+                            value
+                        )));
+                    }
+                }
+            }
+            "role" => {
+                let role = match value.to_ascii_lowercase().as_str() {
+                    "privileged" => Role::Privileged,
+                    "restricted" => Role::Restricted,
+                    _ => {
+                        return Err(DbError::InvalidData(format!(
+                            "role must be privileged or restricted, got {}",
+                            value
+                        )));
+                    }
+                };
+                if source == SettingSource::Session && self.role == Role::Restricted && role == Role::Privileged {
+                    return Err(DbError::TransactionError("A restricted session cannot become privileged".to_string()));
+                }
+                self.role = role;
+            }
+            _ => return Err(DbError::InvalidData(format!("Unknown setting: {}", name))),
+        }
+        self.sources.insert(name, source);
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Option<String> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "segment_rows" => self.segment_rows.to_string(),
+            "segment_bytes" => self.segment_bytes.to_string(),
+            "pending_memory_bytes" => self.pending_memory_bytes.to_string(),
+            "buffer_cache_bytes" => self.buffer_cache_bytes.to_string(),
+            "cache_string_blocks" => if self.cache_string_blocks { "on" } else { "off" }.to_string(),
+            "strict_reads" => if self.strict_reads { "on" } else { "off" }.to_string(),
+            "sync_mode" => self.sync_mode.to_string(),
+            "wal_compression" => if self.wal_compression { "on" } else { "off" }.to_string(),
+            "parallelism" => self.parallelism.to_string(),
+            "deterministic_order" => if self.deterministic_order { "on" } else { "off" }.to_string(),
+            "role" => self.role.to_string(),
+            "trash_retention" => self.trash_retention.to_string(),
+            "compact_threshold" => self.compact_threshold.to_string(),
+            "max_concurrent_queries" => self.max_concurrent_queries.to_string(),
+            "query_memory_bytes" => self.query_memory_bytes.to_string(),
+            "queue_timeout" => self.queue_timeout.to_string(),
+            "priority" => self.priority.to_string(),
+            _ => return None,
+        })
+    }
+
+    /// The caps the governor admits reads under.
+    pub fn query_limits(&self) -> Limits {
+        Limits {
+            max_queries: self.max_concurrent_queries,
+            max_bytes: self.query_memory_bytes,
+            timeout: Duration::from_secs(self.queue_timeout),
+        }
+    }
+
+    pub fn source(&self, name: &str) -> SettingSource {
+        self.sources.get(name).copied().unwrap_or(SettingSource::Default)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::schema::Schema;
+use crate::storage::backup::{copy_dir, BackupReport};
+use crate::storage::buffer::BufferManager;
+use crate::storage::StorageManager;
+use crate::types::DbError;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+fn snapshot_dir(data_dir: &str, name: &str) -> Result<String, DbError> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(DbError::QueryError(format!("Invalid snapshot name: {}", name)));
+    }
+    Ok(format!("{}/snapshots/{}", data_dir, name))
+}
+
+/// Links every file under `from` into `to`, directories included. Column
+/// files only grow or are replaced by a new file, so a link keeps the linked
+/// blocks readable; copies are made where links are not supported.
+pub(crate) fn link_files(from: &Path, to: &Path) -> Result<(), DbError> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            link_files(&entry.path(), &to.join(entry.file_name()))?;
+            continue;
+        }
+        if !entry.file_type()?.is_file() {
+            continue;
+        }
+        let dest = to.join(entry.file_name());
+        if fs::hard_link(entry.path(), &dest).is_err() {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+impl StorageManager {
+    /// A read-only copy of the database as it is now. Block lists, indexes and
+    /// buffered rows are copied and every column file is held open, so the copy
+    /// keeps answering queries consistently while this manager goes on writing:
+    /// appends only add blocks past the copied ones, and rewrites replace the
+    /// file instead of truncating it.
+    pub fn snapshot(&self) -> Result<StorageManager, DbError> {
+        let mut columns = HashMap::with_capacity(self.columns.len());
+        for (table, table_cols) in &self.columns {
+            let mut pinned = HashMap::with_capacity(table_cols.len());
+            for (name, store) in table_cols {
+                pinned.insert(name.clone(), store.pin()?);
+            }
+            columns.insert(table.clone(), pinned);
+        }
+        Ok(StorageManager {
+            data_dir: self.data_dir.clone(),
+            columns,
+            indexes: self.indexes.clone(),
+            buffer: BufferManager::new(self.settings.buffer_cache_bytes),
+            schema: self.schema.clone(),
+            pending_rows: self.pending_rows.clone(),
+            pending_bytes: HashMap::new(),
+            settings: self.settings.clone(),
+            table_versions: self.table_versions.clone(),
+            layout: self.layout,
+            lock: None,
+            governor: self.governor(),
+            query_log: self.query_log(),
+        })
+    }
+
+    /// `CREATE SNAPSHOT name`: records every table's blocks as they are now
+    /// under `snapshots/name`, after flushing buffered rows.
+    pub fn create_named_snapshot(&mut self, name: &str) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        let dir = snapshot_dir(self.data_dir(), name)?;
+        let dest = Path::new(&dir);
+        if dest.exists() {
+            return Err(DbError::InvalidData(format!("Snapshot {} already exists", name)));
+        }
+        self.flush_all()?;
+        self.schema.save()?;
+
+        let source = Path::new(self.data_dir());
+        link_files(&source.join("columns"), &dest.join("columns"))?;
+        let mut report = BackupReport { files: 0, bytes: 0 };
+        for dir in ["metadata", "indexes"] {
+            copy_dir(&source.join(dir), &dest.join(dir), &mut report)?;
+        }
+        fs::copy(source.join("schema.json"), dest.join("schema.json"))?;
+        Ok(())
+    }
+
+    pub fn drop_named_snapshot(&mut self, name: &str) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        let dir = snapshot_dir(self.data_dir(), name)?;
+        if !Path::new(&dir).exists() {
+            return Err(DbError::InvalidData(format!("Snapshot {} not found", name)));
+        }
+        fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    /// The database as `CREATE SNAPSHOT name` recorded it, read-only and with
+    /// this session's settings.
+    pub fn open_named_snapshot(&self, name: &str) -> Result<StorageManager, DbError> {
+        let dir = snapshot_dir(self.data_dir(), name)?;
+        if !Path::new(&dir).exists() {
+            return Err(DbError::InvalidData(format!("Snapshot {} not found", name)));
+        }
+        let mut storage = StorageManager::new_read_only(&dir, Schema::load(&dir)?, &self.options())?;
+        storage.settings = self.settings.clone();
+        storage.buffer.set_max_size(storage.settings.buffer_cache_bytes);
+        Ok(storage)
+    }
+}
@@ -0,0 +1,321 @@
+use crate::query::sketch::HyperLogLog;
+use crate::schema::statistics::{ColumnPairStatistics, ColumnStatistics, TableStatistics};
+use crate::schema::Table;
+use crate::storage::compression::estimate_compressed_size;
+use crate::storage::StorageManager;
+use crate::types::{CompressionType, DbError, Value};
+use std::collections::HashMap;
+use std::fs;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ColumnStats {
+    pub name: String,
+    pub block_count: usize,
+    pub row_count: usize,
+    pub on_disk_bytes: u64,
+    pub compressed_bytes: u64,
+    pub uncompressed_bytes: u64,
+    pub index_bytes: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TableStats {
+    pub table: String,
+    pub columns: Vec<ColumnStats>,
+}
+
+impl TableStats {
+    pub fn on_disk_bytes(&self) -> u64 {
+        self.columns.iter().map(|c| c.on_disk_bytes).sum()
+    }
+
+    pub fn compressed_bytes(&self) -> u64 {
+        self.columns.iter().map(|c| c.compressed_bytes).sum()
+    }
+
+    pub fn uncompressed_bytes(&self) -> u64 {
+        self.columns.iter().map(|c| c.uncompressed_bytes).sum()
+    }
+
+    pub fn index_bytes(&self) -> u64 {
+        self.columns.iter().map(|c| c.index_bytes).sum()
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.columns.iter().map(|c| c.block_count).sum()
+    }
+
+    /// Rows per SHOW TABLE SIZE: one per column followed by a total row.
+    pub fn to_rows(&self) -> Vec<Vec<Value>> {
+        let mut rows: Vec<Vec<Value>> = self
+            .columns
+            .iter()
+            .map(|c| {
+                vec![
+                    Value::String(c.name.clone()),
+                    Value::Int32(c.block_count as i32),
+                    Value::Int32(c.row_count as i32),
+                    Value::Int64(c.on_disk_bytes as i64),
+                    Value::Int64(c.compressed_bytes as i64),
+                    Value::Int64(c.uncompressed_bytes as i64),
+                    Value::Int64(c.index_bytes as i64),
+                ]
+            })
+            .collect();
+        rows.push(vec![
+            Value::String("TOTAL".to_string()),
+            Value::Int32(self.block_count() as i32),
+            Value::Int32(self.columns.first().map_or(0, |c| c.row_count) as i32),
+            Value::Int64(self.on_disk_bytes() as i64),
+            Value::Int64(self.compressed_bytes() as i64),
+            Value::Int64(self.uncompressed_bytes() as i64),
+            Value::Int64(self.index_bytes() as i64),
+        ]);
+        rows
+    }
+}
+
+/// One row of SHOW STORAGE REPORT: the blocks of a column written with one codec.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CodecUsage {
+    pub table: String,
+    pub column: String,
+    pub codec: CompressionType,
+    pub blocks: usize,
+    pub rows: usize,
+    /// Plain-encoded size of the non-NULL values.
+    pub raw_bytes: u64,
+    pub compressed_bytes: u64,
+}
+
+impl CodecUsage {
+    /// Raw bytes per compressed byte; 0 when nothing was written.
+    pub fn ratio(&self) -> f64 {
+        if self.compressed_bytes == 0 {
+            return 0.0;
+        }
+        self.raw_bytes as f64 / self.compressed_bytes as f64
+    }
+
+    pub fn average_block_bytes(&self) -> u64 {
+        self.compressed_bytes / self.blocks.max(1) as u64
+    }
+}
+
+/// One row of SHOW INDEXES.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IndexStats {
+    pub table: String,
+    pub column: String,
+    pub keys: usize,
+    pub entries: usize,
+    /// Size of the `.idx` file; 0 until the index is first saved.
+    pub disk_bytes: u64,
+    /// Changes not yet written to the file.
+    pub unsaved: bool,
+}
+
+fn file_len(path: &str) -> u64 {
+    fs::metadata(path).map(|m| m.len()).unwrap_or(0)
+}
+
+impl StorageManager {
+    pub fn table_stats(&mut self, table_name: &str) -> Result<TableStats, DbError> {
+        let table_def = self
+            .schema()
+            .get_table(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?
+            .clone();
+
+        let mut columns = Vec::new();
+        for col in &table_def.columns {
+            let col_store = self
+                .columns
+                .get(table_name)
+                .and_then(|cols| cols.get(&col.name))
+                .ok_or_else(|| {
+                    DbError::InvalidData(format!("Column {}.{} not found", table_name, col.name))
+                })?;
+            let blocks = &col_store.metadata.blocks;
+            let row_count: usize = blocks.iter().map(|b| b.row_count).sum();
+            let compressed_bytes = blocks
+                .iter()
+                .map(|b| b.serialized_size.unwrap_or(0) as u64)
+                .sum();
+            let on_disk_bytes = file_len(&col_store.file_path);
+            let block_count = blocks.len();
+
+            // Fixed-width types can be sized from metadata alone; strings need their lengths.
+            let uncompressed_bytes = match col.data_type.fixed_width() {
+                Some(width) => (row_count * width) as u64,
+                None => {
+                    let values = col_store.read(None, &self.buffer)?;
+                    estimate_compressed_size(&values, CompressionType::None) as u64
+                }
+            };
+
+            let index_path = format!("{}/{}", self.data_dir(), self.layout().index_file(table_name, col));
+            columns.push(ColumnStats {
+                name: col.name.clone(),
+                block_count,
+                row_count,
+                on_disk_bytes,
+                compressed_bytes,
+                uncompressed_bytes,
+                index_bytes: file_len(&index_path),
+            });
+        }
+
+        Ok(TableStats {
+            table: table_name.to_string(),
+            columns,
+        })
+    }
+
+    /// `SHOW STORAGE REPORT [table]`: how each column's blocks are compressed,
+    /// from block metadata. Only string columns are read, to size them raw.
+    pub fn storage_report(&mut self, table_name: Option<&str>) -> Result<Vec<CodecUsage>, DbError> {
+        let mut tables: Vec<Table> = match table_name {
+            Some(name) => vec![
+                self.schema()
+                    .get_table(name)
+                    .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", name)))?
+                    .clone(),
+            ],
+            None => self.schema().tables().cloned().collect(),
+        };
+        tables.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut report = Vec::new();
+        for table in tables.iter().filter(|t| t.location.is_none()) {
+            for col in &table.columns {
+                let col_store = &self.columns[&table.name][&col.name];
+                let mut codecs: Vec<CompressionType> = Vec::new();
+                for block in &col_store.metadata.blocks {
+                    if !codecs.contains(&block.compression) {
+                        codecs.push(block.compression.clone());
+                    }
+                }
+                for codec in codecs {
+                    let blocks: Vec<_> = col_store.metadata.blocks.iter().filter(|b| b.compression == codec).collect();
+                    let present: usize = blocks.iter().map(|b| b.row_count - b.nulls.len()).sum();
+                    let raw_bytes = match col.data_type.fixed_width() {
+                        Some(width) => (present * width) as u64,
+                        None => {
+                            let blocks = &col_store.metadata.blocks;
+                            let values = col_store.read_selected(|i| blocks[i].compression == codec, &self.buffer)?;
+                            let present: Vec<Value> = values.into_iter().filter(|v| !v.is_null()).collect();
+                            estimate_compressed_size(&present, CompressionType::None) as u64
+                        }
+                    };
+                    report.push(CodecUsage {
+                        table: table.name.clone(),
+                        column: col.name.clone(),
+                        blocks: blocks.len(),
+                        rows: blocks.iter().map(|b| b.row_count).sum(),
+                        raw_bytes,
+                        compressed_bytes: blocks.iter().map(|b| b.serialized_size.unwrap_or(0) as u64).sum(),
+                        codec,
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// `SHOW INDEXES [ON table]`: every index in memory, by table and column.
+    pub fn index_stats(&self, table_name: Option<&str>) -> Result<Vec<IndexStats>, DbError> {
+        if let Some(name) = table_name
+            && self.schema().get_table(name).is_none()
+        {
+            return Err(DbError::InvalidData(format!("Table {} not found", name)));
+        }
+        let mut stats: Vec<IndexStats> = self
+            .indexes
+            .iter()
+            .filter(|(table, _)| table_name.is_none_or(|name| name == table.as_str()))
+            .flat_map(|(table, indexes)| {
+                indexes.iter().map(move |(column, index)| IndexStats {
+                    table: table.clone(),
+                    column: column.clone(),
+                    keys: index.key_count(),
+                    entries: index.entry_count(),
+                    disk_bytes: file_len(index.path()),
+                    unsaved: index.is_dirty(),
+                })
+            })
+            .collect();
+        stats.sort_by(|a, b| (&a.table, &a.column).cmp(&(&b.table, &b.column)));
+        Ok(stats)
+    }
+
+    pub fn database_stats(&mut self) -> Result<Vec<TableStats>, DbError> {
+        let mut names: Vec<String> = self.schema().tables().map(|t| t.name.clone()).collect();
+        names.sort();
+        names.iter().map(|name| self.table_stats(name)).collect()
+    }
+
+    /// Scans the table once and persists per-column and pairwise distinct counts.
+    pub fn analyze_table(&mut self, table_name: &str) -> Result<TableStatistics, DbError> {
+        self.ensure_writable()?;
+        let table_def = self
+            .schema()
+            .get_table(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?
+            .clone();
+
+        let mut column_values = Vec::new();
+        for col in &table_def.columns {
+            column_values.push(self.read_column(table_name, &col.name, None)?);
+        }
+        let row_count = column_values.iter().map(|v| v.len()).min().unwrap_or(0);
+
+        let mut columns = HashMap::new();
+        for (col, values) in table_def.columns.iter().zip(&column_values) {
+            let mut hll = HyperLogLog::new();
+            let present: Vec<&Value> = values[..row_count].iter().filter(|v| !v.is_null()).collect();
+            for v in &present {
+                hll.insert(v);
+            }
+            let (histogram, top_values) = ColumnStatistics::distribution(present.iter().map(|v| (*v).clone()).collect());
+            columns.insert(
+                col.name.clone(),
+                ColumnStatistics {
+                    distinct: hll.estimate(),
+                    min: present.iter().min().map(|v| (*v).clone()),
+                    max: present.iter().max().map(|v| (*v).clone()),
+                    null_count: (row_count - present.len()) as u64,
+                    histogram,
+                    top_values,
+                },
+            );
+        }
+
+        let mut column_pairs = Vec::new();
+        for i in 0..table_def.columns.len() {
+            for j in i + 1..table_def.columns.len() {
+                let mut hll = HyperLogLog::new();
+                for pair in column_values[i].iter().zip(&column_values[j]) {
+                    hll.insert(&pair);
+                }
+                column_pairs.push(ColumnPairStatistics {
+                    left: table_def.columns[i].name.clone(),
+                    right: table_def.columns[j].name.clone(),
+                    distinct: hll.estimate(),
+                });
+            }
+        }
+
+        let stats = TableStatistics {
+            table: table_name.to_string(),
+            row_count: row_count as u64,
+            columns,
+            column_pairs,
+        };
+        stats.save(self.data_dir())?;
+        Ok(stats)
+    }
+
+    pub fn table_statistics(&self, table_name: &str) -> Result<Option<TableStatistics>, DbError> {
+        TableStatistics::load(self.data_dir(), table_name)
+    }
+}
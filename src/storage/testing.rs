@@ -0,0 +1,219 @@
+//! Generators and round-trip checks for the block format, for tests and
+//! fuzzers of the built-in codecs or of custom ones.
+
+use crate::schema::Column;
+use crate::storage::column::{encode, split_nulls};
+use crate::storage::compression::{compress, decompress, decompress_into, estimate_compressed_size, DecodeScratch};
+use crate::types::{CompressionType, DataType, DbError, Value};
+use ordered_float::OrderedFloat;
+
+/// Deterministic splitmix64, so a seed always reproduces the same values.
+#[derive(Debug, Clone)]
+pub struct SplitMix(u64);
+
+impl SplitMix {
+    pub fn new(seed: u64) -> Self {
+        SplitMix(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..n`; 0 when `n` is 0.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n.max(1) as u64) as usize
+    }
+
+    /// Lowercase ASCII of exactly `len` characters.
+    pub fn string(&mut self, len: usize) -> String {
+        (0..len).map(|_| (b'a' + self.below(26) as u8) as char).collect()
+    }
+
+    /// Any value of `data_type`, never NULL.
+    pub fn value(&mut self, data_type: &DataType) -> Value {
+        let bits = self.next_u64();
+        match data_type {
+            DataType::Int32 => Value::Int32(bits as i32),
+            DataType::Int64 => Value::Int64(bits as i64),
+            DataType::Float32 => Value::Float32(OrderedFloat(f32::from_bits(bits as u32))),
+            DataType::Boolean => Value::Boolean(bits & 1 == 1),
+            DataType::Date => Value::Date(bits as i32),
+            DataType::Timestamp => Value::Timestamp(bits as i64),
+            DataType::String => {
+                let len = self.below(24);
+                Value::String(self.string(len))
+            }
+        }
+    }
+}
+
+/// How generated values repeat, which decides what each codec is good at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shape {
+    /// Independent values, mostly distinct.
+    Random,
+    /// Runs of one value up to `max_run` long, longer than one RLE entry holds if asked.
+    Runs { max_run: usize },
+    /// Values drawn from a pool of `distinct`.
+    FewDistinct { distinct: usize },
+}
+
+/// `len` non-NULL values of `data_type` in `shape`.
+pub fn generate(rng: &mut SplitMix, data_type: &DataType, len: usize, shape: Shape) -> Vec<Value> {
+    match shape {
+        Shape::Random => (0..len).map(|_| rng.value(data_type)).collect(),
+        Shape::Runs { max_run } => {
+            let mut values = Vec::with_capacity(len);
+            while values.len() < len {
+                let run = (1 + rng.below(max_run)).min(len - values.len());
+                let value = rng.value(data_type);
+                values.extend(std::iter::repeat_n(value, run));
+            }
+            values
+        }
+        Shape::FewDistinct { distinct } => {
+            let pool: Vec<Value> = (0..distinct.max(1)).map(|_| rng.value(data_type)).collect();
+            (0..len).map(|_| pool[rng.below(pool.len())].clone()).collect()
+        }
+    }
+}
+
+/// Inputs that have broken codecs before or are likely to: empty and single
+/// value blocks, runs at and past the longest RLE entry, extreme numbers,
+/// and empty, multi-byte and long strings.
+pub fn edge_cases(data_type: &DataType) -> Vec<Vec<Value>> {
+    let mut rng = SplitMix::new(0);
+    let one = rng.value(data_type);
+    let other = rng.value(data_type);
+    let mut cases = vec![
+        Vec::new(),
+        vec![one.clone()],
+        vec![one.clone(); 255],
+        vec![one.clone(); 256],
+        vec![one.clone(); 255 * 3 + 1],
+        [vec![one.clone(); 300], vec![other.clone(); 2], vec![one.clone(); 255]].concat(),
+    ];
+    cases.push(match data_type {
+        DataType::Int32 => vec![Value::Int32(i32::MIN), Value::Int32(0), Value::Int32(i32::MAX)],
+        DataType::Int64 => vec![Value::Int64(i64::MIN), Value::Int64(-1), Value::Int64(i64::MAX)],
+        DataType::Date => vec![Value::Date(i32::MIN), Value::Date(0), Value::Date(i32::MAX)],
+        DataType::Timestamp => vec![Value::Timestamp(i64::MIN), Value::Timestamp(0), Value::Timestamp(i64::MAX)],
+        DataType::Boolean => vec![Value::Boolean(true), Value::Boolean(false), Value::Boolean(true)],
+        DataType::Float32 => [f32::MIN, -0.0, f32::MIN_POSITIVE, f32::MAX, f32::INFINITY, f32::NAN]
+            .into_iter()
+            .map(|f| Value::Float32(OrderedFloat(f)))
+            .collect(),
+        DataType::String => ["", "a", "", "ünïcødé ✓", &"x".repeat(70_000), "a"]
+            .into_iter()
+            .map(|s| Value::String(s.to_string()))
+            .collect(),
+    });
+    cases
+}
+
+/// Codecs that accept values of `data_type`.
+pub fn codecs_for(data_type: &DataType) -> Vec<CompressionType> {
+    let mut codecs = vec![CompressionType::None, CompressionType::Rle, CompressionType::Lz4, CompressionType::Zstd];
+    if *data_type == DataType::String {
+        codecs.push(CompressionType::Dictionary);
+    }
+    codecs
+}
+
+/// Checks that `decode(encode(values))` gives `values` back, naming the first
+/// row that differs.
+pub fn round_trip_with(
+    values: &[Value],
+    encode: impl Fn(&[Value]) -> Result<Vec<u8>, DbError>,
+    decode: impl Fn(&[u8]) -> Result<Vec<Value>, DbError>,
+) -> Result<Vec<u8>, DbError> {
+    let data = encode(values)?;
+    same_values(values, &decode(&data)?)?;
+    Ok(data)
+}
+
+fn same_values(values: &[Value], decoded: &[Value]) -> Result<(), DbError> {
+    match (0..values.len().max(decoded.len())).find(|&i| values.get(i) != decoded.get(i)) {
+        Some(row) => Err(DbError::InvalidData(format!(
+            "Round trip of {} values changed row {}: {:?} became {:?}",
+            values.len(),
+            row,
+            values.get(row),
+            decoded.get(row)
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Round-trips `values` through a built-in codec, both on its own and
+/// appended after another block with shared scratch space as scans decode,
+/// and checks that `estimate_compressed_size` matches the bytes written.
+pub fn round_trip(values: &[Value], compression: CompressionType, data_type: &DataType) -> Result<(), DbError> {
+    let data = round_trip_with(
+        values,
+        |values| compress(values, compression.clone()),
+        |data| decompress(data, compression.clone(), data_type),
+    )?;
+    let estimate = estimate_compressed_size(values, compression.clone());
+    if estimate != data.len() && !matches!(compression, CompressionType::Lz4 | CompressionType::Zstd) {
+        return Err(DbError::InvalidData(format!(
+            "{} estimate of {} bytes, wrote {}",
+            compression,
+            estimate,
+            data.len()
+        )));
+    }
+    let mut scratch = DecodeScratch::default();
+    let mut out = Vec::new();
+    decompress_into(&data, compression.clone(), data_type, &mut out, &mut scratch)?;
+    let added = decompress_into(&data, compression.clone(), data_type, &mut out, &mut scratch)?;
+    if added != values.len() || out[..added] != out[added..] {
+        return Err(DbError::InvalidData(format!("{} block decoded differently into a non-empty buffer", compression)));
+    }
+    Ok(())
+}
+
+/// Round-trips a block with NULLs the way columns store it: NULLs split out
+/// into row positions, the rest encoded with the codec `column` picks.
+pub fn round_trip_block(column: &Column, values: &[Value]) -> Result<(), DbError> {
+    let (present, nulls) = split_nulls(values);
+    let (compression, data) = encode(column, &present)?;
+    let mut decoded = decompress(&data, compression, &column.data_type)?.into_iter();
+    let restored: Vec<Value> = (0..values.len() as u32)
+        .map(|row| match nulls.binary_search(&row) {
+            Ok(_) => Value::Null,
+            Err(_) => decoded.next().unwrap_or(Value::Null),
+        })
+        .collect();
+    same_values(values, &restored)
+}
+
+/// Round-trips the edge cases and `cases` generated blocks of every shape
+/// through every codec that takes `data_type`, returning how many checks ran.
+pub fn check_codecs(data_type: &DataType, seed: u64, cases: usize) -> Result<usize, DbError> {
+    let mut rng = SplitMix::new(seed);
+    let mut blocks = edge_cases(data_type);
+    for _ in 0..cases {
+        let len = rng.below(2000);
+        let shape = match rng.below(3) {
+            0 => Shape::Random,
+            1 => Shape::Runs { max_run: 600 },
+            _ => Shape::FewDistinct { distinct: 1 + rng.below(8) },
+        };
+        blocks.push(generate(&mut rng, data_type, len, shape));
+    }
+    let mut checked = 0;
+    for values in &blocks {
+        for compression in codecs_for(data_type) {
+            round_trip(values, compression.clone(), data_type)
+                .map_err(|e| DbError::InvalidData(format!("{} {:?}: {}", compression, data_type, e)))?;
+            checked += 1;
+        }
+    }
+    Ok(checked)
+}
@@ -0,0 +1,163 @@
+use crate::schema::metadata::TableMetadata;
+use crate::schema::Table;
+use crate::storage::column::ColumnStore;
+use crate::storage::index::Index;
+use crate::storage::layout::table_files;
+use crate::storage::settings::DataLayout;
+use crate::storage::StorageManager;
+use crate::types::DbError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Saved in each trash entry next to the dropped table's files.
+#[derive(Serialize, Deserialize)]
+struct TrashEntry {
+    table: Table,
+    /// Seconds since the epoch.
+    dropped_at: u64,
+    /// The layout the files were dropped in; entries written before tables
+    /// had their own directories are flat.
+    #[serde(default = "flat_layout")]
+    layout: DataLayout,
+}
+
+fn flat_layout() -> DataLayout {
+    DataLayout::Flat
+}
+
+const ENTRY_FILE: &str = "table.json";
+
+pub(crate) fn now_seconds() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
+
+fn trash_root(data_dir: &str) -> String {
+    format!("{}/trash", data_dir)
+}
+
+fn move_file(from: &Path, to: &Path) -> Result<(), DbError> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(from, to)?;
+    Ok(())
+}
+
+impl StorageManager {
+    /// Moves a dropped table's files under `trash/{table}`, replacing an
+    /// earlier drop of the same name, so `UNDROP TABLE` can bring it back.
+    pub(crate) fn move_to_trash(&mut self, table: &Table) -> Result<(), DbError> {
+        self.purge_trash()?;
+        let entry = Path::new(&trash_root(self.data_dir())).join(&table.name);
+        if entry.exists() {
+            fs::remove_dir_all(&entry)?;
+        }
+        fs::create_dir_all(&entry)?;
+        let live = Path::new(self.data_dir());
+        for file in table_files(table, self.layout()) {
+            if live.join(&file).exists() {
+                move_file(&live.join(&file), &entry.join(&file))?;
+            }
+        }
+        if self.layout() == DataLayout::PerTable {
+            for dir in ["columns", "metadata"] {
+                let table_dir = live.join(dir).join(&table.name);
+                // Only empty once every file moved; anything left stays for CLEANUP.
+                if table_dir.exists() && fs::read_dir(&table_dir)?.next().is_none() {
+                    fs::remove_dir(&table_dir)?;
+                }
+            }
+        }
+        let record = TrashEntry {
+            table: table.clone(),
+            dropped_at: now_seconds(),
+            layout: self.layout(),
+        };
+        fs::write(entry.join(ENTRY_FILE), serde_json::to_vec_pretty(&record)?)?;
+        Ok(())
+    }
+
+    /// Deletes trash entries older than the `trash_retention` setting.
+    pub fn purge_trash(&self) -> Result<Vec<String>, DbError> {
+        let root = trash_root(self.data_dir());
+        if self.is_read_only() || !Path::new(&root).exists() {
+            return Ok(Vec::new());
+        }
+        let cutoff = now_seconds().saturating_sub(self.settings.trash_retention);
+        let mut purged = Vec::new();
+        for dir in fs::read_dir(&root)? {
+            let path = dir?.path();
+            // An entry without its record cannot be restored anyway.
+            let expired = match fs::read(path.join(ENTRY_FILE)) {
+                Ok(bytes) => serde_json::from_slice::<TrashEntry>(&bytes).map_or(true, |e| e.dropped_at < cutoff),
+                Err(_) => true,
+            };
+            if expired {
+                fs::remove_dir_all(&path)?;
+                purged.push(path.file_name().unwrap_or_default().to_string_lossy().into_owned());
+            }
+        }
+        purged.sort();
+        Ok(purged)
+    }
+
+    /// `UNDROP TABLE t`: restores the most recent drop of `t` with its rows
+    /// and indexes. Fails if a live table now uses the name or its files.
+    pub fn undrop_table(&mut self, table_name: &str) -> Result<(), DbError> {
+        self.ensure_writable()?;
+        self.purge_trash()?;
+        let entry = Path::new(&trash_root(self.data_dir())).join(table_name);
+        let record: TrashEntry = match fs::read(entry.join(ENTRY_FILE)) {
+            Ok(bytes) => serde_json::from_slice(&bytes)?,
+            Err(_) => return Err(DbError::InvalidData(format!("Table {} is not in the trash", table_name))),
+        };
+        let table = record.table;
+        if self.schema.get_table(&table.name).is_some() {
+            return Err(DbError::InvalidData(format!("Table {} already exists", table.name)));
+        }
+        // Files dropped in another layout are restored to where this one keeps them.
+        let files: Vec<(String, String)> =
+            table_files(&table, record.layout).into_iter().zip(table_files(&table, self.layout())).collect();
+        let data_dir = self.data_dir().to_string();
+        let live = Path::new(&data_dir);
+        if let Some((_, file)) = files.iter().find(|(from, to)| entry.join(from).exists() && live.join(to).exists()) {
+            return Err(DbError::InvalidData(format!(
+                "Cannot restore {}: {} belongs to another table",
+                table.name, file
+            )));
+        }
+        self.schema.add_table(&table.name, table.columns.clone())?;
+        if let Some(t) = self.schema.tables.get_mut(&table.name) {
+            t.location = table.location.clone();
+            t.validation = table.validation;
+        }
+        for (from, to) in &files {
+            if entry.join(from).exists() {
+                move_file(&entry.join(from), &live.join(to))?;
+            }
+        }
+        fs::remove_dir_all(&entry)?;
+
+        let row_count = TableMetadata::load(&data_dir, &table.name)?.map_or(0, |m| m.row_count);
+        if let Some(t) = self.schema.tables.get_mut(&table.name) {
+            t.row_count = row_count;
+        }
+        let mut table_cols = HashMap::new();
+        let mut table_indexes = HashMap::new();
+        for col in table.columns.iter().filter(|_| table.location.is_none()) {
+            table_cols.insert(col.name.clone(), ColumnStore::new(col, &table.name, &data_dir, self.layout())?);
+            let index_path = format!("{}/{}", data_dir, self.layout().index_file(&table.name, col));
+            if table.is_unique(&col.name) || Path::new(&index_path).exists() {
+                table_indexes.insert(col.name.clone(), Index::new(&index_path, col.data_type.clone(), col.collation)?);
+            }
+        }
+        self.columns.insert(table.name.clone(), table_cols);
+        self.indexes.insert(table.name.clone(), table_indexes);
+        self.schema.save()?;
+        self.bump_table_version(&table.name);
+        Ok(())
+    }
+}
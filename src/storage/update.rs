@@ -0,0 +1,124 @@
+use crate::query::evaluator::evaluate_condition_row;
+use crate::query::{collect_condition_columns, Condition};
+use crate::storage::{do_flush_pending_rows, StorageManager};
+use crate::types::{DbError, Value};
+use std::collections::HashMap;
+
+impl StorageManager {
+    /// Sets `assignments` on every row matching `condition` and returns the
+    /// number of rows changed. Only blocks holding a matching row are
+    /// rewritten, and only for the assigned columns.
+    pub fn update_rows(
+        &mut self,
+        table_name: &str,
+        assignments: &[(String, Value)],
+        condition: Option<&Condition>,
+    ) -> Result<usize, DbError> {
+        self.ensure_writable()?;
+        self.ensure_stored(table_name)?;
+        let table_def = self
+            .schema
+            .get_table(table_name)
+            .ok_or_else(|| DbError::InvalidData(format!("Table {} not found", table_name)))?
+            .clone();
+        for (column, value) in assignments {
+            let col = table_def
+                .get_column(column)
+                .ok_or_else(|| DbError::InvalidData(format!("Column {}.{} not found", table_name, column)))?;
+            col.check_value(value)?;
+        }
+        let condition_columns = condition.map(collect_condition_columns).unwrap_or_default();
+        let collations = table_def.collations();
+        for col in &condition_columns {
+            if table_def.get_column(col).is_none() {
+                return Err(DbError::InvalidData(format!("Column {}.{} not found in condition", table_name, col)));
+            }
+        }
+
+        // Updates work on blocks, so make every row durable first.
+        {
+            let table_cols = self.columns.get_mut(table_name).ok_or_else(|| {
+                DbError::InvalidData(format!("Table {} not found", table_name))
+            })?;
+            let table_indexes = self.indexes.get_mut(table_name).ok_or_else(|| {
+                DbError::InvalidData(format!("Table {} not found", table_name))
+            })?;
+            do_flush_pending_rows(&mut self.pending_rows, table_name, table_cols, table_indexes, &table_def)?;
+        }
+
+        // Find matching row positions per block before touching anything.
+        let keep = self.block_mask(table_name, condition);
+        let table_cols = &self.columns[table_name];
+        let block_count = table_cols[&table_def.columns[0].name].metadata.blocks.len();
+        let mut matches: Vec<(usize, Vec<usize>)> = Vec::new();
+        for block in 0..block_count {
+            if keep.as_ref().is_some_and(|k| !k.get(block).copied().unwrap_or(true)) {
+                continue;
+            }
+            let rows = match condition {
+                None => (0..table_cols[&table_def.columns[0].name].metadata.blocks[block].row_count).collect(),
+                Some(cond) => {
+                    let mut block_values = HashMap::new();
+                    for col in &condition_columns {
+                        let values = table_cols[col].read_selected(|i| i == block, &self.buffer)?;
+                        block_values.insert(col.clone(), values);
+                    }
+                    let row_count = block_values.values().map(|v| v.len()).min().unwrap_or(0);
+                    let mut rows = Vec::new();
+                    for i in 0..row_count {
+                        if evaluate_condition_row(cond, &block_values, &collations, i)? {
+                            rows.push(i);
+                        }
+                    }
+                    rows
+                }
+            };
+            if !rows.is_empty() {
+                matches.push((block, rows));
+            }
+        }
+        let updated: usize = matches.iter().map(|(_, rows)| rows.len()).sum();
+        if updated == 0 {
+            return Ok(0);
+        }
+
+        // A unique column can take a new non-NULL value on one row only.
+        for (column, value) in assignments {
+            if value.is_null() || !table_def.is_unique(column) {
+                continue;
+            }
+            let Some(index) = self.indexes.get(table_name).and_then(|i| i.get(column)) else {
+                continue;
+            };
+            let unchanged = updated == 1 && {
+                let (block, rows) = &matches[0];
+                table_cols[column].read_selected(|i| i == *block, &self.buffer)?.get(rows[0]) == Some(value)
+            };
+            if updated > 1 || (!unchanged && !index.lookup(value)?.is_empty()) {
+                return Err(DbError::InvalidData(format!("Duplicate {}: {:?}", column, value)));
+            }
+        }
+
+        let table_cols = self.columns.get_mut(table_name).unwrap();
+        for (column, value) in assignments {
+            let col_store = table_cols.get_mut(column).unwrap();
+            for (block, rows) in &matches {
+                let mut values = col_store.read_selected(|i| i == *block, &self.buffer)?;
+                for &row in rows {
+                    if let Some(slot) = values.get_mut(row) {
+                        *slot = value.clone();
+                    }
+                }
+                col_store.replace_block(*block, &values)?;
+            }
+
+            // Block offsets moved, so rebuild any index on the column.
+            if let Some(index) = self.indexes.get_mut(table_name).and_then(|i| i.get_mut(column)) {
+                col_store.reindex(index, &self.buffer)?;
+            }
+        }
+
+        self.bump_table_version(table_name);
+        Ok(updated)
+    }
+}
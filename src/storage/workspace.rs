@@ -0,0 +1,282 @@
+use crate::query::Query;
+use crate::schema::metadata::TableMetadata;
+use crate::schema::Schema;
+use crate::storage::column::ColumnStore;
+use crate::storage::index::Index;
+use crate::storage::layout::{table_files, DATABASE_FILES, TABLE_DIRS};
+use crate::storage::settings::DataLayout;
+use crate::storage::snapshot::link_files;
+use crate::storage::StorageManager;
+use crate::types::DbError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::path::Path;
+
+/// Written last when staging, so only a complete image is ever restored.
+const READY_MARKER: &str = "READY";
+
+/// Names the tables a workspace holds, next to their files.
+const STAGED_FILE: &str = "staged.json";
+
+/// What a workspace holds besides the files themselves.
+#[derive(Serialize, Deserialize)]
+struct Staged {
+    /// The tables the transaction writes, including ones it creates.
+    tables: Vec<String>,
+    layout: DataLayout,
+    /// Column and intern files are linked rather than copied, as they are
+    /// only appended to or replaced; each is cut back to its length here.
+    lengths: Vec<(String, u64)>,
+    /// Directories the transaction writes, such as the trash, staged whole.
+    dirs: Vec<String>,
+    /// Files CLEANUP may remove, which no table accounts for.
+    orphans: Vec<String>,
+}
+
+/// The tables a multi-statement transaction writes, as they were before it
+/// started. The transaction writes to the live files; discarding the
+/// workspace puts these back, so a failing statement leaves nothing
+/// half-applied.
+pub struct Workspace {
+    path: String,
+    tables: Vec<String>,
+    /// Indexed columns of the staged tables, which are reopened after a restore.
+    indexes: Vec<(String, String)>,
+}
+
+fn workspace_root(data_dir: &str) -> String {
+    format!("{}/txn", data_dir)
+}
+
+/// Moves every file under `from` to the same place under `to`.
+fn move_files(from: &Path, to: &Path) -> Result<(), DbError> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            fs::create_dir_all(&dest)?;
+            move_files(&entry.path(), &dest)?;
+        } else if entry.file_type()?.is_file() {
+            fs::rename(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+/// Puts the staged tables and database files in `from` back in place of the
+/// live ones, removing whatever the transaction added for those tables.
+fn restore_files(data_dir: &str, from: &Path) -> Result<(), DbError> {
+    let staged: Staged = serde_json::from_slice(&fs::read(from.join(STAGED_FILE))?)?;
+    let live = Path::new(data_dir);
+    // The schema as the transaction left it names the files it wrote.
+    let current = Schema::load(data_dir)?;
+    for file in &staged.orphans {
+        if let Some(parent) = live.join(file).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(from.join(file), live.join(file))?;
+    }
+    for table in staged.tables.iter().filter_map(|name| current.get_table(name)) {
+        for file in table_files(table, staged.layout) {
+            if live.join(&file).exists() {
+                fs::remove_file(live.join(&file))?;
+            }
+        }
+    }
+    for dir in TABLE_DIRS {
+        if from.join(dir).exists() {
+            move_files(&from.join(dir), &live.join(dir))?;
+        }
+    }
+    for (file, len) in &staged.lengths {
+        OpenOptions::new().write(true).open(live.join(file))?.set_len(*len)?;
+    }
+    if staged.layout == DataLayout::PerTable {
+        for (dir, table) in ["columns", "metadata"].iter().flat_map(|d| staged.tables.iter().map(move |t| (d, t))) {
+            let table_dir = live.join(dir).join(table);
+            if table_dir.exists() && fs::read_dir(&table_dir)?.next().is_none() {
+                fs::remove_dir(&table_dir)?;
+            }
+        }
+    }
+    for file in DATABASE_FILES {
+        if from.join(file).exists() {
+            fs::copy(from.join(file), live.join(file))?;
+        } else if live.join(file).exists() {
+            fs::remove_file(live.join(file))?;
+        }
+    }
+    for dir in &staged.dirs {
+        if live.join(dir).exists() {
+            fs::remove_dir_all(live.join(dir))?;
+        }
+        if from.join(dir).exists() {
+            fs::rename(from.join(dir), live.join(dir))?;
+        }
+    }
+    Ok(())
+}
+
+/// Rolls back transactions interrupted by a crash: complete workspaces are
+/// restored, partial ones (the crash hit while staging) are dropped.
+/// Returns whether any image was restored.
+pub fn recover(data_dir: &str) -> Result<bool, DbError> {
+    let root = workspace_root(data_dir);
+    if !Path::new(&root).exists() {
+        return Ok(false);
+    }
+    let mut restored = false;
+    for entry in fs::read_dir(&root)? {
+        let path = entry?.path();
+        if path.join(READY_MARKER).exists() {
+            log::warn!("Rolling back interrupted transaction {}", path.display());
+            restore_files(data_dir, &path)?;
+            restored = true;
+        }
+        fs::remove_dir_all(&path)?;
+    }
+    Ok(restored)
+}
+
+impl StorageManager {
+    /// Flushes the buffered rows of the tables `writes` change and saves
+    /// their files, the database files and whatever else they may change, as
+    /// transaction `id` can be rolled back to. Every table is staged when a
+    /// write does not tell which it changes.
+    pub fn stage(&mut self, id: u64, writes: &[&Query]) -> Result<Workspace, DbError> {
+        self.ensure_writable()?;
+        let path = format!("{}/{}", workspace_root(self.data_dir()), id);
+        if Path::new(&path).exists() {
+            fs::remove_dir_all(&path)?;
+        }
+        fs::create_dir_all(&path)?;
+        let tables = writes.iter().map(|q| q.written_tables()).collect::<Option<Vec<_>>>().map(|t| t.concat());
+        let mut tables = tables.unwrap_or_else(|| self.schema.tables().map(|t| t.name.clone()).collect());
+        tables.sort();
+        tables.dedup();
+        for table in &tables {
+            self.flush_table(table)?;
+        }
+        self.save_indexes()?;
+        // A database with no tables yet has no schema.json to copy.
+        self.schema.save()?;
+
+        let live = Path::new(self.data_dir());
+        let dest = Path::new(&path);
+        let mut lengths = Vec::new();
+        for table in tables.iter().filter_map(|name| self.schema.get_table(name)) {
+            for file in table_files(table, self.layout) {
+                let (from, to) = (live.join(&file), dest.join(&file));
+                if !from.exists() {
+                    continue;
+                }
+                if let Some(parent) = to.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                if !file.starts_with("columns/") {
+                    fs::copy(&from, &to)?;
+                    continue;
+                }
+                lengths.push((file, fs::metadata(&from)?.len()));
+                if fs::hard_link(&from, &to).is_err() {
+                    fs::copy(&from, &to)?;
+                }
+            }
+        }
+        for file in DATABASE_FILES {
+            if live.join(file).exists() {
+                fs::copy(live.join(file), dest.join(file))?;
+            }
+        }
+        let mut dirs: Vec<String> = writes.iter().flat_map(|q| q.written_dirs()).map(|d| d.to_string()).collect();
+        dirs.sort();
+        dirs.dedup();
+        for dir in dirs.iter().filter(|dir| live.join(dir).exists()) {
+            link_files(&live.join(dir), &dest.join(dir))?;
+        }
+        let orphans = if writes.iter().any(|q| matches!(q, Query::Cleanup { dry_run: false })) {
+            self.find_orphans()?
+        } else {
+            Vec::new()
+        };
+        for file in &orphans {
+            if let Some(parent) = dest.join(file).parent() {
+                fs::create_dir_all(parent)?;
+            }
+            if fs::hard_link(live.join(file), dest.join(file)).is_err() {
+                fs::copy(live.join(file), dest.join(file))?;
+            }
+        }
+        let staged = Staged {
+            tables: tables.clone(),
+            layout: self.layout,
+            lengths,
+            dirs,
+            orphans,
+        };
+        fs::write(dest.join(STAGED_FILE), serde_json::to_vec(&staged)?)?;
+        fs::write(dest.join(READY_MARKER), [])?;
+
+        let mut indexes: Vec<(String, String)> = tables
+            .iter()
+            .filter_map(|table| self.indexes.get(table).map(|cols| (table, cols)))
+            .flat_map(|(table, cols)| cols.keys().map(move |col| (table.clone(), col.clone())))
+            .collect();
+        indexes.sort();
+        Ok(Workspace { path, tables, indexes })
+    }
+
+    /// Keeps the transaction's writes.
+    pub fn apply_staged(&mut self, workspace: Workspace) -> Result<(), DbError> {
+        // Removing the marker first means a crash from here on keeps the writes.
+        fs::remove_file(format!("{}/{}", workspace.path, READY_MARKER))?;
+        fs::remove_dir_all(&workspace.path)?;
+        Ok(())
+    }
+
+    /// Throws the transaction's writes away and reloads the staged tables.
+    pub fn discard_staged(&mut self, workspace: Workspace) -> Result<(), DbError> {
+        restore_files(self.data_dir(), Path::new(&workspace.path))?;
+        self.reload(&workspace.tables, &workspace.indexes)?;
+        fs::remove_dir_all(&workspace.path)?;
+        Ok(())
+    }
+
+    /// Rebuilds `tables` in memory from the files on disk, reopening the
+    /// `indexes` that existed when they were written.
+    fn reload(&mut self, tables: &[String], indexes: &[(String, String)]) -> Result<(), DbError> {
+        let data_dir = self.data_dir().to_string();
+        let saved = Schema::load(&data_dir)?;
+        for name in tables {
+            self.columns.remove(name);
+            self.indexes.remove(name);
+            self.pending_rows.remove(name);
+            self.pending_bytes.remove(name);
+            // Cached results of the table may be stale now.
+            self.bump_table_version(name);
+            let Some(table) = saved.get_table(name) else {
+                self.schema.tables.remove(name);
+                continue;
+            };
+            let mut table = table.clone();
+            if let Some(meta) = TableMetadata::load(&data_dir, name)? {
+                table.row_count = meta.row_count;
+            }
+            let mut table_cols = HashMap::new();
+            let mut table_indexes = HashMap::new();
+            for col in table.columns.iter().filter(|_| table.location.is_none()) {
+                table_cols.insert(col.name.clone(), ColumnStore::new(col, name, &data_dir, self.layout)?);
+                if table.is_unique(&col.name) || indexes.contains(&(name.clone(), col.name.clone())) {
+                    let index_path = format!("{}/{}", data_dir, self.layout.index_file(name, col));
+                    let index = Index::new(&index_path, col.data_type.clone(), col.collation)?;
+                    table_indexes.insert(col.name.clone(), index);
+                }
+            }
+            self.columns.insert(name.clone(), table_cols);
+            self.indexes.insert(name.clone(), table_indexes);
+            self.schema.tables.insert(name.clone(), table);
+        }
+        Ok(())
+    }
+}
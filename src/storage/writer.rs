@@ -0,0 +1,62 @@
+use crate::query::FileFormat;
+use crate::types::{DbError, Value};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// A CSV field, quoted when it holds the delimiter, a quote or a line break.
+/// NULL is an empty field, which `load_csv` reads back as NULL.
+fn csv_field(value: &Value) -> String {
+    let text = match value {
+        Value::Null => return String::new(),
+        other => other.to_string(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
+
+fn json_value(value: &Value) -> serde_json::Value {
+    match value {
+        Value::Int32(i) => (*i).into(),
+        Value::Int64(i) => (*i).into(),
+        Value::Float32(f) => serde_json::Number::from_f64(f.0 as f64).map_or(serde_json::Value::Null, Into::into),
+        Value::Boolean(b) => (*b).into(),
+        Value::Null => serde_json::Value::Null,
+        // Dates and timestamps as the text the parser accepts back.
+        Value::String(_) | Value::Date(_) | Value::Timestamp(_) => value.to_string().into(),
+    }
+}
+
+/// Streams `rows` into `path` as CSV with a header line, or as newline-delimited
+/// JSON objects keyed by column name. Returns the number of rows written.
+pub fn write_rows(
+    path: &str,
+    format: FileFormat,
+    columns: &[String],
+    rows: impl IntoIterator<Item = Vec<Value>>,
+) -> Result<usize, DbError> {
+    let mut out = BufWriter::new(File::create(path)?);
+    if format == FileFormat::Csv {
+        let header: Vec<String> = columns.iter().map(|c| csv_field(&Value::String(c.clone()))).collect();
+        writeln!(out, "{}", header.join(","))?;
+    }
+    let mut written = 0;
+    for row in rows {
+        match format {
+            FileFormat::Csv => {
+                let fields: Vec<String> = row.iter().map(csv_field).collect();
+                writeln!(out, "{}", fields.join(","))?;
+            }
+            FileFormat::Json => {
+                let object: serde_json::Map<String, serde_json::Value> =
+                    columns.iter().cloned().zip(row.iter().map(json_value)).collect();
+                writeln!(out, "{}", serde_json::Value::Object(object))?;
+            }
+        }
+        written += 1;
+    }
+    out.flush()?;
+    Ok(written)
+}
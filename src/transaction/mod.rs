@@ -1,28 +1,118 @@
-use crate::query::{Query, planner::QueryEngine};
+use crate::query::parser::parse_query;
+use crate::query::stream::RowStream;
+use crate::query::{cache::QueryCache, log::QueryLog, planner::QueryEngine, result::QueryResult, Query};
 use crate::storage::StorageManager;
 use crate::types::DbError;
 use crate::Value;
 use serde::{Deserialize, Serialize};
-use std::fs::{self, File, OpenOptions};
-use std::io::{Seek, SeekFrom, Write};
-use std::sync::{Arc, Mutex};
+use std::fs;
+use std::sync::atomic::AtomicU64;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Instant;
+use wal::Wal;
+
+mod session;
+pub mod wal;
+
+pub use session::Session;
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Transaction {
     id: u64,
+    /// Session that began it.
+    session: u64,
     queries: Vec<Query>,
+    /// SQL each query was parsed from, for the query log.
+    #[serde(skip)]
+    texts: Vec<Option<String>>,
 }
 
 impl Transaction {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn session(&self) -> u64 {
+        self.session
+    }
+
+    pub fn queries(&self) -> &[Query] {
+        &self.queries
+    }
+
     pub fn add_query(&mut self, query: Query) {
         self.queries.push(query);
+        self.texts.push(None);
+    }
+
+    /// Adds `query` parsed from `sql`, which the query log shows for it.
+    pub fn add_statement(&mut self, sql: &str, query: Query) {
+        self.queries.push(query);
+        self.texts.push(Some(sql.to_string()));
+    }
+
+    /// Parses `sql` and adds it; it runs when the transaction commits.
+    pub fn add_sql(&mut self, sql: &str) -> Result<(), DbError> {
+        let query = parse_statement(sql)?;
+        self.add_statement(sql, query);
+        Ok(())
     }
 }
 
-pub struct TransactionManager {
+/// Parses `sql`, which must not begin or end a transaction.
+fn parse_statement(sql: &str) -> Result<Query, DbError> {
+    let query = parse_query(sql)?;
+    if matches!(query, Query::StartTransaction | Query::Commit | Query::Rollback) {
+        return Err(DbError::TransactionError(format!("{} cannot be added to a transaction", sql.trim())));
+    }
+    Ok(query)
+}
+
+/// Text logged for a query added without its SQL.
+fn describe(query: &Query) -> String {
+    format!("{:?}", query)
+}
+
+/// Queries against a snapshot taken when the transaction began. Other
+/// sessions keep writing meanwhile; none of their changes are visible here.
+pub struct ReadTransaction {
+    engine: QueryEngine,
+    log: Arc<QueryLog>,
+}
+
+impl ReadTransaction {
+    pub fn execute(&mut self, query: Query) -> Result<QueryResult, DbError> {
+        if query.is_write() {
+            return Err(DbError::TransactionError("Read transactions cannot write".to_string()));
+        }
+        let text = describe(&query);
+        let start = Instant::now();
+        let result = self.engine.execute_with_metadata(query);
+        self.log.record(&text, start.elapsed(), result.as_ref().map(|r| r.rows.len()));
+        result
+    }
+}
+
+/// What a manager and its sessions share.
+struct Shared {
     storage: Arc<Mutex<StorageManager>>,
-    next_tx_id: u64,
-    wal: File,
+    next_tx_id: AtomicU64,
+    next_session_id: AtomicU64,
+    /// Also the commit lock: a commit holds it from appending its record
+    /// until clearing it.
+    wal: Mutex<Wal>,
+    /// Held exclusively by a commit that writes, from its first statement
+    /// until its writes are kept or undone, and shared by reads of the live
+    /// tables, so no read sees a transaction that has not committed.
+    reads: RwLock<()>,
+    query_cache: Arc<Mutex<QueryCache>>,
+}
+
+/// Recovers the WAL and hands out sessions. Its own transaction methods run
+/// in a session of its own; `session` gives one to each further thread.
+pub struct TransactionManager {
+    shared: Arc<Shared>,
+    session: Session,
 }
 
 impl TransactionManager {
@@ -33,50 +123,78 @@ impl TransactionManager {
         };
         let wal_dir = format!("{}/wal", data_dir);
         fs::create_dir_all(&wal_dir)?;
-        let wal = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .create(true)
-            .open(format!("{}/wal.log", wal_dir))
-            .map_err(|e| DbError::IoError(e))?;
-        Ok(TransactionManager {
+        let mut wal = Wal::open(&format!("{}/wal.log", wal_dir))?;
+        // The WAL is emptied only once a commit's indexes are saved; a record
+        // left behind means the index files may miss the blocks it wrote.
+        if !wal.is_empty()? {
+            let mut storage_guard = storage.lock().unwrap();
+            if !storage_guard.is_read_only() {
+                let recovered = wal.recover()?;
+                let ids: Vec<(u64, u64)> = recovered.transactions.iter().map(|tx| (tx.session, tx.id)).collect();
+                log::warn!("Rebuilding indexes of {} after interrupted commits (session, id) {:?}", data_dir, ids);
+                storage_guard.rebuild_indexes()?;
+            }
+        }
+        let shared = Arc::new(Shared {
             storage,
-            next_tx_id: 1,
-            wal,
+            next_tx_id: AtomicU64::new(1),
+            next_session_id: AtomicU64::new(1),
+            wal: Mutex::new(wal),
+            reads: RwLock::new(()),
+            query_cache: Arc::new(Mutex::new(QueryCache::new(256))),
+        });
+        Ok(TransactionManager {
+            session: Session::new(Arc::clone(&shared)),
+            shared,
         })
     }
 
+    /// A new session, to begin and commit transactions from another thread.
+    pub fn session(&self) -> Session {
+        Session::new(Arc::clone(&self.shared))
+    }
+
+    /// Writes buffered rows to disk; a no-op on a read-only database.
+    pub fn flush(&self) -> Result<usize, DbError> {
+        let mut storage_guard = self.shared.storage.lock().unwrap();
+        if storage_guard.is_read_only() {
+            return Ok(0);
+        }
+        storage_guard.flush_all()
+    }
+
+    pub fn query_cache(&self) -> Arc<Mutex<QueryCache>> {
+        Arc::clone(&self.shared.query_cache)
+    }
+
+    pub fn storage(&self) -> Arc<Mutex<StorageManager>> {
+        Arc::clone(&self.shared.storage)
+    }
+
     pub fn begin_transaction(&mut self) -> Transaction {
-        let tx = Transaction {
-            id: self.next_tx_id,
-            queries: Vec::new(),
-        };
-        self.next_tx_id += 1;
-        tx
+        self.session.begin()
     }
 
-    pub fn commit_transaction(&mut self, mut tx: Transaction) -> Result<Vec<Vec<Value>>, DbError> {
-        let tx_data = bincode::serialize(&tx).map_err(|e| DbError::from(*e))?;
-        self.wal.write_all(&tx_data)?;
-        self.wal.flush()?;
+    /// See `Session::begin_read`.
+    pub fn begin_read_transaction(&self) -> Result<ReadTransaction, DbError> {
+        self.session.begin_read()
+    }
 
-        let mut results = Vec::new();
-        let mut query_engine = QueryEngine::new(Arc::clone(&self.storage));
-        for query in tx.queries.drain(..) {
-            let result = query_engine.execute(query)?;
-            if !result.is_empty() {
-                results.push(result);
-            }
-        }
+    pub fn commit_transaction(&mut self, tx: Transaction) -> Result<Vec<Vec<Value>>, DbError> {
+        self.session.commit(tx)
+    }
 
-        self.wal.set_len(0)?;
-        self.wal.seek(SeekFrom::Start(0))?;
-        Ok(results.into_iter().flatten().collect())
+    /// See `Session::commit_results`.
+    pub fn commit_transaction_results(&mut self, tx: Transaction) -> Result<Vec<QueryResult>, DbError> {
+        self.session.commit_results(tx)
     }
 
-    pub fn rollback_transaction(&mut self, _tx: Transaction) -> Result<(), DbError> {
-        self.wal.set_len(0)?;
-        self.wal.seek(SeekFrom::Start(0))?;
-        Ok(())
+    /// See `Session::execute_streaming`.
+    pub fn execute_streaming(&mut self, sql: &str) -> Result<RowStream, DbError> {
+        self.session.execute_streaming(sql)
     }
-}
\ No newline at end of file
+
+    pub fn rollback_transaction(&mut self, tx: Transaction) -> Result<(), DbError> {
+        self.session.rollback(tx)
+    }
+}
@@ -0,0 +1,206 @@
+use crate::query::planner::QueryEngine;
+use crate::query::result::{result_columns, QueryResult};
+use crate::query::stream::RowStream;
+use crate::query::Query;
+use crate::storage::backup::BackupImage;
+use crate::storage::settings::{Priority, SyncMode};
+use crate::storage::StorageManager;
+use crate::transaction::{describe, parse_statement, ReadTransaction, Shared, Transaction};
+use crate::types::DbError;
+use crate::Value;
+use std::sync::atomic::Ordering;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A handle for running transactions from one thread, got from
+/// `TransactionManager::session`. Sessions of a manager draw transaction ids
+/// from one counter and log their commits to its WAL, each record tagged
+/// with the session. Transactions are built and read transactions run
+/// concurrently; commits run one at a time, as rolling one back restores
+/// the tables it wrote from files saved before it started. Reads of the
+/// live tables wait for a commit that writes to finish.
+#[derive(Clone)]
+pub struct Session {
+    id: u64,
+    shared: Arc<Shared>,
+    /// Overrides the `priority` setting for this session's reads.
+    priority: Option<Priority>,
+}
+
+impl Session {
+    pub(super) fn new(shared: Arc<Shared>) -> Self {
+        let id = shared.next_session_id.fetch_add(1, Ordering::Relaxed);
+        Session {
+            id,
+            shared,
+            priority: None,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Runs this session's reads at `priority` whatever the setting says.
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = Some(priority);
+        self
+    }
+
+    pub fn storage(&self) -> Arc<Mutex<StorageManager>> {
+        Arc::clone(&self.shared.storage)
+    }
+
+    /// An engine over `storage` that reads at this session's priority.
+    fn engine(&self, storage: Arc<Mutex<StorageManager>>, cached: bool) -> QueryEngine {
+        let engine = if cached {
+            QueryEngine::with_cache(storage, Arc::clone(&self.shared.query_cache))
+        } else {
+            QueryEngine::new(storage)
+        };
+        match self.priority {
+            Some(priority) => engine.with_priority(priority),
+            None => engine,
+        }
+    }
+
+    pub fn begin(&self) -> Transaction {
+        Transaction {
+            id: self.shared.next_tx_id.fetch_add(1, Ordering::Relaxed),
+            session: self.id,
+            queries: Vec::new(),
+            texts: Vec::new(),
+        }
+    }
+
+    /// Starts a read-only transaction. The storage lock is only held while
+    /// the snapshot is copied, not while its queries run; a commit that
+    /// writes is waited for, so the snapshot only holds committed rows.
+    pub fn begin_read(&self) -> Result<ReadTransaction, DbError> {
+        let snapshot = {
+            let _committed = self.shared.reads.read().unwrap();
+            self.shared.storage.lock().unwrap().snapshot()?
+        };
+        Ok(ReadTransaction {
+            log: snapshot.query_log(),
+            engine: self.engine(Arc::new(Mutex::new(snapshot)), false),
+        })
+    }
+
+    /// Runs the transaction and returns the rows of all its statements together.
+    pub fn commit(&self, tx: Transaction) -> Result<Vec<Vec<Value>>, DbError> {
+        let results = self.commit_results(tx)?;
+        Ok(results.into_iter().flat_map(|r| r.rows).collect())
+    }
+
+    /// Runs the transaction and returns one result per statement that
+    /// produces rows, with its column names and types. Waits for any commit
+    /// of another session to finish first.
+    pub fn commit_results(&self, mut tx: Transaction) -> Result<Vec<QueryResult>, DbError> {
+        if let [Query::Backup { path }] = &tx.queries[..] {
+            let text = tx.texts.pop().flatten().unwrap_or_else(|| describe(&tx.queries[0]));
+            return self.backup(path, &text).map(|result| vec![result]);
+        }
+        let storage = &self.shared.storage;
+        // Held until the record is cleared, so no other commit runs meanwhile.
+        let mut wal = self.shared.wal.lock().unwrap();
+        let (compress, sync) = {
+            let storage_guard = storage.lock().unwrap();
+            let settings = storage_guard.settings();
+            (settings.wal_compression, settings.sync_mode == SyncMode::Full)
+        };
+        wal.append(&tx, compress, sync)?;
+
+        // With more than one write, a failure part way must undo the earlier ones.
+        let writes: Vec<&Query> = tx.queries.iter().filter(|q| q.is_write()).collect();
+        let _writing = (!writes.is_empty()).then(|| self.shared.reads.write().unwrap());
+        let workspace = if writes.len() > 1 {
+            Some(storage.lock().unwrap().stage(tx.id, &writes)?)
+        } else {
+            None
+        };
+
+        let mut results = Vec::new();
+        let mut query_engine = self.engine(Arc::clone(storage), true);
+        let mut outcome = Ok(());
+        let log = storage.lock().unwrap().query_log();
+        for (query, text) in tx.queries.drain(..).zip(tx.texts.drain(..)) {
+            let text = text.unwrap_or_else(|| describe(&query));
+            let start = Instant::now();
+            let result = query_engine.execute_with_metadata(query);
+            log.record(&text, start.elapsed(), result.as_ref().map(|r| r.rows.len()));
+            match result {
+                Ok(result) if !result.columns.is_empty() || !result.rows.is_empty() => results.push(result),
+                Ok(_) => {}
+                Err(e) => {
+                    outcome = Err(e);
+                    break;
+                }
+            }
+        }
+        if let Some(workspace) = workspace {
+            let mut storage_guard = storage.lock().unwrap();
+            match outcome {
+                Ok(()) => storage_guard.apply_staged(workspace)?,
+                Err(_) => storage_guard.discard_staged(workspace)?,
+            }
+        }
+        outcome?;
+        {
+            let mut storage_guard = storage.lock().unwrap();
+            storage_guard.save_indexes()?;
+            storage_guard.save_manifests()?;
+        }
+
+        wal.clear()?;
+        Ok(results)
+    }
+
+    /// Runs one statement, returning its rows as a stream. A SELECT reads
+    /// through `QueryEngine::execute_streaming` and is logged once its rows
+    /// run out or the stream is dropped; any other statement runs as a
+    /// transaction of its own.
+    pub fn execute_streaming(&self, sql: &str) -> Result<RowStream, DbError> {
+        let query = parse_statement(sql)?;
+        if !matches!(query, Query::Select { .. } | Query::AsOfSnapshot { .. }) {
+            let mut tx = self.begin();
+            tx.add_statement(sql, query);
+            let result = self.commit_results(tx)?.pop();
+            return Ok(RowStream::from(result.unwrap_or_else(|| QueryResult::new(Vec::new(), Vec::new()))));
+        }
+        let storage = &self.shared.storage;
+        let log = storage.lock().unwrap().query_log();
+        let start = Instant::now();
+        let mut query_engine = self.engine(Arc::clone(storage), true);
+        // Reads share the lock; they wait only for a commit that writes.
+        let _committed = self.shared.reads.read().unwrap();
+        match query_engine.execute_streaming(query) {
+            Ok(stream) => Ok(stream.logged(log, sql, start)),
+            Err(e) => {
+                log.record(sql, start.elapsed(), Err(&e));
+                Err(e)
+            }
+        }
+    }
+
+    /// `BACKUP TO path` on its own: the image is taken between two commits,
+    /// then copied while other sessions go on reading and committing.
+    fn backup(&self, path: &str, text: &str) -> Result<QueryResult, DbError> {
+        let start = Instant::now();
+        let (image, columns, log) = {
+            let _commit = self.shared.wal.lock().unwrap();
+            let mut storage = self.shared.storage.lock().unwrap();
+            let columns = result_columns(&Query::Backup { path: path.to_string() }, storage.schema());
+            (storage.begin_backup(path), columns, storage.query_log())
+        };
+        let result = image
+            .and_then(BackupImage::finish)
+            .map(|report| QueryResult::new(columns, vec![report.to_row(path.to_string())]));
+        log.record(text, start.elapsed(), result.as_ref().map(|r| r.rows.len()));
+        result
+    }
+
+    pub fn rollback(&self, _tx: Transaction) -> Result<(), DbError> {
+        self.shared.wal.lock().unwrap().clear()
+    }
+}
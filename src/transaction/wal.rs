@@ -0,0 +1,162 @@
+use crate::transaction::Transaction;
+use crate::types::DbError;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Seek, SeekFrom, Write};
+
+/// Payload length and CRC-32, before the flags byte and the payload.
+const HEADER_LEN: usize = 8;
+
+/// Flag of a record whose payload is zstd-compressed.
+const ZSTD: u8 = 1;
+
+const ZSTD_LEVEL: i32 = 3;
+
+const CRC_TABLE: [u32; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC-32 (IEEE), as zlib and most file formats compute it.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0, |crc, &byte| CRC_TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8))
+}
+
+/// The write-ahead log. Each commit appends its transaction as one record:
+/// the payload's length, a CRC-32 of the flags and payload, a flags byte and
+/// the bincode payload, zstd-compressed if the flags say so. The framing
+/// lets a reader keep every whole record of a log a crash cut short.
+pub struct Wal {
+    file: File,
+    path: String,
+}
+
+impl Wal {
+    pub fn open(path: &str) -> Result<Self, DbError> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        Ok(Wal {
+            file,
+            path: path.to_string(),
+        })
+    }
+
+    /// Reads the log after a crash and drops a record it left incomplete, so
+    /// the next one is appended after whole records.
+    pub fn recover(&mut self) -> Result<WalContents, DbError> {
+        let contents = read_wal(&self.path)?;
+        if contents.torn_bytes > 0 {
+            log::warn!("Dropping {} bytes of an incomplete record from {}", contents.torn_bytes, self.path);
+            self.file.set_len(contents.valid_bytes)?;
+        }
+        Ok(contents)
+    }
+
+    pub fn len(&self) -> Result<u64, DbError> {
+        Ok(self.file.metadata()?.len())
+    }
+
+    pub fn is_empty(&self) -> Result<bool, DbError> {
+        Ok(self.len()? == 0)
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Appends `tx` as one record and flushes it; `sync` also waits for the disk.
+    pub fn append(&mut self, tx: &Transaction, compress: bool, sync: bool) -> Result<(), DbError> {
+        let payload = bincode::serialize(tx).map_err(|e| DbError::from(*e))?;
+        self.file.write_all(&encode_record(&payload, compress)?)?;
+        self.file.flush()?;
+        if sync {
+            self.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// Empties the log once what it records is safely applied.
+    pub fn clear(&mut self) -> Result<(), DbError> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+}
+
+fn encode_record(payload: &[u8], compress: bool) -> Result<Vec<u8>, DbError> {
+    let mut body = Vec::with_capacity(payload.len() + 1);
+    if compress {
+        body.push(ZSTD);
+        body.extend(zstd::encode_all(payload, ZSTD_LEVEL)?);
+    } else {
+        body.push(0);
+        body.extend_from_slice(payload);
+    }
+    let len = u32::try_from(body.len() - 1).map_err(|_| {
+        DbError::TransactionError(format!("Transaction of {} bytes is too large to log", payload.len()))
+    })?;
+    let mut record = Vec::with_capacity(HEADER_LEN + body.len());
+    record.write_u32::<LittleEndian>(len)?;
+    record.write_u32::<LittleEndian>(crc32(&body))?;
+    record.extend(body);
+    Ok(record)
+}
+
+/// What a WAL file holds.
+pub struct WalContents {
+    /// Transactions of the whole records, in the order they were logged.
+    pub transactions: Vec<Transaction>,
+    /// Bytes up to the end of the last whole record.
+    pub valid_bytes: u64,
+    /// Bytes after it: a record cut short, or one that fails its CRC or
+    /// cannot be decoded.
+    pub torn_bytes: u64,
+}
+
+/// Reads the records of the WAL at `path`, stopping at the first that is
+/// incomplete, corrupt or undecodable. Logs from before records were framed
+/// read as torn.
+pub fn read_wal(path: &str) -> Result<WalContents, DbError> {
+    let data = fs::read(path)?;
+    let mut contents = WalContents {
+        transactions: Vec::new(),
+        valid_bytes: 0,
+        torn_bytes: data.len() as u64,
+    };
+    let mut rest = &data[..];
+    while rest.len() >= HEADER_LEN {
+        let mut header = &rest[..HEADER_LEN];
+        let len = header.read_u32::<LittleEndian>()? as usize;
+        let crc = header.read_u32::<LittleEndian>()?;
+        let Some(body) = rest.get(HEADER_LEN..HEADER_LEN + 1 + len) else {
+            break;
+        };
+        if crc32(body) != crc {
+            break;
+        }
+        let payload = match body[0] {
+            0 => Some(body[1..].to_vec()),
+            ZSTD => zstd::decode_all(&body[1..]).ok(),
+            _ => None,
+        };
+        let Some(tx) = payload.and_then(|p| bincode::deserialize(&p).ok()) else {
+            break;
+        };
+        contents.transactions.push(tx);
+        let record_len = HEADER_LEN + body.len();
+        contents.valid_bytes += record_len as u64;
+        contents.torn_bytes -= record_len as u64;
+        rest = &rest[record_len..];
+    }
+    Ok(contents)
+}
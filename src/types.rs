@@ -1,5 +1,7 @@
+use crate::datetime::{parse_date, parse_timestamp, SECONDS_PER_DAY};
 use ordered_float::OrderedFloat;
 use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
 use std::fmt;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -7,6 +9,29 @@ pub enum DataType {
     Int32,
     Float32,
     String,
+    Int64,
+    Boolean,
+    /// Days since 1970-01-01.
+    Date,
+    /// Seconds since 1970-01-01 00:00:00 UTC.
+    Timestamp,
+}
+
+impl DataType {
+    /// Types SUM, AVG and the quantile aggregates accept.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, DataType::Int32 | DataType::Int64 | DataType::Float32)
+    }
+
+    /// Encoded width of one value; `None` for variable-length strings.
+    pub fn fixed_width(&self) -> Option<usize> {
+        match self {
+            DataType::Int32 | DataType::Float32 | DataType::Date => Some(4),
+            DataType::Int64 | DataType::Timestamp => Some(8),
+            DataType::Boolean => Some(1),
+            DataType::String => None,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -14,14 +39,119 @@ pub enum Value {
     Int32(i32),
     Float32(OrderedFloat<f32>),
     String(String),
+    Int64(i64),
+    Boolean(bool),
+    Date(i32),
+    Timestamp(i64),
+    /// Declared last so NULL sorts after every other value.
+    Null,
 }
 
 impl Value {
-    pub fn data_type(&self) -> DataType {
+    /// `None` for NULL, which belongs to every type.
+    pub fn data_type(&self) -> Option<DataType> {
+        match self {
+            Value::Int32(_) => Some(DataType::Int32),
+            Value::Float32(_) => Some(DataType::Float32),
+            Value::String(_) => Some(DataType::String),
+            Value::Int64(_) => Some(DataType::Int64),
+            Value::Boolean(_) => Some(DataType::Boolean),
+            Value::Date(_) => Some(DataType::Date),
+            Value::Timestamp(_) => Some(DataType::Timestamp),
+            Value::Null => None,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Value::Null)
+    }
+
+    /// Whether the value can be stored in a column of `data_type`.
+    pub fn fits(&self, data_type: &DataType) -> bool {
+        self.data_type().is_none_or(|t| t == *data_type)
+    }
+
+    /// Numeric value widened to f64; dates and timestamps map to their
+    /// epoch offsets. `None` for strings, booleans and NULL.
+    pub fn as_f64(&self) -> Option<f64> {
         match self {
-            Value::Int32(_) => DataType::Int32,
-            Value::Float32(_) => DataType::Float32,
-            Value::String(_) => DataType::String,
+            Value::Int32(i) | Value::Date(i) => Some(*i as f64),
+            Value::Int64(i) | Value::Timestamp(i) => Some(*i as f64),
+            Value::Float32(f) => Some(f.0 as f64),
+            Value::String(_) | Value::Boolean(_) | Value::Null => None,
+        }
+    }
+
+    /// Ordering of two non-NULL values of the same type; `None` otherwise,
+    /// so predicates over mismatched types or NULL match nothing.
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        if self.is_null() || self.data_type() != other.data_type() {
+            return None;
+        }
+        Some(self.cmp(other))
+    }
+
+    /// Converts a literal to the column type it is compared with or stored
+    /// in: integers widen to Int64 and Timestamp, strings parse as dates and
+    /// timestamps. Other combinations are returned as `TypeMismatch`.
+    pub fn coerce(self, data_type: &DataType) -> Result<Value, DbError> {
+        if self.fits(data_type) {
+            return Ok(self);
+        }
+        match (self, data_type) {
+            (Value::Int32(i), DataType::Int64) => Ok(Value::Int64(i as i64)),
+            (Value::Int32(i), DataType::Timestamp) => Ok(Value::Timestamp(i as i64)),
+            (Value::Int64(i), DataType::Timestamp) => Ok(Value::Timestamp(i)),
+            (Value::Date(d), DataType::Timestamp) => Ok(Value::Timestamp(d as i64 * SECONDS_PER_DAY)),
+            (Value::String(s), DataType::Date) => parse_date(&s)
+                .map(Value::Date)
+                .ok_or_else(|| DbError::InvalidData(format!("Invalid date: {}", s))),
+            (Value::String(s), DataType::Timestamp) => parse_timestamp(&s)
+                .map(Value::Timestamp)
+                .ok_or_else(|| DbError::InvalidData(format!("Invalid timestamp: {}", s))),
+            _ => Err(DbError::TypeMismatch),
+        }
+    }
+
+    /// `coerce` plus what tables with lenient validation accept: numbers
+    /// converted between numeric types when nothing is lost, numbers and
+    /// booleans written as text, and any value as text in a STRING column.
+    pub fn coerce_lenient(self, data_type: &DataType) -> Result<Value, DbError> {
+        if let Ok(value) = self.clone().coerce(data_type) {
+            return Ok(value);
+        }
+        if *data_type == DataType::String {
+            return Ok(Value::String(self.to_string()));
+        }
+        let number = match &self {
+            Value::String(s) => {
+                let s = s.trim();
+                match data_type {
+                    // Parsed directly so large integers keep every digit.
+                    DataType::Int64 if let Ok(i) = s.parse::<i64>() => return Ok(Value::Int64(i)),
+                    DataType::Boolean => {
+                        return match s.to_ascii_lowercase().as_str() {
+                            "true" | "t" | "yes" | "1" => Ok(Value::Boolean(true)),
+                            "false" | "f" | "no" | "0" => Ok(Value::Boolean(false)),
+                            _ => Err(DbError::TypeMismatch),
+                        };
+                    }
+                    DataType::Date | DataType::Timestamp => return Value::String(s.to_string()).coerce(data_type),
+                    _ => s.parse::<f64>().ok(),
+                }
+            }
+            Value::Int32(_) | Value::Int64(_) | Value::Float32(_) => self.as_f64(),
+            _ => None,
+        };
+        match (data_type, number) {
+            (DataType::Float32, Some(f)) => Ok(Value::Float32(OrderedFloat(f as f32))),
+            (DataType::Int32, Some(f)) if f.fract() == 0.0 && f >= i32::MIN as f64 && f <= i32::MAX as f64 => {
+                Ok(Value::Int32(f as i32))
+            }
+            (DataType::Int64, Some(f)) if f.fract() == 0.0 && f >= i64::MIN as f64 && f < i64::MAX as f64 => {
+                Ok(Value::Int64(f as i64))
+            }
+            _ => Err(DbError::TypeMismatch),
         }
     }
 
@@ -36,6 +166,10 @@ impl Value {
                 result.extend(bytes);
                 result
             }
+            Value::Int64(i) | Value::Timestamp(i) => i.to_le_bytes().to_vec(),
+            Value::Boolean(b) => vec![*b as u8],
+            Value::Date(d) => d.to_le_bytes().to_vec(),
+            Value::Null => Vec::new(),
         }
     }
 
@@ -75,6 +209,25 @@ impl Value {
                     Err(DbError::SerializationError("Insufficient bytes for String length".to_string()))
                 }
             }
+            DataType::Int64 | DataType::Timestamp => {
+                let array: [u8; 8] = bytes
+                    .get(..8)
+                    .and_then(|b| b.try_into().ok())
+                    .ok_or_else(|| DbError::SerializationError(format!("Insufficient bytes for {:?}", data_type)))?;
+                let i = i64::from_le_bytes(array);
+                Ok(if *data_type == DataType::Int64 { Value::Int64(i) } else { Value::Timestamp(i) })
+            }
+            DataType::Date => {
+                let array: [u8; 4] = bytes
+                    .get(..4)
+                    .and_then(|b| b.try_into().ok())
+                    .ok_or_else(|| DbError::SerializationError("Insufficient bytes for Date".to_string()))?;
+                Ok(Value::Date(i32::from_le_bytes(array)))
+            }
+            DataType::Boolean => bytes
+                .first()
+                .map(|b| Value::Boolean(*b != 0))
+                .ok_or_else(|| DbError::SerializationError("Insufficient bytes for Boolean".to_string())),
         }
     }
 
@@ -82,7 +235,45 @@ impl Value {
         match self {
             Value::Int32(_) => 4,
             Value::Float32(_) => 4,
-            Value::String(s) => 4 + s.as_bytes().len(),
+            Value::String(s) => 4 + s.len(),
+            Value::Int64(_) | Value::Timestamp(_) => 8,
+            Value::Boolean(_) => 1,
+            Value::Date(_) => 4,
+            Value::Null => 0,
+        }
+    }
+}
+
+/// How a column's strings compare in conditions, ORDER BY and indexes.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Collation {
+    #[default]
+    Binary,
+    /// Case-insensitive: strings compare by their lowercase forms.
+    NoCase,
+}
+
+impl Collation {
+    pub fn compare_str(self, a: &str, b: &str) -> Ordering {
+        match self {
+            Collation::Binary => a.cmp(b),
+            Collation::NoCase => a.chars().flat_map(char::to_lowercase).cmp(b.chars().flat_map(char::to_lowercase)),
+        }
+    }
+
+    /// `Value::compare` under this collation.
+    pub fn compare(self, a: &Value, b: &Value) -> Option<Ordering> {
+        match (a, b) {
+            (Value::String(a), Value::String(b)) => Some(self.compare_str(a, b)),
+            _ => a.compare(b),
+        }
+    }
+
+    /// The value this collation orders and indexes `value` by.
+    pub fn key(self, value: &Value) -> Value {
+        match (self, value) {
+            (Collation::NoCase, Value::String(s)) => Value::String(s.to_lowercase()),
+            _ => value.clone(),
         }
     }
 }
@@ -92,6 +283,28 @@ pub enum CompressionType {
     None,
     Rle,
     Dictionary,
+    /// Plain encoding, then LZ4 over the bytes: fast, for any column type.
+    Lz4,
+    /// Plain encoding, then Zstandard over the bytes: smaller, slower to write.
+    Zstd,
+    /// STRING only: each distinct value is stored once in the column's intern
+    /// file, and blocks hold run-length encoded INT ids into it.
+    Interned,
+}
+
+/// Written as in a `COMPRESSION` clause.
+impl fmt::Display for CompressionType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            CompressionType::None => "NONE",
+            CompressionType::Rle => "RLE",
+            CompressionType::Dictionary => "DICTIONARY",
+            CompressionType::Lz4 => "LZ4",
+            CompressionType::Zstd => "ZSTD",
+            CompressionType::Interned => "INTERNED",
+        };
+        write!(f, "{}", name)
+    }
 }
 
 #[derive(Debug)]